@@ -76,6 +76,7 @@ export class NetbenchInfra extends cdk.Stack {
         new cdk.CfnOutput(this, "output:NetbenchSubnetTagKey", { value: subnetTagKey });
         new cdk.CfnOutput(this, "output:NetbenchSubnetTagValue", { value: subnetTagValue });
         new cdk.CfnOutput(this, "output:" + this.stackName + "Region", { value: this.region });
+        new cdk.CfnOutput(this, "output:" + this.stackName + "Account", { value: this.account });
     };
     private createCloudFront(id: string, bucket: IBucket) {
         const cfDistribution = new cdk.aws_cloudfront.Distribution(this, id, {
@@ -86,6 +87,7 @@ export class NetbenchInfra extends cdk.Stack {
             defaultRootObject: "index.html"
         });
         new cdk.CfnOutput(this, 'output:NetbenchCloudfrontDistribution', { value: "https://" + cfDistribution.distributionDomainName });
+        new cdk.CfnOutput(this, 'output:NetbenchCloudfrontDistributionId', { value: cfDistribution.distributionId });
     };
 
     private createRole() {
@@ -133,6 +135,8 @@ export class NetbenchInfra extends cdk.Stack {
             blockPublicAccess: cdk.aws_s3.BlockPublicAccess.BLOCK_ALL,
             encryption: cdk.aws_s3.BucketEncryption.S3_MANAGED,
             enforceSSL: true,
+            // Allow the orchestrator to opt into S3 Transfer Acceleration.
+            transferAcceleration: true,
             // On stack destroy, keep the bucket and it's contents, leaving an orphan.
             // This will require manual cleanup if you'd like to recreate the stack.
             removalPolicy: cdk.RemovalPolicy.RETAIN,
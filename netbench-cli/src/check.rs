@@ -0,0 +1,88 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    compare::{self, Status, Threshold},
+    report_tree::Filter,
+    summary, Result,
+};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Checks a candidate run for regressions against a baseline run
+///
+/// Exits with a non-zero status if any metric regressed by more than the
+/// threshold, or if a driver in the baseline is missing from the candidate.
+#[derive(Debug, StructOpt)]
+pub struct Check {
+    #[structopt(long)]
+    baseline: PathBuf,
+
+    #[structopt(long)]
+    candidate: PathBuf,
+
+    /// Regressions smaller than the threshold are considered noise (eg. "5%")
+    #[structopt(long, default_value = "5%")]
+    threshold: Threshold,
+
+    #[structopt(flatten)]
+    filter: Filter,
+}
+
+impl Check {
+    pub fn run(&self) -> Result<()> {
+        let mut warnings = vec![];
+        let baseline = summary::load_tree(&self.baseline, &self.filter, &mut warnings)?;
+        let candidate = summary::load_tree(&self.candidate, &self.filter, &mut warnings)?;
+
+        for warning in &warnings {
+            eprintln!("warning: {warning}");
+        }
+
+        let mut failures = vec![];
+
+        for summary in &baseline {
+            let found = candidate.iter().any(|candidate| {
+                candidate.scenario == summary.scenario
+                    && candidate.driver == summary.driver
+                    && candidate.endpoint == summary.endpoint
+            });
+            if !found {
+                failures.push(format!(
+                    "{}/{}: missing from candidate",
+                    summary.scenario, summary.driver
+                ));
+            }
+        }
+
+        for change in compare::compare(&baseline, &candidate, self.threshold) {
+            if change.status == Status::Regression {
+                failures.push(format!(
+                    "{}/{}: {} regressed {:.2}% ({} -> {})",
+                    change.scenario,
+                    change.driver,
+                    change.metric_name,
+                    change.change,
+                    change.a,
+                    change.b
+                ));
+            }
+        }
+
+        if failures.is_empty() {
+            println!("no regressions found (threshold: {}%)", self.threshold.0);
+            return Ok(());
+        }
+
+        eprintln!(
+            "{} regression(s) found (threshold: {}%):",
+            failures.len(),
+            self.threshold.0
+        );
+        for failure in &failures {
+            eprintln!("  {failure}");
+        }
+
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    report_tree::Filter,
+    summary::{self, Direction, Metric, Summary},
+    Result,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+
+static COMPARE_HTML: &str = include_str!("./compare.html");
+
+/// Compares the results of two runs
+#[derive(Debug, StructOpt)]
+pub struct Compare {
+    run_a_dir: PathBuf,
+    run_b_dir: PathBuf,
+
+    /// Changes smaller than the threshold are considered noise (eg. "5%")
+    #[structopt(long, default_value = "5%")]
+    threshold: Threshold,
+
+    #[structopt(flatten)]
+    filter: Filter,
+
+    /// Directory to write `compare.json` and `compare.html` to
+    ///
+    /// The json diff is written to stdout if not specified.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl Compare {
+    pub fn run(&self) -> Result<()> {
+        let mut warnings = vec![];
+        let run_a = summary::load_tree(&self.run_a_dir, &self.filter, &mut warnings)?;
+        let run_b = summary::load_tree(&self.run_b_dir, &self.filter, &mut warnings)?;
+        let changes = compare(&run_a, &run_b, self.threshold);
+
+        for warning in &warnings {
+            eprintln!("warning: {warning}");
+        }
+
+        let diff = json!({
+            "run_a": self.run_a_dir.display().to_string(),
+            "run_b": self.run_b_dir.display().to_string(),
+            "threshold": self.threshold.0,
+            "changes": changes,
+            "warnings": warnings,
+        });
+
+        if let Some(out_dir) = self.output.as_ref() {
+            std::fs::create_dir_all(out_dir)?;
+
+            let mut out_file = std::fs::File::create(out_dir.join("compare.json"))?;
+            serde_json::to_writer(&mut out_file, &diff)?;
+
+            let template = handlebars::Handlebars::new();
+            let html = template.render_template(COMPARE_HTML, &diff)?;
+            std::fs::write(out_dir.join("compare.html"), html)?;
+        } else {
+            serde_json::to_writer(std::io::stdout(), &diff)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A relative change threshold, expressed in percent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Threshold(pub f64);
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let value = s.trim().trim_end_matches('%');
+        let value: f64 = value
+            .parse()
+            .map_err(|_| format!("invalid threshold: {s:?}"))?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("threshold must be a positive percentage: {s:?}"));
+        }
+        Ok(Self(value))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Regression,
+    Improvement,
+    Unchanged,
+}
+
+/// The change in a single metric for a driver between two runs
+#[derive(Clone, Debug, Serialize)]
+pub struct Change {
+    pub scenario: String,
+    pub driver: String,
+    pub endpoint: &'static str,
+    /// The stable id of the metric
+    pub metric: &'static str,
+    /// The display name of the metric
+    pub metric_name: &'static str,
+    pub a: f64,
+    pub b: f64,
+    /// Percent change from `a` to `b`
+    pub change: f64,
+    pub status: Status,
+}
+
+/// Compares each metric for the drivers present in both runs
+pub fn compare(run_a: &[Summary], run_b: &[Summary], threshold: Threshold) -> Vec<Change> {
+    let run_b: BTreeMap<_, _> = run_b
+        .iter()
+        .map(|summary| {
+            (
+                (&summary.scenario, &summary.driver, summary.endpoint),
+                summary,
+            )
+        })
+        .collect();
+
+    let mut changes = vec![];
+    for a in run_a {
+        let b = if let Some(b) = run_b.get(&(&a.scenario, &a.driver, a.endpoint)) {
+            b
+        } else {
+            continue;
+        };
+
+        for metric in Metric::ALL.iter().copied() {
+            let (a_value, b_value) = match (a.get(metric), b.get(metric)) {
+                (Some(a_value), Some(b_value)) => (a_value, b_value),
+                _ => continue,
+            };
+
+            let change = percent_change(a_value, b_value);
+            let status = status(metric.direction(), change, threshold);
+
+            changes.push(Change {
+                scenario: a.scenario.clone(),
+                driver: a.driver.clone(),
+                endpoint: a.endpoint.as_str(),
+                metric: metric.id(),
+                metric_name: metric.name(),
+                a: a_value,
+                b: b_value,
+                change,
+                status,
+            });
+        }
+    }
+
+    changes
+}
+
+fn percent_change(a: f64, b: f64) -> f64 {
+    if a == 0.0 {
+        if b == 0.0 {
+            return 0.0;
+        }
+        return f64::INFINITY.copysign(b);
+    }
+    (b - a) / a.abs() * 100.0
+}
+
+fn status(direction: Direction, change: f64, threshold: Threshold) -> Status {
+    if change.abs() <= threshold.0 {
+        return Status::Unchanged;
+    }
+
+    match direction {
+        Direction::HigherIsBetter if change > 0.0 => Status::Improvement,
+        Direction::HigherIsBetter => Status::Regression,
+        Direction::LowerIsBetter if change < 0.0 => Status::Improvement,
+        Direction::LowerIsBetter => Status::Regression,
+        Direction::Neutral => Status::Unchanged,
+    }
+}
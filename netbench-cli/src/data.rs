@@ -0,0 +1,266 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Writes the normalized `data/` directory of a report
+//!
+//! The layout is:
+//!
+//! ```text
+//! data/
+//! ├─ index.json
+//! ├─ <scenario>/
+//!    ├─ <driver>/ # eg. client-s2n-quic
+//!       ├─ <metric>.json
+//! ```
+//!
+//! `index.json` lists every series in the directory:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "series": [
+//!     { "scenario": "request_response", "driver": "s2n-quic", "endpoint": "client",
+//!       "metric": "send_bytes", "unit": "bytes", "path": "request_response/client-s2n-quic/send_bytes.json" }
+//!   ]
+//! }
+//! ```
+//!
+//! Each `<metric>.json` file contains a series per result file (ie. per host or
+//! iteration) in the driver directory. Each point is a `[time_ms, value]` pair, where
+//! `time_ms` is the time since the collector started and `value` is the per-interval
+//! value of the metric.
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "scenario": "request_response",
+//!   "driver": "s2n-quic",
+//!   "endpoint": "client",
+//!   "metric": "send_bytes",
+//!   "unit": "bytes",
+//!   "series": [
+//!     { "source": "client-0.json", "points": [[1000, 12345.0], [2000, 23456.0]] }
+//!   ]
+//! }
+//! ```
+//!
+//! Breaking changes to the layout increment `SCHEMA_VERSION`.
+
+use crate::{report_tree::ResultFile, summary::trim_driver_name, Result};
+use netbench::stats::{Initialize, Stats};
+use serde::Serialize;
+use std::{collections::BTreeMap, io::BufRead, path::Path};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+struct Index {
+    schema_version: u32,
+    series: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexEntry {
+    scenario: String,
+    driver: String,
+    endpoint: &'static str,
+    metric: String,
+    unit: &'static str,
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct MetricFile<'a> {
+    schema_version: u32,
+    scenario: &'a str,
+    driver: &'a str,
+    endpoint: &'static str,
+    metric: &'a str,
+    unit: &'static str,
+    series: &'a [Series],
+}
+
+#[derive(Debug, Serialize)]
+struct Series {
+    source: String,
+    points: Vec<(u64, f64)>,
+}
+
+// metric -> (unit, series)
+type MetricMap = BTreeMap<String, (&'static str, Vec<Series>)>;
+
+/// Writes the `data/` directory for the provided result files
+///
+/// Result files which can't be read are skipped and recorded in `warnings`.
+pub fn write(out_dir: &Path, results: &[ResultFile], warnings: &mut Vec<String>) -> Result<()> {
+    let mut drivers: BTreeMap<(String, String), (String, &'static str, MetricMap)> =
+        BTreeMap::new();
+
+    for result in results {
+        let (driver, metrics) = match read_series(&result.path, warnings) {
+            Ok(series) => series,
+            Err(err) => {
+                warnings.push(format!("{}: skipped: {err}", result.path.display()));
+                continue;
+            }
+        };
+
+        let source = result.relative_name();
+
+        let (_, _, entry) = drivers
+            .entry((result.scenario.clone(), result.driver.clone()))
+            .or_insert_with(|| (driver, result.endpoint.as_str(), BTreeMap::new()));
+
+        for (metric, (unit, points)) in metrics {
+            entry
+                .entry(metric)
+                .or_insert_with(|| (unit, vec![]))
+                .1
+                .push(Series {
+                    source: source.clone(),
+                    points,
+                });
+        }
+    }
+
+    let mut index = Index {
+        schema_version: SCHEMA_VERSION,
+        series: vec![],
+    };
+
+    for ((scenario, driver_dir), (driver, endpoint, metrics)) in drivers {
+        let dir = out_dir.join(&scenario).join(&driver_dir);
+        std::fs::create_dir_all(&dir)?;
+
+        for (metric, (unit, series)) in metrics {
+            let filename = format!("{}.json", metric.replace('/', "_"));
+            let file = MetricFile {
+                schema_version: SCHEMA_VERSION,
+                scenario: &scenario,
+                driver: &driver,
+                endpoint,
+                metric: &metric,
+                unit,
+                series: &series,
+            };
+            let out_file = std::fs::File::create(dir.join(&filename))?;
+            serde_json::to_writer(std::io::BufWriter::new(out_file), &file)?;
+
+            index.series.push(IndexEntry {
+                scenario: scenario.clone(),
+                driver: driver.clone(),
+                endpoint,
+                metric,
+                unit,
+                path: format!("{scenario}/{driver_dir}/{filename}"),
+            });
+        }
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+    let out_file = std::fs::File::create(out_dir.join("index.json"))?;
+    serde_json::to_writer(std::io::BufWriter::new(out_file), &index)?;
+
+    Ok(())
+}
+
+/// Reads each metric series from a single result file
+///
+/// A truncated file keeps the points read up to that point.
+fn read_series(
+    path: &Path,
+    warnings: &mut Vec<String>,
+) -> Result<(String, BTreeMap<String, (&'static str, Vec<(u64, f64)>)>)> {
+    let input = std::fs::File::open(path)?;
+    let mut input = std::io::BufReader::new(input);
+
+    let mut first = String::new();
+    input.read_line(&mut first)?;
+    let Initialize { driver, traces, .. } = serde_json::from_str(&first)?;
+    let driver = trim_driver_name(&driver);
+
+    let mut metrics: BTreeMap<String, (&'static str, Vec<(u64, f64)>)> = BTreeMap::new();
+
+    let input = serde_json::de::IoRead::new(input);
+    let input = serde_json::StreamDeserializer::new(input);
+    for event in input {
+        let stats: Stats = match event {
+            Ok(stats) => stats,
+            Err(err) => {
+                warnings.push(format!("{}: truncated: {err}", path.display()));
+                break;
+            }
+        };
+
+        let x = stats.time.as_millis() as u64;
+        let mut push = |metric: &str, unit: &'static str, value: f64| {
+            if !value.is_finite() {
+                return;
+            }
+            metrics
+                .entry(metric.to_string())
+                .or_insert_with(|| (unit, vec![]))
+                .1
+                .push((x, value));
+        };
+
+        push("cpu", "percent", stats.cpu as f64);
+        push("memory", "bytes", stats.memory as f64);
+        push("virtual_memory", "bytes", stats.virtual_memory as f64);
+        push("cycles", "count", stats.cycles as f64);
+        push("instructions", "count", stats.instructions as f64);
+        push("branches", "count", stats.branches as f64);
+        push("context_switches", "count", stats.context_switches as f64);
+        push("syscalls", "count", stats.syscalls as f64);
+        push("connections", "count", stats.connections as f64);
+        push("accept", "count", stats.accept as f64);
+        push("alloc_bytes", "bytes", stats.allocs.total as f64);
+        push("alloc_count", "count", stats.allocs.count as f64);
+        push("realloc_bytes", "bytes", stats.reallocs.total as f64);
+        push("realloc_count", "count", stats.reallocs.count as f64);
+        push("dealloc_bytes", "bytes", stats.deallocs.total as f64);
+        push("dealloc_count", "count", stats.deallocs.count as f64);
+        push(
+            "send_bytes",
+            "bytes",
+            stats.send.values().map(|s| s.total).sum::<u64>() as f64,
+        );
+        push(
+            "send_count",
+            "count",
+            stats.send.values().map(|s| s.count).sum::<u64>() as f64,
+        );
+        push(
+            "receive_bytes",
+            "bytes",
+            stats.receive.values().map(|s| s.total).sum::<u64>() as f64,
+        );
+        push(
+            "receive_count",
+            "count",
+            stats.receive.values().map(|s| s.count).sum::<u64>() as f64,
+        );
+        if stats.connect_time.count > 0 {
+            push(
+                "connect_time_mean",
+                "microseconds",
+                stats.connect_time.average(),
+            );
+        }
+
+        for (trace_id, hist) in &stats.profiles {
+            if hist.stat.count == 0 {
+                continue;
+            }
+            if let Some(trace) = traces.get(*trace_id as usize) {
+                push(
+                    &format!("trace/{trace}"),
+                    "microseconds",
+                    hist.stat.average(),
+                );
+            }
+        }
+    }
+
+    Ok((driver, metrics))
+}
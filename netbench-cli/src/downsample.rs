@@ -0,0 +1,159 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+/// Splits `points` into series by `key` and downsamples each series to at most `target` points
+///
+/// The points in each series are expected to be ordered by their x value.
+pub fn by_series<T, K, KeyFn, XyFn>(points: Vec<T>, target: usize, key: KeyFn, xy: XyFn) -> Vec<T>
+where
+    T: Copy,
+    K: Ord,
+    KeyFn: Fn(&T) -> K,
+    XyFn: Fn(&T) -> (f64, f64) + Copy,
+{
+    let mut series: BTreeMap<K, Vec<T>> = BTreeMap::new();
+    for point in points {
+        series.entry(key(&point)).or_default().push(point);
+    }
+
+    series
+        .into_values()
+        .flat_map(|points| lttb(&points, target, xy))
+        .collect()
+}
+
+/// Downsamples a series with the Largest-Triangle-Three-Buckets algorithm
+///
+/// LTTB keeps the first and last points and, for each bucket in between, the
+/// point forming the largest triangle with its neighbors. This preserves the
+/// visual shape of the series (including peaks) much better than averaging.
+///
+/// See <https://skemman.is/bitstream/1946/15343/3/SS_MSthesis.pdf>
+fn lttb<T, XyFn>(points: &[T], target: usize, xy: XyFn) -> Vec<T>
+where
+    T: Copy,
+    XyFn: Fn(&T) -> (f64, f64),
+{
+    if target >= points.len() || target < 3 {
+        return points.to_vec();
+    }
+
+    let mut sampled = Vec::with_capacity(target);
+    sampled.push(points[0]);
+
+    // the first and last points are always kept
+    let bucket_size = (points.len() - 2) as f64 / (target - 2) as f64;
+    let mut prev = 0;
+
+    for bucket in 0..target - 2 {
+        let start = (bucket as f64 * bucket_size) as usize + 1;
+        let end = (((bucket + 1) as f64 * bucket_size) as usize + 1).min(points.len() - 1);
+
+        // average of the next bucket
+        let next_start = end;
+        let next_end = (((bucket + 2) as f64 * bucket_size) as usize + 1).min(points.len());
+        let next = &points[next_start..next_end.max(next_start + 1)];
+        let (mut avg_x, mut avg_y) = (0.0, 0.0);
+        for point in next {
+            let (x, y) = xy(point);
+            avg_x += x;
+            avg_y += y;
+        }
+        avg_x /= next.len() as f64;
+        avg_y /= next.len() as f64;
+
+        let (prev_x, prev_y) = xy(&points[prev]);
+        let mut max_area = -1.0;
+        let mut max_idx = start;
+        for (idx, point) in points.iter().enumerate().take(end).skip(start) {
+            let (x, y) = xy(point);
+            let area = ((prev_x - avg_x) * (y - prev_y) - (prev_x - x) * (avg_y - prev_y)).abs();
+            if area > max_area {
+                max_area = area;
+                max_idx = idx;
+            }
+        }
+
+        sampled.push(points[max_idx]);
+        prev = max_idx;
+    }
+
+    sampled.push(points[points.len() - 1]);
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xy(point: &(f64, f64)) -> (f64, f64) {
+        *point
+    }
+
+    fn series(len: usize) -> Vec<(f64, f64)> {
+        (0..len).map(|x| (x as f64, (x % 7) as f64)).collect()
+    }
+
+    #[test]
+    fn short_series_are_kept() {
+        let points = series(10);
+        assert_eq!(lttb(&points, 10, xy), points);
+        assert_eq!(lttb(&points, 100, xy), points);
+        assert_eq!(lttb(&points, 2, xy), points);
+        assert_eq!(lttb(&points, 0, xy), points);
+        assert!(lttb(&[], 3, xy).is_empty());
+    }
+
+    #[test]
+    fn first_and_last_points_are_kept() {
+        let points = series(1000);
+        for target in [3, 10, 999] {
+            let sampled = lttb(&points, target, xy);
+            assert_eq!(sampled.len(), target);
+            assert_eq!(sampled[0], points[0]);
+            assert_eq!(sampled[target - 1], points[999]);
+            // the sampled points stay in order
+            assert!(sampled.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        }
+    }
+
+    #[test]
+    fn peaks_are_kept() {
+        let points = [(0.0, 0.0), (1.0, 1.0), (2.0, 10.0), (3.0, 1.0), (4.0, 0.0)];
+        assert_eq!(
+            lttb(&points, 3, xy),
+            vec![(0.0, 0.0), (2.0, 10.0), (4.0, 0.0)]
+        );
+
+        let points = [
+            (0.0, 5.0),
+            (1.0, 5.0),
+            (2.0, 0.0),
+            (3.0, 5.0),
+            (4.0, 5.0),
+            (5.0, 9.0),
+            (6.0, 5.0),
+            (7.0, 5.0),
+        ];
+        assert_eq!(
+            lttb(&points, 4, xy),
+            vec![(0.0, 5.0), (2.0, 0.0), (5.0, 9.0), (7.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn series_are_downsampled_separately() {
+        let points: Vec<(u8, f64, f64)> = (0..20)
+            .map(|x| (x as u8 % 2, x as f64, (x % 3) as f64))
+            .collect();
+        let sampled = by_series(points, 4, |point| point.0, |point| (point.1, point.2));
+        assert_eq!(sampled.len(), 8);
+        assert_eq!(sampled.iter().filter(|point| point.0 == 0).count(), 4);
+        assert_eq!(sampled[0].1, 0.0);
+        assert_eq!(sampled[3].1, 18.0);
+        assert_eq!(sampled[4].1, 1.0);
+        assert_eq!(sampled[7].1, 19.0);
+    }
+}
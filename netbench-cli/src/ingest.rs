@@ -0,0 +1,176 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    report_tree::{self, Filter},
+    summary::{self, Metric},
+    Result,
+};
+use rusqlite::{params, Connection};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// The schema of the results database
+///
+/// `runs` has a row per ingested run, keyed by the orchestrator's unique id.
+/// `hosts` and `drivers` describe the environment of each run, as uploaded by
+/// the orchestrator in `metadata.json`. `metrics` has a row per scenario,
+/// driver, endpoint and summary metric, in the collector units (see
+/// `metrics.name`).
+static SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS runs (
+    id TEXT PRIMARY KEY,
+    date TEXT NOT NULL,
+    scenario_file TEXT,
+    region TEXT,
+    orchestrator_version TEXT,
+    orchestrator_git_sha TEXT
+);
+CREATE TABLE IF NOT EXISTS hosts (
+    run_id TEXT NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    endpoint TEXT NOT NULL,
+    instance_id TEXT,
+    instance_type TEXT,
+    az TEXT,
+    placement TEXT
+);
+CREATE TABLE IF NOT EXISTS drivers (
+    run_id TEXT NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    source TEXT
+);
+CREATE TABLE IF NOT EXISTS metrics (
+    run_id TEXT NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    scenario TEXT NOT NULL,
+    driver TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS metrics_by_driver ON metrics (driver, metric);
+"#;
+
+/// Loads the summary of a run into a SQLite database for historical queries
+#[derive(Debug, StructOpt)]
+pub struct Ingest {
+    /// Results directory of the run (in the same layout as `report-tree`)
+    input_dir: PathBuf,
+
+    /// Path to the SQLite database, which is created if it doesn't exist
+    #[structopt(long, default_value = "netbench.sqlite")]
+    db: PathBuf,
+
+    /// Identifier of the run
+    ///
+    /// Defaults to the `unique_id` in the run's `metadata.json` or the name of
+    /// the results directory. Ingesting a run with an existing id replaces it.
+    #[structopt(long)]
+    run_id: Option<String>,
+
+    #[structopt(flatten)]
+    filter: Filter,
+}
+
+impl Ingest {
+    pub fn run(&self) -> Result<()> {
+        let mut warnings = vec![];
+        let summaries = summary::load_tree(&self.input_dir, &self.filter, &mut warnings)?;
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+
+        let metadata = report_tree::load_metadata(&self.input_dir)?.unwrap_or_default();
+        let run = &metadata["run"];
+        let run_str = |key: &str| run[key].as_str().map(String::from);
+
+        let run_id = self
+            .run_id
+            .clone()
+            .or_else(|| run_str("unique_id"))
+            .or_else(|| {
+                self.input_dir
+                    .canonicalize()
+                    .ok()?
+                    .file_name()?
+                    .to_str()
+                    .map(String::from)
+            })
+            .ok_or("unable to determine the run id; pass --run-id")?;
+
+        let mut db = Connection::open(&self.db)?;
+        db.execute_batch("PRAGMA foreign_keys = ON;")?;
+        db.execute_batch(SCHEMA)?;
+
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM runs WHERE id = ?1", params![run_id])?;
+        tx.execute(
+            "INSERT INTO runs (id, date, scenario_file, region, orchestrator_version, orchestrator_git_sha)
+             VALUES (?1, COALESCE(?2, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')), ?3, ?4, ?5, ?6)",
+            params![
+                run_id,
+                run_str("date"),
+                run_str("scenario"),
+                run_str("region"),
+                run_str("orchestrator_version"),
+                run_str("orchestrator_git_sha"),
+            ],
+        )?;
+
+        for host in metadata["hosts"].as_array().into_iter().flatten() {
+            tx.execute(
+                "INSERT INTO hosts (run_id, endpoint, instance_id, instance_type, az, placement)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    run_id,
+                    host["endpoint"].as_str(),
+                    host["instance_id"].as_str(),
+                    host["instance_type"].as_str(),
+                    host["az"].as_str(),
+                    host["placement"].as_str(),
+                ],
+            )?;
+        }
+
+        for driver in metadata["drivers"].as_array().into_iter().flatten() {
+            tx.execute(
+                "INSERT INTO drivers (run_id, name, source) VALUES (?1, ?2, ?3)",
+                params![run_id, driver["name"].as_str(), driver["source"].as_str()],
+            )?;
+        }
+
+        let mut count = 0;
+        for summary in &summaries {
+            for metric in Metric::ALL.iter().copied() {
+                let value = if let Some(value) = summary.get(metric) {
+                    value
+                } else {
+                    continue;
+                };
+                tx.execute(
+                    "INSERT INTO metrics (run_id, scenario, driver, endpoint, metric, name, value)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        run_id,
+                        summary.scenario,
+                        summary.driver,
+                        summary.endpoint.as_str(),
+                        metric.id(),
+                        metric.name(),
+                        value,
+                    ],
+                )?;
+                count += 1;
+            }
+        }
+
+        tx.commit()?;
+
+        eprintln!(
+            "ingested run {run_id}: {count} metrics into {}",
+            self.db.display()
+        );
+
+        Ok(())
+    }
+}
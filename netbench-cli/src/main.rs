@@ -4,18 +4,37 @@
 use netbench::Result;
 use structopt::StructOpt;
 
+mod check;
+mod compare;
+mod data;
+mod downsample;
+mod ingest;
+mod openmetrics;
+mod outliers;
 mod report;
 mod report_tree;
+mod significance;
+mod summary;
+mod units;
+mod validate;
 
 #[derive(StructOpt)]
 enum Args {
     Report(report::Report),
     ReportTree(report_tree::ReportTree),
+    Compare(compare::Compare),
+    Check(check::Check),
+    Ingest(ingest::Ingest),
+    ValidateScenario(validate::ValidateScenario),
 }
 
 fn main() -> Result<()> {
     match Args::from_args() {
         Args::Report(report) => report.run(),
         Args::ReportTree(report) => report.run(),
+        Args::Compare(compare) => compare.run(),
+        Args::Check(check) => check.run(),
+        Args::Ingest(ingest) => ingest.run(),
+        Args::ValidateScenario(validate) => validate.run(),
     }
 }
@@ -0,0 +1,193 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    summary::{Metric, Summary},
+    Result,
+};
+use std::{collections::BTreeMap, io::Write, str::FromStr};
+
+/// A `key=value` label added to every exported sample
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Label {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("label should be of the form key=value: {s:?}"))?;
+        if !is_valid_name(key) {
+            return Err(format!("invalid label name: {key:?}"));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// The metric family a summary metric is exported as
+struct Family {
+    name: &'static str,
+    unit: &'static str,
+    help: &'static str,
+    quantile: Option<&'static str>,
+    /// Converts the summary value to the base unit of the family
+    scale: f64,
+}
+
+fn family(metric: Metric) -> Family {
+    macro_rules! family {
+        ($name:expr, $unit:expr, $help:expr) => {
+            family!($name, $unit, $help, None, 1.0)
+        };
+        ($name:expr, $unit:expr, $help:expr, $quantile:expr, $scale:expr) => {
+            Family {
+                name: $name,
+                unit: $unit,
+                help: $help,
+                quantile: $quantile,
+                scale: $scale,
+            }
+        };
+    }
+
+    match metric {
+        Metric::Duration => family!(
+            "netbench_duration_seconds",
+            "seconds",
+            "Duration of the driver run"
+        ),
+        Metric::SendBytes => family!("netbench_send_bytes", "bytes", "Total bytes sent"),
+        Metric::ReceiveBytes => family!("netbench_receive_bytes", "bytes", "Total bytes received"),
+        Metric::SendThroughput => family!(
+            "netbench_send_throughput_bytes_per_second",
+            "bytes_per_second",
+            "Mean send throughput"
+        ),
+        Metric::ReceiveThroughput => family!(
+            "netbench_receive_throughput_bytes_per_second",
+            "bytes_per_second",
+            "Mean receive throughput"
+        ),
+        Metric::ConnectTime => family!(
+            "netbench_connect_time_seconds",
+            "seconds",
+            "Mean connection establishment time",
+            None,
+            1e-6
+        ),
+        Metric::LatencyP50 => family!(
+            "netbench_latency_seconds",
+            "seconds",
+            "Latency percentiles of the profiled traces",
+            Some("0.5"),
+            1e-6
+        ),
+        Metric::LatencyP90 => family!(
+            "netbench_latency_seconds",
+            "seconds",
+            "Latency percentiles of the profiled traces",
+            Some("0.9"),
+            1e-6
+        ),
+        Metric::LatencyP99 => family!(
+            "netbench_latency_seconds",
+            "seconds",
+            "Latency percentiles of the profiled traces",
+            Some("0.99"),
+            1e-6
+        ),
+        Metric::Cpu => family!("netbench_cpu_percent", "percent", "Mean cpu utilization"),
+        Metric::MaxMemory => family!(
+            "netbench_memory_max_bytes",
+            "bytes",
+            "Maximum resident memory"
+        ),
+    }
+}
+
+/// Writes the summaries as OpenMetrics text
+///
+/// Each summary metric is exported as a gauge sample labeled with the
+/// scenario, driver and endpoint, in addition to the provided run `labels`.
+pub fn write<W: Write>(summaries: &[Summary], labels: &[Label], mut out: W) -> Result<()> {
+    // families are grouped together in the exposition format
+    let mut families: BTreeMap<&'static str, (Family, Vec<String>)> = BTreeMap::new();
+
+    for summary in summaries {
+        for metric in Metric::ALL.iter().copied() {
+            let value = if let Some(value) = summary.get(metric) {
+                value
+            } else {
+                continue;
+            };
+
+            let family = family(metric);
+            let mut sample_labels = vec![
+                ("scenario", summary.scenario.as_str()),
+                ("driver", summary.driver.as_str()),
+                ("endpoint", summary.endpoint.as_str()),
+            ];
+            if let Some(quantile) = family.quantile {
+                sample_labels.push(("quantile", quantile));
+            }
+            sample_labels.extend(
+                labels
+                    .iter()
+                    .map(|label| (label.key.as_str(), label.value.as_str())),
+            );
+
+            let sample = format!(
+                "{}{{{}}} {}",
+                family.name,
+                format_labels(&sample_labels),
+                value * family.scale
+            );
+
+            families
+                .entry(family.name)
+                .or_insert_with(|| (family, vec![]))
+                .1
+                .push(sample);
+        }
+    }
+
+    for (name, (family, samples)) in families {
+        writeln!(out, "# TYPE {name} gauge")?;
+        writeln!(out, "# UNIT {name} {}", family.unit)?;
+        writeln!(out, "# HELP {name} {}", family.help)?;
+        for sample in samples {
+            writeln!(out, "{sample}")?;
+        }
+    }
+    writeln!(out, "# EOF")?;
+
+    Ok(())
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{key}=\"{value}\"")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns true if `name` is a valid OpenMetrics label name
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,162 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+/// The maximum number of segments reported for a single series
+const MAX_SEGMENTS: usize = 5;
+
+/// Series shorter than this don't have enough points for a meaningful deviation
+const MIN_POINTS: usize = 10;
+
+/// A contiguous range of points which deviate from the mean of the series
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub start: u64,
+    pub end: u64,
+    pub mean: f64,
+    /// The value furthest from the mean in the segment
+    pub peak: f64,
+    /// The distance of the peak from the mean, in standard deviations
+    pub deviations: f64,
+}
+
+impl Segment {
+    pub fn is_above(&self) -> bool {
+        self.peak > self.mean
+    }
+}
+
+/// Finds the segments of a series which deviate more than `threshold` standard
+/// deviations from the mean (eg. connection stalls or throughput cliffs)
+///
+/// `points` are `(x, y)` pairs ordered by `x`. Only the largest segments are returned.
+pub fn segments(points: &[(u64, f64)], threshold: f64) -> Vec<Segment> {
+    if points.len() < MIN_POINTS || threshold <= 0.0 {
+        return vec![];
+    }
+
+    let count = points.len() as f64;
+    let mean = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+    let variance = points
+        .iter()
+        .map(|(_, y)| (y - mean) * (y - mean))
+        .sum::<f64>()
+        / count;
+    let stddev = variance.sqrt();
+    if !stddev.is_normal() {
+        return vec![];
+    }
+
+    let mut segments: Vec<Segment> = vec![];
+    let mut current: Option<Segment> = None;
+
+    for (x, y) in points.iter().copied() {
+        let deviations = (y - mean) / stddev;
+        if deviations.abs() <= threshold {
+            segments.extend(current.take());
+            continue;
+        }
+
+        match current.as_mut() {
+            // extend the segment if it's deviating in the same direction
+            Some(segment) if segment.is_above() == (y > mean) => {
+                segment.end = x;
+                if deviations.abs() > segment.deviations {
+                    segment.peak = y;
+                    segment.deviations = deviations.abs();
+                }
+            }
+            _ => {
+                segments.extend(current.take());
+                current = Some(Segment {
+                    start: x,
+                    end: x,
+                    mean,
+                    peak: y,
+                    deviations: deviations.abs(),
+                });
+            }
+        }
+    }
+    segments.extend(current);
+
+    segments.sort_by(|a, b| b.deviations.total_cmp(&a.deviations));
+    segments.truncate(MAX_SEGMENTS);
+    segments.sort_by_key(|segment| segment.start);
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: &[f64]) -> Vec<(u64, f64)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(x, y)| (x as u64 * 10, *y))
+            .collect()
+    }
+
+    #[test]
+    fn short_series_have_no_segments() {
+        assert!(segments(&[], 1.0).is_empty());
+        assert!(segments(&[(0, 100.0)], 1.0).is_empty());
+        assert!(segments(&series(&[0.0, 100.0, 0.0]), 1.0).is_empty());
+        // a flat series doesn't deviate
+        assert!(segments(&series(&[5.0; 20]), 1.0).is_empty());
+    }
+
+    #[test]
+    fn segments_at_the_start_and_end() {
+        let mut values = vec![0.0, 0.0];
+        values.extend([50.0; 16]);
+        values.extend([100.0, 100.0]);
+
+        // the mean is 50 and the standard deviation about 22.4
+        let segments = segments(&series(&values), 2.0);
+        assert_eq!(segments.len(), 2);
+
+        assert_eq!((segments[0].start, segments[0].end), (0, 10));
+        assert_eq!(segments[0].mean, 50.0);
+        assert_eq!(segments[0].peak, 0.0);
+        assert!(!segments[0].is_above());
+
+        assert_eq!((segments[1].start, segments[1].end), (180, 190));
+        assert_eq!(segments[1].peak, 100.0);
+        assert!(segments[1].is_above());
+        assert!((segments[1].deviations - 5f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn threshold_boundary() {
+        // every point is exactly one standard deviation from the mean
+        let values = [0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 2.0, 2.0, 2.0, 2.0];
+        assert!(segments(&series(&values), 1.0).is_empty());
+
+        // the segments are split when the deviation changes direction
+        let segments = segments(&series(&values), 0.99);
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].start, segments[0].end), (0, 40));
+        assert_eq!((segments[1].start, segments[1].end), (50, 90));
+        assert_eq!(segments[1].deviations, 1.0);
+    }
+
+    #[test]
+    fn largest_segments_are_kept() {
+        let mut values = vec![];
+        for peak in 1..=8 {
+            values.extend([0.0; 5]);
+            values.push(peak as f64 * 10.0);
+        }
+        let segments = segments(&series(&values), 1.0);
+        assert_eq!(segments.len(), MAX_SEGMENTS);
+        // ordered by position, with the smallest peaks dropped
+        assert_eq!(
+            segments
+                .iter()
+                .map(|segment| segment.peak)
+                .collect::<Vec<_>>(),
+            vec![40.0, 50.0, 60.0, 70.0, 80.0]
+        );
+    }
+}
@@ -1,15 +1,15 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::Result;
+use crate::{downsample, outliers, significance, units::Units, Result};
 use netbench::stats::{Initialize, Stats};
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{
-    collections::{BTreeSet, HashMap},
-    io::BufRead,
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
 };
 use structopt::StructOpt;
 
@@ -27,32 +27,121 @@ pub struct Report {
     pub inputs: Vec<PathBuf>,
     #[structopt(short, long)]
     pub output: Option<PathBuf>,
+
+    /// Align the inputs on a common clock using the recorded start times
+    ///
+    /// Useful when combining results from different hosts (eg. clients and
+    /// servers) so that events line up on a shared time axis.
+    #[structopt(long)]
+    pub align: bool,
+
+    /// Downsample each series to at most this many points
+    ///
+    /// Long runs can produce enough points to freeze the browser. Series are
+    /// downsampled with LTTB, which preserves peaks and the overall shape.
+    #[structopt(long)]
+    pub max_points: Option<usize>,
+
+    #[structopt(flatten)]
+    pub units: Units,
+
+    /// Annotate segments of each series which deviate from the mean by more
+    /// than this many standard deviations
+    #[structopt(long)]
+    pub outlier_threshold: Option<f64>,
+
+    /// Combine inputs with the same driver and file name (eg. repeated
+    /// iterations) into a single series of means with a 95% confidence band
+    #[structopt(long)]
+    pub aggregate: bool,
+}
+
+/// Diagnostics collected while generating a report
+#[derive(Debug, Default)]
+pub struct Rendered {
+    /// Inputs which were skipped or truncated
+    pub warnings: Vec<String>,
+    /// Outliers found in the results
+    pub findings: Vec<String>,
 }
 
 impl Report {
+    /// Returns the offset of each input's start time from the earliest start time
+    ///
+    /// Inputs which can't be read are given an offset of 0.
+    fn clock_offsets(&self) -> Vec<u64> {
+        let start_times: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|input| read_initialize(input).ok().map(|init| init.start_time))
+            .collect();
+
+        let earliest = if let Some(earliest) = start_times.iter().flatten().min() {
+            *earliest
+        } else {
+            return vec![0; self.inputs.len()];
+        };
+
+        start_times
+            .iter()
+            .map(|start_time| {
+                start_time
+                    .and_then(|start_time| start_time.duration_since(earliest).ok())
+                    .unwrap_or_default()
+                    .as_millis() as u64
+            })
+            .collect()
+    }
+
     pub fn run(&self) -> Result<()> {
+        let Rendered { warnings, findings } = self.render()?;
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+        for finding in findings {
+            eprintln!("finding: {finding}");
+        }
+        Ok(())
+    }
+
+    /// Generates the report, skipping any inputs which are missing or corrupt
+    pub fn render(&self) -> Result<Rendered> {
+        let mut warnings = vec![];
         let mut stats_table = vec![];
         let mut stream_table = vec![];
         let mut signals = vec![];
         let mut names = vec![];
+        let mut pid_scenarios = vec![];
         let mut scenario_names = BTreeSet::new();
         let mut stream_ids = HashMap::new();
         let mut trace_ids = vec![];
         let mut pids = vec![];
+        let mut pid_paths = vec![];
 
-        for (pid, input) in self.inputs.iter().enumerate() {
-            let pid = pid as u64;
-            let input = std::fs::File::open(input)?;
-            let mut input = std::io::BufReader::new(input);
-
-            let mut first = String::new();
-            input.read_line(&mut first)?;
-            let Initialize {
-                driver,
-                scenario,
-                traces,
-                ..
-            } = serde_json::from_str(&first)?;
+        let clock_offsets = if self.align {
+            self.clock_offsets()
+        } else {
+            vec![0; self.inputs.len()]
+        };
+
+        for (input_idx, input_path) in self.inputs.iter().enumerate() {
+            let clock_offset = clock_offsets[input_idx];
+            let (
+                Initialize {
+                    driver,
+                    scenario,
+                    traces,
+                    ..
+                },
+                input,
+            ) = match open_input(input_path) {
+                Ok(input) => input,
+                Err(err) => {
+                    warnings.push(format!("{}: skipped: {err}", input_path.display()));
+                    continue;
+                }
+            };
+            let pid = pids.len() as u64;
 
             let name = driver
                 .split('/')
@@ -69,14 +158,26 @@ impl Report {
                 .trim_end_matches(".json");
 
             scenario_names.insert(scenario_name.to_string());
+            pid_scenarios.push(scenario_name.to_string());
 
             pids.push(format!("!indata('data$hidden', 'name', {name:?})"));
             names.push(name);
+            pid_paths.push(input_path);
 
             let input = serde_json::de::IoRead::new(input);
             let input = serde_json::StreamDeserializer::new(input);
-            let mut prev_x = 0;
+            let mut prev_x = clock_offset;
             for event in input {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(err) => {
+                        // keep the results up to this point; a truncated upload
+                        // shouldn't invalidate the entire run
+                        warnings.push(format!("{}: truncated: {err}", input_path.display()));
+                        break;
+                    }
+                };
+
                 let Stats {
                     time,
                     cpu,
@@ -96,9 +197,9 @@ impl Report {
                     receive,
                     connect_time,
                     profiles,
-                } = event?;
+                } = event;
 
-                let x = time.as_millis() as u64;
+                let x = time.as_millis() as u64 + clock_offset;
 
                 macro_rules! emit {
                     ($name:ident, $value:expr) => {{
@@ -112,6 +213,8 @@ impl Report {
                             pid,
                             stat: Stat::$name as _,
                             stream_id: None,
+                            lo: None,
+                            hi: None,
                         });
                     }};
                     ($name:ident, $value:expr, $id:expr) => {{
@@ -128,6 +231,8 @@ impl Report {
                                 pid,
                                 stat: Stat::StreamSendBytes as _,
                                 stream_id: Some(next_id),
+                                lo: None,
+                                hi: None,
                             });
                             stream_table.push(Row {
                                 x: prev_x,
@@ -135,6 +240,8 @@ impl Report {
                                 pid,
                                 stat: Stat::StreamReceiveBytes as _,
                                 stream_id: Some(next_id),
+                                lo: None,
+                                hi: None,
                             });
                             next_id
                         });
@@ -144,6 +251,8 @@ impl Report {
                             pid,
                             stat: Stat::$name as _,
                             stream_id: Some(*stream_id),
+                            lo: None,
+                            hi: None,
                         });
                     };};
                 }
@@ -175,6 +284,11 @@ impl Report {
                     }
                     emit!(SendBytes, bytes);
                     emit!(SendCount, count);
+                    emit!(
+                        SendThroughput,
+                        self.units
+                            .throughput(bytes as f64, x.saturating_sub(prev_x) as f64)
+                    );
                     emit!(SendBytesPerCpu, bytes as f64 / cpu as f64);
                     emit!(SendBytesPerInstruction, bytes as f64 / instructions as f64);
                 }
@@ -189,6 +303,11 @@ impl Report {
                     }
                     emit!(ReceiveBytes, bytes);
                     emit!(ReceiveCount, count);
+                    emit!(
+                        ReceiveThroughput,
+                        self.units
+                            .throughput(bytes as f64, x.saturating_sub(prev_x) as f64)
+                    );
                     emit!(ReceiveBytesPerCpu, bytes as f64 / cpu as f64);
                     emit!(
                         ReceiveBytesPerInstruction,
@@ -203,8 +322,7 @@ impl Report {
                         y = 0.0;
                     }
 
-                    // convert micros to seconds
-                    y /= 1_000_000.0;
+                    let y = self.units.latency(y);
 
                     stats_table.push(Row {
                         x,
@@ -212,11 +330,17 @@ impl Report {
                         pid,
                         stat: Stat::ConnectTime as _,
                         stream_id: None,
+                        lo: None,
+                        hi: None,
                     });
                 }
 
                 for (trace_id, hist) in profiles {
-                    let trace = &traces[trace_id as usize];
+                    let trace = if let Some(trace) = traces.get(trace_id as usize) {
+                        trace
+                    } else {
+                        continue;
+                    };
                     let trace_id = if let Some(id) = trace_ids.iter().position(|v| v == trace) {
                         id as u64
                     } else {
@@ -233,8 +357,7 @@ impl Report {
                         y = 0.0;
                     }
 
-                    // convert micros to seconds
-                    let y = y / 1_000_000.0;
+                    let y = self.units.latency(y);
 
                     stats_table.push(Row {
                         x,
@@ -242,6 +365,8 @@ impl Report {
                         pid,
                         stat,
                         stream_id: None,
+                        lo: None,
+                        hi: None,
                     });
 
                     /*
@@ -263,6 +388,71 @@ impl Report {
             }
         }
 
+        if self.aggregate {
+            // (driver, file name) -> group
+            let mut groups = BTreeMap::new();
+            let mut pid_groups = vec![];
+            let mut group_names = vec![];
+            let mut group_scenarios = vec![];
+            let mut group_pids = vec![];
+            for (pid, path) in pid_paths.iter().enumerate() {
+                let key = (
+                    names[pid].clone(),
+                    path.file_name().map(|name| name.to_owned()),
+                );
+                let group = *groups.entry(key).or_insert_with(|| {
+                    group_names.push(names[pid].clone());
+                    group_scenarios.push(pid_scenarios[pid].clone());
+                    group_pids.push(pids[pid].clone());
+                    group_names.len() as u64 - 1
+                });
+                pid_groups.push(group);
+            }
+
+            stats_table = aggregate(&stats_table, &pid_groups);
+            names = group_names;
+            pid_scenarios = group_scenarios;
+            pids = group_pids;
+
+            // streams differ between iterations so they can't be combined
+            stream_table.clear();
+            stream_ids.clear();
+        }
+
+        // detect outliers before downsampling so the statistics cover every point
+        let mut segments = BTreeMap::new();
+        if let Some(threshold) = self.outlier_threshold {
+            let mut series: BTreeMap<(u64, u64), Vec<(u64, f64)>> = BTreeMap::new();
+            for row in &stats_table {
+                series
+                    .entry((row.pid, row.stat))
+                    .or_default()
+                    .push((row.x, row.y));
+            }
+            for (key, points) in series {
+                let found = outliers::segments(&points, threshold);
+                if !found.is_empty() {
+                    segments.insert(key, found);
+                }
+            }
+        }
+
+        if let Some(max_points) = self.max_points {
+            let xy = |row: &Row| (row.x as f64, row.y);
+            stats_table = downsample::by_series(
+                stats_table,
+                max_points,
+                |row| (row.pid, row.stat, row.stream_id),
+                xy,
+            );
+            stream_table = downsample::by_series(
+                stream_table,
+                max_points,
+                |row| (row.pid, row.stat, row.stream_id),
+                xy,
+            );
+        }
+
         stats_table.sort_by(|a, b| {
             a.x.cmp(&b.x)
                 .then(a.pid.cmp(&b.pid))
@@ -277,11 +467,47 @@ impl Report {
 
         let mut view_names = Stat::NAMES
             .iter()
-            .map(|v| v.to_string())
+            .map(|v| self.units.label(v))
             .collect::<Vec<_>>();
 
         for trace in &trace_ids {
-            view_names.push(format!("trace - {trace}"));
+            view_names.push(format!("trace - {trace} ({})", self.units.latency_unit));
+        }
+
+        let mut findings = vec![];
+        let mut outlier_table = vec![];
+        {
+            // the chart only has the x values which survived downsampling so
+            // snap the segments to the closest ones
+            let mut xs: Vec<u64> = stats_table.iter().map(|row| row.x).collect();
+            xs.dedup();
+            let snap = |x: u64| match xs.binary_search(&x) {
+                Ok(idx) => xs[idx],
+                Err(idx) => xs[idx.min(xs.len() - 1)],
+            };
+
+            for ((pid, stat), found) in &segments {
+                let name = &names[*pid as usize];
+                let scenario = &pid_scenarios[*pid as usize];
+                let view = &view_names[*stat as usize];
+                for segment in found {
+                    findings.push(format!(
+                        "{scenario}/{name}: {view} {} the mean by {:.1} standard deviations from {:.1}s to {:.1}s (peak {:.2}, mean {:.2})",
+                        if segment.is_above() { "above" } else { "below" },
+                        segment.deviations,
+                        (segment.start as f64) / 1000.0,
+                        (segment.end as f64) / 1000.0,
+                        segment.peak,
+                        segment.mean,
+                    ));
+                    outlier_table.push(json!({
+                        "x": snap(segment.start),
+                        "x2": snap(segment.end),
+                        "p": pid,
+                        "s": stat,
+                    }));
+                }
+            }
         }
 
         // expose an option to select the view
@@ -349,46 +575,160 @@ impl Report {
             .unwrap()
             .extend(signals);
 
-        let data = root.get_mut("data").unwrap().as_array_mut().unwrap();
+        let template_data = root.remove("data").unwrap();
+        let outliers = json!({
+            "name": "data$outliers",
+            "values": outlier_table,
+        });
+        let drivers = json!({
+            "name": "data$drivers",
+            "values": names.iter().map(|name|{
+                json!({ "name": name })
+            }).collect::<Vec<_>>()
+        });
 
-        data.insert(
-            0,
-            json!({
-                "name": "data$stats",
-                "values": &stats_table,
-            }),
-        );
-        data.insert(
-            1,
-            json!({
-                "name": "data$streams",
-                "values": &stream_table,
-            }),
-        );
-        data.insert(
-            2,
-            json!({
-                "name": "data$drivers",
-                "values": names.iter().map(|name|{
-                    json!({ "name": name })
-                }).collect::<Vec<_>>()
-            }),
-        );
+        let mut data = vec![
+            Dataset::Rows {
+                name: "data$stats",
+                values: &stats_table,
+            },
+            Dataset::Rows {
+                name: "data$streams",
+                values: &stream_table,
+            },
+            Dataset::Value(&drivers),
+            Dataset::Value(&outliers),
+        ];
+        data.extend(template_data.as_array().unwrap().iter().map(Dataset::Value));
+
+        let report = Output { root, data };
 
         if let Some(out_path) = self.output.as_ref() {
             if let Some(parent) = out_path.parent() {
                 std::fs::create_dir_all(parent)?;
             }
-            let mut out_file = std::fs::File::create(out_path)?;
-            serde_json::to_writer(&mut out_file, &output)?;
+            let out_file = std::fs::File::create(out_path)?;
+            let mut out_file = std::io::BufWriter::new(out_file);
+            serde_json::to_writer(&mut out_file, &report)?;
+            out_file.flush()?;
         } else {
-            serde_json::to_writer(std::io::stdout(), &output)?;
+            let out = std::io::stdout();
+            let mut out = std::io::BufWriter::new(out.lock());
+            serde_json::to_writer(&mut out, &report)?;
+            out.flush()?;
         }
 
-        Ok(())
+        Ok(Rendered { warnings, findings })
     }
 }
 
+/// Serializes the report directly from the tables
+///
+/// Converting the tables into a `serde_json::Value` takes several times more
+/// memory than the rows themselves, which limited the length of the runs that
+/// could be reported on.
+struct Output<'a> {
+    root: &'a serde_json::Map<String, serde_json::Value>,
+    data: Vec<Dataset<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Dataset<'a> {
+    Rows {
+        name: &'static str,
+        values: &'a [Row],
+    },
+    Value(&'a serde_json::Value),
+}
+
+impl Serialize for Output<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.root.len() + 1))?;
+        for (key, value) in self.root {
+            map.serialize_entry(key, value)?;
+        }
+        map.serialize_entry("data", &self.data)?;
+        map.end()
+    }
+}
+
+/// Combines the series of each pid in a group into a single series of means
+///
+/// Inputs of the same scenario are sampled at the same interval so the k-th
+/// rows of each input are aligned. The rows of each pid must be in time order.
+fn aggregate(rows: &[Row], pid_groups: &[u64]) -> Vec<Row> {
+    // (group, stat) -> k -> samples
+    let mut series: BTreeMap<(u64, u64), Vec<Vec<(u64, f64)>>> = BTreeMap::new();
+    let mut positions: HashMap<(u64, u64), usize> = HashMap::new();
+
+    for row in rows {
+        let position = positions.entry((row.pid, row.stat)).or_default();
+        let samples = series
+            .entry((pid_groups[row.pid as usize], row.stat))
+            .or_default();
+        if samples.len() <= *position {
+            samples.push(vec![]);
+        }
+        samples[*position].push((row.x, row.y));
+        *position += 1;
+    }
+
+    let mut aggregated = vec![];
+    for ((pid, stat), samples) in series {
+        for sample in samples {
+            let count = sample.len() as f64;
+            let x = sample.iter().map(|(x, _)| *x).sum::<u64>() / sample.len() as u64;
+            let y = sample.iter().map(|(_, y)| y).sum::<f64>() / count;
+
+            let (lo, hi) = if sample.len() > 1 {
+                let variance =
+                    sample.iter().map(|(_, v)| (v - y) * (v - y)).sum::<f64>() / (count - 1.0);
+                let ci = significance::t_critical(sample.len() - 1) * (variance / count).sqrt();
+                (Some(y - ci), Some(y + ci))
+            } else {
+                (None, None)
+            };
+
+            aggregated.push(Row {
+                x,
+                y,
+                pid,
+                stat,
+                stream_id: None,
+                lo,
+                hi,
+            });
+        }
+    }
+
+    aggregated
+}
+
+/// Reads the [`Initialize`] line at the start of a result file
+fn read_initialize(path: &Path) -> Result<Initialize> {
+    Ok(open_input(path)?.0)
+}
+
+/// Opens a result file and reads the [`Initialize`] line
+///
+/// The returned reader is positioned at the first [`Stats`] event.
+fn open_input(path: &Path) -> Result<(Initialize, std::io::BufReader<std::fs::File>)> {
+    let input = std::fs::File::open(path)?;
+    let mut input = std::io::BufReader::new(input);
+
+    let mut first = String::new();
+    input.read_line(&mut first)?;
+    let init = serde_json::from_str(&first)?;
+
+    Ok((init, input))
+}
+
 macro_rules! stat {
     (enum Stat { $($name:ident = $desc:expr),* $(,)? }) => {
         #[repr(u64)]
@@ -415,7 +755,7 @@ stat!(
         ContextSwitches = "context-switches",
         Syscalls = "syscalls",
         Connections = "connections",
-        ConnectTime = "connect-time",
+        ConnectTime = "connect-time ({latency})",
         Accept = "accept (streams)",
         AllocBytes = "alloc (bytes)",
         AllocCount = "alloc (count)",
@@ -425,10 +765,12 @@ stat!(
         DeallocCount = "dealloc (count)",
         SendBytes = "send (bytes)",
         SendCount = "send (count)",
+        SendThroughput = "send throughput ({throughput})",
         SendBytesPerCpu = "send (bytes/cpu %)",
         SendBytesPerInstruction = "send (bytes/instruction)",
         ReceiveBytes = "receive (bytes)",
         ReceiveCount = "receive (count)",
+        ReceiveThroughput = "receive throughput ({throughput})",
         ReceiveBytesPerCpu = "receive (bytes/cpu %)",
         ReceiveBytesPerInstruction = "receive (bytes/instruction)",
         StreamSendBytes = "stream send",
@@ -446,6 +788,12 @@ struct Row {
     stat: u64,
     #[serde(rename = "i", skip_serializing_if = "Option::is_none")]
     stream_id: Option<u64>,
+    /// Lower bound of the confidence interval for aggregated rows
+    #[serde(rename = "l", skip_serializing_if = "Option::is_none")]
+    lo: Option<f64>,
+    /// Upper bound of the confidence interval for aggregated rows
+    #[serde(rename = "h", skip_serializing_if = "Option::is_none")]
+    hi: Option<f64>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -1,18 +1,148 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{report::Report, Result};
+use crate::{
+    data,
+    openmetrics::{self, Label},
+    report::{Rendered, Report},
+    significance::{self, Comparison},
+    summary,
+    units::Units,
+    Result,
+};
+use serde::Deserialize;
 use serde_json::json;
 use std::{
     collections::BTreeMap,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
 };
 use structopt::StructOpt;
 
-#[derive(Debug, StructOpt)]
+#[derive(Clone, Debug, StructOpt)]
 pub struct ReportTree {
+    /// Results directory, or an `s3://` prefix to download the results from
     input_dir: PathBuf,
     out_dir: PathBuf,
+
+    /// Output format of the report: html, csv, tsv or openmetrics
+    ///
+    /// The csv and tsv formats emit a `summary.{csv,tsv}` file with a row of
+    /// summary statistics per driver and scenario. The openmetrics format emits
+    /// the same statistics as a `metrics.txt` file which can be pushed to
+    /// Prometheus.
+    #[structopt(long, default_value = "html")]
+    format: Format,
+
+    /// Label (`key=value`) to add to every exported OpenMetrics sample
+    #[structopt(long = "label")]
+    labels: Vec<Label>,
+
+    #[structopt(flatten)]
+    filter: Filter,
+
+    #[structopt(flatten)]
+    units: Units,
+
+    /// Downsample each series in the html report to at most this many points
+    ///
+    /// A value of 0 disables downsampling.
+    #[structopt(long, default_value = "2000")]
+    max_points: usize,
+
+    /// Flag segments of each series which deviate from the mean by more than
+    /// this many standard deviations
+    ///
+    /// A value of 0 disables outlier detection.
+    #[structopt(long, default_value = "3")]
+    outlier_threshold: f64,
+
+    /// Significance level used when comparing drivers across iterations
+    #[structopt(long, default_value = "0.05")]
+    alpha: f64,
+
+    /// Regenerate the report whenever new results land in the input directory
+    ///
+    /// Useful for viewing partial results while a long run is in progress.
+    #[structopt(long)]
+    watch: bool,
+
+    /// Seconds between checks for new results in watch mode
+    #[structopt(long, default_value = "10")]
+    watch_interval: u64,
+
+    /// Handlebars template to use instead of the built-in `index.html` template
+    ///
+    /// See the README for the context passed to the template.
+    #[structopt(long)]
+    template: Option<PathBuf>,
+
+    /// Number of reports to render in parallel
+    ///
+    /// Defaults to the number of available CPUs.
+    #[structopt(long)]
+    jobs: Option<usize>,
+}
+
+/// Selects a subset of the drivers and scenarios in a results tree
+#[derive(Clone, Debug, Default, StructOpt)]
+pub struct Filter {
+    /// Only include the specified drivers (eg. "s2n-quic" or "client-s2n-quic")
+    #[structopt(long)]
+    include_driver: Vec<String>,
+
+    /// Exclude the specified drivers (eg. "tcp" or "server-tcp")
+    #[structopt(long)]
+    exclude_driver: Vec<String>,
+
+    /// Only include the specified scenarios
+    #[structopt(long)]
+    scenario: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, result: &ResultFile) -> bool {
+        let driver_matches = |name: &String| {
+            let driver = result
+                .driver
+                .trim_start_matches("client-")
+                .trim_start_matches("server-");
+            result.driver == *name || driver == name
+        };
+
+        if !self.scenario.is_empty() && !self.scenario.contains(&result.scenario) {
+            return false;
+        }
+
+        if !self.include_driver.is_empty() && !self.include_driver.iter().any(driver_matches) {
+            return false;
+        }
+
+        !self.exclude_driver.iter().any(driver_matches)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Csv,
+    Tsv,
+    OpenMetrics,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Format::Html),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "openmetrics" => Ok(Format::OpenMetrics),
+            _ => Err(format!("unsupported format: {s:?}")),
+        }
+    }
 }
 
 static INDEX_HTML: &str = include_str!("./report_tree.html");
@@ -21,74 +151,260 @@ type ScenarioMap = BTreeMap<String, Report>;
 
 impl ReportTree {
     pub fn run(&self) -> Result<()> {
-        let mut client_scenarios: ScenarioMap = Default::default();
-        let mut server_scenarios: ScenarioMap = Default::default();
+        let s3_prefix = self
+            .input_dir
+            .to_str()
+            .filter(|input_dir| input_dir.starts_with("s3://"))
+            .map(String::from);
 
-        for scenario in self.input_dir.read_dir()? {
-            let scenario = scenario?;
-            let path = scenario.path();
-            let scenario_name = if let Some(name) = path_name(&path) {
-                name
-            } else {
-                continue;
-            };
+        let mut tree = self.clone();
+        if s3_prefix.is_some() {
+            tree.input_dir = self.out_dir.join(".results");
+        }
 
-            // scenario.path() = sample/results/requst_response_incast
-            for driver in scenario.path().read_dir()? {
-                let driver_path = driver?.path();
-
-                macro_rules! push_scenario {
-                    ($target:ident, $json_result_path:ident, $url_name:literal) => {{
-                        $target
-                            .entry(scenario_name.to_string())
-                            .or_insert_with(|| Report {
-                                output: Some(self.out_dir.join(scenario_name).join($url_name)),
-                                ..Default::default()
-                            })
-                            .inputs
-                            .push($json_result_path);
-                    }};
+        if !self.watch {
+            if let Some(s3_prefix) = &s3_prefix {
+                sync_s3(s3_prefix, &tree.input_dir)?;
+            }
+            return tree.run_once();
+        }
+
+        let mut last_fingerprint = None;
+        loop {
+            if let Some(s3_prefix) = &s3_prefix {
+                // the results may still be uploading; try again on the next poll
+                if let Err(err) = sync_s3(s3_prefix, &tree.input_dir) {
+                    eprintln!("warning: {err}");
                 }
+            }
 
-                // driver_path = sample/results/requst_response_incast/server-tcp
-                for json_result in driver_path.read_dir()? {
-                    let json_result_path = json_result?.path();
+            // the report is written to `out_dir`, which may be inside the results tree
+            let out_dir = self.out_dir.canonicalize().ok();
+            let fingerprint = fingerprint(&tree.input_dir, out_dir.as_deref());
+            if last_fingerprint.as_ref() != Some(&fingerprint) {
+                match tree.run_once() {
+                    Ok(()) => eprintln!(
+                        "report updated ({} files in the results tree)",
+                        fingerprint.len()
+                    ),
+                    Err(err) => eprintln!("warning: failed to generate report: {err}"),
+                }
+                last_fingerprint = Some(fingerprint);
+            }
 
-                    let json_result_filename = json_result_path
-                        .file_name()
-                        .expect("expected file path")
-                        .to_str()
-                        .unwrap();
+            std::thread::sleep(core::time::Duration::from_secs(self.watch_interval));
+        }
+    }
 
-                    // only process .json log files
-                    if !json_result_filename.ends_with(".json") {
-                        continue;
-                    }
+    fn run_once(&self) -> Result<()> {
+        match self.format {
+            Format::Html => self.run_html(),
+            Format::Csv => self.run_table("summary.csv", ','),
+            Format::Tsv => self.run_table("summary.tsv", '\t'),
+            Format::OpenMetrics => self.run_openmetrics(),
+        }
+    }
 
-                    if json_result_filename.contains("server") {
-                        push_scenario!(server_scenarios, json_result_path, "servers.json");
-                    } else if json_result_filename.contains("client") {
-                        push_scenario!(client_scenarios, json_result_path, "clients.json");
-                    } else {
-                        panic!(
-                            "result filename: {}, should contain either 'client' or 'server'",
-                            json_result_filename
-                        )
+    fn run_openmetrics(&self) -> Result<()> {
+        let mut warnings = vec![];
+        let summaries = summary::load_tree(&self.input_dir, &self.filter, &mut warnings)?;
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+
+        // label the samples with the run described by the orchestrator
+        let mut labels = vec![];
+        if let Some(metadata) = load_metadata(&self.input_dir)? {
+            if let Some(run) = metadata.get("run").and_then(|run| run.as_object()) {
+                for (key, value) in run {
+                    let key = format!("run_{key}");
+                    if let (true, Some(value)) = (openmetrics::is_valid_name(&key), value.as_str())
+                    {
+                        labels.push(Label {
+                            key,
+                            value: value.to_string(),
+                        });
                     }
                 }
             }
         }
+        labels.extend(self.labels.iter().cloned());
 
         std::fs::create_dir_all(&self.out_dir)?;
+        let out_file = std::fs::File::create(self.out_dir.join("metrics.txt"))?;
+        let out_file = std::io::BufWriter::new(out_file);
+        openmetrics::write(&summaries, &labels, out_file)?;
+
+        Ok(())
+    }
+
+    fn jobs(&self) -> usize {
+        self.jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|jobs| jobs.get())
+                .unwrap_or(1)
+        })
+    }
+
+    /// Compares the drivers in each scenario, treating each result file as a sample
+    fn compare_drivers(&self, warnings: &mut Vec<String>) -> Result<Vec<Comparison>> {
+        let samples = summary::load_samples(&self.input_dir, &self.filter, warnings)?;
+        Ok(significance::compare_drivers(&samples, self.alpha))
+    }
+
+    fn outlier_threshold(&self) -> Option<f64> {
+        Some(self.outlier_threshold).filter(|threshold| *threshold > 0.0)
+    }
+
+    fn max_points(&self) -> Option<usize> {
+        Some(self.max_points).filter(|max_points| *max_points > 0)
+    }
+
+    fn run_table(&self, filename: &str, delimiter: char) -> Result<()> {
+        let mut warnings = vec![];
+        let summaries = summary::load_tree(&self.input_dir, &self.filter, &mut warnings)?;
+        for warning in warnings {
+            eprintln!("warning: {warning}");
+        }
+
+        std::fs::create_dir_all(&self.out_dir)?;
+        let out_file = std::fs::File::create(self.out_dir.join(filename))?;
+        let out_file = std::io::BufWriter::new(out_file);
+        summary::write_table(&summaries, delimiter, &self.units, out_file)?;
+
+        let comparisons = self.compare_drivers(&mut Vec::new())?;
+        if !comparisons.is_empty() {
+            let extension = Path::new(filename)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or_default();
+            let out_file =
+                std::fs::File::create(self.out_dir.join(format!("significance.{extension}")))?;
+            let out_file = std::io::BufWriter::new(out_file);
+            significance::write_table(&comparisons, delimiter, &self.units, out_file)?;
+        }
+
+        Ok(())
+    }
+
+    fn run_html(&self) -> Result<()> {
+        let mut client_scenarios: ScenarioMap = Default::default();
+        let mut server_scenarios: ScenarioMap = Default::default();
+        // clients and servers on a common clock
+        let mut timeline_scenarios: ScenarioMap = Default::default();
+        let mut warnings = vec![];
+
+        let results: Vec<_> = result_files(&self.input_dir, &mut warnings)?
+            .into_iter()
+            .filter(|result| self.filter.matches(result))
+            .collect();
+
+        // repeated runs are combined into a single series per driver
+        let aggregate = results.iter().any(|result| result.iteration.is_some());
+
+        for ResultFile {
+            scenario,
+            endpoint,
+            path,
+            ..
+        } in results.iter().cloned()
+        {
+            let target = match endpoint {
+                Endpoint::Server => &mut server_scenarios,
+                Endpoint::Client => &mut client_scenarios,
+            };
+            target
+                .entry(scenario.clone())
+                .or_insert_with(|| Report {
+                    output: Some(self.out_dir.join(&scenario).join(endpoint.report_name())),
+                    max_points: self.max_points(),
+                    units: self.units,
+                    outlier_threshold: self.outlier_threshold(),
+                    aggregate,
+                    ..Default::default()
+                })
+                .inputs
+                .push(path.clone());
+
+            timeline_scenarios
+                .entry(scenario.clone())
+                .or_insert_with(|| Report {
+                    output: Some(self.out_dir.join(&scenario).join("timeline.json")),
+                    align: true,
+                    max_points: self.max_points(),
+                    units: self.units,
+                    outlier_threshold: self.outlier_threshold(),
+                    ..Default::default()
+                })
+                .inputs
+                .push(path);
+        }
+
+        std::fs::create_dir_all(&self.out_dir)?;
+
+        let metadata = load_metadata(&self.input_dir)?;
+
+        let events = load_events(&self.input_dir)?;
+        let coordination = load_coordination(&self.input_dir)?;
+        let clock = load_clock(&self.input_dir, &mut warnings)?;
+        let significance: Vec<_> = self
+            .compare_drivers(&mut Vec::new())?
+            .iter()
+            .map(|comparison| significance::render(comparison, &self.units))
+            .collect();
+
+        data::write(&self.out_dir.join("data"), &results, &mut Vec::new())?;
+
+        let flamegraphs = self.copy_flamegraphs(&mut warnings)?;
+
+        let clients: Vec<_> = client_scenarios.keys().cloned().collect();
+        let servers: Vec<_> = server_scenarios.keys().cloned().collect();
+        let timelines: Vec<_> = timeline_scenarios.keys().cloned().collect();
+
+        // the timelines read the same inputs as the clients and servers so any
+        // warnings and findings are already covered
+        let Rendered {
+            warnings: mut report_warnings,
+            mut findings,
+        } = render_reports(
+            client_scenarios
+                .into_values()
+                .chain(server_scenarios.into_values())
+                .collect(),
+            self.jobs(),
+        )?;
+        render_reports(timeline_scenarios.into_values().collect(), self.jobs())?;
+        report_warnings.sort();
+        warnings.extend(report_warnings);
+        findings.sort();
+
+        for warning in &warnings {
+            eprintln!("warning: {warning}");
+        }
 
         let index = {
+            let custom_template = self
+                .template
+                .as_ref()
+                .map(std::fs::read_to_string)
+                .transpose()?;
             let template = handlebars::Handlebars::new();
 
             template.render_template(
-                INDEX_HTML,
+                custom_template.as_deref().unwrap_or(INDEX_HTML),
                 &json!({
-                    "clients": render_scenarios(client_scenarios)?,
-                    "servers": render_scenarios(server_scenarios)?,
+                    "clients": clients,
+                    "servers": servers,
+                    "timelines": timelines,
+                    "metadata": metadata,
+                    "events": events,
+                    "coordination": coordination,
+                    "clock": clock,
+                    "flamegraphs": flamegraphs,
+                    "warnings": warnings,
+                    "findings": findings,
+                    "significance": significance,
+                    "alpha": self.alpha,
                 }),
             )?
         };
@@ -99,13 +415,528 @@ impl ReportTree {
     }
 }
 
-fn render_scenarios(scenarios: ScenarioMap) -> Result<Vec<String>> {
-    let mut names = vec![];
-    for (name, report) in scenarios {
-        names.push(name);
-        report.run()?;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Endpoint {
+    Client,
+    Server,
+}
+
+impl Endpoint {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::Client => "client",
+            Endpoint::Server => "server",
+        }
+    }
+
+    fn report_name(&self) -> &'static str {
+        match self {
+            Endpoint::Client => "clients.json",
+            Endpoint::Server => "servers.json",
+        }
+    }
+}
+
+/// A netbench result file found in a results tree
+#[derive(Clone, Debug)]
+pub struct ResultFile {
+    pub scenario: String,
+    /// The name of the driver directory (eg. "server-tcp")
+    pub driver: String,
+    pub endpoint: Endpoint,
+    /// The iteration of the driver pair, if the run was repeated
+    pub iteration: Option<u32>,
+    pub path: PathBuf,
+}
+
+impl ResultFile {
+    /// The path of the file relative to the driver directory
+    pub fn relative_name(&self) -> String {
+        let filename = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if let Some(iteration) = self.iteration {
+            format!("{}/{filename}", iteration_dir(iteration))
+        } else {
+            filename
+        }
+    }
+}
+
+/// The name of the directory containing the results of an iteration
+pub fn iteration_dir(iteration: u32) -> String {
+    format!("iteration-{iteration}")
+}
+
+fn parse_iteration_dir(name: &str) -> Option<u32> {
+    name.strip_prefix("iteration-")?.parse().ok()
+}
+
+/// Walks a results tree and returns all of the netbench result files
+///
+/// The tree is expected to be laid out as `<input_dir>/<scenario>/<driver>/*.json`.
+pub fn result_files(input_dir: &Path, warnings: &mut Vec<String>) -> Result<Vec<ResultFile>> {
+    walk_tree(input_dir, ".json", warnings)
+}
+
+/// Walks a results tree and returns all of the flamegraphs captured while profiling
+///
+/// Flamegraphs are placed next to the netbench results as `<input_dir>/<scenario>/<driver>/*.svg`.
+pub fn flamegraph_files(input_dir: &Path, warnings: &mut Vec<String>) -> Result<Vec<ResultFile>> {
+    walk_tree(input_dir, ".svg", warnings)
+}
+
+/// Files which don't match the expected layout are skipped and recorded in `warnings`
+fn walk_tree(
+    input_dir: &Path,
+    extension: &str,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<ResultFile>> {
+    let mut results = vec![];
+
+    for scenario in input_dir.read_dir()? {
+        let scenario = scenario?;
+        let path = scenario.path();
+
+        // skip any files in the root of the tree (eg. metadata.json)
+        if !path.is_dir() {
+            continue;
+        }
+
+        let scenario_name = if let Some(name) = path_name(&path) {
+            name
+        } else {
+            continue;
+        };
+
+        // scenario.path() = sample/results/requst_response_incast
+        for driver in scenario.path().read_dir()? {
+            let driver_path = driver?.path();
+            if !driver_path.is_dir() {
+                continue;
+            }
+            let driver_name = if let Some(name) = path_name(&driver_path) {
+                name.to_string()
+            } else {
+                continue;
+            };
+
+            // driver_path = sample/results/requst_response_incast/server-tcp
+            //
+            // repeated runs place each iteration in a subdirectory
+            // (eg. server-tcp/iteration-0)
+            let mut dirs = vec![(driver_path, None)];
+            while let Some((dir, iteration)) = dirs.pop() {
+                for json_result in dir.read_dir()? {
+                    let json_result_path = json_result?.path();
+
+                    let Some(json_result_filename) =
+                        json_result_path.file_name().and_then(|name| name.to_str())
+                    else {
+                        warnings.push(format!(
+                            "{}: skipped: filename isn't valid utf-8",
+                            json_result_path.display()
+                        ));
+                        continue;
+                    };
+
+                    if iteration.is_none() && json_result_path.is_dir() {
+                        if let Some(iteration) = parse_iteration_dir(json_result_filename) {
+                            dirs.push((json_result_path.clone(), Some(iteration)));
+                        }
+                        continue;
+                    }
+
+                    // only process files with the requested extension
+                    if !json_result_filename.ends_with(extension) {
+                        continue;
+                    }
+
+                    let endpoint = if json_result_filename.contains("server") {
+                        Endpoint::Server
+                    } else if json_result_filename.contains("client") {
+                        Endpoint::Client
+                    } else {
+                        warnings.push(format!(
+                            "{}: skipped: filename should contain either 'client' or 'server'",
+                            json_result_path.display()
+                        ));
+                        continue;
+                    };
+
+                    results.push(ResultFile {
+                        scenario: scenario_name.to_string(),
+                        driver: driver_name.clone(),
+                        endpoint,
+                        iteration,
+                        path: json_result_path,
+                    });
+                }
+            }
+        }
+    }
+
+    results.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(results)
+}
+
+impl ReportTree {
+    /// Copies any captured flamegraphs into the report and returns the links
+    /// to them, grouped by scenario
+    fn copy_flamegraphs(
+        &self,
+        warnings: &mut Vec<String>,
+    ) -> Result<BTreeMap<String, Vec<serde_json::Value>>> {
+        let mut flamegraphs: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+
+        for flamegraph in flamegraph_files(&self.input_dir, warnings)?
+            .into_iter()
+            .filter(|result| self.filter.matches(result))
+        {
+            let filename = flamegraph.relative_name();
+            let href = format!("{}/{}/{}", flamegraph.scenario, flamegraph.driver, filename);
+
+            let out_path = self.out_dir.join(&href);
+            if let Some(parent) = out_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&flamegraph.path, out_path)?;
+
+            flamegraphs
+                .entry(flamegraph.scenario)
+                .or_default()
+                .push(json!({
+                    "driver": flamegraph.driver,
+                    "name": filename,
+                    "href": href,
+                }));
+        }
+
+        Ok(flamegraphs)
+    }
+}
+
+/// Renders the reports on up to `jobs` threads
+///
+/// Each thread renders a single report at a time, which bounds the memory
+/// used to `jobs` reports.
+fn render_reports(reports: Vec<Report>, jobs: usize) -> Result<Rendered> {
+    let jobs = jobs.clamp(1, reports.len().max(1));
+    let queue = Mutex::new(reports.into_iter());
+
+    let results: Vec<Result<Rendered>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| -> Result<Rendered> {
+                    let mut rendered = Rendered::default();
+                    loop {
+                        let report = queue.lock().unwrap().next();
+                        let Some(report) = report else {
+                            break;
+                        };
+                        let Rendered { warnings, findings } = report.render()?;
+                        rendered.warnings.extend(warnings);
+                        rendered.findings.extend(findings);
+                    }
+                    Ok(rendered)
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("report worker panicked"))
+            .collect()
+    });
+
+    let mut rendered = Rendered::default();
+    for result in results {
+        let Rendered { warnings, findings } = result?;
+        rendered.warnings.extend(warnings);
+        rendered.findings.extend(findings);
+    }
+    Ok(rendered)
+}
+
+/// Loads the description of the run which the orchestrator uploads to the
+/// root of the tree, if present
+pub fn load_metadata(input_dir: &Path) -> Result<Option<serde_json::Value>> {
+    let path = input_dir.join("metadata.json");
+    if !path.exists() {
+        return Ok(None);
     }
-    Ok(names)
+    let metadata = std::fs::File::open(path)?;
+    Ok(Some(serde_json::from_reader(std::io::BufReader::new(
+        metadata,
+    ))?))
+}
+
+#[derive(Debug, Deserialize)]
+struct OrchestratorEvents {
+    events: Vec<OrchestratorEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OrchestratorEvent {
+    name: String,
+    phase: String,
+    start_ms: u64,
+    duration_ms: u64,
+}
+
+/// Loads the phase timeline uploaded by the orchestrator, if present
+///
+/// Each event is positioned relative to the length of the run so it can be
+/// drawn as a bar without any scripting in the template.
+fn load_events(input_dir: &Path) -> Result<Option<serde_json::Value>> {
+    let path = input_dir.join("events.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let events = std::fs::File::open(path)?;
+    let OrchestratorEvents { events } = serde_json::from_reader(std::io::BufReader::new(events))?;
+
+    let total_ms = events
+        .iter()
+        .map(|event| event.start_ms + event.duration_ms)
+        .max()
+        .unwrap_or_default()
+        .max(1) as f64;
+
+    let mut phase_totals: BTreeMap<&str, u64> = BTreeMap::new();
+    for event in &events {
+        *phase_totals.entry(&event.phase).or_default() += event.duration_ms;
+    }
+
+    let rows: Vec<_> = events
+        .iter()
+        .map(|event| {
+            json!({
+                "name": event.name,
+                "phase": event.phase,
+                "start": format!("{:.1}", event.start_ms as f64 / 1000.0),
+                "duration": format!("{:.1}", event.duration_ms as f64 / 1000.0),
+                "offset_pct": format!("{:.2}", event.start_ms as f64 / total_ms * 100.0),
+                "width_pct": format!("{:.2}", event.duration_ms as f64 / total_ms * 100.0),
+            })
+        })
+        .collect();
+
+    let totals: BTreeMap<_, _> = phase_totals
+        .into_iter()
+        .map(|(phase, duration_ms)| {
+            (
+                phase,
+                format!(
+                    "{:.1}s ({:.1}%)",
+                    duration_ms as f64 / 1000.0,
+                    duration_ms as f64 / total_ms * 100.0
+                ),
+            )
+        })
+        .collect();
+
+    Ok(Some(json!({
+        "rows": rows,
+        "totals": totals,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct CoordinationPairs {
+    pairs: Vec<CoordinationPair>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoordinationPair {
+    pair: String,
+    wall_ms: u64,
+    measurement_ms: u64,
+    #[serde(default)]
+    workers: Vec<CoordinationWorkflow>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoordinationWorkflow {
+    endpoint: String,
+    #[serde(default)]
+    states: Vec<CoordinationState>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CoordinationState {
+    state: String,
+    duration_ms: u64,
+}
+
+/// Loads the Russula state durations uploaded by the orchestrator, if present
+///
+/// Splits the wall time of each driver pair into the time the clients spent
+/// running netbench and the time spent coordinating the hosts (starting the
+/// workers, waiting for peers and copying results). The mean time the workers
+/// spent in each state shows where the coordination time goes.
+fn load_coordination(input_dir: &Path) -> Result<Option<serde_json::Value>> {
+    let path = input_dir.join("coordination.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = std::fs::File::open(path)?;
+    let CoordinationPairs { pairs } = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+    let row = |name: &str, wall_ms: u64, measurement_ms: u64| {
+        let coordination_ms = wall_ms.saturating_sub(measurement_ms);
+        json!({
+            "pair": name,
+            "wall": format!("{:.1}", wall_ms as f64 / 1000.0),
+            "measurement": format!("{:.1}", measurement_ms as f64 / 1000.0),
+            "coordination": format!("{:.1}", coordination_ms as f64 / 1000.0),
+            "coordination_pct": format!("{:.1}", coordination_ms as f64 / wall_ms.max(1) as f64 * 100.0),
+        })
+    };
+
+    let mut rows: Vec<_> = pairs
+        .iter()
+        .map(|pair| row(&pair.pair, pair.wall_ms, pair.measurement_ms))
+        .collect();
+    let wall_ms = pairs.iter().map(|pair| pair.wall_ms).sum();
+    let measurement_ms = pairs.iter().map(|pair| pair.measurement_ms).sum();
+    rows.push(row("total", wall_ms, measurement_ms));
+
+    let mut state_totals: BTreeMap<(&str, &str), (u64, u64)> = BTreeMap::new();
+    for worker in pairs.iter().flat_map(|pair| pair.workers.iter()) {
+        for state in &worker.states {
+            let (total_ms, count) = state_totals
+                .entry((worker.endpoint.as_str(), state.state.as_str()))
+                .or_default();
+            *total_ms += state.duration_ms;
+            *count += 1;
+        }
+    }
+    let states: Vec<_> = state_totals
+        .into_iter()
+        .map(|((endpoint, state), (total_ms, count))| {
+            json!({
+                "endpoint": endpoint,
+                "state": state,
+                "mean": format!("{:.1}", total_ms as f64 / count as f64 / 1000.0),
+            })
+        })
+        .collect();
+
+    Ok(Some(json!({
+        "rows": rows,
+        "states": states,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct HostClocks {
+    max_skew_ms: f64,
+    host_skew_ms: f64,
+    hosts: Vec<HostClock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HostClock {
+    endpoint: String,
+    instance_id: String,
+    ntp_offset_ms: f64,
+    synchronised: bool,
+    orchestrator_skew_ms: i64,
+    uncertainty_ms: u64,
+}
+
+/// Loads the host clocks checked by the orchestrator before the run, if present
+///
+/// The client and server timelines are aligned by their timestamps, so a skew
+/// between the hosts above the orchestrator's threshold is also reported as a
+/// warning.
+fn load_clock(input_dir: &Path, warnings: &mut Vec<String>) -> Result<Option<serde_json::Value>> {
+    let path = input_dir.join("clock.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = std::fs::File::open(path)?;
+    let clocks: HostClocks = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+    if clocks.host_skew_ms > clocks.max_skew_ms {
+        warnings.push(format!(
+            "the host clocks were {:.3}ms apart (more than {}ms), so the client and server timelines may be misaligned",
+            clocks.host_skew_ms, clocks.max_skew_ms
+        ));
+    }
+
+    let hosts: Vec<_> = clocks
+        .hosts
+        .iter()
+        .map(|host| {
+            json!({
+                "endpoint": host.endpoint,
+                "instance_id": host.instance_id,
+                "ntp_offset": format!("{:.3}", host.ntp_offset_ms),
+                "synchronised": host.synchronised,
+                "orchestrator_skew": host.orchestrator_skew_ms,
+                "uncertainty": host.uncertainty_ms,
+            })
+        })
+        .collect();
+
+    Ok(Some(json!({
+        "host_skew": format!("{:.3}", clocks.host_skew_ms),
+        "max_skew": clocks.max_skew_ms,
+        "hosts": hosts,
+    })))
+}
+
+/// Downloads the results under an S3 prefix with the aws cli
+fn sync_s3(s3_prefix: &str, local_dir: &Path) -> Result<()> {
+    let status = std::process::Command::new("aws")
+        .args(["s3", "sync", "--only-show-errors", s3_prefix])
+        .arg(local_dir)
+        .status()?;
+    if !status.success() {
+        return Err(format!("failed to sync {s3_prefix}: {status}").into());
+    }
+    Ok(())
+}
+
+/// Returns the path, size and modification time of every file in a directory, except for
+/// the files in the canonical `exclude` directory
+///
+/// Used to detect new or updated results in watch mode.
+fn fingerprint(
+    dir: &Path,
+    exclude: Option<&Path>,
+) -> Vec<(PathBuf, u64, Option<std::time::SystemTime>)> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                if exclude.is_some() && path.canonicalize().ok().as_deref() == exclude {
+                    continue;
+                }
+                dirs.push(path);
+            } else {
+                files.push((path, metadata.len(), metadata.modified().ok()));
+            }
+        }
+    }
+    files.sort();
+    files
 }
 
 fn path_name(path: &Path) -> Option<&str> {
@@ -118,3 +949,61 @@ fn path_name(path: &Path) -> Option<&str> {
 
     Some(stem)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for each test, since the tests run in parallel
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "netbench-report-tree-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_result_files_are_skipped() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = test_dir("non-utf8");
+        let driver = dir.join("request_response").join("tcp");
+        std::fs::create_dir_all(&driver).unwrap();
+        std::fs::write(driver.join("client.json"), "{}").unwrap();
+        std::fs::write(driver.join(OsStr::from_bytes(b"server-\xff.json")), "{}").unwrap();
+
+        let mut warnings = vec![];
+        let results = result_files(&dir, &mut warnings).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].endpoint, Endpoint::Client);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].ends_with("skipped: filename isn't valid utf-8"));
+    }
+
+    #[test]
+    fn fingerprint_excludes_the_out_dir() {
+        let dir = test_dir("fingerprint");
+        let driver = dir.join("request_response").join("tcp");
+        let out_dir = dir.join("report");
+        std::fs::create_dir_all(&driver).unwrap();
+        std::fs::create_dir_all(&out_dir).unwrap();
+        std::fs::write(driver.join("client.json"), "{}").unwrap();
+
+        let exclude = out_dir.canonicalize().unwrap();
+        let before = fingerprint(&dir, Some(&exclude));
+        std::fs::write(out_dir.join("index.html"), "").unwrap();
+        let after = fingerprint(&dir, Some(&exclude));
+        let unfiltered = fingerprint(&dir, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(before, after);
+        assert_eq!(after.len(), 1);
+        assert_eq!(unfiltered.len(), 2);
+    }
+}
@@ -0,0 +1,410 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    summary::{self, Direction, Metric, Summary},
+    units::Units,
+    Result,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// A comparison of a single metric between two drivers in the same scenario
+///
+/// Each result file for a driver is treated as a sample, so a driver run on 2
+/// hosts for 3 iterations has 6 samples, rather than 3.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub scenario: String,
+    pub endpoint: &'static str,
+    pub metric: Metric,
+    pub a: Sample,
+    pub b: Sample,
+    /// Two-sided p-value of the Mann-Whitney U test
+    pub p_value: f64,
+    pub significant: bool,
+}
+
+/// The mean of a driver's samples with a 95% confidence interval
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub driver: String,
+    pub count: usize,
+    pub mean: f64,
+    /// Half-width of the confidence interval
+    pub ci: f64,
+}
+
+/// Compares every pair of drivers with at least two samples for each metric
+pub fn compare_drivers(samples: &[Summary], alpha: f64) -> Vec<Comparison> {
+    // (scenario, endpoint) -> driver -> samples
+    let mut groups: BTreeMap<_, BTreeMap<&str, Vec<&Summary>>> = BTreeMap::new();
+    for sample in samples {
+        groups
+            .entry((&sample.scenario, sample.endpoint))
+            .or_default()
+            .entry(&sample.driver)
+            .or_default()
+            .push(sample);
+    }
+
+    let mut comparisons = vec![];
+    for ((scenario, endpoint), drivers) in groups {
+        let drivers: Vec<_> = drivers
+            .into_iter()
+            .filter(|(_, samples)| samples.len() > 1)
+            .collect();
+
+        for (idx, (a_driver, a_samples)) in drivers.iter().enumerate() {
+            for (b_driver, b_samples) in drivers.iter().skip(idx + 1) {
+                for metric in Metric::ALL.iter().copied() {
+                    if metric.direction() == Direction::Neutral {
+                        continue;
+                    }
+
+                    let a: Vec<f64> = a_samples.iter().filter_map(|s| s.get(metric)).collect();
+                    let b: Vec<f64> = b_samples.iter().filter_map(|s| s.get(metric)).collect();
+                    if a.len() < 2 || b.len() < 2 {
+                        continue;
+                    }
+
+                    let p_value = mann_whitney(&a, &b);
+                    comparisons.push(Comparison {
+                        scenario: scenario.clone(),
+                        endpoint: endpoint.as_str(),
+                        metric,
+                        a: sample(a_driver, &a),
+                        b: sample(b_driver, &b),
+                        p_value,
+                        significant: p_value < alpha,
+                    });
+                }
+            }
+        }
+    }
+
+    comparisons
+}
+
+/// Renders a comparison in the selected units for the html report
+pub fn render(comparison: &Comparison, units: &Units) -> serde_json::Value {
+    let value = |v: f64| units.metric_value(comparison.metric, v);
+    json!({
+        "scenario": comparison.scenario,
+        "endpoint": comparison.endpoint,
+        "metric": units.metric_name(comparison.metric),
+        "a": comparison.a.driver,
+        "a_value": format!("{:.3} ± {:.3} (n={})", value(comparison.a.mean), value(comparison.a.ci), comparison.a.count),
+        "b": comparison.b.driver,
+        "b_value": format!("{:.3} ± {:.3} (n={})", value(comparison.b.mean), value(comparison.b.ci), comparison.b.count),
+        "p_value": format!("{:.4}", comparison.p_value),
+        "significant": comparison.significant,
+    })
+}
+
+/// Writes one row per comparison, separated by `delimiter`
+pub fn write_table<W: Write>(
+    comparisons: &[Comparison],
+    delimiter: char,
+    units: &Units,
+    mut out: W,
+) -> Result<()> {
+    summary::write_row(
+        &mut out,
+        delimiter,
+        [
+            "scenario",
+            "endpoint",
+            "metric",
+            "driver a",
+            "mean a",
+            "ci a",
+            "n a",
+            "driver b",
+            "mean b",
+            "ci b",
+            "n b",
+            "p-value",
+            "significant",
+        ],
+    )?;
+
+    for comparison in comparisons {
+        let value = |v: f64| units.metric_value(comparison.metric, v).to_string();
+        summary::write_row(
+            &mut out,
+            delimiter,
+            [
+                comparison.scenario.clone(),
+                comparison.endpoint.to_string(),
+                units.metric_name(comparison.metric),
+                comparison.a.driver.clone(),
+                value(comparison.a.mean),
+                value(comparison.a.ci),
+                comparison.a.count.to_string(),
+                comparison.b.driver.clone(),
+                value(comparison.b.mean),
+                value(comparison.b.ci),
+                comparison.b.count.to_string(),
+                comparison.p_value.to_string(),
+                comparison.significant.to_string(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn sample(driver: &str, values: &[f64]) -> Sample {
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (count - 1.0);
+    let ci = t_critical(values.len() - 1) * (variance / count).sqrt();
+
+    Sample {
+        driver: driver.to_string(),
+        count: values.len(),
+        mean,
+        ci,
+    }
+}
+
+/// Samples with at most this many pairs of values are tested with the exact
+/// distribution of U, which is cheap to compute for them
+const EXACT_MAX_PAIRS: usize = 100;
+
+/// Two-tailed critical values of the t distribution for a 95% confidence interval
+pub fn t_critical(degrees_of_freedom: usize) -> f64 {
+    const TABLE: &[f64] = &[
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    TABLE
+        .get(degrees_of_freedom.saturating_sub(1))
+        .copied()
+        .unwrap_or(1.960)
+}
+
+/// Returns the two-sided p-value of the Mann-Whitney U test
+///
+/// Small samples use the exact permutation distribution of U, since the normal
+/// approximation is too coarse for them. Even the exact test can't go below
+/// `2 / C(n1 + n2, n1)`, so with fewer than 4 samples per driver no difference
+/// is significant at the 0.05 level. Larger samples use the normal
+/// approximation with a tie correction.
+fn mann_whitney(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    let n1 = a.len() as f64;
+    let n2 = b.len() as f64;
+
+    let mut values: Vec<(f64, bool)> = a
+        .iter()
+        .map(|v| (*v, true))
+        .chain(b.iter().map(|v| (*v, false)))
+        .collect();
+    values.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // assign the average rank to ties. The ranks are doubled, so that the
+    // average ranks of ties are whole numbers.
+    let mut ranks = Vec::with_capacity(values.len());
+    let mut rank_sum_a = 0;
+    let mut tie_correction = 0.0;
+    let mut idx = 0;
+    while idx < values.len() {
+        let mut end = idx + 1;
+        while end < values.len() && values[end].0 == values[idx].0 {
+            end += 1;
+        }
+        let ties = (end - idx) as f64;
+        let rank = idx + end + 1;
+        ranks.resize(end, rank);
+        rank_sum_a += rank * values[idx..end].iter().filter(|(_, is_a)| *is_a).count();
+        tie_correction += ties * ties * ties - ties;
+        idx = end;
+    }
+
+    if a.len() * b.len() <= EXACT_MAX_PAIRS {
+        return exact_p_value(&ranks, a.len(), rank_sum_a);
+    }
+
+    let rank_sum_a = rank_sum_a as f64 / 2.0;
+    let u = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return 1.0;
+    }
+
+    // continuity correction
+    let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    (2.0 * (1.0 - normal_cdf(z))).min(1.0)
+}
+
+/// The share of the ways to pick `n1` of the (doubled) `ranks` whose sum is at
+/// least as far from its mean as `rank_sum`
+fn exact_p_value(ranks: &[usize], n1: usize, rank_sum: usize) -> f64 {
+    let max_sum: usize = ranks.iter().sum();
+    // counts[k][sum] is the number of ways to pick k of the ranks with the sum
+    let mut counts = vec![vec![0u64; max_sum + 1]; n1 + 1];
+    counts[0][0] = 1;
+    for (idx, rank) in ranks.iter().copied().enumerate() {
+        for k in (1..=n1.min(idx + 1)).rev() {
+            for sum in (rank..=max_sum).rev() {
+                counts[k][sum] += counts[k - 1][sum - rank];
+            }
+        }
+    }
+
+    let mean = (n1 * (ranks.len() + 1)) as i64;
+    let observed = (rank_sum as i64 - mean).abs();
+    let (mut extreme, mut total) = (0, 0);
+    for (sum, count) in counts[n1].iter().copied().enumerate() {
+        total += count;
+        if (sum as i64 - mean).abs() >= observed {
+            extreme += count;
+        }
+    }
+    extreme as f64 / total as f64
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / core::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun approximation 7.1.26
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0
+        - (((((1.061405429 * t - 1.453152027) * t) + 1.421413741) * t - 0.284496736) * t
+            + 0.254829592)
+            * t
+            * (-x * x).exp();
+    sign * y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report_tree::Endpoint;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    fn summary(driver: &str, throughput: f64) -> Summary {
+        Summary {
+            scenario: "request_response".to_string(),
+            driver: driver.to_string(),
+            endpoint: Endpoint::Client,
+            values: [
+                (Metric::ReceiveThroughput, throughput),
+                (Metric::Duration, 10.0),
+            ]
+            .into_iter()
+            .collect(),
+            stddev: BTreeMap::new(),
+            iterations: 1,
+        }
+    }
+
+    #[test]
+    fn exact_mann_whitney() {
+        // completely separated samples are the 2 most extreme of C(n1 + n2, n1) splits
+        assert_close(mann_whitney(&[1.0, 2.0], &[3.0, 4.0]), 2.0 / 6.0);
+        assert_close(mann_whitney(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]), 2.0 / 20.0);
+        assert_close(
+            mann_whitney(&[5.0, 6.0, 7.0, 8.0], &[1.0, 2.0, 3.0, 4.0]),
+            2.0 / 70.0,
+        );
+        // U = 1 of 9, ie. 2 splits with U = 0 and 2 with U = 1 on each side
+        assert_close(mann_whitney(&[1.0, 2.0, 4.0], &[3.0, 5.0, 6.0]), 4.0 / 20.0);
+
+        assert_close(mann_whitney(&[1.0, 2.0], &[1.0, 2.0]), 1.0);
+        assert_close(mann_whitney(&[3.0, 3.0, 3.0], &[3.0, 3.0, 3.0]), 1.0);
+        // ties between the samples get the average rank
+        assert_close(mann_whitney(&[1.0, 2.0, 2.0], &[2.0, 3.0, 4.0]), 0.3);
+        assert_close(mann_whitney(&[], &[1.0]), 1.0);
+    }
+
+    #[test]
+    fn approximate_mann_whitney() {
+        let a: Vec<f64> = (0..11).map(f64::from).collect();
+        let b: Vec<f64> = (11..22).map(f64::from).collect();
+        // z = 60 / sqrt(121 * 23 / 12)
+        assert_close(
+            mann_whitney(&a, &b),
+            2.0 * (1.0 - normal_cdf(60.0 / (121.0f64 * 23.0 / 12.0).sqrt())),
+        );
+        assert!(mann_whitney(&a, &b) < 0.001);
+
+        let b: Vec<f64> = (0..11).map(|v| v as f64 + 0.5).collect();
+        assert!(mann_whitney(&a, &b) > 0.5);
+    }
+
+    #[test]
+    fn t_critical_values() {
+        assert_eq!(t_critical(0), 12.706);
+        assert_eq!(t_critical(1), 12.706);
+        assert_eq!(t_critical(2), 4.303);
+        assert_eq!(t_critical(30), 2.042);
+        assert_eq!(t_critical(31), 1.960);
+        assert_eq!(t_critical(1000), 1.960);
+    }
+
+    #[test]
+    fn normal_distribution() {
+        assert_close(erf(0.0), 0.0);
+        assert_close(erf(0.5), 0.520_499_877_8);
+        assert_close(erf(1.0), 0.842_700_792_9);
+        assert_close(erf(-1.0), -0.842_700_792_9);
+        assert_close(erf(3.0), 0.999_977_909_5);
+
+        assert_close(normal_cdf(0.0), 0.5);
+        assert_close(normal_cdf(1.0), 0.841_344_746_1);
+        assert_close(normal_cdf(1.959_964), 0.975);
+        assert_close(normal_cdf(-1.959_964), 0.025);
+    }
+
+    #[test]
+    fn drivers_are_compared() {
+        let samples = vec![
+            summary("s2n-quic", 10.0),
+            summary("s2n-quic", 11.0),
+            summary("s2n-quic", 12.0),
+            summary("s2n-quic", 13.0),
+            summary("tcp", 1.0),
+            summary("tcp", 2.0),
+            summary("tcp", 3.0),
+            summary("tcp", 4.0),
+            // a single sample can't be compared
+            summary("quic", 4.0),
+        ];
+        let comparisons = compare_drivers(&samples, 0.05);
+
+        // the neutral duration isn't compared
+        assert_eq!(comparisons.len(), 1);
+        let comparison = &comparisons[0];
+        assert_eq!(comparison.metric, Metric::ReceiveThroughput);
+        assert_eq!(comparison.endpoint, "client");
+        assert_eq!(comparison.a.driver, "s2n-quic");
+        assert_eq!(comparison.a.count, 4);
+        assert_close(comparison.a.mean, 11.5);
+        // 3.182 * sqrt(5 / 3 / 4)
+        assert_close(comparison.a.ci, 3.182 * (5.0f64 / 12.0).sqrt());
+        assert_eq!(comparison.b.driver, "tcp");
+        assert_close(comparison.p_value, 2.0 / 70.0);
+        assert!(comparison.significant);
+
+        assert!(!compare_drivers(&samples, 0.01)[0].significant);
+    }
+}
@@ -0,0 +1,464 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    report_tree::{self, Endpoint, Filter, ResultFile},
+    units::Units,
+    Result,
+};
+use netbench::stats::{Initialize, Stats};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+/// Whether a larger or smaller value of a metric is preferable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    HigherIsBetter,
+    LowerIsBetter,
+    /// The metric describes the run rather than its performance
+    Neutral,
+}
+
+macro_rules! metric {
+    (enum Metric { $($name:ident = ($id:expr, $desc:expr, $direction:ident $(,)?)),* $(,)? }) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub enum Metric {
+            $(
+                $name,
+            )*
+        }
+
+        impl Metric {
+            pub const ALL: &'static [Metric] = &[$(Metric::$name,)*];
+
+            /// A stable identifier for the metric, suitable for machine-readable outputs
+            pub fn id(&self) -> &'static str {
+                match self {
+                    $(
+                        Metric::$name => $id,
+                    )*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(
+                        Metric::$name => $desc,
+                    )*
+                }
+            }
+
+            pub fn direction(&self) -> Direction {
+                match self {
+                    $(
+                        Metric::$name => Direction::$direction,
+                    )*
+                }
+            }
+        }
+    };
+}
+
+metric!(
+    enum Metric {
+        Duration = ("duration", "duration (s)", Neutral),
+        SendBytes = ("send_bytes", "send (bytes)", Neutral),
+        ReceiveBytes = ("receive_bytes", "receive (bytes)", Neutral),
+        SendThroughput = (
+            "send_throughput",
+            "send throughput (bytes/s)",
+            HigherIsBetter,
+        ),
+        ReceiveThroughput = (
+            "receive_throughput",
+            "receive throughput (bytes/s)",
+            HigherIsBetter,
+        ),
+        ConnectTime = ("connect_time", "connect-time mean (us)", LowerIsBetter),
+        LatencyP50 = ("latency_p50", "latency p50 (us)", LowerIsBetter),
+        LatencyP90 = ("latency_p90", "latency p90 (us)", LowerIsBetter),
+        LatencyP99 = ("latency_p99", "latency p99 (us)", LowerIsBetter),
+        Cpu = ("cpu", "cpu mean (%)", LowerIsBetter),
+        MaxMemory = ("max_memory", "memory max (bytes)", LowerIsBetter),
+    }
+);
+
+/// Summary statistics for a single driver in a single scenario
+///
+/// If a driver was run on multiple hosts, the results from each host are
+/// combined into a single summary. If the driver was run for multiple
+/// iterations, `values` is the mean across the iterations.
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub scenario: String,
+    pub driver: String,
+    pub endpoint: Endpoint,
+    pub values: BTreeMap<Metric, f64>,
+    /// Sample standard deviation of each metric across the iterations
+    pub stddev: BTreeMap<Metric, f64>,
+    pub iterations: usize,
+}
+
+impl Summary {
+    pub fn get(&self, metric: Metric) -> Option<f64> {
+        self.values.get(&metric).copied()
+    }
+
+    /// Combines the values of each iteration into their mean and standard deviation
+    fn aggregate(
+        scenario: String,
+        driver: String,
+        endpoint: Endpoint,
+        iterations: Vec<BTreeMap<Metric, f64>>,
+    ) -> Self {
+        let mut values = BTreeMap::new();
+        let mut stddev = BTreeMap::new();
+
+        for metric in Metric::ALL.iter().copied() {
+            let samples: Vec<f64> = iterations
+                .iter()
+                .filter_map(|values| values.get(&metric).copied())
+                .collect();
+            if samples.is_empty() {
+                continue;
+            }
+
+            let count = samples.len() as f64;
+            let mean = samples.iter().sum::<f64>() / count;
+            values.insert(metric, mean);
+
+            if samples.len() > 1 {
+                let variance =
+                    samples.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (count - 1.0);
+                stddev.insert(metric, variance.sqrt());
+            }
+        }
+
+        Summary {
+            scenario,
+            driver,
+            endpoint,
+            values,
+            stddev,
+            iterations: iterations.len(),
+        }
+    }
+}
+
+/// A group of result files which are combined into a single sample
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SampleKey {
+    /// All of the results for the driver
+    Run,
+    /// The results of a single iteration
+    Iteration(u32),
+    /// A single result file
+    File(PathBuf),
+}
+
+/// Loads the summaries for the selected scenarios and drivers in a results tree
+///
+/// Result files which are missing or corrupt are skipped and recorded in `warnings`.
+pub fn load_tree(
+    input_dir: &Path,
+    filter: &Filter,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<Summary>> {
+    load(input_dir, filter, warnings, false)
+}
+
+/// Loads a summary for each result file in a results tree
+///
+/// Unlike [`load_tree`], the result files for a driver aren't combined so
+/// each one (ie. each host of each iteration) can be treated as a sample.
+pub fn load_samples(
+    input_dir: &Path,
+    filter: &Filter,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<Summary>> {
+    load(input_dir, filter, warnings, true)
+}
+
+fn load(
+    input_dir: &Path,
+    filter: &Filter,
+    warnings: &mut Vec<String>,
+    per_file: bool,
+) -> Result<Vec<Summary>> {
+    // (scenario, driver, endpoint) -> sample -> result files
+    let mut inputs: BTreeMap<(String, String, Endpoint), BTreeMap<SampleKey, Vec<PathBuf>>> =
+        BTreeMap::new();
+
+    for ResultFile {
+        scenario,
+        endpoint,
+        iteration,
+        path,
+        ..
+    } in report_tree::result_files(input_dir, warnings)?
+        .into_iter()
+        .filter(|result| filter.matches(result))
+    {
+        let driver = match driver_name(&path) {
+            Ok(driver) => driver,
+            Err(err) => {
+                warnings.push(format!("{}: skipped: {err}", path.display()));
+                continue;
+            }
+        };
+
+        // the results from each host in an iteration are combined, unless
+        // each host's result file is a sample of its own
+        let sample = match iteration {
+            _ if per_file => SampleKey::File(path.clone()),
+            Some(iteration) => SampleKey::Iteration(iteration),
+            None => SampleKey::Run,
+        };
+
+        inputs
+            .entry((scenario, driver, endpoint))
+            .or_default()
+            .entry(sample)
+            .or_default()
+            .push(path);
+    }
+
+    let mut summaries = vec![];
+    for ((scenario, driver, endpoint), samples) in inputs {
+        let mut iterations = vec![];
+        for paths in samples.into_values() {
+            let mut acc = Accumulator::default();
+            for path in paths {
+                // keep the results up to this point; a truncated upload shouldn't
+                // invalidate the entire run
+                if let Err(err) = acc.push_file(&path) {
+                    warnings.push(format!("{}: truncated: {err}", path.display()));
+                }
+            }
+            iterations.push(acc.finish());
+        }
+
+        if per_file {
+            for values in iterations {
+                summaries.push(Summary::aggregate(
+                    scenario.clone(),
+                    driver.clone(),
+                    endpoint,
+                    vec![values],
+                ));
+            }
+        } else {
+            summaries.push(Summary::aggregate(scenario, driver, endpoint, iterations));
+        }
+    }
+
+    Ok(summaries)
+}
+
+/// Writes one row per summary, separated by `delimiter`
+///
+/// Missing metrics are written as empty fields.
+pub fn write_table<W: Write>(
+    summaries: &[Summary],
+    delimiter: char,
+    units: &Units,
+    mut out: W,
+) -> Result<()> {
+    // only include the deviations if the run was repeated
+    let has_iterations = summaries.iter().any(|summary| summary.iterations > 1);
+
+    let mut header: Vec<String> = vec!["scenario".into(), "driver".into(), "endpoint".into()];
+    if has_iterations {
+        header.push("iterations".into());
+    }
+    for metric in Metric::ALL.iter().copied() {
+        header.push(units.metric_name(metric));
+        if has_iterations {
+            header.push(format!("{} stddev", units.metric_name(metric)));
+        }
+    }
+    write_row(&mut out, delimiter, header)?;
+
+    for summary in summaries {
+        let mut row = vec![
+            summary.scenario.clone(),
+            summary.driver.clone(),
+            summary.endpoint.as_str().to_string(),
+        ];
+        if has_iterations {
+            row.push(summary.iterations.to_string());
+        }
+        for metric in Metric::ALL.iter().copied() {
+            let value = |value: Option<f64>| {
+                value
+                    .map(|value| units.metric_value(metric, value).to_string())
+                    .unwrap_or_default()
+            };
+            row.push(value(summary.get(metric)));
+            if has_iterations {
+                row.push(value(summary.stddev.get(&metric).copied()));
+            }
+        }
+        write_row(&mut out, delimiter, row)?;
+    }
+
+    Ok(())
+}
+
+pub fn write_row<W: Write, T: AsRef<str>>(
+    out: &mut W,
+    delimiter: char,
+    fields: impl IntoIterator<Item = T>,
+) -> Result<()> {
+    let mut line = String::new();
+    for (idx, field) in fields.into_iter().enumerate() {
+        if idx > 0 {
+            line.push(delimiter);
+        }
+
+        let field = field.as_ref();
+        if field.contains(delimiter) || field.contains('"') || field.contains('\n') {
+            line.push('"');
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(field);
+        }
+    }
+    writeln!(out, "{line}")?;
+    Ok(())
+}
+
+/// Reads the driver name from the first line of a result file
+fn driver_name(path: &Path) -> Result<String> {
+    let input = std::fs::File::open(path)?;
+    let mut input = std::io::BufReader::new(input);
+    let mut first = String::new();
+    input.read_line(&mut first)?;
+    let Initialize { driver, .. } = serde_json::from_str(&first)?;
+    Ok(trim_driver_name(&driver))
+}
+
+pub fn trim_driver_name(driver: &str) -> String {
+    driver
+        .split('/')
+        .last()
+        .unwrap()
+        .trim_start_matches("netbench-driver-")
+        .trim_start_matches("s2n-netbench-driver-")
+        .to_string()
+}
+
+#[derive(Debug, Default)]
+struct Accumulator {
+    duration: u64,
+    send_bytes: u64,
+    receive_bytes: u64,
+    connect_time_total: u64,
+    connect_time_count: u64,
+    cpu_total: f64,
+    cpu_count: u64,
+    max_memory: u64,
+    // (lower, upper) -> count
+    latency: BTreeMap<(u64, u64), u64>,
+}
+
+impl Accumulator {
+    fn push_file(&mut self, path: &Path) -> Result<()> {
+        let input = std::fs::File::open(path)?;
+        let mut input = std::io::BufReader::new(input);
+
+        // skip the Initialize line
+        let mut first = String::new();
+        input.read_line(&mut first)?;
+
+        let input = serde_json::de::IoRead::new(input);
+        let input = serde_json::StreamDeserializer::new(input);
+        for event in input {
+            let stats: Stats = event?;
+            self.push(&stats);
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, stats: &Stats) {
+        self.duration = self.duration.max(stats.time.as_millis() as u64);
+        self.send_bytes += stats.send.values().map(|s| s.total).sum::<u64>();
+        self.receive_bytes += stats.receive.values().map(|s| s.total).sum::<u64>();
+        self.connect_time_total += stats.connect_time.total;
+        self.connect_time_count += stats.connect_time.count;
+        self.cpu_total += stats.cpu as f64;
+        self.cpu_count += 1;
+        self.max_memory = self.max_memory.max(stats.memory);
+
+        for hist in stats.profiles.values() {
+            for bucket in &hist.buckets {
+                *self
+                    .latency
+                    .entry((bucket.lower, bucket.upper))
+                    .or_default() += bucket.count;
+            }
+        }
+    }
+
+    fn finish(self) -> BTreeMap<Metric, f64> {
+        let mut values = BTreeMap::new();
+
+        let duration = self.duration as f64 / 1000.0;
+        values.insert(Metric::Duration, duration);
+        values.insert(Metric::SendBytes, self.send_bytes as f64);
+        values.insert(Metric::ReceiveBytes, self.receive_bytes as f64);
+        if duration > 0.0 {
+            values.insert(Metric::SendThroughput, self.send_bytes as f64 / duration);
+            values.insert(
+                Metric::ReceiveThroughput,
+                self.receive_bytes as f64 / duration,
+            );
+        }
+        if self.connect_time_count > 0 {
+            values.insert(
+                Metric::ConnectTime,
+                self.connect_time_total as f64 / self.connect_time_count as f64,
+            );
+        }
+        if self.cpu_count > 0 {
+            values.insert(Metric::Cpu, self.cpu_total / self.cpu_count as f64);
+        }
+        values.insert(Metric::MaxMemory, self.max_memory as f64);
+
+        for (metric, percentile) in [
+            (Metric::LatencyP50, 0.50),
+            (Metric::LatencyP90, 0.90),
+            (Metric::LatencyP99, 0.99),
+        ] {
+            if let Some(value) = percentile_of(&self.latency, percentile) {
+                values.insert(metric, value);
+            }
+        }
+
+        values
+    }
+}
+
+/// Returns the upper bound of the bucket containing the requested percentile
+fn percentile_of(buckets: &BTreeMap<(u64, u64), u64>, percentile: f64) -> Option<f64> {
+    let total: u64 = buckets.values().sum();
+    if total == 0 {
+        return None;
+    }
+
+    let target = (total as f64 * percentile).ceil() as u64;
+    let mut seen = 0;
+    for ((_lower, upper), count) in buckets {
+        seen += count;
+        if seen >= target {
+            return Some(*upper as f64);
+        }
+    }
+
+    buckets.keys().last().map(|(_lower, upper)| *upper as f64)
+}
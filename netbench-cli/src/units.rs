@@ -0,0 +1,185 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::summary::Metric;
+use core::fmt;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// The units used to present throughput and latency in reports
+///
+/// The collector records bytes and microseconds; values are converted from
+/// those when rendering charts and summary tables.
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct Units {
+    /// Unit for throughput: B/s, KB/s, MB/s, GB/s, Kbps, Mbps or Gbps
+    #[structopt(long, default_value = "MB/s")]
+    pub throughput_unit: ThroughputUnit,
+
+    /// Unit for latency: us, ms or s
+    #[structopt(long, default_value = "ms")]
+    pub latency_unit: LatencyUnit,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            throughput_unit: ThroughputUnit::MegabytesPerSecond,
+            latency_unit: LatencyUnit::Milliseconds,
+        }
+    }
+}
+
+impl Units {
+    /// Converts a number of bytes transferred over `millis` milliseconds
+    pub fn throughput(&self, bytes: f64, millis: f64) -> f64 {
+        if millis <= 0.0 {
+            return 0.0;
+        }
+        self.throughput_unit
+            .scale_bytes_per_second(bytes / (millis / 1000.0))
+    }
+
+    pub fn latency(&self, micros: f64) -> f64 {
+        self.latency_unit.scale_micros(micros)
+    }
+
+    /// Replaces the `{throughput}` and `{latency}` placeholders in a chart name
+    pub fn label(&self, name: &str) -> String {
+        name.replace("{throughput}", self.throughput_unit.as_str())
+            .replace("{latency}", self.latency_unit.as_str())
+    }
+
+    /// Returns the name of a summary metric in the selected units
+    pub fn metric_name(&self, metric: Metric) -> String {
+        let throughput = self.throughput_unit;
+        let latency = self.latency_unit;
+        match metric {
+            Metric::SendThroughput => format!("send throughput ({throughput})"),
+            Metric::ReceiveThroughput => format!("receive throughput ({throughput})"),
+            Metric::ConnectTime => format!("connect-time mean ({latency})"),
+            Metric::LatencyP50 => format!("latency p50 ({latency})"),
+            Metric::LatencyP90 => format!("latency p90 ({latency})"),
+            Metric::LatencyP99 => format!("latency p99 ({latency})"),
+            _ => metric.name().to_string(),
+        }
+    }
+
+    /// Converts a summary metric from the collector units to the selected units
+    pub fn metric_value(&self, metric: Metric, value: f64) -> f64 {
+        match metric {
+            Metric::SendThroughput | Metric::ReceiveThroughput => {
+                self.throughput_unit.scale_bytes_per_second(value)
+            }
+            Metric::ConnectTime | Metric::LatencyP50 | Metric::LatencyP90 | Metric::LatencyP99 => {
+                self.latency_unit.scale_micros(value)
+            }
+            _ => value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThroughputUnit {
+    BytesPerSecond,
+    KilobytesPerSecond,
+    MegabytesPerSecond,
+    GigabytesPerSecond,
+    KilobitsPerSecond,
+    MegabitsPerSecond,
+    GigabitsPerSecond,
+}
+
+impl ThroughputUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BytesPerSecond => "B/s",
+            Self::KilobytesPerSecond => "KB/s",
+            Self::MegabytesPerSecond => "MB/s",
+            Self::GigabytesPerSecond => "GB/s",
+            Self::KilobitsPerSecond => "Kbps",
+            Self::MegabitsPerSecond => "Mbps",
+            Self::GigabitsPerSecond => "Gbps",
+        }
+    }
+
+    // SI prefixes are used for both bytes and bits
+    fn scale_bytes_per_second(&self, value: f64) -> f64 {
+        match self {
+            Self::BytesPerSecond => value,
+            Self::KilobytesPerSecond => value / 1e3,
+            Self::MegabytesPerSecond => value / 1e6,
+            Self::GigabytesPerSecond => value / 1e9,
+            Self::KilobitsPerSecond => value * 8.0 / 1e3,
+            Self::MegabitsPerSecond => value * 8.0 / 1e6,
+            Self::GigabitsPerSecond => value * 8.0 / 1e9,
+        }
+    }
+}
+
+impl FromStr for ThroughputUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "B/s" => Ok(Self::BytesPerSecond),
+            "KB/s" => Ok(Self::KilobytesPerSecond),
+            "MB/s" => Ok(Self::MegabytesPerSecond),
+            "GB/s" => Ok(Self::GigabytesPerSecond),
+            "Kbps" => Ok(Self::KilobitsPerSecond),
+            "Mbps" => Ok(Self::MegabitsPerSecond),
+            "Gbps" => Ok(Self::GigabitsPerSecond),
+            _ => Err(format!("unsupported throughput unit: {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for ThroughputUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LatencyUnit {
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl LatencyUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Microseconds => "us",
+            Self::Milliseconds => "ms",
+            Self::Seconds => "s",
+        }
+    }
+
+    fn scale_micros(&self, value: f64) -> f64 {
+        match self {
+            Self::Microseconds => value,
+            Self::Milliseconds => value / 1e3,
+            Self::Seconds => value / 1e6,
+        }
+    }
+}
+
+impl FromStr for LatencyUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "us" | "µs" => Ok(Self::Microseconds),
+            "ms" => Ok(Self::Milliseconds),
+            "s" => Ok(Self::Seconds),
+            _ => Err(format!("unsupported latency unit: {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for LatencyUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
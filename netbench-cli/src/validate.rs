@@ -0,0 +1,303 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::Result;
+use netbench::{
+    operation as op,
+    scenario::{Connection, Scenario},
+};
+use std::{collections::BTreeSet, path::PathBuf};
+use structopt::StructOpt;
+
+/// Checks that a scenario file can be run
+///
+/// In addition to the required fields, the ids referenced by the operations
+/// (servers, connections, routers, traces, checkpoints and certificates) are
+/// checked against the scenario. Exits with a non-zero status if any errors
+/// are found.
+#[derive(Debug, StructOpt)]
+pub struct ValidateScenario {
+    file: PathBuf,
+}
+
+impl ValidateScenario {
+    pub fn run(&self) -> Result<()> {
+        let input = std::fs::read_to_string(&self.file)
+            .map_err(|err| format!("{}: {err}", self.file.display()))?;
+
+        let scenario: Scenario = match serde_json::from_str(&input) {
+            Ok(scenario) => scenario,
+            Err(err) => {
+                eprintln!("{}: invalid scenario: {err}", self.file.display());
+                std::process::exit(1);
+            }
+        };
+
+        let issues = validate(&scenario);
+
+        for warning in &issues.warnings {
+            eprintln!("warning: {warning}");
+        }
+
+        if issues.errors.is_empty() {
+            println!(
+                "{}: ok ({} client(s), {} server(s))",
+                self.file.display(),
+                scenario.clients.len(),
+                scenario.servers.len()
+            );
+            return Ok(());
+        }
+
+        eprintln!(
+            "{}: {} error(s) found:",
+            self.file.display(),
+            issues.errors.len()
+        );
+        for error in &issues.errors {
+            eprintln!("  {error}");
+        }
+
+        std::process::exit(1);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Issues {
+    /// Problems which would prevent the scenario from running to completion
+    pub errors: Vec<String>,
+    /// Problems which are likely mistakes but don't prevent the scenario from running
+    pub warnings: Vec<String>,
+}
+
+/// Checks the consistency of a parsed scenario
+///
+/// Each issue is prefixed with the path to the offending field
+/// (eg. `clients[0].scenario[2]`).
+pub fn validate(scenario: &Scenario) -> Issues {
+    let mut issues = Issues::default();
+
+    if scenario.clients.is_empty() {
+        issues
+            .errors
+            .push("clients: the scenario needs at least one client".to_string());
+    }
+    if scenario.servers.is_empty() {
+        issues
+            .errors
+            .push("servers: the scenario needs at least one server".to_string());
+    }
+
+    // (server, connection) pairs which are connected to by a client
+    let mut connected = BTreeSet::new();
+
+    for (client_idx, client) in scenario.clients.iter().enumerate() {
+        let path = format!("clients[{client_idx}]");
+        let mut host = Host::new(scenario, &mut issues);
+
+        let mut check_connect = |op_path: &str, op: &op::Client| {
+            if let op::Client::Connect {
+                server_id,
+                router_id,
+                server_connection_id,
+                client_connection_id,
+            } = op
+            {
+                let mut errors = vec![];
+                match scenario.servers.get(*server_id as usize) {
+                    Some(server) => {
+                        if *server_connection_id as usize >= server.connections.len() {
+                            errors.push(format!(
+                                "{op_path}: connect references connection {server_connection_id} of server {server_id}, but the server only has {} connection(s)",
+                                server.connections.len()
+                            ));
+                        }
+                        connected.insert((*server_id, *server_connection_id));
+                    }
+                    None => errors.push(format!(
+                        "{op_path}: connect references server {server_id}, but the scenario only has {} server(s)",
+                        scenario.servers.len()
+                    )),
+                }
+                if *client_connection_id as usize >= client.connections.len() {
+                    errors.push(format!(
+                        "{op_path}: connect references connection {client_connection_id}, but the client only has {} connection(s)",
+                        client.connections.len()
+                    ));
+                }
+                if let Some(router_id) = router_id {
+                    if *router_id as usize >= scenario.routers.len() {
+                        errors.push(format!(
+                            "{op_path}: connect references router {router_id}, but the scenario only has {} router(s)",
+                            scenario.routers.len()
+                        ));
+                    }
+                }
+                errors
+            } else {
+                vec![]
+            }
+        };
+        host.client_ops(
+            &format!("{path}.scenario"),
+            &client.scenario,
+            &mut check_connect,
+        );
+
+        for (conn_idx, conn) in client.connections.iter().enumerate() {
+            host.connection(&format!("{path}.connections[{conn_idx}]"), conn);
+        }
+
+        for (idx, ca) in client.certificate_authorities.iter().enumerate() {
+            host.certificate(&format!("{path}.certificate_authorities[{idx}]"), *ca);
+        }
+
+        host.finish(&path);
+    }
+
+    for (server_idx, server) in scenario.servers.iter().enumerate() {
+        let path = format!("servers[{server_idx}]");
+        let mut host = Host::new(scenario, &mut issues);
+
+        for (conn_idx, conn) in server.connections.iter().enumerate() {
+            let conn_path = format!("{path}.connections[{conn_idx}]");
+            host.connection(&conn_path, conn);
+            if !connected.contains(&(server_idx as u64, conn_idx as u64)) {
+                host.issues.warnings.push(format!(
+                    "{conn_path}: no client connects to this connection so its operations will not run"
+                ));
+            }
+        }
+
+        host.certificate(&format!("{path}.private_key"), server.private_key);
+        host.certificate(&format!("{path}.certificate"), server.certificate);
+        host.certificate(
+            &format!("{path}.certificate_authority"),
+            server.certificate_authority,
+        );
+
+        host.finish(&path);
+    }
+
+    issues
+}
+
+/// Tracks the checkpoints of a single client or server
+///
+/// Checkpoints are local to the host so a park needs a matching unpark on the
+/// same host, otherwise it will block forever.
+struct Host<'a> {
+    scenario: &'a Scenario,
+    issues: &'a mut Issues,
+    parks: Vec<(String, u64)>,
+    unparks: BTreeSet<u64>,
+}
+
+impl<'a> Host<'a> {
+    fn new(scenario: &'a Scenario, issues: &'a mut Issues) -> Self {
+        Self {
+            scenario,
+            issues,
+            parks: vec![],
+            unparks: BTreeSet::new(),
+        }
+    }
+
+    fn client_ops(
+        &mut self,
+        path: &str,
+        ops: &[op::Client],
+        on_op: &mut impl FnMut(&str, &op::Client) -> Vec<String>,
+    ) {
+        for (idx, op) in ops.iter().enumerate() {
+            let op_path = format!("{path}[{idx}]");
+            let errors = on_op(&op_path, op);
+            self.issues.errors.extend(errors);
+
+            match op {
+                op::Client::Park { checkpoint } => self.parks.push((op_path, *checkpoint)),
+                op::Client::Unpark { checkpoint } => {
+                    self.unparks.insert(*checkpoint);
+                }
+                op::Client::Trace { trace_id } => self.trace(&op_path, *trace_id),
+                op::Client::Scope { threads } => {
+                    for (thread_idx, thread) in threads.iter().enumerate() {
+                        self.client_ops(&format!("{op_path}.threads[{thread_idx}]"), thread, on_op);
+                    }
+                }
+                op::Client::Sleep { .. } | op::Client::Connect { .. } => {}
+            }
+        }
+    }
+
+    fn connection(&mut self, path: &str, conn: &Connection) {
+        self.connection_ops(&format!("{path}.ops"), &conn.ops);
+        for (idx, stream) in conn.peer_streams.iter().enumerate() {
+            self.connection_ops(&format!("{path}.peer_streams[{idx}]"), stream);
+        }
+    }
+
+    fn connection_ops(&mut self, path: &str, ops: &[op::Connection]) {
+        for (idx, op) in ops.iter().enumerate() {
+            let op_path = format!("{path}[{idx}]");
+            match op {
+                op::Connection::Park { checkpoint } => self.parks.push((op_path, *checkpoint)),
+                op::Connection::Unpark { checkpoint } => {
+                    self.unparks.insert(*checkpoint);
+                }
+                op::Connection::Trace { trace_id } => self.trace(&op_path, *trace_id),
+                op::Connection::Profile {
+                    trace_id,
+                    operations,
+                } => {
+                    self.trace(&op_path, *trace_id);
+                    self.connection_ops(&format!("{op_path}.operations"), operations);
+                }
+                op::Connection::Iterate {
+                    trace_id,
+                    operations,
+                    ..
+                } => {
+                    if let Some(trace_id) = trace_id {
+                        self.trace(&op_path, *trace_id);
+                    }
+                    self.connection_ops(&format!("{op_path}.operations"), operations);
+                }
+                op::Connection::Scope { threads } => {
+                    for (thread_idx, thread) in threads.iter().enumerate() {
+                        self.connection_ops(&format!("{op_path}.threads[{thread_idx}]"), thread);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn trace(&mut self, path: &str, trace_id: u64) {
+        if trace_id as usize >= self.scenario.traces.len() {
+            self.issues.errors.push(format!(
+                "{path}: references trace {trace_id}, but the scenario only has {} trace(s)",
+                self.scenario.traces.len()
+            ));
+        }
+    }
+
+    fn certificate(&mut self, path: &str, id: u64) {
+        if id as usize >= self.scenario.certificates.len() {
+            self.issues.errors.push(format!(
+                "{path}: references certificate {id}, but the scenario only has {} certificate(s)",
+                self.scenario.certificates.len()
+            ));
+        }
+    }
+
+    fn finish(self, path: &str) {
+        for (park_path, checkpoint) in self.parks {
+            if !self.unparks.contains(&checkpoint) {
+                self.issues.errors.push(format!(
+                    "{park_path}: checkpoint {checkpoint} is never unparked by {path} so this operation will block forever"
+                ));
+            }
+        }
+    }
+}
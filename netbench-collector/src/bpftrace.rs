@@ -320,9 +320,16 @@ pub fn try_run(args: &crate::Args) -> Result<Option<()>> {
         )?
     };
 
+    // bpftrace splits the command on whitespace so arguments can't contain spaces
+    let driver_command = std::iter::once(driver)
+        .chain(&args.driver_args)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+
     command
         .arg("-c")
-        .arg(driver)
+        .arg(driver_command)
         .arg("-e")
         .arg(program)
         .env("TRACE", "disabled")
@@ -14,6 +14,7 @@ use std::{
 
 pub fn run(args: &Args) -> Result<()> {
     let mut command = Command::new(&args.driver);
+    command.args(&args.driver_args);
 
     let driver = &args.driver;
     let interval = args.interval;
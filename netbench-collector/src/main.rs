@@ -16,8 +16,13 @@ pub struct Args {
     #[structopt(long, short, env = "SCENARIO")]
     pub scenario: String,
 
-    #[structopt(long, short, parse(try_from_str=parse_duration), default_value = "1s")]
+    /// How often the driver process is sampled
+    #[structopt(long, short, parse(try_from_str=parse_duration), default_value = "1s", env = "COLLECTOR_INTERVAL")]
     pub interval: Duration,
+
+    /// Arguments passed through to the driver, after `--`
+    #[structopt(last = true)]
+    pub driver_args: Vec<String>,
 }
 
 impl Args {
@@ -0,0 +1,135 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::{Result, Timer, Trace};
+use netbench_driver::Allocator;
+use netbench_driver_iperf::{Event, Transfer};
+use std::{path::PathBuf, process::Stdio};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator::new();
+
+fn main() -> Result<()> {
+    let args = NetbenchClient::parse();
+    let runtime = args.opts.runtime();
+    runtime.block_on(args.run())
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchClient {
+    #[command(flatten)]
+    opts: netbench_driver::Client,
+
+    /// The iperf3 executable
+    ///
+    /// iperf3 3.17 or later is required for `--json-stream`
+    #[arg(long, default_value = "iperf3", env = "IPERF3")]
+    iperf3: PathBuf,
+}
+
+impl NetbenchClient {
+    pub async fn run(&self) -> Result<()> {
+        let addresses = self.opts.address_map().await?;
+        let scenario = self.opts.scenario();
+        let transfer = Transfer::new(&scenario);
+
+        if transfer.connections == 0 || transfer.bytes() == 0 {
+            return Ok(());
+        }
+
+        let addr = addresses.server(0);
+        let is_reverse = transfer.is_reverse();
+
+        let mut command = Command::new(&self.iperf3);
+        command
+            .arg("--client")
+            .arg(addr.ip().to_string())
+            .arg("--port")
+            .arg(addr.port().to_string())
+            .arg("--parallel")
+            .arg(transfer.connections.to_string())
+            .arg("--bytes")
+            .arg(transfer.bytes().to_string())
+            .arg("--interval")
+            .arg("1")
+            .arg("--json-stream")
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+
+        if is_reverse {
+            command.arg("--reverse");
+        }
+
+        if !self.opts.nagle {
+            command.arg("--no-delay");
+        }
+
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take().ok_or("missing iperf3 stdout")?;
+        let mut lines = BufReader::new(stdout).lines();
+
+        let mut trace = self.opts.trace();
+        let timer = netbench::timer::Tokio::default();
+
+        // iperf3 identifies streams by socket so map them onto connection ids in the
+        // order they were connected
+        let mut sockets = vec![];
+
+        while let Some(line) = lines.next_line().await? {
+            let now = timer.now();
+
+            match Event::parse(&line)? {
+                Event::Start(start) => {
+                    for (id, connected) in start.connected.iter().enumerate() {
+                        trace.enter_connection(id as _);
+                        // iperf3 doesn't report a connect time
+                        trace.connect(now, id as _, Default::default());
+                        sockets.push(connected.socket);
+                    }
+                }
+                Event::Interval(interval) => {
+                    for stream in interval.streams {
+                        let id = sockets
+                            .iter()
+                            .position(|socket| *socket == stream.socket)
+                            .ok_or("unknown iperf3 stream")?;
+                        trace.enter_connection(id as _);
+
+                        if stream.sender.unwrap_or(!is_reverse) {
+                            trace.send(now, 0, stream.bytes);
+                        } else {
+                            trace.receive(now, 0, stream.bytes);
+                        }
+                    }
+                }
+                Event::End => {
+                    for id in 0..sockets.len() {
+                        trace.enter_connection(id as _);
+
+                        if is_reverse {
+                            trace.receive_finish(now, 0);
+                        } else {
+                            trace.send_finish(now, 0);
+                        }
+                    }
+                }
+                Event::Error(err) => {
+                    return Err(format!("iperf3 failed: {err}").into());
+                }
+                Event::Other => {}
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(format!("iperf3 exited with {status}").into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::Result;
+use std::{os::unix::process::CommandExt, path::PathBuf, process::Command};
+
+fn main() -> Result<()> {
+    let args = NetbenchServer::parse();
+    args.run()
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchServer {
+    #[command(flatten)]
+    opts: netbench_driver::Server,
+
+    /// The iperf3 executable
+    #[arg(long, default_value = "iperf3", env = "IPERF3")]
+    iperf3: PathBuf,
+}
+
+impl NetbenchServer {
+    pub fn run(&self) -> Result<()> {
+        let mut command = Command::new(&self.iperf3);
+        command
+            .arg("--server")
+            .arg("--port")
+            .arg(self.opts.port.to_string());
+
+        if !self.opts.ip.is_unspecified() {
+            command.arg("--bind").arg(self.opts.ip.to_string());
+        }
+
+        // replace the driver process with iperf3 so the collector monitors the server directly
+        let err = command.exec();
+
+        Err(format!("could not start {}: {err}", self.iperf3.display()).into())
+    }
+}
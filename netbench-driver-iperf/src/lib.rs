@@ -0,0 +1,105 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs iperf3 as a raw transport baseline for netbench scenarios
+//!
+//! iperf3 doesn't execute the individual scenario operations. Instead, the client opens the
+//! same number of connections as the scenario and transfers the total number of bytes in the
+//! dominant direction, which gives an upper bound for what a driver can achieve over the path.
+
+use netbench::Result;
+pub use netbench_driver::Transfer;
+use serde::Deserialize;
+
+/// An event from `iperf3 --json-stream`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Start(Start),
+    Interval(Interval),
+    End,
+    Error(String),
+    Other,
+}
+
+impl Event {
+    pub fn parse(line: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Raw {
+            event: String,
+            #[serde(default)]
+            data: serde_json::Value,
+        }
+
+        let Raw { event, data } = serde_json::from_str(line)?;
+
+        Ok(match event.as_str() {
+            "start" => Self::Start(serde_json::from_value(data)?),
+            "interval" => Self::Interval(serde_json::from_value(data)?),
+            "end" => Self::End,
+            "error" => Self::Error(data.as_str().unwrap_or_default().to_string()),
+            _ => Self::Other,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Start {
+    #[serde(default)]
+    pub connected: Vec<Connected>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Connected {
+    pub socket: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Interval {
+    #[serde(default)]
+    pub streams: Vec<IntervalStream>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct IntervalStream {
+    pub socket: u64,
+    pub bytes: u64,
+    #[serde(default)]
+    pub sender: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_stream_events() {
+        let start = r#"{"event":"start","data":{"connected":[{"socket":5,"local_host":"::1","local_port":40000,"remote_host":"::1","remote_port":5201}],"version":"iperf 3.17"}}"#;
+        assert_eq!(
+            Event::parse(start).unwrap(),
+            Event::Start(Start {
+                connected: vec![Connected { socket: 5 }],
+            })
+        );
+
+        let interval = r#"{"event":"interval","data":{"streams":[{"socket":5,"start":0,"end":1.0,"seconds":1.0,"bytes":1024,"bits_per_second":8192.0,"omitted":false,"sender":true}],"sum":{"bytes":1024}}}"#;
+        assert_eq!(
+            Event::parse(interval).unwrap(),
+            Event::Interval(Interval {
+                streams: vec![IntervalStream {
+                    socket: 5,
+                    bytes: 1024,
+                    sender: Some(true),
+                }],
+            })
+        );
+
+        let error = r#"{"event":"error","data":"unable to connect to server"}"#;
+        assert_eq!(
+            Event::parse(error).unwrap(),
+            Event::Error("unable to connect to server".to_string())
+        );
+
+        let end = r#"{"event":"end","data":{"sum_sent":{"bytes":1024}}}"#;
+        assert_eq!(Event::parse(end).unwrap(), Event::End);
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::{Result, Timer, Trace};
+use netbench_driver::{Allocator, Transfer};
+use netbench_driver_msquic::{Direction, Report};
+use std::{path::PathBuf, process::Stdio};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator::new();
+
+fn main() -> Result<()> {
+    let args = NetbenchClient::parse();
+    let runtime = args.opts.runtime();
+    runtime.block_on(args.run())
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchClient {
+    #[command(flatten)]
+    opts: netbench_driver::Client,
+
+    /// The secnetperf executable
+    #[arg(long, default_value = "secnetperf", env = "SECNETPERF")]
+    secnetperf: PathBuf,
+}
+
+impl NetbenchClient {
+    pub async fn run(&self) -> Result<()> {
+        let addresses = self.opts.address_map().await?;
+        let scenario = self.opts.scenario();
+        let transfer = Transfer::new(&scenario);
+
+        if transfer.connections == 0 || transfer.bytes() == 0 {
+            return Ok(());
+        }
+
+        let addr = addresses.server(0);
+        // the bytes are split evenly over the connections
+        let per_connection = |bytes: u64| bytes.div_ceil(transfer.connections);
+
+        let mut command = Command::new(&self.secnetperf);
+        command
+            .arg(format!("-target:{}", addr.ip()))
+            .arg(format!("-port:{}", addr.port()))
+            .arg(format!("-conns:{}", transfer.connections))
+            .arg("-streams:1")
+            .arg("-ptput:1")
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+
+        if transfer.sent > 0 {
+            command.arg(format!("-up:{}", per_connection(transfer.sent)));
+        }
+
+        if transfer.received > 0 {
+            command.arg(format!("-down:{}", per_connection(transfer.received)));
+        }
+
+        let mut child = command.spawn()?;
+        let stdout = child.stdout.take().ok_or("missing secnetperf stdout")?;
+        let mut lines = BufReader::new(stdout).lines();
+
+        let mut trace = self.opts.trace();
+        let timer = netbench::timer::Tokio::default();
+
+        let now = timer.now();
+        for id in 0..transfer.connections {
+            trace.enter_connection(id);
+            // secnetperf doesn't report a connect time
+            trace.connect(now, id, Default::default());
+        }
+
+        // secnetperf prints a result for each stream once it's done, so the results of each
+        // direction are mapped onto the connections in order
+        let (mut uploads, mut downloads) = (0, 0);
+        let mut output = vec![];
+
+        while let Some(line) = lines.next_line().await? {
+            let now = timer.now();
+
+            let Some(report) = Report::parse(&line) else {
+                output.push(line);
+                continue;
+            };
+
+            match report.direction {
+                Direction::Upload => {
+                    trace.enter_connection(uploads % transfer.connections);
+                    trace.send(now, 0, report.bytes);
+                    trace.send_finish(now, 0);
+                    uploads += 1;
+                }
+                Direction::Download => {
+                    trace.enter_connection(downloads % transfer.connections);
+                    trace.receive(now, 0, report.bytes);
+                    trace.receive_finish(now, 0);
+                    downloads += 1;
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(format!("secnetperf exited with {status}: {}", output.join("\n")).into());
+        }
+
+        Ok(())
+    }
+}
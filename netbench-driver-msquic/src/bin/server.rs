@@ -0,0 +1,40 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::Result;
+use std::{net::SocketAddr, os::unix::process::CommandExt, path::PathBuf, process::Command};
+
+fn main() -> Result<()> {
+    let args = NetbenchServer::parse();
+    args.run()
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchServer {
+    #[command(flatten)]
+    opts: netbench_driver::Server,
+
+    /// The secnetperf executable
+    #[arg(long, default_value = "secnetperf", env = "SECNETPERF")]
+    secnetperf: PathBuf,
+}
+
+impl NetbenchServer {
+    pub fn run(&self) -> Result<()> {
+        let mut command = Command::new(&self.secnetperf);
+
+        // secnetperf ignores the port when an address is bound
+        if self.opts.ip.is_unspecified() {
+            command.arg(format!("-port:{}", self.opts.port));
+        } else {
+            let addr = SocketAddr::new(self.opts.ip, self.opts.port);
+            command.arg(format!("-bind:{addr}"));
+        }
+
+        // replace the driver process with secnetperf so the collector monitors the server directly
+        let err = command.exec();
+
+        Err(format!("could not start {}: {err}", self.secnetperf.display()).into())
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs msquic's `secnetperf` tool for netbench scenarios
+//!
+//! Like the iperf driver, secnetperf doesn't execute the individual scenario operations. The
+//! client opens the same number of connections as the scenario, with a single stream each, and
+//! splits the scenario's sent and received bytes evenly over them.
+
+/// The direction of a stream's transfer, from the client's point of view
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// The result of a stream printed by `secnetperf -ptput:1`, eg.
+/// `Result: Upload 1048576 bytes @ 4342 kbps (1931.707 ms).`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub direction: Direction,
+    pub bytes: u64,
+}
+
+impl Report {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.trim().strip_prefix("Result:")?.split_whitespace();
+        let direction = match words.next()? {
+            "Upload" => Direction::Upload,
+            "Download" => Direction::Download,
+            _ => return None,
+        };
+        let bytes = words.next()?.parse().ok()?;
+        if words.next()? != "bytes" {
+            return None;
+        }
+        Some(Self { direction, bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secnetperf_results() {
+        assert_eq!(
+            Report::parse("Result: Upload 1048576 bytes @ 4342 kbps (1931.707 ms)."),
+            Some(Report {
+                direction: Direction::Upload,
+                bytes: 1048576,
+            })
+        );
+        assert_eq!(
+            Report::parse("  Result: Download 10000 bytes @ 80 kbps (1000.000 ms)."),
+            Some(Report {
+                direction: Direction::Download,
+                bytes: 10000,
+            })
+        );
+
+        assert_eq!(Report::parse("Started!"), None);
+        assert_eq!(Report::parse("Result: 1234 RPS, Min: 10, Max: 20"), None);
+        assert_eq!(Report::parse("Result: Upload many bytes"), None);
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::{scenario, Result};
+use netbench_driver::Allocator;
+use netbench_driver_nginx::Connection;
+use std::{collections::HashSet, future::Future, net::SocketAddr, pin::Pin, sync::Arc};
+use tokio::{io, net::TcpStream};
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator::new();
+
+fn main() -> Result<()> {
+    let args = NetbenchClient::parse();
+    let runtime = args.opts.runtime();
+    runtime.block_on(args.run())
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchClient {
+    #[command(flatten)]
+    opts: netbench_driver::Client,
+}
+
+impl NetbenchClient {
+    pub async fn run(&self) -> Result<()> {
+        let addresses = self.opts.address_map().await?;
+        let scenario = self.opts.scenario();
+
+        let mut trace = self.opts.trace();
+        let mut checkpoints = HashSet::new();
+        let mut timer = netbench::timer::Tokio::default();
+
+        let client = self.client()?;
+        let client = netbench::Client::new(client, &scenario, &addresses);
+        client.run(&mut trace, &mut checkpoints, &mut timer).await?;
+
+        Ok(())
+    }
+
+    fn client(&self) -> Result<ClientImpl> {
+        Ok(ClientImpl {
+            id: 0,
+            nagle: self.opts.nagle,
+            rx_buffer: *self.opts.rx_buffer as _,
+            tx_buffer: *self.opts.tx_buffer as _,
+        })
+    }
+}
+
+type Stream = io::BufStream<TcpStream>;
+type HttpConnection<'a> = netbench::Driver<'a, Connection<Stream>>;
+
+#[derive(Debug)]
+struct ClientImpl {
+    id: u64,
+    nagle: bool,
+    rx_buffer: usize,
+    tx_buffer: usize,
+}
+
+impl ClientImpl {
+    fn id(&mut self) -> u64 {
+        let id = self.id;
+        self.id = id + 1;
+        id
+    }
+}
+
+impl<'a> netbench::client::Client<'a> for ClientImpl {
+    type Connect = Pin<Box<dyn Future<Output = Result<Self::Connection>> + 'a>>;
+    type Connection = HttpConnection<'a>;
+
+    fn connect(
+        &mut self,
+        addr: SocketAddr,
+        server_name: &str,
+        _server_conn_id: u64,
+        scenario: &'a Arc<scenario::Connection>,
+    ) -> Self::Connect {
+        let id = self.id();
+        let rx_buffer = self.rx_buffer;
+        let tx_buffer = self.tx_buffer;
+        let nagle = self.nagle;
+        let server_name = server_name.to_string();
+
+        let fut = async move {
+            let conn = TcpStream::connect(addr).await?;
+
+            if !nagle {
+                let _ = conn.set_nodelay(true);
+            }
+
+            let conn = io::BufStream::with_capacity(rx_buffer, tx_buffer, conn);
+            let conn = Connection::new(id, Box::pin(conn), &server_name, scenario)?;
+            let conn: Self::Connection = netbench::Driver::new(scenario, conn);
+
+            Result::Ok(conn)
+        };
+
+        Box::pin(fut)
+    }
+}
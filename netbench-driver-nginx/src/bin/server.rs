@@ -0,0 +1,63 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::Result;
+use netbench_driver_nginx::{nginx, response_sizes};
+use std::{fs, os::unix::process::CommandExt, path::PathBuf, process::Command};
+
+fn main() -> Result<()> {
+    let args = NetbenchServer::parse();
+    args.run()
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchServer {
+    #[command(flatten)]
+    opts: netbench_driver::Server,
+
+    /// The nginx executable
+    #[arg(long, default_value = "nginx", env = "NGINX")]
+    nginx: PathBuf,
+
+    /// The directory where the nginx configuration and responses are written
+    #[arg(long, env = "NGINX_PREFIX")]
+    prefix: Option<PathBuf>,
+}
+
+impl NetbenchServer {
+    pub fn run(&self) -> Result<()> {
+        let scenario = self.opts.scenario();
+        let responses = response_sizes(&scenario)?;
+
+        let prefix = self
+            .prefix
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("s2n-netbench-nginx"));
+        fs::create_dir_all(&prefix)?;
+        // nginx expects the prefix to be absolute
+        let prefix = prefix.canonicalize()?;
+
+        nginx::write_responses(&prefix, &responses)?;
+
+        let config = nginx::Config {
+            ip: self.opts.ip,
+            port: self.opts.port,
+            multithreaded: matches!(self.opts.multithreaded, Some(Some(true)) | Some(None)),
+            nagle: self.opts.nagle,
+            rx_buffer: *self.opts.rx_buffer,
+        };
+        let config_path = prefix.join("nginx.conf");
+        fs::write(&config_path, config.render(&prefix))?;
+
+        // replace the driver process with nginx so the collector monitors the server directly
+        let err = Command::new(&self.nginx)
+            .arg("-p")
+            .arg(&prefix)
+            .arg("-c")
+            .arg(&config_path)
+            .exec();
+
+        Err(format!("could not start {}: {err}", self.nginx.display()).into())
+    }
+}
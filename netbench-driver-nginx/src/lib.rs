@@ -0,0 +1,451 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Maps netbench scenarios onto HTTP/1.1 exchanges with an nginx server
+//!
+//! Each bidirectional stream opened by the client is sent as a single `GET` request. The
+//! request carries a body with the number of bytes the client sends on the stream and the
+//! path names a static file with the number of bytes the server sends back, which the server
+//! driver generates before starting nginx.
+
+use core::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use netbench::{connection::Owner, operation as op, scenario, Result};
+use std::{collections::BTreeMap, mem::MaybeUninit};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+pub mod nginx;
+
+const READ_BUFFER_SIZE: usize = 100_000;
+static SEND_BUFFER: [u8; 16_384] = [42; 16_384];
+const MAX_HEAD_LEN: usize = 8192;
+
+/// The number of bytes sent and received on a stream over the course of a scenario
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamSizes {
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// Returns the number of bytes sent and received on each stream in `ops`
+pub fn stream_sizes(ops: &[op::Connection]) -> Result<BTreeMap<u64, StreamSizes>> {
+    let mut sizes = BTreeMap::new();
+    collect_sizes(ops, 1, &mut sizes)?;
+    Ok(sizes)
+}
+
+fn collect_sizes(
+    ops: &[op::Connection],
+    multiplier: u64,
+    sizes: &mut BTreeMap<u64, StreamSizes>,
+) -> Result<()> {
+    use op::Connection::*;
+
+    for op in ops {
+        match op {
+            OpenBidirectionalStream { stream_id } => {
+                sizes.entry(*stream_id).or_default();
+            }
+            OpenSendStream { .. } => {
+                return Err("send-only streams can't be mapped onto http requests".into());
+            }
+            Send { stream_id, bytes } => {
+                sizes.entry(*stream_id).or_default().sent += **bytes * multiplier;
+            }
+            Receive { stream_id, bytes } => {
+                sizes.entry(*stream_id).or_default().received += **bytes * multiplier;
+            }
+            ReceiveAll { .. } => {
+                return Err("http requests need to know the size of the response".into());
+            }
+            Profile { operations, .. } => {
+                collect_sizes(operations, multiplier, sizes)?;
+            }
+            Iterate {
+                value: op::IterateValue::Count { amount },
+                operations,
+                ..
+            } => {
+                collect_sizes(operations, multiplier * amount, sizes)?;
+            }
+            Iterate { .. } => {
+                return Err("http requests need a fixed number of iterations".into());
+            }
+            Scope { threads } => {
+                for thread in threads {
+                    collect_sizes(thread, multiplier, sizes)?;
+                }
+            }
+            Sleep { .. }
+            | SendFinish { .. }
+            | SendRate { .. }
+            | ReceiveFinish { .. }
+            | ReceiveRate { .. }
+            | Park { .. }
+            | Unpark { .. }
+            | Trace { .. } => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the sizes of the responses the server sends over all of its connections
+pub fn response_sizes(server: &scenario::Server) -> Result<Vec<u64>> {
+    let mut responses = vec![];
+
+    for connection in &server.connections {
+        if !stream_sizes(&connection.ops)?.is_empty() {
+            return Err("http servers can't open streams".into());
+        }
+
+        for stream in &connection.peer_streams {
+            for sizes in stream_sizes(stream)?.values() {
+                responses.push(sizes.sent);
+            }
+        }
+    }
+
+    responses.sort_unstable();
+    responses.dedup();
+    Ok(responses)
+}
+
+/// The request path for a response of `len` bytes
+pub fn path(len: u64) -> String {
+    format!("/{len}")
+}
+
+#[derive(Debug)]
+struct Request {
+    id: u64,
+    head: Vec<u8>,
+    head_sent: usize,
+    body: u64,
+    response: Response,
+}
+
+#[derive(Debug)]
+enum Response {
+    Head(Vec<u8>),
+    Body(u64),
+}
+
+/// An HTTP/1.1 client connection
+///
+/// HTTP/1.1 only allows one request in flight on a keep-alive connection so streams opened
+/// while another request is active wait for it to complete.
+#[derive(Debug)]
+pub struct Connection<T: AsyncBufRead + AsyncWrite> {
+    id: u64,
+    inner: Pin<Box<T>>,
+    hostname: String,
+    streams: BTreeMap<u64, StreamSizes>,
+    request: Option<Request>,
+}
+
+impl<T: AsyncBufRead + AsyncWrite> Connection<T> {
+    pub fn new(
+        id: u64,
+        inner: Pin<Box<T>>,
+        hostname: &str,
+        scenario: &scenario::Connection,
+    ) -> Result<Self> {
+        if !scenario.peer_streams.is_empty() {
+            return Err("http servers can't open streams".into());
+        }
+
+        Ok(Self {
+            id,
+            inner,
+            hostname: hostname.to_string(),
+            streams: stream_sizes(&scenario.ops)?,
+            request: None,
+        })
+    }
+
+    fn poll_head(&mut self, id: u64, cx: &mut Context) -> Poll<Result<()>> {
+        loop {
+            let request = active(&mut self.request, id)?;
+            let Response::Head(head) = &mut request.response else {
+                return Ok(()).into();
+            };
+
+            let buf = ready!(self.inner.as_mut().poll_fill_buf(cx))?;
+            if buf.is_empty() {
+                return Err("connection closed before the response was received".into()).into();
+            }
+
+            // only consume bytes up to the end of the head so the body is left in the buffer
+            let prev_len = head.len();
+            head.extend_from_slice(buf);
+            let consumed = if let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+                head.truncate(end + 4);
+                head.len() - prev_len
+            } else {
+                buf.len()
+            };
+            self.inner.as_mut().consume(consumed);
+
+            if head.ends_with(b"\r\n\r\n") {
+                let len = parse_head(head)?;
+                request.response = Response::Body(len);
+            } else if head.len() > MAX_HEAD_LEN {
+                return Err("response head is too large".into()).into();
+            }
+        }
+    }
+
+    fn complete(&mut self, id: u64, cx: &mut Context) -> Result<()> {
+        let request = active(&mut self.request, id)?;
+        if request.body == 0 && matches!(request.response, Response::Body(0)) {
+            self.request = None;
+            // wake up any streams waiting for the connection
+            cx.waker().wake_by_ref();
+        }
+        Ok(())
+    }
+}
+
+fn active(request: &mut Option<Request>, id: u64) -> Result<&mut Request> {
+    match request.as_mut() {
+        Some(request) if request.id == id => Ok(request),
+        _ => Err(format!("stream {id} doesn't have an active request").into()),
+    }
+}
+
+fn parse_head(head: &[u8]) -> Result<u64> {
+    let head = core::str::from_utf8(head)?;
+    let mut lines = head.split("\r\n");
+
+    let status = lines.next().unwrap_or_default();
+    let code = status.split(' ').nth(1).unwrap_or_default();
+    if code != "200" {
+        return Err(format!("unexpected response status: {status}").into());
+    }
+
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                return Ok(value.trim().parse()?);
+            }
+        }
+    }
+
+    Err("response is missing a content-length".into())
+}
+
+impl<T: AsyncBufRead + AsyncWrite> netbench::Connection for Connection<T> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn poll_open_bidirectional_stream(&mut self, id: u64, cx: &mut Context) -> Poll<Result<()>> {
+        if self.request.is_none() {
+            let sizes = self.streams.get(&id).copied().unwrap_or_default();
+            let head = format!(
+                "GET {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n",
+                path(sizes.received),
+                self.hostname,
+                sizes.sent
+            );
+            self.request = Some(Request {
+                id,
+                head: head.into_bytes(),
+                head_sent: 0,
+                body: sizes.sent,
+                response: Response::Head(vec![]),
+            });
+        }
+
+        let request = self.request.as_mut().unwrap();
+        if request.id != id {
+            // wait for the active request to complete
+            return Poll::Pending;
+        }
+
+        while request.head_sent < request.head.len() {
+            let head = &request.head[request.head_sent..];
+            request.head_sent += ready!(self.inner.as_mut().poll_write(cx, head))?;
+        }
+
+        if request.body == 0 {
+            ready!(self.inner.as_mut().poll_flush(cx))?;
+        }
+
+        Ok(()).into()
+    }
+
+    fn poll_open_send_stream(&mut self, _: u64, _: &mut Context) -> Poll<Result<()>> {
+        Err("send-only streams can't be mapped onto http requests".into()).into()
+    }
+
+    fn poll_accept_stream(&mut self, _: &mut Context) -> Poll<Result<Option<u64>>> {
+        Err("http servers can't open streams".into()).into()
+    }
+
+    fn poll_send(
+        &mut self,
+        _owner: Owner,
+        id: u64,
+        bytes: u64,
+        cx: &mut Context,
+    ) -> Poll<Result<u64>> {
+        let request = active(&mut self.request, id)?;
+        let bytes = bytes.min(request.body);
+
+        let mut sent: u64 = 0;
+        while sent < bytes {
+            let to_send = ((bytes - sent) as usize).min(SEND_BUFFER.len());
+            match self.inner.as_mut().poll_write(cx, &SEND_BUFFER[..to_send]) {
+                Poll::Ready(result) => {
+                    sent += result? as u64;
+                }
+                Poll::Pending if sent == 0 => {
+                    return Poll::Pending;
+                }
+                Poll::Pending => {
+                    break;
+                }
+            }
+        }
+
+        let request = active(&mut self.request, id)?;
+        request.body -= sent;
+
+        // once the whole body is written, make sure it's flushed to the socket
+        if request.body == 0 {
+            if let Poll::Ready(res) = self.inner.as_mut().poll_flush(cx) {
+                res?;
+            }
+        }
+
+        self.complete(id, cx)?;
+
+        Ok(sent).into()
+    }
+
+    fn poll_receive(
+        &mut self,
+        _owner: Owner,
+        id: u64,
+        bytes: u64,
+        cx: &mut Context,
+    ) -> Poll<Result<u64>> {
+        ready!(self.poll_head(id, cx))?;
+
+        let Response::Body(remaining) = active(&mut self.request, id)?.response else {
+            unreachable!()
+        };
+        let bytes = bytes.min(remaining);
+
+        let mut buf: [MaybeUninit<u8>; READ_BUFFER_SIZE] =
+            unsafe { MaybeUninit::uninit().assume_init() };
+
+        let mut received: u64 = 0;
+        while received < bytes {
+            let len = ((bytes - received) as usize).min(READ_BUFFER_SIZE);
+            let mut buf = ReadBuf::uninit(&mut buf[..len]);
+
+            match self.inner.as_mut().poll_read(cx, &mut buf) {
+                Poll::Ready(result) => {
+                    result?;
+                    if buf.filled().is_empty() {
+                        return Err("connection closed before the response was received".into())
+                            .into();
+                    }
+                    received += buf.filled().len() as u64;
+                }
+                // we didn't get any data on any iterations so we're pending
+                Poll::Pending if received == 0 => {
+                    return Poll::Pending;
+                }
+                // we got at least one byte previously so return that
+                Poll::Pending => {
+                    break;
+                }
+            }
+        }
+
+        active(&mut self.request, id)?.response = Response::Body(remaining - received);
+        self.complete(id, cx)?;
+
+        Ok(received).into()
+    }
+
+    fn poll_send_finish(&mut self, _: Owner, id: u64, cx: &mut Context) -> Poll<Result<()>> {
+        // streams which don't receive anything still need to read the response head before
+        // the connection can be used for the next request
+        if self.streams.get(&id).is_some_and(|s| s.received == 0) {
+            ready!(self.poll_head(id, cx))?;
+            self.complete(id, cx)?;
+        }
+        Ok(()).into()
+    }
+
+    fn poll_receive_finish(&mut self, _: Owner, _: u64, _: &mut Context) -> Poll<Result<()>> {
+        Ok(()).into()
+    }
+
+    fn poll_finish(&mut self, _cx: &mut Context) -> Poll<Result<()>> {
+        if self.request.is_some() {
+            return Err("connection finished with a request in flight".into()).into();
+        }
+        Ok(()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netbench::units::ByteExt;
+
+    #[test]
+    fn request_response_sizes() {
+        let scenario = scenario::Scenario::build(|scenario| {
+            let server = scenario.create_server();
+            scenario.create_client(|client| {
+                client.connect_to(server, |conn| {
+                    for _ in 0..2 {
+                        conn.open_bidirectional_stream(
+                            |local| {
+                                local.send(1.kilobytes());
+                                local.receive(10.kilobytes());
+                            },
+                            |remote| {
+                                remote.receive(1.kilobytes());
+                                remote.send(10.kilobytes());
+                            },
+                        );
+                    }
+                });
+            });
+        });
+
+        let client = &scenario.clients[0].connections[0];
+        let sizes = stream_sizes(&client.ops).unwrap();
+        assert_eq!(sizes.len(), 2);
+        for sizes in sizes.values() {
+            assert_eq!(
+                *sizes,
+                StreamSizes {
+                    sent: 1_000,
+                    received: 10_000,
+                }
+            );
+        }
+
+        assert_eq!(response_sizes(&scenario.servers[0]).unwrap(), vec![10_000]);
+    }
+
+    #[test]
+    fn response_head() {
+        let head = b"HTTP/1.1 200 OK\r\nServer: nginx\r\nContent-Length: 1234\r\n\r\n";
+        assert_eq!(parse_head(head).unwrap(), 1234);
+
+        let head = b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+        assert!(parse_head(head).is_err());
+    }
+}
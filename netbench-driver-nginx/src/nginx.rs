@@ -0,0 +1,100 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use netbench::Result;
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write as _},
+    net::IpAddr,
+    path::Path,
+};
+
+/// Settings mapped from the netbench server options onto the nginx configuration
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub multithreaded: bool,
+    pub nagle: bool,
+    pub rx_buffer: u64,
+}
+
+impl Config {
+    /// Renders an `nginx.conf` serving the files in `{prefix}/www`
+    ///
+    /// nginx is kept in the foreground so it can be monitored by the collector. When running
+    /// single-threaded, the master process also handles the requests, which means the
+    /// collector's process stats cover all of the work done by the server.
+    pub fn render(&self, prefix: &Path) -> String {
+        let mut out = String::new();
+
+        let listen = match self.ip {
+            IpAddr::V4(ip) => format!("{ip}:{}", self.port),
+            IpAddr::V6(ip) if ip.is_unspecified() => format!("[::]:{} ipv6only=off", self.port),
+            IpAddr::V6(ip) => format!("[{ip}]:{}", self.port),
+        };
+
+        macro_rules! line {
+            ($($arg:tt)*) => {
+                let _ = writeln!(out, $($arg)*);
+            };
+        }
+
+        line!("daemon off;");
+        if self.multithreaded {
+            line!("worker_processes auto;");
+        } else {
+            line!("master_process off;");
+            line!("worker_processes 1;");
+        }
+        line!("pid {};", prefix.join("nginx.pid").display());
+        line!("error_log stderr warn;");
+        line!("events {{");
+        line!("    worker_connections 65536;");
+        line!("}}");
+        line!("http {{");
+        line!("    access_log off;");
+        line!("    sendfile on;");
+        line!("    tcp_nodelay {};", if self.nagle { "off" } else { "on" });
+        line!("    keepalive_requests 4294967295;");
+        line!("    keepalive_timeout 3600s;");
+        line!("    client_body_buffer_size {};", self.rx_buffer);
+        line!("    client_max_body_size 0;");
+        line!("    default_type application/octet-stream;");
+        line!("    server {{");
+        line!("        listen {listen};");
+        line!("        root {};", prefix.join("www").display());
+        line!("    }}");
+        line!("}}");
+
+        out
+    }
+}
+
+/// Writes a file for each response size to `{prefix}/www`
+pub fn write_responses(prefix: &Path, responses: &[u64]) -> Result<()> {
+    let root = prefix.join("www");
+    fs::create_dir_all(&root)?;
+
+    let chunk = [42u8; 16_384];
+    for len in responses {
+        let path = root.join(len.to_string());
+
+        // skip responses left over from a previous run
+        if fs::metadata(&path).is_ok_and(|meta| meta.len() == *len) {
+            continue;
+        }
+
+        let mut file = io::BufWriter::new(fs::File::create(&path)?);
+        let mut remaining = *len;
+        while remaining > 0 {
+            let n = remaining.min(chunk.len() as u64);
+            file.write_all(&chunk[..n as usize])?;
+            remaining -= n;
+        }
+        file.flush()?;
+    }
+
+    Ok(())
+}
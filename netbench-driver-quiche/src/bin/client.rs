@@ -0,0 +1,188 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::{Result, Timer, Trace};
+use netbench_driver::{Allocator, Transfer};
+use netbench_driver_quiche::{
+    config, connection_id, flush, write_pem, Request, Sender, MAX_DATAGRAM_SIZE, STREAM_ID,
+};
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator::new();
+
+fn main() -> Result<()> {
+    let args = NetbenchClient::parse();
+    let runtime = args.opts.runtime();
+    runtime.block_on(args.run())
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchClient {
+    #[command(flatten)]
+    opts: netbench_driver::Client,
+}
+
+impl NetbenchClient {
+    pub async fn run(&self) -> Result<()> {
+        let addresses = self.opts.address_map().await?;
+        let scenario = self.opts.scenario();
+        let transfer = Transfer::new(&scenario);
+
+        if transfer.connections == 0 || transfer.bytes() == 0 {
+            return Ok(());
+        }
+
+        let addr = addresses.server(0);
+        let server_name = addresses.hostname(0);
+        // the bytes are split evenly over the connections
+        let per_connection = |bytes: u64| bytes.div_ceil(transfer.connections);
+
+        let mut ca = String::new();
+        for cert in self.opts.certificate_authorities() {
+            ca.push_str(&cert.pem);
+        }
+        let ca = write_pem("ca", &ca)?;
+        let ca = ca.to_str().ok_or("invalid certificate path")?;
+
+        let trace = self.opts.trace();
+        let mut connections = vec![];
+        for id in 0..transfer.connections {
+            let mut config = config(&self.opts.application_protocols)?;
+            config.load_verify_locations_from_file(ca)?;
+            config.verify_peer(true);
+
+            let connection = Connection {
+                id,
+                addr,
+                server_name,
+                sent: per_connection(transfer.sent),
+                received: per_connection(transfer.received),
+            };
+            let socket = UdpSocket::bind((self.opts.local_ip, 0)).await?;
+            connections.push(connection.run(config, socket, trace.clone()));
+        }
+
+        futures::future::try_join_all(connections).await?;
+
+        Ok(())
+    }
+}
+
+struct Connection<'a> {
+    id: u64,
+    addr: SocketAddr,
+    server_name: &'a str,
+    sent: u64,
+    received: u64,
+}
+
+impl Connection<'_> {
+    async fn run(
+        self,
+        mut config: quiche::Config,
+        socket: UdpSocket,
+        mut trace: impl Trace,
+    ) -> Result<()> {
+        let timer = netbench::timer::Tokio::default();
+        let local = socket.local_addr()?;
+        let scid = connection_id();
+        let mut conn =
+            quiche::connect(Some(self.server_name), &scid, local, self.addr, &mut config)?;
+
+        let start = timer.now();
+        let mut connected = false;
+
+        let request = Request {
+            response_bytes: self.received,
+        };
+        let mut sender = Sender::new(&request.encode(), self.sent);
+        let mut received = 0;
+        let mut receive_finished = false;
+        let mut closing = false;
+
+        let mut buf = vec![0; 65535];
+        let mut out = vec![0; MAX_DATAGRAM_SIZE];
+
+        flush(&mut conn, &socket, &mut out).await?;
+
+        loop {
+            let timeout = conn.timeout().unwrap_or(std::time::Duration::from_secs(1));
+
+            tokio::select! {
+                result = socket.recv_from(&mut buf) => {
+                    let (len, from) = result?;
+                    let info = quiche::RecvInfo { from, to: local };
+                    match conn.recv(&mut buf[..len], info) {
+                        Ok(_) | Err(quiche::Error::Done) => {}
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    conn.on_timeout();
+                }
+            }
+
+            if conn.is_closed() {
+                break;
+            }
+
+            let now = timer.now();
+            trace.enter_connection(self.id);
+
+            if conn.is_established() && !connected {
+                connected = true;
+                trace.connect(now, self.id, now - start);
+            }
+
+            if connected && !sender.is_finished() {
+                let len = sender.write(&mut conn, STREAM_ID)?;
+                if len > 0 {
+                    trace.send(now, STREAM_ID, len);
+                }
+                if sender.is_finished() {
+                    trace.send_finish(now, STREAM_ID);
+                }
+            }
+
+            // the stream is opened by the first write
+            while connected && !receive_finished {
+                let (len, fin) = match conn.stream_recv(STREAM_ID, &mut buf) {
+                    Ok(v) => v,
+                    Err(quiche::Error::Done | quiche::Error::InvalidStreamState(_)) => break,
+                    Err(err) => return Err(err.into()),
+                };
+                received += len as u64;
+                if len > 0 {
+                    trace.receive(now, STREAM_ID, len as u64);
+                }
+                if fin {
+                    receive_finished = true;
+                    trace.receive_finish(now, STREAM_ID);
+                }
+            }
+
+            if sender.is_finished() && receive_finished && !closing {
+                closing = true;
+                if received != self.received {
+                    return Err(
+                        format!("expected {} bytes, received {received}", self.received).into(),
+                    );
+                }
+                conn.close(true, 0x0, b"done")?;
+            }
+
+            flush(&mut conn, &socket, &mut out).await?;
+        }
+
+        if !(sender.is_finished() && receive_finished) {
+            return Err(
+                format!("connection {} closed before the transfer finished", self.id).into(),
+            );
+        }
+
+        Ok(())
+    }
+}
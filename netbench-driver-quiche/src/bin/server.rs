@@ -0,0 +1,146 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::Parser;
+use netbench::Result;
+use netbench_driver::Allocator;
+use netbench_driver_quiche::{config, flush, write_pem, RequestReader, Sender, MAX_DATAGRAM_SIZE};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use tokio::net::UdpSocket;
+
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator::new();
+
+fn main() -> Result<()> {
+    let args = NetbenchServer::parse();
+    let runtime = args.opts.runtime();
+    runtime.block_on(args.run())
+}
+
+#[derive(Debug, Parser)]
+pub struct NetbenchServer {
+    #[command(flatten)]
+    opts: netbench_driver::Server,
+}
+
+#[derive(Default)]
+struct Stream {
+    reader: RequestReader,
+    sender: Option<Sender>,
+}
+
+struct Connection {
+    conn: quiche::Connection,
+    streams: HashMap<u64, Stream>,
+}
+
+impl Connection {
+    fn on_ready(&mut self, buf: &mut [u8]) -> Result<()> {
+        for id in self.conn.readable() {
+            let stream = self.streams.entry(id).or_default();
+            loop {
+                let len = match self.conn.stream_recv(id, buf) {
+                    Ok((len, _fin)) => len,
+                    Err(quiche::Error::Done) => break,
+                    Err(err) => return Err(err.into()),
+                };
+                if let Some(request) = stream.reader.on_receive(&buf[..len]) {
+                    stream
+                        .sender
+                        .get_or_insert_with(|| Sender::new(&[], request.response_bytes));
+                }
+            }
+        }
+
+        for (id, stream) in self.streams.iter_mut() {
+            if let Some(sender) = stream.sender.as_mut() {
+                if !sender.is_finished() {
+                    sender.write(&mut self.conn, *id)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl NetbenchServer {
+    pub async fn run(&self) -> Result<()> {
+        let mut config = config(&self.opts.application_protocols)?;
+
+        let (cert, private_key) = self.opts.certificate();
+        let cert = write_pem("cert", &cert.pem)?;
+        let private_key = write_pem("key", &private_key.pem)?;
+        config.load_cert_chain_from_pem_file(cert.to_str().ok_or("invalid certificate path")?)?;
+        config.load_priv_key_from_pem_file(private_key.to_str().ok_or("invalid key path")?)?;
+
+        let socket = UdpSocket::bind(SocketAddr::new(self.opts.ip, self.opts.port)).await?;
+        let local = socket.local_addr()?;
+
+        let mut connections: HashMap<quiche::ConnectionId<'static>, Connection> = HashMap::new();
+        let mut buf = vec![0; 65535];
+        let mut out = vec![0; MAX_DATAGRAM_SIZE];
+
+        loop {
+            let timeout = connections
+                .values()
+                .filter_map(|connection| connection.conn.timeout())
+                .min()
+                .unwrap_or(Duration::from_secs(1));
+
+            tokio::select! {
+                result = socket.recv_from(&mut buf) => {
+                    let (len, from) = result?;
+                    let packet = &mut buf[..len];
+
+                    let Ok(header) = quiche::Header::from_slice(packet, quiche::MAX_CONN_ID_LEN) else {
+                        continue;
+                    };
+                    let (ty, version) = (header.ty, header.version);
+                    let id = header.dcid.into_owned();
+
+                    if !connections.contains_key(&id) {
+                        if ty != quiche::Type::Initial || !quiche::version_is_supported(version) {
+                            continue;
+                        }
+
+                        // the client's initial destination id is reused as the server's id so the
+                        // client's retransmitted initial packets find the connection
+                        let conn = match quiche::accept(&id, None, local, from, &mut config) {
+                            Ok(conn) => conn,
+                            Err(err) => {
+                                eprintln!("could not accept connection: {err}");
+                                continue;
+                            }
+                        };
+                        let streams = HashMap::new();
+                        connections.insert(id.clone(), Connection { conn, streams });
+                    }
+
+                    let connection = connections.get_mut(&id).expect("connection was inserted");
+                    let info = quiche::RecvInfo { from, to: local };
+                    if let Err(err) = connection.conn.recv(packet, info) {
+                        eprintln!("connection error: {err}");
+                    }
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    for connection in connections.values_mut() {
+                        connection.conn.on_timeout();
+                    }
+                }
+            }
+
+            for connection in connections.values_mut() {
+                if let Err(err) = connection.on_ready(&mut buf) {
+                    eprintln!("stream error: {err}");
+                    let _ = connection.conn.close(false, 0x1, b"stream error");
+                }
+                if let Err(err) = flush(&mut connection.conn, &socket, &mut out).await {
+                    eprintln!("send error: {err}");
+                }
+            }
+
+            connections.retain(|_, connection| !connection.conn.is_closed());
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs netbench scenarios over [quiche](https://github.com/cloudflare/quiche) connections
+//!
+//! Like the msquic driver, the quiche driver doesn't execute the individual scenario operations.
+//! The client opens the same number of connections as the scenario, with a single bidirectional
+//! stream each, and splits the scenario's sent and received bytes evenly over them. Each stream
+//! starts with a [`Request`] for the number of bytes the server sends back.
+
+use netbench::Result;
+use std::{io, path::PathBuf};
+use tokio::net::UdpSocket;
+
+/// The stream each connection transfers its bytes on
+pub const STREAM_ID: u64 = 0;
+
+/// The largest UDP payload sent or received
+pub const MAX_DATAGRAM_SIZE: usize = 1350;
+
+const CHUNK: [u8; 16 * 1024] = [0; 16 * 1024];
+
+/// Returns a quiche configuration for the application protocols
+pub fn config(application_protocols: &[String]) -> Result<quiche::Config> {
+    let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION)?;
+    let protocols: Vec<&[u8]> = application_protocols.iter().map(|p| p.as_bytes()).collect();
+    config.set_application_protos(&protocols)?;
+    config.set_max_idle_timeout(30_000);
+    config.set_max_recv_udp_payload_size(MAX_DATAGRAM_SIZE);
+    config.set_max_send_udp_payload_size(MAX_DATAGRAM_SIZE);
+    config.set_initial_max_data(100_000_000);
+    config.set_initial_max_stream_data_bidi_local(10_000_000);
+    config.set_initial_max_stream_data_bidi_remote(10_000_000);
+    config.set_initial_max_streams_bidi(1);
+    config.set_disable_active_migration(true);
+    Ok(config)
+}
+
+/// Writes a PEM to a temporary file, since quiche only loads certificates from files
+pub fn write_pem(name: &str, pem: &str) -> io::Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!(
+        "s2n-netbench-quiche-{}-{name}.pem",
+        std::process::id()
+    ));
+    std::fs::write(&path, pem)?;
+    Ok(path)
+}
+
+/// Returns a random connection id
+pub fn connection_id() -> quiche::ConnectionId<'static> {
+    let mut id = [0; quiche::MAX_CONN_ID_LEN];
+    rand::Rng::fill(&mut rand::thread_rng(), &mut id[..]);
+    quiche::ConnectionId::from_vec(id.to_vec())
+}
+
+/// Sends the connection's pending packets
+pub async fn flush(
+    conn: &mut quiche::Connection,
+    socket: &UdpSocket,
+    out: &mut [u8],
+) -> io::Result<()> {
+    loop {
+        let (len, info) = match conn.send(out) {
+            Ok(v) => v,
+            Err(quiche::Error::Done) => return Ok(()),
+            Err(err) => {
+                let _ = conn.close(false, 0x1, b"send failed");
+                return Err(io::Error::new(io::ErrorKind::Other, err));
+            }
+        };
+        socket.send_to(&out[..len], info.to).await?;
+    }
+}
+
+/// The number of bytes the client asks the server to send back on a stream
+///
+/// The request is encoded as a big-endian `u64` at the start of the stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub response_bytes: u64,
+}
+
+impl Request {
+    pub const LEN: usize = 8;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        self.response_bytes.to_be_bytes()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::LEN)?.try_into().ok()?;
+        let response_bytes = u64::from_be_bytes(bytes);
+        Some(Self { response_bytes })
+    }
+}
+
+/// Reads the [`Request`] from the start of a server stream and discards the rest of the client's
+/// bytes
+#[derive(Debug, Default)]
+pub struct RequestReader {
+    buffer: Vec<u8>,
+    request: Option<Request>,
+}
+
+impl RequestReader {
+    /// Returns the request once all of its bytes are received
+    pub fn on_receive(&mut self, data: &[u8]) -> Option<Request> {
+        if self.request.is_none() {
+            let missing = Request::LEN - self.buffer.len();
+            self.buffer
+                .extend_from_slice(&data[..missing.min(data.len())]);
+            self.request = Request::decode(&self.buffer);
+        }
+        self.request
+    }
+}
+
+/// Writes a stream: an optional prefix, followed by a number of payload bytes and the fin
+#[derive(Debug)]
+pub struct Sender {
+    prefix: Vec<u8>,
+    remaining: u64,
+    finished: bool,
+}
+
+impl Sender {
+    pub fn new(prefix: &[u8], len: u64) -> Self {
+        Self {
+            prefix: prefix.to_vec(),
+            remaining: len,
+            finished: false,
+        }
+    }
+
+    /// Returns `true` once all of the bytes and the fin are written
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Writes as much of the stream as flow control allows, returning the number of payload bytes
+    /// written
+    pub fn write(&mut self, conn: &mut quiche::Connection, stream_id: u64) -> Result<u64> {
+        let mut written = 0;
+
+        while !self.finished {
+            let (data, is_prefix) = if self.prefix.is_empty() {
+                let len = self.remaining.min(CHUNK.len() as u64) as usize;
+                (&CHUNK[..len], false)
+            } else {
+                (&self.prefix[..], true)
+            };
+            let fin = !is_prefix && data.len() as u64 == self.remaining;
+
+            let len = match conn.stream_send(stream_id, data, fin) {
+                Ok(len) => len,
+                Err(quiche::Error::Done) => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            if is_prefix {
+                self.prefix.drain(..len);
+                if !self.prefix.is_empty() {
+                    break;
+                }
+                continue;
+            }
+
+            self.remaining -= len as u64;
+            written += len as u64;
+
+            if len < data.len() {
+                break;
+            }
+
+            self.finished = fin;
+        }
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_round_trip() {
+        let request = Request {
+            response_bytes: 1 << 40,
+        };
+        assert_eq!(Request::decode(&request.encode()), Some(request));
+        assert_eq!(Request::decode(&request.encode()[..7]), None);
+    }
+
+    #[test]
+    fn request_reader_test() {
+        let request = Request {
+            response_bytes: 1234,
+        };
+        let encoded = request.encode();
+
+        let mut reader = RequestReader::default();
+        assert_eq!(reader.on_receive(&encoded[..3]), None);
+        assert_eq!(reader.on_receive(&[]), None);
+
+        // the payload after the request is ignored
+        let mut rest = encoded[3..].to_vec();
+        rest.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(reader.on_receive(&rest), Some(request));
+        assert_eq!(reader.on_receive(&[4, 5, 6]), Some(request));
+    }
+}
@@ -12,7 +12,9 @@ use std::path::PathBuf;
 use std::{net::IpAddr, ops::Deref, path::Path, str::FromStr, sync::Arc, time::Duration};
 
 mod alloc;
+mod transfer;
 pub use alloc::Allocator;
+pub use transfer::Transfer;
 
 #[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
 pub enum Trace {
@@ -0,0 +1,87 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use netbench::{operation as op, scenario};
+
+/// The amount of work in a client scenario
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transfer {
+    pub connections: u64,
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl Transfer {
+    pub fn new(client: &scenario::Client) -> Self {
+        let mut transfer = Self::default();
+        transfer.on_client(client, &client.scenario);
+        transfer
+    }
+
+    /// Returns `true` if the scenario receives more data than it sends
+    pub fn is_reverse(&self) -> bool {
+        self.received > self.sent
+    }
+
+    /// The number of bytes transferred in the dominant direction
+    pub fn bytes(&self) -> u64 {
+        self.sent.max(self.received)
+    }
+
+    fn on_client(&mut self, client: &scenario::Client, ops: &[op::Client]) {
+        for op in ops {
+            match op {
+                op::Client::Connect {
+                    client_connection_id,
+                    ..
+                } => {
+                    self.connections += 1;
+                    let connection = &client.connections[*client_connection_id as usize];
+                    self.on_connection(&connection.ops, 1);
+                    for stream in &connection.peer_streams {
+                        self.on_connection(stream, 1);
+                    }
+                }
+                op::Client::Scope { threads } => {
+                    for thread in threads {
+                        self.on_client(client, thread);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn on_connection(&mut self, ops: &[op::Connection], multiplier: u64) {
+        for op in ops {
+            match op {
+                op::Connection::Send { bytes, .. } => {
+                    self.sent += **bytes * multiplier;
+                }
+                op::Connection::Receive { bytes, .. } => {
+                    self.received += **bytes * multiplier;
+                }
+                op::Connection::Profile { operations, .. } => {
+                    self.on_connection(operations, multiplier);
+                }
+                op::Connection::Iterate {
+                    value: op::IterateValue::Count { amount },
+                    operations,
+                    ..
+                } => {
+                    self.on_connection(operations, multiplier * amount);
+                }
+                // time-based iterations can't be sized ahead of time so they're counted once
+                op::Connection::Iterate { operations, .. } => {
+                    self.on_connection(operations, multiplier);
+                }
+                op::Connection::Scope { threads } => {
+                    for thread in threads {
+                        self.on_connection(thread, multiplier);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::S3Api,
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig, RunPaths},
+    s3_utils,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use std::{fs::OpenOptions, path::PathBuf, sync::Mutex};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::{filter::Targets, fmt, registry::LookupSpan, Layer};
+
+// The tracing target of the audit events
+pub const TARGET: &str = "audit";
+
+// Records a significant action of the orchestrator in the audit log.
+//
+// The category is the service acted on (eg. "ec2", "ssm") or "run" for state
+// transitions of the run. Fields identify the affected resources, eg.
+// `audit::record!("ssm", "send_command", command_id)`.
+macro_rules! record {
+    ($category:literal, $action:literal $(, $($fields:tt)+)?) => {
+        tracing::info!(
+            target: $crate::audit::TARGET,
+            category = $category,
+            $($($fields)+,)?
+            $action
+        )
+    };
+}
+pub(crate) use record;
+
+// The audit log of a run.
+//
+// Every event is appended to the file as a json line, with its timestamp and
+// the fields of the enclosing spans, so that the log has a complete timeline
+// of a failed or suspicious run.
+fn path(unique_id: &str) -> PathBuf {
+    RunPaths::new(unique_id).audit_log()
+}
+
+// The layer which writes the audit events to the audit log, regardless of
+// `RUST_LOG`.
+pub fn layer<S>(unique_id: &str) -> OrchResult<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let path = path(unique_id);
+    let init_err = |err: std::io::Error| {
+        OrchError::new(
+            ErrorKind::Init,
+            format!("Failed to create the audit log {:?}", path),
+        )
+        .with_source(err)
+    };
+    std::fs::create_dir_all(path.parent().expect("audit log has a parent dir"))
+        .map_err(init_err)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(init_err)?;
+
+    Ok(fmt::layer()
+        .json()
+        .with_writer(Mutex::new(file))
+        .with_filter(Targets::new().with_target(TARGET, Level::INFO)))
+}
+
+// Uploads the audit log next to the run's private artifacts.
+pub async fn upload(
+    s3_client: &impl S3Api,
+    unique_id: &str,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    let body = ByteStream::from_path(path(unique_id))
+        .await
+        .map_err(|err| {
+            OrchError::new(ErrorKind::S3, "Failed to read the audit log").with_source(err)
+        })?;
+    s3_utils::upload_object(
+        s3_client,
+        config.cdk_config.netbench_runner_private_s3_bucket(),
+        body,
+        &RunPaths::new(unique_id).key("audit.jsonl"),
+    )
+    .await?;
+
+    Ok(())
+}
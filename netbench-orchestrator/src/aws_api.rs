@@ -0,0 +1,390 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+// The AWS operations used by the orchestrator.
+//
+// `ec2_utils`, `ssm_utils` and `s3_utils` call AWS through these traits
+// rather than the SDK clients, so that a run can be driven end-to-end against
+// the mocks in `mock` without touching AWS. The traits are implemented for
+// the SDK clients in `sdk`.
+//
+// Each operation maps the SDK error to an `OrchError` and returns the
+// outcomes the callers branch on (eg. an object which doesn't exist) as
+// values rather than errors.
+
+use crate::orchestrator::{AwsEndpoints, ErrorKind, OrchError, OrchResult, OrchestratorConfig};
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sdk_ec2::types::{Instance, IpPermission, Placement, PlacementGroup, Subnet};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_ssm::{
+    operation::{
+        get_command_invocation::GetCommandInvocationOutput, send_command::SendCommandOutput,
+    },
+    types::{CommandInvocation, InstanceInformation},
+};
+use aws_types::{region::Region, sdk_config::SharedCredentialsProvider};
+use std::{fmt, future::Future, process::Command, time::Duration};
+
+#[cfg(test)]
+pub mod mock;
+mod sdk;
+
+// The size of the hosts' root volume in GiB, which holds the drivers' builds
+// and results
+pub const ROOT_VOLUME_SIZE: i32 = 50;
+
+// The parameters of a single instance launch
+#[derive(Clone, Debug)]
+pub struct LaunchRequest {
+    // The `Name` tag of the instance
+    pub name: String,
+    pub instance_type: String,
+    pub image_id: String,
+    pub instance_profile_arn: String,
+    pub key_name: Option<String>,
+    pub placement: Placement,
+    pub subnet_id: String,
+    pub security_group_id: String,
+}
+
+// The parameters shared by the instances launched by the fleets of a run. The
+// instance type, subnet and placement come from the fleet's overrides.
+#[derive(Clone, Debug)]
+pub struct LaunchTemplateRequest {
+    pub name: String,
+    pub image_id: String,
+    pub instance_profile_arn: String,
+    pub key_name: Option<String>,
+    pub security_group_id: String,
+}
+
+// The launch of several instances with an EC2 Fleet in instant mode, which
+// launches each instance with the most preferred override that has capacity
+#[derive(Clone, Debug)]
+pub struct FleetRequest {
+    // The `Name` tag of the instances
+    pub name: String,
+    pub launch_template_id: String,
+    pub count: usize,
+    pub overrides: Vec<FleetOverride>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FleetOverride {
+    pub instance_type: String,
+    pub az: String,
+    pub subnet_id: String,
+    // The cluster placement group of the AZ
+    pub placement_group: Option<String>,
+    // Lower is preferred
+    pub priority: f64,
+}
+
+// The instances launched by a fleet, which can be fewer than requested, and
+// the reasons the others weren't launched
+#[derive(Clone, Debug, Default)]
+pub struct FleetLaunch {
+    pub instances: Vec<Instance>,
+    pub errors: Vec<String>,
+}
+
+// The outcome of deleting a resource which is still in use until the
+// instances are fully terminated, eg. a security group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deletion {
+    Deleted,
+    InUse,
+}
+
+// A resource which isn't deleted with the instances, eg. the ones retained by
+// the hosts kept with `--keep-servers`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetainedResource {
+    SecurityGroup { id: String },
+    PlacementGroup { name: String },
+    LaunchTemplate { id: String },
+}
+
+impl fmt::Display for RetainedResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetainedResource::SecurityGroup { id } => write!(f, "security group {id}"),
+            RetainedResource::PlacementGroup { name } => write!(f, "placement group {name}"),
+            RetainedResource::LaunchTemplate { id } => write!(f, "launch template {id}"),
+        }
+    }
+}
+
+pub trait Ec2Api: Clone + Send + Sync + 'static {
+    fn run_instance(
+        &self,
+        request: LaunchRequest,
+    ) -> impl Future<Output = OrchResult<Instance>> + Send;
+
+    // Returns the id of the launch template
+    fn create_launch_template(
+        &self,
+        request: LaunchTemplateRequest,
+    ) -> impl Future<Output = OrchResult<String>> + Send;
+
+    fn delete_launch_template(
+        &self,
+        launch_template_id: &str,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // The launched instances have their id, type and placement
+    fn create_fleet(
+        &self,
+        request: FleetRequest,
+    ) -> impl Future<Output = OrchResult<FleetLaunch>> + Send;
+
+    // None if the instance isn't visible yet
+    fn describe_instance(
+        &self,
+        instance_id: &str,
+    ) -> impl Future<Output = OrchResult<Option<Instance>>> + Send;
+
+    // The instances with the `Name` tag, in any state
+    fn describe_instances_by_name(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = OrchResult<Vec<Instance>>> + Send;
+
+    fn terminate_instances(
+        &self,
+        instance_ids: Vec<String>,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // Adds the tags to the resources, or replaces the values of existing tags
+    fn create_tags(
+        &self,
+        resource_ids: Vec<String>,
+        tags: Vec<(String, String)>,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // The security groups, placement groups and launch templates with the
+    // tag, with the tag's value
+    fn describe_tagged_resources(
+        &self,
+        tag_key: &str,
+    ) -> impl Future<Output = OrchResult<Vec<(RetainedResource, String)>>> + Send;
+
+    // Returns the id of the security group
+    fn create_security_group(
+        &self,
+        name: &str,
+        vpc_id: &str,
+    ) -> impl Future<Output = OrchResult<String>> + Send;
+
+    fn authorize_egress(
+        &self,
+        security_group_id: &str,
+        permissions: Vec<IpPermission>,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    fn authorize_ingress(
+        &self,
+        security_group_id: &str,
+        permissions: Vec<IpPermission>,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    fn delete_security_group(
+        &self,
+        security_group_id: &str,
+    ) -> impl Future<Output = OrchResult<Deletion>> + Send;
+
+    // A cluster placement group
+    fn create_placement_group(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = OrchResult<PlacementGroup>> + Send;
+
+    fn delete_placement_group(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = OrchResult<Deletion>> + Send;
+
+    // The subnets with the tag, eg. `tag:Name`
+    fn describe_subnets(
+        &self,
+        tag_key: &str,
+        tag_value: &str,
+    ) -> impl Future<Output = OrchResult<Vec<Subnet>>> + Send;
+
+    // The base64 encoded console output of the instance, if any
+    fn console_output(
+        &self,
+        instance_id: &str,
+    ) -> impl Future<Output = OrchResult<Option<String>>> + Send;
+}
+
+pub trait SsmApi: Clone + Send + Sync + 'static {
+    // Runs the shell commands on the instances, with the output sent to the
+    // log group.
+    fn send_command(
+        &self,
+        comment: &str,
+        instance_ids: Vec<String>,
+        commands: Vec<String>,
+        log_group: &str,
+    ) -> impl Future<Output = OrchResult<SendCommandOutput>> + Send;
+
+    fn list_command_invocations(
+        &self,
+        command_id: &str,
+    ) -> impl Future<Output = OrchResult<Vec<CommandInvocation>>> + Send;
+
+    // Every command run on the instance, with the output of each plugin
+    fn list_instance_invocations(
+        &self,
+        instance_id: &str,
+    ) -> impl Future<Output = OrchResult<Vec<CommandInvocation>>> + Send;
+
+    // None until the invocation is visible, shortly after the command is sent
+    fn get_command_invocation(
+        &self,
+        command_id: &str,
+        instance_id: &str,
+    ) -> impl Future<Output = OrchResult<Option<GetCommandInvocationOutput>>> + Send;
+
+    fn cancel_command(&self, command_id: &str) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // The value of a parameter, eg. the latest AMI
+    fn get_parameter(&self, name: &str) -> impl Future<Output = OrchResult<Option<String>>> + Send;
+
+    // The agent's report of a managed instance, eg. its address and whether
+    // it's online. None if the instance isn't registered.
+    fn describe_instance_information(
+        &self,
+        instance_id: &str,
+    ) -> impl Future<Output = OrchResult<Option<InstanceInformation>>> + Send;
+
+    // Used to link to the command logs
+    fn region(&self) -> Option<String>;
+}
+
+pub trait S3Api: Clone + Send + Sync + 'static {
+    fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        body: ByteStream,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // None if the object doesn't exist
+    fn get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> impl Future<Output = OrchResult<Option<Vec<u8>>>> + Send;
+
+    // The keys of the objects under the prefix
+    fn list_keys(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> impl Future<Output = OrchResult<Vec<String>>> + Send;
+
+    // Deletes the objects, which is a no-op for keys that don't exist
+    fn delete_objects(
+        &self,
+        bucket: &str,
+        keys: &[String],
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // A time-limited link to download the object
+    fn presign_get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        expires_in: Duration,
+    ) -> impl Future<Output = OrchResult<String>> + Send;
+}
+
+pub trait IamApi: Clone + Send + Sync + 'static {
+    // None if the instance profile doesn't exist
+    fn instance_profile_arn(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = OrchResult<Option<String>>> + Send;
+}
+
+// The clients a run is driven with.
+//
+// The CloudWatch and SES clients aren't abstracted, since they're only used
+// for best effort reporting once the run is complete. They're created from
+// `sdk_config`, and skipped without it (eg. in tests).
+#[derive(Clone, Debug)]
+pub struct AwsClients<Ec2, Ssm, S3, Iam> {
+    pub ec2: Ec2,
+    pub ssm: Ssm,
+    pub s3: S3,
+    pub iam: Iam,
+    pub sdk_config: Option<aws_types::SdkConfig>,
+}
+
+pub type SdkClients =
+    AwsClients<aws_sdk_ec2::Client, aws_sdk_ssm::Client, aws_sdk_s3::Client, aws_sdk_iam::Client>;
+
+impl SdkClients {
+    pub fn new(aws_config: &aws_types::SdkConfig, config: &OrchestratorConfig) -> Self {
+        AwsClients {
+            ec2: aws_sdk_ec2::Client::new(aws_config),
+            ssm: aws_sdk_ssm::Client::new(aws_config),
+            s3: crate::s3_utils::s3_client(aws_config, config),
+            iam: aws_sdk_iam::Client::new(aws_config),
+            sdk_config: Some(aws_config.clone()),
+        }
+    }
+}
+
+// The region, credentials and endpoints of the local `aws` cli commands, eg.
+// the s3 syncs and the CloudFront invalidations.
+//
+// They're passed to each command rather than exported, and the credentials
+// are fetched from the clients' provider for each command, so that the
+// credentials of an assumed role are refreshed for the cli as they are for
+// the clients. Without an `SdkConfig` (eg. in tests) the commands use the
+// cli's own config.
+#[derive(Clone, Debug, Default)]
+pub struct AwsCli {
+    region: Option<Region>,
+    credentials: Option<SharedCredentialsProvider>,
+    endpoints: AwsEndpoints,
+}
+
+impl AwsCli {
+    pub fn new(aws_config: &aws_types::SdkConfig) -> Self {
+        AwsCli {
+            region: aws_config.region().cloned(),
+            credentials: aws_config.credentials_provider(),
+            endpoints: AwsEndpoints::of(aws_config),
+        }
+    }
+
+    pub async fn command(&self) -> OrchResult<Command> {
+        let mut cmd = Command::new("aws");
+        cmd.envs(self.endpoints.variables().iter().cloned());
+        if let Some(region) = &self.region {
+            cmd.env("AWS_REGION", region.as_ref());
+        }
+        if let Some(provider) = &self.credentials {
+            let credentials = provider.provide_credentials().await.map_err(|err| {
+                OrchError::new(
+                    ErrorKind::Init,
+                    "Failed to load the credentials of the aws cli",
+                )
+                .with_source(err)
+            })?;
+            // the credentials take precedence over the cli's profile
+            cmd.env("AWS_ACCESS_KEY_ID", credentials.access_key_id())
+                .env("AWS_SECRET_ACCESS_KEY", credentials.secret_access_key());
+            match credentials.session_token() {
+                Some(token) => cmd.env("AWS_SESSION_TOKEN", token),
+                None => cmd.env_remove("AWS_SESSION_TOKEN"),
+            };
+        }
+        Ok(cmd)
+    }
+}
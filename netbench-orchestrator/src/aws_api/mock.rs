@@ -0,0 +1,545 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+// In-memory implementations of the AWS operations, which record the calls
+// made so that tests can assert on them.
+
+use super::{
+    AwsClients, Deletion, Ec2Api, FleetLaunch, FleetRequest, IamApi, LaunchRequest,
+    LaunchTemplateRequest, RetainedResource, S3Api, SsmApi,
+};
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult};
+use aws_sdk_ec2::types::{
+    Instance, InstanceState, InstanceStateName, InstanceType, IpPermission, Placement,
+    PlacementGroup, Subnet, Tag,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_ssm::{
+    operation::{
+        get_command_invocation::GetCommandInvocationOutput, send_command::SendCommandOutput,
+    },
+    types::{Command, CommandInvocation, CommandInvocationStatus, InstanceInformation, PingStatus},
+};
+use std::{
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub type MockClients = AwsClients<MockEc2, MockSsm, MockS3, MockIam>;
+
+impl MockClients {
+    // Hosts are given the ips in the order they're launched, which is the
+    // servers and then the clients.
+    pub fn new(azs: &[&str], host_ips: Vec<IpAddr>) -> Self {
+        AwsClients {
+            ec2: MockEc2::new(azs, host_ips),
+            ssm: MockSsm::default(),
+            s3: MockS3::default(),
+            iam: MockIam,
+            sdk_config: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Ec2State {
+    subnets: Vec<Subnet>,
+    host_ips: Vec<IpAddr>,
+    // The running instances, by id
+    pub instances: BTreeMap<String, Instance>,
+    pub terminated: Vec<String>,
+    pub security_groups: Vec<String>,
+    pub placement_groups: Vec<String>,
+    pub ingress: Vec<IpPermission>,
+    pub launch_templates: Vec<String>,
+    // The instance types a fleet has no capacity for
+    pub unavailable_instance_types: Vec<String>,
+    // The tags added after the resources were created, by resource id
+    pub tags: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Ec2State {
+    fn launch(
+        &mut self,
+        name: &str,
+        instance_type: &str,
+        az: Option<&str>,
+    ) -> OrchResult<Instance> {
+        let launched = self.instances.len() + self.terminated.len();
+        let ip = self.host_ips.get(launched).copied().ok_or_else(|| {
+            OrchError::new(ErrorKind::Ec2, "The mock has no ip left for the instance")
+        })?;
+        let instance_id = format!("i-{launched}");
+        let instance = Instance::builder()
+            .instance_id(&instance_id)
+            .instance_type(InstanceType::from(instance_type))
+            .set_placement(Some(
+                Placement::builder()
+                    .set_availability_zone(az.map(str::to_string))
+                    .build(),
+            ))
+            .private_ip_address(ip.to_string())
+            .public_ip_address(ip.to_string())
+            .tags(Tag::builder().key("Name").value(name).build())
+            .state(
+                InstanceState::builder()
+                    .name(InstanceStateName::Running)
+                    .build(),
+            )
+            .build();
+        self.instances.insert(instance_id, instance.clone());
+        Ok(instance)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MockEc2 {
+    pub state: Arc<Mutex<Ec2State>>,
+}
+
+impl MockEc2 {
+    fn new(azs: &[&str], host_ips: Vec<IpAddr>) -> Self {
+        let subnets = azs
+            .iter()
+            .enumerate()
+            .map(|(i, az)| {
+                Subnet::builder()
+                    .availability_zone(*az)
+                    .subnet_id(format!("subnet-{i}"))
+                    .vpc_id("vpc-0")
+                    .build()
+            })
+            .collect();
+        let state = Ec2State {
+            subnets,
+            host_ips,
+            ..Default::default()
+        };
+        MockEc2 {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+}
+
+impl Ec2Api for MockEc2 {
+    async fn run_instance(&self, request: LaunchRequest) -> OrchResult<Instance> {
+        self.state.lock().unwrap().launch(
+            &request.name,
+            &request.instance_type,
+            request.placement.availability_zone(),
+        )
+    }
+
+    async fn create_launch_template(&self, request: LaunchTemplateRequest) -> OrchResult<String> {
+        let id = format!("lt-{}", request.name);
+        self.state.lock().unwrap().launch_templates.push(id.clone());
+        Ok(id)
+    }
+
+    async fn delete_launch_template(&self, launch_template_id: &str) -> OrchResult<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .launch_templates
+            .retain(|id| id != launch_template_id);
+        Ok(())
+    }
+
+    // Launches the instances with the most preferred override whose instance
+    // type is available
+    async fn create_fleet(&self, request: FleetRequest) -> OrchResult<FleetLaunch> {
+        let mut state = self.state.lock().unwrap();
+        let mut overrides = request.overrides.clone();
+        overrides.sort_by(|a, b| a.priority.total_cmp(&b.priority));
+        let Some(fleet_override) = overrides
+            .iter()
+            .find(|o| !state.unavailable_instance_types.contains(&o.instance_type))
+        else {
+            return Ok(FleetLaunch {
+                instances: vec![],
+                errors: vec!["InsufficientInstanceCapacity: no capacity".to_string()],
+            });
+        };
+        let mut launch = FleetLaunch::default();
+        for _ in 0..request.count {
+            launch.instances.push(state.launch(
+                &request.name,
+                &fleet_override.instance_type,
+                Some(&fleet_override.az),
+            )?);
+        }
+        Ok(launch)
+    }
+
+    async fn describe_instance(&self, instance_id: &str) -> OrchResult<Option<Instance>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .instances
+            .get(instance_id)
+            .cloned())
+    }
+
+    async fn describe_instances_by_name(&self, name: &str) -> OrchResult<Vec<Instance>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .instances
+            .values()
+            .filter(|instance| {
+                instance
+                    .tags()
+                    .iter()
+                    .any(|tag| tag.key() == Some("Name") && tag.value() == Some(name))
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn terminate_instances(&self, instance_ids: Vec<String>) -> OrchResult<()> {
+        let mut state = self.state.lock().unwrap();
+        for instance_id in instance_ids {
+            state.instances.remove(&instance_id);
+            state.terminated.push(instance_id);
+        }
+        Ok(())
+    }
+
+    async fn create_tags(
+        &self,
+        resource_ids: Vec<String>,
+        tags: Vec<(String, String)>,
+    ) -> OrchResult<()> {
+        let mut state = self.state.lock().unwrap();
+        for resource_id in resource_ids {
+            state
+                .tags
+                .entry(resource_id)
+                .or_default()
+                .extend(tags.iter().cloned());
+        }
+        Ok(())
+    }
+
+    async fn describe_tagged_resources(
+        &self,
+        tag_key: &str,
+    ) -> OrchResult<Vec<(RetainedResource, String)>> {
+        let state = self.state.lock().unwrap();
+        let resources = state
+            .tags
+            .iter()
+            .filter_map(|(id, tags)| {
+                let resource = if state.security_groups.contains(id) {
+                    RetainedResource::SecurityGroup { id: id.clone() }
+                } else if state.launch_templates.contains(id) {
+                    RetainedResource::LaunchTemplate { id: id.clone() }
+                } else {
+                    let name = id.strip_prefix("pg-")?;
+                    state.placement_groups.iter().find(|group| *group == name)?;
+                    RetainedResource::PlacementGroup {
+                        name: name.to_string(),
+                    }
+                };
+                Some((resource, tags.get(tag_key)?.clone()))
+            })
+            .collect();
+        Ok(resources)
+    }
+
+    async fn create_security_group(&self, name: &str, _vpc_id: &str) -> OrchResult<String> {
+        let id = format!("sg-{name}");
+        self.state.lock().unwrap().security_groups.push(id.clone());
+        Ok(id)
+    }
+
+    async fn authorize_egress(
+        &self,
+        _security_group_id: &str,
+        _permissions: Vec<IpPermission>,
+    ) -> OrchResult<()> {
+        Ok(())
+    }
+
+    async fn authorize_ingress(
+        &self,
+        _security_group_id: &str,
+        permissions: Vec<IpPermission>,
+    ) -> OrchResult<()> {
+        self.state.lock().unwrap().ingress.extend(permissions);
+        Ok(())
+    }
+
+    // Like EC2, the security group is in use until the instances are terminated
+    async fn delete_security_group(&self, security_group_id: &str) -> OrchResult<Deletion> {
+        let mut state = self.state.lock().unwrap();
+        if !state.instances.is_empty() {
+            return Ok(Deletion::InUse);
+        }
+        state.security_groups.retain(|id| id != security_group_id);
+        Ok(Deletion::Deleted)
+    }
+
+    async fn create_placement_group(&self, name: &str) -> OrchResult<PlacementGroup> {
+        self.state
+            .lock()
+            .unwrap()
+            .placement_groups
+            .push(name.to_string());
+        Ok(PlacementGroup::builder()
+            .group_name(name)
+            .group_id(format!("pg-{name}"))
+            .build())
+    }
+
+    async fn delete_placement_group(&self, name: &str) -> OrchResult<Deletion> {
+        let mut state = self.state.lock().unwrap();
+        if !state.instances.is_empty() {
+            return Ok(Deletion::InUse);
+        }
+        state.placement_groups.retain(|group| group != name);
+        Ok(Deletion::Deleted)
+    }
+
+    async fn describe_subnets(&self, _tag_key: &str, _tag_value: &str) -> OrchResult<Vec<Subnet>> {
+        Ok(self.state.lock().unwrap().subnets.clone())
+    }
+
+    async fn console_output(&self, _instance_id: &str) -> OrchResult<Option<String>> {
+        Ok(None)
+    }
+}
+
+// A command sent to the hosts
+#[derive(Clone, Debug)]
+pub struct SentCommand {
+    pub command_id: String,
+    pub comment: String,
+    pub instance_ids: Vec<String>,
+    pub commands: Vec<String>,
+}
+
+type OnSend = Arc<dyn Fn(&SentCommand) + Send + Sync>;
+
+// Every command succeeds as soon as it's sent.
+#[derive(Clone, Default)]
+pub struct MockSsm {
+    pub sent: Arc<Mutex<Vec<SentCommand>>>,
+    on_send: Option<OnSend>,
+    // The online managed instances, by id
+    managed_instances: Arc<Mutex<BTreeMap<String, IpAddr>>>,
+}
+
+impl MockSsm {
+    // Called with each command as it's sent, eg. to start the Russula workers
+    // the command would run on the hosts.
+    pub fn on_send(&mut self, on_send: impl Fn(&SentCommand) + Send + Sync + 'static) {
+        self.on_send = Some(Arc::new(on_send));
+    }
+
+    // Registers an online managed instance, as a hybrid activation would
+    pub fn register_managed_instance(&self, instance_id: &str, ip: IpAddr) {
+        let mut managed_instances = self.managed_instances.lock().unwrap();
+        managed_instances.insert(instance_id.to_string(), ip);
+    }
+
+    // The comments of the commands sent so far, eg. `build_russula_server`
+    pub fn comments(&self) -> Vec<String> {
+        let sent = self.sent.lock().unwrap();
+        sent.iter().map(|command| command.comment.clone()).collect()
+    }
+
+    fn find(&self, command_id: &str) -> Option<SentCommand> {
+        let sent = self.sent.lock().unwrap();
+        sent.iter()
+            .find(|command| command.command_id == command_id)
+            .cloned()
+    }
+}
+
+impl std::fmt::Debug for MockSsm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockSsm")
+            .field("sent", &self.sent)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SsmApi for MockSsm {
+    async fn send_command(
+        &self,
+        comment: &str,
+        instance_ids: Vec<String>,
+        commands: Vec<String>,
+        _log_group: &str,
+    ) -> OrchResult<SendCommandOutput> {
+        let command = {
+            let mut sent = self.sent.lock().unwrap();
+            let command = SentCommand {
+                command_id: format!("command-{}", sent.len()),
+                comment: comment.to_string(),
+                instance_ids,
+                commands,
+            };
+            sent.push(command.clone());
+            command
+        };
+        if let Some(on_send) = &self.on_send {
+            on_send(&command);
+        }
+
+        Ok(SendCommandOutput::builder()
+            .command(
+                Command::builder()
+                    .command_id(command.command_id)
+                    .comment(command.comment)
+                    .set_instance_ids(Some(command.instance_ids))
+                    .build(),
+            )
+            .build())
+    }
+
+    async fn list_command_invocations(
+        &self,
+        command_id: &str,
+    ) -> OrchResult<Vec<CommandInvocation>> {
+        let Some(command) = self.find(command_id) else {
+            return Ok(vec![]);
+        };
+        Ok(command
+            .instance_ids
+            .iter()
+            .map(|instance_id| {
+                CommandInvocation::builder()
+                    .command_id(&command.command_id)
+                    .comment(&command.comment)
+                    .instance_id(instance_id)
+                    .status(CommandInvocationStatus::Success)
+                    .build()
+            })
+            .collect())
+    }
+
+    async fn list_instance_invocations(
+        &self,
+        _instance_id: &str,
+    ) -> OrchResult<Vec<CommandInvocation>> {
+        Ok(vec![])
+    }
+
+    async fn get_command_invocation(
+        &self,
+        command_id: &str,
+        instance_id: &str,
+    ) -> OrchResult<Option<GetCommandInvocationOutput>> {
+        Ok(self.find(command_id).map(|_command| {
+            GetCommandInvocationOutput::builder()
+                .command_id(command_id)
+                .instance_id(instance_id)
+                .status(CommandInvocationStatus::Success)
+                .standard_output_content("")
+                .build()
+        }))
+    }
+
+    async fn cancel_command(&self, _command_id: &str) -> OrchResult<()> {
+        Ok(())
+    }
+
+    async fn get_parameter(&self, _name: &str) -> OrchResult<Option<String>> {
+        Ok(Some("ami-0".to_string()))
+    }
+
+    async fn describe_instance_information(
+        &self,
+        instance_id: &str,
+    ) -> OrchResult<Option<InstanceInformation>> {
+        let managed_instances = self.managed_instances.lock().unwrap();
+        Ok(managed_instances.get(instance_id).map(|ip| {
+            InstanceInformation::builder()
+                .instance_id(instance_id)
+                .ping_status(PingStatus::Online)
+                .ip_address(ip.to_string())
+                .build()
+        }))
+    }
+
+    fn region(&self) -> Option<String> {
+        None
+    }
+}
+
+// The objects, by bucket and key
+#[derive(Clone, Debug, Default)]
+pub struct MockS3 {
+    pub objects: Arc<Mutex<BTreeMap<(String, String), Vec<u8>>>>,
+}
+
+impl MockS3 {
+    pub fn object(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        let objects = self.objects.lock().unwrap();
+        objects.get(&(bucket.to_string(), key.to_string())).cloned()
+    }
+}
+
+impl S3Api for MockS3 {
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        _content_type: &str,
+        body: ByteStream,
+    ) -> OrchResult<()> {
+        let body = body.collect().await.map_err(|err| {
+            OrchError::new(ErrorKind::S3, "Failed to read the body").with_source(err)
+        })?;
+        self.objects
+            .lock()
+            .unwrap()
+            .insert((bucket.to_string(), key.to_string()), body.to_vec());
+        Ok(())
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> OrchResult<Option<Vec<u8>>> {
+        Ok(self.object(bucket, key))
+    }
+
+    async fn list_keys(&self, bucket: &str, prefix: &str) -> OrchResult<Vec<String>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects
+            .keys()
+            .filter(|(object_bucket, key)| object_bucket == bucket && key.starts_with(prefix))
+            .map(|(_, key)| key.clone())
+            .collect())
+    }
+
+    async fn delete_objects(&self, bucket: &str, keys: &[String]) -> OrchResult<()> {
+        let mut objects = self.objects.lock().unwrap();
+        for key in keys {
+            objects.remove(&(bucket.to_string(), key.clone()));
+        }
+        Ok(())
+    }
+
+    async fn presign_get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        _expires_in: Duration,
+    ) -> OrchResult<String> {
+        Ok(format!("https://{bucket}.s3.amazonaws.com/{key}?presigned"))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MockIam;
+
+impl IamApi for MockIam {
+    async fn instance_profile_arn(&self, name: &str) -> OrchResult<Option<String>> {
+        Ok(Some(format!(
+            "arn:aws:iam::000000000000:instance-profile/{name}"
+        )))
+    }
+}
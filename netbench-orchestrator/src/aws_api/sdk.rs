@@ -0,0 +1,886 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    Deletion, Ec2Api, FleetLaunch, FleetRequest, IamApi, LaunchRequest, LaunchTemplateRequest,
+    RetainedResource, S3Api, SsmApi, ROOT_VOLUME_SIZE,
+};
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult};
+use aws_sdk_ec2::{
+    error::SdkError,
+    types::{
+        BlockDeviceMapping, DefaultTargetCapacityType, EbsBlockDevice, Filter,
+        FleetLaunchTemplateConfigRequest, FleetLaunchTemplateOverridesRequest,
+        FleetLaunchTemplateSpecificationRequest, FleetOnDemandAllocationStrategy, FleetType,
+        IamInstanceProfileSpecification, Instance, InstanceNetworkInterfaceSpecification,
+        InstanceType, IpPermission, LaunchTemplateBlockDeviceMappingRequest,
+        LaunchTemplateEbsBlockDeviceRequest, LaunchTemplateIamInstanceProfileSpecificationRequest,
+        LaunchTemplateInstanceNetworkInterfaceSpecificationRequest, OnDemandOptionsRequest,
+        Placement, PlacementGroup, PlacementStrategy, RequestLaunchTemplateData, ResourceType,
+        ShutdownBehavior, Subnet, Tag, TagSpecification, TargetCapacitySpecificationRequest,
+    },
+};
+use aws_sdk_s3::{
+    presigning::PresigningConfig,
+    primitives::ByteStream,
+    types::{Delete, ObjectIdentifier},
+};
+use aws_sdk_ssm::{
+    operation::{
+        get_command_invocation::GetCommandInvocationOutput, send_command::SendCommandOutput,
+    },
+    types::{
+        CloudWatchOutputConfig, CommandInvocation, InstanceInformation,
+        InstanceInformationStringFilter,
+    },
+};
+use std::time::Duration;
+
+impl Ec2Api for aws_sdk_ec2::Client {
+    async fn run_instance(&self, request: LaunchRequest) -> OrchResult<Instance> {
+        let output = self
+            .run_instances()
+            .placement(request.placement)
+            .set_key_name(request.key_name)
+            .iam_instance_profile(
+                IamInstanceProfileSpecification::builder()
+                    .arn(&request.instance_profile_arn)
+                    .build(),
+            )
+            .instance_type(InstanceType::from(request.instance_type.as_str()))
+            .image_id(&request.image_id)
+            .instance_initiated_shutdown_behavior(ShutdownBehavior::Terminate)
+            // give the instances human readable names. name is set via tags
+            .tag_specifications(
+                TagSpecification::builder()
+                    .resource_type(ResourceType::Instance)
+                    .tags(Tag::builder().key("Name").value(&request.name).build())
+                    .build(),
+            )
+            .block_device_mappings(
+                BlockDeviceMapping::builder()
+                    .device_name("/dev/xvda")
+                    .ebs(
+                        EbsBlockDevice::builder()
+                            .delete_on_termination(true)
+                            .volume_size(ROOT_VOLUME_SIZE)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .network_interfaces(
+                InstanceNetworkInterfaceSpecification::builder()
+                    .associate_public_ip_address(true)
+                    .delete_on_termination(true)
+                    .device_index(0)
+                    .subnet_id(&request.subnet_id)
+                    .groups(&request.security_group_id)
+                    .build(),
+            )
+            .min_count(1_i32)
+            .max_count(1_i32)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!(
+                        "Failed to launch a {} instance {}",
+                        request.instance_type, request.name
+                    ),
+                    err,
+                )
+            })?;
+
+        output
+            .instances()
+            .first()
+            .cloned()
+            .ok_or(OrchError::new(ErrorKind::Ec2, "Failed to launch instance"))
+    }
+
+    async fn create_launch_template(&self, request: LaunchTemplateRequest) -> OrchResult<String> {
+        let data = RequestLaunchTemplateData::builder()
+            .image_id(&request.image_id)
+            .set_key_name(request.key_name)
+            .iam_instance_profile(
+                LaunchTemplateIamInstanceProfileSpecificationRequest::builder()
+                    .arn(&request.instance_profile_arn)
+                    .build(),
+            )
+            .instance_initiated_shutdown_behavior(ShutdownBehavior::Terminate)
+            .block_device_mappings(
+                LaunchTemplateBlockDeviceMappingRequest::builder()
+                    .device_name("/dev/xvda")
+                    .ebs(
+                        LaunchTemplateEbsBlockDeviceRequest::builder()
+                            .delete_on_termination(true)
+                            .volume_size(ROOT_VOLUME_SIZE)
+                            .build(),
+                    )
+                    .build(),
+            )
+            // the subnet is set by the fleet's overrides
+            .network_interfaces(
+                LaunchTemplateInstanceNetworkInterfaceSpecificationRequest::builder()
+                    .associate_public_ip_address(true)
+                    .delete_on_termination(true)
+                    .device_index(0)
+                    .groups(&request.security_group_id)
+                    .build(),
+            )
+            .build();
+        let output = self
+            .create_launch_template()
+            .launch_template_name(&request.name)
+            .launch_template_data(data)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to create the launch template {}", request.name),
+                    err,
+                )
+            })?;
+        output
+            .launch_template()
+            .and_then(|template| template.launch_template_id())
+            .map(str::to_string)
+            .ok_or(OrchError::new(
+                ErrorKind::Ec2,
+                "Created launch template has no id",
+            ))
+    }
+
+    async fn delete_launch_template(&self, launch_template_id: &str) -> OrchResult<()> {
+        self.delete_launch_template()
+            .launch_template_id(launch_template_id)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to delete the launch template {launch_template_id}"),
+                    err,
+                )
+            })?;
+        Ok(())
+    }
+
+    async fn create_fleet(&self, request: FleetRequest) -> OrchResult<FleetLaunch> {
+        let overrides = request
+            .overrides
+            .iter()
+            .map(|fleet_override| {
+                FleetLaunchTemplateOverridesRequest::builder()
+                    .instance_type(InstanceType::from(fleet_override.instance_type.as_str()))
+                    .subnet_id(&fleet_override.subnet_id)
+                    .availability_zone(&fleet_override.az)
+                    .set_placement(
+                        fleet_override
+                            .placement_group
+                            .as_ref()
+                            .map(|group_name| Placement::builder().group_name(group_name).build()),
+                    )
+                    .priority(fleet_override.priority)
+                    .build()
+            })
+            .collect();
+        let output = self
+            .create_fleet()
+            .r#type(FleetType::Instant)
+            .launch_template_configs(
+                FleetLaunchTemplateConfigRequest::builder()
+                    .launch_template_specification(
+                        FleetLaunchTemplateSpecificationRequest::builder()
+                            .launch_template_id(&request.launch_template_id)
+                            .version("$Latest")
+                            .build(),
+                    )
+                    .set_overrides(Some(overrides))
+                    .build(),
+            )
+            .target_capacity_specification(
+                TargetCapacitySpecificationRequest::builder()
+                    .total_target_capacity(request.count as i32)
+                    .default_target_capacity_type(DefaultTargetCapacityType::OnDemand)
+                    .build(),
+            )
+            .on_demand_options(
+                OnDemandOptionsRequest::builder()
+                    .allocation_strategy(FleetOnDemandAllocationStrategy::Prioritized)
+                    .build(),
+            )
+            // give the instances human readable names. name is set via tags
+            .tag_specifications(
+                TagSpecification::builder()
+                    .resource_type(ResourceType::Instance)
+                    .tags(Tag::builder().key("Name").value(&request.name).build())
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to create a fleet for {}", request.name),
+                    err,
+                )
+            })?;
+
+        let mut launch = FleetLaunch::default();
+        for fleet_instance in output.instances() {
+            let az = fleet_instance
+                .launch_template_and_overrides()
+                .and_then(|template| template.overrides())
+                .and_then(|overrides| overrides.availability_zone());
+            for instance_id in fleet_instance.instance_ids() {
+                launch.instances.push(
+                    Instance::builder()
+                        .instance_id(instance_id)
+                        .set_instance_type(fleet_instance.instance_type().cloned())
+                        .placement(
+                            Placement::builder()
+                                .set_availability_zone(az.map(str::to_string))
+                                .build(),
+                        )
+                        .build(),
+                );
+            }
+        }
+        launch.errors = output
+            .errors()
+            .iter()
+            .map(|err| {
+                format!(
+                    "{}: {}",
+                    err.error_code().unwrap_or("unknown"),
+                    err.error_message().unwrap_or_default()
+                )
+            })
+            .collect();
+        Ok(launch)
+    }
+
+    async fn describe_instance(&self, instance_id: &str) -> OrchResult<Option<Instance>> {
+        let output = self
+            .describe_instances()
+            .instance_ids(instance_id)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to describe instance {instance_id}"),
+                    err,
+                )
+            })?;
+        Ok(output
+            .reservations()
+            .first()
+            .and_then(|reservation| reservation.instances().first())
+            .cloned())
+    }
+
+    async fn describe_instances_by_name(&self, name: &str) -> OrchResult<Vec<Instance>> {
+        let output = self
+            .describe_instances()
+            .filters(Filter::builder().name("tag:Name").values(name).build())
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to describe the instances named {name}"),
+                    err,
+                )
+            })?;
+        Ok(output
+            .reservations()
+            .iter()
+            .flat_map(|reservation| reservation.instances())
+            .cloned()
+            .collect())
+    }
+
+    async fn terminate_instances(&self, instance_ids: Vec<String>) -> OrchResult<()> {
+        self.terminate_instances()
+            .set_instance_ids(Some(instance_ids.clone()))
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to terminate instances {instance_ids:?}"),
+                    err,
+                )
+            })?;
+        Ok(())
+    }
+
+    async fn create_tags(
+        &self,
+        resource_ids: Vec<String>,
+        tags: Vec<(String, String)>,
+    ) -> OrchResult<()> {
+        self.create_tags()
+            .set_resources(Some(resource_ids.clone()))
+            .set_tags(Some(
+                tags.into_iter()
+                    .map(|(key, value)| Tag::builder().key(key).value(value).build())
+                    .collect(),
+            ))
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to tag {resource_ids:?}"),
+                    err,
+                )
+            })?;
+        Ok(())
+    }
+
+    async fn describe_tagged_resources(
+        &self,
+        tag_key: &str,
+    ) -> OrchResult<Vec<(RetainedResource, String)>> {
+        let filter = Filter::builder().name("tag-key").values(tag_key).build();
+        let tag_value = |tags: &[Tag]| {
+            tags.iter()
+                .find(|tag| tag.key() == Some(tag_key))
+                .and_then(Tag::value)
+                .map(str::to_string)
+        };
+        let mut resources = Vec::new();
+
+        let output = self
+            .describe_security_groups()
+            .filters(filter.clone())
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(ErrorKind::Ec2, "Couldn't describe security groups", err)
+            })?;
+        resources.extend(output.security_groups().iter().filter_map(|group| {
+            let id = group.group_id()?.to_string();
+            Some((
+                RetainedResource::SecurityGroup { id },
+                tag_value(group.tags())?,
+            ))
+        }));
+
+        let output = self
+            .describe_placement_groups()
+            .filters(filter.clone())
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(ErrorKind::Ec2, "Couldn't describe placement groups", err)
+            })?;
+        resources.extend(output.placement_groups().iter().filter_map(|group| {
+            let name = group.group_name()?.to_string();
+            Some((
+                RetainedResource::PlacementGroup { name },
+                tag_value(group.tags())?,
+            ))
+        }));
+
+        let output = self
+            .describe_launch_templates()
+            .filters(filter)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(ErrorKind::Ec2, "Couldn't describe launch templates", err)
+            })?;
+        resources.extend(output.launch_templates().iter().filter_map(|template| {
+            let id = template.launch_template_id()?.to_string();
+            Some((
+                RetainedResource::LaunchTemplate { id },
+                tag_value(template.tags())?,
+            ))
+        }));
+
+        Ok(resources)
+    }
+
+    async fn create_security_group(&self, name: &str, vpc_id: &str) -> OrchResult<String> {
+        let output = self
+            .create_security_group()
+            .group_name(name)
+            .description("This is a security group for a single run of netbench.")
+            .vpc_id(vpc_id)
+            .tag_specifications(
+                TagSpecification::builder()
+                    .resource_type(ResourceType::SecurityGroup)
+                    .tags(Tag::builder().key("Name").value(name).build())
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(ErrorKind::Ec2, "Failed to create security group", err)
+            })?;
+
+        output.group_id().map(str::to_string).ok_or(OrchError::new(
+            ErrorKind::Ec2,
+            "Failed to create security group",
+        ))
+    }
+
+    async fn authorize_egress(
+        &self,
+        security_group_id: &str,
+        permissions: Vec<IpPermission>,
+    ) -> OrchResult<()> {
+        self.authorize_security_group_egress()
+            .group_id(security_group_id)
+            .set_ip_permissions(Some(permissions))
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(ErrorKind::Ec2, "Failed to set egress permissions", err)
+            })?;
+        Ok(())
+    }
+
+    async fn authorize_ingress(
+        &self,
+        security_group_id: &str,
+        permissions: Vec<IpPermission>,
+    ) -> OrchResult<()> {
+        self.authorize_security_group_ingress()
+            .group_id(security_group_id)
+            .set_ip_permissions(Some(permissions))
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(ErrorKind::Ec2, "Failed to set ingress permissions", err)
+            })?;
+        Ok(())
+    }
+
+    async fn delete_security_group(&self, security_group_id: &str) -> OrchResult<Deletion> {
+        match self
+            .delete_security_group()
+            .group_id(security_group_id)
+            .send()
+            .await
+        {
+            Ok(_) => Ok(Deletion::Deleted),
+            Err(SdkError::ServiceError(service_err))
+                if service_err.err().meta().code() == Some("DependencyViolation") =>
+            {
+                Ok(Deletion::InUse)
+            }
+            Err(err) => Err(OrchError::sdk(
+                ErrorKind::Ec2,
+                format!("Failed to delete security group {security_group_id}"),
+                err,
+            )),
+        }
+    }
+
+    async fn create_placement_group(&self, name: &str) -> OrchResult<PlacementGroup> {
+        let output = self
+            .create_placement_group()
+            .group_name(name)
+            .strategy(PlacementStrategy::Cluster)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to create placement group {name}"),
+                    err,
+                )
+            })?;
+        output.placement_group().cloned().ok_or(OrchError::new(
+            ErrorKind::Ec2,
+            "Failed to retrieve placement_group",
+        ))
+    }
+
+    async fn delete_placement_group(&self, name: &str) -> OrchResult<Deletion> {
+        match self.delete_placement_group().group_name(name).send().await {
+            Ok(_) => Ok(Deletion::Deleted),
+            Err(SdkError::ServiceError(service_err))
+                if service_err.err().meta().code() == Some("InvalidPlacementGroup.InUse") =>
+            {
+                Ok(Deletion::InUse)
+            }
+            Err(err) => Err(OrchError::sdk(
+                ErrorKind::Ec2,
+                format!("Failed to delete placement group {name}"),
+                err,
+            )),
+        }
+    }
+
+    async fn describe_subnets(&self, tag_key: &str, tag_value: &str) -> OrchResult<Vec<Subnet>> {
+        let output = self
+            .describe_subnets()
+            .filters(Filter::builder().name(tag_key).values(tag_value).build())
+            .send()
+            .await
+            .map_err(|err| OrchError::sdk(ErrorKind::Ec2, "Couldn't describe subnets", err))?;
+        Ok(output.subnets().to_vec())
+    }
+
+    async fn console_output(&self, instance_id: &str) -> OrchResult<Option<String>> {
+        let output = self
+            .get_console_output()
+            .instance_id(instance_id)
+            .latest(true)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to get the console output of {instance_id}"),
+                    err,
+                )
+            })?;
+        Ok(output.output().map(str::to_string))
+    }
+}
+
+impl SsmApi for aws_sdk_ssm::Client {
+    async fn send_command(
+        &self,
+        comment: &str,
+        instance_ids: Vec<String>,
+        commands: Vec<String>,
+        log_group: &str,
+    ) -> OrchResult<SendCommandOutput> {
+        self.send_command()
+            .comment(comment)
+            .set_instance_ids(Some(instance_ids.clone()))
+            .document_name("AWS-RunShellScript")
+            .document_version("$LATEST")
+            .parameters("commands", commands)
+            .cloud_watch_output_config(
+                CloudWatchOutputConfig::builder()
+                    .cloud_watch_log_group_name(log_group)
+                    .cloud_watch_output_enabled(true)
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| {
+                // the instances aren't registered with ssm until shortly
+                // after they're running
+                let unregistered = err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_invalid_instance_id());
+                let err = OrchError::sdk(
+                    ErrorKind::Ssm,
+                    format!("Failed to send ssm command {comment} to {instance_ids:?}"),
+                    err,
+                );
+                match unregistered {
+                    true => err.retryable(),
+                    false => err,
+                }
+            })
+    }
+
+    async fn list_command_invocations(
+        &self,
+        command_id: &str,
+    ) -> OrchResult<Vec<CommandInvocation>> {
+        let output = self
+            .list_command_invocations()
+            .command_id(command_id)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ssm,
+                    format!("error listing ssm command {command_id}"),
+                    err,
+                )
+            })?;
+        Ok(output.command_invocations().to_vec())
+    }
+
+    async fn list_instance_invocations(
+        &self,
+        instance_id: &str,
+    ) -> OrchResult<Vec<CommandInvocation>> {
+        let mut invocations = Vec::new();
+        let mut pages = self
+            .list_command_invocations()
+            .instance_id(instance_id)
+            .details(true)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ssm,
+                    format!("Failed to list the ssm commands of {instance_id}"),
+                    err,
+                )
+            })?;
+            invocations.extend(page.command_invocations().iter().cloned());
+        }
+        Ok(invocations)
+    }
+
+    async fn get_command_invocation(
+        &self,
+        command_id: &str,
+        instance_id: &str,
+    ) -> OrchResult<Option<GetCommandInvocationOutput>> {
+        match self
+            .get_command_invocation()
+            .command_id(command_id)
+            .instance_id(instance_id)
+            .send()
+            .await
+        {
+            Ok(invocation) => Ok(Some(invocation)),
+            Err(err) => {
+                let err = err.into_service_error();
+                if err.is_invocation_does_not_exist() {
+                    return Ok(None);
+                }
+                Err(OrchError::new(
+                    ErrorKind::Ssm,
+                    format!("error getting ssm command {command_id}"),
+                )
+                .with_source(err))
+            }
+        }
+    }
+
+    async fn cancel_command(&self, command_id: &str) -> OrchResult<()> {
+        self.cancel_command()
+            .command_id(command_id)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ssm,
+                    format!("error cancelling ssm command {command_id}"),
+                    err,
+                )
+            })?;
+        Ok(())
+    }
+
+    async fn get_parameter(&self, name: &str) -> OrchResult<Option<String>> {
+        let output = self
+            .get_parameter()
+            .name(name)
+            .with_decryption(true)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ssm,
+                    format!("Failed to get the ssm parameter {name}"),
+                    err,
+                )
+            })?;
+        Ok(output
+            .parameter()
+            .and_then(|parameter| parameter.value())
+            .map(str::to_string))
+    }
+
+    async fn describe_instance_information(
+        &self,
+        instance_id: &str,
+    ) -> OrchResult<Option<InstanceInformation>> {
+        let filter = InstanceInformationStringFilter::builder()
+            .key("InstanceIds")
+            .values(instance_id)
+            .build()
+            .map_err(|err| {
+                OrchError::new(ErrorKind::Ssm, "Failed to build the instance filter")
+                    .with_source(err)
+            })?;
+        let output = self
+            .describe_instance_information()
+            .filters(filter)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ssm,
+                    format!("Failed to describe the managed instance {instance_id}"),
+                    err,
+                )
+            })?;
+        Ok(output.instance_information_list().first().cloned())
+    }
+
+    fn region(&self) -> Option<String> {
+        self.config().region().map(|region| region.to_string())
+    }
+}
+
+impl S3Api for aws_sdk_s3::Client {
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        body: ByteStream,
+    ) -> OrchResult<()> {
+        self.put_object()
+            .bucket(bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::S3,
+                    format!("Failed to upload s3://{bucket}/{key}"),
+                    err,
+                )
+            })?;
+        Ok(())
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> OrchResult<Option<Vec<u8>>> {
+        let output = match self.get_object().bucket(bucket).key(key).send().await {
+            Ok(output) => output,
+            Err(err) => {
+                let err = err.into_service_error();
+                if err.is_no_such_key() {
+                    return Ok(None);
+                }
+                return Err(OrchError::new(
+                    ErrorKind::S3,
+                    format!("Failed to download s3://{bucket}/{key}"),
+                )
+                .with_source(err));
+            }
+        };
+        let body = output.body.collect().await.map_err(|err| {
+            OrchError::new(
+                ErrorKind::S3,
+                format!("Failed to download s3://{bucket}/{key}"),
+            )
+            .with_source(err)
+            .retryable()
+        })?;
+        Ok(Some(body.to_vec()))
+    }
+
+    async fn list_keys(&self, bucket: &str, prefix: &str) -> OrchResult<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut pages = self
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::S3,
+                    format!("Failed to list s3://{bucket}/{prefix}"),
+                    err,
+                )
+            })?;
+            keys.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key().map(str::to_string)),
+            );
+        }
+        Ok(keys)
+    }
+
+    async fn delete_objects(&self, bucket: &str, keys: &[String]) -> OrchResult<()> {
+        let invalid =
+            |err| OrchError::new(ErrorKind::S3, "Invalid delete request").with_source(err);
+        // a request deletes at most 1000 objects
+        for keys in keys.chunks(1000) {
+            let objects = keys
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(invalid)?;
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+                .map_err(invalid)?;
+            let output = self
+                .delete_objects()
+                .bucket(bucket)
+                .delete(delete)
+                .send()
+                .await
+                .map_err(|err| {
+                    OrchError::sdk(
+                        ErrorKind::S3,
+                        format!("Failed to delete objects from s3://{bucket}"),
+                        err,
+                    )
+                })?;
+            if let Some(error) = output.errors().first() {
+                return Err(OrchError::new(
+                    ErrorKind::S3,
+                    format!(
+                        "Failed to delete s3://{bucket}/{}: {}",
+                        error.key().unwrap_or_default(),
+                        error.message().unwrap_or_default()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn presign_get_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        expires_in: Duration,
+    ) -> OrchResult<String> {
+        let presigning = PresigningConfig::expires_in(expires_in).map_err(|err| {
+            OrchError::new(ErrorKind::S3, "Invalid report link expiry").with_source(err)
+        })?;
+        let request = self
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .presigned(presigning)
+            .await
+            .map_err(|err| {
+                OrchError::new(ErrorKind::S3, "Failed to presign the report link").with_source(err)
+            })?;
+        Ok(request.uri().to_string())
+    }
+}
+
+impl IamApi for aws_sdk_iam::Client {
+    async fn instance_profile_arn(&self, name: &str) -> OrchResult<Option<String>> {
+        let output = self
+            .get_instance_profile()
+            .instance_profile_name(name)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Iam,
+                    format!("Failed to get instance profile {name}"),
+                    err,
+                )
+            })?;
+        Ok(output
+            .instance_profile()
+            .map(|instance_profile| instance_profile.arn().to_string()))
+    }
+}
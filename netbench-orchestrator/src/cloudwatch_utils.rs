@@ -0,0 +1,197 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::Partition;
+use aws_sdk_cloudwatchlogs::types::InputLogEvent;
+use std::{
+    io,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tracing_subscriber::fmt::MakeWriter;
+
+// PutLogEvents accepts at most 10,000 events (and 1MB) per request
+const MAX_BATCH: usize = 1000;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+// The log stream of the orchestrator's own logs
+pub fn orchestrator_log_stream(unique_id: &str) -> String {
+    format!("orchestrator/{unique_id}")
+}
+
+// The log stream SSM writes the stdout of a command to, which includes the
+// logs of the russula workers.
+pub fn ssm_stdout_log_stream(command_id: &str, instance_id: &str) -> String {
+    format!("{command_id}/{instance_id}/aws-runShellScript/stdout")
+}
+
+// A link to a log stream in the CloudWatch console.
+pub fn log_stream_url(region: &str, log_group: &str, log_stream: &str) -> String {
+    format!(
+        "{}/cloudwatch/home?region={region}#logsV2:log-groups/log-group/{}/log-events/{}",
+        Partition::of_region(region).console_url(region),
+        console_encode(log_group),
+        console_encode(log_stream)
+    )
+}
+
+// The console url encodes the names twice, with `$` in place of the first `%`.
+fn console_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("$25{b:02X}"),
+        })
+        .collect()
+}
+
+type LogLine = (i64, String);
+
+// Creates the writer for a tracing layer whose output is shipped to
+// CloudWatch, along with the receiving end to pass to `LogShipper::spawn`.
+//
+// Lines written before the shipper is spawned are buffered, since the log
+// group is only known once the config has been loaded.
+pub fn log_channel() -> (LogWriter, mpsc::UnboundedReceiver<LogLine>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (LogWriter { tx }, rx)
+}
+
+#[derive(Clone)]
+pub struct LogWriter {
+    tx: mpsc::UnboundedSender<LogLine>,
+}
+
+impl io::Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let line = String::from_utf8_lossy(buf).trim_end().to_string();
+        // the shipper was never started or has stopped
+        let _ = self.tx.send((timestamp, line));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+// Periodically ships the lines written to a `LogWriter` to a CloudWatch log
+// stream.
+//
+// Shipping is best effort: failures are printed to stderr rather than
+// logged, since the logs would be fed back to the shipper.
+pub struct LogShipper {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl LogShipper {
+    pub fn spawn(
+        aws_config: &aws_types::SdkConfig,
+        log_group: &str,
+        log_stream: &str,
+        mut rx: mpsc::UnboundedReceiver<LogLine>,
+    ) -> Self {
+        let client = aws_sdk_cloudwatchlogs::Client::new(aws_config);
+        let log_group = log_group.to_string();
+        let log_stream = log_stream.to_string();
+        let (stop, mut stopped) = oneshot::channel();
+
+        let task = tokio::spawn(async move {
+            if let Err(err) = client
+                .create_log_stream()
+                .log_group_name(&log_group)
+                .log_stream_name(&log_stream)
+                .send()
+                .await
+            {
+                let err = err.into_service_error();
+                if !err.is_resource_already_exists_exception() {
+                    eprintln!("Failed to create log stream {log_stream}. {err}");
+                    return;
+                }
+            }
+
+            loop {
+                let done = tokio::select! {
+                    _ = &mut stopped => true,
+                    _ = tokio::time::sleep(FLUSH_INTERVAL) => false,
+                };
+
+                let mut lines = Vec::new();
+                while let Ok(line) = rx.try_recv() {
+                    lines.push(line);
+                }
+                for batch in lines.chunks(MAX_BATCH) {
+                    let events = batch
+                        .iter()
+                        .filter_map(|(timestamp, message)| {
+                            InputLogEvent::builder()
+                                .timestamp(*timestamp)
+                                .message(message)
+                                .build()
+                                .ok()
+                        })
+                        .collect();
+                    if let Err(err) = client
+                        .put_log_events()
+                        .log_group_name(&log_group)
+                        .log_stream_name(&log_stream)
+                        .set_log_events(Some(events))
+                        .send()
+                        .await
+                    {
+                        eprintln!("Failed to ship logs to {log_stream}. {err}");
+                    }
+                }
+
+                if done {
+                    break;
+                }
+            }
+        });
+
+        LogShipper { stop, task }
+    }
+
+    // Ships the remaining lines and stops the shipper.
+    pub async fn finish(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn console_url() {
+        assert_eq!(
+            log_stream_url(
+                "us-west-2",
+                "NetbenchRunnerLogGroup",
+                &orchestrator_log_stream("2024-01-01T00:00:00Z-v1.0.0")
+            ),
+            "https://us-west-2.console.aws.amazon.com/cloudwatch/home?region=us-west-2#logsV2:log-groups/log-group/NetbenchRunnerLogGroup/log-events/orchestrator$252F2024-01-01T00$253A00$253A00Z-v1.0.0"
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+// The backend which provides the hosts of a run.
+//
+// The coordination pipeline only provisions the server and client hosts, runs
+// commands on them, starts the Russula workers, collects the results the
+// workers write and releases the hosts once the run is done. A backend
+// implements these operations, so that the pipeline can run on something
+// other than EC2 (eg. an existing fleet reached over SSH, ECS or a lab)
+// without changes.
+//
+// `Ec2Provider`, which launches EC2 instances and runs the commands with SSM,
+// is the default.
+
+use crate::{
+    ec2_utils::{EndpointType, InfraDetail, InstanceDetail},
+    orchestrator::{OrchResult, OrchestratorConfig},
+    ssm_utils::{NetbenchDriverType, Step},
+};
+use core::task::Poll;
+use std::{collections::BTreeMap, future::Future, ops::Deref, time::Duration};
+
+mod ec2;
+
+pub use ec2::Ec2Provider;
+
+pub trait ComputeProvider: Send + Sync {
+    // The provisioned hosts. The hosts should be released with `release`, but
+    // are also released if the handle is dropped, eg. on a panic.
+    type Hosts: Deref<Target = InfraDetail> + Send + Sync;
+
+    // Provisions the server and client hosts of `config`
+    fn provision(
+        &self,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> impl Future<Output = OrchResult<Self::Hosts>> + Send;
+
+    fn release(&self, hosts: Self::Hosts) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // Leaves the hosts running instead of releasing them, for interactive
+    // clients once the run is done (see `--keep-servers`). The hosts must
+    // still be released after `ttl`.
+    fn keep(
+        &self,
+        hosts: Self::Hosts,
+        ttl: Duration,
+        config: &OrchestratorConfig,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // Installs the dependencies, drivers and Russula on the hosts. Returns
+    // how long the setup of each driver took, by driver name.
+    //
+    // In loopback mode the client hosts get the server drivers as well. The
+    // skipped phases (`--skip-configure` and `--skip-build`) are only checked.
+    fn configure(
+        &self,
+        hosts: &InfraDetail,
+        unique_id: &str,
+        server_drivers: &[NetbenchDriverType],
+        client_drivers: &[NetbenchDriverType],
+        config: &OrchestratorConfig,
+    ) -> impl Future<Output = OrchResult<BTreeMap<String, Duration>>> + Send;
+
+    // Starts the commands of a step on the hosts and returns the id of the
+    // command, which is used to poll it.
+    fn execute(
+        &self,
+        step: Step,
+        comment: &str,
+        host_ids: Vec<String>,
+        commands: Vec<String>,
+        config: &OrchestratorConfig,
+    ) -> impl Future<Output = OrchResult<String>> + Send;
+
+    // The standard output of a command on a host, or None until the command
+    // has succeeded there
+    fn output(
+        &self,
+        command_id: &str,
+        host_id: &str,
+    ) -> impl Future<Output = OrchResult<Option<String>>> + Send;
+
+    // Ready once the command has succeeded on all of its hosts, or an error
+    // if it failed on any of them
+    fn poll(
+        &self,
+        host_group: &str,
+        command_id: &str,
+    ) -> impl Future<Output = OrchResult<Poll<()>>> + Send;
+
+    // Stops a running command on all of its hosts
+    fn cancel(&self, command_id: &str) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // Starts the Russula workers of the driver on the server or client hosts,
+    // and returns the id of the command running them
+    //
+    // In loopback mode the client workers also run the `loopback_server`
+    // driver, since there are no server hosts. With `keep_servers` the server
+    // workers leave their servers running once they're done, see
+    // `--keep-servers`.
+    fn start_worker(
+        &self,
+        hosts: &InfraDetail,
+        endpoint_type: EndpointType,
+        driver: &NetbenchDriverType,
+        loopback_server: Option<&NetbenchDriverType>,
+        keep_servers: bool,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> impl Future<Output = OrchResult<String>> + Send;
+
+    // Moves the results of a driver pair from the hosts to the results bucket
+    fn collect_artifacts(
+        &self,
+        hosts: &InfraDetail,
+        unique_id: &str,
+        server_driver: &NetbenchDriverType,
+        client_driver: &NetbenchDriverType,
+        iteration: Option<u32>,
+        config: &OrchestratorConfig,
+    ) -> impl Future<Output = OrchResult<()>> + Send;
+
+    // Backend specific details to debug a failed run on the host, by the
+    // name of the diagnostics folder they're written to, eg. `console`
+    fn diagnostics(
+        &self,
+        host: &InstanceDetail,
+    ) -> impl Future<Output = Vec<(&'static str, OrchResult<String>)>> + Send;
+}
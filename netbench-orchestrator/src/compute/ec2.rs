@@ -0,0 +1,438 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::ComputeProvider;
+use crate::{
+    audit,
+    aws_api::{Ec2Api, IamApi, SsmApi},
+    ec2_utils::{
+        EndpointType, InfraDetail, InfraGuard, InstanceDetail, LaunchPlan, EXPIRES_AT_TAG,
+    },
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig},
+    progress,
+    ssm_utils::{self, NetbenchDriverType, Step},
+};
+use base64::Engine;
+use core::task::Poll;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    time::{Duration, SystemTime},
+};
+use tracing::{info, instrument, warn};
+
+// Launches EC2 instances for the hosts and runs the commands on them with SSM.
+#[derive(Clone, Debug)]
+pub struct Ec2Provider<E, S, I> {
+    ec2_client: E,
+    ssm_client: S,
+    iam_client: I,
+}
+
+impl<E: Ec2Api, S: SsmApi, I: IamApi> Ec2Provider<E, S, I> {
+    pub fn new(ec2_client: E, ssm_client: S, iam_client: I) -> Self {
+        Ec2Provider {
+            ec2_client,
+            ssm_client,
+            iam_client,
+        }
+    }
+
+    // Reschedules the shutdown of the launched hosts to `ttl` from now, and
+    // tags them and the resources they retain with the time they shut down.
+    // The shutdown terminates the instances, and the retained resources are
+    // deleted by `prune` once they expired. The servers kept on managed hosts
+    // are stopped by their workers after `ttl`.
+    async fn expire(
+        &self,
+        hosts: &InfraDetail,
+        ttl: Duration,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<String> {
+        let expires_at = humantime::format_rfc3339_seconds(SystemTime::now() + ttl).to_string();
+        // the managed instances weren't launched for the run
+        let instance_ids: Vec<String> = hosts
+            .servers
+            .iter()
+            .chain(hosts.clients.iter())
+            .filter(|host| !host.is_managed())
+            .map(|host| host.instance_id().to_string())
+            .collect();
+
+        if !instance_ids.is_empty() {
+            // replaces the shutdown scheduled when the hosts were configured
+            let minutes = ttl.as_secs().div_ceil(60).max(1);
+            let cmd = ssm_utils::send_command(
+                vec![],
+                Step::KeepHosts,
+                "keep_hosts",
+                &self.ssm_client,
+                instance_ids.clone(),
+                vec![format!("shutdown -P +{minutes}")],
+                config,
+            )
+            .await?;
+            ssm_utils::common::wait_complete(
+                "Keep hosts: reschedule the shutdown",
+                &self.ssm_client,
+                vec![cmd],
+            )
+            .await?;
+        }
+
+        let resource_ids: Vec<String> = instance_ids
+            .into_iter()
+            .chain(hosts.retained_resource_ids())
+            .collect();
+        self.ec2_client
+            .create_tags(
+                resource_ids.clone(),
+                vec![(EXPIRES_AT_TAG.to_string(), expires_at.clone())],
+            )
+            .await?;
+        audit::record!(
+            "ec2",
+            "create_tags",
+            resource_ids = ?resource_ids,
+            expires_at = %expires_at
+        );
+        Ok(expires_at)
+    }
+}
+
+impl<E: Ec2Api, S: SsmApi, I: IamApi> ComputeProvider for Ec2Provider<E, S, I> {
+    type Hosts = InfraGuard<E>;
+
+    async fn provision(
+        &self,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<InfraGuard<E>> {
+        LaunchPlan::create(&self.ec2_client, &self.iam_client, &self.ssm_client, config)
+            .await?
+            .launch(&self.ec2_client, &self.ssm_client, unique_id)
+            .await
+    }
+
+    async fn release(&self, hosts: InfraGuard<E>) -> OrchResult<()> {
+        hosts.cleanup().await
+    }
+
+    // The hosts are only kept once their shutdown is rescheduled, so that
+    // they're never left running without a TTL.
+    async fn keep(
+        &self,
+        hosts: InfraGuard<E>,
+        ttl: Duration,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<()> {
+        let expires_at = match self.expire(&hosts, ttl, config).await {
+            Ok(expires_at) => expires_at,
+            Err(err) => {
+                warn!("Releasing the hosts, which can't be kept without a TTL. {err}");
+                hosts.cleanup().await?;
+                return Err(err);
+            }
+        };
+        let infra = hosts.keep();
+        for host in infra.servers.iter().chain(infra.clients.iter()) {
+            progress::output!("Kept: {host}");
+        }
+        progress::output!("Kept: the hosts shut down and the servers stop at {expires_at}");
+        progress::output!(
+            "Kept: {} are deleted by `prune` once they expired",
+            infra.retained_resource_ids().join(", ")
+        );
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn configure(
+        &self,
+        hosts: &InfraDetail,
+        unique_id: &str,
+        server_drivers: &[NetbenchDriverType],
+        client_drivers: &[NetbenchDriverType],
+        config: &OrchestratorConfig,
+    ) -> OrchResult<BTreeMap<String, Duration>> {
+        let build_cmds = if config.loopback() {
+            // the servers run on the client hosts
+            let drivers: Vec<_> = server_drivers
+                .iter()
+                .chain(client_drivers)
+                .cloned()
+                .collect();
+            ssm_utils::common::collect_config_cmds(
+                EndpointType::Client,
+                &self.ssm_client,
+                hosts.client_ids(),
+                &drivers,
+                unique_id,
+                config,
+            )
+            .await?
+        } else {
+            let (mut build_cmds, client_build_cmds) = tokio::try_join!(
+                ssm_utils::common::collect_config_cmds(
+                    EndpointType::Server,
+                    &self.ssm_client,
+                    hosts.server_ids(),
+                    server_drivers,
+                    unique_id,
+                    config,
+                ),
+                ssm_utils::common::collect_config_cmds(
+                    EndpointType::Client,
+                    &self.ssm_client,
+                    hosts.client_ids(),
+                    client_drivers,
+                    unique_id,
+                    config,
+                ),
+            )?;
+            build_cmds.extend(client_build_cmds);
+            build_cmds
+        };
+        let completed = ssm_utils::common::wait_complete(
+            "Setup hosts: update and install dependencies",
+            &self.ssm_client,
+            build_cmds,
+        )
+        .await?;
+
+        info!("Host setup Successful");
+        Ok(build_durations(completed))
+    }
+
+    async fn execute(
+        &self,
+        step: Step,
+        comment: &str,
+        host_ids: Vec<String>,
+        commands: Vec<String>,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<String> {
+        let cmd = ssm_utils::send_command(
+            vec![],
+            step,
+            comment,
+            &self.ssm_client,
+            host_ids,
+            commands,
+            config,
+        )
+        .await?;
+        Ok(ssm_utils::command_id(&cmd)?.to_string())
+    }
+
+    async fn output(&self, command_id: &str, host_id: &str) -> OrchResult<Option<String>> {
+        ssm_utils::command_output(&self.ssm_client, command_id, host_id).await
+    }
+
+    async fn poll(&self, host_group: &str, command_id: &str) -> OrchResult<Poll<()>> {
+        ssm_utils::poll_ssm_results(host_group, &self.ssm_client, command_id).await
+    }
+
+    async fn cancel(&self, command_id: &str) -> OrchResult<()> {
+        ssm_utils::cancel_command(&self.ssm_client, command_id).await
+    }
+
+    async fn start_worker(
+        &self,
+        hosts: &InfraDetail,
+        endpoint_type: EndpointType,
+        driver: &NetbenchDriverType,
+        loopback_server: Option<&NetbenchDriverType>,
+        keep_servers: bool,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<String> {
+        let servers = hosts.server_map(driver, config)?;
+        let worker = match endpoint_type {
+            EndpointType::Server => {
+                ssm_utils::server::run_russula_worker(
+                    &self.ssm_client,
+                    hosts.server_ids(),
+                    &servers,
+                    driver,
+                    keep_servers,
+                    unique_id,
+                    config,
+                )
+                .await?
+            }
+            EndpointType::Client => {
+                ssm_utils::client::run_russula_worker(
+                    &self.ssm_client,
+                    hosts.client_ids(),
+                    &servers,
+                    driver,
+                    loopback_server,
+                    unique_id,
+                    config,
+                )
+                .await?
+            }
+        };
+        Ok(ssm_utils::command_id(&worker)?.to_string())
+    }
+
+    #[instrument(skip_all)]
+    async fn collect_artifacts(
+        &self,
+        hosts: &InfraDetail,
+        unique_id: &str,
+        server_driver: &NetbenchDriverType,
+        client_driver: &NetbenchDriverType,
+        iteration: Option<u32>,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<()> {
+        let copy_netbench = if config.loopback() {
+            // the server results are on the client hosts
+            vec![
+                ssm_utils::common::upload_netbench_data_to_s3(
+                    &self.ssm_client,
+                    hosts.client_ids(),
+                    unique_id,
+                    config,
+                    &[server_driver, client_driver],
+                    iteration,
+                )
+                .await?,
+            ]
+        } else {
+            let (copy_server_netbench, copy_client_netbench) = tokio::try_join!(
+                ssm_utils::common::upload_netbench_data_to_s3(
+                    &self.ssm_client,
+                    hosts.server_ids(),
+                    unique_id,
+                    config,
+                    &[server_driver],
+                    iteration,
+                ),
+                ssm_utils::common::upload_netbench_data_to_s3(
+                    &self.ssm_client,
+                    hosts.client_ids(),
+                    unique_id,
+                    config,
+                    &[client_driver],
+                    iteration,
+                ),
+            )?;
+            vec![copy_server_netbench, copy_client_netbench]
+        };
+        let msg = format!(
+            "copy netbench results to s3 for drivers: {}, {}",
+            server_driver.results_name(),
+            client_driver.results_name()
+        );
+        ssm_utils::common::wait_complete(&msg, &self.ssm_client, copy_netbench).await?;
+        info!("client_server netbench copy results!: Successful");
+
+        Ok(())
+    }
+
+    // - ssm: the SSM commands run on the host and their output
+    // - console: the EC2 console output of the host, unless it's a managed
+    //   instance
+    async fn diagnostics(&self, host: &InstanceDetail) -> Vec<(&'static str, OrchResult<String>)> {
+        let instance_id = host.instance_id();
+        let mut diagnostics = vec![("ssm", ssm_invocations(&self.ssm_client, instance_id).await)];
+        // the managed instances aren't EC2 instances
+        if !host.is_managed() {
+            diagnostics.push((
+                "console",
+                console_output(&self.ec2_client, instance_id).await,
+            ));
+        }
+        diagnostics
+    }
+}
+
+// The time each driver took to build, keyed by driver name.
+//
+// The builds wait for the dependencies to be installed on the hosts, so the
+// time until the other setup steps completed is subtracted.
+fn build_durations(completed: Vec<(String, Duration)>) -> BTreeMap<String, Duration> {
+    let setup_done = completed
+        .iter()
+        .filter(|(comment, _)| !comment.starts_with("build_"))
+        .map(|(_, elapsed)| *elapsed)
+        .max()
+        .unwrap_or_default();
+    let mut builds: BTreeMap<String, Duration> = BTreeMap::new();
+    for (comment, elapsed) in completed {
+        if let Some(driver) = comment.strip_prefix("build_driver_") {
+            // the slowest of the server and client hosts
+            let build = builds.entry(driver.to_string()).or_default();
+            *build = (*build).max(elapsed.saturating_sub(setup_done));
+        }
+    }
+    builds
+}
+
+// Every SSM command run on the host, oldest first, with the output of each of
+// its steps.
+//
+// SSM truncates the output returned here, but the complete output is in the
+// CloudWatch log group.
+async fn ssm_invocations(ssm_client: &impl SsmApi, instance_id: &str) -> OrchResult<String> {
+    let mut invocations = ssm_client.list_instance_invocations(instance_id).await?;
+    invocations.sort_by_key(|invocation| invocation.requested_date_time().map(|date| date.secs()));
+
+    let mut out = String::new();
+    for invocation in invocations {
+        let _ = writeln!(
+            out,
+            "=== {} ({}): {:?} requested {:?}",
+            invocation.comment().unwrap_or_default(),
+            invocation.command_id().unwrap_or_default(),
+            invocation.status(),
+            invocation.requested_date_time(),
+        );
+        for plugin in invocation.command_plugins() {
+            let _ = writeln!(
+                out,
+                "--- {}: {:?} exit code {}",
+                plugin.name().unwrap_or_default(),
+                plugin.status(),
+                plugin.response_code(),
+            );
+            let _ = writeln!(out, "{}", plugin.output().unwrap_or_default());
+        }
+    }
+    Ok(out)
+}
+
+async fn console_output(ec2_client: &impl Ec2Api, instance_id: &str) -> OrchResult<String> {
+    let Some(encoded) = ec2_client.console_output(instance_id).await? else {
+        return Ok(String::new());
+    };
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Ec2, "Failed to decode the console output").with_source(err)
+        })?;
+    Ok(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn driver_build_durations() {
+        let secs = Duration::from_secs;
+        let completed = vec![
+            ("configure_host_server".to_string(), secs(60)),
+            ("build_russula_server".to_string(), secs(90)),
+            ("build_driver_s2n-quic".to_string(), secs(150)),
+            ("configure_host_client".to_string(), secs(70)),
+            ("build_driver_s2n-quic".to_string(), secs(170)),
+            ("build_driver_tcp".to_string(), secs(80)),
+        ];
+        let builds = build_durations(completed);
+        assert_eq!(builds["s2n-quic"], secs(100));
+        assert_eq!(builds["tcp"], secs(10));
+    }
+}
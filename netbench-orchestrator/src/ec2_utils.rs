@@ -1,18 +1,38 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::orchestrator::{OrchError, OrchResult};
-use aws_sdk_ec2::{error::SdkError, types::PlacementGroup};
-use std::{collections::HashMap, time::Duration};
+use crate::{
+    audit,
+    aws_api::{Deletion, Ec2Api},
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig, ServerMap},
+    ssm_utils::NetbenchDriverType,
+};
+use aws_sdk_ec2::types::PlacementGroup;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
 use tracing::{debug, error, info};
 
+mod controller;
+mod fleet;
+mod guard;
+mod hybrid;
 mod instance;
 mod launch_plan;
 mod networking;
 mod types;
 
+pub use controller::Controller;
+pub use guard::InfraGuard;
+pub use instance::instance_name;
 pub use launch_plan::LaunchPlan;
-pub use types::{Az, EndpointType, InstanceDetail, PrivIp, PubIp};
+pub use types::{Az, EndpointType, HostIps, InstanceDetail, PrivIp, PubIp};
+
+// The tag of the hosts kept with `--keep-servers` and the resources they
+// retain, with the time the hosts shut down
+pub const EXPIRES_AT_TAG: &str = "netbench:expires-at";
 
 const MAX_RETRY_COUNT: usize = 25;
 const RETRY_BACKOFF: Duration = Duration::from_secs(5);
@@ -23,19 +43,50 @@ pub struct InfraDetail {
     pub clients: Vec<InstanceDetail>,
     pub servers: Vec<InstanceDetail>,
     placement_map: HashMap<Az, PlacementGroup>,
+    // The launch template of the fleets, with `--fleet`
+    launch_template_id: Option<String>,
+    // Instances which were launched but aren't running yet, so that a launch
+    // which fails while waiting for them still terminates them.
+    unresolved_ids: Vec<String>,
 }
 
 impl InfraDetail {
-    pub async fn cleanup(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
+    pub async fn cleanup(&self, ec2_client: &impl Ec2Api) -> OrchResult<()> {
         // instances must be deleted before other infra
         self.delete_instances(ec2_client).await?;
 
+        if let Some(launch_template_id) = &self.launch_template_id {
+            ec2_client
+                .delete_launch_template(launch_template_id)
+                .await?;
+            audit::record!(
+                "ec2",
+                "delete_launch_template",
+                launch_template_id = %launch_template_id
+            );
+        }
+
         // attempt only after deleting instances
         self.delete_placement_group(ec2_client).await?;
         self.delete_security_group(ec2_client).await?;
         Ok(())
     }
 
+    // The ids of the resources which aren't deleted when the instances
+    // terminate, which are left behind when the hosts are kept (see
+    // `--keep-servers`)
+    pub fn retained_resource_ids(&self) -> Vec<String> {
+        let mut resources = vec![self.security_group_id.clone()];
+        resources.extend(
+            self.placement_map
+                .values()
+                .filter_map(|placement_group| placement_group.group_id())
+                .map(str::to_string),
+        );
+        resources.extend(self.launch_template_id.iter().cloned());
+        resources
+    }
+
     pub fn server_ids(&self) -> Vec<String> {
         self.servers
             .iter()
@@ -64,79 +115,75 @@ impl InfraDetail {
             .collect()
     }
 
+    // The private address of each of the scenario's servers for the driver,
+    // or the loopback address in loopback mode
+    pub fn server_map(
+        &self,
+        driver: &NetbenchDriverType,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<ServerMap> {
+        let host_ips: Vec<IpAddr> = if config.loopback() {
+            vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]
+        } else {
+            self.private_server_ips().iter().map(|ip| ip.0).collect()
+        };
+        ServerMap::for_scenario(config, &host_ips, driver.port())
+    }
+
     pub fn public_client_ips(&self) -> Vec<&PubIp> {
         self.clients
             .iter()
             .map(|instance| instance.host_ips().public_ip())
             .collect()
     }
+
+    // The addresses the coordinators connect to the Russula workers of the
+    // host group on: the public ips, or the private ips when the orchestrator
+    // runs in the hosts' VPC. See `ControllerConfig`.
+    pub fn russula_ips(
+        &self,
+        endpoint_type: EndpointType,
+        config: &OrchestratorConfig,
+    ) -> Vec<IpAddr> {
+        let hosts = match endpoint_type {
+            EndpointType::Server => &self.servers,
+            EndpointType::Client => &self.clients,
+        };
+        hosts
+            .iter()
+            .map(|instance| match config.controller.russula_private_ips() {
+                true => instance.host_ips().private_ip().0,
+                false => instance.host_ips().public_ip().0,
+            })
+            .collect()
+    }
 }
 
 impl InfraDetail {
-    async fn delete_instances(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
+    async fn delete_instances(&self, ec2_client: &impl Ec2Api) -> OrchResult<()> {
         info!("Start: deleting instances");
-        let mut ids = self.client_ids();
-        ids.append(&mut self.server_ids());
-
-        ec2_client
-            .terminate_instances()
-            .set_instance_ids(Some(ids))
-            .send()
-            .await
-            .map_err(|err| OrchError::Ec2 {
-                dbg: err.to_string(),
-            })?;
+        // the managed instances weren't launched for the run
+        let mut ids: Vec<String> = self
+            .clients
+            .iter()
+            .chain(self.servers.iter())
+            .filter(|host| !host.is_managed())
+            .map(|host| host.instance_id().to_string())
+            .collect();
+        ids.extend(self.unresolved_ids.iter().cloned());
+        // a launch can fail before any instance was launched
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        ec2_client.terminate_instances(ids.clone()).await?;
+        audit::record!("ec2", "terminate_instances", instance_ids = ?ids);
 
         Ok(())
     }
 
-    // Attempt to delete the Security Group.
-    //
-    // Retry the operation if the resource is still 'in-use' (`DependencyViolation`). Since an
-    // EC2 instance takes time to fully terminate, a Security Group could be 'in-use' until the
-    // EC2 host is fully cleaned up.
-    async fn delete_security_group(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
-        info!("Start: deleting security groups");
-
-        let mut attempt = 0;
-        while attempt < MAX_RETRY_COUNT {
-            attempt += 1;
-            let delete_security_group = ec2_client
-                .delete_security_group()
-                .group_id(self.security_group_id.to_string())
-                .send()
-                .await;
-            debug!(
-                "deleting security group. attempt: {attempt}. result: {:?}",
-                delete_security_group
-            );
-
-            match delete_security_group {
-                Ok(_) => break,
-                Err(SdkError::ServiceError(service_err))
-                    if service_err.err().meta().code() == Some("DependencyViolation") =>
-                {
-                    // retryable error
-                    tokio::time::sleep(RETRY_BACKOFF).await;
-                }
-                Err(err) => {
-                    // non-retryable error
-                    error!("abort deleting security group {}", self.security_group_id);
-                    return Err(OrchError::Ec2 {
-                        dbg: err.to_string(),
-                    });
-                }
-            }
-
-            if attempt == MAX_RETRY_COUNT {
-                error!("abort deleting security group {}", self.security_group_id);
-                return Err(OrchError::Ec2 {
-                    dbg: "Failed to delete security group because it's still in use".to_string(),
-                });
-            }
-        }
-
-        Ok(())
+    async fn delete_security_group(&self, ec2_client: &impl Ec2Api) -> OrchResult<()> {
+        delete_security_group(ec2_client, &self.security_group_id).await
     }
 
     // Attempt to delete the Placement Group.
@@ -144,20 +191,19 @@ impl InfraDetail {
     // Retry the operation if the resource is still 'in-use' (`InvalidPlacementGroup.InUse`). Since
     // an EC2 instance takes time to fully terminate, a Placement Group could be 'in-use' until the
     // EC2 host is fully cleaned up.
-    async fn delete_placement_group(&self, ec2_client: &aws_sdk_ec2::Client) -> OrchResult<()> {
+    async fn delete_placement_group(&self, ec2_client: &impl Ec2Api) -> OrchResult<()> {
         info!("Start: deleting placement groups");
         for (_az, placement_group) in self.placement_map.iter() {
-            let placement_group_name = placement_group.group_name().ok_or(OrchError::Ec2 {
-                dbg: "Failed to get placement_group name".to_string(),
-            })?;
+            let placement_group_name = placement_group.group_name().ok_or(OrchError::new(
+                ErrorKind::Ec2,
+                "Failed to get placement_group name",
+            ))?;
 
             let mut attempt = 0;
             while attempt < MAX_RETRY_COUNT {
                 attempt += 1;
                 let delete_placement_group = ec2_client
-                    .delete_placement_group()
-                    .group_name(placement_group_name)
-                    .send()
+                    .delete_placement_group(placement_group_name)
                     .await;
                 debug!(
                     "deleting placement group. attempt: {attempt}. \nresult: {:?}",
@@ -165,29 +211,31 @@ impl InfraDetail {
                 );
 
                 match delete_placement_group {
-                    Ok(_) => break,
-                    Err(SdkError::ServiceError(service_err))
-                        if service_err.err().meta().code()
-                            == Some("InvalidPlacementGroup.InUse") =>
-                    {
+                    Ok(Deletion::Deleted) => {
+                        audit::record!(
+                            "ec2",
+                            "delete_placement_group",
+                            placement_group = placement_group_name
+                        );
+                        break;
+                    }
+                    Ok(Deletion::InUse) => {
                         // retryable error
                         tokio::time::sleep(RETRY_BACKOFF).await;
                     }
                     Err(err) => {
                         // non-retryable error
                         error!("abort deleting placement group {:?}", placement_group);
-                        return Err(OrchError::Ec2 {
-                            dbg: err.to_string(),
-                        });
+                        return Err(err);
                     }
                 }
 
                 if attempt == MAX_RETRY_COUNT {
                     error!("abort deleting placement groups");
-                    return Err(OrchError::Ec2 {
-                        dbg: "Failed to delete placement group because it's still in-use"
-                            .to_string(),
-                    });
+                    return Err(OrchError::new(
+                        ErrorKind::Ec2,
+                        "Failed to delete placement group because it's still in-use",
+                    ));
                 }
             }
         }
@@ -195,3 +243,55 @@ impl InfraDetail {
         Ok(())
     }
 }
+
+// Attempt to delete the Security Group.
+//
+// Retry the operation if the resource is still 'in-use' (`DependencyViolation`). Since an
+// EC2 instance takes time to fully terminate, a Security Group could be 'in-use' until the
+// EC2 host is fully cleaned up.
+async fn delete_security_group(
+    ec2_client: &impl Ec2Api,
+    security_group_id: &str,
+) -> OrchResult<()> {
+    info!("Start: deleting security groups");
+
+    let mut attempt = 0;
+    while attempt < MAX_RETRY_COUNT {
+        attempt += 1;
+        let delete_security_group = ec2_client.delete_security_group(security_group_id).await;
+        debug!(
+            "deleting security group. attempt: {attempt}. result: {:?}",
+            delete_security_group
+        );
+
+        match delete_security_group {
+            Ok(Deletion::Deleted) => {
+                audit::record!(
+                    "ec2",
+                    "delete_security_group",
+                    security_group_id = %security_group_id
+                );
+                break;
+            }
+            Ok(Deletion::InUse) => {
+                // retryable error
+                tokio::time::sleep(RETRY_BACKOFF).await;
+            }
+            Err(err) => {
+                // non-retryable error
+                error!("abort deleting security group {}", security_group_id);
+                return Err(err);
+            }
+        }
+
+        if attempt == MAX_RETRY_COUNT {
+            error!("abort deleting security group {}", security_group_id);
+            return Err(OrchError::new(
+                ErrorKind::Ec2,
+                "Failed to delete security group because it's still in use",
+            ));
+        }
+    }
+
+    Ok(())
+}
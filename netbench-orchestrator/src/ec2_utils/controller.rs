@@ -0,0 +1,183 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit,
+    aws_api::{Ec2Api, IamApi, LaunchRequest, SsmApi},
+    ec2_utils::{delete_security_group, instance, networking, Az},
+    orchestrator::{state, ErrorKind, OrchError, OrchResult, OrchestratorConfig},
+    russula::Backoff,
+};
+use aws_sdk_ec2::types::Placement;
+use aws_sdk_ssm::types::PingStatus;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// How long a launched controller has to register with SSM
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const MIN_POLL_DELAY: Duration = Duration::from_secs(5);
+
+// The instance in the hosts' VPC which runs the orchestrator in controller
+// mode. See `ControllerConfig`.
+#[derive(Debug)]
+pub struct Controller {
+    instance_id: String,
+    // Only set for a controller launched for the run, which is terminated
+    // along with its security group once the run is done
+    security_group_id: Option<String>,
+}
+
+impl Controller {
+    // Launches the controller in the subnet of the first client host, or uses
+    // the `--controller-instance`. Returns once the controller's SSM agent is
+    // online.
+    pub async fn provision(
+        ec2_client: &impl Ec2Api,
+        iam_client: &impl IamApi,
+        ssm_client: &impl SsmApi,
+        config: &OrchestratorConfig,
+        unique_id: &str,
+    ) -> OrchResult<Self> {
+        if let Some(instance_id) = config.controller.instance() {
+            let controller = Controller {
+                instance_id: instance_id.to_string(),
+                security_group_id: None,
+            };
+            controller.wait_online(ssm_client, Duration::ZERO).await?;
+            return Ok(controller);
+        }
+
+        let profile = config.controller.instance_profile().ok_or_else(|| {
+            OrchError::new(
+                ErrorKind::Init,
+                "--controller-instance-profile is required to launch the controller",
+            )
+        })?;
+        let instance_profile_arn =
+            iam_client
+                .instance_profile_arn(profile)
+                .await?
+                .ok_or_else(|| {
+                    OrchError::new(
+                        ErrorKind::Iam,
+                        format!("Instance profile {profile} not found"),
+                    )
+                })?;
+        let ami_id = instance::get_latest_ami(ssm_client).await?;
+        let (subnets, vpc_id) = networking::get_subnet_vpc_ids(ec2_client, config).await?;
+        let (az, subnet_id) = config
+            .client_config
+            .first()
+            .map(|host| Az::from(host.az.clone()))
+            .and_then(|az| subnets.get_key_value(&az))
+            .or_else(|| subnets.iter().min())
+            .ok_or_else(|| OrchError::new(ErrorKind::Ec2, "No subnet for the controller"))?;
+
+        // the controller only makes outbound connections, which the new
+        // security group allows
+        let name = controller_name(unique_id);
+        let security_group_id = ec2_client
+            .create_security_group(&name, &vpc_id.as_string())
+            .await?;
+        audit::record!("ec2", "create_security_group", security_group_id = %security_group_id);
+
+        let launch = ec2_client
+            .run_instance(LaunchRequest {
+                name,
+                instance_type: config.controller.instance_type().to_string(),
+                image_id: ami_id,
+                instance_profile_arn,
+                key_name: state().ssh_key_name.clone(),
+                placement: Placement::builder()
+                    .availability_zone(az.as_string())
+                    .build(),
+                subnet_id: subnet_id.as_string(),
+                security_group_id: security_group_id.clone(),
+            })
+            .await
+            .and_then(|instance| {
+                instance
+                    .instance_id()
+                    .map(str::to_string)
+                    .ok_or_else(|| OrchError::new(ErrorKind::Ec2, "Launched controller has no id"))
+            });
+        let instance_id = match launch {
+            Ok(instance_id) => instance_id,
+            Err(err) => {
+                if let Err(delete_err) = delete_security_group(ec2_client, &security_group_id).await
+                {
+                    warn!("Failed to delete the controller's security group. {delete_err}");
+                }
+                return Err(err);
+            }
+        };
+        audit::record!(
+            "ec2",
+            "run_instances",
+            instance_id = %instance_id,
+            endpoint = "controller",
+            instance_type = config.controller.instance_type()
+        );
+
+        let controller = Controller {
+            instance_id,
+            security_group_id: Some(security_group_id),
+        };
+        if let Err(err) = controller.wait_online(ssm_client, REGISTER_TIMEOUT).await {
+            if let Err(cleanup_err) = controller.cleanup(ec2_client).await {
+                warn!("Failed to cleanup the controller. {cleanup_err}");
+            }
+            return Err(err);
+        }
+        Ok(controller)
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    // Terminates a launched controller and deletes its security group. A
+    // `--controller-instance` is left running.
+    pub async fn cleanup(&self, ec2_client: &impl Ec2Api) -> OrchResult<()> {
+        let Some(security_group_id) = &self.security_group_id else {
+            return Ok(());
+        };
+        info!("Start: deleting the controller");
+        ec2_client
+            .terminate_instances(vec![self.instance_id.clone()])
+            .await?;
+        audit::record!("ec2", "terminate_instances", instance_ids = ?[&self.instance_id]);
+        delete_security_group(ec2_client, security_group_id).await
+    }
+
+    // The commands are sent to the controller with SSM, so its agent must be
+    // online
+    async fn wait_online(&self, ssm_client: &impl SsmApi, timeout: Duration) -> OrchResult<()> {
+        let start = Instant::now();
+        let mut backoff = Backoff::new(MIN_POLL_DELAY, state().poll_delay_ssm);
+        loop {
+            let status = ssm_client
+                .describe_instance_information(&self.instance_id)
+                .await?
+                .and_then(|information| information.ping_status().cloned());
+            if status == Some(PingStatus::Online) {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                return Err(OrchError::new(
+                    ErrorKind::Ssm,
+                    format!(
+                        "The SSM agent of the controller {} isn't online: {:?}",
+                        self.instance_id, status
+                    ),
+                ));
+            }
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+}
+
+// The `Name` tag of the controller and the name of its security group
+fn controller_name(unique_id: &str) -> String {
+    format!("controller_{unique_id}")
+}
@@ -0,0 +1,270 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit,
+    aws_api::{Ec2Api, FleetOverride, FleetRequest, LaunchTemplateRequest},
+    ec2_utils::{instance_name, launch_plan::NetworkingInfraDetail, Az, EndpointType, LaunchPlan},
+    orchestrator::{state, ErrorKind, HostConfig, OrchError, OrchResult},
+};
+use aws_sdk_ec2::types::{Instance, PlacementGroup};
+use std::collections::{BTreeMap, HashMap};
+
+// The hosts of a group launched by a single fleet
+#[derive(Debug, PartialEq)]
+struct PlannedFleet {
+    // The indexes of the hosts in the group's host configs, which get the
+    // fleet's instances in order
+    hosts: Vec<usize>,
+    overrides: Vec<FleetOverride>,
+}
+
+// The launch template of the run's fleets, which is deleted along with the
+// rest of the infrastructure
+pub async fn create_launch_template(
+    ec2_client: &impl Ec2Api,
+    launch_plan: &LaunchPlan<'_>,
+    security_group_id: &str,
+    unique_id: &str,
+) -> OrchResult<String> {
+    let launch_template_id = ec2_client
+        .create_launch_template(LaunchTemplateRequest {
+            name: format!("netbench-{unique_id}"),
+            image_id: launch_plan.ami_id.clone(),
+            instance_profile_arn: launch_plan.instance_profile_arn.clone(),
+            key_name: state().ssh_key_name.clone(),
+            security_group_id: security_group_id.to_string(),
+        })
+        .await?;
+    audit::record!(
+        "ec2",
+        "create_launch_template",
+        launch_template_id = %launch_template_id
+    );
+    Ok(launch_template_id)
+}
+
+// Launches the hosts of the group with a fleet per AZ and placement, and
+// returns the instances in the order of the host configs.
+//
+// A fleet which launches fewer instances than requested fails the launch with
+// the fleet's errors. Its instances are returned in `launched` either way, so
+// that they're terminated.
+#[allow(clippy::too_many_arguments)]
+pub async fn launch_fleets(
+    ec2_client: &impl Ec2Api,
+    launch_plan: &LaunchPlan<'_>,
+    launch_template_id: &str,
+    unique_id: &str,
+    host_configs: &[HostConfig],
+    placement_map: &HashMap<Az, PlacementGroup>,
+    endpoint_type: EndpointType,
+    launched: &mut Vec<String>,
+) -> OrchResult<Vec<Instance>> {
+    let fleets = plan(
+        host_configs,
+        &launch_plan.networking_detail,
+        placement_map,
+        launch_plan.config.fleet.fallback_instance_types(),
+        launch_plan.config.fleet.any_az(),
+    )?;
+
+    let mut instances: Vec<Option<Instance>> = vec![None; host_configs.len()];
+    for fleet in fleets {
+        let launch = ec2_client
+            .create_fleet(FleetRequest {
+                name: instance_name(unique_id, endpoint_type),
+                launch_template_id: launch_template_id.to_string(),
+                count: fleet.hosts.len(),
+                overrides: fleet.overrides,
+            })
+            .await?;
+        for instance in &launch.instances {
+            audit::record!(
+                "ec2",
+                "create_fleet",
+                instance_id = instance.instance_id(),
+                endpoint = endpoint_type.as_str(),
+                instance_type = instance
+                    .instance_type()
+                    .map(|instance_type| instance_type.as_str())
+            );
+        }
+        launched.extend(
+            launch
+                .instances
+                .iter()
+                .filter_map(|instance| instance.instance_id())
+                .map(str::to_string),
+        );
+        if launch.instances.len() < fleet.hosts.len() {
+            return Err(OrchError::new(
+                ErrorKind::Ec2,
+                format!(
+                    "The fleet launched {} of {} {} hosts: {}",
+                    launch.instances.len(),
+                    fleet.hosts.len(),
+                    endpoint_type.as_str().to_lowercase(),
+                    launch.errors.join(", ")
+                ),
+            ));
+        }
+        for (index, instance) in fleet.hosts.into_iter().zip(launch.instances) {
+            instances[index] = Some(instance);
+        }
+    }
+    Ok(instances.into_iter().flatten().collect())
+}
+
+// Groups the hosts into fleets, with the overrides of each fleet in order of
+// preference: the hosts' instance type before the fallbacks, and for each
+// instance type the hosts' AZs before the others.
+//
+// The hosts stay in their AZ (and its cluster placement group), unless they
+// have no cluster placement and `any_az` is set.
+fn plan(
+    host_configs: &[HostConfig],
+    networking_detail: &NetworkingInfraDetail,
+    placement_map: &HashMap<Az, PlacementGroup>,
+    fallback_instance_types: &[String],
+    any_az: bool,
+) -> OrchResult<Vec<PlannedFleet>> {
+    // (AZ unless any AZ, cluster placement, instance type)
+    let mut groups: BTreeMap<(Option<&str>, bool, &str), Vec<usize>> = BTreeMap::new();
+    for (index, host_config) in host_configs.iter().enumerate() {
+        let cluster = host_config.is_cluster();
+        let az = (cluster || !any_az).then_some(host_config.az.as_str());
+        groups
+            .entry((az, cluster, host_config.instance_type().as_str()))
+            .or_default()
+            .push(index);
+    }
+
+    let mut other_azs: Vec<String> = networking_detail.keys().map(Az::as_string).collect();
+    other_azs.sort();
+    let mut fleets = Vec::with_capacity(groups.len());
+    for ((az, cluster, instance_type), hosts) in groups {
+        let mut azs: Vec<String> = match az {
+            Some(az) => vec![az.to_string()],
+            None => hosts
+                .iter()
+                .map(|index| host_configs[*index].az.clone())
+                .chain(other_azs.iter().cloned())
+                .collect(),
+        };
+        dedup_in_order(&mut azs);
+        let mut instance_types: Vec<String> = std::iter::once(instance_type.to_string())
+            .chain(fallback_instance_types.iter().cloned())
+            .collect();
+        dedup_in_order(&mut instance_types);
+
+        let mut overrides = Vec::with_capacity(instance_types.len() * azs.len());
+        for instance_type in &instance_types {
+            for az in &azs {
+                let key = Az::from(az.clone());
+                let subnet_id = networking_detail.get(&key).ok_or_else(|| {
+                    OrchError::new(ErrorKind::Ec2, format!("Subnet not found for Az: {az}"))
+                })?;
+                let placement_group = match cluster {
+                    true => Some(
+                        placement_map
+                            .get(&key)
+                            .and_then(|placement_group| placement_group.group_name())
+                            .ok_or_else(|| {
+                                OrchError::new(
+                                    ErrorKind::Ec2,
+                                    format!("Placement group not found for Az: {az}"),
+                                )
+                            })?
+                            .to_string(),
+                    ),
+                    false => None,
+                };
+                overrides.push(FleetOverride {
+                    instance_type: instance_type.clone(),
+                    az: az.clone(),
+                    subnet_id: subnet_id.as_string(),
+                    placement_group,
+                    priority: overrides.len() as f64,
+                });
+            }
+        }
+        fleets.push(PlannedFleet { hosts, overrides });
+    }
+    Ok(fleets)
+}
+
+fn dedup_in_order(values: &mut Vec<String>) {
+    let mut seen = std::collections::HashSet::new();
+    values.retain(|value| seen.insert(value.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{orchestrator::OrchestratorConfig, ssm_utils::DriverRegistry};
+
+    #[test]
+    fn fleets_keep_the_cluster_hosts_in_their_az() {
+        let driver_registry: DriverRegistry = serde_json::from_str(r#"{"drivers": []}"#).unwrap();
+        let config = OrchestratorConfig::testing(
+            "scenario.json".into(),
+            driver_registry,
+            &[],
+            &["us-west-2a", "us-west-2b", "us-west-2a"],
+        );
+        let networking_detail = NetworkingInfraDetail::from([
+            (
+                Az::from("us-west-2a".to_string()),
+                "subnet-a".to_string().into(),
+            ),
+            (
+                Az::from("us-west-2b".to_string()),
+                "subnet-b".to_string().into(),
+            ),
+        ]);
+        let placement_map = networking_detail
+            .keys()
+            .map(|az| {
+                (
+                    az.clone(),
+                    PlacementGroup::builder()
+                        .group_name(format!("cluster-{}", az.as_string()))
+                        .build(),
+                )
+            })
+            .collect();
+
+        let fleets = plan(
+            &config.client_config,
+            &networking_detail,
+            &placement_map,
+            &["c5n.4xlarge".to_string()],
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(fleets.len(), 2);
+        assert_eq!(fleets[0].hosts, vec![0, 2]);
+        assert_eq!(fleets[1].hosts, vec![1]);
+        assert_eq!(
+            fleets[0].overrides,
+            vec![
+                FleetOverride {
+                    instance_type: "c5.4xlarge".to_string(),
+                    az: "us-west-2a".to_string(),
+                    subnet_id: "subnet-a".to_string(),
+                    placement_group: Some("cluster-us-west-2a".to_string()),
+                    priority: 0.0,
+                },
+                FleetOverride {
+                    instance_type: "c5n.4xlarge".to_string(),
+                    az: "us-west-2a".to_string(),
+                    subnet_id: "subnet-a".to_string(),
+                    placement_group: Some("cluster-us-west-2a".to_string()),
+                    priority: 1.0,
+                },
+            ]
+        );
+    }
+}
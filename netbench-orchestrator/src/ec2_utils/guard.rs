@@ -0,0 +1,87 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::InfraDetail;
+use crate::{aws_api::Ec2Api, orchestrator::OrchResult};
+use std::ops::Deref;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::error;
+
+// A handle to the launched infrastructure which deletes it when dropped.
+//
+// The infrastructure should be deleted with `cleanup`, which reports whether
+// the cleanup succeeded. If the guard is dropped instead, eg. because a later
+// phase panicked, the cleanup runs in `Drop` so that the instances, security
+// group and placement groups aren't leaked.
+#[derive(Debug)]
+pub struct InfraGuard<E: Ec2Api> {
+    // `None` once cleaned up
+    infra: Option<InfraDetail>,
+    ec2_client: E,
+}
+
+impl<E: Ec2Api> InfraGuard<E> {
+    pub(super) fn new(infra: InfraDetail, ec2_client: &E) -> Self {
+        InfraGuard {
+            infra: Some(infra),
+            ec2_client: ec2_client.clone(),
+        }
+    }
+
+    pub(super) fn infra_mut(&mut self) -> &mut InfraDetail {
+        self.infra.as_mut().expect("infra is only taken on cleanup")
+    }
+
+    pub async fn cleanup(mut self) -> OrchResult<()> {
+        let infra = self.infra.take().expect("infra is only taken on cleanup");
+        let res = infra.cleanup(&self.ec2_client).await;
+        if let Err(err) = &res {
+            error!("Failed to cleanup all resources. {err} {:?}", infra);
+        }
+        res
+    }
+
+    // Takes the infrastructure without deleting it, eg. to leave the hosts
+    // running after the run
+    pub fn keep(mut self) -> InfraDetail {
+        self.infra.take().expect("infra is only taken on cleanup")
+    }
+}
+
+impl<E: Ec2Api> Deref for InfraGuard<E> {
+    type Target = InfraDetail;
+
+    fn deref(&self) -> &Self::Target {
+        self.infra.as_ref().expect("infra is only taken on cleanup")
+    }
+}
+
+impl<E: Ec2Api> Drop for InfraGuard<E> {
+    fn drop(&mut self) {
+        let Some(infra) = self.infra.take() else {
+            return;
+        };
+        error!(
+            panicking = std::thread::panicking(),
+            "The infrastructure was dropped before it was cleaned up. Cleaning up {:?}", infra
+        );
+
+        // `Drop` can't be async, so block on the cleanup. This is only
+        // possible on the multi-threaded runtime, which the orchestrator uses.
+        let handle = match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => handle,
+            _ => {
+                error!(
+                    "Unable to cleanup outside of the tokio runtime. Delete the resources manually. {:?}",
+                    infra
+                );
+                return;
+            }
+        };
+        let ec2_client = &self.ec2_client;
+        let res = tokio::task::block_in_place(|| handle.block_on(infra.cleanup(ec2_client)));
+        if let Err(err) = res {
+            error!("Failed to cleanup all resources. {err} {:?}", infra);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::SsmApi,
+    ec2_utils::{
+        types::{Az, EndpointType, HostIps, PrivIp, PubIp},
+        InstanceDetail,
+    },
+    orchestrator::{ErrorKind, OrchError, OrchResult},
+};
+use aws_sdk_ssm::types::PingStatus;
+use std::net::IpAddr;
+use tracing::info;
+
+// The AZ of the managed instances, which aren't in an AWS AZ
+const HYBRID_AZ: &str = "hybrid";
+
+// Hosts which aren't launched for the run, but registered with SSM as managed
+// instances with a hybrid activation, eg. lab hardware with the NICs under
+// test or hosts in another cloud.
+//
+// The run's commands are sent to them with SSM like to the EC2 hosts, so they
+// only need to be resolved to the address their agent reports. They're left
+// running once the run is done.
+pub async fn resolve_managed_instances(
+    ssm_client: &impl SsmApi,
+    endpoint_type: EndpointType,
+    instance_ids: &[String],
+) -> OrchResult<Vec<InstanceDetail>> {
+    let mut hosts = Vec::with_capacity(instance_ids.len());
+    for instance_id in instance_ids {
+        let information = ssm_client
+            .describe_instance_information(instance_id)
+            .await?
+            .ok_or_else(|| {
+                OrchError::new(
+                    ErrorKind::Init,
+                    format!("{instance_id} isn't registered with SSM. Register it with a hybrid activation"),
+                )
+            })?;
+        if information.ping_status() != Some(&PingStatus::Online) {
+            return Err(OrchError::new(
+                ErrorKind::Ssm,
+                format!(
+                    "The SSM agent of {instance_id} isn't online: {:?}",
+                    information.ping_status()
+                ),
+            ));
+        }
+        let ip: IpAddr = information
+            .ip_address()
+            .and_then(|ip| ip.parse().ok())
+            .ok_or_else(|| {
+                OrchError::new(
+                    ErrorKind::Ssm,
+                    format!("The SSM agent of {instance_id} didn't report an address"),
+                )
+            })?;
+        info!(host = %instance_id, %ip, "{:?} managed instance", endpoint_type);
+
+        // the agent only reports a single address
+        let host_ips = HostIps::new(PrivIp(ip), PubIp(ip));
+        hosts.push(InstanceDetail::managed(
+            endpoint_type,
+            Az::from(HYBRID_AZ.to_string()),
+            instance_id.clone(),
+            host_ips,
+        ));
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_api::mock::MockSsm;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn managed_instances_resolve_to_their_agent_address() {
+        let ssm = MockSsm::default();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+        ssm.register_managed_instance("mi-0", ip);
+
+        let ids = ["mi-0".to_string()];
+        let hosts = resolve_managed_instances(&ssm, EndpointType::Client, &ids)
+            .await
+            .unwrap();
+        assert_eq!(hosts[0].instance_id(), "mi-0");
+        assert_eq!(hosts[0].host_ips().private_ip().0, ip);
+        assert!(hosts[0].is_managed());
+
+        let ids = ["mi-1".to_string()];
+        assert!(resolve_managed_instances(&ssm, EndpointType::Client, &ids)
+            .await
+            .is_err());
+    }
+}
@@ -2,16 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    aws_api::{Ec2Api, IamApi, SsmApi},
     ec2_utils::{
-        instance, networking,
+        fleet, hybrid, instance, networking,
         types::{EndpointType, SubnetId, VpcId},
-        Az, InfraDetail, InstanceDetail,
+        Az, InfraDetail, InfraGuard, InstanceDetail,
     },
-    orchestrator::{OrchError, OrchResult, OrchestratorConfig},
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig},
 };
 use aws_sdk_ec2::types::Instance;
 use std::{collections::HashMap, time::Duration};
-use tracing::debug;
+use tracing::{debug, warn};
 
 const WAIT_INSTANCE_LAUNCH: Duration = Duration::from_secs(10);
 
@@ -29,26 +30,15 @@ pub struct LaunchPlan<'a> {
 
 impl<'a> LaunchPlan<'a> {
     pub async fn create(
-        ec2_client: &aws_sdk_ec2::Client,
-        iam_client: &aws_sdk_iam::Client,
-        ssm_client: &aws_sdk_ssm::Client,
+        ec2_client: &impl Ec2Api,
+        iam_client: &impl IamApi,
+        ssm_client: &impl SsmApi,
         config: &'a OrchestratorConfig,
     ) -> OrchResult<Self> {
-        let instance_profile_arn = instance::get_instance_profile(iam_client, config)
-            .await
-            .map_err(|err| OrchError::Ec2 {
-                dbg: format!("{}", err),
-            })?;
-        let ami_id = instance::get_latest_ami(ssm_client)
-            .await
-            .map_err(|err| OrchError::Ec2 {
-                dbg: format!("{}", err),
-            })?;
-        let (networking_detail, vpc_id) = networking::get_subnet_vpc_ids(ec2_client, config)
-            .await
-            .map_err(|err| OrchError::Ec2 {
-                dbg: format!("{}", err),
-            })?;
+        let instance_profile_arn = instance::get_instance_profile(iam_client, config).await?;
+        let ami_id = instance::get_latest_ami(ssm_client).await?;
+        let (networking_detail, vpc_id) =
+            networking::get_subnet_vpc_ids(ec2_client, config).await?;
         Ok(LaunchPlan {
             ami_id,
             networking_detail,
@@ -58,41 +48,37 @@ impl<'a> LaunchPlan<'a> {
         })
     }
 
-    pub async fn launch(
+    pub async fn launch<E: Ec2Api>(
         &self,
-        ec2_client: &aws_sdk_ec2::Client,
+        ec2_client: &E,
+        ssm_client: &impl SsmApi,
         unique_id: &str,
-    ) -> OrchResult<InfraDetail> {
+    ) -> OrchResult<InfraGuard<E>> {
         debug!("{:?}", self);
         let security_group_id =
-            networking::create_security_group(ec2_client, &self.vpc_id, unique_id)
-                .await
-                .map_err(|err| OrchError::Ec2 {
-                    dbg: format!("{}", err),
-                })?;
-
-        // Create placement per az.
-        //
-        // Only cluster placement supported at the moment
-        let mut placement_map = HashMap::new();
-        for az in self.networking_detail.keys() {
-            let placement = networking::create_placement_group(ec2_client, az, unique_id).await?;
-            placement_map.insert(az.clone(), placement.clone());
-        }
+            networking::create_security_group(ec2_client, &self.vpc_id, unique_id).await?;
 
-        let mut infra = InfraDetail {
+        let infra = InfraDetail {
             security_group_id,
             clients: Vec::new(),
             servers: Vec::new(),
-            placement_map,
+            placement_map: HashMap::new(),
+            launch_template_id: None,
+            unresolved_ids: Vec::new(),
         };
+        let mut infra = InfraGuard::new(infra, ec2_client);
 
-        self.launch_host_group(ec2_client, EndpointType::Server, &mut infra, unique_id)
-            .await?;
-        self.launch_host_group(ec2_client, EndpointType::Client, &mut infra, unique_id)
-            .await?;
-
-        networking::set_routing_permissions(ec2_client, &infra).await?;
+        // Delete whatever was launched so far if any step fails, since the
+        // caller only gets the guard of a fully launched `InfraDetail`.
+        if let Err(launch_err) = self
+            .launch_infra(ec2_client, ssm_client, infra.infra_mut(), unique_id)
+            .await
+        {
+            if let Err(delete_err) = infra.cleanup().await {
+                warn!("Failed to cleanup the partially launched infrastructure. {delete_err}");
+            }
+            return Err(launch_err);
+        }
 
         // wait for instance to spawn
         tokio::time::sleep(WAIT_INSTANCE_LAUNCH).await;
@@ -100,19 +86,83 @@ impl<'a> LaunchPlan<'a> {
         Ok(infra)
     }
 
+    async fn launch_infra(
+        &self,
+        ec2_client: &impl Ec2Api,
+        ssm_client: &impl SsmApi,
+        infra: &mut InfraDetail,
+        unique_id: &str,
+    ) -> OrchResult<()> {
+        // Create placement per az.
+        //
+        // Only cluster placement supported at the moment
+        for az in self.networking_detail.keys() {
+            let placement = networking::create_placement_group(ec2_client, az, unique_id).await?;
+            infra.placement_map.insert(az.clone(), placement);
+        }
+
+        if self.config.fleet.is_enabled() {
+            let launch_template_id = fleet::create_launch_template(
+                ec2_client,
+                self,
+                &infra.security_group_id,
+                unique_id,
+            )
+            .await?;
+            infra.launch_template_id = Some(launch_template_id);
+        }
+
+        for endpoint_type in [EndpointType::Server, EndpointType::Client] {
+            let managed_instances = self.config.managed_instances(endpoint_type);
+            if managed_instances.is_empty() {
+                self.launch_host_group(ec2_client, endpoint_type, infra, unique_id)
+                    .await?;
+                continue;
+            }
+            let hosts =
+                hybrid::resolve_managed_instances(ssm_client, endpoint_type, managed_instances)
+                    .await?;
+            match endpoint_type {
+                EndpointType::Server => infra.servers = hosts,
+                EndpointType::Client => infra.clients = hosts,
+            }
+        }
+
+        networking::set_routing_permissions(ec2_client, infra, &self.config.ports).await
+    }
+
     async fn launch_host_group(
         &self,
-        ec2_client: &aws_sdk_ec2::Client,
+        ec2_client: &impl Ec2Api,
         endpoint_type: EndpointType,
         infra: &mut InfraDetail,
         unique_id: &str,
     ) -> OrchResult<()> {
-        let (instance_detail, host_config) = match endpoint_type {
-            EndpointType::Server => (&mut infra.servers, &self.config.server_config),
-            EndpointType::Client => (&mut infra.clients, &self.config.client_config),
+        let host_config = match endpoint_type {
+            EndpointType::Server => &self.config.server_config,
+            EndpointType::Client => &self.config.client_config,
         };
 
-        let mut launch_requests = Vec::with_capacity(host_config.len());
+        if let Some(launch_template_id) = infra.launch_template_id.clone() {
+            // track the instances so that they're terminated if the launch
+            // fails before their ips are resolved
+            let instances = fleet::launch_fleets(
+                ec2_client,
+                self,
+                &launch_template_id,
+                unique_id,
+                host_config,
+                &infra.placement_map,
+                endpoint_type,
+                &mut infra.unresolved_ids,
+            )
+            .await?;
+            return self
+                .resolve_ips(instances, ec2_client, endpoint_type, infra)
+                .await;
+        }
+
+        let mut instances = Vec::with_capacity(host_config.len());
         for host_config in host_config {
             let instance = instance::launch_instances(
                 ec2_client,
@@ -127,37 +177,25 @@ impl<'a> LaunchPlan<'a> {
             .map_err(|err| {
                 debug!("{}", err);
                 err
-            });
-            launch_requests.push(instance);
-        }
-
-        let launch_request: OrchResult<Vec<_>> = launch_requests.into_iter().collect();
-        // FIXME make cleanup more resilient.
-        if let Err(launch_err) = launch_request {
-            let _ = infra.cleanup(ec2_client).await.map_err(|delete_err| {
-                // ignore error on cleanup.. since this is best effort
-                debug!("{}", delete_err);
-            });
-
-            return Err(launch_err);
+            })?;
+            // track the instance so that it's terminated if the launch fails
+            // before its ips are resolved
+            if let Some(instance_id) = instance.instance_id() {
+                infra.unresolved_ids.push(instance_id.to_string());
+            }
+            instances.push(instance);
         }
 
-        let instances = launch_request.map_err(|err| OrchError::Ec2 {
-            dbg: format!("{}", err),
-        })?;
-
-        self.resolve_ips(instances, ec2_client, endpoint_type, instance_detail)
-            .await?;
-
-        Ok(())
+        self.resolve_ips(instances, ec2_client, endpoint_type, infra)
+            .await
     }
 
     async fn resolve_ips(
         &self,
         instances: Vec<Instance>,
-        ec2_client: &aws_sdk_ec2::Client,
+        ec2_client: &impl Ec2Api,
         endpoint_type: EndpointType,
-        instance_detail: &mut Vec<InstanceDetail>,
+        infra: &mut InfraDetail,
     ) -> OrchResult<()> {
         for (launch_id, server) in instances.into_iter().enumerate() {
             let server_ip =
@@ -165,12 +203,14 @@ impl<'a> LaunchPlan<'a> {
             let az = server
                 .placement()
                 .and_then(|placement| placement.availability_zone())
-                .ok_or(OrchError::Ec2 {
-                    dbg: "Failed to find placement".to_string(),
-                })?;
+                .ok_or(OrchError::new(ErrorKind::Ec2, "Failed to find placement"))?;
             let server =
                 InstanceDetail::new(endpoint_type, Az::from(az.to_string()), server, server_ip)?;
-            instance_detail.push(server);
+            infra.unresolved_ids.retain(|id| id != server.instance_id());
+            match endpoint_type {
+                EndpointType::Server => infra.servers.push(server),
+                EndpointType::Client => infra.clients.push(server),
+            }
         }
         Ok(())
     }
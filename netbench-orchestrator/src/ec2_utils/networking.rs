@@ -2,23 +2,25 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    audit,
+    aws_api::Ec2Api,
     ec2_utils::{
         launch_plan::NetworkingInfraDetail,
         types::{Az, SubnetId, VpcId},
         InfraDetail, PlacementGroup,
     },
-    orchestrator::{OrchError, OrchResult, OrchestratorConfig, STATE},
-};
-use aws_sdk_ec2::types::{
-    Filter, IpPermission, IpRange, PlacementStrategy, ResourceType, TagSpecification,
-    UserIdGroupPair,
+    orchestrator::{state, ErrorKind, OrchError, OrchResult, OrchestratorConfig, PortPlan},
 };
+use aws_sdk_ec2::types::{IpPermission, IpRange, UserIdGroupPair};
 use std::collections::HashMap;
 use tracing::info;
 
+// Open the transports of the drivers between the hosts, along with ssh and
+// the russula and metrics ports of the `PortPlan`.
 pub async fn set_routing_permissions(
-    ec2_client: &aws_sdk_ec2::Client,
+    ec2_client: &impl Ec2Api,
     infra: &InfraDetail,
+    ports: &PortPlan,
 ) -> OrchResult<()> {
     let security_group_id = &infra.security_group_id;
 
@@ -28,22 +30,24 @@ pub async fn set_routing_permissions(
 
     // Egress
     ec2_client
-        .authorize_security_group_egress()
-        .group_id(security_group_id.clone())
-        .ip_permissions(
-            // Authorize security group (all traffic within the same security group)
-            IpPermission::builder()
-                .from_port(-1)
-                .to_port(-1)
-                .ip_protocol("-1")
-                .user_id_group_pairs(sg_group.clone())
-                .build(),
+        .authorize_egress(
+            security_group_id,
+            vec![
+                // Authorize security group (all traffic within the same security group)
+                IpPermission::builder()
+                    .from_port(-1)
+                    .to_port(-1)
+                    .ip_protocol("-1")
+                    .user_id_group_pairs(sg_group.clone())
+                    .build(),
+            ],
         )
-        .send()
-        .await
-        .map_err(|err| OrchError::Ec2 {
-            dbg: format!("Failed to set egress permissions: {err}"),
-        })?;
+        .await?;
+    audit::record!(
+        "ec2",
+        "authorize_security_group_egress",
+        security_group_id = %security_group_id
+    );
 
     let ssh_ip_range = IpRange::builder().cidr_ip("0.0.0.0/0").build();
     // TODO only specify the russula ports
@@ -53,7 +57,7 @@ pub async fn set_routing_permissions(
         .iter()
         .chain(infra.servers.iter())
         .map(|instance_detail| {
-            info!("{}", instance_detail);
+            info!(host = instance_detail.instance_id(), "{}", instance_detail);
 
             IpRange::builder()
                 .cidr_ip(format!("{}/32", instance_detail.host_ips().public_ip()))
@@ -62,112 +66,103 @@ pub async fn set_routing_permissions(
         .collect();
 
     // Ingress
-    ec2_client
-        .authorize_security_group_ingress()
-        .group_id(security_group_id.clone())
-        .ip_permissions(
-            // Authorize security group (all traffic within the same security group)
+    let mut ingress = vec![
+        // Authorize icmp within the security group, which is needed for
+        // path MTU discovery
+        IpPermission::builder()
+            .from_port(-1)
+            .to_port(-1)
+            .ip_protocol("icmp")
+            .user_id_group_pairs(sg_group.clone())
+            .build(),
+    ];
+    for (protocol, driver_ports) in ports.driver_ranges() {
+        // Authorize the driver transport from the other hosts
+        ingress.push(
             IpPermission::builder()
-                .from_port(-1)
-                .to_port(-1)
-                .ip_protocol("-1")
-                .user_id_group_pairs(sg_group)
-                .build(),
-        )
-        .ip_permissions(
-            // Authorize all host ips
-            IpPermission::builder()
-                .from_port(-1)
-                .to_port(-1)
-                .ip_protocol("-1")
+                .from_port((*driver_ports.start()).into())
+                .to_port((*driver_ports.end()).into())
+                .ip_protocol(protocol.as_str())
+                .user_id_group_pairs(sg_group.clone())
                 .set_ip_ranges(Some(public_host_ip_ranges.clone()))
                 .build(),
-        )
-        .ip_permissions(
-            // Authorize port 22 (ssh)
-            IpPermission::builder()
-                .from_port(22)
-                .to_port(22)
-                .ip_protocol("tcp")
-                .ip_ranges(ssh_ip_range)
-                .build(),
-        )
-        .ip_permissions(
-            // Authorize russula ports (Coordinator <-> Workers)
+        );
+    }
+    ingress.push(
+        // Authorize port 22 (ssh)
+        IpPermission::builder()
+            .from_port(22)
+            .to_port(22)
+            .ip_protocol("tcp")
+            .ip_ranges(ssh_ip_range)
+            .build(),
+    );
+    ingress.push(
+        // Authorize russula ports (Coordinator <-> Workers)
+        IpPermission::builder()
+            .from_port(ports.russula().into())
+            .to_port(ports.russula().into())
+            .ip_protocol("tcp")
+            .ip_ranges(russula_ip_range.clone())
+            .build(),
+    );
+    for port in ports.metrics() {
+        // Authorize the metrics ports, which are reached from the local host
+        // like the russula port
+        ingress.push(
             IpPermission::builder()
-                .from_port(STATE.russula_port.into())
-                .to_port(STATE.russula_port.into())
+                .from_port(port.into())
+                .to_port(port.into())
                 .ip_protocol("tcp")
-                .ip_ranges(russula_ip_range)
+                .ip_ranges(russula_ip_range.clone())
                 .build(),
-        )
-        .send()
-        .await
-        .map_err(|err| OrchError::Ec2 {
-            dbg: format!("Failed to set ingress permissions: {err}"),
-        })?;
+        );
+    }
+    ec2_client
+        .authorize_ingress(security_group_id, ingress)
+        .await?;
+    audit::record!(
+        "ec2",
+        "authorize_security_group_ingress",
+        security_group_id = %security_group_id
+    );
 
     Ok(())
 }
 
 // Create one per VPC. There is 1 VPC per region.
 pub async fn create_security_group(
-    ec2_client: &aws_sdk_ec2::Client,
+    ec2_client: &impl Ec2Api,
     vpc_id: &VpcId,
     unique_id: &str,
 ) -> OrchResult<String> {
-    let security_group_id = {
-        let req = ec2_client
-            .create_security_group()
-            .group_name(STATE.security_group_name(unique_id))
-            .description("This is a security group for a single run of netbench.")
-            .vpc_id(vpc_id.as_string())
-            .tag_specifications(
-                TagSpecification::builder()
-                    .resource_type(ResourceType::SecurityGroup)
-                    .tags(
-                        aws_sdk_ec2::types::Tag::builder()
-                            .key("Name")
-                            .value(STATE.security_group_name(unique_id))
-                            .build(),
-                    )
-                    .build(),
-            )
-            .send()
-            .await
-            .map_err(|err| OrchError::Ec2 {
-                dbg: err.to_string(),
-            })?;
-
-        req.group_id()
-            .ok_or(OrchError::Ec2 {
-                dbg: "Failed to create security group".to_owned(),
-            })?
-            .into()
-    };
+    let security_group_id = ec2_client
+        .create_security_group(&state().security_group_name(unique_id), &vpc_id.as_string())
+        .await?;
+    audit::record!("ec2", "create_security_group", security_group_id = %security_group_id);
     Ok(security_group_id)
 }
 
 pub async fn get_subnet_vpc_ids(
-    ec2_client: &aws_sdk_ec2::Client,
+    ec2_client: &impl Ec2Api,
     config: &OrchestratorConfig,
 ) -> OrchResult<(NetworkingInfraDetail, VpcId)> {
     let subnets = ec2_client
-        .describe_subnets()
-        .filters(
-            Filter::builder()
-                .name(config.cdk_config.netbench_runner_subnet_tag_key())
-                .values(config.cdk_config.netbench_runner_subnet_tag_value())
-                .build(),
+        .describe_subnets(
+            &config.cdk_config.netbench_runner_subnet_tag_key(),
+            config.cdk_config.netbench_runner_subnet_tag_value(),
         )
-        .send()
-        .await
-        .map_err(|e| OrchError::Ec2 {
-            dbg: format!("Couldn't describe subnets: {:#?}", e),
-        })?;
-
-    let subnets = subnets.subnets();
-    assert!(!subnets.is_empty(), "No subnets found");
+        .await?;
+    if subnets.is_empty() {
+        return Err(OrchError::new(
+            ErrorKind::Ec2,
+            format!(
+                "No subnets found with the tag {}={}",
+                config.cdk_config.netbench_runner_subnet_tag_key(),
+                config.cdk_config.netbench_runner_subnet_tag_value()
+            ),
+        ));
+    }
     tracing::debug!("{:?}", subnets);
 
     let mut az_subnet_map = HashMap::new();
@@ -177,53 +172,58 @@ pub async fn get_subnet_vpc_ids(
         let az = Az::from(
             subnet
                 .availability_zone()
-                .ok_or(OrchError::Ec2 {
-                    dbg: "Couldn't find AZ".into(),
-                })?
+                .ok_or(OrchError::new(ErrorKind::Ec2, "Couldn't find AZ"))?
                 .to_owned(),
         );
         let subnet_id = SubnetId::from(
             subnet
                 .subnet_id()
-                .ok_or(OrchError::Ec2 {
-                    dbg: "Couldn't find subnet".into(),
-                })?
+                .ok_or(OrchError::new(ErrorKind::Ec2, "Couldn't find subnet"))?
                 .to_owned(),
         );
         let subnet_vpc_id = VpcId::from(
             subnet
                 .vpc_id()
-                .ok_or(OrchError::Ec2 {
-                    dbg: "Couldn't find vpc".into(),
-                })?
+                .ok_or(OrchError::new(ErrorKind::Ec2, "Couldn't find vpc"))?
                 .to_owned(),
         );
         // all subnets should have the same VPC id
         if let Some(ref vpc_id) = vpc_id {
-            assert_eq!(vpc_id, &subnet_vpc_id);
+            if vpc_id != &subnet_vpc_id {
+                return Err(OrchError::new(
+                    ErrorKind::Ec2,
+                    format!(
+                        "The subnets are in different VPCs: {} and {}",
+                        vpc_id.as_string(),
+                        subnet_vpc_id.as_string()
+                    ),
+                ));
+            }
         }
         vpc_id = Some(subnet_vpc_id);
 
         az_subnet_map.insert(az, subnet_id);
     }
-    let vpc_id = vpc_id.expect("VPC id should be set at this point");
+    let vpc_id = vpc_id.ok_or(OrchError::new(ErrorKind::Ec2, "Couldn't find vpc"))?;
 
     // Validate that we have a subnet for each AZ
     for host_config in config.client_config.iter() {
         let az = Az::from(host_config.az.clone());
         if !az_subnet_map.contains_key(&az) {
-            return Err(OrchError::Ec2 {
-                dbg: "Subnet not found for Az: {az}".into(),
-            });
+            return Err(OrchError::new(
+                ErrorKind::Ec2,
+                format!("Subnet not found for Az: {}", az.as_string()),
+            ));
         }
     }
     // Validate that we have a subnet for each AZ
     for host_config in config.server_config.iter() {
         let az = Az::from(host_config.az.clone());
         if !az_subnet_map.contains_key(&az) {
-            return Err(OrchError::Ec2 {
-                dbg: "Subnet not found for Az: {az}".into(),
-            });
+            return Err(OrchError::new(
+                ErrorKind::Ec2,
+                format!("Subnet not found for Az: {}", az.as_string()),
+            ));
         }
     }
 
@@ -231,23 +231,17 @@ pub async fn get_subnet_vpc_ids(
 }
 
 pub async fn create_placement_group(
-    ec2_client: &aws_sdk_ec2::Client,
+    ec2_client: &impl Ec2Api,
     az: &Az,
     unique_id: &str,
 ) -> OrchResult<PlacementGroup> {
-    let placement = ec2_client
-        .create_placement_group()
-        .group_name(format!("cluster-{}-{}", unique_id, az))
-        .strategy(PlacementStrategy::Cluster)
-        .send()
-        .await
-        .map_err(|err| OrchError::Ec2 {
-            dbg: format!("{}", err),
-        })?;
-    placement
-        .placement_group()
-        .ok_or(OrchError::Ec2 {
-            dbg: "Failed to retrieve placement_group".to_string(),
-        })
-        .cloned()
+    let placement_group = ec2_client
+        .create_placement_group(&format!("cluster-{}-{}", unique_id, az))
+        .await?;
+    audit::record!(
+        "ec2",
+        "create_placement_group",
+        placement_group = placement_group.group_name()
+    );
+    Ok(placement_group)
 }
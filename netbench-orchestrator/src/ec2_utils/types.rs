@@ -1,7 +1,7 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::orchestrator::{OrchError, OrchResult};
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult};
 use aws_sdk_ec2::types::Instance;
 use std::net::IpAddr;
 use tracing::debug;
@@ -12,7 +12,12 @@ pub struct InstanceDetail {
     endpoint_type: EndpointType,
     az: Az,
     instance_id: String,
+    // The launched instance type, which a fleet can pick from the fallbacks
+    instance_type: Option<String>,
     host_ips: HostIps,
+    // Registered with SSM rather than launched for the run, so it's neither
+    // an EC2 instance nor terminated once the run is done. See `hybrid`.
+    managed: bool,
 }
 
 impl std::fmt::Display for &InstanceDetail {
@@ -35,9 +40,7 @@ impl InstanceDetail {
     ) -> OrchResult<Self> {
         let instance_id = instance
             .instance_id()
-            .ok_or(OrchError::Ec2 {
-                dbg: "No instance id".to_string(),
-            })
+            .ok_or(OrchError::new(ErrorKind::Ec2, "No instance id"))
             .map_err(|err| {
                 debug!("{}", err);
                 err
@@ -48,14 +51,44 @@ impl InstanceDetail {
             endpoint_type,
             az,
             instance_id,
+            instance_type: instance
+                .instance_type()
+                .map(|instance_type| instance_type.as_str().to_string()),
             host_ips,
+            managed: false,
         })
     }
 
+    // A managed instance, eg. lab hardware registered with a hybrid
+    // activation
+    pub fn managed(
+        endpoint_type: EndpointType,
+        az: Az,
+        instance_id: String,
+        host_ips: HostIps,
+    ) -> Self {
+        InstanceDetail {
+            endpoint_type,
+            az,
+            instance_id,
+            instance_type: None,
+            host_ips,
+            managed: true,
+        }
+    }
+
     pub fn instance_id(&self) -> &str {
         &self.instance_id
     }
 
+    pub fn instance_type(&self) -> Option<&str> {
+        self.instance_type.as_deref()
+    }
+
+    pub fn az(&self) -> &Az {
+        &self.az
+    }
+
     pub fn host_ips(&self) -> &HostIps {
         &self.host_ips
     }
@@ -63,9 +96,13 @@ impl InstanceDetail {
     pub fn endpoint_type(&self) -> &EndpointType {
         &self.endpoint_type
     }
+
+    pub fn is_managed(&self) -> bool {
+        self.managed
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, clap::ValueEnum)]
 pub enum EndpointType {
     Server,
     Client,
@@ -1,14 +1,21 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::orchestrator::{OrchError, OrchResult, STATE};
-use aws_config::BehaviorVersion;
-use aws_types::region::Region;
+use crate::orchestrator::{state, ErrorKind, LogFormat, OrchError, OrchResult, RunPaths, LOG_DIR};
 use clap::Parser;
-use tracing_subscriber::EnvFilter;
+use std::process::ExitCode;
+use tracing::Instrument;
+use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, EnvFilter};
 
+mod audit;
+mod aws_api;
+mod cloudwatch_utils;
+mod compute;
 mod ec2_utils;
 mod orchestrator;
+#[cfg(feature = "otel")]
+mod otel;
+mod progress;
 mod russula;
 mod s3_utils;
 mod ssm_utils;
@@ -26,31 +33,144 @@ pub enum RunMode {
     Full,
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> OrchResult<()> {
-    let unique_id = format!(
-        "{}-{}",
-        humantime::format_rfc3339_seconds(std::time::SystemTime::now()),
-        STATE.version
-    );
+// Exits with a code per class of failure. See `OrchError::exit_code`.
+//
+// The multi-threaded runtime lets the waits on independent hosts and uploads
+// (eg. polling the SSM commands of each host) run in parallel.
+#[tokio::main]
+async fn main() -> ExitCode {
+    match orchestrate().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
+
+async fn orchestrate() -> OrchResult<()> {
+    let cli = orchestrator::Cli::parse();
+    // the settings are used from here on, eg. for the local workspace
+    orchestrator::init_state(cli.settings_file())?;
+
+    if let Some(command) = cli.command() {
+        // the bootstrap writes the cdk config, so there's none to load yet
+        let cdk_config = match command {
+            orchestrator::Command::Bootstrap(_) => orchestrator::CdkConfig::default(),
+            _ => cli.cdk_config()?,
+        };
+        let aws_config = cli.aws_access().load(&cdk_config).await?;
+        return orchestrator::run_command(command, cli.cdk_config_file(), &cdk_config, &aws_config)
+            .await;
+    }
+
+    let unique_id = cli.run_id()?;
 
     let file_appender =
-        tracing_appender::rolling::daily("./target", format!("russula_{}", unique_id));
+        tracing_appender::rolling::daily(LOG_DIR, RunPaths::new(&unique_id).log_file_prefix());
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_writer(non_blocking)
+    // The logs are also shipped to CloudWatch as json, once the log group is
+    // known.
+    let (log_writer, log_rx) = cloudwatch_utils::log_channel();
+
+    // The spans are exported regardless of RUST_LOG, which only applies to
+    // the logs.
+    #[cfg(feature = "otel")]
+    let otel_layer = cli.otlp_endpoint().map(otel::layer).transpose()?;
+    #[cfg(not(feature = "otel"))]
+    let otel_layer: Option<tracing_subscriber::layer::Identity> = match cli.otlp_endpoint() {
+        Some(_) => {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "--otlp-endpoint requires building with the `otel` feature",
+            ))
+        }
+        None => None,
+    };
+
+    // `-v`, `-q` and `--log-level` take precedence over RUST_LOG
+    let log_filter = cli.log_filter();
+    if let Some(log_filter) = &log_filter {
+        EnvFilter::try_new(log_filter).map_err(|err| {
+            OrchError::new(ErrorKind::Init, format!("Invalid --log-level {log_filter}"))
+                .with_source(err)
+        })?;
+    }
+    let env_filter = || match &log_filter {
+        Some(log_filter) => EnvFilter::new(log_filter),
+        None => EnvFilter::from_default_env(),
+    };
+    if cli.quiet() {
+        progress::set_quiet();
+    }
+
+    // Only one of the file layers is enabled, depending on the log format.
+    let (text_layer, json_layer) = match cli.log_format() {
+        LogFormat::Text => (Some(fmt::layer().with_writer(non_blocking)), None),
+        LogFormat::Json => (None, Some(fmt::layer().json().with_writer(non_blocking))),
+    };
+
+    tracing_subscriber::registry()
+        .with(otel_layer.with_filter(LevelFilter::INFO))
+        .with(audit::layer(&unique_id)?)
+        .with(text_layer.with_filter(env_filter()))
+        .with(json_layer.with_filter(env_filter()))
+        .with(
+            fmt::layer()
+                .json()
+                .with_writer(log_writer)
+                .with_filter(env_filter()),
+        )
         .init();
+    tracing::debug!("{:?}", state());
 
-    let cli = orchestrator::Cli::parse().process_config_files()?;
-    let region = Region::new(cli.region());
-    let aws_config = aws_config::defaults(BehaviorVersion::latest())
-        .region(region)
-        .load()
-        .await;
+    let tui = cli.tui();
+    let status_port = cli.status_port();
+    let aws_access = cli.aws_access().clone();
+    let cli = cli.process_config_files()?;
+    let aws_config = aws_access.load(cli.cdk_config()).await?;
 
     // perform sanity and check before proceeding
     let config = cli.check_requirements(&aws_config).await?;
 
-    orchestrator::run(unique_id, &config, &aws_config, RunMode::Full).await
+    let log_group = config.cdk_config.netbench_runner_log_group();
+    let log_stream = cloudwatch_utils::orchestrator_log_stream(&unique_id);
+    let log_shipper =
+        cloudwatch_utils::LogShipper::spawn(&aws_config, log_group, &log_stream, log_rx);
+    let logs_url = cloudwatch_utils::log_stream_url(
+        config.cdk_config.netbench_primary_region(),
+        log_group,
+        &log_stream,
+    );
+    progress::output!("Logs: URL: {logs_url}");
+
+    let span = tracing::info_span!("run", unique_id = %unique_id);
+    let result = if config.controller.is_enabled() {
+        orchestrator::run_on_controller(&unique_id, &config, &aws_config)
+            .instrument(span)
+            .await
+    } else {
+        orchestrator::run(
+            &unique_id,
+            &config,
+            &aws_config,
+            RunMode::Full,
+            tui,
+            status_port,
+        )
+        .instrument(span)
+        .await
+    };
+    // best effort, since the run itself is complete
+    let s3_client = s3_utils::s3_client(&aws_config, &config);
+    if let Err(err) = audit::upload(&s3_client, &unique_id, &config).await {
+        tracing::warn!("Failed to upload the audit log. {err}");
+    }
+    log_shipper.finish().await;
+    #[cfg(feature = "otel")]
+    otel::shutdown();
+    if result.is_err() {
+        eprintln!("Orchestrator logs: {logs_url}");
+    }
+    result
 }
@@ -1,82 +1,308 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+mod attach;
+mod bootstrap;
 mod cli;
+mod clock;
+mod controller;
+mod coordination;
 mod dashboard;
+mod diagnostics;
+#[cfg(test)]
+mod e2e_tests;
 mod error;
+mod events;
+mod hooks;
+mod metadata;
+mod metrics;
+mod notify;
+mod partition;
+mod paths;
+mod permissions;
+mod phases;
+mod port_plan;
+mod prune;
+mod quota;
 mod report;
+mod run_id;
+mod runs;
+mod server_map;
+mod sizing;
+mod ssh;
 mod state;
+mod status_server;
+mod throttle;
+mod tui;
+mod versions;
 
 use crate::{
-    ec2_utils, ec2_utils::InfraDetail, s3_utils, ssm_utils, ssm_utils::NetbenchDriverType, RunMode,
+    aws_api::{AwsCli, AwsClients, Ec2Api, IamApi, S3Api, SdkClients, SsmApi},
+    compute::{ComputeProvider, Ec2Provider},
+    ec2_utils::{EndpointType, InfraDetail},
+    progress,
+    russula::EventRecorder,
+    s3_utils, ssm_utils,
+    ssm_utils::NetbenchDriverType,
+    RunMode,
 };
 use aws_sdk_s3::primitives::ByteStream;
-use tracing::info;
-
-pub use cli::{Cli, HostConfig, OrchestratorConfig};
-pub use error::{OrchError, OrchResult};
-pub use state::STATE;
+use dashboard::{Dashboard, RunPhase};
+use events::{EventTimeline, Phase};
+use status_server::StatusServer;
+use std::{path::Path, time::SystemTime};
+use tracing::{info, info_span, instrument, warn, Instrument};
+use tui::{Abort, AbortSignal, Tui};
+
+pub use cli::{AwsEndpoints, CdkConfig, Cli, Command, HostConfig, LogFormat, OrchestratorConfig};
+pub use controller::run_on_controller;
+pub use error::{ErrorKind, OrchError, OrchResult};
+pub use partition::Partition;
+pub use paths::{RunPaths, LOG_DIR};
+pub use port_plan::PortPlan;
+pub use run_id::RunId;
+pub use server_map::ServerMap;
+pub use state::{init_state, state};
 
 pub async fn run(
-    unique_id: String,
+    run_id: &RunId,
     config: &OrchestratorConfig,
     aws_config: &aws_types::SdkConfig,
     run_mode: RunMode,
+    tui: bool,
+    status_port: Option<u16>,
 ) -> OrchResult<()> {
-    let iam_client = aws_sdk_iam::Client::new(aws_config);
-    let s3_client = aws_sdk_s3::Client::new(aws_config);
-    let ec2_client = aws_sdk_ec2::Client::new(aws_config);
-    let ssm_client = aws_sdk_ssm::Client::new(aws_config);
-
-    upload_run_parameters_to_s3(&s3_client, config, &unique_id).await?;
-
-    // Setup instances
-    let infra = ec2_utils::LaunchPlan::create(&ec2_client, &iam_client, &ssm_client, config)
-        .await?
-        .launch(&ec2_client, &unique_id)
-        .await?;
+    let clients = SdkClients::new(aws_config, config);
+    run_with_clients(run_id, config, &clients, run_mode, tui, status_port).await
+}
 
-    update_dashboard_with_instances(&s3_client, config, &infra, &unique_id).await?;
+// Runs a maintenance subcommand instead of a scenario
+pub async fn run_command(
+    command: &Command,
+    cdk_config_file: &Path,
+    cdk_config: &CdkConfig,
+    aws_config: &aws_types::SdkConfig,
+) -> OrchResult<()> {
+    match command {
+        Command::Bootstrap(args) => bootstrap::bootstrap(aws_config, cdk_config_file, args).await,
+        Command::Prune(args) => {
+            let s3_client = aws_sdk_s3::Client::new(aws_config);
+            let ec2_client = aws_sdk_ec2::Client::new(aws_config);
+            let aws_cli = AwsCli::new(aws_config);
+            prune::prune(
+                &s3_client,
+                &ec2_client,
+                cdk_config,
+                &aws_cli,
+                args,
+                SystemTime::now(),
+            )
+            .await
+        }
+        Command::Attach(args) => {
+            let ec2_client = aws_sdk_ec2::Client::new(aws_config);
+            let ssm_client = aws_sdk_ssm::Client::new(aws_config);
+            let s3_client = aws_sdk_s3::Client::new(aws_config);
+            attach::attach(&ec2_client, &ssm_client, &s3_client, cdk_config, args).await
+        }
+    }
+}
 
-    run_netbench(
-        run_mode,
+// Runs the scenario with the given AWS clients, eg. the mocks in tests. The
+// hosts are EC2 instances.
+pub async fn run_with_clients(
+    run_id: &RunId,
+    config: &OrchestratorConfig,
+    clients: &AwsClients<impl Ec2Api, impl SsmApi, impl S3Api, impl IamApi>,
+    run_mode: RunMode,
+    tui: bool,
+    status_port: Option<u16>,
+) -> OrchResult<()> {
+    let compute = Ec2Provider::new(
+        clients.ec2.clone(),
+        clients.ssm.clone(),
+        clients.iam.clone(),
+    );
+    run_with_compute(
+        run_id,
         config,
-        &infra,
-        &ssm_client,
-        &s3_client,
-        &unique_id,
+        &compute,
+        &clients.s3,
+        clients.sdk_config.as_ref(),
+        run_mode,
+        tui,
+        status_port,
     )
-    .await?;
+    .await
+}
 
-    // Cleanup
-    infra
-        .cleanup(&ec2_client)
-        .await
-        .map_err(|err| eprintln!("Failed to cleanup all resources. {err} {:?}", infra))
-        .unwrap();
+// Runs the scenario on the hosts of the compute backend. The results are
+// stored in S3 regardless of the backend.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_with_compute(
+    run_id: &RunId,
+    config: &OrchestratorConfig,
+    compute: &impl ComputeProvider,
+    s3_client: &impl S3Api,
+    sdk_config: Option<&aws_types::SdkConfig>,
+    run_mode: RunMode,
+    tui: bool,
+    status_port: Option<u16>,
+) -> OrchResult<()> {
+    let unique_id = run_id.as_str();
+
+    let mut timeline = EventTimeline::default();
+
+    let start = SystemTime::now();
+    upload_run_parameters_to_s3(s3_client, config, unique_id).await?;
+    let mut dashboard = Dashboard::new(s3_client, unique_id, config);
+    let (tui, mut abort) = if tui {
+        let (tui, abort) = Tui::spawn(dashboard.subscribe())?;
+        (Some(tui), abort)
+    } else {
+        (None, AbortSignal::none())
+    };
+    let _status_server = match status_port {
+        Some(port) => Some(StatusServer::spawn(port, dashboard.subscribe()).await?),
+        None => None,
+    };
+    dashboard.set_phase(RunPhase::Launching).await?;
+    timeline.record("upload run parameters", Phase::Setup, start);
 
-    Ok(())
+    // Setup instances
+    let start = SystemTime::now();
+    let infra = compute
+        .provision(unique_id, config)
+        .instrument(info_span!(
+            "launch_infrastructure",
+            phase = RunPhase::Launching.as_str()
+        ))
+        .await;
+    let infra = match infra {
+        Ok(infra) => infra,
+        Err(err) => {
+            let err = err.with_run(unique_id, RunPhase::Launching);
+            diagnostics::collect(unique_id, &err, None, compute).await;
+            notify::github_error_annotation(config, &err);
+            return Err(err);
+        }
+    };
+    let duration = timeline.record("launch infrastructure", Phase::Setup, start);
+    timeline.metrics.infra_launch(duration);
+    timeline.phases.add("launch", duration);
+    ssh::write_access(unique_id, &infra);
+
+    // From here on the hosts are released even if the run fails. If the run
+    // panics, they're released when they're dropped.
+    let result = async {
+        dashboard.set_hosts(&infra).await?;
+        run_netbench(
+            run_mode,
+            config,
+            &infra,
+            compute,
+            s3_client,
+            run_id,
+            &mut timeline,
+            &mut dashboard,
+            &mut abort,
+        )
+        .await
+    }
+    .await
+    .map_err(|err| err.with_run(unique_id, dashboard.phase()));
+    if tui.is_some() {
+        // restore the terminal
+        drop(tui);
+        progress::output!("Status: URL: {}", dashboard.url());
+    }
+    if let Err(err) = &result {
+        diagnostics::collect(unique_id, err, Some(&*infra), compute).await;
+        notify::github_error_annotation(config, err);
+        if let Err(err) = dashboard.set_failed(err.to_string()).await {
+            warn!("Failed to mark the run as failed on the dashboard. {err}");
+        }
+    }
+    // best effort, since the run itself is complete
+    timeline.metrics.run_result(result.is_err());
+    let run_entry = dashboard.run_entry(config);
+    // the metrics and summary are skipped without an AWS config, eg. in tests
+    let (metrics, runs_index, summary) = tokio::join!(
+        async {
+            match sdk_config {
+                Some(aws_config) => timeline.metrics.publish(aws_config).await,
+                None => Ok(()),
+            }
+        },
+        async {
+            // a private run isn't listed in the public runs index
+            match config.report.is_private() {
+                true => Ok(()),
+                false => {
+                    runs::update_runs_index(s3_client, config, dashboard.run_entry(config)).await
+                }
+            }
+        },
+        async {
+            match sdk_config {
+                Some(aws_config) => {
+                    notify::send_summary(aws_config, s3_client, config, &run_entry).await
+                }
+                None => Ok(()),
+            }
+        },
+    );
+    if let Err(err) = metrics {
+        warn!("Failed to publish the run metrics. {err}");
+    }
+    if let Err(err) = runs_index {
+        warn!("Failed to update the runs index. {err}");
+    }
+    if let Err(err) = summary {
+        warn!("Failed to send the run summary. {err}");
+    }
+    progress::output!("{}", timeline.phases.table());
+    timeline
+        .phases
+        .upload(s3_client, unique_id, config, &run_entry.status)
+        .await;
+
+    // Cleanup, unless the hosts are kept for interactive clients. The hosts
+    // of a failed run are released either way.
+    let cleanup = match config.run.keep_servers() {
+        Some(ttl) if result.is_ok() => compute.keep(infra, ttl, config).await,
+        _ => compute.release(infra).await,
+    };
+    // the run's error takes precedence over the cleanup's
+    result?;
+    cleanup
 }
 
 async fn upload_run_parameters_to_s3(
-    s3_client: &aws_sdk_s3::Client,
+    s3_client: &impl S3Api,
     config: &OrchestratorConfig,
     unique_id: &str,
 ) -> OrchResult<()> {
     let scenario_file = ByteStream::from_path(config.netbench_scenario_filepath())
         .await
-        .map_err(|err| OrchError::Init {
-            dbg: err.to_string(),
+        .map_err(|err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!(
+                    "Failed to read the scenario {:?}",
+                    config.netbench_scenario_filepath()
+                ),
+            )
+            .with_source(err)
         })?;
 
     s3_utils::upload_object(
         s3_client,
-        config.cdk_config.netbench_runner_public_s3_bucket(),
+        config.results_bucket(),
         scenario_file,
-        &format!("{unique_id}/{}", config.netbench_scenario_filename()),
+        &RunPaths::new(unique_id).key(config.netbench_scenario_filename()),
     )
-    .await
-    .unwrap();
+    .await?;
 
     // upload the index.html dashboard file
     dashboard::upload_index_html(s3_client, unique_id, config).await?;
@@ -84,205 +310,342 @@ async fn upload_run_parameters_to_s3(
     Ok(())
 }
 
-async fn update_dashboard_with_instances(
-    s3_client: &aws_sdk_s3::Client,
-    config: &OrchestratorConfig,
-    infra: &InfraDetail,
-    unique_id: &str,
-) -> OrchResult<()> {
-    dashboard::update_instance_running(
-        s3_client,
-        infra,
-        unique_id,
-        config,
-        ec2_utils::EndpointType::Server,
-    )
-    .await?;
-
-    dashboard::update_instance_running(
-        s3_client,
-        infra,
-        unique_id,
-        config,
-        ec2_utils::EndpointType::Client,
-    )
-    .await?;
-
-    Ok(())
-}
-
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all)]
 async fn run_netbench(
     run_mode: RunMode,
     config: &OrchestratorConfig,
     infra: &InfraDetail,
-    ssm_client: &aws_sdk_ssm::Client,
-    s3_client: &aws_sdk_s3::Client,
-    unique_id: &str,
+    compute: &impl ComputeProvider,
+    s3_client: &impl S3Api,
+    run_id: &RunId,
+    timeline: &mut EventTimeline,
+    dashboard: &mut Dashboard<impl S3Api>,
+    abort: &mut AbortSignal,
 ) -> OrchResult<()> {
+    let unique_id = run_id.as_str();
     if matches!(run_mode, RunMode::Full) {
-        // TODO: investigate native_tls_driver failure
-        //
-        // `native_tls_driver` can get stuck and results in the orchestrator
-        // never finishing.
-        // https://github.com/aws/s2n-netbench/issues/37
-
-        let server_drivers = vec![
-            ssm_utils::s2n_quic_dc_driver::dc_quic_server_driver(unique_id, config),
-            ssm_utils::tcp_driver_crates::tcp_server_driver(),
-            ssm_utils::s2n_quic_driver_crates::s2n_quic_server_driver(),
-            ssm_utils::s2n_tls_driver::s2n_tls_server_driver(),
-        ];
-        let client_drivers = vec![
-            ssm_utils::s2n_quic_dc_driver::dc_quic_client_driver(unique_id, config),
-            ssm_utils::tcp_driver_crates::tcp_client_driver(),
-            ssm_utils::s2n_quic_driver_crates::s2n_quic_client_driver(),
-            ssm_utils::s2n_tls_driver::s2n_tls_client_driver(),
-        ];
-
-        assert_eq!(server_drivers.len(), client_drivers.len());
-
-        configure_remote_hosts(
+        let (server_drivers, client_drivers) =
+            config.driver_registry.drivers(unique_id, config).await?;
+        if server_drivers.len() != client_drivers.len() {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                format!(
+                    "Each server driver needs a client driver, got {} server and {} client drivers",
+                    server_drivers.len(),
+                    client_drivers.len()
+                ),
+            ));
+        }
+
+        metadata::upload_run_metadata(
+            s3_client,
+            run_id,
             config,
             infra,
-            ssm_client,
-            unique_id,
-            &server_drivers,
-            &client_drivers,
+            &server_drivers
+                .iter()
+                .chain(client_drivers.iter())
+                .collect::<Vec<_>>(),
         )
         .await?;
 
-        let driver_pairs = client_drivers.into_iter().zip(server_drivers);
-        for (client_driver, server_driver) in driver_pairs {
-            let msg = format!(
-                "Running server: {} and client: {}",
-                server_driver.driver_name(),
-                client_driver.driver_name()
-            );
-            info!(msg);
-
-            // run russula
-            {
-                let mut server_russula = ssm_utils::ServerNetbenchRussula::new(
-                    ssm_client,
-                    infra,
-                    config,
-                    &server_driver,
-                )
-                .await?;
+        let iterations: Vec<_> = (1..=config.run.iterations())
+            .map(|iteration| config.run.iteration(iteration))
+            .collect();
+        dashboard
+            .set_pairs(iterations.iter().flat_map(|iteration| {
+                server_drivers
+                    .iter()
+                    .zip(client_drivers.iter())
+                    .map(move |(server, client)| (server, client, *iteration))
+            }))
+            .await?;
 
-                let mut client_russula = ssm_utils::ClientNetbenchRussula::new(
-                    ssm_client,
-                    infra,
+        dashboard.set_phase(RunPhase::Configuring).await?;
+        let start = SystemTime::now();
+        let builds = compute
+            .configure(infra, unique_id, &server_drivers, &client_drivers, config)
+            .instrument(info_span!(
+                "configure_remote_hosts",
+                phase = RunPhase::Configuring.as_str()
+            ))
+            .await?;
+        let duration = timeline.record("configure hosts", Phase::Setup, start);
+        timeline.metrics.configure(duration);
+        timeline.phases.add("configure", duration);
+        for (driver, duration) in builds {
+            timeline
+                .phases
+                .add(format!("configure: build {driver}"), duration);
+        }
+
+        // best effort, since a skew only affects the alignment of the report
+        let start = SystemTime::now();
+        if let Err(err) =
+            clock::check_clock_skew(compute, s3_client, infra, unique_id, config).await
+        {
+            warn!("Failed to check the clocks of the hosts. {err}");
+        }
+        timeline.record("check host clocks", Phase::Setup, start);
+
+        dashboard.set_phase(RunPhase::Running).await?;
+        // with `--keep-servers` the servers of the last pair are left running
+        let last_pair = iterations.len() * client_drivers.len() - 1;
+        let mut pair = 0;
+        for iteration in iterations {
+            let driver_pairs = client_drivers.iter().zip(server_drivers.iter());
+            for (client_driver, server_driver) in driver_pairs {
+                // an abort of a driver pair only applies while it's running
+                if abort.try_requested() == Some(Abort::Run) {
+                    return Err(aborted());
+                }
+                run_driver_pair(
                     config,
-                    &client_driver,
+                    infra,
+                    compute,
+                    unique_id,
+                    server_driver,
+                    client_driver,
+                    iteration,
+                    timeline,
+                    dashboard,
+                    pair,
+                    config.run.keep_servers().is_some() && pair == last_pair,
+                    abort,
                 )
                 .await?;
-
-                // run client/server
-                server_russula.wait_netbench_running(ssm_client).await?;
-                client_russula.wait_done(ssm_client).await?;
-                server_russula.wait_done(ssm_client).await?;
+                pair += 1;
             }
-
-            copy_netbench_results_to_s3(
-                config,
-                infra,
-                ssm_client,
-                unique_id,
-                &server_driver,
-                &client_driver,
-            )
-            .await?;
         }
 
-        report::generate_report(s3_client, unique_id, infra, config).await?;
+        let start = SystemTime::now();
+        timeline.upload(s3_client, unique_id, config).await?;
+        // best effort, since the workers' events are only used by the report
+        if let Err(err) = timeline
+            .coordination
+            .upload(s3_client, unique_id, config)
+            .await
+        {
+            warn!("Failed to upload the coordination events. {err}");
+        }
+        let duration = start.elapsed().unwrap_or_default();
+        timeline.phases.add("upload results", duration);
+
+        if config.skip.report() {
+            info!("Skipping the report");
+        } else {
+            dashboard.set_phase(RunPhase::Reporting).await?;
+            let start = SystemTime::now();
+            report::generate_report(s3_client, unique_id, infra, config).await?;
+            let duration = start.elapsed().unwrap_or_default();
+            timeline.phases.add("report", duration);
+        }
+        dashboard.set_phase(RunPhase::Finished).await?;
     }
 
     Ok(())
 }
 
-async fn configure_remote_hosts(
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(phase = RunPhase::Running.as_str(), server = %server_driver.results_name(), client = %client_driver.results_name(), iteration = ?iteration))]
+async fn run_driver_pair(
     config: &OrchestratorConfig,
     infra: &InfraDetail,
-    ssm_client: &aws_sdk_ssm::Client,
+    compute: &impl ComputeProvider,
     unique_id: &str,
-    server_drivers: &Vec<NetbenchDriverType>,
-    client_drivers: &Vec<NetbenchDriverType>,
+    server_driver: &NetbenchDriverType,
+    client_driver: &NetbenchDriverType,
+    iteration: Option<u32>,
+    timeline: &mut EventTimeline,
+    dashboard: &mut Dashboard<impl S3Api>,
+    pair_index: usize,
+    keep_servers: bool,
+    abort: &mut AbortSignal,
 ) -> OrchResult<()> {
-    let client_ids = infra.client_ids();
-    let server_ids = infra.server_ids();
+    let msg = format!(
+        "Running server: {} and client: {}",
+        server_driver.driver_name(),
+        client_driver.driver_name()
+    );
+    info!(msg);
 
-    let mut build_cmds = ssm_utils::common::collect_config_cmds(
-        "server",
-        ssm_client,
-        server_ids.clone(),
-        config,
-        server_drivers,
-        unique_id,
-        config,
-    )
-    .await;
-    let client_build_cmds = ssm_utils::common::collect_config_cmds(
-        "client",
-        ssm_client,
-        client_ids.clone(),
+    let mut pair = format!(
+        "{} / {}",
+        server_driver.results_name(),
+        client_driver.results_name()
+    );
+    if let Some(iteration) = iteration {
+        pair.push_str(&format!(" (iteration {iteration})"));
+    }
+
+    // run russula
+    dashboard.start_pair(pair_index).await?;
+    hooks::pre_run(compute, infra, server_driver, client_driver, config).await?;
+    // the limits only apply to the drivers, so that copying the results isn't
+    // limited
+    throttle::apply(compute, infra, config).await?;
+    let pair_start = SystemTime::now();
+    let coordinators = run_workers(
         config,
-        client_drivers,
+        infra,
+        compute,
         unique_id,
-        config,
-    )
-    .await;
-    build_cmds.extend(client_build_cmds);
-    ssm_utils::common::wait_complete(
-        "Setup hosts: update and install dependencies",
-        ssm_client,
-        build_cmds,
+        server_driver,
+        client_driver,
+        dashboard,
+        pair_index,
+        &pair,
+        keep_servers,
+        abort,
     )
     .await;
+    // the post run hooks and the limits are run and removed even if the pair
+    // failed, since managed hosts outlive the run
+    let post_run = hooks::post_run(compute, infra, server_driver, client_driver, config).await;
+    let removed = throttle::remove(compute, infra, config).await;
+    let coordinators = coordinators?;
+    post_run?;
+    removed?;
+    let Some(coordinators) = coordinators else {
+        return Ok(());
+    };
+    let duration = timeline.record(format!("run {pair}"), Phase::Measurement, pair_start);
+    timeline.phases.add(format!("run {pair}"), duration);
+    timeline.metrics.driver_run(
+        &server_driver.results_name(),
+        &client_driver.results_name(),
+        duration,
+    );
+
+    // with `--skip-upload` the results are left on the hosts
+    if !config.skip.upload() {
+        dashboard.copy_pair_results(pair_index).await?;
+        let start = SystemTime::now();
+        compute
+            .collect_artifacts(
+                infra,
+                unique_id,
+                server_driver,
+                client_driver,
+                iteration,
+                config,
+            )
+            .await?;
+        let duration = timeline.record(format!("copy results {pair}"), Phase::Coordination, start);
+        timeline.phases.add("upload results", duration);
+    }
+    timeline.coordination.record_pair(
+        pair,
+        server_driver,
+        client_driver,
+        iteration,
+        pair_start.elapsed().unwrap_or_default(),
+        coordinators,
+    );
+    dashboard.finish_pair(pair_index).await?;
 
-    info!("Host setup Successful");
     Ok(())
 }
 
-async fn copy_netbench_results_to_s3(
+// Runs the workers of a driver pair, returning the coordinators' events or
+// None if the pair was aborted
+#[allow(clippy::too_many_arguments)]
+async fn run_workers(
     config: &OrchestratorConfig,
     infra: &InfraDetail,
-    ssm_client: &aws_sdk_ssm::Client,
+    compute: &impl ComputeProvider,
     unique_id: &str,
     server_driver: &NetbenchDriverType,
     client_driver: &NetbenchDriverType,
-) -> OrchResult<()> {
-    let client_ids = infra.client_ids();
-    let server_ids = infra.server_ids();
+    dashboard: &mut Dashboard<impl S3Api>,
+    pair_index: usize,
+    pair: &str,
+    keep_servers: bool,
+    abort: &mut AbortSignal,
+) -> OrchResult<Option<Vec<(EndpointType, EventRecorder)>>> {
+    // the workers wait for their coordinator, so the server and client
+    // workers are started together. In loopback mode the client workers
+    // run the servers, so there are no server workers.
+    let loopback_server = config.loopback().then_some(server_driver);
+    let (mut server_russula, mut client_russula) = tokio::try_join!(
+        async {
+            match loopback_server {
+                Some(_) => Ok(None),
+                None => ssm_utils::ServerNetbenchRussula::new(
+                    compute,
+                    infra,
+                    config,
+                    server_driver,
+                    keep_servers,
+                    unique_id,
+                )
+                .await
+                .map(Some),
+            }
+        },
+        ssm_utils::ClientNetbenchRussula::new(
+            compute,
+            infra,
+            config,
+            client_driver,
+            loopback_server,
+            unique_id,
+        ),
+    )?;
+    if let Some(server_russula) = &server_russula {
+        dashboard
+            .set_worker_logs(EndpointType::Server, server_russula.worker_command_id())
+            .await?;
+    }
+    dashboard
+        .set_worker_logs(EndpointType::Client, client_russula.worker_command_id())
+        .await?;
 
-    let copy_server_netbench = ssm_utils::common::upload_netbench_data_to_s3(
-        ssm_client,
-        server_ids.clone(),
-        unique_id,
-        config,
-        server_driver,
-    )
-    .await;
-    let copy_client_netbench = ssm_utils::common::upload_netbench_data_to_s3(
-        ssm_client,
-        client_ids.clone(),
-        unique_id,
-        config,
-        client_driver,
-    )
-    .await;
-    let msg = format!(
-        "copy netbench results to s3 for drivers: {}, {}",
-        server_driver.trim_driver_name(),
-        client_driver.trim_driver_name()
-    );
-    ssm_utils::common::wait_complete(
-        &msg,
-        ssm_client,
-        vec![copy_server_netbench, copy_client_netbench],
-    )
-    .await;
-    info!("client_server netbench copy results!: Successful");
+    // run client/server, unless aborted from the TUI
+    let run = async {
+        if let Some(server_russula) = server_russula.as_mut() {
+            server_russula.wait_netbench_running(compute).await?;
+        }
+        client_russula.wait_done(compute).await?;
+        if let Some(server_russula) = server_russula.as_mut() {
+            server_russula.wait_done(compute).await?;
+        }
+        Ok::<_, OrchError>(())
+    };
+    let request = tokio::select! {
+        result = run => {
+            result?;
+            None
+        }
+        request = abort.requested() => Some(request),
+    };
+
+    if let Some(request) = request {
+        info!("Aborting {pair}: {:?}", request);
+        // stop the workers so that the hosts are free for the next pair
+        if let Some(server_russula) = &server_russula {
+            compute.cancel(server_russula.worker_command_id()).await?;
+        }
+        compute.cancel(client_russula.worker_command_id()).await?;
+        dashboard.abort_pair(pair_index).await?;
+        return match request {
+            Abort::Pair => Ok(None),
+            Abort::Run => Err(aborted()),
+        };
+    }
 
-    Ok(())
+    let server_events = server_russula
+        .iter_mut()
+        .flat_map(|server_russula| server_russula.event_recorders());
+    let client_events = client_russula.event_recorders().into_iter();
+    let coordinators = server_events
+        .map(|events| (EndpointType::Server, events))
+        .chain(client_events.map(|events| (EndpointType::Client, events)))
+        .collect();
+    Ok(Some(coordinators))
+}
+
+fn aborted() -> OrchError {
+    OrchError::new(ErrorKind::Aborted, "The run was aborted from the TUI")
 }
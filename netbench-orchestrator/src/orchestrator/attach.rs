@@ -0,0 +1,317 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::{Ec2Api, S3Api, SsmApi},
+    ec2_utils::{instance_name, EndpointType},
+    orchestrator::{paths::STATUS_JSON, CdkConfig, ErrorKind, OrchError, OrchResult, RunPaths},
+    progress,
+    s3_utils::download_object,
+};
+use aws_sdk_ssm::types::CommandInvocationStatus;
+use clap::Args;
+use core::time::Duration;
+use serde::Deserialize;
+
+// Follows the progress of a run started by another orchestrator
+#[derive(Clone, Debug, Args)]
+pub struct AttachArgs {
+    /// The unique id of the run, as printed when it started
+    #[arg(long)]
+    unique_id: String,
+
+    /// How often the run is polled (eg. "10s")
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    interval: Duration,
+}
+
+// The parts of the dashboard's `status.json` shown while attached. See
+// `RunStatus`.
+#[derive(Debug, Deserialize)]
+struct RunView {
+    #[serde(default)]
+    label: Option<String>,
+    phase: String,
+    updated: String,
+    #[serde(default)]
+    pairs: Vec<PairView>,
+    #[serde(default)]
+    eta: Option<String>,
+    #[serde(default)]
+    report_url: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PairView {
+    server: String,
+    client: String,
+    #[serde(default)]
+    iteration: Option<u32>,
+    state: String,
+}
+
+// A host of the run, found by its `Name` tag
+#[derive(Debug)]
+struct HostView {
+    endpoint: &'static str,
+    instance_id: String,
+    state: String,
+    // The status of the latest Russula worker command on the host
+    worker: Option<String>,
+}
+
+// The phases after which the run no longer changes
+const FINAL_PHASES: [&str; 2] = ["finished", "failed"];
+
+// Shows the progress of a run until it's finished, eg. when the orchestrator
+// running it died or runs on another machine.
+//
+// Only reads the run's state: the hosts are found by their `Name` tag, the
+// Russula workers by their SSM commands, and the progress of the driver pairs
+// by the dashboard's `status.json`. The workers aren't connected to, since a
+// worker only accepts a single coordinator.
+pub async fn attach(
+    ec2_client: &impl Ec2Api,
+    ssm_client: &impl SsmApi,
+    s3_client: &impl S3Api,
+    cdk_config: &CdkConfig,
+    args: &AttachArgs,
+) -> OrchResult<()> {
+    let mut follower = RunFollower::new(&args.unique_id);
+    loop {
+        match follower
+            .update(ec2_client, ssm_client, s3_client, cdk_config)
+            .await?
+        {
+            Followed::Unknown => {
+                return Err(OrchError::new(
+                    ErrorKind::Init,
+                    format!("No status or hosts found for the run {}", args.unique_id),
+                ))
+            }
+            Followed::Running => {}
+            Followed::Done => return Ok(()),
+        }
+        tokio::time::sleep(args.interval).await;
+    }
+}
+
+// The state of a followed run, after an update
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Followed {
+    // Neither the status nor the hosts of the run exist (yet)
+    Unknown,
+    Running,
+    // The run is finished or failed, or all of its hosts are terminated
+    Done,
+}
+
+// Prints the progress of a run whenever it changes. See `attach`.
+pub struct RunFollower {
+    unique_id: String,
+    shown: Vec<String>,
+}
+
+impl RunFollower {
+    pub fn new(unique_id: &str) -> Self {
+        RunFollower {
+            unique_id: unique_id.to_string(),
+            shown: Vec::new(),
+        }
+    }
+
+    pub async fn update(
+        &mut self,
+        ec2_client: &impl Ec2Api,
+        ssm_client: &impl SsmApi,
+        s3_client: &impl S3Api,
+        cdk_config: &CdkConfig,
+    ) -> OrchResult<Followed> {
+        let status_key = RunPaths::new(&self.unique_id).key(STATUS_JSON);
+        // the status of a private run is kept in the private bucket
+        let mut status = None;
+        for bucket in [
+            cdk_config.netbench_runner_public_s3_bucket(),
+            cdk_config.netbench_runner_private_s3_bucket(),
+        ] {
+            status = download_object(s3_client, bucket, &status_key).await?;
+            if status.is_some() {
+                break;
+            }
+        }
+        let run = match status {
+            Some(body) => Some(serde_json::from_slice::<RunView>(&body).map_err(|err| {
+                OrchError::new(ErrorKind::S3, format!("Failed to parse {status_key}"))
+                    .with_source(err)
+            })?),
+            None => None,
+        };
+        let hosts = discover_hosts(ec2_client, ssm_client, &self.unique_id).await?;
+        if run.is_none() && hosts.is_empty() {
+            return Ok(Followed::Unknown);
+        }
+
+        let lines = describe(run.as_ref(), &hosts);
+        if lines != self.shown {
+            for line in &lines {
+                progress::output!("{line}");
+            }
+            self.shown = lines;
+        }
+
+        let finished = run
+            .as_ref()
+            .is_some_and(|run| FINAL_PHASES.contains(&run.phase.as_str()));
+        let terminated = !hosts.is_empty()
+            && hosts
+                .iter()
+                .all(|host| matches!(host.state.as_str(), "shutting-down" | "terminated"));
+        if finished || terminated {
+            return Ok(Followed::Done);
+        }
+        Ok(Followed::Running)
+    }
+}
+
+async fn discover_hosts(
+    ec2_client: &impl Ec2Api,
+    ssm_client: &impl SsmApi,
+    unique_id: &str,
+) -> OrchResult<Vec<HostView>> {
+    let mut hosts = Vec::new();
+    for endpoint_type in [EndpointType::Server, EndpointType::Client] {
+        let name = instance_name(unique_id, endpoint_type);
+        for instance in ec2_client.describe_instances_by_name(&name).await? {
+            let instance_id = instance.instance_id().unwrap_or_default().to_string();
+            let state = instance
+                .state()
+                .and_then(|state| state.name())
+                .map_or("unknown", |name| name.as_str())
+                .to_string();
+            let worker = match state.as_str() {
+                "running" => worker_status(ssm_client, &instance_id).await?,
+                _ => None,
+            };
+            hosts.push(HostView {
+                endpoint: match endpoint_type {
+                    EndpointType::Server => "server",
+                    EndpointType::Client => "client",
+                },
+                instance_id,
+                state,
+                worker,
+            });
+        }
+    }
+    Ok(hosts)
+}
+
+// The status of the latest `run_*_russula` command on the host
+async fn worker_status(ssm_client: &impl SsmApi, instance_id: &str) -> OrchResult<Option<String>> {
+    let invocations = ssm_client.list_instance_invocations(instance_id).await?;
+    let latest = invocations
+        .iter()
+        .filter(|invocation| {
+            invocation
+                .comment()
+                .is_some_and(|comment| comment.starts_with("run_") && comment.ends_with("_russula"))
+        })
+        .max_by_key(|invocation| invocation.requested_date_time().map(|date| date.secs()));
+    Ok(latest.and_then(|invocation| {
+        invocation.status().map(|status| match status {
+            CommandInvocationStatus::InProgress => "running".to_string(),
+            status => status.as_str().to_lowercase(),
+        })
+    }))
+}
+
+// The lines shown for the run, which are printed again whenever they change
+fn describe(run: Option<&RunView>, hosts: &[HostView]) -> Vec<String> {
+    let mut lines = Vec::new();
+    match run {
+        Some(run) => {
+            let done = run.pairs.iter().filter(|pair| pair.state == "done").count();
+            let mut line = format!(
+                "Phase: {}, {done} / {} driver pairs done, updated {}",
+                run.phase,
+                run.pairs.len(),
+                run.updated
+            );
+            if let Some(label) = &run.label {
+                line = format!("{label}: {line}");
+            }
+            if let Some(eta) = &run.eta {
+                line.push_str(&format!(", eta {eta}"));
+            }
+            lines.push(line);
+            for pair in run.pairs.iter().filter(|pair| pair.state != "pending") {
+                let mut line = format!("  {} / {}", pair.server, pair.client);
+                if let Some(iteration) = pair.iteration {
+                    line.push_str(&format!(" (iteration {iteration})"));
+                }
+                lines.push(format!("{line}: {}", pair.state.replace('_', " ")));
+            }
+            if let Some(error) = &run.error {
+                lines.push(format!("Error: {error}"));
+            }
+            if let Some(report_url) = &run.report_url {
+                lines.push(format!("Report: URL: {report_url}"));
+            }
+        }
+        None => lines.push("Phase: unknown, the run has no status yet".to_string()),
+    }
+    for host in hosts {
+        let worker = host.worker.as_deref().unwrap_or("not started");
+        lines.push(format!(
+            "  {} {}: {}, russula worker {worker}",
+            host.endpoint, host.instance_id, host.state
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_the_run() {
+        let run: RunView = serde_json::from_value(serde_json::json!({
+            "unique_id": "2024-01-01T00-00-00Z-rr-abcd",
+            "label": "main baseline",
+            "phase": "running",
+            "started": "2024-01-01T00:00:00Z",
+            "updated": "2024-01-01T00:10:00Z",
+            "hosts": [],
+            "pairs": [
+                { "server": "s2n-quic", "client": "s2n-quic", "state": "done", "duration_ms": 1000 },
+                { "server": "s2n-tls", "client": "s2n-tls", "state": "running" },
+                { "server": "tcp", "client": "tcp", "state": "pending" },
+            ],
+            "logs_url": "",
+        }))
+        .unwrap();
+        let hosts = [HostView {
+            endpoint: "server",
+            instance_id: "i-0".to_string(),
+            state: "running".to_string(),
+            worker: Some("running".to_string()),
+        }];
+
+        assert_eq!(
+            describe(Some(&run), &hosts),
+            vec![
+                "main baseline: Phase: running, 1 / 3 driver pairs done, updated 2024-01-01T00:10:00Z",
+                "  s2n-quic / s2n-quic: done",
+                "  s2n-tls / s2n-tls: running",
+                "  server i-0: running, russula worker running",
+            ]
+        );
+        assert_eq!(
+            describe(None, &[])[0],
+            "Phase: unknown, the run has no status yet"
+        );
+    }
+}
@@ -0,0 +1,461 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit,
+    orchestrator::{ErrorKind, OrchError, OrchResult, Partition},
+    progress,
+};
+use aws_sdk_ec2::types::{Filter, Tag};
+use aws_sdk_s3::types::{
+    AccelerateConfiguration, BucketAccelerateStatus, BucketLocationConstraint,
+    CreateBucketConfiguration, PublicAccessBlockConfiguration,
+};
+use clap::Args;
+use std::path::Path;
+use tracing::warn;
+
+// The names of the resources, which match the cdk stack's so that a bootstrapped
+// account and a deployed stack are interchangeable
+const LOG_GROUP: &str = "NetbenchRunnerLogGroup";
+const ROLE: &str = "NetbenchRunnerInstanceRole";
+const INSTANCE_PROFILE: &str = "NetbenchRunnerInstanceProfile";
+const SUBNET_TAG_KEY: &str = "aws-cdk:netbench-subnet-name";
+const SUBNET_TAG_VALUE: &str = "public-subnet-for-netbench-runners";
+// The managed policies of the hosts' role, as in the cdk stack
+const ROLE_POLICIES: &[&str] = &["AmazonSSMFullAccess", "AmazonS3FullAccess"];
+
+// Creates the resources the orchestrator needs without the cdk stack, and
+// writes the cdk config file
+#[derive(Clone, Debug, Args)]
+pub struct BootstrapArgs {
+    /// Suffix of the bucket names, which must be globally unique (eg. your
+    /// user name)
+    #[arg(long)]
+    bucket_suffix: String,
+
+    /// VPC whose subnets the hosts are launched in, instead of the region's
+    /// default VPC. The subnets must assign public ips.
+    #[arg(long)]
+    vpc_id: Option<String>,
+}
+
+// The resources created by the bootstrap
+#[derive(Debug)]
+struct Bootstrapped {
+    account: String,
+    region: String,
+    public_bucket: String,
+    private_bucket: String,
+}
+
+// Creates the buckets, the log group, the hosts' instance profile and tags
+// the subnets of the VPC, then writes the cdk config of the resources to
+// `cdk_config_file`.
+//
+// Unlike the cdk stack there's no CloudFront distribution, so the reports are
+// only served from the bucket (eg. shared with `--private-report`), and no
+// instance monitor. The resources which already exist are reused, so the
+// bootstrap can be run again, eg. after it failed part way.
+pub async fn bootstrap(
+    aws_config: &aws_types::SdkConfig,
+    cdk_config_file: &Path,
+    args: &BootstrapArgs,
+) -> OrchResult<()> {
+    if cdk_config_file.exists() {
+        return Err(OrchError::new(
+            ErrorKind::Init,
+            format!(
+                "{cdk_config_file:?} already exists. Remove it or choose another --cdk-config-file"
+            ),
+        ));
+    }
+    let region = aws_config
+        .region()
+        .map(|region| region.to_string())
+        .filter(|region| !region.is_empty())
+        .ok_or_else(|| {
+            OrchError::new(
+                ErrorKind::Init,
+                "The bootstrap needs a region. Use `--region` or a `--profile` with a region",
+            )
+        })?;
+    let account = aws_sdk_sts::Client::new(aws_config)
+        .get_caller_identity()
+        .send()
+        .await
+        .map_err(|err| OrchError::sdk(ErrorKind::Init, "Missing AWS credentials", err))?
+        .account()
+        .unwrap_or_default()
+        .to_string();
+
+    let bootstrapped = Bootstrapped {
+        account,
+        region,
+        public_bucket: format!("netbenchrunnerlogs-public-{}", args.bucket_suffix),
+        private_bucket: format!("netbenchrunner-private-source-{}", args.bucket_suffix),
+    };
+    let s3_client = aws_sdk_s3::Client::new(aws_config);
+    for bucket in [&bootstrapped.public_bucket, &bootstrapped.private_bucket] {
+        create_bucket(&s3_client, bucket, &bootstrapped.region).await?;
+    }
+    create_log_group(aws_config).await?;
+    create_instance_profile(aws_config, Partition::of_region(&bootstrapped.region)).await?;
+    tag_subnets(aws_config, args.vpc_id.as_deref()).await?;
+
+    let cdk_config = serde_json::to_string_pretty(&cdk_config(&bootstrapped))
+        .expect("the cdk config is serializable");
+    std::fs::write(cdk_config_file, cdk_config).map_err(|err| {
+        OrchError::new(
+            ErrorKind::Init,
+            format!("Failed to write {cdk_config_file:?}"),
+        )
+        .with_source(err)
+    })?;
+    progress::output!(
+        "Bootstrap: wrote {} for account {} in {}",
+        cdk_config_file.display(),
+        bootstrapped.account,
+        bootstrapped.region
+    );
+    Ok(())
+}
+
+// The buckets block public access, and have transfer acceleration enabled so
+// that runs can opt into it
+async fn create_bucket(
+    s3_client: &aws_sdk_s3::Client,
+    bucket: &str,
+    region: &str,
+) -> OrchResult<()> {
+    let mut create = s3_client.create_bucket().bucket(bucket);
+    // us-east-1 is the default location, which can't be a constraint
+    if region != "us-east-1" {
+        create = create.create_bucket_configuration(
+            CreateBucketConfiguration::builder()
+                .location_constraint(BucketLocationConstraint::from(region))
+                .build(),
+        );
+    }
+    match create.send().await {
+        Ok(_) => audit::record!("s3", "create_bucket", bucket),
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_bucket_already_owned_by_you()) =>
+        {
+            progress::output!("Bootstrap: reusing the bucket {bucket}");
+        }
+        Err(err) => {
+            return Err(OrchError::sdk(
+                ErrorKind::S3,
+                format!("Failed to create the bucket {bucket}"),
+                err,
+            ))
+        }
+    }
+
+    s3_client
+        .put_public_access_block()
+        .bucket(bucket)
+        .public_access_block_configuration(
+            PublicAccessBlockConfiguration::builder()
+                .block_public_acls(true)
+                .ignore_public_acls(true)
+                .block_public_policy(true)
+                .restrict_public_buckets(true)
+                .build(),
+        )
+        .send()
+        .await
+        .map_err(|err| {
+            OrchError::sdk(
+                ErrorKind::S3,
+                format!("Failed to block the public access to {bucket}"),
+                err,
+            )
+        })?;
+    // best effort, since acceleration is opt in and not offered in every region
+    if let Err(err) = s3_client
+        .put_bucket_accelerate_configuration()
+        .bucket(bucket)
+        .accelerate_configuration(
+            AccelerateConfiguration::builder()
+                .status(BucketAccelerateStatus::Enabled)
+                .build(),
+        )
+        .send()
+        .await
+    {
+        warn!("Failed to enable transfer acceleration on {bucket}. {err}");
+    }
+    Ok(())
+}
+
+async fn create_log_group(aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+    let logs_client = aws_sdk_cloudwatchlogs::Client::new(aws_config);
+    match logs_client
+        .create_log_group()
+        .log_group_name(LOG_GROUP)
+        .send()
+        .await
+    {
+        Ok(_) => {
+            audit::record!("logs", "create_log_group", log_group = LOG_GROUP);
+            Ok(())
+        }
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_resource_already_exists_exception()) =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(OrchError::sdk(
+            ErrorKind::CloudWatch,
+            format!("Failed to create the log group {LOG_GROUP}"),
+            err,
+        )),
+    }
+}
+
+// The hosts' role, which lets them run the SSM commands and upload the
+// results
+async fn create_instance_profile(
+    aws_config: &aws_types::SdkConfig,
+    partition: Partition,
+) -> OrchResult<()> {
+    let iam_client = aws_sdk_iam::Client::new(aws_config);
+    match iam_client
+        .create_role()
+        .role_name(ROLE)
+        .assume_role_policy_document(ec2_trust_policy(partition))
+        .send()
+        .await
+    {
+        Ok(_) => audit::record!("iam", "create_role", role = ROLE),
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_entity_already_exists_exception()) => {}
+        Err(err) => {
+            return Err(OrchError::sdk(
+                ErrorKind::Iam,
+                format!("Failed to create the role {ROLE}"),
+                err,
+            ))
+        }
+    }
+    // attaching an attached policy is a no-op
+    for policy in ROLE_POLICIES {
+        let policy_arn = partition.managed_policy_arn(policy);
+        iam_client
+            .attach_role_policy()
+            .role_name(ROLE)
+            .policy_arn(&policy_arn)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Iam,
+                    format!("Failed to attach {policy_arn} to {ROLE}"),
+                    err,
+                )
+            })?;
+    }
+
+    match iam_client
+        .create_instance_profile()
+        .instance_profile_name(INSTANCE_PROFILE)
+        .send()
+        .await
+    {
+        Ok(_) => audit::record!(
+            "iam",
+            "create_instance_profile",
+            instance_profile = INSTANCE_PROFILE
+        ),
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_entity_already_exists_exception()) => {}
+        Err(err) => {
+            return Err(OrchError::sdk(
+                ErrorKind::Iam,
+                format!("Failed to create the instance profile {INSTANCE_PROFILE}"),
+                err,
+            ))
+        }
+    }
+    let instance_profile = iam_client
+        .get_instance_profile()
+        .instance_profile_name(INSTANCE_PROFILE)
+        .send()
+        .await
+        .map_err(|err| {
+            OrchError::sdk(
+                ErrorKind::Iam,
+                format!("Failed to get the instance profile {INSTANCE_PROFILE}"),
+                err,
+            )
+        })?;
+    // an instance profile has at most one role
+    let has_role = instance_profile
+        .instance_profile()
+        .is_some_and(|profile| profile.roles().iter().any(|role| role.role_name() == ROLE));
+    if !has_role {
+        iam_client
+            .add_role_to_instance_profile()
+            .instance_profile_name(INSTANCE_PROFILE)
+            .role_name(ROLE)
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Iam,
+                    format!("Failed to add {ROLE} to {INSTANCE_PROFILE}"),
+                    err,
+                )
+            })?;
+    }
+    Ok(())
+}
+
+// Tags the subnets of the VPC, which is how the orchestrator finds a subnet
+// per AZ
+async fn tag_subnets(aws_config: &aws_types::SdkConfig, vpc_id: Option<&str>) -> OrchResult<()> {
+    let ec2_client = aws_sdk_ec2::Client::new(aws_config);
+    let vpc_filter = match vpc_id {
+        Some(vpc_id) => Filter::builder().name("vpc-id").values(vpc_id).build(),
+        None => {
+            let vpcs = ec2_client
+                .describe_vpcs()
+                .filters(Filter::builder().name("is-default").values("true").build())
+                .send()
+                .await
+                .map_err(|err| {
+                    OrchError::sdk(ErrorKind::Ec2, "Failed to describe the default VPC", err)
+                })?;
+            let vpc_id = vpcs
+                .vpcs()
+                .first()
+                .and_then(|vpc| vpc.vpc_id())
+                .ok_or_else(|| {
+                    OrchError::new(
+                        ErrorKind::Ec2,
+                        "The region has no default VPC. Use `--vpc-id` to choose one",
+                    )
+                })?;
+            Filter::builder().name("vpc-id").values(vpc_id).build()
+        }
+    };
+    let subnets = ec2_client
+        .describe_subnets()
+        .filters(vpc_filter)
+        .send()
+        .await
+        .map_err(|err| OrchError::sdk(ErrorKind::Ec2, "Failed to describe the subnets", err))?;
+    let mut subnet_ids = Vec::new();
+    for subnet in subnets.subnets() {
+        let Some(subnet_id) = subnet.subnet_id() else {
+            continue;
+        };
+        if subnet.map_public_ip_on_launch() != Some(true) {
+            warn!("{subnet_id} doesn't assign public ips, which the coordinators connect to");
+        }
+        subnet_ids.push(subnet_id.to_string());
+    }
+    if subnet_ids.is_empty() {
+        return Err(OrchError::new(ErrorKind::Ec2, "The VPC has no subnets"));
+    }
+
+    ec2_client
+        .create_tags()
+        .set_resources(Some(subnet_ids.clone()))
+        .tags(
+            Tag::builder()
+                .key(SUBNET_TAG_KEY)
+                .value(SUBNET_TAG_VALUE)
+                .build(),
+        )
+        .send()
+        .await
+        .map_err(|err| OrchError::sdk(ErrorKind::Ec2, "Failed to tag the subnets", err))?;
+    audit::record!("ec2", "create_tags", subnet_ids = ?subnet_ids);
+    Ok(())
+}
+
+// The EC2 service principal is in the partition's domain, eg.
+// `ec2.amazonaws.com.cn`
+fn ec2_trust_policy(partition: Partition) -> String {
+    format!(
+        r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":{{"Service":"ec2.{}"}},"Action":"sts:AssumeRole"}}]}}"#,
+        partition.dns_suffix()
+    )
+}
+
+// The outputs of the cdk stack, as read by `CdkConfig`. The reports are linked
+// to the bucket, which replaces the CloudFront distribution. Since the bucket
+// blocks public access, the config records that the reports aren't public so
+// that the runs default to `--private-report`.
+fn cdk_config(bootstrapped: &Bootstrapped) -> serde_json::Value {
+    let Bootstrapped {
+        account,
+        region,
+        public_bucket,
+        private_bucket,
+    } = bootstrapped;
+    serde_json::json!({
+        "NetbenchInfraPrimaryProd": {
+            "outputNetbenchRunnerLogGroup": LOG_GROUP,
+            "outputNetbenchRunnerPublicLogsBucket": public_bucket,
+            "outputNetbenchRunnerPrivateSrcBucket": private_bucket,
+            "outputNetbenchCloudfrontDistribution": format!(
+                "https://{public_bucket}.s3.{region}.{}",
+                Partition::of_region(region).dns_suffix()
+            ),
+            "outputNetbenchPublicReports": false,
+            "outputNetbenchRunnerInstanceProfile": INSTANCE_PROFILE,
+            "outputNetbenchSubnetTagKey": SUBNET_TAG_KEY,
+            "outputNetbenchSubnetTagValue": SUBNET_TAG_VALUE,
+            "outputNetbenchInfraPrimaryProdRegion": region,
+            "outputNetbenchInfraPrimaryProdAccount": account,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orchestrator::CdkConfig;
+
+    #[test]
+    fn cdk_config_of_the_bootstrapped_resources() {
+        let bootstrapped = Bootstrapped {
+            account: "123456789012".to_string(),
+            region: "us-west-2".to_string(),
+            public_bucket: "netbenchrunnerlogs-public-me".to_string(),
+            private_bucket: "netbenchrunner-private-source-me".to_string(),
+        };
+        let cdk_config: CdkConfig = serde_json::from_value(cdk_config(&bootstrapped)).unwrap();
+
+        assert_eq!(
+            cdk_config.netbench_runner_public_s3_bucket(),
+            "netbenchrunnerlogs-public-me"
+        );
+        assert_eq!(
+            cdk_config.netbench_runner_subnet_tag_key(),
+            "tag:aws-cdk:netbench-subnet-name"
+        );
+        assert_eq!(
+            cdk_config.netbench_cloudfront_distribution(),
+            "https://netbenchrunnerlogs-public-me.s3.us-west-2.amazonaws.com"
+        );
+        assert_eq!(cdk_config.netbench_cloudfront_distribution_id(), None);
+        assert!(!cdk_config.netbench_public_reports());
+        assert_eq!(
+            cdk_config.netbench_account().map(String::as_str),
+            Some("123456789012")
+        );
+    }
+}
@@ -1,19 +1,39 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::orchestrator::{
-    cli::types::{CdkConfig, CliInfraScenario, IntermediateCli, NetbenchScenario},
-    OrchResult,
+use crate::{
+    aws_api::AwsCli,
+    ec2_utils::EndpointType,
+    orchestrator::{
+        attach::AttachArgs,
+        bootstrap::BootstrapArgs,
+        cli::types::{CliInfraScenario, IntermediateCli, NetbenchScenario},
+        prune::PruneArgs,
+        ErrorKind, OrchError, OrchResult, PortPlan, RunId,
+    },
+    ssm_utils::DriverRegistry,
 };
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
 
 mod types;
 
-pub use types::HostConfig;
+pub use types::{
+    AwsAccessConfig, AwsEndpoints, CaptureConfig, CdkConfig, ControllerConfig, FleetConfig,
+    HostConfig, NotifyConfig, ProfileConfig, ReportConfig, RunConfig, S3TransferConfig, SkipConfig,
+};
 
+// Runs the scenario, unless a subcommand is given
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Credentials and region of the AWS clients
+    #[command(flatten)]
+    aws_access: AwsAccessConfig,
+
     /// Path to cdk parameter file
     #[arg(long, default_value = "cdk_config.json")]
     cdk_config_file: PathBuf,
@@ -21,43 +41,419 @@ pub struct Cli {
     /// Path to the scenario file
     ///
     /// eg. "../target/s2n-netbench/request_response.json"
+    #[arg(long, required = true)]
+    netbench_scenario_file: Option<PathBuf>,
+
+    /// Path to the driver registry file, which defines the drivers to run
+    #[arg(long, default_value = "drivers.json")]
+    driver_registry_file: PathBuf,
+
+    /// Names of the driver pairs to run, instead of the pairs enabled in the
+    /// driver registry (eg. "s2n-quic,s2n-tls,native-tls")
+    #[arg(long, value_delimiter = ',')]
+    drivers: Vec<String>,
+
+    /// Path to the source of a driver pair with a local source, replacing the
+    /// path in the driver registry (eg. "s2n-quic-dc=/home/me/projects/src")
+    #[arg(long, value_parser = parse_local_driver_path)]
+    local_driver_path: Vec<(String, PathBuf)>,
+
+    /// Argument passed to a driver pair when it's run, in addition to the
+    /// `args` in the driver registry (eg. "tcp=--nagle").
+    /// Can be repeated.
+    #[arg(long, value_parser = parse_driver_arg)]
+    driver_arg: Vec<(String, String)>,
+
+    /// Environment variable set for a driver pair when it's run, replacing the
+    /// `env` in the driver registry (eg. "s2n-quic=MULTITHREADED=true").
+    /// Can be repeated.
+    #[arg(long, value_parser = parse_driver_env)]
+    driver_env: Vec<(String, (String, String))>,
+
+    /// How often the collector samples a driver pair, replacing the
+    /// `collector_interval` in the driver registry (eg. "tcp=100ms").
+    /// Can be repeated.
+    #[arg(long, value_parser = parse_collector_interval)]
+    collector_interval: Vec<(String, String)>,
+
+    /// S3 prefix where local driver sources are staged for the hosts
+    /// (eg. "s3://my-bucket/driver-src"). Defaults to the private bucket.
+    #[arg(long)]
+    local_driver_s3_prefix: Option<String>,
+
+    /// OTLP gRPC endpoint to export the orchestrator's tracing spans to
+    /// (eg. "http://localhost:4317")
+    ///
+    /// Requires building with the `otel` feature.
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Log more detail: `-v` for debug and `-vv` for trace
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only print errors to the terminal, and log warnings and errors
+    ///
+    /// Hides the progress bars and the status lines (eg. the dashboard and
+    /// report URLs).
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log filter for the log file, overriding `-v`, `-q` and `RUST_LOG`
+    ///
+    /// Either a level (eg. "debug") or `RUST_LOG` style directives (eg.
+    /// "info,s2n_netbench_orchestrator::russula=trace").
+    #[arg(long, conflicts_with = "verbose")]
+    log_level: Option<String>,
+
+    /// Format of the orchestrator's log file in `./target`
+    ///
+    /// `json` writes one object per line with the `unique_id`, `phase`,
+    /// `host` and `driver` fields of the enclosing spans, so the logs can be
+    /// ingested and queried.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Monitor the run in an interactive terminal UI, which can abort the
+    /// current driver pair (`a`) or the whole run (`q`)
+    #[arg(long)]
+    tui: bool,
+
+    /// Serve the run status as json on `http://127.0.0.1:<port>/status` while
+    /// the orchestrator is running
+    #[arg(long)]
+    status_port: Option<u16>,
+
+    /// Path to a git checkout (eg. of the drivers under test) whose short sha
+    /// is added to the run's unique id
+    #[arg(long)]
+    run_id_git_dir: Option<PathBuf>,
+
+    /// Unique id of the run instead of generating one, which is set for the
+    /// orchestrator on the controller
+    #[arg(long, hide = true, conflicts_with = "run_id_git_dir")]
+    unique_id: Option<String>,
+
+    /// Path to a json file overriding the orchestrator's settings (eg. the
+    /// ssh key name or the SSM poll delay)
+    ///
+    /// `NETBENCH_*` environment variables take precedence over the file. See
+    /// the README for the settings.
+    #[arg(long)]
+    settings_file: Option<PathBuf>,
+
+    /// Handlebars template to use instead of the built-in dashboard `index.html`
+    ///
+    /// See the README for the context passed to the template.
     #[arg(long)]
-    netbench_scenario_file: PathBuf,
+    dashboard_template: Option<PathBuf>,
 
     // An infrastructure overlay for the hosts specified in the
     // netbench scenario file
     #[command(flatten)]
     infra: CliInfraScenario,
+
+    // Tuning for the s3 transfers of run artifacts
+    #[command(flatten)]
+    s3_transfer: S3TransferConfig,
+
+    // Repetition of the driver pairs
+    #[command(flatten)]
+    run: RunConfig,
+
+    // Publishing of the report
+    #[command(flatten)]
+    report: ReportConfig,
+
+    // Summary of the run sent by email and Slack
+    #[command(flatten)]
+    notify: NotifyConfig,
+
+    // Phases of the run which are skipped
+    #[command(flatten)]
+    skip: SkipConfig,
+
+    // Running the orchestrator on a controller in the hosts' VPC
+    #[command(flatten)]
+    controller: ControllerConfig,
+
+    // Launching the host groups with EC2 Fleet
+    #[command(flatten)]
+    fleet: FleetConfig,
+
+    // Profiling the drivers while they run
+    #[command(flatten)]
+    profile: ProfileConfig,
+
+    // Capturing the drivers' packets while they run
+    #[command(flatten)]
+    capture: CaptureConfig,
+}
+
+// Maintenance of the benchmark infrastructure, run instead of a scenario
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Delete the results of old runs from the public bucket
+    Prune(PruneArgs),
+
+    /// Follow the progress of a run started by another orchestrator
+    Attach(AttachArgs),
+
+    /// Create the buckets, log group, instance profile and subnet tags the
+    /// orchestrator needs without deploying the cdk stack, and write the cdk
+    /// config file
+    Bootstrap(BootstrapArgs),
+}
+
+// Format of the orchestrator's logs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
 }
 
 impl Cli {
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
+    pub fn cdk_config(&self) -> OrchResult<CdkConfig> {
+        CdkConfig::from_file(&self.cdk_config_file)
+    }
+
+    pub fn cdk_config_file(&self) -> &Path {
+        &self.cdk_config_file
+    }
+
+    pub fn aws_access(&self) -> &AwsAccessConfig {
+        &self.aws_access
+    }
+
+    // Required by clap unless a subcommand is given
+    fn netbench_scenario_file(&self) -> &Path {
+        self.netbench_scenario_file
+            .as_deref()
+            .expect("--netbench-scenario-file is required")
+    }
+
+    pub fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    // The log filter directives requested on the command line, or None to
+    // use `RUST_LOG`.
+    pub fn log_filter(&self) -> Option<String> {
+        if let Some(log_level) = &self.log_level {
+            return Some(log_level.clone());
+        }
+        match (self.quiet, self.verbose) {
+            (true, _) => Some("warn".to_string()),
+            (false, 0) => None,
+            (false, 1) => Some("debug".to_string()),
+            (false, _) => Some("trace".to_string()),
+        }
+    }
+
+    pub fn tui(&self) -> bool {
+        self.tui
+    }
+
+    pub fn status_port(&self) -> Option<u16> {
+        self.status_port
+    }
+
+    pub fn settings_file(&self) -> Option<&Path> {
+        self.settings_file.as_deref()
+    }
+
+    // The id of the run, from the time, the scenario and a random suffix
+    pub fn run_id(&self) -> OrchResult<RunId> {
+        if let Some(unique_id) = &self.unique_id {
+            return RunId::parse(unique_id);
+        }
+        RunId::generate(
+            self.netbench_scenario_file(),
+            self.run_id_git_dir.as_deref(),
+        )
+    }
+
+    pub fn otlp_endpoint(&self) -> Option<&str> {
+        self.otlp_endpoint.as_deref()
+    }
+
     pub fn process_config_files(self) -> OrchResult<IntermediateCli> {
+        let netbench_scenario_file = self
+            .netbench_scenario_file
+            .expect("--netbench-scenario-file is required");
         let (netbench_scenario, netbench_scenario_filename) =
-            NetbenchScenario::from_file(&self.netbench_scenario_file)?;
+            NetbenchScenario::from_file(&netbench_scenario_file)?;
         let cdk_config = CdkConfig::from_file(&self.cdk_config_file)?;
+        let mut driver_registry = DriverRegistry::from_file(&self.driver_registry_file)?;
+        if !self.drivers.is_empty() {
+            driver_registry.select(&self.drivers)?;
+        }
+        for (name, arg) in self.driver_arg {
+            driver_registry.add_arg(&name, arg)?;
+        }
+        for (name, (key, value)) in self.driver_env {
+            driver_registry.set_env(&name, key, value)?;
+        }
+        for (name, interval) in self.collector_interval {
+            driver_registry.set_collector_interval(&name, interval)?;
+        }
+        for (name, path) in self.local_driver_path {
+            driver_registry.set_local_path(&name, path)?;
+        }
+        if let Some(prefix) = &self.local_driver_s3_prefix {
+            driver_registry.set_local_s3_prefix(prefix);
+        }
+        let dashboard_template = self
+            .dashboard_template
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(&path).map_err(|err| {
+                    OrchError::new(
+                        ErrorKind::Init,
+                        format!("Failed to read dashboard template {:?}", path),
+                    )
+                    .with_source(err)
+                })
+            })
+            .transpose()?;
+        let mut controller = self.controller;
+        let mut files = vec![
+            ("cdk-config-file", self.cdk_config_file),
+            ("netbench-scenario-file", netbench_scenario_file.clone()),
+            ("driver-registry-file", self.driver_registry_file),
+        ];
+        files.extend(self.settings_file.map(|path| ("settings-file", path)));
+        files.extend(
+            self.dashboard_template
+                .map(|path| ("dashboard-template", path)),
+        );
+        controller.set_files(files);
 
         Ok(IntermediateCli::new(
             cdk_config,
             netbench_scenario,
             netbench_scenario_filename,
-            self.netbench_scenario_file,
+            netbench_scenario_file,
+            driver_registry,
+            dashboard_template,
             self.infra,
+            self.s3_transfer,
+            self.run,
+            self.report,
+            self.notify,
+            self.skip,
+            controller,
+            self.fleet,
+            self.profile,
+            self.capture,
+            self.aws_access.endpoints(),
         ))
     }
 }
 
+fn parse_driver_arg(value: &str) -> Result<(String, String), String> {
+    let (name, arg) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <driver>=<arg>, got {value}"))?;
+    Ok((name.to_string(), arg.to_string()))
+}
+
+fn parse_collector_interval(value: &str) -> Result<(String, String), String> {
+    let (name, interval) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <driver>=<interval>, got {value}"))?;
+    Ok((name.to_string(), interval.to_string()))
+}
+
+fn parse_driver_env(value: &str) -> Result<(String, (String, String)), String> {
+    let (name, key, value) = value
+        .split_once('=')
+        .and_then(|(name, env)| {
+            let (key, value) = env.split_once('=')?;
+            Some((name, key, value))
+        })
+        .ok_or_else(|| format!("expected <driver>=<KEY>=<VALUE>, got {value}"))?;
+    Ok((name.to_string(), (key.to_string(), value.to_string())))
+}
+
+fn parse_local_driver_path(value: &str) -> Result<(String, PathBuf), String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <driver>=<path>, got {value}"))?;
+    Ok((name.to_string(), PathBuf::from(path)))
+}
+
 #[derive(Clone, Debug)]
 pub struct OrchestratorConfig {
     // netbench
     netbench_scenario_filename: String,
     netbench_scenario_filepath: PathBuf,
+    // The number of servers in the scenario
+    scenario_servers: usize,
 
     // cdk
     pub cdk_config: CdkConfig,
 
+    // drivers
+    pub driver_registry: DriverRegistry,
+
+    // A handlebars template replacing the built-in dashboard
+    pub dashboard_template: Option<String>,
+
+    // s3
+    pub s3_transfer: S3TransferConfig,
+
+    // run
+    pub run: RunConfig,
+
+    // report
+    pub report: ReportConfig,
+
+    // notifications
+    pub notify: NotifyConfig,
+
+    // skipped phases
+    pub skip: SkipConfig,
+
+    // controller
+    pub controller: ControllerConfig,
+
+    // fleet
+    pub fleet: FleetConfig,
+
+    // profiling
+    pub profile: ProfileConfig,
+
+    // packet capture
+    pub capture: CaptureConfig,
+
+    // The endpoint overrides of the AWS services
+    pub aws_endpoints: AwsEndpoints,
+
+    // The region and credentials of the local `aws` cli commands
+    pub aws_cli: AwsCli,
+
     // infra
+    //
+    // The servers run on the client hosts, so `server_config` is empty
+    loopback: bool,
     pub client_config: Vec<HostConfig>,
     pub server_config: Vec<HostConfig>,
+    // The SSM managed instances used as a host group instead of launching
+    // hosts, in which case the group's `*_config` is empty
+    client_managed_instances: Vec<String>,
+    server_managed_instances: Vec<String>,
+
+    // ports
+    pub ports: PortPlan,
 }
 
 impl OrchestratorConfig {
@@ -71,6 +467,27 @@ impl OrchestratorConfig {
         &self.netbench_scenario_filepath
     }
 
+    // The number of servers in the scenario, which are addressed by their
+    // index. See `ServerMap`.
+    pub fn scenario_servers(&self) -> usize {
+        self.scenario_servers
+    }
+
+    // The scenario's servers run on the client hosts, which connect to them
+    // over loopback
+    pub fn loopback(&self) -> bool {
+        self.loopback
+    }
+
+    // The managed instances of the host group, which are empty if the group's
+    // hosts are launched
+    pub fn managed_instances(&self, endpoint_type: EndpointType) -> &[String] {
+        match endpoint_type {
+            EndpointType::Server => &self.server_managed_instances,
+            EndpointType::Client => &self.client_managed_instances,
+        }
+    }
+
     pub fn netbench_scenario_filepath_stem(&self) -> &str {
         self.netbench_scenario_filepath
             .as_path()
@@ -88,12 +505,18 @@ impl OrchestratorConfig {
         )
     }
 
+    // The bucket with the run's results and report, which is only public
+    // without `--private-report`.
+    pub fn results_bucket(&self) -> &str {
+        if self.report.is_private() {
+            self.cdk_config.netbench_runner_private_s3_bucket()
+        } else {
+            self.cdk_config.netbench_runner_public_s3_bucket()
+        }
+    }
+
     pub fn s3_path(&self, unique_id: &str) -> String {
-        format!(
-            "s3://{}/{}",
-            self.cdk_config.netbench_runner_public_s3_bucket(),
-            unique_id
-        )
+        format!("s3://{}/{}", self.results_bucket(), unique_id)
     }
 
     pub fn s3_private_path(&self, unique_id: &str) -> String {
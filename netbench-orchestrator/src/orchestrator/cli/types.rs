@@ -2,20 +2,36 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    ec2_utils::Az,
-    orchestrator::{OrchError, OrchResult, OrchestratorConfig, STATE},
+    aws_api::AwsCli,
+    ec2_utils::{Az, EndpointType},
+    orchestrator::{
+        dashboard, paths, permissions, quota, sizing, state, throttle::ThrottleProfile, versions,
+        ErrorKind, OrchError, OrchResult, OrchestratorConfig, PortPlan, ServerMap,
+    },
+    russula::netbench::Profiler,
+    ssm_utils::DriverRegistry,
 };
+use aws_config::{
+    meta::region::RegionProviderChain, profile::ProfileFileRegionProvider, sts::AssumeRoleProvider,
+    BehaviorVersion,
+};
+use aws_credential_types::provider::ProvideCredentials;
 use aws_sdk_ec2::types::{Placement as AwsPlacement, PlacementGroup};
+use aws_types::{
+    region::Region,
+    sdk_config::SharedCredentialsProvider,
+    service_config::{LoadServiceConfig, ServiceConfigKey},
+};
 use clap::Args;
+use core::time::Duration;
 use serde::Deserialize;
-use serde_json::Value;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fs::File,
     path::{Path, PathBuf},
     process::Command,
 };
-use tracing::debug;
+use tracing::{debug, info};
 
 // Parse the netbench and cdk config files
 pub struct IntermediateCli {
@@ -23,62 +39,138 @@ pub struct IntermediateCli {
     netbench_scenario: NetbenchScenario,
     netbench_scenario_filename: String,
     netbench_scenario_filepath: PathBuf,
+    driver_registry: DriverRegistry,
+    dashboard_template: Option<String>,
     infra: CliInfraScenario,
+    s3_transfer: S3TransferConfig,
+    run: RunConfig,
+    report: ReportConfig,
+    notify: NotifyConfig,
+    skip: SkipConfig,
+    controller: ControllerConfig,
+    fleet: FleetConfig,
+    profile: ProfileConfig,
+    capture: CaptureConfig,
+    aws_endpoints: AwsEndpoints,
 }
 
 impl IntermediateCli {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         cdk_config: CdkConfig,
         netbench_scenario: NetbenchScenario,
         netbench_scenario_filename: String,
         netbench_scenario_filepath: PathBuf,
+        driver_registry: DriverRegistry,
+        dashboard_template: Option<String>,
         infra: CliInfraScenario,
+        s3_transfer: S3TransferConfig,
+        run: RunConfig,
+        report: ReportConfig,
+        notify: NotifyConfig,
+        skip: SkipConfig,
+        controller: ControllerConfig,
+        fleet: FleetConfig,
+        profile: ProfileConfig,
+        capture: CaptureConfig,
+        aws_endpoints: AwsEndpoints,
     ) -> Self {
         IntermediateCli {
             cdk_config,
             netbench_scenario,
             netbench_scenario_filename,
             netbench_scenario_filepath,
+            driver_registry,
+            dashboard_template,
             infra,
+            s3_transfer,
+            run,
+            report,
+            notify,
+            skip,
+            controller,
+            fleet,
+            profile,
+            capture,
+            aws_endpoints,
         }
     }
 
-    pub fn region(&self) -> String {
-        self.cdk_config.netbench_primary_region().to_string()
+    pub fn cdk_config(&self) -> &CdkConfig {
+        &self.cdk_config
     }
 
     // Perform sanity checks on user environment and input
     //
     // Things to check for include:
     // - user arguments are sanity
+    // - driver sources exist
+    // - the hosts' netbench releases are compatible with the local cli
     // - s2n-netbench utility is installed
     // - aws cli is installed
     // - aws credentials are loaded
+    // - the hosts' instance types fit the scenario's load
     // - local report directory has been created
     pub async fn check_requirements(
-        self,
+        mut self,
         aws_config: &aws_types::SdkConfig,
     ) -> OrchResult<OrchestratorConfig> {
+        validate_scenario(&self.netbench_scenario_filepath)?;
+        self.driver_registry.check_sources()?;
+        // the hosts keep the releases they have without a build
+        if !self.skip.build() {
+            versions::check(
+                &mut self.driver_registry,
+                self.report.allow_version_mismatch(),
+            )?;
+        }
+        if let Some(template) = &self.dashboard_template {
+            dashboard::check_template(template)?;
+        }
+        self.run.validate()?;
+        // the reports can't be published without a public bucket, eg. one
+        // created by `bootstrap`
+        if !self.cdk_config.netbench_public_reports() && !self.report.is_private() {
+            info!("The reports of the cdk config aren't public, defaulting to --private-report");
+            self.report.private_report = true;
+        }
+        self.report.validate()?;
+        self.notify.validate()?;
+        self.controller.validate(&self.driver_registry)?;
+
         let scenario = self.netbench_scenario;
         let netbench_scenario_filename = self.netbench_scenario_filename;
         let cdk_config = self.cdk_config;
 
         // AZ
+        //
+        // The scenario's servers are spread over the server hosts, so there
+        // can be fewer server hosts than servers. See `ServerMap`. In loopback
+        // mode there are no server hosts. A host group of managed instances
+        // replaces the AZ overlay of the group.
+        let server_hosts = self.infra.server_az.len() + self.infra.server_managed_instance.len();
+        let client_hosts = self.infra.client_az.len() + self.infra.client_managed_instance.len();
+        if self.infra.loopback {
+            assert!(
+                server_hosts == 0,
+                "AZ overlay should have no server hosts in loopback mode"
+            );
+        } else {
+            assert!(
+                server_hosts != 0 && server_hosts <= scenario.servers.len(),
+                "AZ overlay should have a server host for at most each server in the netbench scenario"
+            );
+        }
         assert_eq!(
-            self.infra.server_az.len(),
-            scenario.servers.len(),
-            "AZ overlay should match the number of server hosts in the netbench scenario"
-        );
-        assert_eq!(
-            self.infra.client_az.len(),
+            client_hosts,
             scenario.clients.len(),
             "AZ overlay should match the number of client hosts in the netbench scenario"
         );
         // Placement
         assert!(
             self.infra.server_placement.is_empty()
-                || self.infra.server_placement.len() == scenario.servers.len(),
-            "Placement overlay should be empty or match the number of client hosts in the netbench scenario"
+                || self.infra.server_placement.len() == self.infra.server_az.len(),
+            "Placement overlay should be empty or match the number of server hosts"
         );
         assert!(
             self.infra.client_placement.is_empty()
@@ -113,46 +205,140 @@ impl IntermediateCli {
             ));
         }
 
-        let config = OrchestratorConfig {
+        // In loopback mode the drivers are only reached from their own host
+        let driver_ports = if self.infra.loopback {
+            BTreeSet::new()
+        } else {
+            self.driver_registry.ports()
+        };
+        let ports = PortPlan::new(
+            self.infra.russula_port.unwrap_or(state().russula_port),
+            driver_ports,
+            ServerMap::ports_per_host(scenario.servers.len(), server_hosts),
+            self.infra.metrics_port.into_iter().collect(),
+        )?;
+
+        let mut config = OrchestratorConfig {
             netbench_scenario_filename,
             netbench_scenario_filepath: self.netbench_scenario_filepath,
+            scenario_servers: scenario.servers.len(),
+            loopback: self.infra.loopback,
             client_config,
             server_config,
+            client_managed_instances: self.infra.client_managed_instance,
+            server_managed_instances: self.infra.server_managed_instance,
+            ports,
             cdk_config,
+            driver_registry: self.driver_registry,
+            dashboard_template: self.dashboard_template,
+            s3_transfer: self.s3_transfer,
+            run: self.run,
+            report: self.report,
+            notify: self.notify,
+            skip: self.skip,
+            controller: self.controller,
+            fleet: self.fleet,
+            profile: self.profile,
+            capture: self.capture,
+            aws_endpoints: self.aws_endpoints,
+            aws_cli: AwsCli::new(aws_config),
         };
         debug!("{:?}", config);
 
-        Command::new("s2n-netbench")
-            .output()
-            .map_err(|_err| OrchError::Init {
-                dbg: "Missing `s2n-netbench` cli. Please the Getting started section in the Readme"
+        Command::new("s2n-netbench").output().map_err(|_err| {
+            OrchError::new(
+                ErrorKind::Init,
+                "Missing `s2n-netbench` cli. Please the Getting started section in the Readme"
                     .to_string(),
-            })?;
+            )
+        })?;
 
         Command::new("aws")
             .output()
-            .map_err(|_err| OrchError::Init {
-                dbg: "Missing `aws` cli.".to_string(),
-            })?;
+            .map_err(|_err| OrchError::new(ErrorKind::Init, "Missing `aws` cli."))?;
 
-        let iam_client = aws_sdk_iam::Client::new(aws_config);
-        iam_client
-            .list_roles()
-            .send()
-            .await
-            .map_err(|_err| OrchError::Init {
-                dbg: "Missing AWS credentials.".to_string(),
-            })?;
+        let caller_arn = check_identity(aws_config, &config.cdk_config).await?;
+        permissions::check(aws_config, &config, &caller_arn).await?;
+        quota::check(aws_config, &config).await?;
+        sizing::check(aws_config, &config).await?;
 
         // report folder
-        std::fs::create_dir_all(STATE.workspace_dir).map_err(|_err| OrchError::Init {
-            dbg: "Failed to create local workspace".to_string(),
-        })?;
+        std::fs::create_dir_all(paths::workspace_root())
+            .map_err(|_err| OrchError::new(ErrorKind::Init, "Failed to create local workspace"))?;
+
+        // s3 transfer settings for the local `aws` cli
+        config.s3_transfer.write_local_aws_config()?;
 
         Ok(config)
     }
 }
 
+impl OrchestratorConfig {
+    // A run of the scenario with a server and a client host in each AZ and
+    // the default settings, eg. for the end-to-end tests. Without server AZs
+    // the run is in loopback mode, with a single server.
+    #[cfg(test)]
+    pub fn testing(
+        netbench_scenario_filepath: PathBuf,
+        driver_registry: DriverRegistry,
+        server_az: &[&str],
+        client_az: &[&str],
+    ) -> Self {
+        let host_config =
+            |az: &&str| HostConfig::new("", az.to_string(), PlacementGroupConfig::Cluster);
+        let ports = PortPlan::new(
+            state().russula_port,
+            driver_registry.ports(),
+            1,
+            BTreeSet::new(),
+        )
+        .expect("the default ports don't overlap");
+        OrchestratorConfig {
+            netbench_scenario_filename: netbench_scenario_filepath
+                .file_name()
+                .expect("scenario has a file name")
+                .to_string_lossy()
+                .to_string(),
+            netbench_scenario_filepath,
+            scenario_servers: server_az.len().max(1),
+            loopback: server_az.is_empty(),
+            cdk_config: CdkConfig::testing(),
+            driver_registry,
+            dashboard_template: None,
+            s3_transfer: S3TransferConfig::default(),
+            run: RunConfig {
+                iterations: 1,
+                warmup_secs: 0,
+                max_clock_skew: Duration::from_millis(10),
+                server_egress_limit: None,
+                client_egress_limit: None,
+                label: None,
+                description: None,
+                keep_servers: false,
+                keep_servers_ttl: Duration::from_secs(60 * 60),
+            },
+            report: ReportConfig {
+                private_report: false,
+                report_link_expiry: MAX_PRESIGNED_EXPIRY,
+                allow_version_mismatch: false,
+            },
+            notify: NotifyConfig::default(),
+            skip: SkipConfig::default(),
+            controller: ControllerConfig::default(),
+            fleet: FleetConfig::default(),
+            profile: ProfileConfig::default(),
+            capture: CaptureConfig::default(),
+            aws_endpoints: AwsEndpoints::default(),
+            aws_cli: AwsCli::default(),
+            client_config: client_az.iter().map(host_config).collect(),
+            server_config: server_az.iter().map(host_config).collect(),
+            client_managed_instances: vec![],
+            server_managed_instances: vec![],
+            ports,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct HostConfig {
     pub az: String,
@@ -179,6 +365,19 @@ impl HostConfig {
         &self.instance_type
     }
 
+    // The hosts of a cluster placement must be launched in the placement
+    // group of their AZ
+    pub fn is_cluster(&self) -> bool {
+        matches!(self.placement, PlacementGroupConfig::Cluster)
+    }
+
+    pub fn placement_name(&self) -> &'static str {
+        match self.placement {
+            PlacementGroupConfig::Unspecified => "unspecified",
+            PlacementGroupConfig::Cluster => "cluster",
+        }
+    }
+
     pub fn to_ec2_placement(
         &self,
         placement_map: &HashMap<Az, PlacementGroup>,
@@ -193,13 +392,17 @@ impl HostConfig {
             PlacementGroupConfig::Cluster => {
                 debug!("cluster placement group specified");
                 let az = Az::from(self.az.clone());
-                let placement_group = placement_map.get(&az).expect("placement group not found");
+                let group_name = placement_map
+                    .get(&az)
+                    .and_then(|placement_group| placement_group.group_name())
+                    .ok_or_else(|| {
+                        OrchError::new(
+                            ErrorKind::Ec2,
+                            format!("Placement group not found for Az: {}", self.az),
+                        )
+                    })?;
 
-                aws_placement = aws_placement.group_name(
-                    placement_group
-                        .group_name()
-                        .expect("placement group_name not found"),
-                );
+                aws_placement = aws_placement.group_name(group_name);
             }
         };
 
@@ -229,31 +432,68 @@ enum PlacementGroupConfig {
 // Used for parsing the scenario file generated by the s2n-netbench project
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct NetbenchScenario {
-    pub clients: Vec<Value>,
-    pub servers: Vec<Value>,
+    pub clients: Vec<ScenarioHost>,
+    pub servers: Vec<ScenarioHost>,
 }
 
+// The orchestrator only needs the number of hosts in the scenario. The rest
+// of the scenario is checked by `s2n-netbench validate-scenario`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScenarioHost {}
+
 impl NetbenchScenario {
     pub fn from_file(netbench_scenario_file: &PathBuf) -> OrchResult<(Self, String)> {
         let path = Path::new(&netbench_scenario_file);
         let name = path
             .file_name()
             .and_then(|f| f.to_str())
-            .ok_or(OrchError::Init {
-                dbg: "Scenario file not specified".to_string(),
-            })?
+            .ok_or(OrchError::new(
+                ErrorKind::Init,
+                "Scenario file not specified",
+            ))?
             .to_string();
-        let netbench_scenario_file = File::open(path).map_err(|_err| OrchError::Init {
-            dbg: format!("Scenario file not found: {:?}", path),
+        let netbench_scenario_file = File::open(path).map_err(|_err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Scenario file not found: {:?}", path),
+            )
         })?;
         let scenario: NetbenchScenario =
-            serde_json::from_reader(netbench_scenario_file).map_err(|err| OrchError::Init {
-                dbg: format!("Failed to parse netbench file. {err}"),
+            serde_json::from_reader(netbench_scenario_file).map_err(|err| {
+                OrchError::new(ErrorKind::Init, "Failed to parse netbench file").with_source(err)
             })?;
         Ok((scenario, name))
     }
 }
 
+// Check the scenario with the `s2n-netbench` cli before launching any
+// infrastructure, so that mistakes in the scenario are reported up front.
+fn validate_scenario(netbench_scenario_filepath: &Path) -> OrchResult<()> {
+    let output = Command::new("s2n-netbench")
+        .arg("validate-scenario")
+        .arg(netbench_scenario_filepath)
+        .output()
+        .map_err(|_err| {
+            OrchError::new(
+                ErrorKind::Init,
+                "Missing `s2n-netbench` cli. Please the Getting started section in the Readme"
+                    .to_string(),
+            )
+        })?;
+
+    if !output.status.success() {
+        return Err(OrchError::new(
+            ErrorKind::Init,
+            format!(
+                "Invalid netbench scenario.\n{}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Clone, Debug, Default, Args)]
 pub struct CliInfraScenario {
     /// Placement strategy for the netbench client hosts
@@ -271,6 +511,961 @@ pub struct CliInfraScenario {
     /// AZ placement for the netbench server hosts
     #[arg(long, value_delimiter = ',')]
     server_az: Vec<String>,
+
+    /// Run the scenario's servers on the client hosts and connect to them over
+    /// loopback, instead of launching server hosts
+    ///
+    /// Isolates the protocol and CPU costs of the drivers from the network.
+    /// Each client host runs its own copy of the servers.
+    #[arg(long, conflicts_with_all = ["server_az", "server_placement"])]
+    loopback: bool,
+
+    /// SSM managed instances (eg. lab hardware registered with a hybrid
+    /// activation) to use as the server hosts instead of launching them
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["server_az", "server_placement", "loopback"])]
+    server_managed_instance: Vec<String>,
+
+    /// SSM managed instances (eg. lab hardware registered with a hybrid
+    /// activation) to use as the client hosts instead of launching them
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["client_az", "client_placement"])]
+    client_managed_instance: Vec<String>,
+
+    /// Port the Russula workers listen on for their coordinator, replacing
+    /// the `russula_port` setting
+    #[arg(long)]
+    russula_port: Option<u16>,
+
+    /// TCP port opened on the hosts to the local machine, eg. for a metrics
+    /// exporter started by a host hook. Can be repeated.
+    #[arg(long)]
+    metrics_port: Vec<u16>,
+}
+
+// Tuning for s3 transfers performed by the `aws` cli.
+//
+// Netbench results can be multiple GB in size and are transferred between the
+// remote hosts, s3 and the local host. These settings are applied to the `aws`
+// cli on both the remote hosts and the local host.
+//
+// https://docs.aws.amazon.com/cli/latest/topic/s3-config.html
+#[derive(Clone, Debug, Default, Args)]
+pub struct S3TransferConfig {
+    /// Use S3 Transfer Acceleration when transferring artifacts.
+    ///
+    /// Useful when running the orchestrator far from the primary region.
+    /// Transfer Acceleration must be enabled on the netbench buckets.
+    #[arg(long)]
+    s3_transfer_acceleration: bool,
+
+    /// Part size used for multipart s3 transfers (eg. "64MB")
+    #[arg(long, value_parser = parse_s3_size)]
+    s3_multipart_chunksize: Option<String>,
+
+    /// Maximum number of concurrent requests used for s3 transfers
+    #[arg(long)]
+    s3_max_concurrent_requests: Option<u32>,
+
+    // A copy of the user's aws config with the s3 settings applied.
+    //
+    // Used by local `aws` cli commands so that the user's config is not modified.
+    #[arg(skip)]
+    local_aws_config: Option<PathBuf>,
+}
+
+// Controls how the driver pairs are run
+#[derive(Clone, Debug, Args)]
+pub struct RunConfig {
+    /// Number of times to run each driver pair
+    ///
+    /// The results of each iteration are stored under an `iteration-N`
+    /// directory and aggregated in the report.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+
+    /// Seconds to run each client driver before the measured run
+    ///
+    /// The warmup results are discarded. The servers keep running between the
+    /// warmup and the measured run, so their results include the warmup.
+    #[arg(long, default_value_t = 0)]
+    warmup_secs: u64,
+
+    /// Warn when the clocks of the hosts are further apart than this (eg.
+    /// "10ms")
+    ///
+    /// The client and server timelines in the report are aligned by their
+    /// timestamps, so they're skewed by as much as the clocks.
+    #[arg(long, default_value = "10ms", value_parser = humantime::parse_duration)]
+    max_clock_skew: Duration,
+
+    /// Limit the egress of the server hosts while the drivers run, to emulate
+    /// a constrained link
+    ///
+    /// A profile (`3g`, `lte`, `dsl` or `cable`) or
+    /// `rate=<rate>[,burst=<bytes>][,latency=<duration>]` (eg.
+    /// "rate=20mbit,latency=100ms"). See the README.
+    #[arg(long, value_parser = ThrottleProfile::parse, conflicts_with = "loopback")]
+    server_egress_limit: Option<ThrottleProfile>,
+
+    /// Limit the egress of the client hosts while the drivers run. Takes the
+    /// same values as `--server-egress-limit`.
+    #[arg(long, value_parser = ThrottleProfile::parse, conflicts_with = "loopback")]
+    client_egress_limit: Option<ThrottleProfile>,
+
+    /// Short label shown with the run on the dashboard and the runs index (eg.
+    /// "PR #1234 candidate" or "main baseline")
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Longer description of the run, shown with its label
+    #[arg(long)]
+    description: Option<String>,
+
+    /// Leave the servers of the last driver pair running and the hosts up
+    /// once the run is done, for interactive clients and debugging
+    ///
+    /// The hosts still shut down (and terminate) after `--keep-servers-ttl`,
+    /// and are tagged with `netbench:expires-at`. See the README.
+    #[arg(long, conflicts_with = "loopback")]
+    keep_servers: bool,
+
+    /// How long the hosts are kept with `--keep-servers` (eg. "30m"), at most
+    /// 24 hours
+    #[arg(long, default_value = "1h", value_parser = humantime::parse_duration, requires = "keep_servers")]
+    keep_servers_ttl: Duration,
+}
+
+impl RunConfig {
+    // Keeps the hosts once the run is done, eg. for the end-to-end tests
+    #[cfg(test)]
+    pub fn keep_servers_for(&mut self, ttl: Duration) {
+        self.keep_servers = true;
+        self.keep_servers_ttl = ttl;
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    pub fn warmup_secs(&self) -> u64 {
+        self.warmup_secs
+    }
+
+    pub fn max_clock_skew(&self) -> Duration {
+        self.max_clock_skew
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    // How long the hosts are kept once the run is done, with `--keep-servers`
+    pub fn keep_servers(&self) -> Option<Duration> {
+        self.keep_servers.then_some(self.keep_servers_ttl)
+    }
+
+    // The egress limit of the host group while the drivers run, if any
+    pub fn egress_limit(&self, endpoint_type: EndpointType) -> Option<&ThrottleProfile> {
+        match endpoint_type {
+            EndpointType::Server => self.server_egress_limit.as_ref(),
+            EndpointType::Client => self.client_egress_limit.as_ref(),
+        }
+    }
+
+    // The iteration subdirectory is only used when the pairs are repeated so
+    // that the layout of single runs is unchanged.
+    pub fn iteration(&self, iteration: u32) -> Option<u32> {
+        (self.iterations > 1).then_some(iteration)
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        if self.keep_servers_ttl > MAX_KEEP_SERVERS_TTL {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "--keep-servers-ttl must be at most 24 hours",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The longest the hosts are kept with `--keep-servers`, so that forgotten
+// hosts don't run for days
+const MAX_KEEP_SERVERS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Where the report is published
+#[derive(Clone, Debug, Args)]
+pub struct ReportConfig {
+    /// Keep the results, report and run dashboard in the private bucket, and
+    /// share the report with a time-limited presigned link instead of
+    /// publishing it
+    ///
+    /// Useful for benchmarking unreleased drivers whose numbers shouldn't be
+    /// public.
+    #[arg(long)]
+    private_report: bool,
+
+    /// How long the presigned link to a private report is valid (eg. "12h")
+    ///
+    /// Presigned links are valid for at most 7 days, and no longer than the
+    /// credentials used to sign them.
+    #[arg(long, default_value = "7days", value_parser = humantime::parse_duration)]
+    report_link_expiry: Duration,
+
+    /// Warn instead of failing when the collector or drivers installed on the
+    /// hosts are from a netbench release incompatible with the local
+    /// `s2n-netbench` cli, which generates the report
+    #[arg(long)]
+    allow_version_mismatch: bool,
+}
+
+impl ReportConfig {
+    #[cfg(test)]
+    pub fn set_private(&mut self) {
+        self.private_report = true;
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private_report
+    }
+
+    pub fn link_expiry(&self) -> Duration {
+        self.report_link_expiry
+    }
+
+    pub fn allow_version_mismatch(&self) -> bool {
+        self.allow_version_mismatch
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        if self.report_link_expiry > MAX_PRESIGNED_EXPIRY {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "--report-link-expiry must be at most 7 days",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The longest expiry S3 accepts for a presigned url
+const MAX_PRESIGNED_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// Where to send a summary of the run once it completes
+#[derive(Clone, Debug, Default, Args)]
+pub struct NotifyConfig {
+    /// Email address to send the run summary to via SES. Can be repeated.
+    #[arg(long)]
+    notify_email: Vec<String>,
+
+    /// SES verified identity the run summary is sent from
+    #[arg(long)]
+    notify_email_from: Option<String>,
+
+    /// Slack incoming webhook URL to post the run summary to
+    #[arg(long)]
+    notify_slack_webhook: Option<String>,
+
+    /// Unique id of a previous run to compare the summary's metrics against
+    #[arg(long)]
+    baseline_run: Option<String>,
+
+    /// Write the run summary to the GitHub Actions step summary and annotate
+    /// the workflow with the error of a failed run
+    #[arg(long)]
+    github_actions: bool,
+}
+
+impl NotifyConfig {
+    pub fn emails(&self) -> &[String] {
+        &self.notify_email
+    }
+
+    pub fn email_from(&self) -> Option<&str> {
+        self.notify_email_from.as_deref()
+    }
+
+    pub fn slack_webhook(&self) -> Option<&str> {
+        self.notify_slack_webhook.as_deref()
+    }
+
+    pub fn baseline_run(&self) -> Option<&str> {
+        self.baseline_run.as_deref()
+    }
+
+    pub fn github_actions(&self) -> bool {
+        self.github_actions
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.notify_email.is_empty() || self.notify_slack_webhook.is_some() || self.github_actions
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        if !self.notify_email.is_empty() && self.notify_email_from.is_none() {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "--notify-email requires --notify-email-from",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Phases of a run which are skipped, eg. while iterating on a driver
+//
+// The hosts are checked for what the skipped phases would have installed, so
+// that a missing prerequisite fails the setup rather than the driver run.
+#[derive(Clone, Debug, Default, Args)]
+pub struct SkipConfig {
+    /// Don't install the dependencies on the hosts
+    ///
+    /// The hosts must already have them, eg. from an AMI built from a previous
+    /// run's hosts (see the `ami_name` setting).
+    #[arg(long)]
+    skip_configure: bool,
+
+    /// Don't build the drivers and Russula on the hosts
+    ///
+    /// The driver executables must already be in the hosts' bin folder, and
+    /// Russula in its checkout.
+    #[arg(long)]
+    skip_build: bool,
+
+    /// Don't generate the report once the driver pairs have run
+    #[arg(long)]
+    skip_report: bool,
+
+    /// Leave the results of the driver pairs on the hosts instead of moving
+    /// them to S3
+    ///
+    /// The report is generated from the results in S3, so this requires
+    /// `--skip-report`.
+    #[arg(long, requires = "skip_report")]
+    skip_upload: bool,
+}
+
+impl SkipConfig {
+    // Skips the setup of the hosts, eg. for the end-to-end tests
+    #[cfg(test)]
+    pub fn setup() -> Self {
+        SkipConfig {
+            skip_configure: true,
+            skip_build: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn configure(&self) -> bool {
+        self.skip_configure
+    }
+
+    pub fn build(&self) -> bool {
+        self.skip_build
+    }
+
+    pub fn report(&self) -> bool {
+        self.skip_report
+    }
+
+    pub fn upload(&self) -> bool {
+        self.skip_upload
+    }
+}
+
+// Running the orchestrator on a controller instance in the hosts' VPC
+//
+// The coordinators connect to the Russula workers on the hosts, which fails
+// when the local machine can't reach the hosts' public ips (eg. behind a VPN or
+// NAT). The controller reaches the workers over their private ips instead.
+#[derive(Clone, Debug, Default, Args)]
+pub struct ControllerConfig {
+    /// Run the orchestrator on a controller instance in the hosts' VPC and
+    /// follow the run locally, as with `attach`
+    #[arg(long, conflicts_with_all = ["tui", "status_port"])]
+    controller: bool,
+
+    /// SSM managed instance in the hosts' VPC to use as the controller,
+    /// instead of launching one for the run
+    #[arg(long, requires = "controller")]
+    controller_instance: Option<String>,
+
+    /// Instance type of the launched controller
+    #[arg(long, default_value = "t3.large")]
+    controller_instance_type: String,
+
+    /// Instance profile of the launched controller, which needs the
+    /// permissions of the orchestrator (eg. to launch the hosts)
+    #[arg(long, requires = "controller", conflicts_with = "controller_instance")]
+    controller_instance_profile: Option<String>,
+
+    /// Connect to the Russula workers over the hosts' private ips, which is
+    /// set for the orchestrator on the controller
+    #[arg(long, hide = true)]
+    russula_private_ips: bool,
+
+    // The local files read by the run, which are staged for the controller
+    // under the name of their flag. See `controller::remote_args`.
+    #[arg(skip)]
+    files: Vec<(&'static str, PathBuf)>,
+}
+
+impl ControllerConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.controller
+    }
+
+    pub fn instance(&self) -> Option<&str> {
+        self.controller_instance.as_deref()
+    }
+
+    pub fn instance_type(&self) -> &str {
+        &self.controller_instance_type
+    }
+
+    pub fn instance_profile(&self) -> Option<&str> {
+        self.controller_instance_profile.as_deref()
+    }
+
+    pub fn russula_private_ips(&self) -> bool {
+        self.russula_private_ips
+    }
+
+    pub fn files(&self) -> &[(&'static str, PathBuf)] {
+        &self.files
+    }
+
+    pub fn set_files(&mut self, files: Vec<(&'static str, PathBuf)>) {
+        self.files = files;
+    }
+
+    // The local driver sources are uploaded by the local orchestrator, which
+    // doesn't run the scenario in controller mode
+    fn validate(&self, driver_registry: &DriverRegistry) -> OrchResult<()> {
+        if !self.controller {
+            return Ok(());
+        }
+        if self.controller_instance.is_none() && self.controller_instance_profile.is_none() {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "--controller requires --controller-instance-profile to launch the controller, or --controller-instance",
+            ));
+        }
+        let local_drivers = driver_registry.local_drivers();
+        if !local_drivers.is_empty() {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                format!(
+                    "--controller doesn't support drivers with a local source: {}",
+                    local_drivers.join(", ")
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Launching the host groups with EC2 Fleet
+//
+// A fleet launches all the hosts of a group in a single request, falling back
+// to other instance types (and with `--fleet-any-az`, other AZs) when the
+// preferred ones are out of capacity, rather than failing part way through a
+// large launch. The hosts of a fleet are launched together or not at all.
+#[derive(Clone, Debug, Default, Args)]
+pub struct FleetConfig {
+    /// Launch each host group with an EC2 Fleet in instant mode, instead of
+    /// one instance at a time
+    #[arg(long)]
+    fleet: bool,
+
+    /// Instance types the fleet falls back to when the hosts' instance type
+    /// has no capacity, in order of preference
+    ///
+    /// The preference is the priority of the fleet's overrides. The report
+    /// shows the instance type of each host.
+    #[arg(long, value_delimiter = ',', requires = "fleet")]
+    fleet_fallback_instance_type: Vec<String>,
+
+    /// Let the fleet launch hosts without a cluster placement in any AZ of the
+    /// VPC, preferring the hosts' AZs
+    ///
+    /// The hosts with a cluster placement stay in the placement group of their
+    /// AZ.
+    #[arg(long, requires = "fleet")]
+    fleet_any_az: bool,
+}
+
+impl FleetConfig {
+    // A fleet for each host group, eg. for the end-to-end tests
+    #[cfg(test)]
+    pub fn testing(fallback_instance_types: &[&str]) -> Self {
+        FleetConfig {
+            fleet: true,
+            fleet_fallback_instance_type: fallback_instance_types
+                .iter()
+                .map(|instance_type| instance_type.to_string())
+                .collect(),
+            fleet_any_az: false,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.fleet
+    }
+
+    pub fn fallback_instance_types(&self) -> &[String] {
+        &self.fleet_fallback_instance_type
+    }
+
+    pub fn any_az(&self) -> bool {
+        self.fleet_any_az
+    }
+}
+
+// Profiling the drivers while they run
+//
+// The workers of the profiled host groups attach the profiler to their
+// drivers, and write a flamegraph of each driver which is uploaded with its
+// results. The report links the flamegraphs from `flamegraphs.html`.
+#[derive(Clone, Debug, Default, Args)]
+pub struct ProfileConfig {
+    /// Profile the drivers while they run, with `perf record` or a bpftrace
+    /// script, and upload a flamegraph of each driver with its results
+    ///
+    /// Named `--profiler` since `--profile` selects the AWS profile.
+    #[arg(long, value_enum)]
+    profiler: Option<Profiler>,
+
+    /// The host groups whose drivers are profiled (eg. "server"). Defaults to
+    /// both.
+    #[arg(long, value_enum, value_delimiter = ',', requires = "profiler")]
+    profile_hosts: Vec<EndpointType>,
+
+    /// The sampling frequency of the profiler, in Hz
+    #[arg(long, default_value_t = 99, value_parser = clap::value_parser!(u32).range(1..))]
+    profile_frequency: u32,
+}
+
+impl ProfileConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    fn is_profiled(&self, endpoint_type: EndpointType) -> bool {
+        self.is_enabled()
+            && (self.profile_hosts.is_empty() || self.profile_hosts.contains(&endpoint_type))
+    }
+
+    // The flags of the russula workers of the host group, which attach the
+    // profiler to their drivers
+    pub fn worker_args(&self, endpoint_type: EndpointType) -> String {
+        match self.profiler {
+            Some(profiler) if self.is_profiled(endpoint_type) => format!(
+                " --profiler {profiler} --profile-frequency {}",
+                self.profile_frequency
+            ),
+            _ => String::new(),
+        }
+    }
+}
+
+// Capturing the drivers' packets while they run
+//
+// The workers of the captured host groups run tcpdump on the Netbench ports
+// during the measured run, into a ring buffer which is limited in size and
+// duration. The pcaps are uploaded under `captures/`, out of the results tree.
+#[derive(Clone, Debug, Default, Args)]
+pub struct CaptureConfig {
+    /// Capture the drivers' packets with tcpdump while they run, and upload
+    /// the pcaps with the results
+    #[arg(long)]
+    capture: bool,
+
+    /// The host groups whose packets are captured (eg. "client"). Defaults to
+    /// both.
+    #[arg(long, value_enum, value_delimiter = ',', requires = "capture")]
+    capture_hosts: Vec<EndpointType>,
+
+    /// The bytes captured of each packet
+    ///
+    /// The default only covers the headers. 0 captures whole packets.
+    #[arg(long, default_value_t = 128)]
+    capture_snaplen: u32,
+
+    /// The size of each file of the capture's ring buffer, in MB
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    capture_file_size: u32,
+
+    /// The files of the capture's ring buffer. Once they're full, the oldest
+    /// file is overwritten.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    capture_files: u32,
+
+    /// Stop capturing after this long, even if the drivers are still running
+    /// (eg. "10m")
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    capture_max_duration: Duration,
+}
+
+impl CaptureConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.capture
+    }
+
+    fn is_captured(&self, endpoint_type: EndpointType) -> bool {
+        self.is_enabled()
+            && (self.capture_hosts.is_empty() || self.capture_hosts.contains(&endpoint_type))
+    }
+
+    // The most bytes captured of each driver of the host group, which fill
+    // the capture's ring buffer
+    pub fn max_bytes(&self, endpoint_type: EndpointType) -> u64 {
+        if !self.is_captured(endpoint_type) {
+            return 0;
+        }
+        self.capture_files as u64 * self.capture_file_size as u64 * 1_000_000
+    }
+
+    // The flags of the russula workers of the host group, which capture the
+    // drivers' packets
+    pub fn worker_args(&self, endpoint_type: EndpointType) -> String {
+        if !self.is_captured(endpoint_type) {
+            return String::new();
+        }
+        format!(
+            " --capture --capture-snaplen {} --capture-file-size {} --capture-files {} --capture-max-secs {}",
+            self.capture_snaplen,
+            self.capture_file_size,
+            self.capture_files,
+            self.capture_max_duration.as_secs().max(1)
+        )
+    }
+}
+
+// A size in bytes, KB, MB or GB, eg. "64MB"
+//
+// The size is set with a shell command on the remote hosts, so nothing else is
+// accepted.
+fn parse_s3_size(value: &str) -> Result<String, String> {
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &value[digits.len()..];
+    if digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+        || !["", "KB", "MB", "GB"].contains(&unit)
+    {
+        return Err(format!("expected a size such as 64MB, got {value}"));
+    }
+    Ok(value.to_string())
+}
+
+impl S3TransferConfig {
+    pub fn transfer_acceleration(&self) -> bool {
+        self.s3_transfer_acceleration
+    }
+
+    pub fn local_aws_config(&self) -> Option<&PathBuf> {
+        self.local_aws_config.as_ref()
+    }
+
+    // Settings for the `s3` section of the aws cli config
+    fn settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = Vec::new();
+        if self.s3_transfer_acceleration {
+            settings.push(("s3.use_accelerate_endpoint", "true".to_string()));
+        }
+        if let Some(chunksize) = &self.s3_multipart_chunksize {
+            settings.push(("s3.multipart_chunksize", chunksize.clone()));
+        }
+        if let Some(max_concurrent_requests) = self.s3_max_concurrent_requests {
+            settings.push((
+                "s3.max_concurrent_requests",
+                max_concurrent_requests.to_string(),
+            ));
+        }
+        settings
+    }
+
+    // Set of commands that configure the `aws` cli on remote hosts via SSM.
+    //
+    // The remote hosts are ephemeral so its fine to modify the default config.
+    pub fn ssm_configure_cmds(&self) -> Vec<String> {
+        self.settings()
+            .into_iter()
+            .map(|(key, value)| format!("aws configure set default.{key} {value}"))
+            .collect()
+    }
+
+    // Write a copy of the user's aws config with the s3 settings applied.
+    fn write_local_aws_config(&mut self) -> OrchResult<()> {
+        let settings = self.settings();
+        if settings.is_empty() {
+            return Ok(());
+        }
+
+        let local_aws_config = paths::local_aws_config();
+        let user_aws_config = std::env::var_os("AWS_CONFIG_FILE")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".aws/config"))
+            });
+        match user_aws_config {
+            Some(user_aws_config) if user_aws_config.exists() => {
+                std::fs::copy(user_aws_config, &local_aws_config)
+            }
+            _ => std::fs::write(&local_aws_config, "").map(|_| 0),
+        }
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Init, "Failed to create local aws config").with_source(err)
+        })?;
+
+        for (key, value) in settings {
+            let status = Command::new("aws")
+                .env("AWS_CONFIG_FILE", &local_aws_config)
+                .args(["configure", "set", key, &value])
+                .status()
+                .map_err(|err| {
+                    OrchError::new(ErrorKind::Init, format!("Failed to set aws config {key}"))
+                        .with_source(err)
+                })?;
+            if !status.success() {
+                return Err(OrchError::new(
+                    ErrorKind::Init,
+                    format!("Failed to set aws config {key}={value}"),
+                ));
+            }
+        }
+
+        self.local_aws_config = Some(local_aws_config);
+        Ok(())
+    }
+}
+
+// The credentials and region of the AWS clients, which default to the default
+// credential chain and the region of the cdk stack
+#[derive(Clone, Debug, Default, Args)]
+pub struct AwsAccessConfig {
+    /// Named profile of the AWS config to load the credentials from, eg. an
+    /// SSO profile (`aws sso login --profile <profile>`)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Region of the AWS clients, instead of the profile's region or the
+    /// region of the cdk stack
+    #[arg(long, global = true)]
+    region: Option<String>,
+
+    /// ARN of a role to assume with the loaded credentials, eg. the role
+    /// allowed to run netbench in the account of the cdk stack
+    #[arg(long, global = true)]
+    assume_role: Option<String>,
+
+    /// Endpoint of an AWS service instead of the region's public endpoint,
+    /// eg. a VPC interface endpoint ("ssm=https://vpce-1234.ssm.us-west-2.vpce.amazonaws.com")
+    ///
+    /// Can be given for each of the services ec2, ssm, s3, iam, sts, logs,
+    /// cloudwatch, sesv2, service-quotas and cloudfront. The s3 endpoint is
+    /// also used by the hosts.
+    #[arg(long = "endpoint-url", global = true, value_parser = parse_endpoint_url)]
+    endpoint_urls: Vec<(String, String)>,
+}
+
+// The services whose endpoint can be overridden, with the suffix of their
+// `AWS_ENDPOINT_URL_<SERVICE>` variable
+const ENDPOINT_SERVICES: &[(&str, &str)] = &[
+    ("ec2", "EC2"),
+    ("ssm", "SSM"),
+    ("s3", "S3"),
+    ("iam", "IAM"),
+    ("sts", "STS"),
+    ("logs", "CLOUDWATCH_LOGS"),
+    ("cloudwatch", "CLOUDWATCH"),
+    ("sesv2", "SESV2"),
+    ("service-quotas", "SERVICE_QUOTAS"),
+    ("cloudfront", "CLOUDFRONT"),
+];
+
+fn parse_endpoint_url(value: &str) -> Result<(String, String), String> {
+    let (service, url) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <service>=<url>, got {value}"))?;
+    let Some((_, variable)) = ENDPOINT_SERVICES.iter().find(|(name, _)| *name == service) else {
+        let services: Vec<_> = ENDPOINT_SERVICES.iter().map(|(name, _)| *name).collect();
+        return Err(format!(
+            "unknown service {service}, expected one of {}",
+            services.join(", ")
+        ));
+    };
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("expected the url of the endpoint, got {url}"));
+    }
+    Ok((format!("AWS_ENDPOINT_URL_{variable}"), url.to_string()))
+}
+
+// The endpoint overrides of the AWS services, as their
+// `AWS_ENDPOINT_URL_<SERVICE>` variables
+//
+// The overrides are the service config of the `SdkConfig`, so that every
+// client built from it uses them, and are passed to the local `aws` cli
+// commands (v2.13 or later) by `AwsCli`. The other services and settings
+// are loaded from the environment.
+#[derive(Clone, Debug, Default)]
+pub struct AwsEndpoints(Vec<(String, String)>);
+
+impl LoadServiceConfig for AwsEndpoints {
+    fn load_config(&self, key: ServiceConfigKey<'_>) -> Option<String> {
+        let url = self
+            .0
+            .iter()
+            .find(|(variable, _)| key.profile() == "endpoint_url" && variable == key.env());
+        match url {
+            Some((_, url)) => Some(url.clone()),
+            None => std::env::var(key.env()).ok(),
+        }
+    }
+}
+
+impl AwsEndpoints {
+    // The endpoints of the services in the config, including the ones of
+    // the environment
+    pub fn of(aws_config: &aws_types::SdkConfig) -> Self {
+        let Some(service_config) = aws_config.service_config() else {
+            return AwsEndpoints::default();
+        };
+        let endpoints = ENDPOINT_SERVICES
+            .iter()
+            .filter_map(|(service, suffix)| {
+                let variable = format!("AWS_ENDPOINT_URL_{suffix}");
+                let key = ServiceConfigKey::builder()
+                    .service_id(service)
+                    .env(&variable)
+                    .profile("endpoint_url")
+                    .build()
+                    .ok()?;
+                let url = service_config.load_config(key)?;
+                Some((variable, url))
+            })
+            .collect();
+        AwsEndpoints(endpoints)
+    }
+
+    // The variables of the endpoints, for the local `aws` cli commands
+    pub fn variables(&self) -> &[(String, String)] {
+        &self.0
+    }
+
+    // The commands which export the s3 endpoint for the `aws s3` commands run
+    // on the hosts. The hosts reach the other services through the SSM agent.
+    pub fn host_cmds(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(variable, _)| variable == "AWS_ENDPOINT_URL_S3")
+            .map(|(variable, url)| format!("export {variable}={url}"))
+            .collect()
+    }
+}
+
+impl AwsAccessConfig {
+    pub fn endpoints(&self) -> AwsEndpoints {
+        AwsEndpoints(self.endpoint_urls.clone())
+    }
+
+    // Loads the config of the AWS clients.
+    //
+    // The region is the `--region`, the region of the `--profile` or the
+    // region of the cdk stack, in that order. The local `aws` cli commands are
+    // given the region, credentials and endpoints of the config by `AwsCli`.
+    pub async fn load(&self, cdk_config: &CdkConfig) -> OrchResult<aws_types::SdkConfig> {
+        let mut region = RegionProviderChain::first_try(self.region.clone().map(Region::new));
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(profile) = &self.profile {
+            region = region.or_else(
+                ProfileFileRegionProvider::builder()
+                    .profile_name(profile)
+                    .build(),
+            );
+            loader = loader.profile_name(profile);
+        }
+        let region = region.or_else(Region::new(cdk_config.netbench_primary_region().clone()));
+        let mut aws_config = loader.region(region).load().await;
+        // before assuming the role, so that the sts endpoint is used for it
+        if !self.endpoint_urls.is_empty() {
+            aws_config = aws_config
+                .into_builder()
+                .service_config(self.endpoints())
+                .build();
+        }
+
+        let Some(role_arn) = &self.assume_role else {
+            return Ok(aws_config);
+        };
+        let provider = AssumeRoleProvider::builder(role_arn)
+            .session_name("netbench-orchestrator")
+            .configure(&aws_config)
+            .build()
+            .await;
+        // fail early if the role can't be assumed
+        provider.provide_credentials().await.map_err(|err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Failed to assume the role {role_arn}"),
+            )
+            .with_source(err)
+        })?;
+        Ok(aws_config
+            .into_builder()
+            .credentials_provider(SharedCredentialsProvider::new(provider))
+            .build())
+    }
+}
+
+// Checks that the AWS clients act on the cdk stack's account and region, since
+// the stack's buckets, subnets and instance profile are looked up in them.
+// Returns the arn of the caller.
+async fn check_identity(
+    aws_config: &aws_types::SdkConfig,
+    cdk_config: &CdkConfig,
+) -> OrchResult<String> {
+    let identity = aws_sdk_sts::Client::new(aws_config)
+        .get_caller_identity()
+        .send()
+        .await
+        .map_err(|err| {
+            OrchError::sdk(
+                ErrorKind::Init,
+                "Missing AWS credentials. For an SSO profile, run `aws sso login --profile <profile>`",
+                err,
+            )
+        })?;
+    let account = identity.account().unwrap_or_default();
+    let region = aws_config
+        .region()
+        .map(|region| region.as_ref())
+        .unwrap_or_default();
+    info!(account, arn = identity.arn(), region, "AWS identity");
+
+    if let Some(stack_account) = cdk_config.netbench_account() {
+        if account != stack_account {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                format!(
+                    "The AWS credentials are for the account {account}, but the cdk stack is in {stack_account}. Use `--profile` or `--assume-role` to select the account"
+                ),
+            ));
+        }
+    }
+    let stack_region = cdk_config.netbench_primary_region();
+    if region != stack_region {
+        return Err(OrchError::new(
+            ErrorKind::Init,
+            format!(
+                "The AWS region is {region}, but the cdk stack is in {stack_region}. Use `--region` to select the region"
+            ),
+        ));
+    }
+    Ok(identity.arn().unwrap_or_default().to_string())
 }
 
 // Used for parsing the scenario file generated by the s2n-netbench project
@@ -295,6 +1490,17 @@ struct CdkResources {
     output_netbench_runner_private_src_bucket: String,
     // CloudFront stack which serves the netbench reports
     output_netbench_cloudfront_distribution: String,
+    // CloudFront distribution id used to invalidate cached reports
+    //
+    // Optional since older cdk deployments don't emit this value.
+    #[serde(default)]
+    output_netbench_cloudfront_distribution_id: Option<String>,
+    // Whether the reports in the public bucket are served publicly
+    //
+    // Optional since the cdk stack, whose reports are served by CloudFront,
+    // doesn't emit this value. Written as false by `bootstrap`.
+    #[serde(default)]
+    output_netbench_public_reports: Option<bool>,
     // EC2 instance profile for orchestrator hosts
     output_netbench_runner_instance_profile: String,
     // Subnet tag/key values
@@ -306,9 +1512,26 @@ struct CdkResources {
     output_netbench_subnet_tag_value: String,
     // AWS region where the netbench orchestrator will run
     output_netbench_infra_primary_prod_region: String,
+    // AWS account the stack is deployed to
+    //
+    // Optional since older cdk deployments don't emit this value.
+    #[serde(default)]
+    output_netbench_infra_primary_prod_account: Option<String>,
 }
 
 impl CdkConfig {
+    // Distinct buckets, so that tests can tell where the objects are uploaded
+    #[cfg(test)]
+    pub fn testing() -> Self {
+        CdkConfig {
+            resources: CdkResources {
+                output_netbench_runner_public_logs_bucket: "netbench-public".to_string(),
+                output_netbench_runner_private_src_bucket: "netbench-private".to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
     pub fn netbench_runner_public_s3_bucket(&self) -> &String {
         &self.resources.output_netbench_runner_public_logs_bucket
     }
@@ -321,6 +1544,18 @@ impl CdkConfig {
         &self.resources.output_netbench_cloudfront_distribution
     }
 
+    pub fn netbench_cloudfront_distribution_id(&self) -> Option<&String> {
+        self.resources
+            .output_netbench_cloudfront_distribution_id
+            .as_ref()
+    }
+
+    pub fn netbench_public_reports(&self) -> bool {
+        self.resources
+            .output_netbench_public_reports
+            .unwrap_or(true)
+    }
+
     pub fn netbench_runner_log_group(&self) -> &String {
         &self.resources.output_netbench_runner_log_group
     }
@@ -343,10 +1578,19 @@ impl CdkConfig {
         &self.resources.output_netbench_infra_primary_prod_region
     }
 
+    pub fn netbench_account(&self) -> Option<&String> {
+        self.resources
+            .output_netbench_infra_primary_prod_account
+            .as_ref()
+    }
+
     pub fn from_file(cdk_config_file: &PathBuf) -> OrchResult<Self> {
         let path = Path::new(&cdk_config_file);
-        let cdk_config_file = File::open(path).map_err(|_err| OrchError::Init {
-            dbg: format!("Scenario file not found: {:?}", path),
+        let cdk_config_file = File::open(path).map_err(|_err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Scenario file not found: {:?}", path),
+            )
         })?;
         let config: CdkConfig = serde_json::from_reader(cdk_config_file).unwrap();
         Ok(config)
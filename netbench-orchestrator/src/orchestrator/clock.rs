@@ -0,0 +1,231 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::S3Api,
+    compute::ComputeProvider,
+    ec2_utils::InfraDetail,
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig, RunPaths},
+    s3_utils::upload_object,
+    ssm_utils::Step,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, instrument, warn};
+
+// How often the hosts are polled for the output of the clock check. The
+// orchestrator's skew is only known to within the time it takes to see the
+// output, so this is much shorter than the `poll_delay_ssm` setting.
+const POLL_DELAY: Duration = Duration::from_millis(500);
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+// The clock of a host as reported by the clock check command
+#[derive(Debug, PartialEq)]
+struct HostClock {
+    // The host's offset from NTP time according to chrony
+    ntp_offset_ms: f64,
+    // chrony's leap status is "Normal" once the clock is synchronised
+    synchronised: bool,
+    // The host's time when the command ran
+    epoch_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct HostSkew {
+    endpoint: String,
+    instance_id: String,
+    ntp_offset_ms: f64,
+    synchronised: bool,
+    // The skew from the orchestrator's clock. The orchestrator only knows the
+    // host's time to within `uncertainty_ms`, so this is a lower bound and is
+    // 0 when the host's time falls within the uncertainty.
+    orchestrator_skew_ms: i64,
+    uncertainty_ms: u64,
+}
+
+// Compares the clocks of the hosts with each other and with the orchestrator.
+//
+// The client and server results are correlated by their timestamps in the
+// report, so a skew between the hosts silently shifts one timeline against the
+// other. The hosts' offsets from NTP time are reported by chrony, so the skew
+// between the hosts is precise, while the skew from the orchestrator is
+// bounded by the round trip of the command.
+//
+// Warns when the skew exceeds `--max-clock-skew`, and uploads the clocks as
+// `clock.json` to the root of the results tree for the report.
+#[instrument(skip_all)]
+pub async fn check_clock_skew(
+    compute: &impl ComputeProvider,
+    s3_client: &impl S3Api,
+    infra: &InfraDetail,
+    unique_id: &str,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    let instances: Vec<_> = infra.servers.iter().chain(infra.clients.iter()).collect();
+    let instance_ids = instances
+        .iter()
+        .map(|instance| instance.instance_id().to_string())
+        .collect();
+
+    // reports the host's offset from NTP time according to chrony, followed by
+    // the host's time in milliseconds since the epoch
+    let sent = SystemTime::now();
+    let command_id = compute
+        .execute(
+            Step::CheckClock,
+            "check_clock",
+            instance_ids,
+            vec!["chronyc -c tracking".to_string(), "date +%s%3N".to_string()],
+            config,
+        )
+        .await?;
+
+    let mut hosts = Vec::with_capacity(instances.len());
+    for instance in instances {
+        let instance_id = instance.instance_id();
+        let output = loop {
+            if let Some(output) = compute.output(&command_id, instance_id).await? {
+                break output;
+            }
+            if sent.elapsed().unwrap_or_default() > TIMEOUT {
+                return Err(OrchError::new(
+                    ErrorKind::Ssm,
+                    format!("Timed out checking the clock of {instance_id}"),
+                ));
+            }
+            tokio::time::sleep(POLL_DELAY).await;
+        };
+        let received = SystemTime::now();
+        debug!(host = instance_id, "{output}");
+
+        let clock = parse_clock(&output).ok_or_else(|| {
+            OrchError::new(
+                ErrorKind::Ssm,
+                format!("Failed to parse the clock of {instance_id}: {output}"),
+            )
+        })?;
+        let (orchestrator_skew_ms, uncertainty_ms) = orchestrator_skew(&clock, sent, received);
+        hosts.push(HostSkew {
+            endpoint: instance.endpoint_type().as_str().to_lowercase(),
+            instance_id: instance_id.to_string(),
+            ntp_offset_ms: clock.ntp_offset_ms,
+            synchronised: clock.synchronised,
+            orchestrator_skew_ms,
+            uncertainty_ms,
+        });
+    }
+
+    let max_skew = config.run.max_clock_skew();
+    let max_skew_ms = max_skew.as_secs_f64() * 1000.0;
+    let host_skew_ms = host_skew_ms(&hosts);
+    info!("Clock skew between hosts: {host_skew_ms:.3}ms");
+    if host_skew_ms > max_skew_ms {
+        warn!(
+            "The clocks of the hosts are {host_skew_ms:.3}ms apart, more than {:?}. The client and server timelines in the report will be misaligned.",
+            max_skew
+        );
+    }
+    for host in &hosts {
+        if !host.synchronised {
+            warn!(
+                host = host.instance_id,
+                "The host's clock isn't synchronised"
+            );
+        }
+        if host.orchestrator_skew_ms.unsigned_abs() as f64 > max_skew_ms {
+            warn!(
+                host = host.instance_id,
+                "The host's clock is at least {}ms off the orchestrator's clock",
+                host.orchestrator_skew_ms
+            );
+        }
+    }
+
+    let body = serde_json::to_vec_pretty(&serde_json::json!({
+        "max_skew_ms": max_skew_ms,
+        "host_skew_ms": host_skew_ms,
+        "hosts": hosts,
+    }))
+    .expect("failed to serialize the host clocks");
+    upload_object(
+        s3_client,
+        config.results_bucket(),
+        ByteStream::from(Bytes::from(body)),
+        &RunPaths::new(unique_id).results_key("clock.json"),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// Parses the output of `chronyc -c tracking` followed by `date +%s%3N`.
+//
+// The 5th field of the csv is the system time offset in seconds and the last
+// is the leap status.
+fn parse_clock(output: &str) -> Option<HostClock> {
+    let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+    let tracking: Vec<&str> = lines.next()?.split(',').collect();
+    let epoch_ms = lines.next()?.trim().parse().ok()?;
+    let ntp_offset: f64 = tracking.get(4)?.parse().ok()?;
+    let synchronised = tracking.last()?.trim() == "Normal";
+    Some(HostClock {
+        ntp_offset_ms: ntp_offset * 1000.0,
+        synchronised,
+        epoch_ms,
+    })
+}
+
+// The host's time must be between when the command was sent and when its
+// output was received, if the clocks agree.
+fn orchestrator_skew(clock: &HostClock, sent: SystemTime, received: SystemTime) -> (i64, u64) {
+    let epoch_ms = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64
+    };
+    let (sent, received) = (epoch_ms(sent), epoch_ms(received));
+    let host = clock.epoch_ms as i64;
+    let skew = if host < sent {
+        host - sent
+    } else if host > received {
+        host - received
+    } else {
+        0
+    };
+    (skew, (received - sent) as u64)
+}
+
+// The largest difference between the hosts' offsets from NTP time
+fn host_skew_ms(hosts: &[HostSkew]) -> f64 {
+    let offsets = || hosts.iter().map(|host| host.ntp_offset_ms);
+    match (offsets().reduce(f64::max), offsets().reduce(f64::min)) {
+        (Some(max), Some(min)) => max - min,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_output() {
+        let output = "\
+A9FEA97B,169.254.169.123,3,1700000000.123456789,-0.000250000,0.000001,0.000012,-2.345,0.001,0.010,0.000123,0.000456,64.2,Normal
+1700000000500
+";
+        let clock = parse_clock(output).unwrap();
+        assert!((clock.ntp_offset_ms + 0.25).abs() < 1e-9);
+        assert!(clock.synchronised);
+        assert_eq!(clock.epoch_ms, 1_700_000_000_500);
+        assert_eq!(parse_clock("506 Cannot talk to daemon\n"), None);
+
+        let sent = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+        let received = sent + Duration::from_secs(1);
+        assert_eq!(orchestrator_skew(&clock, sent, received), (0, 1000));
+        let late = sent - Duration::from_secs(2);
+        assert_eq!(orchestrator_skew(&clock, late, late), (2500, 0));
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::SdkClients,
+    ec2_utils::Controller,
+    orchestrator::{
+        attach::{Followed, RunFollower},
+        state, ErrorKind, OrchError, OrchResult, OrchestratorConfig, RunId, RunPaths,
+    },
+    progress,
+    s3_utils::{download_object, upload_object},
+    ssm_utils::{self, common::wait_complete, Step},
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use core::time::Duration;
+use std::path::Path;
+use tracing::warn;
+
+// How often the run is polled while the controller runs it
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(10);
+// The consecutive failed polls after which the run is no longer followed,
+// ie. about 10 minutes of failures
+const MAX_FAILED_POLLS: u32 = 60;
+const RUN_SCRIPT: &str = "run.sh";
+const EXIT_CODE: &str = "exit_code";
+
+// The flags which only apply to the local orchestrator, and whether they take
+// a value. The files are staged and passed again, see `remote_args`. The
+// controller uses the credentials of its instance profile.
+const LOCAL_FLAGS: &[(&str, bool)] = &[
+    ("profile", true),
+    ("region", true),
+    ("assume-role", true),
+    ("controller", false),
+    ("controller-instance", true),
+    ("controller-instance-type", true),
+    ("controller-instance-profile", true),
+    ("tui", false),
+    ("status-port", true),
+    ("run-id-git-dir", true),
+    ("cdk-config-file", true),
+    ("netbench-scenario-file", true),
+    ("driver-registry-file", true),
+    ("settings-file", true),
+    ("dashboard-template", true),
+];
+
+// Runs the scenario with the orchestrator on a controller instance in the
+// hosts' VPC, which connects to the Russula workers over their private ips.
+//
+// The files read by the run are staged in the private bucket, from where the
+// controller copies them before starting the orchestrator with the same
+// arguments and unique id. The orchestrator is built on the controller, from
+// the same repository as Russula on the hosts. The run is then followed
+// locally as with `attach`, until the controller uploads the orchestrator's
+// exit code.
+pub async fn run_on_controller(
+    run_id: &RunId,
+    config: &OrchestratorConfig,
+    aws_config: &aws_types::SdkConfig,
+) -> OrchResult<()> {
+    let clients = SdkClients::new(aws_config, config);
+    let unique_id = run_id.as_str();
+    let paths = RunPaths::new(unique_id);
+    let bucket = config.cdk_config.netbench_runner_private_s3_bucket();
+
+    // the arguments of this orchestrator, without the executable
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = remote_args(&args, config.controller.files(), unique_id);
+    for (flag, path) in config.controller.files() {
+        let body = std::fs::read(path).map_err(|err| {
+            OrchError::new(ErrorKind::Init, format!("Failed to read {:?}", path)).with_source(err)
+        })?;
+        let key = paths.controller_key(&staged_path(flag, path));
+        upload_object(
+            &clients.s3,
+            bucket,
+            ByteStream::from(Bytes::from(body)),
+            &key,
+        )
+        .await?;
+    }
+
+    let controller =
+        Controller::provision(&clients.ec2, &clients.iam, &clients.ssm, config, unique_id).await?;
+    progress::output!("Controller: {}", controller.instance_id());
+
+    // a launched controller terminates itself once the run is done, in case
+    // the local orchestrator is interrupted
+    let script = run_script(
+        &args,
+        &format!("s3://{bucket}/{}", paths.controller_key(EXIT_CODE)),
+        config.controller.instance().is_none(),
+    );
+    let started = async {
+        upload_object(
+            &clients.s3,
+            bucket,
+            ByteStream::from(Bytes::from(script)),
+            &paths.controller_key(RUN_SCRIPT),
+        )
+        .await?;
+        start(&clients, &controller, config, unique_id).await
+    }
+    .await;
+    if let Err(err) = started {
+        cleanup(&clients, &controller).await;
+        return Err(err);
+    }
+
+    // the controller is only deleted once the orchestrator on it exited, since
+    // it would otherwise leave the hosts it launched and the run unfinished
+    let exit_code = match follow(&clients, config, unique_id).await {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            progress::output!(
+                "Controller: {} is left running the orchestrator, follow the run with `attach --unique-id {unique_id}`",
+                controller.instance_id()
+            );
+            return Err(err);
+        }
+    };
+    cleanup(&clients, &controller).await;
+    exit_result(&exit_code)
+}
+
+async fn cleanup(clients: &SdkClients, controller: &Controller) {
+    if let Err(err) = controller.cleanup(&clients.ec2).await {
+        warn!("Failed to cleanup the controller. {err}");
+    }
+}
+
+// Builds the orchestrator and the netbench cli on the controller, unless
+// they're already built (eg. on a `--controller-instance`), and starts the
+// run in the background, so it isn't bound by the timeout of the SSM command.
+async fn start(
+    clients: &SdkClients,
+    controller: &Controller,
+    config: &OrchestratorConfig,
+    unique_id: &str,
+) -> OrchResult<()> {
+    let bin = state().host_bin_path();
+    let cargo = "/root/.cargo/bin/cargo";
+    let run_dir = format!("{}/controller/{unique_id}", state().host_home_path);
+    let cmd = ssm_utils::send_command(
+        vec![],
+        Step::Controller,
+        "controller",
+        &clients.ssm,
+        vec![controller.instance_id().to_string()],
+        vec![
+            "export HOME=/root".to_string(),
+            format!("mkdir -p {bin}"),
+            "timeout 5m bash -c 'until yum install cmake gcc git perl openssl-devel -y; do sleep 10; done'".to_string(),
+            format!("[ -x {cargo} ] || (curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y)"),
+            format!(
+                "[ -x {bin}/s2n-netbench ] || (rm -rf s2n-netbench && git clone --branch {} {} s2n-netbench && cd s2n-netbench && {cargo} build --release --bin s2n-netbench && cp target/release/s2n-netbench {bin})",
+                state().netbench_branch,
+                state().netbench_repo
+            ),
+            format!(
+                "[ -x {bin}/s2n-netbench-orchestrator ] || (rm -rf netbench_orchestrator && git clone --branch {} {} netbench_orchestrator && cd netbench_orchestrator && env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse {cargo} build --release && cp target/release/s2n-netbench-orchestrator {bin})",
+                state().russula_branch,
+                state().russula_repo
+            ),
+            format!("mkdir -p {run_dir}"),
+            format!("cd {run_dir}"),
+            format!(
+                "aws s3 cp --recursive s3://{}/{} .",
+                config.cdk_config.netbench_runner_private_s3_bucket(),
+                RunPaths::new(unique_id).controller_key("")
+            ),
+            format!("setsid nohup sh {RUN_SCRIPT} > orchestrator.log 2>&1 < /dev/null &"),
+        ],
+        config,
+    )
+    .await?;
+    wait_complete("controller", &clients.ssm, vec![cmd]).await?;
+    progress::output!(
+        "Controller: started the run, its log is {run_dir}/orchestrator.log on the controller"
+    );
+    Ok(())
+}
+
+// Shows the progress of the run until the orchestrator on the controller
+// exits, and returns its exit code. A failed poll (eg. throttling, or an
+// expired session) is retried, and the run is only given up on after
+// `MAX_FAILED_POLLS` consecutive failures.
+async fn follow(
+    clients: &SdkClients,
+    config: &OrchestratorConfig,
+    unique_id: &str,
+) -> OrchResult<String> {
+    let bucket = config.cdk_config.netbench_runner_private_s3_bucket();
+    let exit_key = RunPaths::new(unique_id).controller_key(EXIT_CODE);
+    let mut follower = RunFollower::new(unique_id);
+    let mut waiting = false;
+    let mut failed_polls = 0;
+    loop {
+        let polled = async {
+            let exit_code = download_object(&clients.s3, bucket, &exit_key).await?;
+            // the run has no status or hosts until the orchestrator launched them
+            let followed = follower
+                .update(&clients.ec2, &clients.ssm, &clients.s3, &config.cdk_config)
+                .await?;
+            Ok::<_, OrchError>((exit_code, followed))
+        }
+        .await;
+        match polled {
+            Ok((Some(exit_code), _)) => return Ok(String::from_utf8_lossy(&exit_code).into()),
+            Ok((None, followed)) => {
+                failed_polls = 0;
+                if followed == Followed::Done && !waiting {
+                    progress::output!("Controller: waiting for the orchestrator to exit");
+                    waiting = true;
+                }
+            }
+            Err(err) => {
+                failed_polls += 1;
+                if failed_polls >= MAX_FAILED_POLLS {
+                    return Err(err);
+                }
+                warn!("Failed to poll the run ({failed_polls}/{MAX_FAILED_POLLS}). {err}");
+            }
+        }
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+    }
+}
+
+// The result of the run from the exit code uploaded by the controller
+fn exit_result(exit_code: &str) -> OrchResult<()> {
+    match exit_code.trim().parse::<u8>() {
+        Ok(0) => Ok(()),
+        Ok(code) => Err(OrchError::new(
+            ErrorKind::from_exit_code(code),
+            format!("The orchestrator on the controller failed with exit code {code}"),
+        )),
+        Err(_) => Err(OrchError::new(
+            ErrorKind::S3,
+            format!("Invalid exit code {exit_code:?} from the controller"),
+        )),
+    }
+}
+
+// The arguments of the orchestrator on the controller: the local arguments
+// without the flags which only apply locally, the files staged under the name
+// of their flag, and the run's unique id.
+fn remote_args(
+    args: &[String],
+    files: &[(&'static str, impl AsRef<Path>)],
+    unique_id: &str,
+) -> Vec<String> {
+    let mut remote = Vec::with_capacity(args.len());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = arg.strip_prefix("--").unwrap_or_default();
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, _)) => (name, true),
+            None => (flag, false),
+        };
+        match LOCAL_FLAGS.iter().find(|(local, _)| *local == name) {
+            Some((_, takes_value)) => {
+                if *takes_value && !inline_value {
+                    args.next();
+                }
+            }
+            None => remote.push(arg.clone()),
+        }
+    }
+    for (flag, path) in files {
+        remote.push(format!("--{flag}"));
+        remote.push(staged_path(flag, path.as_ref()));
+    }
+    remote.extend([
+        "--unique-id".to_string(),
+        unique_id.to_string(),
+        "--russula-private-ips".to_string(),
+    ]);
+    remote
+}
+
+// The path of a staged file on the controller, relative to the run's
+// directory, eg. `netbench-scenario-file/request_response.json`. The file name
+// is kept, since the scenario is named after it.
+fn staged_path(flag: &str, path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map_or_else(|| flag.into(), |name| name.to_string_lossy());
+    format!("{flag}/{name}")
+}
+
+// Runs the orchestrator on the controller and uploads its exit code
+fn run_script(args: &[String], exit_code_uri: &str, shutdown: bool) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| format!("'{}'", ssm_utils::quote(arg)))
+        .collect();
+    let mut script = vec![
+        "cd \"$(dirname \"$0\")\"".to_string(),
+        format!("export PATH={}:$PATH", state().host_bin_path()),
+        format!("s2n-netbench-orchestrator {}", args.join(" ")),
+        format!("echo $? > {EXIT_CODE}"),
+        format!("aws s3 cp {EXIT_CODE} {exit_code_uri}"),
+    ];
+    if shutdown {
+        script.push("shutdown -h now".to_string());
+    }
+    script.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn remote_args_stage_the_files() {
+        let args: Vec<String> = [
+            "--controller",
+            "--controller-instance-profile",
+            "netbench-controller",
+            "--netbench-scenario-file=../target/request_response.json",
+            "--client-az",
+            "us-west-2a",
+            "--cdk-config-file",
+            "cdk_config.json",
+            "--tui",
+            "-v",
+            "--label",
+            "main baseline",
+        ]
+        .map(String::from)
+        .to_vec();
+        let files = [
+            ("cdk-config-file", PathBuf::from("cdk_config.json")),
+            (
+                "netbench-scenario-file",
+                PathBuf::from("../target/request_response.json"),
+            ),
+        ];
+
+        assert_eq!(
+            remote_args(&args, &files, "20240101T000000Z-rr-3f9a1c"),
+            [
+                "--client-az",
+                "us-west-2a",
+                "-v",
+                "--label",
+                "main baseline",
+                "--cdk-config-file",
+                "cdk-config-file/cdk_config.json",
+                "--netbench-scenario-file",
+                "netbench-scenario-file/request_response.json",
+                "--unique-id",
+                "20240101T000000Z-rr-3f9a1c",
+                "--russula-private-ips",
+            ]
+        );
+
+        let script = run_script(&args[9..], "s3://private/id/controller/exit_code", true);
+        assert!(script.contains("s2n-netbench-orchestrator '-v' '--label' 'main baseline'\n"));
+        assert!(script.ends_with("shutdown -h now\n"));
+    }
+
+    #[test]
+    fn exit_code_of_the_controller() {
+        assert!(exit_result("0\n").is_ok());
+        assert_eq!(exit_result("6\n").unwrap_err().kind(), ErrorKind::Russula);
+        assert_eq!(exit_result("").unwrap_err().kind(), ErrorKind::S3);
+    }
+}
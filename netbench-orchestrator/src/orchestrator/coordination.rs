@@ -0,0 +1,157 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::S3Api,
+    ec2_utils::EndpointType,
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig, RunPaths},
+    russula::EventRecorder,
+    s3_utils::{download_object, list_keys, upload_object},
+    ssm_utils::NetbenchDriverType,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::debug;
+
+// The Russula events of a coordinator or worker.
+#[derive(Debug, Serialize)]
+struct WorkflowEvents {
+    endpoint: String,
+    #[serde(flatten)]
+    events: EventRecorder,
+}
+
+#[derive(Debug, Serialize)]
+struct PairCoordination {
+    pair: String,
+    // From starting the workers to copying their results
+    wall_ms: u64,
+    // The longest time a client worker spent running netbench
+    measurement_ms: u64,
+    coordinators: Vec<WorkflowEvents>,
+    workers: Vec<WorkflowEvents>,
+
+    // Locate the workers' events in s3
+    #[serde(skip)]
+    server: String,
+    #[serde(skip)]
+    client: String,
+    #[serde(skip)]
+    iteration: Option<u32>,
+}
+
+// The time the Russula coordinators and workers spent in each state, for
+// each driver pair.
+//
+// Uploaded as `coordination.json` to the root of the results tree so that the
+// report can show how much of each pair's wall time was spent coordinating the
+// hosts rather than measuring.
+#[derive(Debug, Default)]
+pub struct CoordinationTimeline {
+    pairs: Vec<PairCoordination>,
+}
+
+impl CoordinationTimeline {
+    pub fn record_pair(
+        &mut self,
+        pair: String,
+        server_driver: &NetbenchDriverType,
+        client_driver: &NetbenchDriverType,
+        iteration: Option<u32>,
+        wall: Duration,
+        coordinators: Vec<(EndpointType, EventRecorder)>,
+    ) {
+        self.pairs.push(PairCoordination {
+            pair,
+            wall_ms: wall.as_millis() as u64,
+            measurement_ms: 0,
+            coordinators: coordinators
+                .into_iter()
+                .map(|(endpoint, events)| WorkflowEvents {
+                    endpoint: endpoint.as_str().to_lowercase(),
+                    events,
+                })
+                .collect(),
+            workers: Vec::new(),
+            server: server_driver.results_name(),
+            client: client_driver.results_name(),
+            iteration,
+        });
+    }
+
+    // Adds the events which the workers uploaded with their results, and
+    // uploads the timeline.
+    pub async fn upload(
+        &mut self,
+        s3_client: &impl S3Api,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<()> {
+        let paths = RunPaths::new(unique_id);
+        let bucket = config.results_bucket();
+        let scenario = config.netbench_scenario_filepath_stem();
+        for pair in self.pairs.iter_mut() {
+            pair.workers.clear();
+            for (endpoint, driver) in [("server", &pair.server), ("client", &pair.client)] {
+                // the server and client of a pair can share a results name
+                let prefix = format!(
+                    "{}{endpoint}-",
+                    paths.driver_coordination_prefix(scenario, driver, pair.iteration)
+                );
+                for key in list_keys(s3_client, bucket, &prefix).await? {
+                    let Some(body) = download_object(s3_client, bucket, &key).await? else {
+                        continue;
+                    };
+                    let recorders: Vec<EventRecorder> =
+                        serde_json::from_slice(&body).map_err(|err| {
+                            OrchError::new(ErrorKind::S3, format!("Failed to parse {key}"))
+                                .with_source(err)
+                        })?;
+                    pair.workers
+                        .extend(recorders.into_iter().map(|events| WorkflowEvents {
+                            endpoint: endpoint.to_string(),
+                            events,
+                        }));
+                }
+            }
+            pair.measurement_ms = measurement_ms(&pair.workers);
+        }
+
+        let body = serde_json::to_vec_pretty(&serde_json::json!({ "pairs": self.pairs }))
+            .expect("failed to serialize coordination events");
+        debug!(
+            "uploading coordination events for {} pairs",
+            self.pairs.len()
+        );
+        upload_object(
+            s3_client,
+            bucket,
+            ByteStream::from(Bytes::from(body)),
+            &paths.results_key("coordination.json"),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+// The client workers run netbench in their `Running*` states. The hosts run
+// concurrently, so the slowest one bounds the measurement.
+fn measurement_ms(workers: &[WorkflowEvents]) -> u64 {
+    workers
+        .iter()
+        .filter(|worker| worker.endpoint == "client")
+        .map(|worker| {
+            worker
+                .events
+                .states()
+                .iter()
+                .filter(|state| state.state.starts_with("Running"))
+                .map(|state| state.duration_ms)
+                .sum::<u64>()
+        })
+        .max()
+        .unwrap_or_default()
+}
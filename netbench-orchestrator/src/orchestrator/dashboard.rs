@@ -2,78 +2,468 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    audit,
+    aws_api::S3Api,
+    cloudwatch_utils,
     ec2_utils::EndpointType,
-    orchestrator::{InfraDetail, OrchResult, OrchestratorConfig},
+    orchestrator::{
+        paths::{INDEX_HTML, REPORT_DIR, STATUS_JSON},
+        runs::RunEntry,
+        ErrorKind, InfraDetail, OrchError, OrchResult, OrchestratorConfig, RunPaths,
+    },
+    progress,
     s3_utils::upload_object,
+    ssm_utils::NetbenchDriverType,
 };
 use aws_sdk_s3::primitives::ByteStream;
 use bytes::Bytes;
-use tracing::info;
+use serde::Serialize;
+use serde_json::json;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tracing::debug;
+
+static DASHBOARD_HTML: &str = include_str!("./dashboard.html");
+
+// Checks that a custom dashboard template is valid before the run starts.
+pub fn check_template(template: &str) -> OrchResult<()> {
+    handlebars::Template::compile(template).map_err(|err| {
+        OrchError::new(ErrorKind::Init, "Invalid dashboard template").with_source(err)
+    })?;
+    Ok(())
+}
 
 pub async fn upload_index_html(
-    s3_client: &aws_sdk_s3::Client,
+    s3_client: &impl S3Api,
     unique_id: &str,
     config: &OrchestratorConfig,
 ) -> OrchResult<()> {
-    let cf_url = config.cf_url(unique_id);
-    let status = format!("{}/index.html", cf_url);
-    let template_server_prefix = format!("{}/server-step-", cf_url);
-    let template_client_prefix = format!("{}/client-step-", cf_url);
-    let template_finished_prefix = format!("{}/finished-step-", cf_url);
-
-    let index_file = std::fs::read_to_string("index.html")
-        .expect("index.html not found")
-        .replace("template_unique_id", unique_id)
-        .replace("template_server_prefix", &template_server_prefix)
-        .replace("template_client_prefix", &template_client_prefix)
-        .replace("template_finished_prefix", &template_finished_prefix);
+    let base_url = dashboard_base_url(config, unique_id);
+    let status = format!("{base_url}/{INDEX_HTML}");
+
+    let index_file = handlebars::Handlebars::new()
+        .render_template(
+            config.dashboard_template.as_deref().unwrap_or(DASHBOARD_HTML),
+            &json!({
+                "unique_id": unique_id,
+                "status_url": format!("{base_url}/{STATUS_JSON}"),
+                "report_url": public_report_url(config, unique_id),
+            }),
+        )
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Init, "Failed to render the dashboard template")
+                .with_source(err)
+        })?;
 
     // Upload to s3
     upload_object(
         s3_client,
-        config.cdk_config.netbench_runner_public_s3_bucket(),
+        config.results_bucket(),
         ByteStream::from(Bytes::from(index_file)),
-        &format!("{unique_id}/index.html"),
+        &RunPaths::new(unique_id).key(INDEX_HTML),
     )
     .await?;
 
-    println!("Status: URL: {status}");
-    info!("Status: URL: {status}");
+    progress::output!("Status: URL: {status}");
 
     Ok(())
 }
 
-pub async fn update_instance_running(
-    s3_client: &aws_sdk_s3::Client,
-    infra: &InfraDetail,
-    unique_id: &str,
-    config: &OrchestratorConfig,
+// The dashboard of a private run is kept with its results in the private
+// bucket, which isn't served by the CloudFront distribution.
+fn dashboard_base_url(config: &OrchestratorConfig, unique_id: &str) -> String {
+    match config.report.is_private() {
+        true => config.s3_path(unique_id),
+        false => config.cf_url(unique_id),
+    }
+}
+
+// A private report is shared with a presigned link once it's generated, so it's
+// never linked from the dashboard.
+fn public_report_url(config: &OrchestratorConfig, unique_id: &str) -> Option<String> {
+    (!config.report.is_private())
+        .then(|| format!("{}/{REPORT_DIR}/{INDEX_HTML}", config.cf_url(unique_id)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunPhase {
+    Launching,
+    Configuring,
+    Running,
+    Reporting,
+    Finished,
+    Failed,
+}
+
+impl RunPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunPhase::Launching => "launching",
+            RunPhase::Configuring => "configuring",
+            RunPhase::Running => "running",
+            RunPhase::Reporting => "reporting",
+            RunPhase::Finished => "finished",
+            RunPhase::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostState {
+    Launched,
+    Configuring,
+    Ready,
+    Running,
+    CopyingResults,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairState {
+    Pending,
+    Running,
+    CopyingResults,
+    Done,
+    // Aborted from the TUI
+    Aborted,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HostStatus {
+    pub endpoint: String,
+    pub instance_id: String,
+    pub public_ip: String,
+    pub private_ip: String,
+    pub state: HostState,
+    // The driver the host is running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>,
+    // The logs of the latest russula worker on the host
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs_url: Option<String>,
+    #[serde(skip)]
     endpoint_type: EndpointType,
-) -> OrchResult<()> {
-    let instances = match endpoint_type {
-        EndpointType::Server => &infra.servers,
-        EndpointType::Client => &infra.clients,
-    };
-
-    let instance_detail = instances
-        .iter()
-        .map(|instance| format!("{} {}", instance.host_ips(), instance.instance_id()))
-        .collect::<Vec<String>>()
-        .join(" - ");
-
-    let payload = ByteStream::from(Bytes::from(format!(
-        "EC2 {:?} instances up: {}",
-        endpoint_type, instance_detail
-    )));
-    upload_object(
-        s3_client,
-        config.cdk_config.netbench_runner_public_s3_bucket(),
-        payload,
-        &format!(
-            "{unique_id}/{}-step-0",
-            endpoint_type.as_str().to_lowercase()
-        ),
-    )
-    .await?;
-    Ok(())
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PairStatus {
+    pub server: String,
+    pub client: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iteration: Option<u32>,
+    pub state: PairState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip)]
+    start: Option<SystemTime>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RunStatus {
+    pub unique_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub phase: RunPhase,
+    pub started: String,
+    pub updated: String,
+    pub hosts: Vec<HostStatus>,
+    pub pairs: Vec<PairStatus>,
+    // The estimated end of the measurements, based on the pairs run so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report_url: Option<String>,
+    // The orchestrator's logs
+    pub logs_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// The live status of a run.
+//
+// Uploaded as `status.json` next to `index.html` whenever the run makes
+// progress. The dashboard polls the document to show the phase of the run,
+// the state of each host and the progress of the driver pairs.
+pub struct Dashboard<S: S3Api> {
+    s3_client: S,
+    bucket: String,
+    url: String,
+    report_url: Option<String>,
+    region: String,
+    log_group: String,
+    status: RunStatus,
+    // Publishes the status to the TUI
+    watch: watch::Sender<RunStatus>,
+}
+
+impl<S: S3Api> Dashboard<S> {
+    pub fn new(s3_client: &S, unique_id: &str, config: &OrchestratorConfig) -> Self {
+        let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let region = config.cdk_config.netbench_primary_region();
+        let log_group = config.cdk_config.netbench_runner_log_group();
+        let status = RunStatus {
+            unique_id: unique_id.to_string(),
+            label: config.run.label().map(str::to_string),
+            description: config.run.description().map(str::to_string),
+            phase: RunPhase::Launching,
+            started: now.clone(),
+            updated: now,
+            hosts: Vec::new(),
+            pairs: Vec::new(),
+            eta: None,
+            report_url: None,
+            logs_url: cloudwatch_utils::log_stream_url(
+                region,
+                log_group,
+                &cloudwatch_utils::orchestrator_log_stream(unique_id),
+            ),
+            error: None,
+        };
+        let (watch, _) = watch::channel(status.clone());
+        Dashboard {
+            s3_client: s3_client.clone(),
+            bucket: config.results_bucket().to_string(),
+            url: format!("{}/{INDEX_HTML}", dashboard_base_url(config, unique_id)),
+            report_url: public_report_url(config, unique_id),
+            region: region.to_string(),
+            log_group: log_group.to_string(),
+            status,
+            watch,
+        }
+    }
+
+    // Receives the status whenever it's uploaded.
+    pub fn subscribe(&self) -> watch::Receiver<RunStatus> {
+        self.watch.subscribe()
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn phase(&self) -> RunPhase {
+        self.status.phase
+    }
+
+    pub async fn set_phase(&mut self, phase: RunPhase) -> OrchResult<()> {
+        audit::record!("run", "set_phase", phase = phase.as_str());
+        self.status.phase = phase;
+        match phase {
+            RunPhase::Configuring => self.set_hosts_state(HostState::Configuring),
+            RunPhase::Running => self.set_hosts_state(HostState::Ready),
+            RunPhase::Finished => self.status.report_url = self.report_url.clone(),
+            _ => {}
+        }
+        self.upload().await
+    }
+
+    // The run as listed in the runs index.
+    pub fn run_entry(&self, config: &OrchestratorConfig) -> RunEntry {
+        RunEntry {
+            unique_id: self.status.unique_id.clone(),
+            label: self.status.label.clone(),
+            description: self.status.description.clone(),
+            date: self.status.started.clone(),
+            scenario: config.netbench_scenario_filename().to_string(),
+            drivers: config.driver_registry.enabled_names(),
+            status: self.status.phase.as_str().to_string(),
+            url: self.url.clone(),
+            report_url: self.status.report_url.clone(),
+        }
+    }
+
+    pub async fn set_failed(&mut self, error: String) -> OrchResult<()> {
+        audit::record!("run", "set_phase", phase = RunPhase::Failed.as_str(), error = %error);
+        self.status.phase = RunPhase::Failed;
+        self.status.error = Some(error);
+        self.upload().await
+    }
+
+    pub async fn set_hosts(&mut self, infra: &InfraDetail) -> OrchResult<()> {
+        self.status.hosts = infra
+            .servers
+            .iter()
+            .chain(infra.clients.iter())
+            .map(|instance| HostStatus {
+                endpoint: instance.endpoint_type().as_str().to_string(),
+                instance_id: instance.instance_id().to_string(),
+                public_ip: instance.host_ips().public_ip().to_string(),
+                private_ip: instance.host_ips().private_ip().to_string(),
+                state: HostState::Launched,
+                driver: None,
+                logs_url: None,
+                endpoint_type: *instance.endpoint_type(),
+            })
+            .collect();
+        self.upload().await
+    }
+
+    // Lists the driver pairs in the order they will run.
+    pub async fn set_pairs<'a>(
+        &mut self,
+        pairs: impl Iterator<Item = (&'a NetbenchDriverType, &'a NetbenchDriverType, Option<u32>)>,
+    ) -> OrchResult<()> {
+        self.status.pairs = pairs
+            .map(|(server, client, iteration)| PairStatus {
+                server: server.results_name(),
+                client: client.results_name(),
+                iteration,
+                state: PairState::Pending,
+                duration_ms: None,
+                start: None,
+            })
+            .collect();
+        self.upload().await
+    }
+
+    pub async fn start_pair(&mut self, pair: usize) -> OrchResult<()> {
+        let status = &mut self.status.pairs[pair];
+        status.state = PairState::Running;
+        status.start = Some(SystemTime::now());
+        let (server, client) = (status.server.clone(), status.client.clone());
+        audit::record!(
+            "run",
+            "start_pair",
+            server = %server,
+            client = %client,
+            iteration = status.iteration
+        );
+
+        for host in self.status.hosts.iter_mut() {
+            host.state = HostState::Running;
+            host.driver = Some(if host.endpoint_type == EndpointType::Server {
+                server.clone()
+            } else {
+                client.clone()
+            });
+        }
+        self.upload().await
+    }
+
+    // Links the hosts of an endpoint to the logs of the russula workers run by
+    // an SSM command.
+    pub async fn set_worker_logs(
+        &mut self,
+        endpoint_type: EndpointType,
+        command_id: &str,
+    ) -> OrchResult<()> {
+        for host in self.status.hosts.iter_mut() {
+            if host.endpoint_type == endpoint_type {
+                let log_stream =
+                    cloudwatch_utils::ssm_stdout_log_stream(command_id, &host.instance_id);
+                host.logs_url = Some(cloudwatch_utils::log_stream_url(
+                    &self.region,
+                    &self.log_group,
+                    &log_stream,
+                ));
+            }
+        }
+        self.upload().await
+    }
+
+    pub async fn copy_pair_results(&mut self, pair: usize) -> OrchResult<()> {
+        self.status.pairs[pair].state = PairState::CopyingResults;
+        self.set_hosts_state(HostState::CopyingResults);
+        self.upload().await
+    }
+
+    pub async fn finish_pair(&mut self, pair: usize) -> OrchResult<()> {
+        let status = &mut self.status.pairs[pair];
+        audit::record!(
+            "run",
+            "finish_pair",
+            server = %status.server,
+            client = %status.client,
+            iteration = status.iteration
+        );
+        status.state = PairState::Done;
+        status.duration_ms = status
+            .start
+            .and_then(|start| start.elapsed().ok())
+            .map(|elapsed| elapsed.as_millis() as u64);
+        self.set_hosts_state(HostState::Ready);
+        self.upload().await
+    }
+
+    // The driver pair was aborted before it finished, so it has no results.
+    pub async fn abort_pair(&mut self, pair: usize) -> OrchResult<()> {
+        let status = &self.status.pairs[pair];
+        audit::record!(
+            "run",
+            "abort_pair",
+            server = %status.server,
+            client = %status.client,
+            iteration = status.iteration
+        );
+        self.status.pairs[pair].state = PairState::Aborted;
+        self.set_hosts_state(HostState::Ready);
+        self.upload().await
+    }
+
+    fn set_hosts_state(&mut self, state: HostState) {
+        for host in self.status.hosts.iter_mut() {
+            host.state = state;
+            if state != HostState::Running && state != HostState::CopyingResults {
+                host.driver = None;
+            }
+        }
+    }
+
+    // Estimates the remaining time from the average duration of the finished
+    // pairs. None until a pair has finished.
+    fn eta(&self) -> Option<SystemTime> {
+        let done: Vec<u64> = self
+            .status
+            .pairs
+            .iter()
+            .filter_map(|pair| pair.duration_ms)
+            .collect();
+        if done.is_empty() {
+            return None;
+        }
+        let average = done.iter().sum::<u64>() / done.len() as u64;
+
+        let mut remaining = Duration::ZERO;
+        for pair in self.status.pairs.iter() {
+            match pair.state {
+                PairState::Done | PairState::Aborted => {}
+                PairState::Pending => remaining += Duration::from_millis(average),
+                PairState::Running | PairState::CopyingResults => {
+                    let elapsed = pair
+                        .start
+                        .and_then(|start| start.elapsed().ok())
+                        .unwrap_or_default();
+                    remaining += Duration::from_millis(average).saturating_sub(elapsed);
+                }
+            }
+        }
+        Some(SystemTime::now() + remaining)
+    }
+
+    async fn upload(&mut self) -> OrchResult<()> {
+        let now = SystemTime::now();
+        self.status.updated = humantime::format_rfc3339_seconds(now).to_string();
+        self.status.eta = match self.status.phase {
+            RunPhase::Running => self
+                .eta()
+                .map(|eta| humantime::format_rfc3339_seconds(eta).to_string()),
+            _ => None,
+        };
+        debug!("{:?}", self.status);
+        self.watch.send_replace(self.status.clone());
+
+        let body = serde_json::to_vec_pretty(&self.status).expect("failed to serialize status");
+        upload_object(
+            &self.s3_client,
+            &self.bucket,
+            ByteStream::from(Bytes::from(body)),
+            &RunPaths::new(&self.status.unique_id).key(STATUS_JSON),
+        )
+        .await?;
+        Ok(())
+    }
 }
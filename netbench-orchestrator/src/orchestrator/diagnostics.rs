@@ -0,0 +1,121 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    compute::ComputeProvider,
+    ec2_utils::{InfraDetail, InstanceDetail},
+    orchestrator::{
+        paths::{HOST_RUSSULA_LOGS, LOG_DIR},
+        OrchError, RunPaths,
+    },
+    progress,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tracing::{debug, warn};
+
+// Gathers everything needed to debug a failed run into a single folder:
+//
+// - error.txt: the error which failed the run
+// - orchestrator/: the orchestrator's logs, including the Russula coordinators
+// - <kind>/<instance_id>.txt: the compute backend's details of each host, eg.
+//   for EC2 the SSM commands run on the host in ssm/ and its console output
+//   in console/
+// - workers/<endpoint>_<instance_id>/: the Russula worker logs of each host
+//
+// Without `infra` (eg. the launch failed) only the error and orchestrator logs
+// are gathered. This function is best effort and will not return an error.
+pub async fn collect(
+    unique_id: &str,
+    error: &OrchError,
+    infra: Option<&InfraDetail>,
+    compute: &impl ComputeProvider,
+) -> PathBuf {
+    let dir = RunPaths::new(unique_id).diagnostics_dir();
+    if let Err(err) = fs::create_dir_all(&dir) {
+        warn!("Failed to create the diagnostics dir {:?}. {err}", dir);
+        return dir;
+    }
+    write(&dir.join("error.txt"), &format!("{error}\n\n{error:?}\n"));
+    copy_orchestrator_logs(unique_id, &dir.join("orchestrator"));
+
+    if let Some(infra) = infra {
+        for instance in infra.servers.iter().chain(infra.clients.iter()) {
+            let instance_id = instance.instance_id();
+            for (kind, contents) in compute.diagnostics(instance).await {
+                match contents {
+                    Ok(contents) => write(
+                        &dir.join(kind).join(format!("{instance_id}.txt")),
+                        &contents,
+                    ),
+                    Err(err) => warn!(
+                        host = instance_id,
+                        "Failed to get the {kind} diagnostics. {err}"
+                    ),
+                }
+            }
+            download_worker_logs(instance, &dir.join("workers"));
+        }
+    }
+
+    progress::output!("Diagnostics: {}", dir.display());
+    dir
+}
+
+fn write(path: &Path, contents: &str) {
+    let res = fs::create_dir_all(path.parent().expect("diagnostics file has a parent dir"))
+        .and_then(|_| fs::write(path, contents));
+    if let Err(err) = res {
+        warn!("Failed to write {:?}. {err}", path);
+    }
+}
+
+// The orchestrator logs of the run, which are rotated daily.
+fn copy_orchestrator_logs(unique_id: &str, dest: &Path) {
+    let prefix = RunPaths::new(unique_id).log_file_prefix();
+    let Ok(entries) = fs::read_dir(LOG_DIR) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if !name.to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+        let res = fs::create_dir_all(dest).and_then(|_| fs::copy(entry.path(), dest.join(&name)));
+        if let Err(err) = res {
+            warn!("Failed to copy the orchestrator log {:?}. {err}", name);
+        }
+    }
+}
+
+// Requires ssh access to the host. See `ssh_key_name` in the settings for more info
+fn download_worker_logs(instance: &InstanceDetail, dest: &Path) {
+    let log_folder = dest.join(format!(
+        "{}_{}",
+        instance.endpoint_type().as_str().to_lowercase(),
+        instance.instance_id()
+    ));
+    if let Err(err) = fs::create_dir_all(&log_folder) {
+        warn!("Failed to create {:?}. {err}", log_folder);
+        return;
+    }
+    let res = Command::new("scp")
+        .args([
+            "-oStrictHostKeyChecking=no",
+            "-oConnectTimeout=10",
+            &format!(
+                "ec2-user@{}:{HOST_RUSSULA_LOGS}",
+                instance.host_ips().public_ip().as_string()
+            ),
+        ])
+        .arg(&log_folder)
+        .output();
+    debug!(
+        host = instance.instance_id(),
+        "worker log download succeeded: {:?}",
+        res.map(|output| output.status.success()).ok()
+    );
+}
@@ -0,0 +1,430 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+// End-to-end tests of a run against the AWS mocks.
+//
+// The hosts are simulated by the testing Russula workers, which are started
+// when the mock SSM client receives the command that would start them on a
+// host. The hosts are given loopback addresses so that the coordinators
+// connect to the workers.
+
+use super::*;
+use crate::{
+    aws_api::mock::{MockClients, SentCommand},
+    russula::{
+        netbench::{self, client, server},
+        WorkflowBuilder, WorkflowState,
+    },
+    ssm_utils::DriverRegistry,
+};
+use std::{
+    collections::BTreeSet,
+    io::Write,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+const AZ: &str = "us-west-2a";
+// The hosts are launched servers first
+const SERVER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const CLIENT_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+
+fn scenario_file() -> tempfile::NamedTempFile {
+    let mut file = tempfile::Builder::new()
+        .prefix("e2e_scenario")
+        .suffix(".json")
+        .tempfile()
+        .unwrap();
+    file.write_all(b"{}").unwrap();
+    file
+}
+
+// Without `server_az` the run is in loopback mode
+fn config(scenario: &tempfile::NamedTempFile, server_az: &[&str]) -> OrchestratorConfig {
+    let driver_registry: DriverRegistry = serde_json::from_str(
+        r#"{"drivers": [
+            {"name": "quic", "source": {"type": "github", "repo_name": "netbench"}, "server_bin": "server", "client_bin": "client"}
+        ]}"#,
+    )
+    .unwrap();
+    OrchestratorConfig::testing(
+        scenario.path().to_path_buf(),
+        driver_registry,
+        server_az,
+        &[AZ],
+    )
+}
+
+// Starts the testing Russula worker of a host when the command which runs
+// the worker is sent.
+fn spawn_worker(command: &SentCommand) {
+    let poll_delay = Duration::from_secs(1);
+    match command.comment.as_str() {
+        "run_server_russula" => {
+            let addr = SocketAddr::new(SERVER_IP, state().russula_port);
+            tokio::spawn(async move {
+                let workflow = server::WorkerWorkflow::new(
+                    "e2e_server".to_string(),
+                    netbench::ServerContext::testing(),
+                );
+                let mut worker =
+                    WorkflowBuilder::new(BTreeSet::from_iter([addr]), workflow, poll_delay)
+                        .build()
+                        .await
+                        .unwrap();
+                worker.run_till(WorkflowState::Done).await.unwrap();
+            });
+        }
+        "run_client_russula" => {
+            let addr = SocketAddr::new(CLIENT_IP, state().russula_port);
+            tokio::spawn(async move {
+                let workflow = client::WorkerWorkflow::new(
+                    "e2e_client".to_string(),
+                    netbench::ClientContext::testing(),
+                );
+                let mut worker =
+                    WorkflowBuilder::new(BTreeSet::from_iter([addr]), workflow, poll_delay)
+                        .build()
+                        .await
+                        .unwrap();
+                worker.run_till(WorkflowState::Done).await.unwrap();
+            });
+        }
+        _ => {}
+    }
+}
+
+#[tokio::test]
+async fn run_launches_and_cleans_up_infra() {
+    let scenario = scenario_file();
+    let config = config(&scenario, &[AZ]);
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let clients = MockClients::new(&[AZ], vec![SERVER_IP, CLIENT_IP]);
+
+    run_with_clients(&run_id, &config, &clients, RunMode::TestInfra, false, None)
+        .await
+        .unwrap();
+
+    {
+        let ec2 = clients.ec2.state.lock().unwrap();
+        assert!(ec2.instances.is_empty());
+        assert_eq!(ec2.terminated.len(), 2);
+        assert!(ec2.security_groups.is_empty());
+        assert!(ec2.placement_groups.is_empty());
+        // the coordinators connect to the workers on the russula port
+        let russula_port = i32::from(config.ports.russula());
+        assert!(ec2
+            .ingress
+            .iter()
+            .any(|permission| permission.from_port() == Some(russula_port)));
+    }
+
+    let run_paths = RunPaths::new(run_id.as_str());
+    let bucket = config.cdk_config.netbench_runner_public_s3_bucket();
+    assert!(clients
+        .s3
+        .object(bucket, &run_paths.key(config.netbench_scenario_filename()))
+        .is_some());
+    assert!(clients
+        .s3
+        .object(bucket, &run_paths.key(paths::INDEX_HTML))
+        .is_some());
+    let status: serde_json::Value = serde_json::from_slice(
+        &clients
+            .s3
+            .object(bucket, &run_paths.key(paths::STATUS_JSON))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(status["hosts"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn private_run_is_kept_out_of_the_public_bucket() {
+    let scenario = scenario_file();
+    let mut config = config(&scenario, &[AZ]);
+    config.report.set_private();
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let clients = MockClients::new(&[AZ], vec![SERVER_IP, CLIENT_IP]);
+
+    run_with_clients(&run_id, &config, &clients, RunMode::TestInfra, false, None)
+        .await
+        .unwrap();
+
+    let public_bucket = config.cdk_config.netbench_runner_public_s3_bucket();
+    let public_keys: Vec<String> = clients
+        .s3
+        .objects
+        .lock()
+        .unwrap()
+        .keys()
+        .filter(|(bucket, _)| bucket == public_bucket)
+        .map(|(_, key)| key.clone())
+        .collect();
+    assert!(public_keys.is_empty(), "{public_keys:?}");
+
+    let run_paths = RunPaths::new(run_id.as_str());
+    let private_bucket = config.cdk_config.netbench_runner_private_s3_bucket();
+    for key in [
+        config.netbench_scenario_filename(),
+        paths::INDEX_HTML,
+        paths::STATUS_JSON,
+    ] {
+        assert!(clients
+            .s3
+            .object(private_bucket, &run_paths.key(key))
+            .is_some());
+    }
+}
+
+#[tokio::test]
+async fn kept_hosts_are_tagged_with_their_expiry() {
+    let scenario = scenario_file();
+    let mut config = config(&scenario, &[AZ]);
+    config.run.keep_servers_for(Duration::from_secs(30 * 60));
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let clients = MockClients::new(&[AZ], vec![SERVER_IP, CLIENT_IP]);
+
+    run_with_clients(&run_id, &config, &clients, RunMode::TestInfra, false, None)
+        .await
+        .unwrap();
+
+    assert!(clients
+        .ssm
+        .comments()
+        .iter()
+        .any(|sent| sent == "keep_hosts"));
+    let ec2 = clients.ec2.state.lock().unwrap();
+    assert_eq!(ec2.instances.len(), 2);
+    assert!(ec2.terminated.is_empty());
+    assert_eq!(ec2.security_groups.len(), 1);
+    for resource_id in ec2.instances.keys().chain(ec2.security_groups.iter()) {
+        assert!(ec2.tags[resource_id].contains_key("netbench:expires-at"));
+    }
+}
+
+#[tokio::test]
+async fn driver_pair_runs_with_russula_workers() {
+    let scenario = scenario_file();
+    let config = config(&scenario, &[AZ]);
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let unique_id = run_id.as_str();
+    let mut clients = MockClients::new(&[AZ], vec![SERVER_IP, CLIENT_IP]);
+    clients.ssm.on_send(spawn_worker);
+
+    let compute = Ec2Provider::new(
+        clients.ec2.clone(),
+        clients.ssm.clone(),
+        clients.iam.clone(),
+    );
+
+    let infra = compute.provision(unique_id, &config).await.unwrap();
+    let (server_drivers, client_drivers) = config
+        .driver_registry
+        .drivers(unique_id, &config)
+        .await
+        .unwrap();
+    compute
+        .configure(&infra, unique_id, &server_drivers, &client_drivers, &config)
+        .await
+        .unwrap();
+
+    let mut timeline = EventTimeline::default();
+    let mut dashboard = Dashboard::new(&clients.s3, unique_id, &config);
+    dashboard
+        .set_pairs(
+            server_drivers
+                .iter()
+                .zip(client_drivers.iter())
+                .map(|(server, client)| (server, client, None)),
+        )
+        .await
+        .unwrap();
+    run_driver_pair(
+        &config,
+        &infra,
+        &compute,
+        unique_id,
+        &server_drivers[0],
+        &client_drivers[0],
+        None,
+        &mut timeline,
+        &mut dashboard,
+        0,
+        false,
+        &mut AbortSignal::none(),
+    )
+    .await
+    .unwrap();
+    compute.release(infra).await.unwrap();
+
+    let comments = clients.ssm.comments();
+    for comment in [
+        "configure_host_server",
+        "build_russula_client",
+        "run_server_russula",
+        "run_client_russula",
+        "upload_netbench_raw_data",
+    ] {
+        assert!(
+            comments.iter().any(|sent| sent == comment),
+            "{comment} wasn't sent: {comments:?}"
+        );
+    }
+    assert!(clients.ec2.state.lock().unwrap().instances.is_empty());
+}
+
+#[tokio::test]
+async fn skipped_setup_checks_the_hosts() {
+    let scenario = scenario_file();
+    let mut config = config(&scenario, &[AZ]);
+    config.skip = cli::SkipConfig::setup();
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let unique_id = run_id.as_str();
+    let clients = MockClients::new(&[AZ], vec![SERVER_IP, CLIENT_IP]);
+
+    let compute = Ec2Provider::new(
+        clients.ec2.clone(),
+        clients.ssm.clone(),
+        clients.iam.clone(),
+    );
+
+    let infra = compute.provision(unique_id, &config).await.unwrap();
+    let (server_drivers, client_drivers) = config
+        .driver_registry
+        .drivers(unique_id, &config)
+        .await
+        .unwrap();
+    compute
+        .configure(&infra, unique_id, &server_drivers, &client_drivers, &config)
+        .await
+        .unwrap();
+    compute.release(infra).await.unwrap();
+
+    let comments = clients.ssm.comments();
+    for comment in [
+        "check_host_server",
+        "check_russula_client",
+        "check_driver_server",
+    ] {
+        assert!(
+            comments.iter().any(|sent| sent == comment),
+            "{comment} wasn't sent: {comments:?}"
+        );
+    }
+    assert!(
+        !comments
+            .iter()
+            .any(|sent| sent.starts_with("configure_host") || sent.starts_with("build_")),
+        "a skipped phase was sent: {comments:?}"
+    );
+}
+
+#[tokio::test]
+async fn fleet_falls_back_to_another_instance_type() {
+    let scenario = scenario_file();
+    let mut config = config(&scenario, &[AZ]);
+    config.fleet = cli::FleetConfig::testing(&["c5n.4xlarge"]);
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let clients = MockClients::new(&[AZ], vec![SERVER_IP, CLIENT_IP]);
+    clients
+        .ec2
+        .state
+        .lock()
+        .unwrap()
+        .unavailable_instance_types
+        .push("c5.4xlarge".to_string());
+
+    let compute = Ec2Provider::new(
+        clients.ec2.clone(),
+        clients.ssm.clone(),
+        clients.iam.clone(),
+    );
+
+    let infra = compute.provision(run_id.as_str(), &config).await.unwrap();
+    for host in infra.servers.iter().chain(infra.clients.iter()) {
+        assert_eq!(host.instance_type(), Some("c5n.4xlarge"));
+    }
+    assert_eq!(clients.ec2.state.lock().unwrap().launch_templates.len(), 1);
+    compute.release(infra).await.unwrap();
+
+    let ec2 = clients.ec2.state.lock().unwrap();
+    assert!(ec2.instances.is_empty());
+    assert!(ec2.launch_templates.is_empty());
+}
+
+#[tokio::test]
+async fn loopback_driver_pair_runs_on_the_client_hosts() {
+    let scenario = scenario_file();
+    let config = config(&scenario, &[]);
+    let run_id = RunId::generate(scenario.path(), None).unwrap();
+    let unique_id = run_id.as_str();
+    let mut clients = MockClients::new(&[AZ], vec![CLIENT_IP]);
+    clients.ssm.on_send(spawn_worker);
+
+    let compute = Ec2Provider::new(
+        clients.ec2.clone(),
+        clients.ssm.clone(),
+        clients.iam.clone(),
+    );
+
+    let infra = compute.provision(unique_id, &config).await.unwrap();
+    assert!(infra.servers.is_empty());
+    let (server_drivers, client_drivers) = config
+        .driver_registry
+        .drivers(unique_id, &config)
+        .await
+        .unwrap();
+    compute
+        .configure(&infra, unique_id, &server_drivers, &client_drivers, &config)
+        .await
+        .unwrap();
+
+    let mut timeline = EventTimeline::default();
+    let mut dashboard = Dashboard::new(&clients.s3, unique_id, &config);
+    dashboard
+        .set_pairs(
+            server_drivers
+                .iter()
+                .zip(client_drivers.iter())
+                .map(|(server, client)| (server, client, None)),
+        )
+        .await
+        .unwrap();
+    run_driver_pair(
+        &config,
+        &infra,
+        &compute,
+        unique_id,
+        &server_drivers[0],
+        &client_drivers[0],
+        None,
+        &mut timeline,
+        &mut dashboard,
+        0,
+        false,
+        &mut AbortSignal::none(),
+    )
+    .await
+    .unwrap();
+    compute.release(infra).await.unwrap();
+
+    let comments = clients.ssm.comments();
+    for comment in ["configure_host_server", "run_server_russula"] {
+        assert!(
+            !comments.iter().any(|sent| sent == comment),
+            "{comment} was sent: {comments:?}"
+        );
+    }
+    let sent = clients.ssm.sent.lock().unwrap();
+    let client_worker = sent
+        .iter()
+        .find(|command| command.comment == "run_client_russula")
+        .expect("the client worker is started");
+    let worker_cmd = client_worker.commands.join("\n");
+    assert!(worker_cmd.contains("--netbench-servers 127.0.0.1:"));
+    assert!(worker_cmd.contains(&format!(
+        "--loopback-server {}",
+        server_drivers[0].driver_name()
+    )));
+}
@@ -1,35 +1,254 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::{orchestrator::dashboard::RunPhase, russula::RussulaError};
+use aws_sdk_ec2::error::{ProvideErrorMetadata, SdkError};
+use std::fmt::Write;
+
 pub type OrchResult<T, E = OrchError> = Result<T, E>;
 
-#[derive(Debug)]
-pub enum OrchError {
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Service error codes which indicate a transient failure, across the AWS
+// services used by the orchestrator.
+const RETRYABLE_CODES: &[&str] = &[
+    "Throttling",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+    "RequestThrottled",
+    "TooManyRequestsException",
+    "SlowDown",
+    "InternalError",
+    "InternalFailure",
+    "InternalServerError",
+    "ServiceUnavailable",
+];
+
+// The class of a failure, which determines the exit code of the orchestrator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ErrorKind {
     // Initialization error
-    Init { dbg: String },
+    #[error("init")]
+    Init,
     // Ec2 sdk error
-    Ec2 { dbg: String },
+    #[error("ec2")]
+    Ec2,
     // Iam sdk error
-    Iam { dbg: String },
+    #[error("iam")]
+    Iam,
     // Ssm sdk error
-    Ssm { dbg: String },
+    #[error("ssm")]
+    Ssm,
     // S3 sdk error
-    S3 { dbg: String },
+    #[error("s3")]
+    S3,
+    // CloudFront error
+    #[error("cloudfront")]
+    CloudFront,
+    // CloudWatch sdk error
+    #[error("cloudwatch")]
+    CloudWatch,
     // Russula error
-    Russula { dbg: String },
-}
-
-impl std::fmt::Display for OrchError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OrchError::Init { dbg } => write!(f, "{}", dbg),
-            OrchError::Ec2 { dbg } => write!(f, "{}", dbg),
-            OrchError::Iam { dbg } => write!(f, "{}", dbg),
-            OrchError::Ssm { dbg } => write!(f, "{}", dbg),
-            OrchError::S3 { dbg } => write!(f, "{}", dbg),
-            OrchError::Russula { dbg } => write!(f, "{}", dbg),
+    #[error("russula")]
+    Russula,
+    // Failed to generate the report
+    #[error("report")]
+    Report,
+    // Failed to send the run summary
+    #[error("notify")]
+    Notify,
+    // The run was aborted by the user
+    #[error("aborted")]
+    Aborted,
+}
+
+// An orchestrator error.
+//
+// The message describes what the orchestrator was doing, and the underlying
+// error (eg. the SDK error) is kept as the source. The SDK errors only
+// describe themselves in their sources, so the chain of sources is included
+// when the error is displayed.
+#[derive(Debug, thiserror::Error)]
+#[error("{}{context}{}", phase_prefix(.phase), display_sources(.source))]
+pub struct OrchError {
+    kind: ErrorKind,
+    context: String,
+    #[source]
+    source: Option<BoxError>,
+    retryable: bool,
+    // The run and phase in which the error occurred, once known
+    unique_id: Option<String>,
+    phase: Option<RunPhase>,
+}
+
+impl OrchError {
+    pub fn new(kind: ErrorKind, context: impl Into<String>) -> Self {
+        OrchError {
+            kind,
+            context: context.into(),
+            source: None,
+            retryable: false,
+            unique_id: None,
+            phase: None,
+        }
+    }
+
+    // An error returned by an AWS SDK, which is retryable if the request timed
+    // out, failed to send or was throttled.
+    pub fn sdk<E, R>(kind: ErrorKind, context: impl Into<String>, err: SdkError<E, R>) -> Self
+    where
+        E: std::error::Error + ProvideErrorMetadata + Send + Sync + 'static,
+        R: std::fmt::Debug + Send + Sync + 'static,
+    {
+        let retryable = match &err {
+            SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => true,
+            _ => err
+                .code()
+                .is_some_and(|code| RETRYABLE_CODES.contains(&code)),
+        };
+        OrchError {
+            retryable,
+            ..OrchError::new(kind, context).with_source(err)
+        }
+    }
+
+    pub fn with_source(mut self, source: impl Into<BoxError>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    // Marks the error as transient, eg. an SSM command which hasn't become
+    // visible yet.
+    pub fn retryable(mut self) -> Self {
+        self.retryable = true;
+        self
+    }
+
+    // Annotates the error with the run and the phase it failed in. The first
+    // annotation is kept.
+    pub fn with_run(mut self, unique_id: &str, phase: RunPhase) -> Self {
+        if self.unique_id.is_none() {
+            self.unique_id = Some(unique_id.to_string());
+            self.phase = Some(phase);
+        }
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    // Whether retrying the failed operation could succeed.
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
+    pub fn unique_id(&self) -> Option<&str> {
+        self.unique_id.as_deref()
+    }
+
+    pub fn phase(&self) -> Option<RunPhase> {
+        self.phase
+    }
+
+    // The process exit code for each class of failure, so that wrappers can
+    // branch on what went wrong. Keep in sync with the table in the README.
+    //
+    // 2 is left to clap, which exits with it on invalid arguments.
+    pub fn exit_code(&self) -> u8 {
+        match self.kind {
+            ErrorKind::Init => 3,
+            ErrorKind::Ec2 | ErrorKind::Iam => 4,
+            ErrorKind::Ssm => 5,
+            ErrorKind::Russula => 6,
+            ErrorKind::Report => 7,
+            ErrorKind::Aborted => 8,
+            ErrorKind::S3 | ErrorKind::CloudFront | ErrorKind::CloudWatch | ErrorKind::Notify => 1,
+        }
+    }
+}
+
+impl ErrorKind {
+    // The class of a failure from the exit code of another orchestrator, eg.
+    // the one on the controller. See `OrchError::exit_code`.
+    pub fn from_exit_code(code: u8) -> Self {
+        match code {
+            3 => ErrorKind::Init,
+            4 => ErrorKind::Ec2,
+            5 => ErrorKind::Ssm,
+            6 => ErrorKind::Russula,
+            7 => ErrorKind::Report,
+            8 => ErrorKind::Aborted,
+            _ => ErrorKind::S3,
+        }
+    }
+}
+
+// Russula retries the errors it can recover from itself, so only the fatal
+// errors are returned to the orchestrator.
+impl From<RussulaError> for OrchError {
+    fn from(err: RussulaError) -> Self {
+        let retryable = !err.is_fatal();
+        OrchError {
+            retryable,
+            ..OrchError::new(ErrorKind::Russula, "Russula coordination failed").with_source(err)
         }
     }
 }
 
-impl std::error::Error for OrchError {}
+fn phase_prefix(phase: &Option<RunPhase>) -> String {
+    phase
+        .map(|phase| format!("[{}] ", phase.as_str()))
+        .unwrap_or_default()
+}
+
+fn display_sources(source: &Option<BoxError>) -> String {
+    let mut sources = String::new();
+    let mut next = source.as_deref().map(|err| err as &dyn std::error::Error);
+    while let Some(err) = next {
+        let _ = write!(sources, ": {err}");
+        next = err.source();
+    }
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_includes_sources_and_phase() {
+        let io = std::io::Error::new(std::io::ErrorKind::Other, "connection reset");
+        let err = OrchError::new(ErrorKind::S3, "Failed to upload status.json").with_source(io);
+        assert_eq!(
+            err.to_string(),
+            "Failed to upload status.json: connection reset"
+        );
+        assert!(!err.is_retryable());
+
+        let err = err.with_run("2024-01-01", RunPhase::Running);
+        assert_eq!(
+            err.to_string(),
+            "[running] Failed to upload status.json: connection reset"
+        );
+        // the first annotation is kept
+        let err = err.with_run("2024-01-01", RunPhase::Reporting);
+        assert_eq!(err.phase(), Some(RunPhase::Running));
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn russula_errors_are_retryable_unless_fatal() {
+        let err = OrchError::from(RussulaError::NetworkBlocked {
+            dbg: "would block".to_string(),
+        });
+        assert!(err.is_retryable());
+        assert_eq!(err.kind(), ErrorKind::Russula);
+
+        let err = OrchError::from(RussulaError::BadMsg {
+            dbg: "bad msg".to_string(),
+        });
+        assert!(!err.is_retryable());
+        assert_eq!(err.exit_code(), 6);
+    }
+}
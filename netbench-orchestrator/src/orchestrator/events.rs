@@ -0,0 +1,112 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::S3Api,
+    orchestrator::{
+        coordination::CoordinationTimeline, metrics::RunMetrics, phases::PhaseSummary, OrchResult,
+        OrchestratorConfig, RunPaths,
+    },
+    s3_utils::upload_object,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    // Launching and configuring the infrastructure
+    Setup,
+    // Netbench drivers are running
+    Measurement,
+    // Coordinating hosts between measurements (eg. copying results)
+    Coordination,
+}
+
+#[derive(Debug, Serialize)]
+struct Event {
+    name: String,
+    phase: Phase,
+    start: String,
+    end: String,
+    // offset from the start of the run
+    start_ms: u64,
+    duration_ms: u64,
+}
+
+// The phases of a run, as seen by the orchestrator.
+//
+// Uploaded as `events.json` to the root of the results tree so that the
+// report can show how long setup and coordination took relative to the
+// measurements.
+#[derive(Debug)]
+pub struct EventTimeline {
+    origin: SystemTime,
+    events: Vec<Event>,
+    // Metrics derived from the events, published to CloudWatch
+    pub metrics: RunMetrics,
+    // The Russula events of each driver pair
+    pub coordination: CoordinationTimeline,
+    // The time spent in each phase, summarized at the end of the run
+    pub phases: PhaseSummary,
+}
+
+impl Default for EventTimeline {
+    fn default() -> Self {
+        EventTimeline {
+            origin: SystemTime::now(),
+            events: Vec::new(),
+            metrics: RunMetrics::default(),
+            coordination: CoordinationTimeline::default(),
+            phases: PhaseSummary::default(),
+        }
+    }
+}
+
+impl EventTimeline {
+    // Records an event which started at `start` and ends now, returning its
+    // duration.
+    pub fn record(&mut self, name: impl Into<String>, phase: Phase, start: SystemTime) -> Duration {
+        let end = SystemTime::now();
+        let duration = end.duration_since(start).unwrap_or_default();
+        let offset = |time: SystemTime| {
+            time.duration_since(self.origin)
+                .unwrap_or_default()
+                .as_millis() as u64
+        };
+
+        let event = Event {
+            name: name.into(),
+            phase,
+            start: humantime::format_rfc3339_millis(start).to_string(),
+            end: humantime::format_rfc3339_millis(end).to_string(),
+            start_ms: offset(start),
+            duration_ms: duration.as_millis() as u64,
+        };
+        debug!("{:?}", event);
+        self.events.push(event);
+        duration
+    }
+
+    pub async fn upload(
+        &self,
+        s3_client: &impl S3Api,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<()> {
+        let body = serde_json::to_vec_pretty(&serde_json::json!({ "events": self.events }))
+            .expect("failed to serialize events");
+        upload_object(
+            s3_client,
+            config.results_bucket(),
+            ByteStream::from(Bytes::from(body)),
+            &RunPaths::new(unique_id).results_key("events.json"),
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    compute::ComputeProvider,
+    ec2_utils::{EndpointType, InfraDetail},
+    orchestrator::{state, OrchResult, OrchestratorConfig},
+    russula::Backoff,
+    ssm_utils::{NetbenchDriverType, Step},
+};
+use std::time::Duration;
+use tracing::{info, instrument};
+
+// The hooks are short, so they're polled more often than the other steps
+const MIN_POLL_DELAY: Duration = Duration::from_millis(500);
+
+// The user provided commands run on each host group right before the workers
+// of a driver pair are started (`pre_run`) and right after they're done
+// (`post_run`), eg. to start a custom tracer, drop the page cache or collect
+// the NIC's stats. See `HostHooks`.
+//
+// The output of the hooks is written next to the results on the hosts, to
+// `<pre|post>_run_hook-<driver>.log`, and uploaded with the driver's results.
+// A failed hook fails the pair.
+#[instrument(skip_all)]
+pub async fn pre_run(
+    compute: &impl ComputeProvider,
+    infra: &InfraDetail,
+    server_driver: &NetbenchDriverType,
+    client_driver: &NetbenchDriverType,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    run_hooks(
+        compute,
+        infra,
+        Step::PreRunHook,
+        [server_driver, client_driver],
+        config,
+    )
+    .await
+}
+
+// Runs the `post_run` hooks, which also run after a failed or aborted pair
+#[instrument(skip_all)]
+pub async fn post_run(
+    compute: &impl ComputeProvider,
+    infra: &InfraDetail,
+    server_driver: &NetbenchDriverType,
+    client_driver: &NetbenchDriverType,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    run_hooks(
+        compute,
+        infra,
+        Step::PostRunHook,
+        [server_driver, client_driver],
+        config,
+    )
+    .await
+}
+
+async fn run_hooks(
+    compute: &impl ComputeProvider,
+    infra: &InfraDetail,
+    step: Step,
+    [server_driver, client_driver]: [&NetbenchDriverType; 2],
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    for (endpoint_type, driver) in [
+        (EndpointType::Server, server_driver),
+        (EndpointType::Client, client_driver),
+    ] {
+        let hooks = config.driver_registry.host_hooks(endpoint_type);
+        let hooks = match step {
+            Step::PreRunHook => &hooks.pre_run,
+            _ => &hooks.post_run,
+        };
+        let host_ids = match endpoint_type {
+            EndpointType::Server => infra.server_ids(),
+            EndpointType::Client => infra.client_ids(),
+        };
+        // there are no server hosts in loopback mode
+        if hooks.is_empty() || host_ids.is_empty() {
+            continue;
+        }
+
+        let host_group = endpoint_type.as_str().to_lowercase();
+        info!(
+            "Running the {} hooks of the {host_group} hosts for {}",
+            step.as_str(),
+            driver.results_name()
+        );
+        let command_id = compute
+            .execute(
+                step.clone(),
+                &format!("{}_{host_group}", step.as_str()),
+                host_ids,
+                hook_cmds(&step, driver, hooks),
+                config,
+            )
+            .await?;
+        let mut backoff = Backoff::new(MIN_POLL_DELAY, state().poll_delay_ssm);
+        while compute.poll(&host_group, &command_id).await?.is_pending() {
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+    Ok(())
+}
+
+// The hooks run as root from the home directory of the host, like the build
+// hooks, with the driver in `NETBENCH_DRIVER`. Their output is also printed,
+// so that it's in the SSM output of the command.
+fn hook_cmds(step: &Step, driver: &NetbenchDriverType, hooks: &[String]) -> Vec<String> {
+    let log = format!(
+        "netbench_orchestrator/{}-{}.log",
+        step.as_str(),
+        driver.driver_name().short()
+    );
+    let mut cmds = vec![
+        "mkdir -p netbench_orchestrator".to_string(),
+        format!("export NETBENCH_DRIVER={}", driver.results_name()),
+        "(".to_string(),
+        "set -e".to_string(),
+    ];
+    cmds.extend(hooks.iter().cloned());
+    cmds.extend([
+        format!(") > {log} 2>&1 || {{ cat {log}; exit 1; }}"),
+        format!("cat {log}"),
+    ]);
+    cmds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssm_utils::DriverRegistry;
+
+    #[tokio::test]
+    async fn hooks_log_to_the_results() {
+        let registry: DriverRegistry = serde_json::from_str(
+            r#"{"drivers": [{"name": "tcp", "source": {"type": "github", "repo_name": "netbench"}, "server_bin": "s2n-netbench-driver-server-tcp", "client_bin": "s2n-netbench-driver-client-tcp"}]}"#,
+        )
+        .unwrap();
+        let config = OrchestratorConfig::testing(
+            "scenario.json".into(),
+            registry,
+            &["us-west-2a"],
+            &["us-west-2a"],
+        );
+        let (_, client_drivers) = config
+            .driver_registry
+            .drivers("run-1", &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            hook_cmds(
+                &Step::PostRunHook,
+                &client_drivers[0],
+                &["ethtool -S eth0".to_string()]
+            ),
+            vec![
+                "mkdir -p netbench_orchestrator",
+                "export NETBENCH_DRIVER=client-tcp",
+                "(",
+                "set -e",
+                "ethtool -S eth0",
+                ") > netbench_orchestrator/post_run_hook-client-tcp.log 2>&1 || { cat netbench_orchestrator/post_run_hook-client-tcp.log; exit 1; }",
+                "cat netbench_orchestrator/post_run_hook-client-tcp.log",
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::S3Api,
+    ec2_utils::InfraDetail,
+    orchestrator::{state, HostConfig, OrchResult, OrchestratorConfig, RunId, RunPaths},
+    s3_utils::upload_object,
+    ssm_utils::NetbenchDriverType,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::Serialize;
+use std::{collections::BTreeMap, iter, process::Command};
+use tracing::debug;
+
+// Describes the environment of a run.
+//
+// Uploaded as `metadata.json` to the root of the results tree so that the
+// generated report is self-describing.
+#[derive(Debug, Serialize)]
+struct RunMetadata {
+    run: BTreeMap<&'static str, String>,
+    hosts: Vec<HostMetadata>,
+    drivers: Vec<DriverMetadata>,
+}
+
+#[derive(Debug, Serialize)]
+struct HostMetadata {
+    endpoint: String,
+    instance_id: String,
+    instance_type: String,
+    az: String,
+    placement: String,
+    // The host's egress limit while the drivers ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    egress_limit: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DriverMetadata {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collector_interval: Option<String>,
+}
+
+// The host config each host was launched from, or None for the managed
+// instances which aren't launched
+fn host_configs(host_configs: &[HostConfig]) -> impl Iterator<Item = Option<&HostConfig>> {
+    host_configs.iter().map(Some).chain(iter::repeat(None))
+}
+
+pub async fn upload_run_metadata(
+    s3_client: &impl S3Api,
+    run_id: &RunId,
+    config: &OrchestratorConfig,
+    infra: &InfraDetail,
+    drivers: &[&NetbenchDriverType],
+) -> OrchResult<()> {
+    let mut run = BTreeMap::new();
+    run.insert("unique_id", run_id.to_string());
+    if let Some(sha) = run_id.git_sha() {
+        run.insert("drivers_git_sha", sha.to_string());
+    }
+    run.insert(
+        "date",
+        humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string(),
+    );
+    run.insert("scenario", config.netbench_scenario_filename().to_string());
+    if let Some(label) = config.run.label() {
+        run.insert("label", label.to_string());
+    }
+    run.insert(
+        "region",
+        config.cdk_config.netbench_primary_region().clone(),
+    );
+    run.insert("iterations", config.run.iterations().to_string());
+    run.insert("warmup_secs", config.run.warmup_secs().to_string());
+    run.insert("orchestrator_version", state().version.to_string());
+    if let Some(sha) = local_git_sha() {
+        run.insert("orchestrator_git_sha", sha);
+    }
+    run.insert(
+        "russula",
+        format!("{}@{}", state().russula_repo, state().russula_branch),
+    );
+
+    let hosts = infra
+        .servers
+        .iter()
+        .zip(host_configs(&config.server_config))
+        .chain(
+            infra
+                .clients
+                .iter()
+                .zip(host_configs(&config.client_config)),
+        )
+        .map(|(instance, host_config)| {
+            let egress_limit = config
+                .run
+                .egress_limit(*instance.endpoint_type())
+                .map(ToString::to_string);
+            match host_config {
+                Some(host_config) => HostMetadata {
+                    endpoint: instance.endpoint_type().as_str().to_string(),
+                    instance_id: instance.instance_id().to_string(),
+                    // a fleet can launch a fallback instance type, and
+                    // with `--fleet-any-az` in another AZ
+                    instance_type: instance
+                        .instance_type()
+                        .unwrap_or(host_config.instance_type())
+                        .to_string(),
+                    az: instance.az().as_string(),
+                    placement: host_config.placement_name().to_string(),
+                    egress_limit,
+                },
+                None => HostMetadata {
+                    endpoint: instance.endpoint_type().as_str().to_string(),
+                    instance_id: instance.instance_id().to_string(),
+                    instance_type: "managed".to_string(),
+                    az: "hybrid".to_string(),
+                    placement: "none".to_string(),
+                    egress_limit,
+                },
+            }
+        })
+        .collect();
+
+    let drivers = drivers
+        .iter()
+        .map(|driver| DriverMetadata {
+            name: driver.driver_name().to_string(),
+            label: driver.label().map(str::to_string),
+            source: driver.source_detail(),
+            version: driver.version().map(str::to_string),
+            build: driver.build_detail(),
+            collector_interval: driver.collector_interval().map(str::to_string),
+        })
+        .collect();
+
+    let metadata = RunMetadata {
+        run,
+        hosts,
+        drivers,
+    };
+    debug!("{:?}", metadata);
+
+    let body = serde_json::to_vec_pretty(&metadata).expect("failed to serialize metadata");
+    upload_object(
+        s3_client,
+        config.results_bucket(),
+        ByteStream::from(Bytes::from(body)),
+        &RunPaths::new(run_id).results_key("metadata.json"),
+    )
+    .await?;
+
+    Ok(())
+}
+
+// The git sha of the local checkout. Best effort.
+fn local_git_sha() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|sha| sha.trim().to_string())
+}
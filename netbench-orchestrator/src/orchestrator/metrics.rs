@@ -0,0 +1,99 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit,
+    orchestrator::{ErrorKind, OrchError, OrchResult},
+};
+use aws_sdk_cloudwatch::{
+    primitives::DateTime,
+    types::{Dimension, MetricDatum, StandardUnit},
+};
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+const NAMESPACE: &str = "Netbench/Orchestrator";
+
+// PutMetricData accepts at most 1000 metrics per request
+const MAX_BATCH: usize = 1000;
+
+// The orchestration overhead of a run.
+//
+// Published as CloudWatch custom metrics under the `Netbench/Orchestrator`
+// namespace at the end of the run, so that alarms can be set on failures and
+// the overhead can be tracked across runs.
+#[derive(Debug, Default)]
+pub struct RunMetrics {
+    data: Vec<MetricDatum>,
+}
+
+impl RunMetrics {
+    pub fn infra_launch(&mut self, duration: Duration) {
+        self.seconds("InfraLaunchSeconds", duration, &[]);
+    }
+
+    pub fn configure(&mut self, duration: Duration) {
+        self.seconds("ConfigureSeconds", duration, &[]);
+    }
+
+    pub fn driver_run(&mut self, server: &str, client: &str, duration: Duration) {
+        self.seconds(
+            "DriverRunSeconds",
+            duration,
+            &[("Server", server), ("Client", client)],
+        );
+    }
+
+    // Recorded for every run, so that the failure rate can be derived from
+    // the sample count.
+    pub fn run_result(&mut self, failed: bool) {
+        self.data.push(
+            MetricDatum::builder()
+                .metric_name("RunFailures")
+                .value(if failed { 1.0 } else { 0.0 })
+                .unit(StandardUnit::Count)
+                .timestamp(DateTime::from(SystemTime::now()))
+                .build(),
+        );
+    }
+
+    fn seconds(&mut self, name: &str, duration: Duration, dimensions: &[(&str, &str)]) {
+        let dimensions = dimensions
+            .iter()
+            .map(|(name, value)| Dimension::builder().name(*name).value(*value).build())
+            .collect();
+        self.data.push(
+            MetricDatum::builder()
+                .metric_name(name)
+                .set_dimensions(Some(dimensions))
+                .value(duration.as_secs_f64())
+                .unit(StandardUnit::Seconds)
+                .timestamp(DateTime::from(SystemTime::now()))
+                .build(),
+        );
+    }
+
+    pub async fn publish(&self, aws_config: &aws_types::SdkConfig) -> OrchResult<()> {
+        let client = aws_sdk_cloudwatch::Client::new(aws_config);
+        for batch in self.data.chunks(MAX_BATCH) {
+            debug!("{:?}", batch);
+            client
+                .put_metric_data()
+                .namespace(NAMESPACE)
+                .set_metric_data(Some(batch.to_vec()))
+                .send()
+                .await
+                .map_err(|err| {
+                    OrchError::sdk(ErrorKind::CloudWatch, "Failed to put the run metrics", err)
+                })?;
+        }
+        audit::record!(
+            "cloudwatch",
+            "put_metric_data",
+            namespace = NAMESPACE,
+            count = self.data.len()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit,
+    aws_api::S3Api,
+    orchestrator::{
+        dashboard::RunPhase, report, runs::RunEntry, ErrorKind, OrchError, OrchResult,
+        OrchestratorConfig, RunPaths,
+    },
+    s3_utils,
+};
+use aws_sdk_sesv2::types::{Body, Content, Destination, EmailContent, Message};
+use serde::Serialize;
+use serde_json::json;
+use std::{collections::BTreeMap, fs::OpenOptions, io::Write, process::Command};
+use tracing::{debug, info, trace, warn};
+
+static EMAIL_HTML: &str = include_str!("./summary_email.html");
+static SLACK_TEMPLATE: &str = include_str!("./summary_slack.md");
+static GITHUB_TEMPLATE: &str = include_str!("./summary_github.md");
+
+// Columns of the summary csv generated with the report. See
+// `report::generate_summary_from_results` for the units.
+const THROUGHPUT: &str = "receive throughput (Mbps)";
+const LATENCY_P50: &str = "latency p50 (ms)";
+const LATENCY_P99: &str = "latency p99 (ms)";
+
+// The top-line metrics of a driver, as measured by its client
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Metrics {
+    throughput: Option<f64>,
+    latency_p50: Option<f64>,
+    latency_p99: Option<f64>,
+}
+
+// A row of the summary, with the deltas against the baseline if any
+#[derive(Debug, PartialEq, Serialize)]
+struct DriverRow {
+    driver: String,
+    throughput: String,
+    latency_p50: String,
+    latency_p99: String,
+}
+
+// Sends a summary of the run's key metrics to the configured email recipients
+// and Slack webhook, and writes it to the GitHub Actions step summary.
+//
+// The metrics are read from the run's summary csv so that a failed run is
+// still reported, along with the status and report links.
+pub async fn send_summary(
+    aws_config: &aws_types::SdkConfig,
+    s3_client: &impl S3Api,
+    config: &OrchestratorConfig,
+    run: &RunEntry,
+) -> OrchResult<()> {
+    let notify = &config.notify;
+    if !notify.is_enabled() {
+        return Ok(());
+    }
+
+    let current = load_summary(s3_client, config, &run.unique_id)
+        .await?
+        .unwrap_or_default();
+    let baseline = match notify.baseline_run() {
+        Some(baseline_run) => {
+            let baseline = load_summary(s3_client, config, baseline_run).await?;
+            if baseline.is_none() {
+                warn!("No summary found for the baseline run {baseline_run}");
+            }
+            baseline
+        }
+        None => None,
+    };
+    // The summary is only sent to the team, so it can share a private report
+    let report_url = match config.report.is_private() && run.status == RunPhase::Finished.as_str() {
+        true => Some(report::private_report_link(s3_client, &run.unique_id, config).await?),
+        false => run.report_url.clone(),
+    };
+    let context = json!({
+        "unique_id": run.unique_id,
+        "scenario": run.scenario,
+        "status": run.status,
+        "url": run.url,
+        "report_url": report_url,
+        "baseline": baseline.as_ref().and(notify.baseline_run()),
+        "drivers": rows(&current, baseline.as_ref()),
+    });
+    debug!("{:?}", context);
+
+    if !notify.emails().is_empty() {
+        let html = handlebars::Handlebars::new()
+            .render_template(EMAIL_HTML, &context)
+            .map_err(|err| {
+                OrchError::new(ErrorKind::Notify, "Failed to render the summary email")
+                    .with_source(err)
+            })?;
+        let subject = format!("Netbench run {}: {}", run.unique_id, run.status);
+        send_email(aws_config, config, subject, html).await?;
+    }
+
+    if let Some(webhook) = notify.slack_webhook() {
+        let mut handlebars = handlebars::Handlebars::new();
+        // Slack messages are not html
+        handlebars.register_escape_fn(handlebars::no_escape);
+        let text = handlebars
+            .render_template(SLACK_TEMPLATE, &context)
+            .map_err(|err| {
+                OrchError::new(ErrorKind::Notify, "Failed to render the Slack summary")
+                    .with_source(err)
+            })?;
+        post_slack(webhook, &text)?;
+    }
+
+    if notify.github_actions() {
+        let mut handlebars = handlebars::Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        let markdown = handlebars
+            .render_template(GITHUB_TEMPLATE, &context)
+            .map_err(|err| {
+                OrchError::new(
+                    ErrorKind::Notify,
+                    "Failed to render the GitHub step summary",
+                )
+                .with_source(err)
+            })?;
+        write_github_step_summary(&markdown)?;
+    }
+
+    Ok(())
+}
+
+async fn send_email(
+    aws_config: &aws_types::SdkConfig,
+    config: &OrchestratorConfig,
+    subject: String,
+    html: String,
+) -> OrchResult<()> {
+    let notify = &config.notify;
+    let from = notify
+        .email_from()
+        .expect("--notify-email-from is checked with the cli arguments");
+    let content = |data: String| {
+        Content::builder()
+            .data(data)
+            .charset("UTF-8")
+            .build()
+            .map_err(|err| {
+                OrchError::new(ErrorKind::Notify, "Failed to build the summary email")
+                    .with_source(err)
+            })
+    };
+    let message = Message::builder()
+        .subject(content(subject)?)
+        .body(Body::builder().html(content(html)?).build())
+        .build()
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Notify, "Failed to build the summary email").with_source(err)
+        })?;
+
+    let ses_client = aws_sdk_sesv2::Client::new(aws_config);
+    let output = ses_client
+        .send_email()
+        .from_email_address(from)
+        .destination(
+            Destination::builder()
+                .set_to_addresses(Some(notify.emails().to_vec()))
+                .build(),
+        )
+        .content(EmailContent::builder().simple(message).build())
+        .send()
+        .await
+        .map_err(|err| {
+            OrchError::sdk(ErrorKind::Notify, "Failed to send the summary email", err)
+        })?;
+    let message_id = output.message_id().unwrap_or_default();
+    audit::record!(
+        "ses",
+        "send_email",
+        message_id,
+        recipients = notify.emails().len()
+    );
+    info!("Sent the run summary to {:?}", notify.emails());
+
+    Ok(())
+}
+
+fn post_slack(webhook: &str, text: &str) -> OrchResult<()> {
+    let payload = json!({ "text": text }).to_string();
+    let mut cmd = Command::new("curl");
+    cmd.args([
+        "-sSf",
+        "-X",
+        "POST",
+        "-H",
+        "Content-type: application/json",
+        "--data",
+        &payload,
+        webhook,
+    ]);
+    // the webhook url is a secret, so only the payload is logged
+    debug!("{}", payload);
+    let output = cmd.output().map_err(|err| {
+        OrchError::new(ErrorKind::Notify, "Failed to post the Slack summary").with_source(err)
+    })?;
+    trace!("{:?}", output);
+    if !output.status.success() {
+        return Err(OrchError::new(
+            ErrorKind::Notify,
+            format!(
+                "Failed to post the Slack summary: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
+    audit::record!("slack", "post_message");
+    info!("Posted the run summary to Slack");
+
+    Ok(())
+}
+
+// Appends to the markdown file which GitHub Actions renders on the job's
+// summary page.
+fn write_github_step_summary(markdown: &str) -> OrchResult<()> {
+    let Ok(path) = std::env::var("GITHUB_STEP_SUMMARY") else {
+        return Err(OrchError::new(
+            ErrorKind::Notify,
+            "GITHUB_STEP_SUMMARY is not set. Is this running in GitHub Actions?",
+        ));
+    };
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{markdown}"))
+        .map_err(|err| {
+            OrchError::new(
+                ErrorKind::Notify,
+                format!("Failed to write the GitHub step summary to {path}"),
+            )
+            .with_source(err)
+        })?;
+    info!("Wrote the run summary to the GitHub step summary");
+
+    Ok(())
+}
+
+// Annotates the GitHub Actions workflow with the error of a failed run, so
+// that it is shown on the run's page rather than only in the logs.
+pub fn github_error_annotation(config: &OrchestratorConfig, error: &OrchError) {
+    if config.notify.github_actions() {
+        println!(
+            "::error title=Netbench run failed::{}",
+            escape_workflow_command(&error.to_string())
+        );
+    }
+}
+
+// https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+fn escape_workflow_command(message: &str) -> String {
+    message
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+// The summary of a run, or None if the run didn't generate one (eg. it failed
+// before the report).
+async fn load_summary(
+    s3_client: &impl S3Api,
+    config: &OrchestratorConfig,
+    unique_id: &str,
+) -> OrchResult<Option<BTreeMap<(String, String), Metrics>>> {
+    let csv = s3_utils::download_object(
+        s3_client,
+        config.results_bucket(),
+        &RunPaths::new(unique_id).summary_key(),
+    )
+    .await?;
+    Ok(csv.map(|csv| parse_summary(&String::from_utf8_lossy(&csv))))
+}
+
+// The client metrics of each (scenario, driver) in the summary csv.
+//
+// Columns are looked up by name since the csv includes the standard
+// deviations when the run has multiple iterations. Scenario and driver names
+// don't contain commas, so the fields are never quoted.
+fn parse_summary(csv: &str) -> BTreeMap<(String, String), Metrics> {
+    let mut lines = csv.lines();
+    let Some(header) = lines.next() else {
+        return BTreeMap::new();
+    };
+    let header: Vec<&str> = header.split(',').collect();
+    let column = |name: &str| header.iter().position(|column| *column == name);
+    let (Some(scenario), Some(driver), Some(endpoint)) =
+        (column("scenario"), column("driver"), column("endpoint"))
+    else {
+        return BTreeMap::new();
+    };
+    let (throughput, latency_p50, latency_p99) =
+        (column(THROUGHPUT), column(LATENCY_P50), column(LATENCY_P99));
+
+    lines
+        .map(|line| line.split(',').collect::<Vec<_>>())
+        .filter(|row| row.get(endpoint) == Some(&"client"))
+        .map(|row| {
+            let value = |idx: Option<usize>| row.get(idx?)?.parse::<f64>().ok();
+            let key = (
+                row.get(scenario).unwrap_or(&"").to_string(),
+                row.get(driver).unwrap_or(&"").to_string(),
+            );
+            let metrics = Metrics {
+                throughput: value(throughput),
+                latency_p50: value(latency_p50),
+                latency_p99: value(latency_p99),
+            };
+            (key, metrics)
+        })
+        .collect()
+}
+
+fn rows(
+    current: &BTreeMap<(String, String), Metrics>,
+    baseline: Option<&BTreeMap<(String, String), Metrics>>,
+) -> Vec<DriverRow> {
+    current
+        .iter()
+        .map(|(key, metrics)| {
+            let baseline = baseline.and_then(|baseline| baseline.get(key));
+            DriverRow {
+                driver: key.1.clone(),
+                throughput: format_metric(
+                    metrics.throughput,
+                    baseline.and_then(|baseline| baseline.throughput),
+                    "Mbps",
+                ),
+                latency_p50: format_metric(
+                    metrics.latency_p50,
+                    baseline.and_then(|baseline| baseline.latency_p50),
+                    "ms",
+                ),
+                latency_p99: format_metric(
+                    metrics.latency_p99,
+                    baseline.and_then(|baseline| baseline.latency_p99),
+                    "ms",
+                ),
+            }
+        })
+        .collect()
+}
+
+// eg. "942.10 Mbps (+2.3%)"
+fn format_metric(value: Option<f64>, baseline: Option<f64>, unit: &str) -> String {
+    let Some(value) = value else {
+        return "-".to_string();
+    };
+    match baseline {
+        Some(baseline) if baseline != 0.0 => {
+            let delta = (value - baseline) / baseline * 100.0;
+            format!("{value:.2} {unit} ({delta:+.1}%)")
+        }
+        _ => format!("{value:.2} {unit}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMMARY: &str = "\
+scenario,driver,endpoint,duration (s),receive throughput (Mbps),latency p50 (ms),latency p99 (ms)
+request_response,s2n-quic,client,10,950,0.5,2
+request_response,s2n-quic,server,10,50,,
+request_response,tcp,client,10,800,,
+";
+
+    #[test]
+    fn workflow_command_escape() {
+        assert_eq!(
+            escape_workflow_command("100% failed\r\nretry"),
+            "100%25 failed%0D%0Aretry"
+        );
+    }
+
+    #[test]
+    fn summary_rows() {
+        let current = parse_summary(SUMMARY);
+        assert_eq!(current.len(), 2);
+
+        let mut baseline = current.clone();
+        baseline
+            .get_mut(&("request_response".to_string(), "s2n-quic".to_string()))
+            .unwrap()
+            .throughput = Some(1000.0);
+
+        assert_eq!(
+            rows(&current, Some(&baseline)),
+            vec![
+                DriverRow {
+                    driver: "s2n-quic".to_string(),
+                    throughput: "950.00 Mbps (-5.0%)".to_string(),
+                    latency_p50: "0.50 ms (+0.0%)".to_string(),
+                    latency_p99: "2.00 ms (+0.0%)".to_string(),
+                },
+                DriverRow {
+                    driver: "tcp".to_string(),
+                    throughput: "800.00 Mbps (+0.0%)".to_string(),
+                    latency_p50: "-".to_string(),
+                    latency_p99: "-".to_string(),
+                },
+            ]
+        );
+    }
+}
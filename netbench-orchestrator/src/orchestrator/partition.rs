@@ -0,0 +1,94 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+// The AWS partition of a region, which the ARNs, the domains of the service
+// endpoints and the console links differ by.
+//
+// The partition follows from the region (eg. `cn-north-1` or
+// `us-gov-west-1`), so the orchestrator works in the China and GovCloud
+// regions without further configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+    Aws,
+    AwsCn,
+    AwsUsGov,
+}
+
+impl Partition {
+    pub fn of_region(region: &str) -> Self {
+        if region.starts_with("cn-") {
+            Partition::AwsCn
+        } else if region.starts_with("us-gov-") {
+            Partition::AwsUsGov
+        } else {
+            Partition::Aws
+        }
+    }
+
+    // The partition of the ARNs, eg. `arn:aws-cn:s3:::bucket`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Partition::Aws => "aws",
+            Partition::AwsCn => "aws-cn",
+            Partition::AwsUsGov => "aws-us-gov",
+        }
+    }
+
+    // The domain of the regional service endpoints, eg.
+    // `s3.cn-north-1.amazonaws.com.cn`
+    pub fn dns_suffix(&self) -> &'static str {
+        match self {
+            Partition::Aws | Partition::AwsUsGov => "amazonaws.com",
+            Partition::AwsCn => "amazonaws.com.cn",
+        }
+    }
+
+    // The console of the region, which is linked to from the logs and errors
+    pub fn console_url(&self, region: &str) -> String {
+        match self {
+            Partition::Aws => format!("https://{region}.console.aws.amazon.com"),
+            Partition::AwsCn => "https://console.amazonaws.cn".to_string(),
+            Partition::AwsUsGov => "https://console.amazonaws-us-gov.com".to_string(),
+        }
+    }
+
+    // The ARN of a bucket or its objects, eg. `bucket/*`
+    pub fn s3_arn(&self, resource: &str) -> String {
+        format!("arn:{}:s3:::{resource}", self.as_str())
+    }
+
+    // The ARN of an AWS managed IAM policy
+    pub fn managed_policy_arn(&self, policy: &str) -> String {
+        format!("arn:{}:iam::aws:policy/{policy}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_of_the_region() {
+        let aws = Partition::of_region("us-west-2");
+        assert_eq!(aws.s3_arn("bucket/*"), "arn:aws:s3:::bucket/*");
+        assert_eq!(
+            aws.console_url("us-west-2"),
+            "https://us-west-2.console.aws.amazon.com"
+        );
+
+        let china = Partition::of_region("cn-northwest-1");
+        assert_eq!(china, Partition::AwsCn);
+        assert_eq!(
+            china.managed_policy_arn("AmazonS3FullAccess"),
+            "arn:aws-cn:iam::aws:policy/AmazonS3FullAccess"
+        );
+        assert_eq!(china.dns_suffix(), "amazonaws.com.cn");
+
+        let gov_cloud = Partition::of_region("us-gov-west-1");
+        assert_eq!(gov_cloud.s3_arn("bucket"), "arn:aws-us-gov:s3:::bucket");
+        assert_eq!(
+            gov_cloud.console_url("us-gov-west-1"),
+            "https://console.amazonaws-us-gov.com"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::state;
+use std::{fmt::Display, path::PathBuf};
+
+// The input of `s2n-netbench report-tree`, which only contains netbench results
+// and the run's metadata
+pub const RESULTS_DIR: &str = "results";
+// The csv of the top-line metrics of each driver
+pub const SUMMARY_DIR: &str = "summary";
+// The generated report
+pub const REPORT_DIR: &str = "report";
+// The drivers' packet captures, which aren't downloaded for the report
+pub const CAPTURES_DIR: &str = "captures";
+// The flamegraphs of the profiled drivers in the report, and the page linking
+// them
+pub const FLAMEGRAPHS_DIR: &str = "flamegraphs";
+pub const FLAMEGRAPHS_HTML: &str = "flamegraphs.html";
+// The archive of the report of a private run
+pub const REPORT_ARCHIVE: &str = "report.tar.gz";
+// The dashboard page and the status document it polls
+pub const INDEX_HTML: &str = "index.html";
+pub const STATUS_JSON: &str = "status.json";
+// The orchestrator's log files, which are rotated daily
+pub const LOG_DIR: &str = "./target";
+// The files staged for the orchestrator on the controller, in the private
+// bucket. See `ControllerConfig`.
+pub const CONTROLLER_DIR: &str = "controller";
+// The russula logs on the hosts, relative to the home directory
+pub const HOST_RUSSULA_LOGS: &str = "netbench_orchestrator/target/russula*";
+
+// The layout of a run's artifacts, locally and in S3.
+//
+// Locally, the run's artifacts are kept in `<workspace_dir>/<unique_id>/`.
+// In S3 they are kept under `<unique_id>/` in the bucket for the artifact:
+//
+// <unique_id>/
+//   index.html, status.json                   the dashboard
+//   <scenario>.json                           the scenario the hosts run
+//   results/                                  the input of the report
+//     metadata.json, events.json, clock.json, coordination.json
+//     <scenario>/<driver>/[iteration-<n>/]    the netbench results and flamegraphs
+//   coordination/<scenario>/<driver>/[iteration-<n>/]
+//                                             the workers' Russula events
+//   captures/<scenario>/<driver>/[iteration-<n>/]
+//                                             the pcaps, with `--capture`
+//   summary/summary.csv
+//   report/, report.tar.gz
+//     flamegraphs.html, flamegraphs/          the flamegraphs, with `--profiler`
+//   run-summary.json, audit.jsonl
+//   controller/                               the files staged for the controller
+//     run.sh, exit_code, <flag>/<file>
+#[derive(Clone, Copy, Debug)]
+pub struct RunPaths<'a> {
+    unique_id: &'a str,
+}
+
+impl<'a> RunPaths<'a> {
+    pub fn new(unique_id: &'a str) -> Self {
+        RunPaths { unique_id }
+    }
+
+    // The local workspace of the run
+    pub fn workspace(&self) -> PathBuf {
+        workspace_root().join(self.unique_id)
+    }
+
+    pub fn audit_log(&self) -> PathBuf {
+        self.workspace().join("audit.jsonl")
+    }
+
+    pub fn run_summary(&self) -> PathBuf {
+        self.workspace().join("run-summary.json")
+    }
+
+    pub fn diagnostics_dir(&self) -> PathBuf {
+        self.workspace().join("diagnostics")
+    }
+
+    // The ssh access to the hosts, with `ssh_key_name`
+    pub fn ssh_config(&self) -> PathBuf {
+        self.workspace().join("ssh_config")
+    }
+
+    pub fn hosts_file(&self) -> PathBuf {
+        self.workspace().join("hosts.txt")
+    }
+
+    // The prefix of the run's log files in `LOG_DIR`
+    pub fn log_file_prefix(&self) -> String {
+        format!("russula_{}", self.unique_id)
+    }
+
+    // The russula logs downloaded from a host over ssh
+    pub fn host_logs_dir(&self, endpoint: &str, ip: impl Display) -> PathBuf {
+        PathBuf::from(LOG_DIR)
+            .join("logs")
+            .join(self.unique_id)
+            .join(format!("{endpoint}_{ip}"))
+    }
+
+    // The key of an artifact at the root of the run, eg. `status.json`
+    pub fn key(&self, name: &str) -> String {
+        format!("{}/{name}", self.unique_id)
+    }
+
+    // The key of a file in the results tree, eg. `events.json`
+    pub fn results_key(&self, name: &str) -> String {
+        self.key(&format!("{RESULTS_DIR}/{name}"))
+    }
+
+    // The key of a file staged for the controller, eg. `run.sh`
+    pub fn controller_key(&self, name: &str) -> String {
+        self.key(&format!("{CONTROLLER_DIR}/{name}"))
+    }
+
+    pub fn summary_key(&self) -> String {
+        self.key(&format!("{SUMMARY_DIR}/summary.csv"))
+    }
+
+    pub fn report_archive_key(&self) -> String {
+        self.key(REPORT_ARCHIVE)
+    }
+
+    // Where the netbench results of a driver are uploaded. Repeated runs are
+    // stored in a subdirectory per iteration, which the report aggregates.
+    pub fn driver_results_prefix(
+        &self,
+        scenario: &str,
+        driver: &str,
+        iteration: Option<u32>,
+    ) -> String {
+        self.driver_prefix(RESULTS_DIR, scenario, driver, iteration)
+    }
+
+    // Where the Russula events of a driver's workers are uploaded. The events
+    // are kept out of the results tree, which the report expects to only
+    // contain netbench results.
+    pub fn driver_coordination_prefix(
+        &self,
+        scenario: &str,
+        driver: &str,
+        iteration: Option<u32>,
+    ) -> String {
+        self.driver_prefix("coordination", scenario, driver, iteration)
+    }
+
+    // Where the packet captures of a driver's hosts are uploaded
+    pub fn driver_captures_prefix(
+        &self,
+        scenario: &str,
+        driver: &str,
+        iteration: Option<u32>,
+    ) -> String {
+        self.driver_prefix(CAPTURES_DIR, scenario, driver, iteration)
+    }
+
+    fn driver_prefix(
+        &self,
+        dir: &str,
+        scenario: &str,
+        driver: &str,
+        iteration: Option<u32>,
+    ) -> String {
+        let mut prefix = self.key(&format!("{dir}/{scenario}/{driver}/"));
+        if let Some(iteration) = iteration {
+            prefix.push_str(&format!("iteration-{iteration}/"));
+        }
+        prefix
+    }
+}
+
+// The local workspace of the orchestrator, which contains a workspace per run
+pub fn workspace_root() -> PathBuf {
+    PathBuf::from(&state().workspace_dir)
+}
+
+// The copy of the user's aws config with the s3 transfer settings applied
+pub fn local_aws_config() -> PathBuf {
+    workspace_root().join("aws_config")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_layout() {
+        let paths = RunPaths::new("run-1");
+        assert_eq!(
+            paths.results_key("events.json"),
+            "run-1/results/events.json"
+        );
+        assert_eq!(paths.summary_key(), "run-1/summary/summary.csv");
+        assert_eq!(
+            paths.driver_results_prefix("request_response", "s2n-quic", None),
+            "run-1/results/request_response/s2n-quic/"
+        );
+        assert_eq!(
+            paths.driver_coordination_prefix("request_response", "s2n-quic", Some(2)),
+            "run-1/coordination/request_response/s2n-quic/iteration-2/"
+        );
+        assert_eq!(
+            paths.driver_captures_prefix("request_response", "s2n-quic", None),
+            "run-1/captures/request_response/s2n-quic/"
+        );
+        assert_eq!(
+            paths.audit_log(),
+            PathBuf::from("./target/netbench/run-1/audit.jsonl")
+        );
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig, Partition};
+use aws_sdk_iam::types::PolicyEvaluationDecisionType;
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+// The actions the run needs on any resource
+const ANY_RESOURCE: &[&str] = &[
+    "ec2:RunInstances",
+    "ec2:CreateTags",
+    "ec2:DescribeInstances",
+    "ec2:TerminateInstances",
+    "ec2:DescribeSubnets",
+    "ec2:DescribeInstanceTypes",
+    "ec2:CreateSecurityGroup",
+    "ec2:DeleteSecurityGroup",
+    "ec2:AuthorizeSecurityGroupIngress",
+    "ec2:AuthorizeSecurityGroupEgress",
+    "ec2:CreatePlacementGroup",
+    "ec2:DeletePlacementGroup",
+    "iam:GetInstanceProfile",
+    "iam:PassRole",
+    "ssm:GetParameter",
+    "ssm:SendCommand",
+    "ssm:ListCommandInvocations",
+    "ssm:GetCommandInvocation",
+    "ssm:CancelCommand",
+    "ssm:DescribeInstanceInformation",
+];
+// The actions of `--fleet`
+const FLEET: &[&str] = &[
+    "ec2:CreateFleet",
+    "ec2:CreateLaunchTemplate",
+    "ec2:DeleteLaunchTemplate",
+];
+const BUCKET: &[&str] = &["s3:ListBucket"];
+const OBJECTS: &[&str] = &["s3:GetObject", "s3:PutObject"];
+// Only used for best effort reporting, so the run doesn't fail without them
+const BEST_EFFORT: &[&str] = &[
+    "logs:CreateLogStream",
+    "logs:PutLogEvents",
+    "cloudwatch:PutMetricData",
+];
+
+// An action the run needs on a resource
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Needed {
+    action: &'static str,
+    resource: String,
+    // The run fails without the action, rather than skipping what it's for
+    required: bool,
+}
+
+// Simulates the actions the run needs with the policies of the caller, and
+// fails with the actions which aren't allowed before anything is launched.
+//
+// The simulation requires `iam:SimulatePrincipalPolicy` (and `iam:GetRole`
+// for an assumed role). Without them, or for a caller which can't be
+// simulated (eg. the root user), the check is skipped with a warning.
+pub async fn check(
+    aws_config: &aws_types::SdkConfig,
+    config: &OrchestratorConfig,
+    caller_arn: &str,
+) -> OrchResult<()> {
+    let iam_client = aws_sdk_iam::Client::new(aws_config);
+    let principal_arn = match principal(caller_arn) {
+        Principal::Arn(arn) => arn,
+        Principal::Role(name) => {
+            let role = iam_client.get_role().role_name(&name).send().await;
+            match role
+                .ok()
+                .and_then(|role| role.role().map(|role| role.arn().to_string()))
+            {
+                Some(arn) => arn,
+                None => {
+                    warn!("Skipping the permission check: failed to get the role {name}");
+                    return Ok(());
+                }
+            }
+        }
+        Principal::Unsupported => {
+            warn!("Skipping the permission check: {caller_arn} can't be simulated");
+            return Ok(());
+        }
+    };
+
+    // the actions are simulated once per resource
+    let mut by_resource: BTreeMap<&str, Vec<&Needed>> = BTreeMap::new();
+    let needed = needed(config);
+    for needed in needed.iter() {
+        by_resource
+            .entry(&needed.resource)
+            .or_default()
+            .push(needed);
+    }
+    let mut denied = Vec::new();
+    for (resource, actions) in by_resource {
+        let simulation = iam_client
+            .simulate_principal_policy()
+            .policy_source_arn(&principal_arn)
+            .set_action_names(Some(
+                actions
+                    .iter()
+                    .map(|needed| needed.action.to_string())
+                    .collect(),
+            ))
+            .resource_arns(resource)
+            .send()
+            .await;
+        let simulation = match simulation {
+            Ok(simulation) => simulation,
+            Err(err) => {
+                warn!("Skipping the permission check: failed to simulate the policies of {principal_arn}. {err}");
+                return Ok(());
+            }
+        };
+        for result in simulation.evaluation_results() {
+            if result.eval_decision() == &PolicyEvaluationDecisionType::Allowed {
+                continue;
+            }
+            if let Some(needed) = actions
+                .iter()
+                .find(|needed| needed.action == result.eval_action_name())
+            {
+                denied.push((*needed).clone());
+            }
+        }
+    }
+
+    info!(
+        principal_arn = %principal_arn,
+        denied = denied.len(),
+        "Simulated the run's permissions"
+    );
+    report(&principal_arn, &denied)
+}
+
+// The caller's principal to simulate, from its `sts get-caller-identity` arn
+#[derive(Debug, PartialEq, Eq)]
+enum Principal {
+    // A user or role whose arn is the caller's arn
+    Arn(String),
+    // The role of an assumed role session, eg. an SSO session, whose arn
+    // includes a path which isn't in the session's arn
+    Role(String),
+    // The root user or a federated user
+    Unsupported,
+}
+
+fn principal(caller_arn: &str) -> Principal {
+    let resource = caller_arn.splitn(6, ':').nth(5).unwrap_or_default();
+    match resource.split('/').collect::<Vec<_>>().as_slice() {
+        ["user", ..] | ["role", ..] => Principal::Arn(caller_arn.to_string()),
+        ["assumed-role", role, _session] => Principal::Role(role.to_string()),
+        _ => Principal::Unsupported,
+    }
+}
+
+// The actions of the run, on the buckets of the cdk stack
+fn needed(config: &OrchestratorConfig) -> Vec<Needed> {
+    let cdk_config = &config.cdk_config;
+    let partition = Partition::of_region(cdk_config.netbench_primary_region());
+    let any = |actions: &[&'static str], required: bool| {
+        actions
+            .iter()
+            .map(|&action| Needed {
+                action,
+                resource: "*".to_string(),
+                required,
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut needed = any(ANY_RESOURCE, true);
+    if config.fleet.is_enabled() {
+        needed.extend(any(FLEET, true));
+    }
+    needed.extend(any(BEST_EFFORT, false));
+    if cdk_config.netbench_cloudfront_distribution_id().is_some() {
+        needed.extend(any(&["cloudfront:CreateInvalidation"], false));
+    }
+    if !config.notify.emails().is_empty() {
+        needed.extend(any(&["ses:SendEmail"], false));
+    }
+    for bucket in [
+        cdk_config.netbench_runner_public_s3_bucket(),
+        cdk_config.netbench_runner_private_s3_bucket(),
+    ] {
+        for (actions, resource) in [
+            (BUCKET, partition.s3_arn(bucket)),
+            (OBJECTS, partition.s3_arn(&format!("{bucket}/*"))),
+        ] {
+            needed.extend(actions.iter().map(|&action| Needed {
+                action,
+                resource: resource.clone(),
+                required: true,
+            }));
+        }
+    }
+    needed
+}
+
+// Fails with the denied actions which the run needs, and warns about the
+// others
+fn report(principal_arn: &str, denied: &[Needed]) -> OrchResult<()> {
+    let describe = |required: bool| {
+        denied
+            .iter()
+            .filter(|needed| needed.required == required)
+            .map(|needed| format!("  {} on {}", needed.action, needed.resource))
+            .collect::<Vec<_>>()
+    };
+    let optional = describe(false);
+    if !optional.is_empty() {
+        warn!(
+            "{principal_arn} isn't allowed the actions of the best effort reporting, which is skipped:\n{}",
+            optional.join("\n")
+        );
+    }
+    let missing = describe(true);
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(OrchError::new(
+        ErrorKind::Iam,
+        format!(
+            "{principal_arn} is missing the permissions of the run:\n{}",
+            missing.join("\n")
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn principal_of_the_caller() {
+        assert_eq!(
+            principal("arn:aws:iam::123456789012:user/netbench"),
+            Principal::Arn("arn:aws:iam::123456789012:user/netbench".to_string())
+        );
+        assert_eq!(
+            principal(
+                "arn:aws:sts::123456789012:assumed-role/AWSReservedSSO_Admin_1a2b/me@example.com"
+            ),
+            Principal::Role("AWSReservedSSO_Admin_1a2b".to_string())
+        );
+        assert_eq!(
+            principal("arn:aws:iam::123456789012:root"),
+            Principal::Unsupported
+        );
+    }
+
+    #[test]
+    fn report_the_missing_permissions() {
+        let denied = |action, required| Needed {
+            action,
+            resource: "*".to_string(),
+            required,
+        };
+        assert!(report("arn", &[denied("logs:PutLogEvents", false)]).is_ok());
+
+        let err = report(
+            "arn",
+            &[
+                denied("ec2:RunInstances", true),
+                denied("logs:PutLogEvents", false),
+                denied("iam:PassRole", true),
+            ],
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("  ec2:RunInstances on *\n  iam:PassRole on *"));
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::S3Api,
+    orchestrator::{OrchestratorConfig, RunPaths},
+    s3_utils::upload_object,
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    time::{Duration, SystemTime},
+};
+use tracing::warn;
+
+#[derive(Debug, PartialEq, Serialize)]
+struct PhaseTiming {
+    name: String,
+    duration_ms: u64,
+    // The number of times the phase ran, eg. once per driver pair
+    count: u32,
+}
+
+// The wall-clock time of each phase of a run.
+//
+// Unlike the events timeline, a phase which repeats (eg. uploading the
+// results of each driver pair) is added up, so that the summary shows where
+// the run spent its time. Printed at the end of the run and uploaded as
+// `run-summary.json`.
+#[derive(Debug)]
+pub struct PhaseSummary {
+    origin: SystemTime,
+    phases: Vec<PhaseTiming>,
+}
+
+impl Default for PhaseSummary {
+    fn default() -> Self {
+        PhaseSummary {
+            origin: SystemTime::now(),
+            phases: Vec::new(),
+        }
+    }
+}
+
+impl PhaseSummary {
+    pub fn add(&mut self, name: impl Into<String>, duration: Duration) {
+        let name = name.into();
+        let duration_ms = duration.as_millis() as u64;
+        match self.phases.iter_mut().find(|phase| phase.name == name) {
+            Some(phase) => {
+                phase.duration_ms += duration_ms;
+                phase.count += 1;
+            }
+            None => self.phases.push(PhaseTiming {
+                name,
+                duration_ms,
+                count: 1,
+            }),
+        }
+    }
+
+    // eg.
+    //
+    // phase                      duration    share
+    // launch                       95.2s    10.1%
+    // configure                   412.0s    43.6%
+    // configure: build s2n-quic   301.5s    31.9%
+    pub fn table(&self) -> String {
+        let total_ms = self.total_ms();
+        let width = self
+            .phases
+            .iter()
+            .map(|phase| phase.name.len() + count_suffix(phase.count).len())
+            .max()
+            .unwrap_or_default()
+            .max("total".len());
+
+        let mut table = format!("{:width$}  {:>9}  {:>6}\n", "phase", "duration", "share");
+        for phase in &self.phases {
+            let name = format!("{}{}", phase.name, count_suffix(phase.count));
+            let _ = writeln!(
+                table,
+                "{name:width$}  {:>8.1}s  {:>5.1}%",
+                phase.duration_ms as f64 / 1000.0,
+                phase.duration_ms as f64 / total_ms.max(1) as f64 * 100.0,
+            );
+        }
+        let _ = write!(
+            table,
+            "{:width$}  {:>8.1}s",
+            "total",
+            total_ms as f64 / 1000.0
+        );
+        table
+    }
+
+    // Writes `run-summary.json` to the run's workspace and uploads it next to
+    // the run's results. This function is best effort and will not return an
+    // error.
+    pub async fn upload(
+        &self,
+        s3_client: &impl S3Api,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+        status: &str,
+    ) {
+        let body = serde_json::to_vec_pretty(&serde_json::json!({
+            "unique_id": unique_id,
+            "label": config.run.label(),
+            "description": config.run.description(),
+            "status": status,
+            "total_ms": self.total_ms(),
+            "phases": self.phases,
+        }))
+        .expect("failed to serialize the run summary");
+
+        let path = RunPaths::new(unique_id).run_summary();
+        let res = std::fs::create_dir_all(path.parent().expect("run summary has a parent dir"))
+            .and_then(|_| std::fs::write(&path, &body));
+        if let Err(err) = res {
+            warn!("Failed to write {:?}. {err}", path);
+        }
+
+        let res = upload_object(
+            s3_client,
+            config.results_bucket(),
+            ByteStream::from(Bytes::from(body)),
+            &RunPaths::new(unique_id).key("run-summary.json"),
+        )
+        .await;
+        if let Err(err) = res {
+            warn!("Failed to upload the run summary. {err}");
+        }
+    }
+
+    fn total_ms(&self) -> u64 {
+        self.origin.elapsed().unwrap_or_default().as_millis() as u64
+    }
+}
+
+fn count_suffix(count: u32) -> String {
+    match count {
+        1 => String::new(),
+        count => format!(" (x{count})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_phases_are_added_up() {
+        let mut summary = PhaseSummary::default();
+        summary.add("launch", Duration::from_secs(90));
+        summary.add("upload results", Duration::from_millis(1500));
+        summary.add("upload results", Duration::from_millis(2500));
+
+        assert_eq!(
+            summary.phases,
+            vec![
+                PhaseTiming {
+                    name: "launch".to_string(),
+                    duration_ms: 90_000,
+                    count: 1,
+                },
+                PhaseTiming {
+                    name: "upload results".to_string(),
+                    duration_ms: 4000,
+                    count: 2,
+                },
+            ]
+        );
+        let table = summary.table();
+        assert!(table.contains("upload results (x2)"));
+        assert!(table.contains("4.0s"));
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    orchestrator::{ErrorKind, OrchError, OrchResult},
+    ssm_utils::Protocol,
+};
+use std::{collections::BTreeSet, ops::RangeInclusive};
+
+// The ports of a run.
+//
+// The security group rules, the port the Russula workers listen on and the
+// arguments of the worker commands are all derived from the plan, so a port
+// is only configured in one place.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortPlan {
+    // The workers listen for their coordinator on this port
+    russula: u16,
+    // The transport and port of each driver. The drivers of different pairs
+    // can share a port, since the pairs are run one at a time.
+    drivers: BTreeSet<(Protocol, u16)>,
+    // The servers sharing a host listen on consecutive ports starting at the
+    // driver's port. See `ServerMap`.
+    ports_per_host: u16,
+    // Opened to the local host, eg. for metrics exporters started by a host
+    // hook
+    metrics: BTreeSet<u16>,
+}
+
+impl PortPlan {
+    pub fn new(
+        russula: u16,
+        drivers: BTreeSet<(Protocol, u16)>,
+        ports_per_host: u16,
+        metrics: BTreeSet<u16>,
+    ) -> OrchResult<Self> {
+        let plan = PortPlan {
+            russula,
+            drivers,
+            ports_per_host: ports_per_host.max(1),
+            metrics,
+        };
+        plan.check()?;
+        Ok(plan)
+    }
+
+    pub fn russula(&self) -> u16 {
+        self.russula
+    }
+
+    // The ports each driver listens on, which are opened between the hosts
+    pub fn driver_ranges(&self) -> impl Iterator<Item = (Protocol, RangeInclusive<u16>)> + '_ {
+        self.drivers.iter().map(|(protocol, port)| {
+            let last_port = port.saturating_add(self.ports_per_host - 1);
+            (*protocol, *port..=last_port)
+        })
+    }
+
+    pub fn metrics(&self) -> impl Iterator<Item = u16> + '_ {
+        self.metrics.iter().copied()
+    }
+
+    // The Russula and metrics ports are bound on every host while the drivers
+    // run, so they can't be used by a driver of the same transport.
+    fn check(&self) -> OrchResult<()> {
+        let conflict = |port: u16, name: &str, other: &str| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("port {port} is used as both the {name} port and {other}"),
+            )
+        };
+        if self.metrics.contains(&self.russula) {
+            return Err(conflict(self.russula, "russula", "a metrics port"));
+        }
+        let reserved = std::iter::once(("russula", self.russula))
+            .chain(self.metrics().map(|port| ("metrics", port)));
+        for (name, port) in reserved {
+            let driver = self
+                .driver_ranges()
+                .find(|(protocol, ports)| *protocol == Protocol::Tcp && ports.contains(&port));
+            if driver.is_some() {
+                return Err(conflict(port, name, "a tcp driver port"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_dont_overlap() {
+        let drivers = BTreeSet::from([(Protocol::Tcp, 4433), (Protocol::Udp, 9000)]);
+        let plan = PortPlan::new(9000, drivers.clone(), 3, BTreeSet::from([9100])).unwrap();
+        assert_eq!(
+            plan.driver_ranges().collect::<Vec<_>>(),
+            vec![(Protocol::Tcp, 4433..=4435), (Protocol::Udp, 9000..=9002)]
+        );
+
+        assert!(PortPlan::new(4434, drivers.clone(), 3, BTreeSet::new()).is_err());
+        assert!(PortPlan::new(4436, drivers.clone(), 3, BTreeSet::new()).is_ok());
+        assert!(PortPlan::new(9000, drivers.clone(), 3, BTreeSet::from([4435])).is_err());
+        assert!(PortPlan::new(9000, drivers, 3, BTreeSet::from([9000])).is_err());
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit,
+    aws_api::{AwsCli, Deletion, Ec2Api, RetainedResource, S3Api},
+    ec2_utils::EXPIRES_AT_TAG,
+    orchestrator::{
+        paths::RESULTS_DIR,
+        runs::{self, RunEntry},
+        CdkConfig, OrchResult, RunPaths,
+    },
+    progress,
+};
+use clap::Args;
+use core::time::Duration;
+use std::time::SystemTime;
+use tracing::warn;
+
+// Deletes the results of old runs from the public bucket
+#[derive(Clone, Debug, Args)]
+pub struct PruneArgs {
+    /// Prune the runs started longer ago than this (eg. "90d")
+    #[arg(long, value_parser = humantime::parse_duration)]
+    older_than: Duration,
+
+    /// Only delete the raw netbench results and Russula events, keeping the
+    /// report, the summary, the dashboard and the run's metadata
+    #[arg(long)]
+    keep_reports: bool,
+
+    /// List the runs and the number of objects which would be deleted,
+    /// without deleting them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+// Prunes the old runs, and the resources of the hosts kept with
+// `--keep-servers` which expired.
+pub async fn prune(
+    s3_client: &impl S3Api,
+    ec2_client: &impl Ec2Api,
+    cdk_config: &CdkConfig,
+    aws_cli: &AwsCli,
+    args: &PruneArgs,
+    now: SystemTime,
+) -> OrchResult<()> {
+    prune_runs(s3_client, cdk_config, aws_cli, args, now).await?;
+    prune_kept_resources(ec2_client, args, now).await
+}
+
+// Prunes the runs in the runs index which started before `--older-than`.
+//
+// Without `--keep-reports` every artifact of the run is deleted and the run is
+// removed from the index. Runs which aren't in the index (eg. private runs)
+// aren't pruned.
+async fn prune_runs(
+    s3_client: &impl S3Api,
+    cdk_config: &CdkConfig,
+    aws_cli: &AwsCli,
+    args: &PruneArgs,
+    now: SystemTime,
+) -> OrchResult<()> {
+    let bucket = cdk_config.netbench_runner_public_s3_bucket();
+    let cutoff = now
+        .checked_sub(args.older_than)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut index = runs::load_runs_index(s3_client, cdk_config).await?;
+    let (old, kept): (Vec<_>, Vec<_>) = index
+        .runs
+        .drain(..)
+        .partition(|run| started_before(run, cutoff));
+
+    let mut total = 0;
+    let mut pruned = Vec::new();
+    for run in old {
+        let paths = RunPaths::new(&run.unique_id);
+        let keys: Vec<_> = s3_client
+            .list_keys(bucket, &paths.key(""))
+            .await?
+            .into_iter()
+            .filter(|key| !args.keep_reports || is_raw_result(&run.unique_id, key))
+            .collect();
+        progress::output!(
+            "{} ({}): {} objects{}",
+            run.unique_id,
+            run.date,
+            keys.len(),
+            if args.dry_run { " (dry run)" } else { "" }
+        );
+        total += keys.len();
+        if !args.dry_run && !keys.is_empty() {
+            s3_client.delete_objects(bucket, &keys).await?;
+            audit::record!("s3", "delete_objects", bucket, unique_id = %run.unique_id, objects = keys.len());
+        }
+        pruned.push(run);
+    }
+    progress::output!(
+        "Pruned {} runs and {total} objects from s3://{bucket}{}",
+        pruned.len(),
+        if args.dry_run { " (dry run)" } else { "" }
+    );
+
+    // the reports of runs pruned with `--keep-reports` are still linked from
+    // the index
+    if args.dry_run || args.keep_reports || pruned.is_empty() {
+        return Ok(());
+    }
+    index.runs = kept;
+    runs::save_runs_index(s3_client, cdk_config, aws_cli, index).await
+}
+
+// Deletes the security groups, placement groups and launch templates retained
+// by kept hosts, once they're tagged as expired. The resources of hosts which
+// are still terminating are in use, and are left for a later prune.
+async fn prune_kept_resources(
+    ec2_client: &impl Ec2Api,
+    args: &PruneArgs,
+    now: SystemTime,
+) -> OrchResult<()> {
+    let mut resources: Vec<_> = ec2_client
+        .describe_tagged_resources(EXPIRES_AT_TAG)
+        .await?
+        .into_iter()
+        .filter(
+            |(resource, expires_at)| match humantime::parse_rfc3339(expires_at) {
+                Ok(expires_at) => expires_at < now,
+                Err(err) => {
+                    warn!("Skipping {resource} with an invalid expiry {expires_at}. {err}");
+                    false
+                }
+            },
+        )
+        .map(|(resource, _)| resource)
+        .collect();
+    // the launch templates and placement groups before the security groups,
+    // like the cleanup of a run
+    resources.sort_by_key(|resource| match resource {
+        RetainedResource::LaunchTemplate { .. } => 0,
+        RetainedResource::PlacementGroup { .. } => 1,
+        RetainedResource::SecurityGroup { .. } => 2,
+    });
+
+    for resource in resources {
+        if args.dry_run {
+            progress::output!("Expired: {resource} (dry run)");
+            continue;
+        }
+        let deletion = match &resource {
+            RetainedResource::LaunchTemplate { id } => ec2_client
+                .delete_launch_template(id)
+                .await
+                .map(|_| Deletion::Deleted),
+            RetainedResource::PlacementGroup { name } => {
+                ec2_client.delete_placement_group(name).await
+            }
+            RetainedResource::SecurityGroup { id } => ec2_client.delete_security_group(id).await,
+        };
+        match deletion {
+            Ok(Deletion::Deleted) => {
+                progress::output!("Expired: deleted {resource}");
+                audit::record!("ec2", "delete_expired", resource = %resource);
+            }
+            Ok(Deletion::InUse) => {
+                progress::output!("Expired: {resource} is still in use")
+            }
+            Err(err) => warn!("Failed to delete {resource}. {err}"),
+        }
+    }
+    Ok(())
+}
+
+// Runs whose date can't be parsed are kept
+fn started_before(run: &RunEntry, cutoff: SystemTime) -> bool {
+    match humantime::parse_rfc3339(&run.date) {
+        Ok(started) => started < cutoff,
+        Err(err) => {
+            warn!(
+                "Skipping {} with an invalid date {}. {err}",
+                run.unique_id, run.date
+            );
+            false
+        }
+    }
+}
+
+// The netbench results and the workers' Russula events, which are only used
+// to generate the report. The files at the root of the results tree (eg.
+// `metadata.json`) describe the run, so they're kept with the report.
+fn is_raw_result(unique_id: &str, key: &str) -> bool {
+    let Some(key) = key.strip_prefix(&format!("{unique_id}/")) else {
+        return false;
+    };
+    let is_under = |dir: &str| {
+        key.strip_prefix(dir)
+            .and_then(|key| key.strip_prefix('/'))
+            .is_some_and(|key| key.contains('/'))
+    };
+    key.starts_with("coordination/") || is_under(RESULTS_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws_api::mock::{MockEc2, MockS3};
+    use aws_sdk_s3::primitives::ByteStream;
+    use bytes::Bytes;
+
+    fn run(unique_id: &str, date: &str) -> RunEntry {
+        RunEntry {
+            unique_id: unique_id.to_string(),
+            label: None,
+            description: None,
+            date: date.to_string(),
+            scenario: "request_response.json".to_string(),
+            drivers: vec![],
+            status: "finished".to_string(),
+            url: String::new(),
+            report_url: None,
+        }
+    }
+
+    async fn put(s3: &MockS3, key: &str) {
+        s3.put_object("", key, "", ByteStream::from(Bytes::new()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn prunes_old_runs() {
+        let s3 = MockS3::default();
+        let ec2 = MockEc2::default();
+        let cdk_config = CdkConfig::default();
+        let index = runs::RunsIndex {
+            runs: vec![
+                run("old", "2024-01-01T00:00:00Z"),
+                run("new", "2024-06-01T00:00:00Z"),
+            ],
+        };
+        runs::save_runs_index(&s3, &cdk_config, &AwsCli::default(), index)
+            .await
+            .unwrap();
+        for id in ["old", "new"] {
+            put(&s3, &format!("{id}/index.html")).await;
+            put(&s3, &format!("{id}/results/metadata.json")).await;
+            put(&s3, &format!("{id}/results/rr/s2n-quic/server.json")).await;
+            put(&s3, &format!("{id}/coordination/rr/s2n-quic/events.json")).await;
+            put(&s3, &format!("{id}/report/index.html")).await;
+        }
+        let now = humantime::parse_rfc3339("2024-06-02T00:00:00Z").unwrap();
+        let mut args = PruneArgs {
+            older_than: Duration::from_secs(90 * 24 * 60 * 60),
+            keep_reports: true,
+            dry_run: true,
+        };
+        let keys = |prefix: &str| {
+            let objects = s3.objects.lock().unwrap();
+            objects
+                .keys()
+                .filter(|(_, key)| key.starts_with(prefix))
+                .count()
+        };
+
+        prune(&s3, &ec2, &cdk_config, &AwsCli::default(), &args, now)
+            .await
+            .unwrap();
+        assert_eq!(keys("old/"), 5);
+
+        args.dry_run = false;
+        prune(&s3, &ec2, &cdk_config, &AwsCli::default(), &args, now)
+            .await
+            .unwrap();
+        assert_eq!(keys("old/"), 3);
+        assert!(s3.object("", "old/report/index.html").is_some());
+        assert_eq!(keys("new/"), 5);
+        let index = runs::load_runs_index(&s3, &cdk_config).await.unwrap();
+        assert_eq!(index.runs.len(), 2);
+
+        args.keep_reports = false;
+        prune(&s3, &ec2, &cdk_config, &AwsCli::default(), &args, now)
+            .await
+            .unwrap();
+        assert_eq!(keys("old/"), 0);
+        assert_eq!(keys("new/"), 5);
+        let index = runs::load_runs_index(&s3, &cdk_config).await.unwrap();
+        assert_eq!(index.runs.len(), 1);
+        assert_eq!(index.runs[0].unique_id, "new");
+    }
+
+    #[tokio::test]
+    async fn prunes_expired_kept_resources() {
+        let ec2 = MockEc2::default();
+        let expired = ec2.create_security_group("expired", "vpc-0").await.unwrap();
+        let placement_group = ec2.create_placement_group("expired").await.unwrap();
+        let live = ec2.create_security_group("live", "vpc-0").await.unwrap();
+        let expires_at = |date: &str| vec![(EXPIRES_AT_TAG.to_string(), date.to_string())];
+        ec2.create_tags(
+            vec![
+                expired.clone(),
+                placement_group.group_id().unwrap().to_string(),
+            ],
+            expires_at("2024-06-01T00:00:00Z"),
+        )
+        .await
+        .unwrap();
+        ec2.create_tags(vec![live.clone()], expires_at("2024-06-03T00:00:00Z"))
+            .await
+            .unwrap();
+        let now = humantime::parse_rfc3339("2024-06-02T00:00:00Z").unwrap();
+        let mut args = PruneArgs {
+            older_than: Duration::from_secs(90 * 24 * 60 * 60),
+            keep_reports: false,
+            dry_run: true,
+        };
+
+        prune_kept_resources(&ec2, &args, now).await.unwrap();
+        assert_eq!(ec2.state.lock().unwrap().security_groups.len(), 2);
+
+        args.dry_run = false;
+        prune_kept_resources(&ec2, &args, now).await.unwrap();
+        let state = ec2.state.lock().unwrap();
+        assert_eq!(state.security_groups, vec![live]);
+        assert!(state.placement_groups.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig, Partition};
+use aws_sdk_ec2::types::{Filter, InstanceType};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+// The on-demand vCPU quotas of EC2, by the prefix of the instance families
+// they limit. The prefixes are matched in order, so the longer ones come
+// first. The remaining families are limited by the standard quota.
+const QUOTAS: &[(&[&str], &str, &str)] = &[
+    (&["inf"], "L-1945791B", "Running On-Demand Inf instances"),
+    (&["trn"], "L-2C3B7624", "Running On-Demand Trn instances"),
+    (&["dl"], "L-6E869C2A", "Running On-Demand DL instances"),
+    (&["hpc"], "L-F7808C92", "Running On-Demand HPC instances"),
+    (
+        &["vt", "g"],
+        "L-DB2E81BA",
+        "Running On-Demand G and VT instances",
+    ),
+    (&["p"], "L-417A185B", "Running On-Demand P instances"),
+    (&["x"], "L-7295265B", "Running On-Demand X instances"),
+    (&["f"], "L-74FC7D96", "Running On-Demand F instances"),
+    (
+        &["u-"],
+        "L-43DA4232",
+        "Running On-Demand High Memory instances",
+    ),
+];
+const STANDARD: (&str, &str) = (
+    "L-1216C47A",
+    "Running On-Demand Standard (A, C, D, H, I, M, R, T, Z) instances",
+);
+
+// The vCPUs of an on-demand quota, which are launched by the run or already
+// running in the account
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Usage {
+    launched: u32,
+    running: u32,
+}
+
+// Checks that the on-demand vCPU quotas of the region leave room for the
+// hosts of the run (and the controller it launches), so that a run over the
+// quota fails before any host is launched rather than part way through the
+// launch.
+//
+// The instances already running in the account count against the same
+// quotas. Without the `servicequotas:GetServiceQuota` permission, the check is
+// skipped with a warning.
+pub async fn check(
+    aws_config: &aws_types::SdkConfig,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    let mut launched: Vec<&str> = config
+        .server_config
+        .iter()
+        .chain(config.client_config.iter())
+        .map(|host| host.instance_type().as_str())
+        .collect();
+    if config.controller.is_enabled() && config.controller.instance().is_none() {
+        launched.push(config.controller.instance_type());
+    }
+    if launched.is_empty() {
+        return Ok(());
+    }
+
+    let ec2_client = aws_sdk_ec2::Client::new(aws_config);
+    let running = running_instance_types(&ec2_client).await?;
+    let vcpus = instance_vcpus(
+        &ec2_client,
+        launched
+            .iter()
+            .copied()
+            .chain(running.iter().map(String::as_str)),
+    )
+    .await?;
+    let mut usage: BTreeMap<(&str, &str), Usage> = BTreeMap::new();
+    for instance_type in &launched {
+        usage.entry(quota(instance_type)).or_default().launched += vcpus[*instance_type];
+    }
+    for instance_type in &running {
+        // the run only launches instances of some quotas
+        if let Some(usage) = usage.get_mut(&quota(instance_type)) {
+            usage.running += vcpus.get(instance_type).copied().unwrap_or_default();
+        }
+    }
+
+    let quotas_client = aws_sdk_servicequotas::Client::new(aws_config);
+    let region = aws_config
+        .region()
+        .map(|region| region.as_ref())
+        .unwrap_or_default();
+    let mut exceeded = Vec::new();
+    for ((code, name), usage) in usage {
+        let quota = match quotas_client
+            .get_service_quota()
+            .service_code("ec2")
+            .quota_code(code)
+            .send()
+            .await
+        {
+            Ok(output) => output.quota().and_then(|quota| quota.value()),
+            Err(err) => {
+                warn!("Skipping the vCPU quota check of {name}: failed to get the quota. {err}");
+                continue;
+            }
+        };
+        let Some(quota) = quota else {
+            continue;
+        };
+        info!(
+            quota = name,
+            limit = quota,
+            launched = usage.launched,
+            running = usage.running,
+            "vCPU quota"
+        );
+        if let Some(message) = exceeds(code, name, &usage, quota as u32, region) {
+            exceeded.push(message);
+        }
+    }
+    if exceeded.is_empty() {
+        return Ok(());
+    }
+    Err(OrchError::new(ErrorKind::Ec2, exceeded.join("\n")))
+}
+
+// The code and name of the on-demand vCPU quota of an instance type
+fn quota(instance_type: &str) -> (&'static str, &'static str) {
+    QUOTAS
+        .iter()
+        .find(|(prefixes, _, _)| {
+            prefixes
+                .iter()
+                .any(|prefix| instance_type.starts_with(prefix))
+        })
+        .map_or(STANDARD, |(_, code, name)| (*code, *name))
+}
+
+// The types of the on-demand instances running in the region. Spot instances
+// are limited by separate quotas.
+async fn running_instance_types(ec2_client: &aws_sdk_ec2::Client) -> OrchResult<Vec<String>> {
+    let mut instance_types = Vec::new();
+    let mut pages = ec2_client
+        .describe_instances()
+        .filters(
+            Filter::builder()
+                .name("instance-state-name")
+                .values("pending")
+                .values("running")
+                .build(),
+        )
+        .into_paginator()
+        .send();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            OrchError::sdk(
+                ErrorKind::Ec2,
+                "Failed to describe the running instances",
+                err,
+            )
+        })?;
+        instance_types.extend(
+            page.reservations()
+                .iter()
+                .flat_map(|reservation| reservation.instances())
+                .filter(|instance| instance.instance_lifecycle().is_none())
+                .filter_map(|instance| instance.instance_type())
+                .map(|instance_type| instance_type.as_str().to_string()),
+        );
+    }
+    Ok(instance_types)
+}
+
+// The default vCPUs of each instance type
+async fn instance_vcpus(
+    ec2_client: &aws_sdk_ec2::Client,
+    instance_types: impl Iterator<Item = &str>,
+) -> OrchResult<HashMap<String, u32>> {
+    let mut instance_types: Vec<String> = instance_types.map(str::to_string).collect();
+    instance_types.sort();
+    instance_types.dedup();
+
+    let mut vcpus = HashMap::new();
+    // at most 100 instance types are described per request
+    for chunk in instance_types.chunks(100) {
+        let output = ec2_client
+            .describe_instance_types()
+            .set_instance_types(Some(
+                chunk
+                    .iter()
+                    .map(|instance_type| InstanceType::from(instance_type.as_str()))
+                    .collect(),
+            ))
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to describe the instance types {chunk:?}"),
+                    err,
+                )
+            })?;
+        for info in output.instance_types() {
+            let (Some(instance_type), Some(default_vcpus)) = (
+                info.instance_type(),
+                info.v_cpu_info().and_then(|vcpu| vcpu.default_v_cpus()),
+            ) else {
+                continue;
+            };
+            vcpus.insert(
+                instance_type.as_str().to_string(),
+                default_vcpus.max(0) as u32,
+            );
+        }
+    }
+    if let Some(unknown) = instance_types
+        .iter()
+        .find(|instance_type| !vcpus.contains_key(*instance_type))
+    {
+        return Err(OrchError::new(
+            ErrorKind::Ec2,
+            format!("Unknown instance type {unknown}"),
+        ));
+    }
+    Ok(vcpus)
+}
+
+// Describes how to raise a quota which doesn't leave room for the run
+fn exceeds(code: &str, name: &str, usage: &Usage, quota: u32, region: &str) -> Option<String> {
+    let needed = usage.launched + usage.running;
+    if needed <= quota {
+        return None;
+    }
+    Some(format!(
+        "The run launches {} vCPUs of {name} and {} are already running, but the quota is {quota}. Request a quota increase for {code} to at least {needed} vCPUs: {}/servicequotas/home/services/ec2/quotas/{code}",
+        usage.launched,
+        usage.running,
+        Partition::of_region(region).console_url(region)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_of_the_instance_type() {
+        assert_eq!(quota("c5.4xlarge").0, "L-1216C47A");
+        assert_eq!(quota("inf2.xlarge").0, "L-1945791B");
+        assert_eq!(quota("i4i.large").0, "L-1216C47A");
+        assert_eq!(quota("g5.xlarge").0, "L-DB2E81BA");
+        assert_eq!(quota("hpc7g.4xlarge").0, "L-F7808C92");
+        assert_eq!(quota("u-6tb1.metal").0, "L-43DA4232");
+    }
+
+    #[test]
+    fn exceeded_quota() {
+        let usage = Usage {
+            launched: 64,
+            running: 16,
+        };
+        assert_eq!(
+            exceeds("L-1216C47A", "Standard", &usage, 80, "us-west-2"),
+            None
+        );
+
+        let message = exceeds("L-1216C47A", "Standard", &usage, 32, "us-west-2").unwrap();
+        assert!(message.starts_with(
+            "The run launches 64 vCPUs of Standard and 16 are already running, but the quota is 32. Request a quota increase for L-1216C47A to at least 80 vCPUs"
+        ));
+    }
+}
@@ -1,13 +1,29 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{ec2_utils::InfraDetail, orchestrator::OrchestratorConfig, s3_utils, OrchResult};
-use aws_sdk_s3::primitives::{ByteStream, SdkBody};
-use std::{path::Path, process::Command};
-use tracing::{debug, info, trace};
+use crate::{
+    audit,
+    aws_api::{AwsCli, S3Api},
+    ec2_utils::InfraDetail,
+    orchestrator::{
+        dashboard::RunPhase,
+        paths::{
+            CAPTURES_DIR, FLAMEGRAPHS_DIR, FLAMEGRAPHS_HTML, HOST_RUSSULA_LOGS, REPORT_ARCHIVE,
+            REPORT_DIR, RESULTS_DIR, SUMMARY_DIR,
+        },
+        CdkConfig, OrchestratorConfig, RunPaths,
+    },
+    progress, s3_utils, ErrorKind, OrchError, OrchResult,
+};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tracing::{debug, info, instrument, trace, warn};
 
+#[instrument(skip_all, fields(phase = RunPhase::Reporting.as_str()))]
 pub async fn generate_report(
-    s3_client: &aws_sdk_s3::Client,
+    s3_client: &impl S3Api,
     unique_id: &str,
     infra: &InfraDetail,
     config: &OrchestratorConfig,
@@ -15,138 +31,351 @@ pub async fn generate_report(
     let tmp_dir = tempfile::Builder::new()
         .prefix(unique_id)
         .tempdir()
-        .expect("failed to create temp dir")
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Report, "Failed to create the report dir").with_source(err)
+        })?
         .into_path();
-    let tmp_dir = tmp_dir.to_str().expect("failed to create temp dir");
+    let tmp_dir = tmp_dir.to_str().ok_or_else(|| {
+        OrchError::new(
+            ErrorKind::Report,
+            format!("The report dir {:?} isn't valid utf-8", tmp_dir),
+        )
+    })?;
 
+    let bar = progress::spinner("Report: downloading results from s3");
     download_results(unique_id, config, tmp_dir).await?;
+    bar.finish();
+    let bar = progress::spinner("Report: generating report");
     generate_report_from_results(tmp_dir).await?;
+    generate_summary_from_results(tmp_dir).await?;
+    let flamegraphs = link_flamegraphs(tmp_dir)?;
+    bar.finish();
+    let bar = progress::spinner("Report: uploading report to s3");
+    if config.report.is_private() {
+        archive_report(tmp_dir)?;
+    }
     upload_report_to_s3(unique_id, config, tmp_dir).await?;
-    update_report_url(s3_client, unique_id, config).await?;
+    invalidate_cloudfront_cache(
+        &format!("/{}", RunPaths::new(unique_id).key("*")),
+        &config.cdk_config,
+        &config.aws_cli,
+    )
+    .await?;
+    bar.finish();
 
-    println!("Report Finished!: Successful: true");
-    println!("URL: {}/report/index.html", config.cf_url(unique_id));
-    info!("Report Finished!: Successful: true");
-    info!("URL: {}/report/index.html", config.cf_url(unique_id));
+    let url = match config.report.is_private() {
+        true => private_report_link(s3_client, unique_id, config).await?,
+        false => format!("{}/report/index.html", config.cf_url(unique_id)),
+    };
+    progress::output!("Report Finished!: Successful: true");
+    progress::output!("URL: {url}");
+    if flamegraphs > 0 && !config.report.is_private() {
+        progress::output!(
+            "Flamegraphs: {}/{REPORT_DIR}/{FLAMEGRAPHS_HTML}",
+            config.cf_url(unique_id)
+        );
+    }
 
     download_remote_logs(unique_id, infra);
 
     Ok(())
 }
 
+// The report of a private run is shared as an archive, since its pages load
+// the results relative to `index.html` and a presigned link only grants access
+// to a single object.
+fn archive_report(tmp_dir: &str) -> OrchResult<()> {
+    let mut cmd = Command::new("tar");
+    cmd.args([
+        "-czf",
+        &format!("{tmp_dir}/{REPORT_ARCHIVE}"),
+        "-C",
+        tmp_dir,
+        REPORT_DIR,
+    ]);
+    debug!("{:?}", cmd);
+    let output = cmd.output().map_err(|err| {
+        OrchError::new(ErrorKind::Report, "Failed to archive the report").with_source(err)
+    })?;
+    if !output.status.success() {
+        return Err(OrchError::new(
+            ErrorKind::Report,
+            format!(
+                "Failed to archive the report: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// A time-limited link to download the archived report of a private run.
+pub async fn private_report_link(
+    s3_client: &impl S3Api,
+    unique_id: &str,
+    config: &OrchestratorConfig,
+) -> OrchResult<String> {
+    let link = s3_client
+        .presign_get_object(
+            config.results_bucket(),
+            &RunPaths::new(unique_id).report_archive_key(),
+            config.report.link_expiry(),
+        )
+        .await?;
+    audit::record!(
+        "s3",
+        "presign_report",
+        unique_id,
+        expiry_secs = config.report.link_expiry().as_secs()
+    );
+    Ok(link)
+}
+
 async fn upload_report_to_s3(
     unique_id: &str,
     config: &OrchestratorConfig,
     tmp_dir: &str,
 ) -> OrchResult<()> {
-    let mut cmd = Command::new("aws");
+    let mut cmd = s3_utils::aws_cli_command(config).await?;
+    cmd.args(["s3", "sync", tmp_dir, &config.s3_path(unique_id)]);
+    run_aws_sync(cmd, "upload the report")?;
+    audit::record!("s3", "sync_report", unique_id);
+    Ok(())
+}
+
+// Invalidate the CloudFront cache of the objects matching `paths`, eg.
+// "/{unique_id}/*" for a run's prefix or "/runs/*" for the runs index.
+//
+// CloudFront caches objects for up to 24 hours by default. Since the report,
+// the index.html dashboard and the runs index are overwritten, invalidate them
+// so that the latest version is served immediately.
+pub async fn invalidate_cloudfront_cache(
+    paths: &str,
+    cdk_config: &CdkConfig,
+    aws_cli: &AwsCli,
+) -> OrchResult<()> {
+    let distribution_id = match cdk_config.netbench_cloudfront_distribution_id() {
+        Some(distribution_id) => distribution_id,
+        None => {
+            info!("Skipping CloudFront invalidation: distribution id not found in cdk config");
+            return Ok(());
+        }
+    };
+
+    let mut cmd = aws_cli.command().await?;
     let output = cmd
         .args([
-            "s3",
-            "sync",
-            tmp_dir,
-            &format!(
-                "s3://{}/{}",
-                config.cdk_config.netbench_runner_public_s3_bucket(),
-                unique_id
-            ),
+            "cloudfront",
+            "create-invalidation",
+            "--distribution-id",
+            distribution_id,
+            "--paths",
+            paths,
         ])
         .output()
-        .unwrap();
+        .map_err(|err| {
+            OrchError::new(ErrorKind::CloudFront, "Failed to run aws cloudfront").with_source(err)
+        })?;
 
     debug!("{:?}", cmd);
     trace!("{:?}", output);
-    assert!(cmd.status().expect("aws sync").success(), "aws sync");
+    if !output.status.success() {
+        return Err(OrchError::new(
+            ErrorKind::CloudFront,
+            format!(
+                "aws cloudfront create-invalidation failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
+    audit::record!(
+        "cloudfront",
+        "create_invalidation",
+        distribution_id = %distribution_id,
+        paths
+    );
+
     Ok(())
 }
 
 async fn generate_report_from_results(tmp_dir: &str) -> OrchResult<()> {
-    let results_path = format!("{}/results", tmp_dir);
-    let report_path = format!("{}/report", tmp_dir);
+    let results_path = format!("{tmp_dir}/{RESULTS_DIR}");
+    let report_path = format!("{tmp_dir}/{REPORT_DIR}");
     let mut cmd = Command::new("s2n-netbench");
     cmd.args(["report-tree", &results_path, &report_path]);
     debug!("{:?}", cmd);
-    let status = cmd.status().expect("s2n-netbench command failed");
-    assert!(status.success(), " s2n-netbench command failed");
+    run_report_tree(cmd)
+}
 
+// Copies the flamegraphs of the profiled drivers from the results into the
+// report, with a page linking them. Returns the number of flamegraphs, which
+// are only present with `--profiler`.
+//
+// The flamegraphs are copied rather than linked from the results, so that the
+// archive of a private report includes them.
+fn link_flamegraphs(tmp_dir: &str) -> OrchResult<usize> {
+    let map_err = |err| {
+        OrchError::new(
+            ErrorKind::Report,
+            "Failed to add the flamegraphs to the report",
+        )
+        .with_source(err)
+    };
+    let results_path = Path::new(tmp_dir).join(RESULTS_DIR);
+    if !results_path.is_dir() {
+        return Ok(0);
+    }
+    let mut flamegraphs = Vec::new();
+    find_flamegraphs(&results_path, &mut flamegraphs).map_err(map_err)?;
+    if flamegraphs.is_empty() {
+        return Ok(0);
+    }
+    flamegraphs.sort();
+
+    let report_path = Path::new(tmp_dir).join(REPORT_DIR);
+    let mut links = String::new();
+    for flamegraph in &flamegraphs {
+        let relative = flamegraph
+            .strip_prefix(&results_path)
+            .expect("the flamegraphs are found in the results")
+            .to_string_lossy();
+        let dest = report_path.join(FLAMEGRAPHS_DIR).join(relative.as_ref());
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(map_err)?;
+        }
+        std::fs::copy(flamegraph, &dest).map_err(map_err)?;
+        links.push_str(&format!(
+            "<li><a href=\"{FLAMEGRAPHS_DIR}/{relative}\">{relative}</a></li>\n"
+        ));
+    }
+    std::fs::write(
+        report_path.join(FLAMEGRAPHS_HTML),
+        format!("<!DOCTYPE html>\n<html><head><title>Flamegraphs</title></head><body>\n<h1>Flamegraphs</h1>\n<ul>\n{links}</ul>\n</body></html>\n"),
+    )
+    .map_err(map_err)?;
+    info!(
+        count = flamegraphs.len(),
+        "Added the flamegraphs to the report"
+    );
+    Ok(flamegraphs.len())
+}
+
+fn find_flamegraphs(dir: &Path, flamegraphs: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_flamegraphs(&path, flamegraphs)?;
+        } else if path.extension().is_some_and(|extension| extension == "svg") {
+            flamegraphs.push(path);
+        }
+    }
     Ok(())
 }
 
-async fn download_results(
-    unique_id: &str,
-    config: &OrchestratorConfig,
-    tmp_dir: &str,
-) -> OrchResult<()> {
-    let mut cmd = Command::new("aws");
-    let output = cmd
-        .args([
-            "s3",
-            "sync",
-            &format!(
-                "s3://{}/{}",
-                config.cdk_config.netbench_runner_public_s3_bucket(),
-                unique_id
-            ),
-            tmp_dir,
-        ])
-        .output()
-        .unwrap();
+// A csv of the top-line metrics of each driver, uploaded as
+// `{unique_id}/summary/summary.csv`.
+//
+// The run summary notifications are rendered from it, including the deltas
+// against a baseline run, so the units are fixed rather than left to the
+// `s2n-netbench` defaults.
+async fn generate_summary_from_results(tmp_dir: &str) -> OrchResult<()> {
+    let results_path = format!("{tmp_dir}/{RESULTS_DIR}");
+    let summary_path = format!("{tmp_dir}/{SUMMARY_DIR}");
+    let mut cmd = Command::new("s2n-netbench");
+    cmd.args([
+        "report-tree",
+        &results_path,
+        &summary_path,
+        "--format",
+        "csv",
+        "--throughput-unit",
+        "Mbps",
+        "--latency-unit",
+        "ms",
+    ]);
     debug!("{:?}", cmd);
-    trace!("{:?}", output);
-    assert!(cmd.status().expect("aws sync").success(), "aws sync");
+    run_report_tree(cmd)
+}
 
+fn run_report_tree(mut cmd: Command) -> OrchResult<()> {
+    let status = cmd.status().map_err(|err| {
+        OrchError::new(ErrorKind::Report, "Failed to run s2n-netbench").with_source(err)
+    })?;
+    if !status.success() {
+        return Err(OrchError::new(
+            ErrorKind::Report,
+            format!("s2n-netbench report-tree failed: {status}"),
+        ));
+    }
     Ok(())
 }
 
-async fn update_report_url(
-    s3_client: &aws_sdk_s3::Client,
+async fn download_results(
     unique_id: &str,
     config: &OrchestratorConfig,
+    tmp_dir: &str,
 ) -> OrchResult<()> {
-    let body = ByteStream::new(SdkBody::from(format!(
-        "<a href=\"{}/report/index.html\">Final Report</a>",
-        config.cf_url(unique_id)
-    )));
-    let key = format!("{}/finished-step-0", unique_id);
-    s3_utils::upload_object(
-        s3_client,
-        config.cdk_config.netbench_runner_public_s3_bucket(),
-        body,
-        &key,
-    )
-    .await?;
+    let mut cmd = s3_utils::aws_cli_command(config).await?;
+    cmd.args(["s3", "sync", &config.s3_path(unique_id), tmp_dir]);
+    // the packet captures can be large, and aren't part of the report
+    cmd.args(["--exclude", &format!("{CAPTURES_DIR}/*")]);
+    run_aws_sync(cmd, "download the results")
+}
+
+fn run_aws_sync(mut cmd: Command, action: &str) -> OrchResult<()> {
+    debug!("{:?}", cmd);
+    let output = cmd.output().map_err(|err| {
+        OrchError::new(ErrorKind::S3, format!("Failed to {action}")).with_source(err)
+    })?;
+    trace!("{:?}", output);
+    if !output.status.success() {
+        return Err(OrchError::new(
+            ErrorKind::S3,
+            format!(
+                "Failed to {action}: aws s3 sync {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ),
+        ));
+    }
     Ok(())
 }
 
 // This function is best effort and will not return an error.
 //
-// Requires ssh access to the host. See STATE.ssh_key_name for more info
+// Requires ssh access to the host. See `ssh_key_name` in the settings for more info
 fn download_remote_logs(unique_id: &str, infra: &InfraDetail) {
     // get logs
     let get_logs = true;
     if get_logs {
         infra.public_client_ips().iter().for_each(|ip| {
-            let log_folder = format!("./target/logs/{unique_id}/client_{ip}");
-            std::fs::create_dir_all(Path::new(&log_folder)).expect("create log dir");
+            let log_folder = RunPaths::new(unique_id).host_logs_dir("client", ip);
+            if let Err(err) = std::fs::create_dir_all(&log_folder) {
+                warn!("Failed to create {:?}. {err}", log_folder);
+                return;
+            }
             let res = Command::new("scp")
                 .args([
                     "-oStrictHostKeyChecking=no",
-                    &format!("ec2-user@{ip}:netbench_orchestrator/target/russula*"),
-                    &log_folder,
+                    &format!("ec2-user@{ip}:{HOST_RUSSULA_LOGS}"),
                 ])
+                .arg(&log_folder)
                 .output();
             debug!("client log download succeeded: {:?}", res.ok());
         });
 
         infra.public_server_ips().iter().for_each(|ip| {
-            let log_folder = format!("./target/logs/{unique_id}/server_{ip}");
-            std::fs::create_dir_all(Path::new(&log_folder)).expect("create log dir");
+            let log_folder = RunPaths::new(unique_id).host_logs_dir("server", ip);
+            if let Err(err) = std::fs::create_dir_all(&log_folder) {
+                warn!("Failed to create {:?}. {err}", log_folder);
+                return;
+            }
             let res = Command::new("scp")
                 .args([
                     "-oStrictHostKeyChecking=no",
-                    &format!("ec2-user@{ip}:netbench_orchestrator/target/russula*"),
-                    &log_folder,
+                    &format!("ec2-user@{ip}:{HOST_RUSSULA_LOGS}"),
                 ])
+                .arg(&log_folder)
                 .output();
 
             debug!("server log download succeeded: {:?}", res.ok());
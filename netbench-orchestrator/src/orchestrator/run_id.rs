@@ -0,0 +1,171 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult};
+use std::{fmt, ops::Deref, path::Path, process::Command, time::SystemTime};
+
+// Long scenario names are truncated to keep the id within the limits of the
+// resource names it's used in (eg. the security group).
+const MAX_SCENARIO_LEN: usize = 32;
+const SUFFIX_LEN: usize = 6;
+const GIT_SHA_LEN: usize = 7;
+
+// The id of a run, eg. `20240101T120000Z-request_response-3f9a1c` or, with the
+// git sha of the drivers under test, `20240101T120000Z-request_response-3f9a1c-8e2b4d0`.
+//
+// The id is the prefix of the run's S3 paths, the name of its log stream and
+// the tag of its resources. It sorts by the start of the run and only contains
+// characters which are valid in all of those.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunId {
+    id: String,
+    git_sha: Option<String>,
+}
+
+impl RunId {
+    // Generates the id of a new run of the scenario. If `git_dir` is set, the
+    // short sha of its HEAD is included, eg. the checkout of a driver.
+    pub fn generate(scenario_file: &Path, git_dir: Option<&Path>) -> OrchResult<Self> {
+        let scenario = scenario_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        let git_sha = git_dir.map(git_sha).transpose()?;
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        Ok(RunId::from_parts(
+            SystemTime::now(),
+            &scenario,
+            &suffix[..SUFFIX_LEN],
+            git_sha,
+        ))
+    }
+
+    fn from_parts(
+        start: SystemTime,
+        scenario: &str,
+        suffix: &str,
+        git_sha: Option<String>,
+    ) -> Self {
+        // eg. 2024-01-01T12:00:00Z => 20240101T120000Z
+        let timestamp: String = humantime::format_rfc3339_seconds(start)
+            .to_string()
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':'))
+            .collect();
+        let scenario: String = scenario
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' => c,
+                _ => '_',
+            })
+            .take(MAX_SCENARIO_LEN)
+            .collect();
+
+        let mut id = format!("{timestamp}-{scenario}-{suffix}");
+        if let Some(git_sha) = &git_sha {
+            id.push('-');
+            id.push_str(git_sha);
+        }
+        RunId { id, git_sha }
+    }
+
+    // The id of an existing run, eg. for the orchestrator on the controller
+    // which continues a run started locally.
+    pub fn parse(id: &str) -> OrchResult<Self> {
+        let parts: Vec<&str> = id.split('-').collect();
+        let is_hex = |part: &str, len: usize| {
+            part.len() == len && part.chars().all(|c| c.is_ascii_hexdigit())
+        };
+        let git_sha = match parts.as_slice() {
+            [_, _, suffix] if is_hex(suffix, SUFFIX_LEN) => None,
+            [_, _, suffix, git_sha]
+                if is_hex(suffix, SUFFIX_LEN) && is_hex(git_sha, GIT_SHA_LEN) =>
+            {
+                Some(git_sha.to_string())
+            }
+            _ => {
+                return Err(OrchError::new(
+                    ErrorKind::Init,
+                    format!("Invalid unique id {id}"),
+                ))
+            }
+        };
+        Ok(RunId {
+            id: id.to_string(),
+            git_sha,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+
+    pub fn git_sha(&self) -> Option<&str> {
+        self.git_sha.as_deref()
+    }
+}
+
+impl Deref for RunId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.id
+    }
+}
+
+impl fmt::Display for RunId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+fn git_sha(git_dir: &Path) -> OrchResult<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(git_dir)
+        .args(["rev-parse", &format!("--short={GIT_SHA_LEN}"), "HEAD"])
+        .output()
+        .map_err(|err| OrchError::new(ErrorKind::Init, "Failed to run git").with_source(err))?;
+    if !output.status.success() {
+        return Err(OrchError::new(
+            ErrorKind::Init,
+            format!(
+                "Failed to get the git sha of {:?}: {}",
+                git_dir,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn run_id_format() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let run_id = RunId::from_parts(start, "request response", "3f9a1c", None);
+        assert_eq!(run_id.as_str(), "20231114T221320Z-request_response-3f9a1c");
+
+        let run_id = RunId::from_parts(
+            start,
+            &"a".repeat(64),
+            "3f9a1c",
+            Some("8e2b4d0".to_string()),
+        );
+        assert_eq!(
+            run_id.to_string(),
+            format!("20231114T221320Z-{}-3f9a1c-8e2b4d0", "a".repeat(32))
+        );
+        assert_eq!(run_id.git_sha(), Some("8e2b4d0"));
+        assert_eq!(RunId::parse(&run_id).unwrap(), run_id);
+
+        let run_id = RunId::from_parts(start, "rr", "3f9a1c", None);
+        assert_eq!(RunId::parse(&run_id).unwrap(), run_id);
+        assert!(RunId::parse("20231114T221320Z-rr").is_err());
+        assert!(RunId::parse("20231114T221320Z-rr-3f9a1c-xyz").is_err());
+    }
+}
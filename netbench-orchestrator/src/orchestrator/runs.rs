@@ -0,0 +1,113 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::{AwsCli, S3Api},
+    orchestrator::{report, CdkConfig, ErrorKind, OrchError, OrchResult, OrchestratorConfig},
+    progress,
+    s3_utils::{download_object, upload_object},
+};
+use aws_sdk_s3::primitives::ByteStream;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+
+static RUNS_HTML: &str = include_str!("./runs.html");
+
+// A run in the runs index.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RunEntry {
+    pub unique_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub date: String,
+    pub scenario: String,
+    pub drivers: Vec<String>,
+    pub status: String,
+    // The dashboard of the run
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RunsIndex {
+    pub runs: Vec<RunEntry>,
+}
+
+// Adds a run to the index of past runs.
+//
+// The index is stored as `runs/runs.json` in the public bucket and rendered to
+// `runs/index.html`, so that the CloudFront distribution can be browsed for
+// previous results. Runs which finish at the same time can overwrite each
+// other's update, in which case the index is corrected by the next run.
+pub async fn update_runs_index(
+    s3_client: &impl S3Api,
+    config: &OrchestratorConfig,
+    entry: RunEntry,
+) -> OrchResult<()> {
+    let mut index = load_runs_index(s3_client, &config.cdk_config).await?;
+    index.runs.retain(|run| run.unique_id != entry.unique_id);
+    index.runs.push(entry);
+    save_runs_index(s3_client, &config.cdk_config, &config.aws_cli, index).await
+}
+
+pub async fn load_runs_index(
+    s3_client: &impl S3Api,
+    cdk_config: &CdkConfig,
+) -> OrchResult<RunsIndex> {
+    let bucket = cdk_config.netbench_runner_public_s3_bucket();
+    match download_object(s3_client, bucket, "runs/runs.json").await? {
+        Some(body) => serde_json::from_slice(&body).map_err(|err| {
+            OrchError::new(ErrorKind::S3, "Failed to parse runs/runs.json").with_source(err)
+        }),
+        None => Ok(RunsIndex::default()),
+    }
+}
+
+// Uploads the index and its rendered page
+pub async fn save_runs_index(
+    s3_client: &impl S3Api,
+    cdk_config: &CdkConfig,
+    aws_cli: &AwsCli,
+    mut index: RunsIndex,
+) -> OrchResult<()> {
+    let bucket = cdk_config.netbench_runner_public_s3_bucket();
+    // newest first
+    index.runs.sort_by(|a, b| b.date.cmp(&a.date));
+    debug!("{:?}", index);
+
+    let html = handlebars::Handlebars::new()
+        .render_template(RUNS_HTML, &json!({ "runs": index.runs }))
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Init, "Failed to render the runs index").with_source(err)
+        })?;
+    let body = serde_json::to_vec_pretty(&index).expect("failed to serialize runs index");
+
+    upload_object(
+        s3_client,
+        bucket,
+        ByteStream::from(Bytes::from(body)),
+        "runs/runs.json",
+    )
+    .await?;
+    upload_object(
+        s3_client,
+        bucket,
+        ByteStream::from(Bytes::from(html)),
+        "runs/index.html",
+    )
+    .await?;
+    report::invalidate_cloudfront_cache("/runs/*", cdk_config, aws_cli).await?;
+
+    let url = format!(
+        "{}/runs/index.html",
+        cdk_config.netbench_cloudfront_distribution()
+    );
+    progress::output!("Runs: URL: {url}");
+
+    Ok(())
+}
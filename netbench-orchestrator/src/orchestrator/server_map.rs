@@ -0,0 +1,94 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig};
+use std::net::{IpAddr, SocketAddr};
+
+// The address of each server of the scenario, indexed by the scenario's
+// server id.
+//
+// The drivers address the servers by their id (`SERVER_<id>`), so the same
+// map is passed to the client workers, which connect to the servers, and to
+// the server workers, which run the servers assigned to their host.
+//
+// The servers are assigned to the server hosts round robin. The servers
+// sharing a host listen on consecutive ports, starting at the driver's port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerMap {
+    servers: Vec<SocketAddr>,
+}
+
+impl ServerMap {
+    pub fn new(server_count: usize, host_ips: &[IpAddr], base_port: u16) -> OrchResult<Self> {
+        if host_ips.is_empty() {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "no server hosts to run the scenario's servers on",
+            ));
+        }
+        let servers = (0..server_count)
+            .map(|server_id| {
+                let host = server_id % host_ips.len();
+                let slot = server_id / host_ips.len();
+                let port = u16::try_from(slot)
+                    .ok()
+                    .and_then(|slot| base_port.checked_add(slot))
+                    .ok_or_else(|| {
+                        OrchError::new(
+                            ErrorKind::Init,
+                            format!("no port left above {base_port} for server {server_id}"),
+                        )
+                    })?;
+                Ok(SocketAddr::new(host_ips[host], port))
+            })
+            .collect::<OrchResult<_>>()?;
+        Ok(ServerMap { servers })
+    }
+
+    // The map of the scenario in `config` on the server hosts
+    pub fn for_scenario(
+        config: &OrchestratorConfig,
+        host_ips: &[IpAddr],
+        base_port: u16,
+    ) -> OrchResult<Self> {
+        Self::new(config.scenario_servers(), host_ips, base_port)
+    }
+
+    // The most servers run on a single one of the server hosts, which is the
+    // number of ports used by each driver. See `PortPlan`.
+    pub fn ports_per_host(server_count: usize, server_hosts: usize) -> u16 {
+        let servers = server_count.div_ceil(server_hosts.max(1)).max(1);
+        u16::try_from(servers).unwrap_or(u16::MAX)
+    }
+
+    // The `--netbench-servers` argument of the russula workers
+    pub fn as_arg(&self) -> String {
+        self.servers
+            .iter()
+            .map(SocketAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn servers_share_hosts_round_robin() {
+        let hosts = [
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        ];
+        let map = ServerMap::new(3, &hosts, 4433).unwrap();
+        assert_eq!(map.as_arg(), "10.0.0.1:4433 10.0.0.2:4433 10.0.0.1:4434");
+
+        let map = ServerMap::new(2, &hosts, 4433).unwrap();
+        assert_eq!(map.as_arg(), "10.0.0.1:4433 10.0.0.2:4433");
+
+        assert!(ServerMap::new(2, &hosts[..1], u16::MAX).is_err());
+        assert!(ServerMap::new(1, &[], 4433).is_err());
+    }
+}
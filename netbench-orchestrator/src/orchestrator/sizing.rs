@@ -0,0 +1,453 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    aws_api::ROOT_VOLUME_SIZE,
+    ec2_utils::EndpointType,
+    orchestrator::{ErrorKind, OrchError, OrchResult, OrchestratorConfig},
+};
+use aws_sdk_ec2::types::{InstanceType, InstanceTypeInfo};
+use serde_json::Value;
+use std::{collections::HashMap, fs::File};
+use tracing::{info, warn};
+
+const MIB: u64 = 1 << 20;
+const GIB: u64 = 1 << 30;
+
+// A rough upper bound of the drivers' memory for the state and buffers of
+// each connection, and for the send and receive buffers of each stream
+const CONNECTION_MEMORY: u64 = 256 * 1024;
+const STREAM_MEMORY: u64 = 2 * MIB;
+// The drivers share the host with the collector, the profiler and the OS
+const DRIVER_MEMORY_SHARE: f64 = 0.5;
+
+// The collector writes a sample of the driver every second, with the
+// counters of each stream
+const SAMPLE_BYTES: u64 = 1024;
+const STREAM_SAMPLE_BYTES: u64 = 128;
+// The root volume also holds the OS and the drivers' builds
+const RESERVED_DISK: u64 = 20 * GIB;
+
+// The scenario's load on a host, estimated from the ops of its connections
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Load {
+    connections: u64,
+    streams: u64,
+    // sent and received
+    bytes: u64,
+    // the sleeps and timed iterations of the longest connection, which run
+    // for a while without transferring the scenario's bytes
+    idle_ms: u64,
+    // the send and receive rates set by the scenario, in bytes per second
+    rate: f64,
+}
+
+impl Load {
+    fn add(&mut self, other: &Load) {
+        self.connections += other.connections;
+        self.streams += other.streams;
+        self.bytes += other.bytes;
+        self.idle_ms = self.idle_ms.max(other.idle_ms);
+        self.rate += other.rate;
+    }
+}
+
+// The memory and NIC bandwidth of an instance type
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct HostSize {
+    memory: u64,
+    // the baseline bandwidth, in bytes per second
+    bandwidth: f64,
+}
+
+// Warns when the scenario is likely too large for the hosts' instance type:
+// the connections' and streams' buffers for the memory, the collector output
+// (and packet captures) of all the runs for the root volume, or the send and
+// receive rates of the scenario for the NIC.
+//
+// The estimate is rough, so the run goes ahead either way. The managed
+// instances aren't checked, since their size isn't known.
+pub async fn check(
+    aws_config: &aws_types::SdkConfig,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    let file = File::open(config.netbench_scenario_filepath()).map_err(|err| {
+        OrchError::new(ErrorKind::Init, "Failed to open the netbench scenario").with_source(err)
+    })?;
+    let scenario: Value = serde_json::from_reader(file).map_err(|err| {
+        OrchError::new(ErrorKind::Init, "Failed to parse netbench file").with_source(err)
+    })?;
+    let (server_loads, client_loads) = host_loads(&scenario, config);
+    if server_loads.is_empty() && client_loads.is_empty() {
+        return Ok(());
+    }
+
+    let ec2_client = aws_sdk_ec2::Client::new(aws_config);
+    let mut instance_types: Vec<&str> = config
+        .server_config
+        .iter()
+        .chain(config.client_config.iter())
+        .map(|host| host.instance_type().as_str())
+        .collect();
+    instance_types.sort();
+    instance_types.dedup();
+    let sizes = match host_sizes(&ec2_client, &instance_types).await {
+        Ok(sizes) => sizes,
+        Err(err) => {
+            warn!("Skipping the sizing check of the hosts. {err}");
+            return Ok(());
+        }
+    };
+
+    // every host runs each driver pair once per iteration
+    let runs = config.run.iterations() as u64 * config.driver_registry.enabled_names().len() as u64;
+    for (endpoint_type, host_configs, loads) in [
+        (EndpointType::Server, &config.server_config, server_loads),
+        (EndpointType::Client, &config.client_config, client_loads),
+    ] {
+        for (index, (host_config, load)) in host_configs.iter().zip(loads).enumerate() {
+            let instance_type = host_config.instance_type();
+            let Some(size) = sizes.get(instance_type.as_str()) else {
+                continue;
+            };
+            let capture_bytes = config.capture.max_bytes(endpoint_type);
+            info!(
+                endpoint = endpoint_type.as_str(),
+                index,
+                instance_type = instance_type.as_str(),
+                connections = load.connections,
+                streams = load.streams,
+                bytes = load.bytes,
+                output_mib = output_bytes(&load, size, runs, capture_bytes).div_ceil(MIB),
+                "scenario load"
+            );
+            let host = format!(
+                "{} host {index} ({instance_type})",
+                endpoint_type.as_str().to_lowercase()
+            );
+            for warning in warnings(&host, &load, size, runs, capture_bytes) {
+                warn!("{warning}");
+            }
+        }
+    }
+    Ok(())
+}
+
+// The load of each server and client host, in the order of the host configs.
+//
+// Each client host runs a client of the scenario, and the scenario's servers
+// are spread over the server hosts (see `ServerMap`). In loopback mode the
+// servers run on each client host.
+fn host_loads(scenario: &Value, config: &OrchestratorConfig) -> (Vec<Load>, Vec<Load>) {
+    let loads = |endpoints: &str| -> Vec<Load> {
+        scenario[endpoints]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(endpoint_load)
+            .collect()
+    };
+    let servers = loads("servers");
+    let mut clients = loads("clients");
+    clients.truncate(config.client_config.len());
+
+    if config.loopback() {
+        let mut all_servers = Load::default();
+        servers.iter().for_each(|server| all_servers.add(server));
+        clients
+            .iter_mut()
+            .for_each(|client| client.add(&all_servers));
+        return (Vec::new(), clients);
+    }
+
+    let server_hosts =
+        config.server_config.len() + config.managed_instances(EndpointType::Server).len();
+    let mut server_loads = vec![Load::default(); config.server_config.len()];
+    for (server_id, server) in servers.iter().enumerate() {
+        if let Some(load) = server_loads.get_mut(server_id % server_hosts.max(1)) {
+            load.add(server);
+        }
+    }
+    (server_loads, clients)
+}
+
+// The connections of a client or server of the scenario, which run
+// concurrently
+fn endpoint_load(endpoint: &Value) -> Load {
+    let mut load = Load::default();
+    for connection in endpoint["connections"].as_array().into_iter().flatten() {
+        let mut connection_load = Load {
+            connections: 1,
+            ..Default::default()
+        };
+        ops_load(&connection["ops"], 1, &mut connection_load);
+        for peer_stream in connection["peer_streams"].as_array().into_iter().flatten() {
+            connection_load.streams += 1;
+            ops_load(peer_stream, 1, &mut connection_load);
+        }
+        load.add(&connection_load);
+    }
+    load
+}
+
+// Adds the load of the connection ops, which are evaluated `repeat` times.
+// The ops are the `s2n-netbench` connection operations, eg.
+// `{"send": {"stream_id": 0, "bytes": 1024}}`.
+fn ops_load(ops: &Value, repeat: u64, load: &mut Load) {
+    let number = |op: &Value, field: &str| op[field].as_u64().unwrap_or_default();
+    for op in ops.as_array().into_iter().flatten() {
+        let Some((name, op)) = op.as_object().and_then(|op| op.iter().next()) else {
+            continue;
+        };
+        match name.as_str() {
+            "open_bidirectional_stream" | "open_send_stream" => load.streams += repeat,
+            "send" | "receive" => {
+                load.bytes = load
+                    .bytes
+                    .saturating_add(number(op, "bytes").saturating_mul(repeat))
+            }
+            "send_rate" | "receive_rate" => {
+                let period_ms = number(op, "period_ms");
+                if period_ms > 0 {
+                    load.rate += number(op, "bytes") as f64 * 1000.0 / period_ms as f64;
+                }
+            }
+            "sleep" => load.idle_ms += number(op, "amount_ms").saturating_mul(repeat),
+            "profile" => ops_load(&op["operations"], repeat, load),
+            "iterate" => {
+                let value = &op["value"];
+                let repeat = match value.get("count") {
+                    Some(count) => repeat.saturating_mul(number(count, "amount")),
+                    None => {
+                        // timed iterations are counted once
+                        load.idle_ms += number(&value["time"], "amount_ms");
+                        repeat
+                    }
+                };
+                ops_load(&op["operations"], repeat, load);
+            }
+            "scope" => {
+                for thread in op["threads"].as_array().into_iter().flatten() {
+                    ops_load(thread, repeat, load);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// The warnings of a host whose instance type is likely too small for the
+// scenario's load
+fn warnings(
+    host: &str,
+    load: &Load,
+    size: &HostSize,
+    runs: u64,
+    capture_bytes: u64,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let memory = load.connections * CONNECTION_MEMORY + load.streams * STREAM_MEMORY;
+    if memory as f64 > size.memory as f64 * DRIVER_MEMORY_SHARE {
+        warnings.push(format!(
+            "The scenario's {} connections and {} streams on the {host} may need {} GiB of its {} GiB of memory",
+            load.connections,
+            load.streams,
+            memory.div_ceil(GIB),
+            size.memory / GIB
+        ));
+    }
+
+    if size.bandwidth > 0.0 && load.rate > size.bandwidth {
+        warnings.push(format!(
+            "The scenario's send and receive rates of {:.1} Gbps on the {host} exceed its baseline bandwidth of {:.1} Gbps",
+            gbps(load.rate),
+            gbps(size.bandwidth)
+        ));
+    }
+
+    let disk = output_bytes(load, size, runs, capture_bytes);
+    let free_disk = ROOT_VOLUME_SIZE as u64 * GIB - RESERVED_DISK;
+    if disk > free_disk {
+        warnings.push(format!(
+            "The results of the {runs} driver runs on the {host} may need {} GiB of disk, but only about {} GiB of its {ROOT_VOLUME_SIZE} GiB root volume are free",
+            disk.div_ceil(GIB),
+            free_disk / GIB
+        ));
+    }
+    warnings
+}
+
+// The collector output (and packet captures) of all the runs on a host. The
+// drivers transfer the scenario's bytes at most at the baseline bandwidth, and
+// the collector samples them for as long.
+fn output_bytes(load: &Load, size: &HostSize, runs: u64, capture_bytes: u64) -> u64 {
+    let transfer_secs = match size.bandwidth > 0.0 {
+        true => load.bytes as f64 / size.bandwidth,
+        false => 0.0,
+    };
+    let run_secs = (transfer_secs + load.idle_ms as f64 / 1000.0).ceil() as u64;
+    runs.saturating_mul(
+        run_secs
+            .saturating_mul(SAMPLE_BYTES + load.streams * STREAM_SAMPLE_BYTES)
+            .saturating_add(capture_bytes),
+    )
+}
+
+fn gbps(bytes_per_sec: f64) -> f64 {
+    bytes_per_sec * 8.0 / 1e9
+}
+
+// The memory and baseline bandwidth of each instance type
+async fn host_sizes(
+    ec2_client: &aws_sdk_ec2::Client,
+    instance_types: &[&str],
+) -> OrchResult<HashMap<String, HostSize>> {
+    let mut sizes = HashMap::new();
+    // at most 100 instance types are described per request
+    for chunk in instance_types.chunks(100) {
+        let output = ec2_client
+            .describe_instance_types()
+            .set_instance_types(Some(
+                chunk
+                    .iter()
+                    .map(|instance_type| InstanceType::from(*instance_type))
+                    .collect(),
+            ))
+            .send()
+            .await
+            .map_err(|err| {
+                OrchError::sdk(
+                    ErrorKind::Ec2,
+                    format!("Failed to describe the instance types {chunk:?}"),
+                    err,
+                )
+            })?;
+        for info in output.instance_types() {
+            if let Some(instance_type) = info.instance_type() {
+                sizes.insert(instance_type.as_str().to_string(), host_size(info));
+            }
+        }
+    }
+    Ok(sizes)
+}
+
+// The baseline bandwidth of the network cards, or else the bandwidth of the
+// network performance, eg. "Up to 10 Gigabit". The bandwidth is 0 if unknown.
+fn host_size(info: &InstanceTypeInfo) -> HostSize {
+    let memory = info
+        .memory_info()
+        .and_then(|memory| memory.size_in_mib())
+        .unwrap_or_default()
+        .max(0) as u64
+        * MIB;
+    let network_info = info.network_info();
+    let baseline_gbps: f64 = network_info
+        .map(|network| network.network_cards())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|card| card.baseline_bandwidth_in_gbps())
+        .sum();
+    let gbps = match baseline_gbps > 0.0 {
+        true => baseline_gbps,
+        false => network_info
+            .and_then(|network| network.network_performance())
+            .and_then(performance_gbps)
+            .unwrap_or_default(),
+    };
+    HostSize {
+        memory,
+        bandwidth: gbps * 1e9 / 8.0,
+    }
+}
+
+fn performance_gbps(network_performance: &str) -> Option<f64> {
+    network_performance
+        .trim_start_matches("Up to ")
+        .strip_suffix(" Gigabit")?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_of_the_scenario_ops() {
+        let client: Value = serde_json::from_str(
+            r#"{
+                "connections": [
+                    {
+                        "ops": [
+                            {"open_bidirectional_stream": {"stream_id": 0}},
+                            {"send_rate": {"stream_id": 0, "bytes": 1000000, "period_ms": 100}},
+                            {"iterate": {"value": {"count": {"amount": 10}}, "operations": [
+                                {"send": {"stream_id": 0, "bytes": 1024}},
+                                {"sleep": {"amount_ms": 5}}
+                            ]}},
+                            {"scope": {"threads": [[{"open_send_stream": {"stream_id": 1}}]]}}
+                        ],
+                        "peer_streams": [[{"receive": {"stream_id": 0, "bytes": 2048}}]]
+                    },
+                    {"ops": [{"sleep": {"amount_ms": 20}}]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            endpoint_load(&client),
+            Load {
+                connections: 2,
+                streams: 3,
+                bytes: 10 * 1024 + 2048,
+                idle_ms: 50,
+                rate: 10_000_000.0,
+            }
+        );
+    }
+
+    #[test]
+    fn warnings_of_an_undersized_host() {
+        let size = HostSize {
+            memory: 32 * GIB,
+            bandwidth: 10e9 / 8.0,
+        };
+        let load = Load {
+            connections: 100,
+            streams: 100,
+            bytes: 1_000_000_000,
+            idle_ms: 0,
+            rate: 0.0,
+        };
+        assert!(warnings("client host 0 (c5.4xlarge)", &load, &size, 4, 0).is_empty());
+
+        let load = Load {
+            connections: 10_000,
+            streams: 10_000,
+            rate: 25e9 / 8.0,
+            ..load
+        };
+        let warnings = warnings("client host 0 (c5.4xlarge)", &load, &size, 100, 400_000_000);
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(
+            warnings[0],
+            "The scenario's 10000 connections and 10000 streams on the client host 0 (c5.4xlarge) may need 22 GiB of its 32 GiB of memory"
+        );
+        assert_eq!(
+            warnings[1],
+            "The scenario's send and receive rates of 25.0 Gbps on the client host 0 (c5.4xlarge) exceed its baseline bandwidth of 10.0 Gbps"
+        );
+        assert!(warnings[2].starts_with("The results of the 100 driver runs"));
+        // a second of the 10000 streams' samples and the capture, per run
+        assert_eq!(
+            output_bytes(&load, &size, 100, 400_000_000),
+            100 * (1024 + 10_000 * 128 + 400_000_000)
+        );
+
+        assert_eq!(performance_gbps("Up to 10 Gigabit"), Some(10.0));
+        assert_eq!(performance_gbps("25 Gigabit"), Some(25.0));
+        assert_eq!(performance_gbps("Moderate"), None);
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    ec2_utils::{InfraDetail, InstanceDetail},
+    orchestrator::{state, RunPaths},
+    progress,
+};
+use std::{fmt::Write, fs, path::Path};
+use tracing::warn;
+
+// Writes an `ssh_config` with an alias per launched host (eg.
+// `netbench-client-0`) and a `hosts.txt` listing every host to the run's
+// workspace, and prints how to connect to the hosts.
+//
+// The hosts only accept ssh with the key pair of `ssh_key_name`, so nothing
+// is written without it. This is best effort and never fails the run.
+pub fn write_access(unique_id: &str, infra: &InfraDetail) {
+    let Some(key_name) = &state().ssh_key_name else {
+        return;
+    };
+    let paths = RunPaths::new(unique_id);
+    let hosts = aliases(infra);
+    let ssh_config = paths.ssh_config();
+    let res = fs::create_dir_all(paths.workspace())
+        .and_then(|_| fs::write(&ssh_config, ssh_config_file(unique_id, key_name, &hosts)))
+        .and_then(|_| fs::write(paths.hosts_file(), hosts_file(&hosts)));
+    if let Err(err) = res {
+        warn!("Failed to write the ssh access to the hosts. {err}");
+        return;
+    }
+
+    for (alias, host) in &hosts {
+        if !host.is_managed() {
+            progress::output!("SSH: ssh -F {} {alias}", ssh_config.display());
+        }
+    }
+}
+
+// The alias of each host, by host group and index, eg. `netbench-server-1`
+fn aliases(infra: &InfraDetail) -> Vec<(String, &InstanceDetail)> {
+    infra
+        .servers
+        .iter()
+        .enumerate()
+        .chain(infra.clients.iter().enumerate())
+        .map(|(index, host)| {
+            let endpoint = host.endpoint_type().as_str().to_lowercase();
+            (format!("netbench-{endpoint}-{index}"), host)
+        })
+        .collect()
+}
+
+// The managed instances weren't launched with the key pair, so they're only
+// listed in `hosts.txt`. The hosts are replaced by every run, so their host
+// keys aren't checked or remembered.
+fn ssh_config_file(unique_id: &str, key_name: &str, hosts: &[(String, &InstanceDetail)]) -> String {
+    let user = Path::new(&state().host_home_path)
+        .file_name()
+        .map(|user| user.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut out = format!("# The hosts of the netbench run {unique_id}\n");
+    for (alias, host) in hosts.iter().filter(|(_, host)| !host.is_managed()) {
+        let _ = write!(
+            out,
+            "\nHost {alias}\n  HostName {}\n  User {user}\n  IdentityFile ~/.ssh/{key_name}.pem\n  StrictHostKeyChecking no\n  UserKnownHostsFile /dev/null\n",
+            host.host_ips().public_ip()
+        );
+    }
+    out
+}
+
+fn hosts_file(hosts: &[(String, &InstanceDetail)]) -> String {
+    let mut out = String::from("# alias instance_id az public_ip private_ip\n");
+    for (alias, host) in hosts {
+        let _ = writeln!(
+            out,
+            "{alias} {} {} {} {}{}",
+            host.instance_id(),
+            host.az().as_string(),
+            host.host_ips().public_ip(),
+            host.host_ips().private_ip(),
+            if host.is_managed() { " managed" } else { "" }
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ec2_utils::{Az, EndpointType, HostIps, PrivIp, PubIp};
+
+    #[test]
+    fn ssh_config_of_the_launched_hosts() {
+        let host = |endpoint_type, instance_id: &str, ip: [u8; 4], managed| {
+            let host_ips = HostIps::new(PrivIp([10, 0, 0, ip[3]].into()), PubIp(ip.into()));
+            let az = Az::from("us-west-2a".to_string());
+            match managed {
+                true => {
+                    InstanceDetail::managed(endpoint_type, az, instance_id.to_string(), host_ips)
+                }
+                false => InstanceDetail::new(
+                    endpoint_type,
+                    az,
+                    aws_sdk_ec2::types::Instance::builder()
+                        .instance_id(instance_id)
+                        .build(),
+                    host_ips,
+                )
+                .unwrap(),
+            }
+        };
+        let server = host(EndpointType::Server, "i-1", [54, 0, 0, 1], false);
+        let client = host(EndpointType::Client, "mi-2", [192, 168, 0, 2], true);
+        let hosts = vec![
+            ("netbench-server-0".to_string(), &server),
+            ("netbench-client-0".to_string(), &client),
+        ];
+
+        assert_eq!(
+            ssh_config_file("run-1", "my-key", &hosts),
+            "# The hosts of the netbench run run-1\n\nHost netbench-server-0\n  HostName 54.0.0.1\n  User ec2-user\n  IdentityFile ~/.ssh/my-key.pem\n  StrictHostKeyChecking no\n  UserKnownHostsFile /dev/null\n"
+        );
+        assert_eq!(
+            hosts_file(&hosts),
+            "# alias instance_id az public_ip private_ip\nnetbench-server-0 i-1 us-west-2a 54.0.0.1 10.0.0.1\nnetbench-client-0 mi-2 us-west-2a 192.168.0.2 10.0.0.2 managed\n"
+        );
+    }
+}
@@ -1,61 +1,119 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::orchestrator::{error::BoxError, ErrorKind, OrchError, OrchResult};
 use core::time::Duration;
+use std::{collections::BTreeMap, fs::File, path::Path, sync::OnceLock};
 
-pub const STATE: State = State {
-    version: "v1.0.0",
+static STATE: OnceLock<State> = OnceLock::new();
 
-    // netbench
-    netbench_repo: "https://github.com/aws/s2n-netbench.git",
-    netbench_branch: "main",
-    netbench_port: 4433,
-
-    // orchestrator
-    host_home_path: "/home/ec2-user",
-    workspace_dir: "./target/netbench",
-    shutdown_min: 120, // 1 hour
-    poll_delay_ssm: Duration::from_secs(10),
-
-    // russula
-    russula_repo: "https://github.com/toidiu/netbench_orchestrator.git",
-    russula_branch: "ak-main",
-    russula_port: 9000,
-    poll_delay_russula: Duration::from_secs(5),
+// The settings which can be overridden, by their key in the settings file. The
+// environment variable of a setting is its key in uppercase with a `NETBENCH_`
+// prefix, eg. `NETBENCH_SSH_KEY_NAME`.
+const SETTINGS: &[&str] = &[
+    "netbench_repo",
+    "netbench_branch",
+    "netbench_port",
+    "host_home_path",
+    "workspace_dir",
+    "shutdown_min",
+    "min_free_disk_gib",
+    "poll_delay_ssm",
+    "russula_repo",
+    "russula_branch",
+    "russula_port",
+    "poll_delay_russula",
+    "ami_name",
+    "ssh_key_name",
+];
 
-    // aws
-    ami_name: "/aws/service/ami-amazon-linux-latest/al2023-ami-kernel-default-x86_64",
+// The orchestrator's settings.
+//
+// Returns the defaults if `init_state` wasn't called, eg. in tests.
+pub fn state() -> &'static State {
+    STATE.get_or_init(State::default)
+}
 
-    // Configure hosts with an ec2 key pair to enable ssh access.
-    //
-    // https://github.com/aws/s2n-netbench/issues/35
-    // TODO take as input from user
-    ssh_key_name: None,
-};
+// Loads the settings from the defaults, then the optional settings file and
+// then the environment variables, which take precedence.
+//
+// Must be called before the first call to `state`.
+pub fn init_state(settings_file: Option<&Path>) -> OrchResult<()> {
+    let mut state = State::default();
+    if let Some(path) = settings_file {
+        state.apply_file(path)?;
+    }
+    state.apply_env(|key| std::env::var(key).ok())?;
+    STATE.set(state).map_err(|_state| {
+        OrchError::new(
+            ErrorKind::Init,
+            "The orchestrator settings were already loaded",
+        )
+    })
+}
 
+#[derive(Debug)]
 pub struct State {
     pub version: &'static str,
 
     // netbench
-    pub netbench_repo: &'static str,
-    pub netbench_branch: &'static str,
+    pub netbench_repo: String,
+    pub netbench_branch: String,
     pub netbench_port: u16,
 
     // orchestrator
-    pub host_home_path: &'static str,
-    pub workspace_dir: &'static str,
+    pub host_home_path: String,
+    pub workspace_dir: String,
     pub shutdown_min: u16,
+    pub min_free_disk_gib: u64,
     pub poll_delay_ssm: Duration,
 
     // russula
-    pub russula_repo: &'static str,
-    pub russula_branch: &'static str,
+    pub russula_repo: String,
+    pub russula_branch: String,
     pub russula_port: u16,
     pub poll_delay_russula: Duration,
 
     // aws
-    pub ami_name: &'static str,
-    pub ssh_key_name: Option<&'static str>,
+    pub ami_name: String,
+    pub ssh_key_name: Option<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            version: "v1.0.0",
+
+            // netbench
+            netbench_repo: "https://github.com/aws/s2n-netbench.git".to_string(),
+            netbench_branch: "main".to_string(),
+            netbench_port: 4433,
+
+            // orchestrator
+            host_home_path: "/home/ec2-user".to_string(),
+            workspace_dir: "./target/netbench".to_string(),
+            shutdown_min: 120, // 1 hour
+            // below which the configure step cleans up the hosts, see
+            // `disk_cleanup_cmds`
+            min_free_disk_gib: 10,
+            poll_delay_ssm: Duration::from_secs(10),
+
+            // russula
+            russula_repo: "https://github.com/toidiu/netbench_orchestrator.git".to_string(),
+            russula_branch: "ak-main".to_string(),
+            russula_port: 9000,
+            poll_delay_russula: Duration::from_secs(5),
+
+            // aws
+            ami_name: "/aws/service/ami-amazon-linux-latest/al2023-ami-kernel-default-x86_64"
+                .to_string(),
+
+            // Configure hosts with an ec2 key pair to enable ssh access.
+            //
+            // https://github.com/aws/s2n-netbench/issues/35
+            ssh_key_name: None,
+        }
+    }
 }
 
 impl State {
@@ -75,4 +133,112 @@ impl State {
     pub fn security_group_name(&self, unique_id: &str) -> String {
         format!("netbench_{}", unique_id)
     }
+
+    // The settings file is a json object of settings, eg.
+    // `{ "ssh_key_name": "my-key", "poll_delay_ssm": "5s" }`.
+    fn apply_file(&mut self, path: &Path) -> OrchResult<()> {
+        let file = File::open(path).map_err(|err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Failed to open the settings file {:?}", path),
+            )
+            .with_source(err)
+        })?;
+        let settings: BTreeMap<String, serde_json::Value> =
+            serde_json::from_reader(file).map_err(|err| {
+                OrchError::new(
+                    ErrorKind::Init,
+                    format!("Failed to parse the settings file {:?}", path),
+                )
+                .with_source(err)
+            })?;
+
+        for (key, value) in settings {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                serde_json::Value::Null => String::new(),
+                value => value.to_string(),
+            };
+            self.set(&key, &value).map_err(|err| {
+                OrchError::new(
+                    ErrorKind::Init,
+                    format!("Invalid setting {key} in {:?}", path),
+                )
+                .with_source(err)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> OrchResult<()> {
+        for key in SETTINGS {
+            let env_key = format!("NETBENCH_{}", key.to_uppercase());
+            if let Some(value) = var(&env_key) {
+                self.set(key, &value).map_err(|err| {
+                    OrchError::new(ErrorKind::Init, format!("Invalid {env_key}={value}"))
+                        .with_source(err)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    // Durations are in humantime format, eg. "500ms" or "1m". An empty
+    // `ssh_key_name` disables ssh access.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), BoxError> {
+        let string = || value.to_string();
+        match key {
+            "netbench_repo" => self.netbench_repo = string(),
+            "netbench_branch" => self.netbench_branch = string(),
+            "netbench_port" => self.netbench_port = value.parse()?,
+            "host_home_path" => self.host_home_path = string(),
+            "workspace_dir" => self.workspace_dir = string(),
+            "shutdown_min" => self.shutdown_min = value.parse()?,
+            "min_free_disk_gib" => self.min_free_disk_gib = value.parse()?,
+            "poll_delay_ssm" => self.poll_delay_ssm = humantime::parse_duration(value)?,
+            "russula_repo" => self.russula_repo = string(),
+            "russula_branch" => self.russula_branch = string(),
+            "russula_port" => self.russula_port = value.parse()?,
+            "poll_delay_russula" => self.poll_delay_russula = humantime::parse_duration(value)?,
+            "ami_name" => self.ami_name = string(),
+            "ssh_key_name" => self.ssh_key_name = (!value.is_empty()).then(string),
+            _ => return Err(format!("unknown setting. Expected one of {SETTINGS:?}").into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_overrides() {
+        for key in SETTINGS {
+            let value = match *key {
+                "netbench_port" | "russula_port" | "shutdown_min" | "min_free_disk_gib" => "1",
+                "poll_delay_ssm" | "poll_delay_russula" => "1s",
+                _ => "value",
+            };
+            assert!(State::default().set(key, value).is_ok(), "{key}");
+        }
+        assert!(State::default().set("version", "v2").is_err());
+
+        let mut state = State::default();
+        state
+            .apply_env(|key| match key {
+                "NETBENCH_SSH_KEY_NAME" => Some("my-key".to_string()),
+                "NETBENCH_POLL_DELAY_SSM" => Some("500ms".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(state.ssh_key_name.as_deref(), Some("my-key"));
+        assert_eq!(state.poll_delay_ssm, Duration::from_millis(500));
+        assert_eq!(state.russula_port, 9000);
+
+        let err = state
+            .apply_env(|key| (key == "NETBENCH_RUSSULA_PORT").then(|| "http".to_string()))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Init);
+    }
 }
@@ -0,0 +1,104 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::{dashboard::RunStatus, ErrorKind, OrchError, OrchResult};
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    task::JoinHandle,
+};
+use tracing::{debug, info};
+
+// Serves the run status as json on localhost.
+//
+// This is the same document as the dashboard's `status.json`, so tools can
+// poll the progress of a run without going through CloudFront or parsing the
+// orchestrator's output. The server is stopped when dropped.
+pub struct StatusServer {
+    task: JoinHandle<()>,
+}
+
+impl StatusServer {
+    pub async fn spawn(port: u16, status: watch::Receiver<RunStatus>) -> OrchResult<Self> {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let listener = TcpListener::bind(addr).await.map_err(|err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Failed to serve the status on {addr}"),
+            )
+            .with_source(err)
+        })?;
+        info!("Serving the run status on http://{addr}/status");
+
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let status = status.borrow().clone();
+                tokio::spawn(async move {
+                    if let Err(err) = respond(stream, &status).await {
+                        debug!("Failed to serve the status. {err}");
+                    }
+                });
+            }
+        });
+
+        Ok(StatusServer { task })
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// Only the request line is inspected, which is enough for `curl` and other
+// polling clients.
+async fn respond(mut stream: TcpStream, status: &RunStatus) -> std::io::Result<()> {
+    let mut buf = [0; 1024];
+    let len = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let (code, body) = match request_path(&request) {
+        Some("/" | "/status") => (
+            "200 OK",
+            serde_json::to_string(status).expect("failed to serialize the run status"),
+        ),
+        _ => ("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {code}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// The path of a GET request, ignoring the query string.
+fn request_path(request: &str) -> Option<&str> {
+    let mut request_line = request.lines().next()?.split_whitespace();
+    if request_line.next()? != "GET" {
+        return None;
+    }
+    let target = request_line.next()?;
+    Some(target.split('?').next().unwrap_or(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request_path() {
+        assert_eq!(
+            request_path("GET /status?t=1 HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            Some("/status")
+        );
+        assert_eq!(request_path("GET / HTTP/1.1\r\n\r\n"), Some("/"));
+        assert_eq!(request_path("POST /status HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(request_path(""), None);
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    compute::ComputeProvider,
+    ec2_utils::{EndpointType, InfraDetail},
+    orchestrator::{state, OrchResult, OrchestratorConfig},
+    russula::Backoff,
+    ssm_utils::Step,
+};
+use std::{fmt, time::Duration};
+use tracing::{info, instrument};
+
+// The shortest wait between polls of the tc commands, which complete quickly
+const MIN_POLL_DELAY: Duration = Duration::from_millis(500);
+
+// Named profiles of common last-mile links, as `ThrottleProfile` specs
+const PROFILES: &[(&str, &str)] = &[
+    ("3g", "rate=1600kbit"),
+    ("lte", "rate=12mbit"),
+    ("dsl", "rate=8mbit"),
+    ("cable", "rate=50mbit"),
+];
+
+// The default interface of the host, which the limit is applied to
+const DEFAULT_DEV: &str = "$(ip route show default | awk '{print $5; exit}')";
+
+// An egress rate limit applied to a host group with a tbf qdisc, to emulate a
+// constrained link.
+//
+// Only the egress of the hosts is limited, so a limit on both the server and
+// client hosts limits both directions of a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThrottleProfile {
+    rate_bits: u64,
+    // The size of the token bucket, which bounds the bursts sent at the
+    // interface's rate
+    burst_bytes: u64,
+    // How long a packet can wait for tokens before it's dropped, which bounds
+    // the queue
+    latency: Duration,
+}
+
+impl ThrottleProfile {
+    // A profile name (eg. "lte") or `rate=<rate>[,burst=<bytes>][,latency=<duration>]`,
+    // eg. "rate=20mbit,latency=100ms"
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let spec = PROFILES
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map_or(value, |(_, spec)| *spec);
+
+        let mut rate_bits = None;
+        let mut burst_bytes = None;
+        let mut latency = Duration::from_millis(50);
+        for field in spec.split(',') {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("expected a profile or key=value, got {field}"))?;
+            match key {
+                "rate" => rate_bits = Some(parse_rate(value)?),
+                "burst" => {
+                    burst_bytes = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("expected the burst in bytes, got {value}"))?,
+                    )
+                }
+                "latency" => {
+                    latency = humantime::parse_duration(value).map_err(|err| err.to_string())?
+                }
+                _ => return Err(format!("unknown throttle setting {key}")),
+            }
+        }
+        let rate_bits = rate_bits.ok_or_else(|| {
+            let names: Vec<_> = PROFILES.iter().map(|(name, _)| *name).collect();
+            format!(
+                "expected a rate or one of the profiles {}, got {value}",
+                names.join(", ")
+            )
+        })?;
+        // the bucket holds the tokens of at least a 4ms timer tick, and a
+        // full size packet
+        let burst_bytes = burst_bytes.unwrap_or((rate_bits / 8 / 250).max(1540));
+
+        Ok(ThrottleProfile {
+            rate_bits,
+            burst_bytes,
+            latency,
+        })
+    }
+
+    fn apply_cmds(&self) -> Vec<String> {
+        vec![
+            "command -v tc || yum install iproute-tc -y".to_string(),
+            format!(
+                "tc qdisc replace dev {DEFAULT_DEV} root tbf rate {}bit burst {} latency {}ms",
+                self.rate_bits,
+                self.burst_bytes,
+                self.latency.as_millis()
+            ),
+        ]
+    }
+
+    fn remove_cmds() -> Vec<String> {
+        vec![format!(
+            "tc qdisc del dev {DEFAULT_DEV} root 2>/dev/null || true"
+        )]
+    }
+}
+
+// Recorded in the run's metadata
+impl fmt::Display for ThrottleProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rate={}bit,burst={},latency={}ms",
+            self.rate_bits,
+            self.burst_bytes,
+            self.latency.as_millis()
+        )
+    }
+}
+
+// eg. "1600kbit" or "1gbit", in bits per second like tc
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let units = [
+        ("gbit", 1_000_000_000),
+        ("mbit", 1_000_000),
+        ("kbit", 1_000),
+        ("bit", 1),
+    ];
+    units
+        .iter()
+        .find_map(|(unit, scale)| {
+            let rate: u64 = value.strip_suffix(unit)?.parse().ok()?;
+            Some(rate * scale)
+        })
+        .filter(|rate| *rate > 0)
+        .ok_or_else(|| format!("expected a rate in bit, kbit, mbit or gbit, got {value}"))
+}
+
+// Applies the egress limits of the host groups, which are only applied while
+// the drivers run so that the builds and the results uploads aren't limited.
+#[instrument(skip_all)]
+pub async fn apply(
+    compute: &impl ComputeProvider,
+    infra: &InfraDetail,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    for endpoint_type in [EndpointType::Server, EndpointType::Client] {
+        if let Some(profile) = config.run.egress_limit(endpoint_type) {
+            info!(
+                "Limiting the egress of the {:?} hosts to {profile}",
+                endpoint_type
+            );
+            run(compute, infra, endpoint_type, profile.apply_cmds(), config).await?;
+        }
+    }
+    Ok(())
+}
+
+// Removes the limits applied by `apply`
+#[instrument(skip_all)]
+pub async fn remove(
+    compute: &impl ComputeProvider,
+    infra: &InfraDetail,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    for endpoint_type in [EndpointType::Server, EndpointType::Client] {
+        if config.run.egress_limit(endpoint_type).is_some() {
+            run(
+                compute,
+                infra,
+                endpoint_type,
+                ThrottleProfile::remove_cmds(),
+                config,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn run(
+    compute: &impl ComputeProvider,
+    infra: &InfraDetail,
+    endpoint_type: EndpointType,
+    commands: Vec<String>,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    let host_group = endpoint_type.as_str().to_lowercase();
+    let host_ids = match endpoint_type {
+        EndpointType::Server => infra.server_ids(),
+        EndpointType::Client => infra.client_ids(),
+    };
+    let command_id = compute
+        .execute(
+            Step::Throttle,
+            &format!("throttle_{host_group}"),
+            host_ids,
+            commands,
+            config,
+        )
+        .await?;
+    let mut backoff = Backoff::new(MIN_POLL_DELAY, state().poll_delay_ssm);
+    while compute.poll(&host_group, &command_id).await?.is_pending() {
+        tokio::time::sleep(backoff.next_delay()).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_profiles() {
+        let lte = ThrottleProfile::parse("lte").unwrap();
+        assert_eq!(lte.to_string(), "rate=12000000bit,burst=6000,latency=50ms");
+
+        let custom = ThrottleProfile::parse("rate=100kbit,latency=200ms").unwrap();
+        assert_eq!(
+            custom.to_string(),
+            "rate=100000bit,burst=1540,latency=200ms"
+        );
+
+        let burst = ThrottleProfile::parse("rate=1gbit,burst=9000").unwrap();
+        assert_eq!(burst.burst_bytes, 9000);
+
+        assert!(ThrottleProfile::parse("modem").is_err());
+        assert!(ThrottleProfile::parse("rate=10mbps").is_err());
+        assert!(ThrottleProfile::parse("latency=10ms").is_err());
+    }
+}
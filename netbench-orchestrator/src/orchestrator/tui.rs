@@ -0,0 +1,271 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    orchestrator::{dashboard::RunStatus, ErrorKind, OrchError, OrchResult},
+    progress,
+};
+use core::time::Duration;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+use std::io::{self, Stdout};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+// An abort requested from the TUI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Abort {
+    // Stop the current driver pair and continue with the next one
+    Pair,
+    // Stop the run
+    Run,
+}
+
+// Receives the abort requests from the TUI.
+//
+// Without `--tui` an abort is never requested.
+pub struct AbortSignal {
+    rx: Option<mpsc::UnboundedReceiver<Abort>>,
+}
+
+impl AbortSignal {
+    pub fn none() -> Self {
+        AbortSignal { rx: None }
+    }
+
+    // Resolves once an abort is requested.
+    pub async fn requested(&mut self) -> Abort {
+        if let Some(rx) = &mut self.rx {
+            if let Some(abort) = rx.recv().await {
+                return abort;
+            }
+        }
+        // the TUI has exited
+        std::future::pending().await
+    }
+
+    // The abort requested since the last check, if any.
+    pub fn try_requested(&mut self) -> Option<Abort> {
+        self.rx.as_mut()?.try_recv().ok()
+    }
+}
+
+// A live view of the run in the terminal.
+//
+// Shows the hosts and driver pairs of the dashboard status, along with each
+// step of the run (SSM commands, Russula coordination and S3 transfers). The
+// terminal is restored when the TUI is dropped.
+pub struct Tui {
+    task: JoinHandle<()>,
+}
+
+impl Tui {
+    pub fn spawn(mut status: watch::Receiver<RunStatus>) -> OrchResult<(Self, AbortSignal)> {
+        let mut terminal = setup_terminal().map_err(|err| {
+            OrchError::new(ErrorKind::Init, "Failed to start the TUI").with_source(err)
+        })?;
+        // The progress bars would be drawn over the TUI. The steps are shown
+        // by the TUI instead.
+        progress::hide();
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if read_keys(&tx).is_err() {
+                    break;
+                }
+                let status = status.borrow_and_update().clone();
+                if terminal.draw(|frame| draw(frame, &status)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok((Tui { task }, AbortSignal { rx: Some(rx) }))
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = restore_terminal();
+    }
+}
+
+fn setup_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    Terminal::new(CrosstermBackend::new(io::stdout()))
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)
+}
+
+// Forwards the abort keybindings. Ctrl-C is read as a key in raw mode, so it
+// aborts the run as well.
+fn read_keys(abort: &mpsc::UnboundedSender<Abort>) -> io::Result<()> {
+    while event::poll(Duration::ZERO)? {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let request = match key.code {
+            KeyCode::Char('a') => Abort::Pair,
+            KeyCode::Char('q') => Abort::Run,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Abort::Run,
+            _ => continue,
+        };
+        let _ = abort.send(request);
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, status: &RunStatus) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5),
+            Constraint::Length(status.hosts.len() as u16 + 3),
+            Constraint::Length(status.pairs.len() as u16 + 3),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+    let header = Style::default().add_modifier(Modifier::BOLD);
+
+    let mut summary = vec![
+        Line::from(format!(
+            "Phase: {}    ETA: {}",
+            status.phase.as_str(),
+            status.eta.as_deref().unwrap_or("-")
+        )),
+        Line::from(format!("Logs: {}", status.logs_url)),
+    ];
+    if let Some(error) = &status.error {
+        summary.push(Line::styled(
+            format!("Error: {error}"),
+            Style::default().fg(Color::Red),
+        ));
+    } else if let Some(report_url) = &status.report_url {
+        summary.push(Line::from(format!("Report: {report_url}")));
+    }
+    frame.render_widget(
+        Paragraph::new(summary).block(titled(format!("Netbench {}", status.unique_id))),
+        areas[0],
+    );
+
+    let hosts = status.hosts.iter().map(|host| {
+        Row::new(vec![
+            host.endpoint.clone(),
+            host.instance_id.clone(),
+            host.public_ip.clone(),
+            format!("{:?}", host.state),
+            host.driver.clone().unwrap_or_default(),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            hosts,
+            [
+                Constraint::Length(8),
+                Constraint::Length(20),
+                Constraint::Length(16),
+                Constraint::Length(16),
+                Constraint::Min(10),
+            ],
+        )
+        .header(Row::new(["Endpoint", "Instance", "Public IP", "State", "Driver"]).style(header))
+        .block(titled("Hosts".to_string())),
+        areas[1],
+    );
+
+    let pairs = status.pairs.iter().map(|pair| {
+        Row::new(vec![
+            pair.server.clone(),
+            pair.client.clone(),
+            pair.iteration
+                .map(|iteration| iteration.to_string())
+                .unwrap_or_default(),
+            format!("{:?}", pair.state),
+            pair.duration_ms
+                .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+                .unwrap_or_default(),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            pairs,
+            [
+                Constraint::Min(10),
+                Constraint::Min(10),
+                Constraint::Length(10),
+                Constraint::Length(16),
+                Constraint::Length(10),
+            ],
+        )
+        .header(Row::new(["Server", "Client", "Iteration", "State", "Duration"]).style(header))
+        .block(titled("Driver pairs".to_string())),
+        areas[2],
+    );
+
+    // The most recent steps which fit, oldest first
+    let bars = progress::bars();
+    let visible = areas[3].height.saturating_sub(2) as usize;
+    let steps: Vec<Line> = bars
+        .iter()
+        .skip(bars.len().saturating_sub(visible))
+        .map(|bar| {
+            let tasks = match bar.length() {
+                Some(len) if len > 0 => format!(" {}/{len}", bar.position()),
+                _ => String::new(),
+            };
+            if bar.is_finished() {
+                Line::styled(
+                    format!("done        {}{tasks}", bar.message()),
+                    Style::default().fg(Color::DarkGray),
+                )
+            } else {
+                let elapsed = Duration::from_secs(bar.elapsed().as_secs());
+                Line::from(format!(
+                    "{:<12}{}{tasks}",
+                    humantime::format_duration(elapsed).to_string(),
+                    bar.message()
+                ))
+            }
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(steps).block(titled("Steps".to_string())),
+        areas[3],
+    );
+
+    frame.render_widget(
+        Paragraph::new("a: abort the current driver pair    q: abort the run"),
+        areas[4],
+    );
+}
+
+fn titled(title: String) -> Block<'static> {
+    Block::default().borders(Borders::ALL).title(title)
+}
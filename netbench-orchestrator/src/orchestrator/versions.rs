@@ -0,0 +1,95 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    orchestrator::{ErrorKind, OrchError, OrchResult},
+    ssm_utils::DriverRegistry,
+};
+use semver::Version;
+use std::process::Command;
+use tracing::{info, warn};
+
+// Checks that the netbench releases installed on the hosts are compatible with
+// the local `s2n-netbench` cli.
+//
+// The report is generated by the local cli from the results of the collector
+// and drivers on the hosts, so results from an incompatible release can be
+// misread without any error. Releases are compatible if they have the same
+// major version, or the same minor version before 1.0.
+//
+// The collector is pinned to the checked version, since the hosts would
+// otherwise install whichever is the latest once they're configured. Drivers
+// built from GitHub or a local source have no release version to check.
+pub fn check(driver_registry: &mut DriverRegistry, allow_mismatch: bool) -> OrchResult<()> {
+    let Some(local) = local_cli_version() else {
+        warn!("Unable to get the version of the local s2n-netbench cli. The netbench versions of the hosts won't be checked");
+        return Ok(());
+    };
+
+    let mut remote = driver_registry.netbench_crate_versions();
+    if let Some(collector) = driver_registry.pin_collector_version() {
+        remote.push(("s2n-netbench-collector".to_string(), collector));
+    }
+    info!("s2n-netbench {local}, hosts: {remote:?}");
+
+    let incompatible: Vec<String> = remote
+        .iter()
+        .filter(|(_, version)| {
+            Version::parse(version).is_ok_and(|version| !compatible(&local, &version))
+        })
+        .map(|(name, version)| format!("{name} {version}"))
+        .collect();
+    if incompatible.is_empty() {
+        return Ok(());
+    }
+
+    let msg = format!(
+        "The hosts would install netbench releases which are incompatible with the local s2n-netbench {local}, so the report could misread their results: {}",
+        incompatible.join(", ")
+    );
+    if allow_mismatch {
+        warn!("{msg}");
+        return Ok(());
+    }
+    Err(OrchError::new(
+        ErrorKind::Init,
+        format!("{msg}. Install a compatible s2n-netbench, or pass --allow-version-mismatch"),
+    ))
+}
+
+fn local_cli_version() -> Option<Version> {
+    let output = Command::new("s2n-netbench")
+        .arg("--version")
+        .output()
+        .ok()?;
+    parse_version_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+// eg. "s2n-netbench 0.1.0"
+fn parse_version_output(output: &str) -> Option<Version> {
+    let version = output.split_whitespace().last()?;
+    Version::parse(version.trim_start_matches('v')).ok()
+}
+
+fn compatible(local: &Version, remote: &Version) -> bool {
+    match (local.major, remote.major) {
+        (0, 0) => local.minor == remote.minor,
+        (local_major, remote_major) => local_major == remote_major,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semver_compatible_releases() {
+        let local = parse_version_output("s2n-netbench 0.1.2\n").unwrap();
+        assert_eq!(local, Version::new(0, 1, 2));
+        assert!(compatible(&local, &Version::new(0, 1, 0)));
+        assert!(!compatible(&local, &Version::new(0, 2, 0)));
+        assert!(compatible(&Version::new(1, 2, 0), &Version::new(1, 0, 3)));
+        assert!(!compatible(&Version::new(1, 2, 0), &Version::new(2, 0, 0)));
+        assert!(parse_version_output("").is_none());
+    }
+}
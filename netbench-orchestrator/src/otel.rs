@@ -0,0 +1,33 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::orchestrator::{ErrorKind, OrchError, OrchResult};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::Registry;
+
+// A tracing layer which exports the orchestrator's spans to an OTLP collector
+// over gRPC (eg. Jaeger, or the ADOT collector for X-Ray).
+pub fn layer(endpoint: &str) -> OrchResult<OpenTelemetryLayer<Registry, trace::Tracer>> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(endpoint);
+    let resource = Resource::new(vec![KeyValue::new("service.name", env!("CARGO_PKG_NAME"))]);
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(trace::config().with_resource(resource))
+        .install_batch(runtime::Tokio)
+        .map_err(|err| {
+            OrchError::new(ErrorKind::Init, "Failed to create the OTLP exporter").with_source(err)
+        })?;
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+// Exports the remaining spans.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
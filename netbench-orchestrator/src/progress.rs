@@ -0,0 +1,114 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use core::time::Duration;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+const TICK_CHARS: &str = "⠁⠂⠄⡀⢀⠠⠐⠈ ";
+
+// All progress bars of a run are drawn by a single MultiProgress, so that
+// simultaneous steps (eg. building the server and client hosts) each get their
+// own line rather than hiding behind a single spinner.
+//
+// The bars are also kept, in the order they were created, for the TUI to
+// display.
+struct Progress {
+    multi: MultiProgress,
+    bars: Mutex<Vec<ProgressBar>>,
+}
+
+fn progress() -> &'static Progress {
+    static PROGRESS: OnceLock<Progress> = OnceLock::new();
+    PROGRESS.get_or_init(|| Progress {
+        multi: MultiProgress::new(),
+        bars: Mutex::new(Vec::new()),
+    })
+}
+
+fn multi() -> &'static MultiProgress {
+    &progress().multi
+}
+
+// Set by `--quiet`
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Prints a status line for the user (eg. a URL) and logs it.
+//
+// The line is printed above the progress bars so it isn't drawn over. Nothing
+// is printed with `--quiet`.
+macro_rules! output {
+    ($($arg:tt)+) => {{
+        tracing::info!($($arg)+);
+        $crate::progress::print(&format!($($arg)+));
+    }};
+}
+pub(crate) use output;
+
+pub fn print(line: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        multi().suspend(|| println!("{line}"));
+    }
+}
+
+// Stops printing status lines and drawing the progress bars.
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+    hide();
+}
+
+// Stops drawing the bars to the terminal, eg. while the TUI owns it.
+pub fn hide() {
+    multi().set_draw_target(ProgressDrawTarget::hidden());
+}
+
+// The bars created so far, oldest first.
+pub fn bars() -> Vec<ProgressBar> {
+    progress().bars.lock().unwrap().clone()
+}
+
+// A bar tracking the completion of `len` tasks.
+pub fn bar(len: u64, msg: impl Into<Cow<'static, str>>) -> ProgressBar {
+    let style = ProgressStyle::with_template(
+        "{spinner} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+    )
+    .unwrap()
+    .tick_chars(TICK_CHARS);
+    start(multi().add(ProgressBar::new(len)), style, msg)
+}
+
+// A spinner for a single task, eg. waiting for a Russula state or an S3
+// transfer.
+pub fn spinner(msg: impl Into<Cow<'static, str>>) -> ProgressBar {
+    start(multi().add(ProgressBar::new(0)), spinner_style(), msg)
+}
+
+// A spinner for one of the tasks of a bar, drawn below `after` (the bar or the
+// previous task).
+//
+// The elapsed time stops once the task is finished, which shows which host or
+// step is slow.
+pub fn task(after: &ProgressBar, msg: impl Into<Cow<'static, str>>) -> ProgressBar {
+    let task = multi().insert_after(after, ProgressBar::new(0));
+    start(task, spinner_style(), msg)
+}
+
+fn spinner_style() -> ProgressStyle {
+    ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}")
+        .unwrap()
+        .tick_chars(TICK_CHARS)
+}
+
+fn start(bar: ProgressBar, style: ProgressStyle, msg: impl Into<Cow<'static, str>>) -> ProgressBar {
+    bar.set_style(style);
+    bar.enable_steady_tick(Duration::from_secs(1));
+    bar.set_message(msg);
+    progress().bars.lock().unwrap().push(bar.clone());
+    bar
+}
@@ -0,0 +1,60 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use core::time::Duration;
+
+/// The interval between polls, which doubles while nothing happens and is
+/// reset once something does.
+///
+/// The maximum is the safety net for progress which can only be observed by
+/// polling, eg. a worker process exiting.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        let min = min.min(max);
+        Backoff {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// The interval to wait for next, doubling the following one.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_max_and_resets() {
+        let secs = Duration::from_secs;
+        let mut backoff = Backoff::new(secs(1), secs(5));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay()).collect();
+        assert_eq!(delays, [secs(1), secs(2), secs(4), secs(5), secs(5)]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), secs(1));
+
+        assert_eq!(Backoff::new(secs(10), secs(5)).next_delay(), secs(5));
+    }
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use core::fmt::Debug;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
 
 /// A list of events emitted by Russula.
 pub enum EventType {
@@ -10,13 +12,38 @@ pub enum EventType {
 
     /// A Msg was received.
     RecvMsg,
+
+    /// The workflow moved from the `from` state to the next state.
+    Transition { from: String },
+}
+
+/// The time a workflow spent in one of its states.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDuration {
+    pub state: String,
+    pub duration_ms: u64,
 }
 
 /// An event recorder for Russula.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EventRecorder {
     send_msg: u64,
     recv_msg: u64,
+    /// The states which the workflow moved through, in order.
+    states: Vec<StateDuration>,
+    #[serde(skip, default = "Instant::now")]
+    state_entered: Instant,
+}
+
+impl Default for EventRecorder {
+    fn default() -> Self {
+        EventRecorder {
+            send_msg: 0,
+            recv_msg: 0,
+            states: Vec::new(),
+            state_entered: Instant::now(),
+        }
+    }
 }
 
 impl EventRecorder {
@@ -24,6 +51,32 @@ impl EventRecorder {
         match event {
             EventType::SendMsg => self.send_msg += 1,
             EventType::RecvMsg => self.recv_msg += 1,
+            EventType::Transition { from } => {
+                let now = Instant::now();
+                self.states.push(StateDuration {
+                    state: from,
+                    duration_ms: now.duration_since(self.state_entered).as_millis() as u64,
+                });
+                self.state_entered = now;
+            }
         }
     }
+
+    /// The states which the workflow has left, in order.
+    ///
+    /// The terminal state is never left, so it isn't included.
+    #[allow(dead_code)]
+    pub fn states(&self) -> &[StateDuration] {
+        &self.states
+    }
+}
+
+/// The name of a state, without the data it carries (eg. the process id of
+/// `Running(pid)`).
+pub fn state_name(state: &impl Debug) -> String {
+    let name = format!("{:?}", state);
+    match name.split_once('(') {
+        Some((name, _)) => name.to_string(),
+        None => name,
+    }
 }
@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use core::{task::Poll, time::Duration};
+use futures::{stream, StreamExt, TryStreamExt};
 use std::{collections::BTreeSet, net::SocketAddr};
-use tokio::net::TcpStream;
+use tokio::{net::TcpStream, time::Instant};
 use tracing::{error, info};
 
+mod backoff;
 mod error;
 mod event;
 pub mod netbench;
@@ -13,11 +15,24 @@ mod network_utils;
 mod states;
 mod workflow;
 
-use error::{RussulaError, RussulaResult};
+pub use backoff::Backoff;
+pub use error::RussulaError;
+use error::RussulaResult;
+pub use event::EventRecorder;
+use states::{StateApi, TransitionStep};
 use workflow::WorkflowTrait;
 
 const CONNECT_RETRY_ATTEMPT: usize = 10;
 
+// The most peers connected to or polled at once, so that a coordinator of
+// hundreds of workers doesn't open as many sockets in the same instant while
+// still not waiting on each peer in turn.
+const MAX_CONCURRENT_PEERS: usize = 64;
+
+// The shortest wait between polls, which are otherwise made as soon as a peer
+// sends a message
+const MIN_POLL_DELAY: Duration = Duration::from_millis(100);
+
 #[derive(Debug, Copy, Clone)]
 pub enum WorkflowState {
     /// The workflow has established connection with its peer and
@@ -40,6 +55,8 @@ struct Host<W: WorkflowTrait> {
     pub addr: SocketAddr,
     pub stream: TcpStream,
     pub workflow: W,
+    /// The peer was notified that the workflow is Done.
+    pub done_notified: bool,
 }
 
 /// A Workflow instance.
@@ -57,25 +74,78 @@ pub struct Workflow<W: WorkflowTrait> {
 
 impl<W: WorkflowTrait + Send> Workflow<W> {
     pub async fn run_till(&mut self, state: WorkflowState) -> RussulaResult<()> {
+        let mut backoff = self.backoff();
         while self.poll_state(state).await?.is_pending() {
-            tokio::time::sleep(self.poll_delay).await;
+            self.wait_for_peers(state, &mut backoff).await;
+        }
+        if matches!(state, WorkflowState::Done) {
+            self.notify_done().await?;
         }
 
         Ok(())
     }
 
-    pub async fn poll_state(&mut self, state: WorkflowState) -> RussulaResult<Poll<()>> {
-        // Poll each peer workflow instance.
-        //
-        // If the peer is already in the desired state then this should be a noop.
-        for peer in self.instances.iter_mut() {
-            if let Err(err) = peer.workflow.poll_state(&mut peer.stream, state).await {
-                if err.is_fatal() {
-                    error!("{} {}", err, peer.addr);
-                    panic!("{} {}", err, peer.addr);
-                }
+    /// A backoff from [MIN_POLL_DELAY] up to the poll delay of the workflow.
+    pub fn backoff(&self) -> Backoff {
+        Backoff::new(MIN_POLL_DELAY, self.poll_delay)
+    }
+
+    /// Wait till a peer which isn't at the desired state sends a message, or
+    /// for the next interval of the `backoff` otherwise.
+    ///
+    /// Only the peers awaiting a message are waited on, since the others make
+    /// progress on their own (eg. when a process exits) and are only observed
+    /// by polling.
+    pub async fn wait_for_peers(&self, state: WorkflowState, backoff: &mut Backoff) {
+        let start = Instant::now();
+        let delay = backoff.next_delay();
+        let readable: Vec<_> = self
+            .instances
+            .iter()
+            .filter(|peer| {
+                !peer.workflow.is_state(state)
+                    && matches!(
+                        peer.workflow.state().transition_step(),
+                        TransitionStep::AwaitNext(_)
+                    )
+            })
+            .map(|peer| Box::pin(peer.stream.readable()))
+            .collect();
+        if readable.is_empty() {
+            tokio::time::sleep(delay).await;
+            return;
+        }
+
+        tokio::select! {
+            _ = futures::future::select_all(readable) => {
+                backoff.reset();
+                // a peer which closed its stream is always readable, so the
+                // polls are still spaced out
+                tokio::time::sleep_until(start + backoff.min()).await;
             }
+            _ = tokio::time::sleep(delay) => {}
         }
+    }
+
+    pub async fn poll_state(&mut self, state: WorkflowState) -> RussulaResult<Poll<()>> {
+        // Poll the peer workflow instances which aren't in the desired state
+        // yet, concurrently so that a slow peer doesn't delay the others.
+        let pending = self
+            .instances
+            .iter_mut()
+            .filter(|peer| !peer.workflow.is_state(state));
+        stream::iter(pending)
+            .map(Ok)
+            .try_for_each_concurrent(MAX_CONCURRENT_PEERS, |peer| async move {
+                match peer.workflow.poll_state(&mut peer.stream, state).await {
+                    Err(err) if err.is_fatal() => {
+                        error!("{} {}", err, peer.addr);
+                        Err(err)
+                    }
+                    _ => Ok(()),
+                }
+            })
+            .await?;
 
         // Check that all instances are at the desired state.
         let poll = if self.is_state(state) {
@@ -86,6 +156,52 @@ impl<W: WorkflowTrait + Send> Workflow<W> {
         Ok(poll)
     }
 
+    /// The current state of each peer, eg. for displaying progress.
+    #[allow(dead_code)]
+    pub fn peer_states(&self) -> Vec<String> {
+        self.instances
+            .iter()
+            .map(|peer| format!("{:?}", peer.workflow.state()))
+            .collect()
+    }
+
+    /// The events recorded for each peer, eg. the time spent in each state.
+    pub fn event_recorders(&mut self) -> Vec<EventRecorder> {
+        self.instances
+            .iter_mut()
+            .map(|peer| peer.workflow.event_recorder().clone())
+            .collect()
+    }
+
+    /// Notify the peers which reached the Done state that this side is Done.
+    ///
+    /// Called once the Done state is polled, rather than on every poll. Each
+    /// peer is only notified once, since the stream is reliable.
+    pub async fn notify_done(&mut self) -> RussulaResult<()> {
+        let mut done: Vec<_> = self
+            .instances
+            .iter_mut()
+            .filter(|peer| peer.workflow.is_state(WorkflowState::Done) && !peer.done_notified)
+            .collect();
+        if done.is_empty() {
+            return Ok(());
+        }
+        for peer in done.iter_mut() {
+            info!("{:?}", peer.workflow.event_recorder());
+        }
+
+        stream::iter(done.iter_mut())
+            .map(Ok)
+            .try_for_each_concurrent(MAX_CONCURRENT_PEERS, |peer| async move {
+                peer.workflow.notify_done(&mut peer.stream).await
+            })
+            .await?;
+        for peer in done {
+            peer.done_notified = true;
+        }
+        Ok(())
+    }
+
     /// Check if all instances are at the desired state
     fn is_state(&self, state: WorkflowState) -> bool {
         for peer in self.instances.iter() {
@@ -129,52 +245,62 @@ impl<W: WorkflowTrait> WorkflowBuilder<W> {
     /// Build a [Workflow]
     ///
     /// Attempt to establish a connection to all peers via [WorkflowTrait::pair_peer].
+    /// The peers are connected to concurrently, each with its own retries.
     pub async fn build(self) -> RussulaResult<Workflow<W>> {
-        let mut workflow_instances = Vec::new();
-        for (addr, workflow) in self.addrs.into_iter() {
-            let mut retry_attempts = CONNECT_RETRY_ATTEMPT;
-            loop {
-                if retry_attempts == 0 {
-                    return Err(RussulaError::NetworkConnectionRefused {
-                        dbg: "Failed to connect to peer".to_string(),
-                    });
-                }
-                match workflow.pair_peer(&addr).await {
-                    Ok(connect) => {
-                        info!("Coordinator: successfully connected to {}", addr);
-                        workflow_instances.push(Host {
-                            addr,
-                            stream: connect,
-                            workflow,
-                        });
-
-                        break;
-                    }
-                    Err(err) => {
-                        error!(
-                            "Failed to connect.. wait and retry. Try disabling VPN and check your network connectivity.
-                            \nRetry attempts left: {}. addr: {} dbg: {}",
-                            retry_attempts, addr, err
-                        );
-                        println!(
-                            "Failed to connect.. wait and retry. Try disabling VPN and check your network connectivity.
-                            \nRetry attempts left: {}. addr: {} dbg: {}",
-                            retry_attempts, addr, err
-                        );
-                        tokio::time::sleep(self.poll_delay).await;
-                    }
-                }
-                retry_attempts -= 1
-            }
-        }
+        let poll_delay = self.poll_delay;
+        let workflow_instances = stream::iter(self.addrs)
+            .map(|(addr, workflow)| connect(addr, workflow, poll_delay))
+            .buffered(MAX_CONCURRENT_PEERS)
+            .try_collect()
+            .await?;
 
         Ok(Workflow {
             instances: workflow_instances,
-            poll_delay: self.poll_delay,
+            poll_delay,
         })
     }
 }
 
+async fn connect<W: WorkflowTrait>(
+    addr: SocketAddr,
+    workflow: W,
+    poll_delay: Duration,
+) -> RussulaResult<Host<W>> {
+    let mut retry_attempts = CONNECT_RETRY_ATTEMPT;
+    loop {
+        if retry_attempts == 0 {
+            return Err(RussulaError::NetworkConnectionRefused {
+                dbg: "Failed to connect to peer".to_string(),
+            });
+        }
+        match workflow.pair_peer(&addr).await {
+            Ok(connect) => {
+                info!("Coordinator: successfully connected to {}", addr);
+                return Ok(Host {
+                    addr,
+                    stream: connect,
+                    workflow,
+                    done_notified: false,
+                });
+            }
+            Err(err) => {
+                error!(
+                    "Failed to connect.. wait and retry. Try disabling VPN and check your network connectivity.
+                    \nRetry attempts left: {}. addr: {} dbg: {}",
+                    retry_attempts, addr, err
+                );
+                println!(
+                    "Failed to connect.. wait and retry. Try disabling VPN and check your network connectivity.
+                    \nRetry attempts left: {}. addr: {} dbg: {}",
+                    retry_attempts, addr, err
+                );
+                tokio::time::sleep(poll_delay).await;
+            }
+        }
+        retry_attempts -= 1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -246,6 +372,7 @@ mod tests {
         {
             println!("continue to poll till: Done");
         }
+        coord.notify_done().await.unwrap();
 
         {
             let worker_join = join_all(workers).await;
@@ -315,6 +442,7 @@ mod tests {
         {
             println!("continue to poll till: Done");
         }
+        coord.notify_done().await.unwrap();
 
         {
             let worker_join = join_all(workers).await;
@@ -324,4 +452,42 @@ mod tests {
             }
         }
     }
+
+    // A single coordinator drives over a hundred workers, eg. for incast
+    // scenarios with hundreds of clients.
+    #[tokio::test]
+    async fn netbench_client_workflow_many_workers() {
+        let worker_addrs: Vec<SocketAddr> = (10001..=10120)
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+            .collect();
+        let workers: Vec<_> = worker_addrs
+            .iter()
+            .map(|&sock| {
+                tokio::spawn(async move {
+                    let worker = WorkflowBuilder::new(
+                        BTreeSet::from_iter([sock]),
+                        client::WorkerWorkflow::new(
+                            sock.port().to_string(),
+                            netbench::ClientContext::testing(),
+                        ),
+                        POLL_DELAY_DURATION,
+                    );
+                    let mut worker = worker.build().await.unwrap();
+                    worker.run_till(WorkflowState::Done).await.unwrap();
+                    worker
+                })
+            })
+            .collect();
+
+        let addr = BTreeSet::from_iter(worker_addrs);
+        let coord = WorkflowBuilder::new(addr, client::CoordWorkflow::new(), POLL_DELAY_DURATION);
+        let mut coord = coord.build().await.unwrap();
+        coord.run_till(WorkflowState::Ready).await.unwrap();
+        coord.run_till(WorkflowState::WorkerRunning).await.unwrap();
+        coord.run_till(WorkflowState::Done).await.unwrap();
+
+        for w in join_all(workers).await {
+            assert!(w.unwrap().is_state(WorkflowState::Done));
+        }
+    }
 }
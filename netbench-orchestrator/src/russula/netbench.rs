@@ -1,14 +1,29 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{net::SocketAddr, path::PathBuf};
+use std::{
+    net::{IpAddr, SocketAddr, UdpSocket},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use structopt::StructOpt;
+use sysinfo::{Pid, PidExt, ProcessExt, ProcessStatus, Signal, SystemExt};
 
+mod capture;
 mod client_coord;
 mod client_worker;
+mod driver_name;
+mod profiler;
 mod server_coord;
 mod server_worker;
 
+use capture::CaptureArgs;
+pub use driver_name::DriverName;
+pub use profiler::{Profiler, FLAMEGRAPH_DIR};
+
+// How often the workers check on the processes they spawn
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(StructOpt, Debug, Clone)]
 pub struct ClientContext {
     /// Run a test program instead of the Netbench process.
@@ -21,7 +36,19 @@ pub struct ClientContext {
 
     /// Specify the Netbench driver which should be run.
     #[structopt(long)]
-    driver: String,
+    driver: DriverName,
+
+    /// Extra arguments passed to the Netbench driver. Can be repeated.
+    #[structopt(long = "driver-arg", allow_hyphen_values = true, number_of_values = 1)]
+    driver_args: Vec<String>,
+
+    /// Environment variables (`KEY=VALUE`) set for the Netbench driver. Can be repeated.
+    #[structopt(long = "driver-env", parse(try_from_str = parse_env), number_of_values = 1)]
+    driver_env: Vec<(String, String)>,
+
+    /// How often the collector samples the Netbench driver (eg. "100ms").
+    #[structopt(long)]
+    collector_interval: Option<String>,
 
     /// The name of the scenario file.
     ///
@@ -30,9 +57,54 @@ pub struct ClientContext {
     #[structopt(long, default_value = "request_response.json")]
     scenario: String,
 
-    /// List of Netbench Server the client should connect to.
+    /// The address of each server of the scenario, in the order of the scenario.
     #[structopt(long)]
     netbench_servers: Vec<SocketAddr>,
+
+    /// Seconds to run the Netbench driver before the measured run.
+    ///
+    /// The warmup output is discarded so that connection establishment and
+    /// caching effects don't affect the results of short scenarios.
+    #[structopt(long, default_value = "0")]
+    warmup_secs: u64,
+
+    /// Also run the scenario's servers on the host with this Netbench driver,
+    /// for a client connecting to them over loopback.
+    ///
+    /// The servers are started before the client, on the ports of
+    /// `--netbench-servers`, and stopped once the client is done.
+    #[structopt(long)]
+    loopback_server: Option<DriverName>,
+
+    /// Extra arguments passed to the loopback server driver. Can be repeated.
+    #[structopt(
+        long = "loopback-server-arg",
+        allow_hyphen_values = true,
+        number_of_values = 1
+    )]
+    loopback_server_args: Vec<String>,
+
+    /// Environment variables (`KEY=VALUE`) set for the loopback server driver. Can be repeated.
+    #[structopt(long = "loopback-server-env", parse(try_from_str = parse_env), number_of_values = 1)]
+    loopback_server_env: Vec<(String, String)>,
+
+    /// How often the collector samples the loopback server driver (eg. "100ms").
+    #[structopt(long)]
+    loopback_server_collector_interval: Option<String>,
+
+    /// Profile the Netbench driver while it runs (`perf` or `bpftrace`), and
+    /// write a flamegraph of it next to its results.
+    ///
+    /// The loopback servers aren't profiled.
+    #[structopt(long)]
+    profiler: Option<Profiler>,
+
+    /// The sampling frequency of the profiler, in Hz.
+    #[structopt(long, default_value = "99")]
+    profile_frequency: u32,
+
+    #[structopt(flatten)]
+    capture: CaptureArgs,
 }
 
 #[derive(StructOpt, Debug, Clone)]
@@ -47,7 +119,19 @@ pub struct ServerContext {
 
     /// Specify the Netbench driver which should be run.
     #[structopt(long)]
-    driver: String,
+    driver: DriverName,
+
+    /// Extra arguments passed to the Netbench driver. Can be repeated.
+    #[structopt(long = "driver-arg", allow_hyphen_values = true, number_of_values = 1)]
+    driver_args: Vec<String>,
+
+    /// Environment variables (`KEY=VALUE`) set for the Netbench driver. Can be repeated.
+    #[structopt(long = "driver-env", parse(try_from_str = parse_env), number_of_values = 1)]
+    driver_env: Vec<(String, String)>,
+
+    /// How often the collector samples the Netbench driver (eg. "100ms").
+    #[structopt(long)]
+    collector_interval: Option<String>,
 
     /// The name of the scenario file.
     ///
@@ -57,8 +141,42 @@ pub struct ServerContext {
     scenario: String,
 
     /// The port on which the Netbench Server process should accept connections.
+    ///
+    /// Only used without `--netbench-servers`.
     #[structopt(long, default_value = "4433")]
     netbench_port: u16,
+
+    /// The address of each server of the scenario, in the order of the scenario.
+    ///
+    /// The worker runs the servers whose address is local to the host.
+    #[structopt(long)]
+    netbench_servers: Vec<SocketAddr>,
+
+    /// Profile the Netbench drivers while they run (`perf` or `bpftrace`), and
+    /// write a flamegraph of each server next to its results.
+    #[structopt(long)]
+    profiler: Option<Profiler>,
+
+    /// The sampling frequency of the profiler, in Hz.
+    #[structopt(long, default_value = "99")]
+    profile_frequency: u32,
+
+    #[structopt(flatten)]
+    capture: CaptureArgs,
+
+    /// Leave the Netbench servers running for this many seconds once the
+    /// worker is done, for interactive clients. The servers write their
+    /// results to `<worker>.kept.json`, and a snapshot of the run is written
+    /// to the usual results file.
+    #[structopt(long)]
+    keep_servers: Option<u64>,
+}
+
+fn parse_env(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {value}"))?;
+    Ok((key.to_string(), value.to_string()))
 }
 
 impl ServerContext {
@@ -66,42 +184,101 @@ impl ServerContext {
     pub fn testing() -> Self {
         ServerContext {
             netbench_path: "".into(),
-            driver: "".to_string(),
+            driver: DriverName::new("netbench-driver-testing"),
+            driver_args: vec![],
+            driver_env: vec![],
+            collector_interval: None,
             scenario: "".to_string(),
             testing: true,
             netbench_port: 4433,
+            netbench_servers: vec![],
+            profiler: None,
+            profile_frequency: 99,
+            capture: CaptureArgs::default(),
+            keep_servers: None,
         }
     }
 
     #[allow(dead_code)]
-    pub fn trim_driver_name(&self) -> String {
-        self.driver
-            .trim_start_matches("s2n-netbench-driver-")
-            .trim_start_matches("netbench-driver-")
-            .trim_end_matches(".json")
-            .to_owned()
+    pub fn driver(&self) -> &DriverName {
+        &self.driver
+    }
+
+    // The scenario servers run on this host, as their id and port.
+    //
+    // Without `--netbench-servers` the host runs the scenario's first server
+    // on `--netbench-port`.
+    pub fn local_servers(&self) -> Vec<(usize, u16)> {
+        if self.netbench_servers.is_empty() {
+            return vec![(0, self.netbench_port)];
+        }
+        self.netbench_servers
+            .iter()
+            .enumerate()
+            .filter(|(_, addr)| is_local(addr.ip()))
+            .map(|(server_id, addr)| (server_id, addr.port()))
+            .collect()
     }
 }
 
+// An address is local to the host if a socket can be bound to it
+fn is_local(ip: IpAddr) -> bool {
+    UdpSocket::bind(SocketAddr::new(ip, 0)).is_ok()
+}
+
+// Interrupts a process spawned by the worker, eg. a profiler which writes its
+// samples when it's interrupted, and waits for it to exit. Returns false if
+// it's still running after `timeout`.
+async fn interrupt(pid: u32, timeout: Duration) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = sysinfo::System::new();
+    if system.refresh_process(pid) {
+        if let Some(process) = system.process(pid) {
+            process.kill_with(Signal::Interrupt);
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        // the process is a child of the worker, so it's a zombie once it exits
+        let running = system.refresh_process(pid)
+            && system
+                .process(pid)
+                .is_some_and(|process| !matches!(process.status(), ProcessStatus::Zombie));
+        if !running {
+            return true;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    false
+}
+
 impl ClientContext {
     #[cfg(test)]
     pub fn testing() -> Self {
         ClientContext {
             netbench_servers: vec![],
             netbench_path: "".into(),
-            driver: "".to_string(),
+            driver: DriverName::new("netbench-driver-testing"),
+            driver_args: vec![],
+            driver_env: vec![],
+            collector_interval: None,
             scenario: "".to_string(),
             testing: true,
+            warmup_secs: 0,
+            loopback_server: None,
+            loopback_server_args: vec![],
+            loopback_server_env: vec![],
+            loopback_server_collector_interval: None,
+            profiler: None,
+            profile_frequency: 99,
+            capture: CaptureArgs::default(),
         }
     }
 
     #[allow(dead_code)]
-    pub fn trim_driver_name(&self) -> String {
-        self.driver
-            .trim_start_matches("s2n-netbench-driver-")
-            .trim_start_matches("netbench-driver-")
-            .trim_end_matches(".json")
-            .to_owned()
+    pub fn driver(&self) -> &DriverName {
+        &self.driver
     }
 }
 
@@ -186,3 +363,21 @@ pub mod client {
         client_worker::{WorkerState, WorkerWorkflow},
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn servers_local_to_the_host() {
+        let mut ctx = ServerContext::testing();
+        assert_eq!(ctx.local_servers(), vec![(0, 4433)]);
+
+        // 192.0.2.0/24 is reserved for documentation, so isn't local
+        ctx.netbench_servers = ["127.0.0.1:4433", "192.0.2.1:4433", "127.0.0.1:4434"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        assert_eq!(ctx.local_servers(), vec![(0, 4433), (2, 4434)]);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    process::{Command, Stdio},
+    time::Duration,
+};
+use structopt::StructOpt;
+use tracing::{debug, info, warn};
+
+// How long tcpdump has to flush the capture once it's interrupted
+const CAPTURE_SHUTDOWN: Duration = Duration::from_secs(10);
+
+/// Packet capture of the Netbench traffic while the driver runs.
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct CaptureArgs {
+    /// Capture the Netbench traffic of the host with tcpdump while the driver
+    /// runs, to `<worker>.pcap<n>` files next to the results.
+    #[structopt(long)]
+    capture: bool,
+
+    /// The bytes captured of each packet, which only covers the headers by
+    /// default. 0 captures whole packets.
+    #[structopt(long, default_value = "128")]
+    capture_snaplen: u32,
+
+    /// The size of each file of the capture's ring buffer, in MB.
+    #[structopt(long, default_value = "100")]
+    capture_file_size: u32,
+
+    /// The files of the capture's ring buffer. The oldest file is overwritten
+    /// once they're full.
+    #[structopt(long, default_value = "4")]
+    capture_files: u32,
+
+    /// Seconds after which the capture stops, even if the driver is still
+    /// running.
+    #[structopt(long, default_value = "600")]
+    capture_max_secs: u64,
+}
+
+impl CaptureArgs {
+    #[cfg(test)]
+    pub fn testing() -> Self {
+        CaptureArgs {
+            capture: true,
+            capture_snaplen: 128,
+            capture_file_size: 100,
+            capture_files: 4,
+            capture_max_secs: 600,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capture
+    }
+
+    // Captures the traffic of the Netbench `ports` until the capture is
+    // finished, or for at most `--capture-max-secs`. Packet capture is best
+    // effort, so the driver runs without a capture if tcpdump can't be
+    // started.
+    pub fn start(&self, ports: &[u16], name: &str) -> Option<Capture> {
+        let mut cmd = self.command(ports, name);
+        cmd.stdout(Stdio::null());
+        debug!("{:?}", cmd);
+        match cmd.spawn() {
+            Ok(child) => {
+                info!("{name} capturing ports {ports:?}");
+                Some(Capture {
+                    pid: child.id(),
+                    name: name.to_string(),
+                })
+            }
+            Err(err) => {
+                warn!("{name} not captured: failed to start tcpdump. {err}");
+                None
+            }
+        }
+    }
+
+    // tcpdump on all the interfaces, since the loopback servers are captured
+    // on `lo`. The files are written as root, rather than the user tcpdump
+    // drops to, since the worker's directory belongs to root.
+    fn command(&self, ports: &[u16], name: &str) -> Command {
+        let mut cmd = Command::new("timeout");
+        cmd.args([
+            "--signal=INT",
+            &self.capture_max_secs.to_string(),
+            "tcpdump",
+        ])
+        .args(["-i", "any", "-n", "-Z", "root"])
+        .args(["-s", &self.capture_snaplen.to_string()])
+        .args(["-C", &self.capture_file_size.to_string()])
+        .args(["-W", &self.capture_files.to_string()])
+        .args(["-w", &format!("{name}.pcap")]);
+        // the filter is an expression of several arguments, eg. `port 4433 or port 4434`
+        for (i, port) in ports.iter().enumerate() {
+            if i > 0 {
+                cmd.arg("or");
+            }
+            cmd.args(["port", &port.to_string()]);
+        }
+        cmd
+    }
+}
+
+/// A running capture, see `--capture`.
+#[derive(Clone, Debug)]
+pub struct Capture {
+    // the timeout process id, which forwards the interrupt to tcpdump
+    pid: u32,
+    name: String,
+}
+
+impl Capture {
+    // Stops the capture, once tcpdump has written the buffered packets
+    pub async fn finish(self) {
+        match super::interrupt(self.pid, CAPTURE_SHUTDOWN).await {
+            true => info!("{} finished the capture", self.name),
+            false => warn!(
+                "{} tcpdump didn't exit after {:?}",
+                self.name, CAPTURE_SHUTDOWN
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcpdump_of_the_ports() {
+        let cmd = CaptureArgs::testing().command(&[4433, 4434], "server-w-1-server-tcp");
+        let args: Vec<_> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(cmd.get_program(), "timeout");
+        assert_eq!(
+            args.join(" "),
+            "--signal=INT 600 tcpdump -i any -n -Z root -s 128 -C 100 -W 4 -w server-w-1-server-tcp.pcap port 4433 or port 4434"
+        );
+    }
+}
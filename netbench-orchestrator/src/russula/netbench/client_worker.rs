@@ -1,7 +1,7 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::ClientContext;
+use super::{capture::Capture, profiler::Profile, ClientContext, DriverName, FLAMEGRAPH_DIR};
 use crate::russula::{
     error::{RussulaError, RussulaResult},
     event::EventRecorder,
@@ -12,7 +12,12 @@ use crate::russula::{
 };
 use core::fmt::Debug;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, net::SocketAddr, process::Command};
+use std::{
+    fs::File,
+    net::SocketAddr,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
 use sysinfo::{Pid, PidExt, ProcessExt, SystemExt};
 use tokio::net::{TcpListener, TcpStream};
 use tracing::{debug, info, warn};
@@ -20,6 +25,9 @@ use tracing::{debug, info, warn};
 // Only used when creating a state variant for comparison
 const PLACEHOLDER_PID: u32 = 1000;
 
+// Time for the loopback servers to start listening before the client connects
+const LOOPBACK_SERVER_STARTUP: Duration = Duration::from_secs(1);
+
 /// Workflow state machine
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WorkerState {
@@ -46,6 +54,12 @@ pub struct WorkerWorkflow {
     peer_state: CoordState,
     netbench_ctx: ClientContext,
     event_recorder: EventRecorder,
+    // netbench loopback server process ids, see `--loopback-server`
+    loopback_servers: Vec<u32>,
+    // the profile of the client, see `--profiler`
+    profile: Option<Profile>,
+    // the capture of the client's traffic, see `--capture`
+    capture: Option<Capture>,
 }
 
 impl WorkerWorkflow {
@@ -57,6 +71,113 @@ impl WorkerWorkflow {
             peer_state: CoordState::CheckWorker,
             netbench_ctx,
             event_recorder: EventRecorder::default(),
+            loopback_servers: Vec::new(),
+            profile: None,
+            capture: None,
+        }
+    }
+
+    // Runs each of the scenario's servers with the loopback server driver, on
+    // the port of its address. Like the server workers, the results are
+    // written to a file per server whose name ends with the driver name.
+    fn spawn_loopback_servers(&mut self, server: &DriverName) {
+        let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
+        let collector = format!("{}/s2n-netbench-collector", netbench_path);
+        let driver = format!("{}/{}", netbench_path, server);
+        let scenario = format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
+
+        for (server_id, addr) in self.netbench_ctx.netbench_servers.iter().enumerate() {
+            let output_log_file =
+                format!("server-w-{server_id}-{}-{}.json", self.id, server.short());
+            let output_log_file = File::create(output_log_file).expect("failed to open log");
+
+            info!("{} run loopback netbench server {server_id}", self.name());
+
+            let mut cmd = Command::new(&collector);
+            cmd.args([&driver, "--scenario", &scenario]);
+            if let Some(interval) = &self.netbench_ctx.loopback_server_collector_interval {
+                cmd.args(["--interval", interval]);
+            }
+            // arguments after `--` are passed through to the driver
+            if !self.netbench_ctx.loopback_server_args.is_empty() {
+                cmd.arg("--").args(&self.netbench_ctx.loopback_server_args);
+            }
+            // inherited by the driver, which is spawned by the collector
+            cmd.envs(self.netbench_ctx.loopback_server_env.iter().cloned());
+            cmd.stdout(output_log_file);
+            cmd.env("SERVER_ID", server_id.to_string());
+            cmd.env("PORT", addr.port().to_string());
+            debug!("{:?}", cmd);
+            let child = cmd
+                .spawn()
+                .expect("Failed to start netbench loopback server process");
+            self.loopback_servers.push(child.id());
+        }
+    }
+
+    fn kill_loopback_servers(&mut self) {
+        if self.loopback_servers.is_empty() {
+            return;
+        }
+
+        let mut system = sysinfo::System::new_all();
+        for pid in self.loopback_servers.drain(..) {
+            let pid = Pid::from_u32(pid);
+            if system.refresh_process(pid) {
+                let kill = system.process(pid).map(|process| process.kill());
+                debug!("did KILL loopback server pid: {} {:?}", pid, kill);
+            } else {
+                warn!("loopback server process {} already exited", pid);
+            }
+        }
+    }
+
+    // Run the driver without the collector for the warmup duration, repeating
+    // the scenario if it completes early. The output is discarded.
+    async fn warmup(&self, driver: &str, scenario: &str) {
+        let warmup = Duration::from_secs(self.netbench_ctx.warmup_secs);
+        info!("{} warmup netbench process for {:?}", self.name(), warmup);
+        let deadline = Instant::now() + warmup;
+
+        while Instant::now() < deadline {
+            let mut cmd = Command::new(driver);
+            for (i, peer_list) in self.netbench_ctx.netbench_servers.iter().enumerate() {
+                cmd.env(format!("SERVER_{}", i), peer_list.to_string());
+            }
+            cmd.args(&self.netbench_ctx.driver_args)
+                .envs(self.netbench_ctx.driver_env.iter().cloned())
+                .env("SCENARIO", scenario)
+                .env("TRACE", "disabled")
+                .stdout(Stdio::null());
+            debug!("{:?}", cmd);
+
+            let mut child = match cmd.spawn() {
+                Ok(child) => child,
+                Err(err) => {
+                    warn!("{} failed to start warmup: {err}", self.name());
+                    return;
+                }
+            };
+
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) if status.success() => break,
+                    Ok(Some(status)) => {
+                        warn!("{} warmup exited with {status}", self.name());
+                        return;
+                    }
+                    Ok(None) if Instant::now() >= deadline => {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        return;
+                    }
+                    Ok(None) => tokio::time::sleep(Duration::from_millis(100)).await,
+                    Err(err) => {
+                        warn!("{} failed to wait for warmup: {err}", self.name());
+                        return;
+                    }
+                }
+            }
         }
     }
 }
@@ -127,18 +248,58 @@ impl WorkflowTrait for WorkerWorkflow {
                         let driver = format!("{}/{}", netbench_path, self.netbench_ctx.driver);
                         let scenario = format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
 
+                        if let Some(server) = self.netbench_ctx.loopback_server.clone() {
+                            self.spawn_loopback_servers(&server);
+                            tokio::time::sleep(LOOPBACK_SERVER_STARTUP).await;
+                        }
+
+                        if self.netbench_ctx.warmup_secs > 0 {
+                            self.warmup(&driver, &scenario).await;
+                        }
+
+                        // only the measured run is captured, after the warmup
+                        if self.netbench_ctx.capture.is_enabled() {
+                            let ports: Vec<u16> = self
+                                .netbench_ctx
+                                .netbench_servers
+                                .iter()
+                                .map(SocketAddr::port)
+                                .collect();
+                            self.capture = self.netbench_ctx.capture.start(&ports, &self.name());
+                        }
+
                         let mut cmd = Command::new(collector);
                         for (i, peer_list) in self.netbench_ctx.netbench_servers.iter().enumerate()
                         {
                             let server_idx = format!("SERVER_{}", i);
                             cmd.env(server_idx, peer_list.to_string());
                         }
-                        cmd.args([&driver, "--scenario", &scenario])
-                            .stdout(output_log_file);
+                        cmd.args([&driver, "--scenario", &scenario]);
+                        if let Some(interval) = &self.netbench_ctx.collector_interval {
+                            cmd.args(["--interval", interval]);
+                        }
+                        // arguments after `--` are passed through to the driver
+                        if !self.netbench_ctx.driver_args.is_empty() {
+                            cmd.arg("--").args(&self.netbench_ctx.driver_args);
+                        }
+                        // inherited by the driver, which is spawned by the collector
+                        cmd.envs(self.netbench_ctx.driver_env.iter().cloned());
+                        cmd.stdout(output_log_file);
                         println!("{:?}", cmd);
                         debug!("{:?}", cmd);
-                        cmd.spawn()
-                            .expect("Failed to start netbench client process")
+                        let child = cmd
+                            .spawn()
+                            .expect("Failed to start netbench client process");
+                        if let Some(profiler) = self.netbench_ctx.profiler {
+                            self.profile = Profile::attach(
+                                profiler,
+                                self.netbench_ctx.profile_frequency,
+                                child.id(),
+                                self.name(),
+                            )
+                            .await;
+                        }
+                        child
                     }
                     true => {
                         info!("{} run sim_netbench_client", self.name());
@@ -190,6 +351,17 @@ impl WorkflowTrait for WorkerWorkflow {
                 Ok(None)
             }
             WorkerState::Stopped => {
+                // the flamegraph is written before the coordinator is told
+                // the client is done, and the results are uploaded
+                if let Some(profile) = self.profile.take() {
+                    let flamegraph_path = self.netbench_ctx.netbench_path.join(FLAMEGRAPH_DIR);
+                    profile.finish(&flamegraph_path).await;
+                }
+                if let Some(capture) = self.capture.take() {
+                    capture.finish().await;
+                }
+                // the loopback servers run until the client is done
+                self.kill_loopback_servers();
                 self.notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
@@ -0,0 +1,75 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fmt, str::FromStr};
+
+const PREFIXES: [&str; 2] = ["s2n-netbench-driver-", "netbench-driver-"];
+
+/// The name of a Netbench driver executable (eg. `s2n-netbench-driver-server-tcp`).
+///
+/// The worker names its results after the short name of the driver and the
+/// orchestrator uploads them by the same name, so both must be derived from
+/// this type.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DriverName(String);
+
+impl DriverName {
+    pub fn new(name: impl Into<String>) -> Self {
+        DriverName(name.into())
+    }
+
+    /// The driver name without the netbench prefix (eg. `server-tcp`).
+    pub fn short(&self) -> &str {
+        PREFIXES
+            .iter()
+            .find_map(|prefix| self.0.strip_prefix(prefix))
+            .unwrap_or(&self.0)
+    }
+}
+
+impl FromStr for DriverName {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if name.is_empty() {
+            return Err("driver name is empty".to_string());
+        }
+        if name.contains(['/', ' ']) {
+            return Err(format!("driver name must not be a path: {name}"));
+        }
+        Ok(DriverName::new(name))
+    }
+}
+
+impl fmt::Display for DriverName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_name() {
+        let name: DriverName = "s2n-netbench-driver-server-s2n-quic".parse().unwrap();
+        assert_eq!(name.short(), "server-s2n-quic");
+        assert_eq!(name.to_string(), "s2n-netbench-driver-server-s2n-quic");
+
+        let name = DriverName::new("netbench-driver-client-tcp");
+        assert_eq!(name.short(), "client-tcp");
+
+        // isolated builds keep the entry suffix
+        let name = DriverName::new("s2n-netbench-driver-client-tcp-tcp-nagle");
+        assert_eq!(name.short(), "client-tcp-tcp-nagle");
+
+        let name = DriverName::new("iperf-server");
+        assert_eq!(name.short(), "iperf-server");
+
+        assert!("".parse::<DriverName>().is_err());
+        assert!("bin/s2n-netbench-driver-server-tcp"
+            .parse::<DriverName>()
+            .is_err());
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt,
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use sysinfo::{Pid, PidExt, ProcessExt, SystemExt};
+use tracing::{debug, info, warn};
+
+// How long the collector has to spawn the driver
+const DRIVER_STARTUP: Duration = Duration::from_secs(5);
+// How long the profiler has to write its samples once it's interrupted
+const PROFILER_SHUTDOWN: Duration = Duration::from_secs(30);
+
+/// The FlameGraph scripts, relative to the netbench path of the hosts.
+pub const FLAMEGRAPH_DIR: &str = "FlameGraph";
+
+/// The profiler attached to the Netbench drivers while they run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profiler {
+    /// `perf record` of the driver's stacks
+    Perf,
+    /// A bpftrace script which counts the driver's user and kernel stacks
+    Bpftrace,
+}
+
+impl Profiler {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profiler::Perf => "perf",
+            Profiler::Bpftrace => "bpftrace",
+        }
+    }
+
+    // The raw samples, which are collapsed into the flamegraph
+    fn output_file(&self, name: &str) -> String {
+        match self {
+            Profiler::Perf => format!("{name}.perf.data"),
+            Profiler::Bpftrace => format!("{name}.bpftrace"),
+        }
+    }
+}
+
+impl FromStr for Profiler {
+    type Err = String;
+
+    fn from_str(profiler: &str) -> Result<Self, Self::Err> {
+        match profiler {
+            "perf" => Ok(Profiler::Perf),
+            "bpftrace" => Ok(Profiler::Bpftrace),
+            _ => Err(format!(
+                "unknown profiler {profiler}, expected perf or bpftrace"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A profiler attached to a running driver.
+///
+/// The flamegraph of the driver is written to `<name>.svg` once the profile
+/// is finished. The name ends with the driver name, which is how the
+/// orchestrator uploads the flamegraph with the driver's results.
+///
+/// Profiling is best effort: a driver which can't be profiled is logged and
+/// the run continues without its flamegraph.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    profiler: Profiler,
+    // the profiler process id
+    pid: u32,
+    name: String,
+}
+
+impl Profile {
+    // Attaches the profiler to the driver spawned by the collector
+    // `collector_pid`, sampling at `frequency` Hz.
+    pub async fn attach(
+        profiler: Profiler,
+        frequency: u32,
+        collector_pid: u32,
+        name: String,
+    ) -> Option<Self> {
+        let Some(driver_pid) = driver_pid(collector_pid).await else {
+            warn!("{name} not profiled: the driver of collector {collector_pid} wasn't found");
+            return None;
+        };
+
+        let mut cmd = match profiler {
+            Profiler::Perf => {
+                let mut cmd = Command::new("perf");
+                cmd.args(["record", "-g", "-F", &frequency.to_string(), "-p"])
+                    .arg(driver_pid.to_string())
+                    .args(["-o", &profiler.output_file(&name)]);
+                cmd
+            }
+            Profiler::Bpftrace => {
+                let mut cmd = Command::new("bpftrace");
+                cmd.args(["-o", &profiler.output_file(&name), "-e"])
+                    .arg(bpftrace_script(frequency, driver_pid));
+                cmd
+            }
+        };
+        cmd.stdout(Stdio::null());
+        debug!("{:?}", cmd);
+        match cmd.spawn() {
+            Ok(child) => {
+                info!("{name} profiling driver pid {driver_pid} with {profiler}");
+                Some(Profile {
+                    profiler,
+                    pid: child.id(),
+                    name,
+                })
+            }
+            Err(err) => {
+                warn!("{name} not profiled: failed to start {profiler}. {err}");
+                None
+            }
+        }
+    }
+
+    // Stops the profiler and writes the flamegraph with the FlameGraph
+    // scripts in `flamegraph_path`.
+    pub async fn finish(self, flamegraph_path: &Path) {
+        if !super::interrupt(self.pid, PROFILER_SHUTDOWN).await {
+            warn!(
+                "{} {} didn't exit after {:?}",
+                self.name, self.profiler, PROFILER_SHUTDOWN
+            );
+        }
+
+        let script = self.flamegraph_script(flamegraph_path);
+        debug!("{}", script);
+        match Command::new("bash")
+            .args(["-o", "pipefail", "-c", &script])
+            .status()
+        {
+            Ok(status) if status.success() => info!("{} wrote {}.svg", self.name, self.name),
+            Ok(status) => warn!("{} failed to write the flamegraph: {status}", self.name),
+            Err(err) => warn!("{} failed to write the flamegraph. {err}", self.name),
+        }
+        // the raw samples can be large and aren't uploaded
+        let _ = std::fs::remove_file(self.profiler.output_file(&self.name));
+    }
+
+    // Collapses the profiler's stacks into `<name>.svg`
+    fn flamegraph_script(&self, flamegraph_path: &Path) -> String {
+        let flamegraph_path = flamegraph_path.display();
+        let name = &self.name;
+        let output = self.profiler.output_file(name);
+        let collapse = match self.profiler {
+            Profiler::Perf => {
+                format!("perf script -i {output} | {flamegraph_path}/stackcollapse-perf.pl")
+            }
+            Profiler::Bpftrace => {
+                format!("{flamegraph_path}/stackcollapse-bpftrace.pl {output}")
+            }
+        };
+        format!("{collapse} | {flamegraph_path}/flamegraph.pl --title {name} > {name}.svg")
+    }
+}
+
+// Samples the stacks of the driver. The kernel stack is printed first since
+// the stacks are printed from the leaf.
+fn bpftrace_script(frequency: u32, driver_pid: u32) -> String {
+    format!("profile:hz:{frequency} /pid == {driver_pid}/ {{ @[kstack, ustack] = count(); }}")
+}
+
+// The driver is the child process of the collector, which is spawned shortly
+// after the collector starts.
+async fn driver_pid(collector_pid: u32) -> Option<u32> {
+    let collector_pid = Pid::from_u32(collector_pid);
+    let deadline = Instant::now() + DRIVER_STARTUP;
+    let mut system = sysinfo::System::new();
+    while Instant::now() < deadline {
+        system.refresh_processes();
+        let driver = system
+            .processes()
+            .values()
+            .find(|process| process.parent() == Some(collector_pid));
+        if let Some(driver) = driver {
+            return Some(driver.pid().as_u32());
+        }
+        tokio::time::sleep(super::POLL_INTERVAL).await;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flamegraph_of_the_profile() {
+        let profile = |profiler| Profile {
+            profiler,
+            pid: 1000,
+            name: "client-w-1-client-tcp".to_string(),
+        };
+        let flamegraph_path = Path::new("/home/ec2-user/bin/FlameGraph");
+
+        assert_eq!(
+            profile(Profiler::Perf).flamegraph_script(flamegraph_path),
+            "perf script -i client-w-1-client-tcp.perf.data | /home/ec2-user/bin/FlameGraph/stackcollapse-perf.pl | /home/ec2-user/bin/FlameGraph/flamegraph.pl --title client-w-1-client-tcp > client-w-1-client-tcp.svg"
+        );
+        assert_eq!(
+            profile(Profiler::Bpftrace).flamegraph_script(flamegraph_path),
+            "/home/ec2-user/bin/FlameGraph/stackcollapse-bpftrace.pl client-w-1-client-tcp.bpftrace | /home/ec2-user/bin/FlameGraph/flamegraph.pl --title client-w-1-client-tcp > client-w-1-client-tcp.svg"
+        );
+        assert_eq!("bpftrace".parse(), Ok(Profiler::Bpftrace));
+        assert!("dtrace".parse::<Profiler>().is_err());
+    }
+}
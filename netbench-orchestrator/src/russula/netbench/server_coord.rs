@@ -130,7 +130,7 @@ impl StateApi for CoordState {
             CoordState::CheckWorker => TransitionStep::AwaitNext(WorkerState::Ready.as_bytes()),
             CoordState::Ready => TransitionStep::UserDriven,
             CoordState::RunWorker => {
-                TransitionStep::AwaitNext(WorkerState::RunningAwaitKill(0).as_bytes())
+                TransitionStep::AwaitNext(WorkerState::RunningAwaitKill(vec![]).as_bytes())
             }
             CoordState::WorkersRunning => TransitionStep::UserDriven,
             CoordState::KillWorker => TransitionStep::AwaitNext(WorkerState::Stopped.as_bytes()),
@@ -1,7 +1,7 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::ServerContext;
+use super::{capture::Capture, profiler::Profile, ServerContext, FLAMEGRAPH_DIR};
 use crate::russula::{
     error::{RussulaError, RussulaResult},
     event::EventRecorder,
@@ -12,10 +12,15 @@ use crate::russula::{
 };
 use core::fmt::Debug;
 use serde::{Deserialize, Serialize};
-use std::{fs::File, net::SocketAddr, process::Command};
+use std::{
+    fs::File,
+    net::SocketAddr,
+    os::unix::process::CommandExt,
+    process::{Child, Command, Stdio},
+};
 use sysinfo::{Pid, PidExt, ProcessExt, SystemExt};
 use tokio::net::{TcpListener, TcpStream};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 // Only used when creating a state variant for comparison
 const PLACEHOLDER_PID: u32 = 1000;
@@ -27,12 +32,12 @@ pub enum WorkerState {
     Ready,
     Run,
     RunningAwaitKill(
-        // netbench server process id
-        #[serde(skip)] u32,
+        // netbench server process ids, one per server on the host
+        #[serde(skip)] Vec<u32>,
     ),
     Killing(
-        // netbench server process id
-        #[serde(skip)] u32,
+        // netbench server process ids, one per server on the host
+        #[serde(skip)] Vec<u32>,
     ),
     Stopped,
     Done,
@@ -46,6 +51,10 @@ pub struct WorkerWorkflow {
     peer_state: CoordState,
     netbench_ctx: ServerContext,
     event_recorder: EventRecorder,
+    // the profiles of the servers, see `--profiler`
+    profiles: Vec<Profile>,
+    // the capture of the servers' traffic, see `--capture`
+    capture: Option<Capture>,
 }
 
 impl WorkerWorkflow {
@@ -57,6 +66,99 @@ impl WorkerWorkflow {
             peer_state: CoordState::CheckWorker,
             netbench_ctx,
             event_recorder: EventRecorder::default(),
+            profiles: Vec::new(),
+            capture: None,
+        }
+    }
+
+    // The name of the files of a server's results and flamegraph
+    fn output_name(&self, server_id: usize) -> String {
+        format!("server-w-{server_id}-{}", self.id)
+    }
+
+    // Runs a server of the scenario, with its results written to a file per
+    // server. The file names end with the driver name, which is how the
+    // results are found on the host.
+    //
+    // The servers left running by `--keep-servers` write to a file which
+    // isn't uploaded, and are detached from the worker so that they outlive
+    // its SSM command.
+    fn spawn_server(&self, server_id: usize, port: u16) -> Child {
+        let name = self.output_name(server_id);
+        let output_log_file = match self.netbench_ctx.keep_servers {
+            Some(_) => format!("{name}.kept.json"),
+            None => format!("{name}.json"),
+        };
+        let output_log_file = File::create(output_log_file).expect("failed to open log");
+
+        info!("{} run task netbench server {server_id}", self.name());
+        println!("{} run task netbench server {server_id}", self.name());
+
+        let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
+        let collector = format!("{}/s2n-netbench-collector", netbench_path);
+        let driver = format!("{}/{}", netbench_path, self.netbench_ctx.driver);
+        let scenario = format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
+        debug!("server_id: {server_id} netbench_port: {port}");
+
+        let mut cmd = Command::new(collector);
+        cmd.args([&driver, "--scenario", &scenario]);
+        if let Some(interval) = &self.netbench_ctx.collector_interval {
+            cmd.args(["--interval", interval]);
+        }
+        // arguments after `--` are passed through to the driver
+        if !self.netbench_ctx.driver_args.is_empty() {
+            cmd.arg("--").args(&self.netbench_ctx.driver_args);
+        }
+        // inherited by the driver, which is spawned by the collector
+        cmd.envs(self.netbench_ctx.driver_env.iter().cloned());
+        cmd.stdout(output_log_file);
+        cmd.env("SERVER_ID", server_id.to_string());
+        cmd.env("PORT", port.to_string());
+        if self.netbench_ctx.keep_servers.is_some() {
+            let stderr = File::create(format!("{name}.log")).expect("failed to open log");
+            cmd.stderr(stderr).process_group(0);
+        }
+        println!("{:?}", cmd);
+        debug!("{:?}", cmd);
+        cmd.spawn()
+            .expect("Failed to start netbench server process")
+    }
+
+    // Kills the process group of a kept server, ie. the collector and the
+    // driver, after `ttl_secs`. The timer is detached like the server, so
+    // that the servers are stopped even on hosts which aren't shut down once
+    // the TTL expires (eg. managed instances).
+    fn expire_server(&self, pid: u32, ttl_secs: u64) {
+        let timer = Command::new("sh")
+            .args(["-c", &format!("sleep {ttl_secs}; kill -- -{pid}")])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn();
+        if let Err(err) = timer {
+            warn!(
+                "{} failed to schedule the kill of the kept server {pid}. {err}",
+                self.name()
+            );
+        }
+    }
+
+    // Copies the results a kept server has written so far to its results
+    // file, so that the measured run is uploaded while the server keeps
+    // running. The collector writes a line per sample, so a partially
+    // written sample is left out.
+    fn snapshot_results(&self, server_id: usize) {
+        let name = self.output_name(server_id);
+        let snapshot = std::fs::read(format!("{name}.kept.json")).and_then(|results| {
+            let len = results
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(0, |newline| newline + 1);
+            std::fs::write(format!("{name}.json"), &results[..len])
+        });
+        if let Err(err) = snapshot {
+            warn!("{name} failed to snapshot the results of the kept server. {err}");
         }
     }
 }
@@ -116,60 +218,95 @@ impl WorkflowTrait for WorkerWorkflow {
                 self.await_next_msg(stream).await
             }
             WorkerState::Run => {
-                let child = match &self.netbench_ctx.testing {
+                let children = match &self.netbench_ctx.testing {
                     false => {
-                        let output_log_file = format!("{}.json", self.name());
-                        let output_log_file =
-                            File::create(output_log_file).expect("failed to open log");
-
-                        info!("{} run task netbench", self.name());
-                        println!("{} run task netbench", self.name());
-
-                        let netbench_path = self.netbench_ctx.netbench_path.to_str().unwrap();
-                        let collector = format!("{}/s2n-netbench-collector", netbench_path);
-                        let driver = format!("{}/{}", netbench_path, self.netbench_ctx.driver);
-                        let scenario = format!("{}/{}", netbench_path, self.netbench_ctx.scenario);
-                        debug!("netbench_port: {}", self.netbench_ctx.netbench_port);
-
-                        let mut cmd = Command::new(collector);
-                        cmd.args([&driver, "--scenario", &scenario])
-                            .stdout(output_log_file);
-                        cmd.env("PORT", self.netbench_ctx.netbench_port.to_string());
-                        println!("{:?}", cmd);
-                        debug!("{:?}", cmd);
-                        cmd.spawn()
-                            .expect("Failed to start netbench server process")
+                        let servers = self.netbench_ctx.local_servers();
+                        assert!(
+                            !servers.is_empty(),
+                            "no scenario server is assigned to the host"
+                        );
+                        if self.netbench_ctx.capture.is_enabled() {
+                            let ports: Vec<u16> = servers.iter().map(|(_, port)| *port).collect();
+                            self.capture = self.netbench_ctx.capture.start(&ports, &self.name());
+                        }
+                        let mut children = Vec::with_capacity(servers.len());
+                        for (server_id, port) in servers {
+                            let child = self.spawn_server(server_id, port);
+                            if let Some(profiler) = self.netbench_ctx.profiler {
+                                let profile = Profile::attach(
+                                    profiler,
+                                    self.netbench_ctx.profile_frequency,
+                                    child.id(),
+                                    self.output_name(server_id),
+                                )
+                                .await;
+                                self.profiles.extend(profile);
+                            }
+                            children.push(child);
+                        }
+                        children
                     }
                     true => {
                         info!("{} run task sim_netbench_server", self.name());
-                        Command::new("sh")
+                        let child = Command::new("sh")
                             .args(["scripts/sim_netbench_server.sh", &self.name()])
                             .spawn()
-                            .expect("Failed to start echo process")
+                            .expect("Failed to start echo process");
+                        vec![child]
                     }
                 };
 
-                let pid = child.id();
-                debug!("{} child id {}", self.name(), pid);
+                let pids: Vec<u32> = children.iter().map(Child::id).collect();
+                debug!("{} child ids {:?}", self.name(), pids);
 
-                *self.state_mut() = WorkerState::RunningAwaitKill(pid);
+                *self.state_mut() = WorkerState::RunningAwaitKill(pids);
                 Ok(None)
             }
             WorkerState::RunningAwaitKill(_pid) => {
                 self.notify_peer(stream).await?;
                 self.await_next_msg(stream).await
             }
-            WorkerState::Killing(pid) => {
-                let pid = Pid::from_u32(*pid);
+            WorkerState::Killing(pids) => {
+                let pids = pids.clone();
+                // the servers are profiled until they're killed
+                let flamegraph_path = self.netbench_ctx.netbench_path.join(FLAMEGRAPH_DIR);
+                for profile in std::mem::take(&mut self.profiles) {
+                    profile.finish(&flamegraph_path).await;
+                }
+                if let Some(capture) = self.capture.take() {
+                    capture.finish().await;
+                }
+
+                if let Some(ttl_secs) = self.netbench_ctx.keep_servers {
+                    if !self.netbench_ctx.testing {
+                        for (server_id, _port) in self.netbench_ctx.local_servers() {
+                            self.snapshot_results(server_id);
+                        }
+                        for pid in pids.iter() {
+                            self.expire_server(*pid, ttl_secs);
+                        }
+                    }
+                    info!(
+                        "{} left the netbench servers running for {ttl_secs}s: {:?}",
+                        self.name(),
+                        pids
+                    );
+                    self.transition_self_or_user_driven(stream).await?;
+                    return Ok(None);
+                }
+
                 // TODO test only loading the process id we care about
                 let mut system = sysinfo::System::new_all();
-                if system.refresh_process(pid) {
-                    let process = system.process(pid).unwrap();
-                    let kill = process.kill();
-                    debug!("did KILL pid: {} {}", pid, kill);
-                } else {
-                    // log an error but continue since the process is not gone
-                    error!("netbench process not found. pid: {}", pid);
+                for pid in pids.iter() {
+                    let pid = Pid::from_u32(*pid);
+                    if system.refresh_process(pid) {
+                        let process = system.process(pid).unwrap();
+                        let kill = process.kill();
+                        debug!("did KILL pid: {} {}", pid, kill);
+                    } else {
+                        // log an error but continue since the process is not gone
+                        error!("netbench process not found. pid: {}", pid);
+                    }
                 }
 
                 self.transition_self_or_user_driven(stream).await?;
@@ -213,8 +350,8 @@ impl StateApi for WorkerState {
         match self {
             WorkerState::WaitCoordInit => WorkerState::Ready,
             WorkerState::Ready => WorkerState::Run,
-            WorkerState::Run => WorkerState::RunningAwaitKill(PLACEHOLDER_PID),
-            WorkerState::RunningAwaitKill(pid) => WorkerState::Killing(*pid),
+            WorkerState::Run => WorkerState::RunningAwaitKill(vec![PLACEHOLDER_PID]),
+            WorkerState::RunningAwaitKill(pids) => WorkerState::Killing(pids.clone()),
             WorkerState::Killing(_) => WorkerState::Stopped,
             WorkerState::Stopped => WorkerState::Done,
             WorkerState::Done => WorkerState::Done,
@@ -3,23 +3,18 @@
 
 use super::{
     error::RussulaError,
-    event::EventType,
+    event::{self, EventType},
     network_utils,
     network_utils::Msg,
     states::{StateApi, TransitionStep},
     RussulaResult, WorkflowState,
 };
 use crate::russula::event::EventRecorder;
-use core::{task::Poll, time::Duration};
+use core::task::Poll;
 use std::net::SocketAddr;
 use tokio::net::TcpStream;
 use tracing::{debug, info};
 
-// Send the Done status multiple times to the peer in case there is packet loss.
-const NOTIFY_DONE_TIMEOUT: Duration = Duration::from_secs(1);
-// Notify done multiple time in case of packet loss.. this is best effort
-const DONE_SENT_COUNT: usize = 3;
-
 pub(crate) trait WorkflowTrait: Clone {
     type State: StateApi;
 
@@ -94,30 +89,6 @@ pub(crate) trait WorkflowTrait: Clone {
             );
         }
 
-        // Notify the peer that we have reached a terminal state
-        //
-        // The Done state is special and only notifies the peer of our Done status.
-        if self.is_state(WorkflowState::Done) {
-            tracing::info!("{:?}", self.event_recorder());
-
-            // Notify done multiple time in case of packet loss.. this is best effort
-            for _i in 0..DONE_SENT_COUNT {
-                match self.run_current(stream).await {
-                    Ok(_) => (),
-                    // We notify the peer of the Done state multiple times. Since the peer could
-                    // have killed the connection in the meantime, its better to ignore network
-                    // failures
-                    Err(RussulaError::NetworkConnectionRefused { dbg: _ })
-                    | Err(RussulaError::NetworkBlocked { dbg: _ })
-                    | Err(RussulaError::NetworkFail { dbg: _ }) => {
-                        debug!("Ignore network failure since coordination is Done.")
-                    }
-                    Err(err) => return Err(err),
-                }
-                tokio::time::sleep(NOTIFY_DONE_TIMEOUT).await;
-            }
-        }
-
         if self.state().eq(desired_state) {
             Ok(Poll::Ready(()))
         } else {
@@ -125,6 +96,25 @@ pub(crate) trait WorkflowTrait: Clone {
         }
     }
 
+    /// Notify the peer that we have reached the terminal Done state.
+    ///
+    /// The Done state is special and only notifies the peer of our Done status.
+    /// This is best effort, since the peer may already be gone.
+    async fn notify_done(&mut self, stream: &mut TcpStream) -> RussulaResult<()> {
+        match self.run_current(stream).await {
+            Ok(_) => Ok(()),
+            // Since the peer could have killed the connection in the meantime,
+            // its better to ignore network failures
+            Err(RussulaError::NetworkConnectionRefused { dbg: _ })
+            | Err(RussulaError::NetworkBlocked { dbg: _ })
+            | Err(RussulaError::NetworkFail { dbg: _ }) => {
+                debug!("Ignore network failure since coordination is Done.");
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Run operations for the current [Self::State]
     async fn run_current(&mut self, stream: &mut TcpStream) -> RussulaResult<()> {
         if let Some(msg) = self.run(stream).await? {
@@ -208,6 +198,9 @@ pub(crate) trait WorkflowTrait: Clone {
             nxt
         );
 
+        self.on_event(EventType::Transition {
+            from: event::state_name(self.state()),
+        });
         *self.state_mut() = nxt;
 
         // notify the peer of the new state
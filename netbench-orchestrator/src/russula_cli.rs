@@ -5,12 +5,12 @@ use crate::russula::{netbench, WorkflowState};
 use core::time::Duration;
 use russula::{
     netbench::{client, server},
-    WorkflowBuilder,
+    EventRecorder, WorkflowBuilder,
 };
 use std::{collections::BTreeSet, net::SocketAddr};
 use structopt::StructOpt;
-use tracing::debug;
-use tracing_subscriber::EnvFilter;
+use tracing::{debug, error, Instrument};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 mod russula;
 
@@ -22,6 +22,17 @@ struct Opt {
     #[structopt(long, parse(try_from_str=parse_duration), default_value = "5s")]
     poll_delay: Duration,
 
+    /// Also write json logs to stdout.
+    ///
+    /// When run by the orchestrator, SSM ships stdout to the run's CloudWatch
+    /// log group.
+    #[structopt(long)]
+    log_stdout: bool,
+
+    /// The orchestrator run, which is included in the logs.
+    #[structopt(long)]
+    unique_id: Option<String>,
+
     /// Select which Russula workflow to start
     #[structopt(subcommand)]
     workflow: RussulaWorkflow,
@@ -67,11 +78,22 @@ async fn main() {
 
     let file_appender = tracing_appender::rolling::daily("./target", "russula.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_writer(non_blocking)
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(fmt::layer().with_writer(non_blocking))
+        .with(
+            opt.log_stdout
+                .then(|| fmt::layer().json().with_writer(std::io::stdout)),
+        )
         .init();
 
+    let span = tracing::info_span!("russula", unique_id = opt.unique_id.as_deref());
+    run(opt).instrument(span).await;
+
+    println!("cli done");
+}
+
+async fn run(opt: Opt) {
     debug!("{:?}", opt);
     match &opt.workflow {
         RussulaWorkflow::NetbenchServerWorker { ctx, russula_port } => {
@@ -97,14 +119,12 @@ async fn main() {
             run_local_client_coordinator(opt, w).await
         }
     };
-
-    println!("cli done");
 }
 
 async fn run_server_worker(opt: Opt, netbench_ctx: netbench::ServerContext, russula_port: u16) {
     let uuid = uuid::Uuid::new_v4().to_string();
-    let id = format!("{}-{}", uuid, netbench_ctx.trim_driver_name());
-    let workflow = server::WorkerWorkflow::new(id, netbench_ctx);
+    let id = format!("{}-{}", uuid, netbench_ctx.driver().short());
+    let workflow = server::WorkerWorkflow::new(id.clone(), netbench_ctx);
     let worker = WorkflowBuilder::new(
         BTreeSet::from_iter([local_listen_addr(russula_port)]),
         workflow,
@@ -114,12 +134,13 @@ async fn run_server_worker(opt: Opt, netbench_ctx: netbench::ServerContext, russ
     worker.run_till(WorkflowState::Ready).await.unwrap();
 
     worker.run_till(WorkflowState::Done).await.unwrap();
+    write_events(worker.event_recorders(), &format!("server-{id}"));
 }
 
 async fn run_client_worker(opt: Opt, netbench_ctx: netbench::ClientContext, russula_port: u16) {
     let uuid = uuid::Uuid::new_v4().to_string();
-    let id = format!("{}-{}", uuid, netbench_ctx.trim_driver_name());
-    let workflow = client::WorkerWorkflow::new(id, netbench_ctx);
+    let id = format!("{}-{}", uuid, netbench_ctx.driver().short());
+    let workflow = client::WorkerWorkflow::new(id.clone(), netbench_ctx);
     let worker = WorkflowBuilder::new(
         BTreeSet::from_iter([local_listen_addr(russula_port)]),
         workflow,
@@ -129,6 +150,7 @@ async fn run_client_worker(opt: Opt, netbench_ctx: netbench::ClientContext, russ
     worker.run_till(WorkflowState::Ready).await.unwrap();
 
     worker.run_till(WorkflowState::Done).await.unwrap();
+    write_events(worker.event_recorders(), &format!("client-{id}"));
 }
 
 async fn run_local_server_coordinator(opt: Opt, russula_worker_addrs: Vec<SocketAddr>) {
@@ -166,6 +188,20 @@ async fn run_local_client_coordinator(opt: Opt, russula_worker_addrs: Vec<Socket
     coord.run_till(WorkflowState::Done).await.unwrap();
 }
 
+// Writes the worker's events to `<name>.russula.json`, which the orchestrator
+// uploads next to the results to measure the coordination overhead.
+//
+// Best effort, since the results are unaffected.
+fn write_events(events: Vec<EventRecorder>, name: &str) {
+    let path = format!("{name}.russula.json");
+    let res = serde_json::to_vec(&events)
+        .map_err(|err| err.to_string())
+        .and_then(|events| std::fs::write(&path, events).map_err(|err| err.to_string()));
+    if let Err(err) = res {
+        error!("Failed to write {path}. {err}");
+    }
+}
+
 fn local_listen_addr(russula_port: u16) -> SocketAddr {
     format!("0.0.0.0:{}", russula_port).parse().unwrap()
 }
@@ -1,25 +1,54 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{orchestrator::OrchError, OrchResult};
+use crate::{audit, aws_api::S3Api, orchestrator::OrchestratorConfig, OrchResult};
 use aws_sdk_s3 as s3;
-use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use std::process::Command;
+
+pub fn s3_client(aws_config: &aws_types::SdkConfig, config: &OrchestratorConfig) -> s3::Client {
+    let s3_config = s3::config::Builder::from(aws_config)
+        .accelerate(config.s3_transfer.transfer_acceleration())
+        .build();
+    s3::Client::from_conf(s3_config)
+}
+
+// An `aws` cli command which honors the user specified s3 transfer settings,
+// with the region and credentials of the AWS clients.
+pub async fn aws_cli_command(config: &OrchestratorConfig) -> OrchResult<Command> {
+    let mut cmd = config.aws_cli.command().await?;
+    if let Some(local_aws_config) = config.s3_transfer.local_aws_config() {
+        cmd.env("AWS_CONFIG_FILE", local_aws_config);
+    }
+    Ok(cmd)
+}
 
 pub async fn upload_object(
-    client: &s3::Client,
+    client: &impl S3Api,
     bucket_name: &str,
     body: s3::primitives::ByteStream,
     key: &str,
-) -> OrchResult<PutObjectOutput> {
+) -> OrchResult<()> {
     client
-        .put_object()
-        .bucket(bucket_name)
-        .key(key)
-        .content_type("text/html")
-        .body(body)
-        .send()
-        .await
-        .map_err(|err| OrchError::S3 {
-            dbg: err.to_string(),
-        })
+        .put_object(bucket_name, key, "text/html", body)
+        .await?;
+    audit::record!("s3", "put_object", bucket = bucket_name, key);
+    Ok(())
+}
+
+// Downloads an object, or None if it doesn't exist.
+pub async fn download_object(
+    client: &impl S3Api,
+    bucket_name: &str,
+    key: &str,
+) -> OrchResult<Option<Vec<u8>>> {
+    client.get_object(bucket_name, key).await
+}
+
+// The keys of the objects under a prefix.
+pub async fn list_keys(
+    client: &impl S3Api,
+    bucket_name: &str,
+    prefix: &str,
+) -> OrchResult<Vec<String>> {
+    client.list_keys(bucket_name, prefix).await
 }
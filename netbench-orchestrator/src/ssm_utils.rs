@@ -1,13 +1,15 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::orchestrator::{OrchError, OrchResult, OrchestratorConfig, STATE};
-use aws_sdk_ssm::{
-    operation::send_command::SendCommandOutput,
-    types::{CloudWatchOutputConfig, CommandInvocationStatus},
+use crate::{
+    audit,
+    aws_api::SsmApi,
+    cloudwatch_utils,
+    orchestrator::{state, ErrorKind, OrchError, OrchResult, OrchestratorConfig},
 };
+use aws_sdk_ssm::{operation::send_command::SendCommandOutput, types::CommandInvocationStatus};
 use core::task::Poll;
-use tracing::trace;
+use tracing::{error, instrument, trace};
 
 pub mod client;
 pub mod common;
@@ -18,6 +20,55 @@ pub mod server;
 pub use coordination_utils::{ClientNetbenchRussula, ServerNetbenchRussula};
 pub use netbench_driver::*;
 
+// The `--driver-arg`, `--driver-env` and `--collector-interval` flags passed to
+// the russula worker for the driver's arguments, environment and sampling.
+//
+// The values are quoted since the SSM commands are run by a shell.
+fn driver_args(driver: &NetbenchDriverType) -> String {
+    let args = driver
+        .args()
+        .iter()
+        .map(|arg| format!(" --driver-arg '{}'", quote(arg)));
+    let env = driver
+        .env()
+        .iter()
+        .map(|(key, value)| format!(" --driver-env '{}={}'", quote(key), quote(value)));
+    let interval = driver
+        .collector_interval()
+        .map(|interval| format!(" --collector-interval '{}'", quote(interval)));
+    args.chain(env).chain(interval).collect()
+}
+
+// The flags passed to the client worker to also run the server driver on the
+// host, in loopback mode. See `driver_args`.
+fn loopback_server_args(driver: &NetbenchDriverType) -> String {
+    let server = format!(" --loopback-server {}", driver.driver_name());
+    let args = driver
+        .args()
+        .iter()
+        .map(|arg| format!(" --loopback-server-arg '{}'", quote(arg)));
+    let env = driver
+        .env()
+        .iter()
+        .map(|(key, value)| format!(" --loopback-server-env '{}={}'", quote(key), quote(value)));
+    let interval = driver.collector_interval().map(|interval| {
+        format!(
+            " --loopback-server-collector-interval '{}'",
+            quote(interval)
+        )
+    });
+    std::iter::once(server)
+        .chain(args)
+        .chain(env)
+        .chain(interval)
+        .collect()
+}
+
+// Escapes a value for single quotes in a shell command
+pub(crate) fn quote(value: &str) -> String {
+    value.replace('\'', "'\\''")
+}
+
 // Group of SSM commands
 //
 // SSM executes commands asynchronously on remote hosts, and doesn't have
@@ -27,9 +78,14 @@ pub use netbench_driver::*;
 //
 // For example, the Step::RunRussula step waits for the Step::BuildRussula
 // and Step::BuildDriver steps to finish.
+#[derive(Clone, Debug)]
 pub enum Step {
     UploadScenarioFile,
     Configure,
+    // The user provided commands of the host group, which are only run if
+    // the group has any. See `HostHooks`.
+    PreBuildHook,
+    PostBuildHook,
     // We currently don't differentiate between driver builds. This
     // is ok since all driver commands are idempotent and don't depend
     // on one another.
@@ -37,17 +93,34 @@ pub enum Step {
     BuildRussula,
     RunRussula,
     UploadNetbenchRawData,
+    CheckClock,
+    Throttle,
+    // The user provided commands run around each driver pair, see `hooks`
+    PreRunHook,
+    PostRunHook,
+    // Reschedules the shutdown of the hosts kept with `--keep-servers`
+    KeepHosts,
+    // Starts the orchestrator on the controller, see `run_on_controller`
+    Controller,
 }
 
 impl Step {
-    fn as_str(&self) -> &str {
+    pub(crate) fn as_str(&self) -> &str {
         match self {
             Step::UploadScenarioFile => "upload_scenario_file",
             Step::Configure => "configure",
+            Step::PreBuildHook => "pre_build_hook",
+            Step::PostBuildHook => "post_build_hook",
             Step::BuildDriver(_driver_name) => "build_driver",
             Step::BuildRussula => "build_russula",
             Step::RunRussula => "run_russula",
             Step::UploadNetbenchRawData => "upload_netbench_raw_data",
+            Step::CheckClock => "check_clock",
+            Step::Throttle => "throttle",
+            Step::PreRunHook => "pre_run_hook",
+            Step::PostRunHook => "post_run_hook",
+            Step::KeepHosts => "keep_hosts",
+            Step::Controller => "controller",
         }
     }
 
@@ -55,14 +128,23 @@ impl Step {
         match self {
             Step::UploadScenarioFile => None,
             Step::Configure => None,
+            Step::PreBuildHook => None,
+            Step::PostBuildHook => None,
             Step::BuildDriver(driver_name) => Some(driver_name),
             Step::BuildRussula => None,
             Step::RunRussula => None,
             Step::UploadNetbenchRawData => None,
+            Step::CheckClock => None,
+            Step::Throttle => None,
+            Step::PreRunHook => None,
+            Step::PostRunHook => None,
+            Step::KeepHosts => None,
+            Step::Controller => None,
         }
     }
 }
 
+#[instrument(skip_all, fields(step = curr_step.as_str(), comment = comment))]
 pub async fn send_command(
     // Steps to wait for before running the current Step
     wait_steps: Vec<Step>,
@@ -71,14 +153,14 @@ pub async fn send_command(
     // String useful for displaying and debugging
     comment: &str,
     // sm sdk client
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     // EC2 instance Ids
     ids: Vec<String>,
     // The ssm commands to execute
     commands: Vec<String>,
     // Orchestrator config object for this run
     config: &OrchestratorConfig,
-) -> Option<SendCommandOutput> {
+) -> OrchResult<SendCommandOutput> {
     // SSM executes commands asynchronously on remote hosts, and doesn't have
     // a concept of order.
     // To work around this limitation we create files based on the [`Step`]
@@ -91,6 +173,7 @@ pub async fn send_command(
         indicate_curr_step_started(&mut assemble_command, &curr_step);
 
         // execute current step operations
+        assemble_command.extend(config.aws_endpoints.host_cmds());
         assemble_command.extend(commands);
 
         indicate_curr_step_finished(&mut assemble_command, &curr_step);
@@ -104,44 +187,100 @@ pub async fn send_command(
 
 async fn send_and_wait_ssm_command(
     comment: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     ids: Vec<String>,
     command: Vec<String>,
     config: &OrchestratorConfig,
-) -> Option<SendCommandOutput> {
+) -> OrchResult<SendCommandOutput> {
     let mut remaining_try_count: u32 = 5;
-    while remaining_try_count > 0 {
+    loop {
+        // the instances aren't registered with ssm until shortly after
+        // they're running, which is reported as a retryable error
         let send = ssm_client
-            .send_command()
-            .comment(comment)
-            .set_instance_ids(Some(ids.clone()))
-            .document_name("AWS-RunShellScript")
-            .document_version("$LATEST")
-            .parameters("commands", command.clone())
-            .cloud_watch_output_config(
-                CloudWatchOutputConfig::builder()
-                    .cloud_watch_log_group_name(config.cdk_config.netbench_runner_log_group())
-                    .cloud_watch_output_enabled(true)
-                    .build(),
+            .send_command(
+                comment,
+                ids.clone(),
+                command.clone(),
+                config.cdk_config.netbench_runner_log_group(),
             )
-            .send()
-            .await
-            .map_err(|x| format!("{:#?}", x));
+            .await;
 
         match send {
             Ok(sent_command) => {
-                return Some(sent_command);
+                audit::record!(
+                    "ssm",
+                    "send_command",
+                    command_id = sent_command.command().and_then(|command| command.command_id()),
+                    comment,
+                    instance_ids = ?ids
+                );
+                return Ok(sent_command);
             }
-            Err(err) => {
+            Err(err) if err.is_retryable() && remaining_try_count > 1 => {
                 trace!("Send command failed: remaining: {remaining_try_count} err: {err}",);
                 remaining_try_count -= 1;
 
-                tokio::time::sleep(STATE.poll_delay_ssm).await;
+                tokio::time::sleep(state().poll_delay_ssm).await;
             }
+            Err(err) => return Err(err),
         };
     }
+}
+
+// The id of a sent command, which is used to poll and cancel it.
+pub fn command_id(cmd: &SendCommandOutput) -> OrchResult<&str> {
+    cmd.command()
+        .and_then(|command| command.command_id())
+        .ok_or_else(|| OrchError::new(ErrorKind::Ssm, "ssm command has no id"))
+}
 
-    None
+// Stops a running command on all of its hosts, eg. the russula workers of an
+// aborted driver pair.
+pub async fn cancel_command(ssm_client: &impl SsmApi, command_id: &str) -> OrchResult<()> {
+    ssm_client.cancel_command(command_id).await?;
+    audit::record!("ssm", "cancel_command", command_id);
+    Ok(())
+}
+
+// The standard output of a command on a host, or None until the command has
+// succeeded.
+//
+// SSM truncates the output to 24000 characters, so this is only suited to
+// short outputs.
+pub async fn command_output(
+    ssm_client: &impl SsmApi,
+    command_id: &str,
+    instance_id: &str,
+) -> OrchResult<Option<String>> {
+    // the invocation isn't visible until shortly after the command is sent
+    let Some(invocation) = ssm_client
+        .get_command_invocation(command_id, instance_id)
+        .await?
+    else {
+        return Ok(None);
+    };
+    match invocation.status() {
+        Some(CommandInvocationStatus::Success) => Ok(Some(
+            invocation
+                .standard_output_content()
+                .unwrap_or_default()
+                .to_string(),
+        )),
+        Some(
+            CommandInvocationStatus::Pending
+            | CommandInvocationStatus::InProgress
+            | CommandInvocationStatus::Delayed,
+        )
+        | None => Ok(None),
+        Some(status) => Err(OrchError::new(
+            ErrorKind::Ssm,
+            format!(
+                "ssm command {command_id} failed on {instance_id}: {:?} {}",
+                status,
+                invocation.standard_error_content().unwrap_or_default()
+            ),
+        )),
+    }
 }
 
 fn wait_previous_step(wait_steps: Vec<Step>) -> Vec<String> {
@@ -195,51 +334,69 @@ fn indicate_curr_step_finished(assemble_command: &mut Vec<String>, curr_step: &S
     }
 }
 
-async fn poll_ssm_results(
+pub(crate) async fn poll_ssm_results(
     endpoint: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     command_id: &str,
 ) -> OrchResult<Poll<()>> {
-    let status_comment = ssm_client
-        .list_command_invocations()
-        .command_id(command_id)
-        .send()
-        .await
-        .map_err(|err| OrchError::Ssm {
-            dbg: format!("error listing ssm command {err}"),
-        })?
-        .command_invocations()
+    let invocations = ssm_client.list_command_invocations(command_id).await?;
+    let invocation = invocations
         .iter()
-        .find_map(|command| {
-            let status = command.status().cloned();
-            let comment = command.comment().map(|s| s.to_string());
-            status.zip(comment)
-        });
-    let (status, comment) = match status_comment {
-        Some((status, comment)) => (status, comment),
+        .find(|command| command.status().is_some() && command.comment().is_some());
+    let (status, comment) = match invocation
+        .and_then(|command| Some((command.status()?.clone(), command.comment()?.to_string())))
+    {
+        Some(status) => status,
         None => {
             return Ok(Poll::Ready(()));
         }
     };
-    trace!("endpoint: {}  command_id {}", endpoint, command_id);
+    let host = invocation
+        .and_then(|command| command.instance_id())
+        .unwrap_or_default();
+    trace!(host, command_id, "endpoint: {}", endpoint);
+
+    // A link to the output of the failed command, which includes the logs of
+    // the russula workers.
+    let logs = || {
+        let region = ssm_client.region()?;
+        let command = invocation?;
+        let log_group = command
+            .cloud_watch_output_config()?
+            .cloud_watch_log_group_name()?;
+        let log_stream =
+            cloudwatch_utils::ssm_stdout_log_stream(command_id, command.instance_id()?);
+        Some(format!(
+            ". Logs: {}",
+            cloudwatch_utils::log_stream_url(&region, log_group, &log_stream)
+        ))
+    };
 
     let status = match status {
         CommandInvocationStatus::Cancelled
         | CommandInvocationStatus::Cancelling
         | CommandInvocationStatus::Failed
         | CommandInvocationStatus::TimedOut => {
-            return Err(OrchError::Ssm {
-                dbg: format!("ssm command timeout {}", comment),
-            })
+            let dbg = format!(
+                "ssm command timeout {}{}",
+                comment,
+                logs().unwrap_or_default()
+            );
+            error!(host, command_id, "{dbg}");
+            return Err(OrchError::new(ErrorKind::Ssm, dbg));
         }
         CommandInvocationStatus::Delayed
         | CommandInvocationStatus::InProgress
         | CommandInvocationStatus::Pending => Poll::Pending,
         CommandInvocationStatus::Success => Poll::Ready(()),
         _ => {
-            return Err(OrchError::Ssm {
-                dbg: format!("error polling ssm command {}", comment),
-            })
+            let dbg = format!(
+                "error polling ssm command {}{}",
+                comment,
+                logs().unwrap_or_default()
+            );
+            error!(host, command_id, "{dbg}");
+            return Err(OrchError::new(ErrorKind::Ssm, dbg));
         }
     };
     Ok(status)
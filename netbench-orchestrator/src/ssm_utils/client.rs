@@ -1,42 +1,39 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{send_command, Step};
+use super::{common, send_command, Step};
 use crate::{
-    ec2_utils::PrivIp,
-    orchestrator::OrchestratorConfig,
-    ssm_utils::{netbench_driver::NetbenchDriverType, STATE},
-    OrchError, OrchResult,
+    aws_api::SsmApi,
+    ec2_utils::EndpointType,
+    orchestrator::{OrchestratorConfig, ServerMap},
+    ssm_utils::{driver_args, loopback_server_args, netbench_driver::NetbenchDriverType},
+    OrchResult,
 };
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
-use std::net::SocketAddr;
 use tracing::debug;
 
 pub async fn run_russula_worker(
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
-    server_ips: Vec<&PrivIp>,
+    servers: &ServerMap,
     driver: &NetbenchDriverType,
+    // the server driver, which the worker runs on the host in loopback mode
+    loopback_server: Option<&NetbenchDriverType>,
+    unique_id: &str,
     config: &OrchestratorConfig,
 ) -> OrchResult<SendCommandOutput> {
-    // assemble the list of server ips into a string
-    let netbench_server_addr = server_ips
-        .iter()
-        .map(|ip| SocketAddr::new(ip.0, STATE.netbench_port).to_string())
-        .reduce(|mut accum, item| {
-            accum.push(' ');
-            accum.push_str(&item);
-            accum
-        })
-        .unwrap();
+    let netbench_server_addr = servers.as_arg();
+    let loopback_server = loopback_server
+        .map(loopback_server_args)
+        .unwrap_or_default();
 
     let netbench_cmd =
-        format!("env RUST_LOG=debug ./target/release/russula_cli netbench-client-worker --russula-port {} --driver {} --scenario {} --netbench-servers {netbench_server_addr}",
-            STATE.russula_port, driver.driver_name(), config.netbench_scenario_filename());
-    debug!("{}", netbench_cmd);
+        format!("env RUST_LOG=debug ./target/release/russula_cli --log-stdout --unique-id {unique_id} netbench-client-worker --russula-port {} --driver {}{} --scenario {} --warmup-secs {} --netbench-servers {netbench_server_addr}{loopback_server}{}{}",
+            config.ports.russula(), driver.driver_name(), driver_args(driver), config.netbench_scenario_filename(), config.run.warmup_secs(), config.profile.worker_args(EndpointType::Client), config.capture.worker_args(EndpointType::Client));
+    debug!(driver = %driver.results_name(), "{}", netbench_cmd);
 
     send_command(
-        vec![Step::BuildDriver("".to_string()), Step::BuildRussula],
+        common::run_russula_wait_steps(EndpointType::Client, config),
         Step::RunRussula,
         "run_client_russula",
         ssm_client,
@@ -48,7 +45,4 @@ pub async fn run_russula_worker(
         config,
     )
     .await
-    .ok_or(OrchError::Ssm {
-        dbg: "failed to start russula worker".to_string(),
-    })
 }
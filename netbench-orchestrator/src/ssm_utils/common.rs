@@ -1,150 +1,488 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{send_command, Step};
+use super::{command_id, send_command, Step};
 use crate::{
-    orchestrator::{OrchestratorConfig, STATE},
+    aws_api::SsmApi,
+    ec2_utils::EndpointType,
+    orchestrator::{
+        paths::HOST_RUSSULA_LOGS, state, ErrorKind, OrchError, OrchResult, OrchestratorConfig,
+        RunPaths,
+    },
+    progress,
+    russula::{netbench::FLAMEGRAPH_DIR, Backoff},
     ssm_utils::{netbench_driver::NetbenchDriverType, poll_ssm_results},
 };
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
-use core::time::Duration;
-use indicatif::{ProgressBar, ProgressStyle};
-use tracing::info;
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+use tracing::{info, instrument, Instrument};
 
-fn get_progress_bar(cmds: &[SendCommandOutput]) -> ProgressBar {
-    // TODO use multi-progress bar https://github.com/console-rs/indicatif/blob/main/examples/multi.rs
-    let total_tasks = cmds.len() as u64;
-    let bar = ProgressBar::new(total_tasks);
-    let style = ProgressStyle::with_template(
-        "{spinner} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+// The shortest wait between polls of the ssm commands
+const MIN_SSM_POLL_DELAY: Duration = Duration::from_secs(1);
+
+// Describes a command for its progress line, eg. "build_driver_s2n-quic: i-0123"
+fn task_msg(cmd: &SendCommandOutput) -> String {
+    format!(
+        "{}: {}",
+        comment(cmd),
+        cmd.command()
+            .map(|command| command.instance_ids().join(", "))
+            .unwrap_or_default()
     )
-    .unwrap()
-    .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ");
-    bar.set_style(style);
-    bar.enable_steady_tick(Duration::from_secs(1));
-    bar
 }
 
+fn comment(cmd: &SendCommandOutput) -> &str {
+    cmd.command()
+        .and_then(|command| command.comment())
+        .unwrap_or_default()
+}
+
+// Waits for the commands to complete, returning the comment of each command
+// and how long it took to complete, in the order they completed. Returns an
+// error as soon as any of the commands fails.
+//
+// The commands are polled with a backoff from `MIN_SSM_POLL_DELAY` up to
+// `poll_delay_ssm`, which is reset whenever a command completes so that the
+// commands of a step which complete together are noticed early.
+#[instrument(skip(ssm_client, cmds))]
 pub async fn wait_complete(
     host_group: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     cmds: Vec<SendCommandOutput>,
-) {
+) -> OrchResult<Vec<(String, Duration)>> {
+    let start = Instant::now();
+    let mut completed = Vec::with_capacity(cmds.len());
     let total_tasks = cmds.len() as u64;
-    let bar = get_progress_bar(&cmds);
+    let bar = progress::bar(total_tasks, host_group.to_string());
+    // A line per command below the bar, in the order the commands were sent
+    let mut tasks = Vec::with_capacity(cmds.len());
+    for cmd in cmds.iter() {
+        let task = progress::task(tasks.last().unwrap_or(&bar), task_msg(cmd));
+        tasks.push(task);
+    }
+
+    let mut backoff = Backoff::new(MIN_SSM_POLL_DELAY, state().poll_delay_ssm);
     loop {
-        let mut completed_tasks = 0;
-        for cmd in cmds.iter() {
-            let cmd_id = cmd.command().unwrap().command_id().unwrap();
-            let poll_cmd = poll_ssm_results(host_group, ssm_client, cmd_id)
-                .await
-                .unwrap();
-            if poll_cmd.is_ready() {
-                completed_tasks += 1;
+        let completed_before = completed.len();
+        // the commands are polled concurrently, since a run can have many
+        // hosts and drivers
+        let mut polls = JoinSet::new();
+        for (index, (cmd, task)) in cmds.iter().zip(tasks.iter()).enumerate() {
+            if task.is_finished() {
+                continue;
+            }
+
+            let host_group = host_group.to_string();
+            let ssm_client = ssm_client.clone();
+            let cmd_id = command_id(cmd)?.to_string();
+            polls.spawn(
+                async move {
+                    let poll_cmd = poll_ssm_results(&host_group, &ssm_client, &cmd_id).await;
+                    (index, poll_cmd)
+                }
+                .in_current_span(),
+            );
+        }
+        while let Some(poll) = polls.join_next().await {
+            let (index, poll_cmd) = poll.map_err(|err| {
+                OrchError::new(
+                    ErrorKind::Ssm,
+                    format!("failed to poll ssm commands for {host_group}"),
+                )
+                .with_source(err)
+            })?;
+            if poll_cmd?.is_ready() {
+                tasks[index].finish();
+                completed.push((comment(&cmds[index]).to_string(), start.elapsed()));
             }
         }
 
+        let completed_tasks = tasks.iter().filter(|task| task.is_finished()).count() as u64;
         bar.set_position(completed_tasks);
-        bar.set_message(host_group.to_string());
 
         if total_tasks == completed_tasks {
             bar.finish();
             break;
         }
-        tokio::time::sleep(STATE.poll_delay_ssm).await;
+        if completed.len() > completed_before {
+            backoff.reset();
+        }
+        tokio::time::sleep(backoff.next_delay()).await;
     }
+
+    Ok(completed)
 }
 
 pub async fn collect_config_cmds(
-    host_group: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    endpoint_type: EndpointType,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
-    scenario: &OrchestratorConfig,
-    netbench_drivers: &Vec<NetbenchDriverType>,
+    netbench_drivers: &[NetbenchDriverType],
     unique_id: &str,
     config: &OrchestratorConfig,
-) -> Vec<SendCommandOutput> {
-    // configure and build
-    let install_deps = install_deps_cmd(host_group, ssm_client, instance_ids.clone(), config).await;
+) -> OrchResult<Vec<SendCommandOutput>> {
+    let host_group = &endpoint_type.as_str().to_lowercase();
+    let hooks = config.driver_registry.host_hooks(endpoint_type);
+    let managed = !config.managed_instances(endpoint_type).is_empty();
+
+    // configure and build, or with `--skip-configure` check that the hosts
+    // already are
+    let install_deps = if config.skip.configure() {
+        check_configured_cmd(
+            host_group,
+            managed,
+            ssm_client,
+            instance_ids.clone(),
+            netbench_drivers,
+            config,
+        )
+        .await?
+    } else {
+        install_deps_cmd(
+            host_group,
+            managed,
+            ssm_client,
+            instance_ids.clone(),
+            netbench_drivers,
+            config,
+        )
+        .await?
+    };
 
     // download scenario file
     let upload_scenario_file = download_netbench_scenario_file_to_host(
         host_group,
         ssm_client,
         instance_ids.clone(),
-        scenario,
         unique_id,
         config,
     )
-    .await;
+    .await?;
+
+    let mut cmds = vec![install_deps, upload_scenario_file];
+    let mut build_wait_steps = vec![Step::UploadScenarioFile, Step::Configure];
+    if !hooks.pre_build.is_empty() {
+        let pre_build = send_command(
+            vec![Step::Configure],
+            Step::PreBuildHook,
+            &format!("pre_build_hook_{}", host_group),
+            ssm_client,
+            instance_ids.clone(),
+            hooks.pre_build.clone(),
+            config,
+        )
+        .await?;
+        cmds.push(pre_build);
+        build_wait_steps.push(Step::PreBuildHook);
+    }
 
+    // drivers run with several configurations are only built once, as are the
+    // server and client drivers built from the same source in loopback mode
+    let mut built = BTreeSet::new();
+    let mut checked = BTreeSet::new();
     let mut build_drivers = Vec::new();
     for driver in netbench_drivers {
-        let build_driver_cmd =
-            build_netbench_driver_cmd(driver, ssm_client, instance_ids.clone(), config).await;
+        if config.skip.build() {
+            // each executable of a shared build is checked
+            if !checked.insert(driver.driver_name().to_string()) {
+                continue;
+            }
+            let check_driver_cmd = check_netbench_driver_cmd(
+                driver,
+                build_wait_steps.clone(),
+                ssm_client,
+                instance_ids.clone(),
+                config,
+            )
+            .await?;
+            build_drivers.push(check_driver_cmd);
+            continue;
+        }
+        if !built.insert(driver.ssm_build_cmd()) {
+            continue;
+        }
+        let build_driver_cmd = build_netbench_driver_cmd(
+            driver,
+            build_wait_steps.clone(),
+            ssm_client,
+            instance_ids.clone(),
+            config,
+        )
+        .await?;
         build_drivers.push(build_driver_cmd);
     }
-    let build_russula =
-        build_russula_cmd(host_group, ssm_client, instance_ids.clone(), config).await;
+    let build_russula = if config.skip.build() {
+        check_russula_cmd(
+            host_group,
+            build_wait_steps,
+            ssm_client,
+            instance_ids.clone(),
+            config,
+        )
+        .await?
+    } else {
+        build_russula_cmd(
+            host_group,
+            build_wait_steps,
+            ssm_client,
+            instance_ids.clone(),
+            config,
+        )
+        .await?
+    };
+    cmds.push(build_russula);
+    cmds.extend(build_drivers);
+
+    if !hooks.post_build.is_empty() {
+        let post_build = send_command(
+            vec![Step::BuildDriver("".to_string()), Step::BuildRussula],
+            Step::PostBuildHook,
+            &format!("post_build_hook_{}", host_group),
+            ssm_client,
+            instance_ids,
+            hooks.post_build.clone(),
+            config,
+        )
+        .await?;
+        cmds.push(post_build);
+    }
+
+    Ok(cmds)
+}
+
+// The steps a Russula worker waits for before it's started on the hosts
+pub(crate) fn run_russula_wait_steps(
+    endpoint_type: EndpointType,
+    config: &OrchestratorConfig,
+) -> Vec<Step> {
+    let mut wait_steps = vec![Step::BuildDriver("".to_string()), Step::BuildRussula];
+    if !config
+        .driver_registry
+        .host_hooks(endpoint_type)
+        .post_build
+        .is_empty()
+    {
+        wait_steps.push(Step::PostBuildHook);
+    }
+    wait_steps
+}
+
+// The launched hosts shut down after `shutdown_min` regardless of the run,
+// but managed instances (eg. lab hardware) are left running.
+fn shutdown_cmd(managed: bool) -> Vec<String> {
+    if managed {
+        return vec![];
+    }
+    vec![format!("shutdown -P +{}", state().shutdown_min)]
+}
 
-    vec![install_deps, upload_scenario_file, build_russula]
-        .into_iter()
-        .chain(build_drivers)
-        .collect()
+// Frees disk space on the hosts when less than `min_free_disk_gib` is left,
+// and fails the step if that's still not enough, rather than letting a full
+// root volume fail the run part way through.
+//
+// The results, captures and logs a previous run left in the Russula checkout
+// (eg. with `--skip-upload`, or on managed instances) are removed, along with
+// the Russula logs, the downloaded crates and git checkouts of cargo and the
+// yum cache, which are fetched again if needed. The files of the last hour are
+// kept, since the scenario file is downloaded alongside. Unless the build is
+// skipped, the build of the Russula checkout is removed too, since it's
+// rebuilt.
+fn disk_cleanup_cmds(skip_build: bool) -> Vec<String> {
+    let home = &state().host_home_path;
+    let min_free = state().min_free_disk_gib;
+    let free_gib = format!("$(df --output=avail -BG {home} | tail -1 | tr -dc 0-9)");
+    let (logs_dir, logs) = HOST_RUSSULA_LOGS
+        .rsplit_once('/')
+        .expect("the russula logs are in the target dir");
+    let clean_build = match skip_build {
+        true => String::new(),
+        false => format!("rm -rf {home}/{logs_dir}; "),
+    };
+    vec![
+        format!("echo \"{free_gib} GiB free on the root volume\""),
+        format!(
+            "if [ {free_gib} -lt {min_free} ]; then \
+            find {home}/netbench_orchestrator -maxdepth 1 -type f -mmin +60 \\( -name '*.json' -o -name '*.pcap*' -o -name '*.log' -o -name '*.svg' -o -name '*.perf.data' -o -name '*.bpftrace' \\) -delete; \
+            find {home}/{logs_dir} -maxdepth 1 -type f -mmin +60 -name '{logs}' -delete; \
+            {clean_build}\
+            rm -rf /root/.cargo/registry/cache /root/.cargo/git/checkouts {home}/.cargo/registry/cache {home}/.cargo/git/checkouts; \
+            yum clean all; \
+            echo \"cleaned up to {free_gib} GiB free\"; \
+            fi"
+        ),
+        require(
+            &format!("test {free_gib} -ge {min_free}"),
+            &format!("Less than {min_free} GiB of disk space left on the host after cleaning up"),
+        ),
+    ]
 }
 
 async fn install_deps_cmd(
     host_group: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    managed: bool,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
+    netbench_drivers: &[NetbenchDriverType],
     config: &OrchestratorConfig,
-) -> SendCommandOutput {
+) -> OrchResult<SendCommandOutput> {
+    // OS packages required by the drivers, eg. openssl for native-tls
+    let driver_packages: BTreeSet<&String> = netbench_drivers
+        .iter()
+        .flat_map(|driver| driver.packages())
+        .collect();
+
+    let mut cmds = shutdown_cmd(managed);
+    // before the packages and toolchains are installed
+    cmds.extend(disk_cleanup_cmds(config.skip.build()));
+    cmds.extend([
+        // create bin dir
+        format!("mkdir -p {}", state().host_bin_path()),
+        // yum
+        "yum upgrade -y".to_string(),
+        "timeout 5m bash -c 'until yum install cargo cmake git perl openssl-devel bpftrace perf tree -y; do sleep 10; done'".to_string(),
+    ]);
+    if !driver_packages.is_empty() {
+        let driver_packages: Vec<&str> = driver_packages.iter().map(|p| p.as_str()).collect();
+        cmds.push(format!(
+            "timeout 5m bash -c 'until yum install {} -y; do sleep 10; done'",
+            driver_packages.join(" ")
+        ));
+    }
+    if config.capture.is_enabled() {
+        cmds.push(
+            "timeout 5m bash -c 'until yum install tcpdump -y; do sleep 10; done'".to_string(),
+        );
+    }
+    if config.profile.is_enabled() {
+        // the scripts which turn the driver profiles into flamegraphs
+        cmds.push(format!(
+            "test -d {dir} || git clone --depth 1 https://github.com/brendangregg/FlameGraph {dir}",
+            dir = flamegraph_path()
+        ));
+    }
+    cmds.extend([
+        // rustup
+        "runuser -u ec2-user -- curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs > rustup.rs".to_string(),
+        "chmod +x rustup.rs".to_string(),
+        "chgrp ec2-user rustup.rs".to_string(),
+        "chown ec2-user rustup.rs".to_string(),
+        // install rust for ec2-user
+        "sh ./rustup.rs -y".to_string(),
+        "runuser -u ec2-user -- sh ./rustup.rs -y".to_string(),
+        // install rust for root
+        "./root/.cargo/bin/rustup update".to_string(),
+        "runuser -u ec2-user -- ./.cargo/bin/rustup update".to_string(),
+        // sim link rustc from home/ec2-user/bin
+        format!(
+            "ln -s /home/ec2-user/.cargo/bin/cargo {}",
+            state().cargo_path()
+        ),
+    ]);
+    // apply s3 transfer settings to the aws cli
+    cmds.extend(config.s3_transfer.ssm_configure_cmds());
+
     send_command(
         vec![],
         Step::Configure,
         &format!("configure_host_{}", host_group),
         ssm_client,
         instance_ids,
-        vec![
-            // set instances to shutdown after 1 hour
-            format!("shutdown -P +{}", STATE.shutdown_min),
-            // create bin dir
-            format!("mkdir -p {}", STATE.host_bin_path()),
-            // yum
-            "yum upgrade -y".to_string(),
-            "timeout 5m bash -c 'until yum install cargo cmake git perl openssl-devel bpftrace perf tree -y; do sleep 10; done'".to_string(),
-            // rustup
-            "runuser -u ec2-user -- curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs > rustup.rs".to_string(),
-            "chmod +x rustup.rs".to_string(),
-            "chgrp ec2-user rustup.rs".to_string(),
-            "chown ec2-user rustup.rs".to_string(),
-            // install rust for ec2-user
-            "sh ./rustup.rs -y".to_string(),
-            "runuser -u ec2-user -- sh ./rustup.rs -y".to_string(),
-            // install rust for root
-            "./root/.cargo/bin/rustup update".to_string(),
-            "runuser -u ec2-user -- ./.cargo/bin/rustup update".to_string(),
-            // sim link rustc from home/ec2-user/bin
-            format!(
-                "ln -s /home/ec2-user/.cargo/bin/cargo {}",
-                STATE.cargo_path()
+        cmds,
+        config,
+    )
+    .await
+}
+
+// The FlameGraph scripts on the hosts, where the russula workers look for them
+fn flamegraph_path() -> String {
+    format!("{}/{FLAMEGRAPH_DIR}", state().host_bin_path())
+}
+
+// A command which fails the step with `msg` if `test` fails on the host
+fn require(test: &str, msg: &str) -> String {
+    format!("{test} || {{ echo '{msg}' >&2; exit 1; }}")
+}
+
+// Checks that the hosts have the dependencies `install_deps_cmd` installs,
+// for `--skip-configure`. The shutdown is still scheduled, since the hosts
+// are launched for the run (unless they're managed instances).
+async fn check_configured_cmd(
+    host_group: &str,
+    managed: bool,
+    ssm_client: &impl SsmApi,
+    instance_ids: Vec<String>,
+    netbench_drivers: &[NetbenchDriverType],
+    config: &OrchestratorConfig,
+) -> OrchResult<SendCommandOutput> {
+    let driver_packages: BTreeSet<&str> = netbench_drivers
+        .iter()
+        .flat_map(|driver| driver.packages())
+        .map(|package| package.as_str())
+        .collect();
+
+    let mut cmds = shutdown_cmd(managed);
+    cmds.extend(disk_cleanup_cmds(config.skip.build()));
+    cmds.extend([
+        format!("mkdir -p {}", state().host_bin_path()),
+        require(
+            &format!("test -x {}", state().cargo_path()),
+            "--skip-configure: cargo is missing from the bin folder",
+        ),
+        require("command -v aws", "--skip-configure: the aws cli is missing"),
+    ]);
+    if !driver_packages.is_empty() {
+        let driver_packages = driver_packages.into_iter().collect::<Vec<_>>().join(" ");
+        cmds.push(require(
+            &format!("rpm -q {driver_packages}"),
+            &format!("--skip-configure: the driver packages {driver_packages} are missing"),
+        ));
+    }
+    if config.profile.is_enabled() {
+        cmds.extend([
+            require(
+                "command -v perf && command -v bpftrace",
+                "--skip-configure: perf or bpftrace is missing",
             ),
-        ],
+            require(
+                &format!("test -x {}/flamegraph.pl", flamegraph_path()),
+                "--skip-configure: the FlameGraph scripts are missing from the bin folder",
+            ),
+        ]);
+    }
+    if config.capture.is_enabled() {
+        cmds.push(require(
+            "command -v tcpdump",
+            "--skip-configure: tcpdump is missing",
+        ));
+    }
+    cmds.extend(config.s3_transfer.ssm_configure_cmds());
+
+    send_command(
+        vec![],
+        Step::Configure,
+        &format!("check_host_{}", host_group),
+        ssm_client,
+        instance_ids,
+        cmds,
         config,
     )
     .await
-    .expect("Timed out")
 }
 
 async fn build_netbench_driver_cmd(
     driver: &NetbenchDriverType,
-    ssm_client: &aws_sdk_ssm::Client,
+    wait_steps: Vec<Step>,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
     config: &OrchestratorConfig,
-) -> SendCommandOutput {
+) -> OrchResult<SendCommandOutput> {
     send_command(
-        vec![Step::UploadScenarioFile, Step::Configure],
-        Step::BuildDriver(driver.driver_name().clone()),
+        wait_steps,
+        Step::BuildDriver(driver.driver_name().to_string()),
         &format!("build_driver_{}", driver.driver_name()),
         ssm_client,
         instance_ids,
@@ -152,17 +490,48 @@ async fn build_netbench_driver_cmd(
         config,
     )
     .await
-    .expect("Timed out")
+}
+
+// Checks that the driver's executable is on the hosts, for `--skip-build`
+async fn check_netbench_driver_cmd(
+    driver: &NetbenchDriverType,
+    wait_steps: Vec<Step>,
+    ssm_client: &impl SsmApi,
+    instance_ids: Vec<String>,
+    config: &OrchestratorConfig,
+) -> OrchResult<SendCommandOutput> {
+    let bin_path = state().host_bin_path();
+    let driver_name = driver.driver_name();
+    send_command(
+        wait_steps,
+        Step::BuildDriver(driver_name.to_string()),
+        &format!("check_driver_{}", driver_name),
+        ssm_client,
+        instance_ids,
+        vec![
+            require(
+                &format!("test -x {bin_path}/{driver_name}"),
+                &format!("--skip-build: the {driver_name} driver is missing from the bin folder"),
+            ),
+            require(
+                &format!("test -x {bin_path}/s2n-netbench-collector"),
+                "--skip-build: the collector is missing from the bin folder",
+            ),
+        ],
+        config,
+    )
+    .await
 }
 
 async fn build_russula_cmd(
     host_group: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    wait_steps: Vec<Step>,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
     config: &OrchestratorConfig,
-) -> SendCommandOutput {
+) -> OrchResult<SendCommandOutput> {
     send_command(
-        vec![Step::UploadScenarioFile, Step::Configure],
+        wait_steps,
         Step::BuildRussula,
         &format!("build_russula_{}", host_group),
         ssm_client,
@@ -170,33 +539,55 @@ async fn build_russula_cmd(
         vec![
             format!(
                 "git clone --branch {} {}",
-                STATE.russula_branch, STATE.russula_repo
+                state().russula_branch,
+                state().russula_repo
             ),
             "cd netbench_orchestrator".to_string(),
             format!(
                 "env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse {} build --release",
-                STATE.cargo_path()
+                state().cargo_path()
             ),
             // copy executables to bin folder
             format!(
                 "find target/release -maxdepth 1 -type f -perm /a+x -exec cp {{}} {} \\;",
-                STATE.host_bin_path()
+                state().host_bin_path()
             ),
         ],
         config,
     )
     .await
-    .expect("Timed out")
+}
+
+// Checks that Russula is built on the hosts, for `--skip-build`
+async fn check_russula_cmd(
+    host_group: &str,
+    wait_steps: Vec<Step>,
+    ssm_client: &impl SsmApi,
+    instance_ids: Vec<String>,
+    config: &OrchestratorConfig,
+) -> OrchResult<SendCommandOutput> {
+    send_command(
+        wait_steps,
+        Step::BuildRussula,
+        &format!("check_russula_{}", host_group),
+        ssm_client,
+        instance_ids,
+        vec![require(
+            "test -x netbench_orchestrator/target/release/russula_cli",
+            "--skip-build: russula_cli is missing from the netbench_orchestrator checkout",
+        )],
+        config,
+    )
+    .await
 }
 
 async fn download_netbench_scenario_file_to_host(
     host_group: &str,
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
-    scenario: &OrchestratorConfig,
     unique_id: &str,
     config: &OrchestratorConfig,
-) -> SendCommandOutput {
+) -> OrchResult<SendCommandOutput> {
     send_command(
         vec![],
         Step::UploadScenarioFile,
@@ -206,37 +597,87 @@ async fn download_netbench_scenario_file_to_host(
         vec![
             // copy scenario file to host
             format!(
-                "aws s3 cp s3://{}/{unique_id}/{} {}/{}",
+                "aws s3 cp s3://{}/{} {}/{}",
                 // from
-                config.cdk_config.netbench_runner_public_s3_bucket(),
-                scenario.netbench_scenario_filename(),
+                config.results_bucket(),
+                RunPaths::new(unique_id).key(config.netbench_scenario_filename()),
                 // to
-                STATE.host_bin_path(),
-                scenario.netbench_scenario_filename()
+                state().host_bin_path(),
+                config.netbench_scenario_filename()
             ),
         ],
         config,
     )
     .await
-    .expect("Timed out")
 }
 
+// Moves the results of the drivers from the hosts to the results bucket, with
+// a single command since the hosts can run both drivers of a pair in loopback
+// mode.
 pub async fn upload_netbench_data_to_s3(
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
     unique_id: &str,
     config: &OrchestratorConfig,
-    driver: &NetbenchDriverType,
-) -> SendCommandOutput {
-    let driver_name = driver.trim_driver_name();
-    let s3_command = format!(
-        "aws s3 cp *{driver_name}.json {}/results/{}/{driver_name}/",
-        config.s3_path(unique_id),
-        config.netbench_scenario_filepath_stem()
-    );
-    let cmd = vec!["cd netbench_orchestrator".to_string(), s3_command];
+    drivers: &[&NetbenchDriverType],
+    iteration: Option<u32>,
+) -> OrchResult<SendCommandOutput> {
+    let paths = RunPaths::new(unique_id);
+    let scenario = config.netbench_scenario_filepath_stem();
+    let mut cmd = vec!["cd netbench_orchestrator".to_string()];
+    for driver in drivers {
+        // The worker names the results after the short driver name
+        let driver_name = driver.driver_name().short();
+        let results_name = driver.results_name();
+        let s3_results_path = format!(
+            "s3://{}/{}",
+            config.results_bucket(),
+            paths.driver_results_prefix(scenario, &results_name, iteration)
+        );
+        let s3_coordination_path = format!(
+            "s3://{}/{}",
+            config.results_bucket(),
+            paths.driver_coordination_prefix(scenario, &results_name, iteration)
+        );
+        let s3_captures_path = format!(
+            "s3://{}/{}",
+            config.results_bucket(),
+            paths.driver_captures_prefix(scenario, &results_name, iteration)
+        );
+        // The results are moved rather than copied, since the same driver is run
+        // again for each iteration and configuration.
+        let s3_command = format!(
+            "aws s3 mv . {s3_results_path} --recursive --exclude '*' --include '*{driver_name}.json'"
+        );
+        // Flamegraphs are only present if profiling was enabled for the run. They are
+        // placed next to the results so that the report can link to them.
+        let s3_flamegraph_command = format!(
+            "aws s3 mv . {s3_results_path} --recursive --exclude '*' --include '*{driver_name}*.svg'"
+        );
+        // The time each worker spent in each Russula state
+        let s3_events_command = format!(
+            "aws s3 mv . {s3_coordination_path} --recursive --exclude '*' --include '*{driver_name}.russula.json'"
+        );
+        // Pcaps are only present if packet capture was enabled for the run. The
+        // ring buffer files are numbered, eg. `.pcap0`.
+        let s3_captures_command = format!(
+            "aws s3 mv . {s3_captures_path} --recursive --exclude '*' --include '*{driver_name}.pcap*'"
+        );
+        // The output of the pre and post run hooks, see `hooks`
+        let s3_hooks_command = format!(
+            "aws s3 mv . {s3_results_path} --recursive --exclude '*' --include '*_run_hook-{driver_name}.log'"
+        );
+        cmd.extend([
+            s3_command,
+            s3_flamegraph_command,
+            s3_events_command,
+            s3_captures_command,
+            s3_hooks_command,
+        ]);
+    }
 
-    info!("Copying results to s3 for driver: {:?}", cmd);
+    let results_names: Vec<_> = drivers.iter().map(|driver| driver.results_name()).collect();
+    info!(drivers = ?results_names, "Copying results to s3: {:?}", cmd);
 
     send_command(
         vec![Step::RunRussula],
@@ -248,5 +689,24 @@ pub async fn upload_netbench_data_to_s3(
         config,
     )
     .await
-    .expect("Timed out")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_cleanup_removes_the_russula_logs_and_build() {
+        let cleanup = disk_cleanup_cmds(false).join("\n");
+        assert!(cleanup.contains(
+            "find /home/ec2-user/netbench_orchestrator/target -maxdepth 1 -type f -mmin +60 -name 'russula*' -delete; "
+        ));
+        assert!(cleanup.contains("rm -rf /home/ec2-user/netbench_orchestrator/target; "));
+        assert!(cleanup.contains("rm -rf /root/.cargo/registry/cache"));
+
+        // the build is kept when it's not rebuilt
+        let cleanup = disk_cleanup_cmds(true).join("\n");
+        assert!(cleanup.contains("-name 'russula*' -delete; "));
+        assert!(!cleanup.contains("rm -rf /home/ec2-user/netbench_orchestrator/target"));
+    }
 }
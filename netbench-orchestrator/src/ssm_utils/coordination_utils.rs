@@ -2,93 +2,112 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    ec2_utils::{InfraDetail, PubIp},
+    compute::ComputeProvider,
+    ec2_utils::{EndpointType, InfraDetail},
     orchestrator::OrchestratorConfig,
+    progress,
     russula::{
         self,
         netbench::{client, server},
-        WorkflowBuilder, WorkflowState,
+        EventRecorder, WorkflowBuilder, WorkflowState,
     },
-    ssm_utils,
     ssm_utils::NetbenchDriverType,
-    OrchError, OrchResult, STATE,
+    state, OrchResult,
 };
-use aws_sdk_ssm::operation::send_command::SendCommandOutput;
-use core::time::Duration;
-use indicatif::{ProgressBar, ProgressStyle};
-use std::{collections::BTreeSet, net::SocketAddr};
-use tracing::{debug, info};
-
-fn get_progress_bar(msg: String) -> ProgressBar {
-    let bar = ProgressBar::new(0);
-    let style = ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}")
-        .unwrap()
-        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ");
-    bar.set_style(style);
-    bar.enable_steady_tick(Duration::from_secs(1));
-    bar.set_message(msg);
-    bar
-}
+use core::task::Poll;
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, SocketAddr},
+    time::Instant,
+};
+use tracing::{debug, info, instrument};
 
 pub struct ServerNetbenchRussula {
-    // used to poll the remote worker via ssm
-    worker: SendCommandOutput,
+    // used to poll the remote worker via the compute backend
+    worker_command_id: String,
     coord: russula::Workflow<server::CoordWorkflow>,
     driver_name: String,
 }
 
 impl ServerNetbenchRussula {
+    // With `keep_servers` the workers leave the servers running once they're
+    // done, which is only done for the last driver pair of the run.
+    #[instrument(skip_all, fields(driver = %driver.results_name()))]
     pub async fn new(
-        ssm_client: &aws_sdk_ssm::Client,
+        compute: &impl ComputeProvider,
         infra: &InfraDetail,
         scenario: &OrchestratorConfig,
         driver: &NetbenchDriverType,
+        keep_servers: bool,
+        unique_id: &str,
     ) -> OrchResult<Self> {
         debug!("starting server worker");
-        let instance_ids = infra.server_ids();
-        let worker =
-            ssm_utils::server::run_russula_worker(ssm_client, instance_ids, driver, scenario)
-                .await?;
+        let worker_command_id = compute
+            .start_worker(
+                infra,
+                EndpointType::Server,
+                driver,
+                None,
+                keep_servers,
+                unique_id,
+                scenario,
+            )
+            .await?;
         // wait for worker to start
-        tokio::time::sleep(STATE.poll_delay_ssm).await;
+        tokio::time::sleep(state().poll_delay_ssm).await;
 
         // server coord
         debug!("starting server coordinator");
-        let coord = server_coord(infra.public_server_ips()).await?;
+        let coord = server_coord(
+            infra.russula_ips(EndpointType::Server, scenario),
+            scenario.ports.russula(),
+        )
+        .await?;
         Ok(ServerNetbenchRussula {
-            worker,
+            worker_command_id,
             coord,
-            driver_name: driver.trim_driver_name(),
+            driver_name: driver.results_name(),
         })
     }
 
+    // The command running the workers, whose output includes the worker logs.
+    pub fn worker_command_id(&self) -> &str {
+        &self.worker_command_id
+    }
+
+    // The events recorded by the coordinator for each worker.
+    pub fn event_recorders(&mut self) -> Vec<EventRecorder> {
+        self.coord.event_recorders()
+    }
+
     // Poll till netbench is running on the server hosts.
+    #[instrument(skip_all, fields(driver = %self.driver_name))]
     pub async fn wait_netbench_running(
         &mut self,
-        ssm_client: &aws_sdk_ssm::Client,
+        compute: &impl ComputeProvider,
     ) -> OrchResult<()> {
         let msg = format!("{}: Waiting for server state Running.", self.driver_name);
-        let bar = get_progress_bar(msg);
-        let cmd_id = self.worker.command().unwrap().command_id().unwrap();
+        let bar = progress::spinner(msg.clone());
+        let cmd_id = &self.worker_command_id;
+        let mut backoff = self.coord.backoff();
+        let mut worker = WorkerPoll::default();
 
         loop {
-            let poll_worker = ssm_utils::poll_ssm_results("server", ssm_client, cmd_id).await?;
-            let poll_coord_worker_running = self
-                .coord
-                .poll_state(WorkflowState::WorkerRunning)
-                .await
-                .map_err(|err| OrchError::Russula {
-                    dbg: err.to_string(),
-                })?;
+            let poll_worker = worker.poll(compute, "server", cmd_id).await?;
+            let poll_coord_worker_running =
+                self.coord.poll_state(WorkflowState::WorkerRunning).await?;
             debug!(
                 "Server Russula!: poll worker_running. Coordinator: {:?} Worker {:?}",
                 poll_coord_worker_running, poll_worker
             );
+            bar.set_message(format!("{msg} {}", self.coord.peer_states().join(", ")));
 
             if poll_coord_worker_running.is_ready() {
                 break;
             }
-            tokio::time::sleep(STATE.poll_delay_ssm).await;
+            self.coord
+                .wait_for_peers(WorkflowState::WorkerRunning, &mut backoff)
+                .await;
         }
         bar.finish();
 
@@ -96,26 +115,24 @@ impl ServerNetbenchRussula {
     }
 
     // Continue to poll the server worker and coordinator till it is done
-    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) -> OrchResult<()> {
+    #[instrument(skip_all, fields(driver = %self.driver_name))]
+    pub async fn wait_done(&mut self, compute: &impl ComputeProvider) -> OrchResult<()> {
         let msg = format!("{}: Waiting for server state Done.", self.driver_name);
-        let bar = get_progress_bar(msg);
-        let cmd_id = self.worker.command().unwrap().command_id().unwrap();
+        let bar = progress::spinner(msg.clone());
+        let cmd_id = &self.worker_command_id;
+        let mut backoff = self.coord.backoff();
+        let mut worker = WorkerPoll::default();
 
         loop {
-            let poll_worker = ssm_utils::poll_ssm_results("server", ssm_client, cmd_id).await?;
-            let poll_coord_done =
-                self.coord
-                    .poll_state(WorkflowState::Done)
-                    .await
-                    .map_err(|err| OrchError::Russula {
-                        dbg: err.to_string(),
-                    })?;
+            let poll_worker = worker.poll(compute, "server", cmd_id).await?;
+            let poll_coord_done = self.coord.poll_state(WorkflowState::Done).await?;
             debug!(
                 "Server Russula!: Coordinator: {:?} Worker {:?}",
                 poll_coord_done, poll_worker
             );
+            bar.set_message(format!("{msg} {}", self.coord.peer_states().join(", ")));
 
-            // Since the workers are executed via SSM, there is a delay in detecting
+            // Since the workers are executed remotely, there is a delay in detecting
             // when they finish. In practice it's not absolutely necessary to wait
             // for the workers to finish.
             //
@@ -126,8 +143,11 @@ impl ServerNetbenchRussula {
             if poll_coord_done.is_ready() {
                 break;
             }
-            tokio::time::sleep(STATE.poll_delay_ssm).await;
+            self.coord
+                .wait_for_peers(WorkflowState::Done, &mut backoff)
+                .await;
         }
+        self.coord.notify_done().await?;
         bar.finish();
 
         info!("Server Russula!: Successful");
@@ -136,64 +156,83 @@ impl ServerNetbenchRussula {
 }
 
 pub struct ClientNetbenchRussula {
-    // used to poll the remote worker via ssm
-    worker: SendCommandOutput,
+    // used to poll the remote worker via the compute backend
+    worker_command_id: String,
     coord: russula::Workflow<client::CoordWorkflow>,
     driver_name: String,
 }
 
 impl ClientNetbenchRussula {
+    // In loopback mode the workers also run the `loopback_server` driver,
+    // which is started before the client and stopped once it's done.
+    #[instrument(skip_all, fields(driver = %driver.results_name()))]
     pub async fn new(
-        ssm_client: &aws_sdk_ssm::Client,
+        compute: &impl ComputeProvider,
         infra: &InfraDetail,
         scenario: &OrchestratorConfig,
         driver: &NetbenchDriverType,
+        loopback_server: Option<&NetbenchDriverType>,
+        unique_id: &str,
     ) -> OrchResult<Self> {
-        let instance_ids = infra.client_ids();
         debug!("starting client worker");
-        let worker = ssm_utils::client::run_russula_worker(
-            ssm_client,
-            instance_ids,
-            infra.private_server_ips(),
-            driver,
-            scenario,
-        )
-        .await?;
+        let worker_command_id = compute
+            .start_worker(
+                infra,
+                EndpointType::Client,
+                driver,
+                loopback_server,
+                false,
+                unique_id,
+                scenario,
+            )
+            .await?;
 
         // wait for worker to start
-        tokio::time::sleep(STATE.poll_delay_ssm).await;
+        tokio::time::sleep(state().poll_delay_ssm).await;
 
         // client coord
         debug!("starting client coordinator");
-        let coord = client_coord(infra.public_client_ips()).await?;
+        let coord = client_coord(
+            infra.russula_ips(EndpointType::Client, scenario),
+            scenario.ports.russula(),
+        )
+        .await?;
         Ok(ClientNetbenchRussula {
-            worker,
+            worker_command_id,
             coord,
-            driver_name: driver.trim_driver_name(),
+            driver_name: driver.results_name(),
         })
     }
 
+    // The command running the workers, whose output includes the worker logs.
+    pub fn worker_command_id(&self) -> &str {
+        &self.worker_command_id
+    }
+
+    // The events recorded by the coordinator for each worker.
+    pub fn event_recorders(&mut self) -> Vec<EventRecorder> {
+        self.coord.event_recorders()
+    }
+
     // Continue to poll the client worker and coordinator till it is done
-    pub async fn wait_done(&mut self, ssm_client: &aws_sdk_ssm::Client) -> OrchResult<()> {
+    #[instrument(skip_all, fields(driver = %self.driver_name))]
+    pub async fn wait_done(&mut self, compute: &impl ComputeProvider) -> OrchResult<()> {
         let msg = format!("{}: Waiting for client state Done.", self.driver_name);
-        let bar = get_progress_bar(msg);
-        let cmd_id = self.worker.command().unwrap().command_id().unwrap();
+        let bar = progress::spinner(msg.clone());
+        let cmd_id = &self.worker_command_id;
+        let mut backoff = self.coord.backoff();
+        let mut worker = WorkerPoll::default();
 
         loop {
-            let poll_worker = ssm_utils::poll_ssm_results("client", ssm_client, cmd_id).await?;
-            let poll_coord = self
-                .coord
-                .poll_state(WorkflowState::Done)
-                .await
-                .map_err(|err| OrchError::Russula {
-                    dbg: err.to_string(),
-                })?;
+            let poll_worker = worker.poll(compute, "client", cmd_id).await?;
+            let poll_coord = self.coord.poll_state(WorkflowState::Done).await?;
             debug!(
                 "Client Russula!: Coordinator: {:?} Worker {:?}",
                 poll_coord, poll_worker
             );
+            bar.set_message(format!("{msg} {}", self.coord.peer_states().join(", ")));
 
-            // Since the workers are executed via SSM, there is a delay in detecting
+            // Since the workers are executed remotely, there is a delay in detecting
             // when they finish. In practice it's not absolutely necessary to wait
             // for the workers to finish.
             //
@@ -204,8 +243,11 @@ impl ClientNetbenchRussula {
             if poll_coord.is_ready() {
                 break;
             }
-            tokio::time::sleep(STATE.poll_delay_ssm).await;
+            self.coord
+                .wait_for_peers(WorkflowState::Done, &mut backoff)
+                .await;
         }
+        self.coord.notify_done().await?;
         bar.finish();
 
         info!("Client Russula!: Successful");
@@ -213,63 +255,74 @@ impl ClientNetbenchRussula {
     }
 }
 
+// Polls the command running the workers, which fails if the workers exited
+// with an error.
+//
+// The coordinator is polled as soon as a worker sends a message, so the
+// command is only polled every `poll_delay_ssm` to bound the SSM requests.
+#[derive(Default)]
+struct WorkerPoll {
+    last: Option<Instant>,
+}
+
+impl WorkerPoll {
+    // None if the command was polled less than `poll_delay_ssm` ago
+    async fn poll(
+        &mut self,
+        compute: &impl ComputeProvider,
+        host_group: &str,
+        cmd_id: &str,
+    ) -> OrchResult<Option<Poll<()>>> {
+        if self
+            .last
+            .is_some_and(|last| last.elapsed() < state().poll_delay_ssm)
+        {
+            return Ok(None);
+        }
+        self.last = Some(Instant::now());
+        compute.poll(host_group, cmd_id).await.map(Some)
+    }
+}
+
 async fn server_coord(
-    server_ips: Vec<&PubIp>,
+    server_ips: Vec<IpAddr>,
+    russula_port: u16,
 ) -> OrchResult<russula::Workflow<server::CoordWorkflow>> {
     let server_addr: Vec<SocketAddr> = server_ips
-        .iter()
-        .map(|ip| SocketAddr::new(ip.0, STATE.russula_port))
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, russula_port))
         .collect();
     let server_coord = WorkflowBuilder::new(
         BTreeSet::from_iter(server_addr),
         server::CoordWorkflow::new(),
-        STATE.poll_delay_russula,
+        state().poll_delay_russula,
     );
-    let mut server_coord = server_coord
-        .build()
-        .await
-        .map_err(|err| OrchError::Russula {
-            dbg: err.to_string(),
-        })?;
+    let mut server_coord = server_coord.build().await?;
 
     // Attempt to connect to the peer
-    server_coord
-        .run_till(WorkflowState::Ready)
-        .await
-        .map_err(|err| OrchError::Russula {
-            dbg: err.to_string(),
-        })?;
+    server_coord.run_till(WorkflowState::Ready).await?;
 
     info!("server coord Ready");
     Ok(server_coord)
 }
 
 async fn client_coord(
-    client_ips: Vec<&PubIp>,
+    client_ips: Vec<IpAddr>,
+    russula_port: u16,
 ) -> OrchResult<russula::Workflow<client::CoordWorkflow>> {
     let client_addr: Vec<SocketAddr> = client_ips
-        .iter()
-        .map(|ip| SocketAddr::new(ip.0, STATE.russula_port))
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, russula_port))
         .collect();
     let client_coord = WorkflowBuilder::new(
         BTreeSet::from_iter(client_addr),
         client::CoordWorkflow::new(),
-        STATE.poll_delay_russula,
+        state().poll_delay_russula,
     );
-    let mut client_coord = client_coord
-        .build()
-        .await
-        .map_err(|err| OrchError::Russula {
-            dbg: err.to_string(),
-        })?;
+    let mut client_coord = client_coord.build().await?;
 
     // Attempt to connect to the peer
-    client_coord
-        .run_till(WorkflowState::Ready)
-        .await
-        .map_err(|err| OrchError::Russula {
-            dbg: err.to_string(),
-        })?;
+    client_coord.run_till(WorkflowState::Ready).await?;
 
     info!("client coord Ready");
     Ok(client_coord)
@@ -1,73 +1,220 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::orchestrator::STATE;
-use std::path::PathBuf;
-
-#[allow(dead_code)]
-pub mod native_tls_driver;
-pub mod s2n_quic_dc_driver;
-pub mod s2n_quic_driver_crates;
-#[allow(dead_code)]
-pub mod s2n_quic_driver_github;
-pub mod s2n_tls_driver;
-pub mod tcp_driver_crates;
-#[allow(dead_code)]
-pub mod tcp_driver_github;
-
-#[allow(dead_code)]
-pub enum NetbenchDriverType {
+use crate::{orchestrator::state, russula::netbench::DriverName};
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+mod registry;
+
+pub use registry::DriverRegistry;
+
+// A netbench driver (client or server) and how to build it on the hosts.
+//
+// Drivers are defined in the driver registry file rather than in source; see
+// `DriverRegistry`.
+pub struct NetbenchDriverType {
+    driver_name: DriverName,
+    source: DriverSource,
+    // The collector installed with the driver, the latest if unset
+    collector_version: Option<String>,
+    // OS packages required to build or run the driver
+    packages: Vec<String>,
+    // Replaces the default build commands of the source
+    build_cmd: Option<Vec<String>>,
+    // Cargo flags of the default build commands
+    build_flags: BuildFlags,
+    // Built separately from other drivers with the same executable and
+    // installed under `driver_name`, rather than the executable name
+    isolated: bool,
+    // Arguments passed to the driver when it's run
+    args: Vec<String>,
+    // Environment variables set for the driver when it's run
+    env: BTreeMap<String, String>,
+    // The runtime configuration of the driver, if the pair is run with several
+    label: Option<String>,
+    // The transport the server listens on
+    protocol: Protocol,
+    port: u16,
+    // How often the collector samples the driver, the collector's default if unset
+    collector_interval: Option<String>,
+}
+
+// The transport protocol of a driver, used to derive the security group rules
+// of the hosts.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+pub enum DriverSource {
     GithubRustProj(GithubRustSource),
     CratesIo(CrateIoSource),
+    GithubRelease(GithubReleaseSource),
     Local(LocalSource),
 }
 
+// Cargo flags used by the default build commands of a driver
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildFlags {
+    pub rustflags: Option<String>,
+    pub features: Vec<String>,
+    // A cargo profile. Defaults to `release`
+    pub profile: Option<String>,
+}
+
 pub struct GithubRustSource {
-    pub driver_name: String,
     pub repo_name: String,
+    // Defaults to the netbench repo
+    pub repo: Option<String>,
+    // A branch, tag or commit. Defaults to the netbench branch
+    pub git_ref: Option<String>,
+    // The executable built by the project
+    pub bin: String,
+}
+
+pub struct GithubReleaseSource {
+    // The repository in `owner/name` form
+    pub repo: String,
+    pub tag: String,
+    // The executable, which is also the name of the release asset
+    pub bin: String,
+    // The expected sha256 of the executable
+    pub sha256: String,
 }
 
 pub struct LocalSource {
-    pub driver_name: String,
-    pub ssm_build_cmd: Vec<String>,
     pub proj_name: String,
+    // The executable built by the project
+    pub bin: String,
     // Used to copy local driver source to hosts
     //
     // upload to s3 locally and download form s3 in ssm_build_cmd
-    local_path_to_proj: PathBuf,
+    pub local_path_to_proj: PathBuf,
+    // The s3 location the source is uploaded to
+    pub s3_path: String,
 }
 
 pub struct CrateIoSource {
     pub krate: String,
-    pub driver_name: String,
-    // TODO use version
-    #[allow(dead_code)]
-    version: String,
+    // The executable installed by the crate
+    pub bin: String,
+    // The version requirement from the driver registry
+    pub version: String,
+    // The exact version matching `version`, resolved from the crates.io index
+    // when the drivers are loaded so that every host installs the same version
+    pub resolved_version: Option<String>,
+    // Install with `--locked` so the crate's Cargo.lock is used
+    pub locked: bool,
 }
 
 impl NetbenchDriverType {
-    pub fn driver_name(&self) -> &String {
-        match self {
-            NetbenchDriverType::GithubRustProj(source) => &source.driver_name,
-            NetbenchDriverType::Local(source) => &source.driver_name,
-            NetbenchDriverType::CratesIo(source) => &source.driver_name,
+    pub fn driver_name(&self) -> &DriverName {
+        &self.driver_name
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    // The name of the driver in the results, which distinguishes the runtime
+    // configurations of the same driver.
+    pub fn results_name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{}-{label}", self.driver_name.short()),
+            None => self.driver_name.short().to_string(),
+        }
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn collector_interval(&self) -> Option<&str> {
+        self.collector_interval.as_deref()
+    }
+
+    pub fn packages(&self) -> &[String] {
+        &self.packages
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    // A human readable description of where the driver is built from.
+    pub fn source_detail(&self) -> String {
+        match &self.source {
+            DriverSource::GithubRustProj(source) => format!(
+                "github: {}@{} ({})",
+                source.repo(),
+                source.git_ref(),
+                source.repo_name
+            ),
+            DriverSource::GithubRelease(source) => format!(
+                "github release: {}@{} ({})",
+                source.repo, source.tag, source.bin
+            ),
+            DriverSource::Local(source) => format!(
+                "local: {} ({})",
+                source.proj_name,
+                source.local_path_to_proj.display()
+            ),
+            DriverSource::CratesIo(source) => {
+                let version = source.resolved_version.as_ref().unwrap_or(&source.version);
+                let locked = if source.locked { " (locked)" } else { "" };
+                format!("crates.io: {}@{}{}", source.krate, version, locked)
+            }
         }
     }
 
-    pub fn trim_driver_name(&self) -> String {
-        self.driver_name()
-            .trim_start_matches("s2n-netbench-driver-")
-            .trim_start_matches("netbench-driver-")
-            .trim_end_matches(".json")
-            .to_owned()
+    // A human readable description of the cargo flags, if any are set.
+    pub fn build_detail(&self) -> Option<String> {
+        self.build_flags.detail()
+    }
+
+    // The exact version of the driver, if known before it's installed.
+    pub fn version(&self) -> Option<&str> {
+        match &self.source {
+            DriverSource::CratesIo(source) => source.resolved_version.as_deref(),
+            DriverSource::GithubRelease(source) => Some(&source.tag),
+            DriverSource::GithubRustProj(_) | DriverSource::Local(_) => None,
+        }
     }
 
     // Set of commands that are execute on remote hosts via SSM.
     pub fn ssm_build_cmd(&self) -> Vec<String> {
-        let build_cmd = match self {
-            NetbenchDriverType::GithubRustProj(source) => source.ssm_build_rust_proj(),
-            NetbenchDriverType::Local(source) => source.ssm_build_cmd.clone(),
-            NetbenchDriverType::CratesIo(source) => source.ssm_build_crates_io_proj(),
+        let build_cmd = match &self.source {
+            DriverSource::GithubRustProj(source) => source.ssm_build_rust_proj(self),
+            DriverSource::Local(source) => source.ssm_build_local_proj(self),
+            DriverSource::CratesIo(source) => match &self.build_cmd {
+                Some(build_cmd) => build_cmd.clone(),
+                None => source.ssm_build_crates_io_proj(self),
+            },
+            DriverSource::GithubRelease(source) => match &self.build_cmd {
+                Some(build_cmd) => build_cmd.clone(),
+                None => source.ssm_download_release(&self.driver_name.to_string()),
+            },
         };
         self.ssm_build_collector()
             .into_iter()
@@ -76,57 +223,262 @@ impl NetbenchDriverType {
     }
 
     pub fn ssm_build_collector(&self) -> Vec<String> {
+        let version = self
+            .collector_version
+            .as_ref()
+            .map(|version| format!(" --version {version}"))
+            .unwrap_or_default();
         vec![
             format!(
-                "runuser -u ec2-user -- env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse {} install s2n-netbench-collector",
-                STATE.cargo_path(),
+                "runuser -u ec2-user -- env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse {} install s2n-netbench-collector{version}",
+                state().cargo_path(),
             ),
             // link from the bin folder
             format!(
                 "ln -s /home/ec2-user/.cargo/bin/s2n-netbench-collector {}/s2n-netbench-collector",
-                STATE.host_bin_path(),
+                state().host_bin_path(),
             )
         ]
     }
 }
 
+impl BuildFlags {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn detail(&self) -> Option<String> {
+        let mut detail = vec![];
+        if let Some(profile) = &self.profile {
+            detail.push(format!("profile: {profile}"));
+        }
+        if !self.features.is_empty() {
+            detail.push(format!("features: {}", self.features.join(",")));
+        }
+        if let Some(rustflags) = &self.rustflags {
+            detail.push(format!("rustflags: {rustflags}"));
+        }
+        (!detail.is_empty()).then(|| detail.join("; "))
+    }
+
+    // Environment variables set for cargo
+    fn env(&self) -> String {
+        match &self.rustflags {
+            Some(rustflags) => format!(" RUSTFLAGS='{}'", rustflags.replace('\'', "'\\''")),
+            None => String::new(),
+        }
+    }
+
+    // Arguments shared by `cargo build` and `cargo install`
+    fn args(&self) -> String {
+        let mut args = String::new();
+        if !self.features.is_empty() {
+            args.push_str(&format!(" --features '{}'", self.features.join(",")));
+        }
+        if let Some(profile) = &self.profile {
+            args.push_str(&format!(" --profile {profile}"));
+        }
+        args
+    }
+
+    // The directory in the target directory which the executables are built to
+    fn profile_dir(&self) -> &str {
+        match self.profile.as_deref() {
+            None => "release",
+            // cargo uses `debug` for the built-in development profiles
+            Some("dev" | "test") => "debug",
+            Some(profile) => profile,
+        }
+    }
+
+    fn cargo_build(&self, target_dir: Option<&str>) -> String {
+        let mut cmd = format!(
+            "env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse{} {} build",
+            self.env(),
+            state().cargo_path()
+        );
+        if self.profile.is_none() {
+            cmd.push_str(" --release");
+        }
+        cmd.push_str(&self.args());
+        if let Some(target_dir) = target_dir {
+            cmd.push_str(&format!(" --target-dir {target_dir}"));
+        }
+        cmd
+    }
+}
+
+// Build the project in the current directory, unless overridden by the registry.
+fn ssm_build_proj(build_cmd: Option<&Vec<String>>, build_flags: &BuildFlags) -> Vec<String> {
+    let mut cmds = ssm_build_proj_only(build_cmd, build_flags, None);
+    // copy executables to bin folder
+    cmds.push(format!(
+        "find target/{} -maxdepth 1 -type f -perm /a+x -exec cp {{}} {} \\;",
+        build_flags.profile_dir(),
+        state().host_bin_path()
+    ));
+    cmds
+}
+
+fn ssm_build_proj_only(
+    build_cmd: Option<&Vec<String>>,
+    build_flags: &BuildFlags,
+    target_dir: Option<&str>,
+) -> Vec<String> {
+    match build_cmd {
+        Some(build_cmd) => build_cmd.clone(),
+        None => vec![build_flags.cargo_build(target_dir)],
+    }
+}
+
 impl GithubRustSource {
-    pub fn ssm_build_rust_proj(&self) -> Vec<String> {
+    pub fn repo(&self) -> &str {
+        self.repo.as_deref().unwrap_or(&state().netbench_repo)
+    }
+
+    pub fn git_ref(&self) -> &str {
+        self.git_ref.as_deref().unwrap_or(&state().netbench_branch)
+    }
+
+    pub fn ssm_build_rust_proj(&self, driver: &NetbenchDriverType) -> Vec<String> {
+        let driver_name = &driver.driver_name;
+        let build_cmd = driver.build_cmd.as_ref();
+        if !driver.isolated {
+            let mut cmds = vec![
+                format!(
+                    "git clone --branch {} {}",
+                    state().netbench_branch,
+                    state().netbench_repo
+                ),
+                format!("cd {}", self.repo_name),
+            ];
+            cmds.extend(ssm_build_proj(build_cmd, &driver.build_flags));
+            return cmds;
+        }
+
+        // Isolated sources are cloned into their own directory and only the
+        // driver's executable is installed, under the driver name, so that
+        // they can be built alongside the same driver from another ref or
+        // with other build flags.
+        let mut cmds = vec![
+            format!("rm -rf {driver_name}"),
+            format!("git clone {} {driver_name}", self.repo()),
+            format!("cd {driver_name}"),
+            // checkout supports branches, tags and commits, unlike `clone --branch`
+            format!("git checkout {}", self.git_ref()),
+        ];
+        cmds.extend(ssm_build_proj_only(build_cmd, &driver.build_flags, None));
+        cmds.push(format!(
+            "cp target/{}/{} {}/{driver_name}",
+            driver.build_flags.profile_dir(),
+            self.bin,
+            state().host_bin_path()
+        ));
+        cmds
+    }
+}
+
+impl GithubReleaseSource {
+    fn url(&self) -> String {
+        format!(
+            "https://github.com/{}/releases/download/{}/{}",
+            self.repo, self.tag, self.bin
+        )
+    }
+
+    pub fn ssm_download_release(&self, driver_name: &str) -> Vec<String> {
+        let download = format!("/tmp/{driver_name}");
         vec![
+            // SSM doesn't stop at a failed command, so the executable is only
+            // installed if the download and checksum succeed
             format!(
-                "git clone --branch {} {}",
-                STATE.netbench_branch, STATE.netbench_repo
-            ),
-            format!("cd {}", self.repo_name),
-            format!(
-                "env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse {} build --release",
-                STATE.cargo_path()
+                "curl -sSfL -o {download} {} && echo '{}  {download}' | sha256sum --check && install -m 755 {download} {}/{driver_name}",
+                self.url(),
+                self.sha256,
+                state().host_bin_path(),
             ),
-            // copy netbench executables to bin folder
+            format!("rm -f {download}"),
+        ]
+    }
+}
+
+impl LocalSource {
+    pub fn ssm_build_local_proj(&self, driver: &NetbenchDriverType) -> Vec<String> {
+        let build_cmd = driver.build_cmd.as_ref();
+        let mut cmds = vec![
+            // copy source from s3 to host
             format!(
-                "find target/release -maxdepth 1 -type f -perm /a+x -exec cp {{}} {} \\;",
-                STATE.host_bin_path()
+                "aws s3 sync {}/{}/ {}/{}",
+                self.s3_path,
+                self.proj_name,
+                state().host_home_path,
+                self.proj_name,
             ),
-        ]
+            format!("cd {}", self.proj_name),
+        ];
+        if !driver.isolated {
+            cmds.extend(ssm_build_proj(build_cmd, &driver.build_flags));
+            return cmds;
+        }
+
+        // Isolated drivers share the source but are built to their own target
+        // directory, and installed under the driver name
+        let target_dir = format!("target/{}", driver.driver_name);
+        cmds.extend(ssm_build_proj_only(
+            build_cmd,
+            &driver.build_flags,
+            Some(&target_dir),
+        ));
+        cmds.push(format!(
+            "cp {target_dir}/{}/{} {}/{}",
+            driver.build_flags.profile_dir(),
+            self.bin,
+            state().host_bin_path(),
+            driver.driver_name
+        ));
+        cmds
     }
 }
 
 impl CrateIoSource {
-    pub fn ssm_build_crates_io_proj(&self) -> Vec<String> {
+    pub fn ssm_build_crates_io_proj(&self, driver: &NetbenchDriverType) -> Vec<String> {
+        let mut install = format!(
+            "runuser -u ec2-user -- env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse{} {} install {}",
+            driver.build_flags.env(),
+            state().cargo_path(),
+            self.krate,
+        );
+        match &self.resolved_version {
+            Some(version) => install.push_str(&format!(" --version '={version}'")),
+            // `*` matches the latest version, which is the default of cargo install
+            None if self.version != "*" => {
+                install.push_str(&format!(" --version '{}'", self.version))
+            }
+            None => {}
+        }
+        if self.locked {
+            install.push_str(" --locked");
+        }
+        install.push_str(&driver.build_flags.args());
+
+        // isolated drivers are installed to their own root so they don't
+        // replace the same crate installed with other build flags
+        let root = if driver.isolated {
+            let root = format!("/home/ec2-user/.cargo/{}", driver.driver_name);
+            install.push_str(&format!(" --root {root}"));
+            root
+        } else {
+            "/home/ec2-user/.cargo".to_string()
+        };
+
         vec![
-            format!(
-                // "runuser -u ec2-user -- ./.cargo/bin/rustup update".to_string(),
-                "runuser -u ec2-user -- env CARGO_REGISTRIES_CRATES_IO_PROTOCOL=sparse {} install {}",
-                STATE.cargo_path(),
-                self.krate,
-                // self.version
-            ),
+            install,
             // link this from bin folder
             format!(
-                "ln -s /home/ec2-user/.cargo/bin/{} {}/{}",
-                self.driver_name,
-                STATE.host_bin_path(),
-                self.driver_name,
+                "ln -s {root}/bin/{} {}/{}",
+                self.bin,
+                state().host_bin_path(),
+                driver.driver_name,
             ),
         ]
     }
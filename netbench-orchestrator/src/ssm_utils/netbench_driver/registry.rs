@@ -0,0 +1,1107 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::{
+    BuildFlags, CrateIoSource, DriverSource, GithubReleaseSource, GithubRustSource, LocalSource,
+    NetbenchDriverType, Protocol,
+};
+use crate::{
+    audit,
+    ec2_utils::EndpointType,
+    orchestrator::{state, ErrorKind, OrchError, OrchResult, OrchestratorConfig},
+    progress,
+    russula::netbench::DriverName,
+    s3_utils,
+};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use tracing::{debug, warn};
+
+// The set of drivers available to a run, loaded from the driver registry file.
+//
+// Each entry describes a client/server driver pair:
+//
+// {
+//   "name": "s2n-quic",
+//   "source": { "type": "crates_io", "krate": "s2n-netbench-driver-s2n-quic", "version": "0.1", "locked": true },
+//   "server_bin": "s2n-netbench-driver-server-s2n-quic",
+//   "client_bin": "s2n-netbench-driver-client-s2n-quic",
+//   "protocol": "udp",
+//   "port": 4433,
+//   "packages": ["..."],
+//   "build": ["..."],
+//   "rustflags": "...",
+//   "features": ["..."],
+//   "profile": "release",
+//   "args": ["..."],
+//   "env": { "KEY": "VALUE" },
+//   "configs": [{ "label": "...", "args": ["..."], "env": { "KEY": "VALUE" } }],
+//   "collector_interval": "1s"
+// }
+//
+// `protocol` and `port` (the netbench port by default) are the transport the
+// server listens on. `packages` are OS packages installed when configuring the
+// hosts, `build` replaces the default build commands of the source and `args`
+// and `env` are passed to the driver when it's run. The `{cargo}` and `{bin}` placeholders
+// in the build commands are replaced with the cargo and bin paths on the host,
+// and `{driver}` with the name of the server or client executable being built.
+// `rustflags`, `features` and `profile` are used by the default build commands.
+// Each of the `configs` runs the pair again with additional `args` and `env`,
+// labeled in the results. `collector_interval` is how often the collector
+// samples the drivers.
+//
+// The optional `hosts` object contains commands to set up each host group,
+// eg. to install an agent or extra certificates:
+//
+// "hosts": {
+//   "server": { "pre_build": ["..."], "post_build": ["..."] },
+//   "client": { "pre_build": ["..."], "pre_run": ["..."], "post_run": ["..."] }
+// }
+//
+// `pre_build` runs once the host is configured and before the drivers and
+// Russula are built. `post_build` runs after the builds and before the
+// workers are started. `pre_run` and `post_run` run before and after each
+// driver pair, and their output is uploaded with the driver's results. See
+// `hooks`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DriverRegistry {
+    drivers: Vec<DriverEntry>,
+    #[serde(default)]
+    hosts: HostGroupHooks,
+    // The collector version installed on the hosts, the latest if unset. See
+    // `pin_collector_version`.
+    #[serde(skip)]
+    collector_version: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostGroupHooks {
+    #[serde(default)]
+    server: HostHooks,
+    #[serde(default)]
+    client: HostHooks,
+}
+
+// The commands run as root from the home directory of the host
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostHooks {
+    #[serde(default)]
+    pub pre_build: Vec<String>,
+    #[serde(default)]
+    pub post_build: Vec<String>,
+    #[serde(default)]
+    pub pre_run: Vec<String>,
+    #[serde(default)]
+    pub post_run: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct DriverEntry {
+    name: String,
+    // Disabled drivers are kept in the registry but not run
+    #[serde(default = "enabled_default")]
+    enabled: bool,
+    source: SourceEntry,
+    server_bin: String,
+    client_bin: String,
+    #[serde(default)]
+    packages: Vec<String>,
+    #[serde(default)]
+    build: Option<Vec<String>>,
+    // Cargo flags of the default build commands
+    #[serde(default)]
+    rustflags: Option<String>,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    profile: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    // The transport the server listens on, which is opened to the other hosts
+    #[serde(default)]
+    protocol: Protocol,
+    // Defaults to the netbench port
+    #[serde(default)]
+    port: Option<u16>,
+    // Runtime configurations the pair is run with, each as a separate series
+    #[serde(default)]
+    configs: Vec<ConfigEntry>,
+    // How often the collector samples the drivers (eg. "100ms")
+    #[serde(default)]
+    collector_interval: Option<String>,
+}
+
+// A runtime configuration of a driver pair. The args and env are added to
+// those of the entry.
+#[derive(Clone, Debug, Deserialize)]
+struct ConfigEntry {
+    label: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SourceEntry {
+    // A crate published to crates.io
+    CratesIo {
+        krate: String,
+        #[serde(default = "version_default")]
+        version: String,
+        // Install with the crate's Cargo.lock
+        #[serde(default)]
+        locked: bool,
+    },
+    // A project in a git repository, the netbench repository by default
+    Github {
+        repo_name: String,
+        // The url of the repository to clone
+        #[serde(default)]
+        repo: Option<String>,
+        // A branch, tag or commit to check out
+        #[serde(default, rename = "ref")]
+        git_ref: Option<String>,
+    },
+    // Prebuilt executables attached to a GitHub release. Each executable is
+    // an asset named after the server or client bin.
+    GithubRelease {
+        // The repository in `owner/name` form
+        repo: String,
+        tag: String,
+        // The sha256 of each executable, keyed by the bin
+        sha256: BTreeMap<String, String>,
+    },
+    // A project on the local machine, which is uploaded to the hosts via s3
+    Local {
+        // The directory containing the project. Can be set with
+        // `--local-driver-path` instead.
+        #[serde(default)]
+        path: Option<PathBuf>,
+        proj_name: String,
+        // Where the source is staged for the hosts, the private bucket by default
+        #[serde(default)]
+        s3_prefix: Option<String>,
+    },
+}
+
+fn version_default() -> String {
+    "*".to_string()
+}
+
+impl DriverRegistry {
+    pub fn from_file(path: &Path) -> OrchResult<Self> {
+        let file = File::open(path).map_err(|_err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Driver registry file not found: {:?}", path),
+            )
+        })?;
+        let registry: DriverRegistry = serde_json::from_reader(file).map_err(|err| {
+            OrchError::new(
+                ErrorKind::Init,
+                format!("Failed to parse driver registry file {:?}", path),
+            )
+            .with_source(err)
+        })?;
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    fn validate(&self) -> OrchResult<()> {
+        let mut names = HashSet::new();
+        for entry in &self.drivers {
+            if !names.insert(&entry.name) {
+                return Err(OrchError::new(
+                    ErrorKind::Init,
+                    format!("Duplicate driver in the driver registry: {}", entry.name),
+                ));
+            }
+        }
+
+        for entry in &self.drivers {
+            entry.validate_bins()?;
+            entry.validate_configs()?;
+            entry.validate_collector_interval()?;
+        }
+
+        for entry in &self.drivers {
+            if let SourceEntry::GithubRelease { sha256, .. } = &entry.source {
+                for bin in [&entry.server_bin, &entry.client_bin] {
+                    let valid = sha256.get(bin).is_some_and(|sha| {
+                        sha.len() == 64 && sha.chars().all(|c| c.is_ascii_hexdigit())
+                    });
+                    if !valid {
+                        return Err(OrchError::new(
+                            ErrorKind::Init,
+                            format!(
+                                "Driver {} is missing a valid sha256 checksum for {bin}",
+                                entry.name
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if !self.drivers.iter().any(|entry| entry.enabled) {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                "No drivers are enabled in the driver registry",
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn host_hooks(&self, endpoint_type: EndpointType) -> &HostHooks {
+        match endpoint_type {
+            EndpointType::Server => &self.hosts.server,
+            EndpointType::Client => &self.hosts.client,
+        }
+    }
+
+    // Run only the named driver pairs, regardless of whether they're enabled
+    // in the registry.
+    pub fn select(&mut self, names: &[String]) -> OrchResult<()> {
+        for name in names {
+            if !self.drivers.iter().any(|entry| &entry.name == name) {
+                let known: Vec<_> = self
+                    .drivers
+                    .iter()
+                    .map(|entry| entry.name.as_str())
+                    .collect();
+                return Err(OrchError::new(
+                    ErrorKind::Init,
+                    format!(
+                        "Unknown driver: {name}. Drivers in the registry: {}",
+                        known.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        for entry in self.drivers.iter_mut() {
+            entry.enabled = names.contains(&entry.name);
+        }
+
+        Ok(())
+    }
+
+    // Set the path of a local driver source, replacing the path from the
+    // registry file.
+    pub fn set_local_path(&mut self, name: &str, local_path: PathBuf) -> OrchResult<()> {
+        let entry = self.entry_mut(name)?;
+        match &mut entry.source {
+            SourceEntry::Local { path, .. } => {
+                *path = Some(local_path);
+                Ok(())
+            }
+            _ => Err(OrchError::new(
+                ErrorKind::Init,
+                format!("Driver {name} doesn't have a local source"),
+            )),
+        }
+    }
+
+    // Append an argument passed to the named driver pair when it's run.
+    pub fn add_arg(&mut self, name: &str, arg: String) -> OrchResult<()> {
+        self.entry_mut(name)?.args.push(arg);
+        Ok(())
+    }
+
+    // Set an environment variable for the named driver pair when it's run,
+    // replacing the value from the registry file.
+    pub fn set_env(&mut self, name: &str, key: String, value: String) -> OrchResult<()> {
+        self.entry_mut(name)?.env.insert(key, value);
+        Ok(())
+    }
+
+    fn entry_mut(&mut self, name: &str) -> OrchResult<&mut DriverEntry> {
+        self.drivers
+            .iter_mut()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| OrchError::new(ErrorKind::Init, format!("Unknown driver: {name}")))
+    }
+
+    // Set how often the collector samples the named driver pair, replacing
+    // the interval from the registry file.
+    pub fn set_collector_interval(&mut self, name: &str, interval: String) -> OrchResult<()> {
+        let entry = self.entry_mut(name)?;
+        entry.collector_interval = Some(interval);
+        entry.validate_collector_interval()
+    }
+
+    // Stage all local driver sources under the s3 prefix, replacing the
+    // prefixes from the registry file.
+    pub fn set_local_s3_prefix(&mut self, prefix: &str) {
+        for entry in self.drivers.iter_mut() {
+            if let SourceEntry::Local { s3_prefix, .. } = &mut entry.source {
+                *s3_prefix = Some(prefix.to_string());
+            }
+        }
+    }
+
+    // The names of the enabled driver pairs.
+    pub fn enabled_names(&self) -> Vec<String> {
+        self.drivers
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    // The names of the enabled driver pairs with a local source, which is
+    // uploaded from the local machine.
+    pub fn local_drivers(&self) -> Vec<String> {
+        self.drivers
+            .iter()
+            .filter(|entry| entry.enabled && matches!(entry.source, SourceEntry::Local { .. }))
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    // The transports of the enabled drivers, which are opened between the
+    // hosts.
+    pub fn ports(&self) -> BTreeSet<(Protocol, u16)> {
+        self.drivers
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| (entry.protocol, entry.port()))
+            .collect()
+    }
+
+    // Pins the collector installed on the hosts to its latest version, which
+    // is returned. Best effort, like the versions of crates.io drivers.
+    pub fn pin_collector_version(&mut self) -> Option<String> {
+        self.collector_version = resolve_crate_version(COLLECTOR_CRATE, "*");
+        self.collector_version.clone()
+    }
+
+    // The versions of the enabled drivers installed from the netbench crates,
+    // by driver name.
+    pub fn netbench_crate_versions(&self) -> Vec<(String, String)> {
+        self.drivers
+            .iter()
+            .filter(|entry| entry.enabled)
+            .filter_map(|entry| match &entry.source {
+                SourceEntry::CratesIo { krate, version, .. }
+                    if krate.starts_with("s2n-netbench") =>
+                {
+                    let resolved = resolve_crate_version(krate, version)?;
+                    Some((format!("{} ({krate})", entry.name), resolved))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Check that the sources of the enabled drivers exist before launching
+    // any infrastructure, since a mistake in the registry would otherwise
+    // only be noticed once the hosts fail to build the driver.
+    pub fn check_sources(&self) -> OrchResult<()> {
+        let problems: Vec<_> = self
+            .drivers
+            .iter()
+            .filter(|entry| entry.enabled)
+            .flat_map(|entry| {
+                entry
+                    .check_source()
+                    .into_iter()
+                    .map(move |problem| format!("{}: {problem}", entry.name))
+            })
+            .collect();
+
+        if !problems.is_empty() {
+            return Err(OrchError::new(
+                ErrorKind::Init,
+                format!("Invalid driver sources.\n{}", problems.join("\n")),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // The server and client drivers of each enabled entry, in registry order.
+    //
+    // Local sources are uploaded to s3 so that they can be downloaded by the
+    // hosts, unless the drivers aren't built.
+    pub async fn drivers(
+        &self,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+    ) -> OrchResult<(Vec<NetbenchDriverType>, Vec<NetbenchDriverType>)> {
+        let mut server_drivers = Vec::new();
+        let mut client_drivers = Vec::new();
+
+        for entry in self.drivers.iter().filter(|entry| entry.enabled) {
+            // Resolve the version once so the server and client drivers match
+            let mut resolved_version = None;
+            match &entry.source {
+                SourceEntry::Local {
+                    path, proj_name, ..
+                } => {
+                    let path = path.as_ref().ok_or_else(|| OrchError::new(ErrorKind::Init, format!(
+                            "Driver {} has a local source without a path. Set it with `--local-driver-path {}=<path>`",
+                            entry.name, entry.name
+                        )))?;
+                    if !config.skip.build() {
+                        let s3_path = entry.local_s3_path(unique_id, config);
+                        local_upload_source_to_s3(path, proj_name, &s3_path, config).await?;
+                    }
+                }
+                SourceEntry::CratesIo { krate, version, .. } => {
+                    resolved_version = resolve_crate_version(krate, version);
+                }
+                SourceEntry::Github { .. } | SourceEntry::GithubRelease { .. } => {}
+            }
+
+            // the pair is built once and run with each configuration
+            let isolated = self.is_isolated(entry);
+            let run_configs: Vec<Option<&ConfigEntry>> = if entry.configs.is_empty() {
+                vec![None]
+            } else {
+                entry.configs.iter().map(Some).collect()
+            };
+            for run_config in run_configs {
+                server_drivers.push(entry.driver(
+                    &entry.server_bin,
+                    isolated,
+                    run_config,
+                    unique_id,
+                    config,
+                    resolved_version.clone(),
+                ));
+                client_drivers.push(entry.driver(
+                    &entry.client_bin,
+                    isolated,
+                    run_config,
+                    unique_id,
+                    config,
+                    resolved_version.clone(),
+                ));
+            }
+        }
+
+        Ok((server_drivers, client_drivers))
+    }
+
+    // Drivers built from a pinned repo or ref, downloaded from a release, or
+    // with build flags that differ from another entry of the same driver, are
+    // built separately and installed under a name suffixed with the entry
+    // name. This allows comparing a driver against itself, eg. a PR branch
+    // and main or with and without a cargo feature.
+    fn is_isolated(&self, entry: &DriverEntry) -> bool {
+        let pinned = match &entry.source {
+            SourceEntry::Github { repo, git_ref, .. } => repo.is_some() || git_ref.is_some(),
+            SourceEntry::GithubRelease { .. } => true,
+            SourceEntry::CratesIo { .. } | SourceEntry::Local { .. } => false,
+        };
+        if pinned {
+            return true;
+        }
+
+        let build_flags = entry.build_flags();
+        self.drivers.iter().any(|other| {
+            other.name != entry.name
+                && (other.server_bin == entry.server_bin || other.client_bin == entry.client_bin)
+                && other.build_flags() != build_flags
+                && !build_flags.is_default()
+        })
+    }
+}
+
+impl DriverEntry {
+    // Drivers following the `s2n-netbench-driver-{server,client}-X` naming
+    // should be the server and client of the same X.
+    fn validate_bins(&self) -> OrchResult<()> {
+        let err = |reason: &str| {
+            Err(OrchError::new(
+                ErrorKind::Init,
+                format!(
+                    "Driver {} has mismatched executables {} and {}: {reason}",
+                    self.name, self.server_bin, self.client_bin
+                ),
+            ))
+        };
+
+        if self.server_bin.is_empty() || self.client_bin.is_empty() {
+            return err("empty executable name");
+        }
+        if self.server_bin == self.client_bin {
+            return err("the server and client are the same executable");
+        }
+
+        let server = self.server_bin.split_once("-server-");
+        let client = self.client_bin.split_once("-client-");
+        if let (Some(server), Some(client)) = (server, client) {
+            if server != client {
+                return err("the server and client are of different drivers");
+            }
+        } else if self.client_bin.contains("-server-") || self.server_bin.contains("-client-") {
+            return err("the server and client are swapped");
+        }
+
+        Ok(())
+    }
+
+    fn validate_collector_interval(&self) -> OrchResult<()> {
+        let Some(interval) = &self.collector_interval else {
+            return Ok(());
+        };
+        match humantime::parse_duration(interval) {
+            Ok(duration) if !duration.is_zero() => Ok(()),
+            _ => Err(OrchError::new(
+                ErrorKind::Init,
+                format!(
+                    "Driver {} has an invalid collector interval {interval:?}",
+                    self.name
+                ),
+            )),
+        }
+    }
+
+    // Labels name the results directory of each configuration, so they must
+    // be unique and safe to use in a path.
+    fn validate_configs(&self) -> OrchResult<()> {
+        let mut labels = HashSet::new();
+        for run_config in &self.configs {
+            let label = &run_config.label;
+            let valid = !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+            if !valid {
+                return Err(OrchError::new(ErrorKind::Init, format!(
+                        "Driver {} has an invalid config label {label:?}. Labels can only contain letters, digits, '-', '_' and '.'",
+                        self.name
+                    )));
+            }
+            if !labels.insert(label) {
+                return Err(OrchError::new(
+                    ErrorKind::Init,
+                    format!("Driver {} has a duplicate config label {label}", self.name),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Problems with the source of the driver which would only be noticed once
+    // the hosts try to build it.
+    fn check_source(&self) -> Vec<String> {
+        let mut problems = vec![];
+        match &self.source {
+            SourceEntry::CratesIo { krate, version, .. } => match fetch_crate_index(krate) {
+                Ok(Some(index)) => {
+                    if select_version(&index, version).is_none() {
+                        problems.push(format!("no version of crate {krate} matches {version}"));
+                    }
+                }
+                Ok(None) => problems.push(format!("crate {krate} doesn't exist on crates.io")),
+                Err(err) => warn!("Unable to check crate {krate}. {err}"),
+            },
+            SourceEntry::Github { repo, git_ref, .. } => {
+                let repo = repo.as_deref().unwrap_or(&state().netbench_repo);
+                let git_ref = git_ref.as_deref().unwrap_or(&state().netbench_branch);
+                if let Some(problem) = check_git_ref(repo, git_ref) {
+                    problems.push(problem);
+                }
+            }
+            SourceEntry::GithubRelease { repo, tag, .. } => {
+                for bin in [&self.server_bin, &self.client_bin] {
+                    let url = format!("https://github.com/{repo}/releases/download/{tag}/{bin}");
+                    let output = Command::new("curl").args(["-sSfLI", &url]).output();
+                    match output {
+                        Ok(output) if output.status.success() => {}
+                        Ok(_) => problems.push(format!("release asset {url} doesn't exist")),
+                        Err(err) => warn!("Unable to check release asset {url}. {err}"),
+                    }
+                }
+            }
+            SourceEntry::Local {
+                path, proj_name, ..
+            } => match path {
+                Some(path) if path.join(proj_name).is_dir() => {}
+                Some(path) => problems.push(format!(
+                    "local project {} doesn't exist",
+                    path.join(proj_name).display()
+                )),
+                None => problems.push(format!(
+                    "local source has no path, set it with `--local-driver-path {}=<path>`",
+                    self.name
+                )),
+            },
+        }
+
+        // The port is chosen by the orchestrator, which passes it to the server
+        // and the client
+        let mut env = self.env.keys().chain(
+            self.configs
+                .iter()
+                .flat_map(|run_config| run_config.env.keys()),
+        );
+        if env.any(|key| key == "PORT" || key.starts_with("SERVER_")) {
+            problems.push("env overrides the netbench port or server addresses".to_string());
+        }
+        let mut args = self
+            .args
+            .iter()
+            .chain(self.configs.iter().flat_map(|run_config| &run_config.args));
+        if args.any(|arg| arg == "-p" || arg == "--port" || arg.starts_with("--port=")) {
+            problems.push("args override the netbench port".to_string());
+        }
+
+        problems
+    }
+
+    // The s3 location a local source is staged at for the run
+    fn local_s3_path(&self, unique_id: &str, config: &OrchestratorConfig) -> String {
+        match &self.source {
+            SourceEntry::Local {
+                s3_prefix: Some(prefix),
+                ..
+            } => format!("{}/{unique_id}", prefix.trim_end_matches('/')),
+            _ => config.s3_private_path(unique_id),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(state().netbench_port)
+    }
+
+    fn build_flags(&self) -> BuildFlags {
+        BuildFlags {
+            rustflags: self.rustflags.clone(),
+            features: self.features.clone(),
+            profile: self.profile.clone(),
+        }
+    }
+
+    fn driver(
+        &self,
+        bin: &str,
+        isolated: bool,
+        run_config: Option<&ConfigEntry>,
+        unique_id: &str,
+        config: &OrchestratorConfig,
+        resolved_version: Option<String>,
+    ) -> NetbenchDriverType {
+        let driver_name = &if isolated {
+            format!("{bin}-{}", self.name)
+        } else {
+            bin.to_string()
+        };
+        let source = match &self.source {
+            SourceEntry::CratesIo {
+                krate,
+                version,
+                locked,
+            } => DriverSource::CratesIo(CrateIoSource {
+                krate: krate.clone(),
+                bin: bin.to_string(),
+                version: version.clone(),
+                resolved_version,
+                locked: *locked,
+            }),
+            SourceEntry::Github {
+                repo_name,
+                repo,
+                git_ref,
+            } => DriverSource::GithubRustProj(GithubRustSource {
+                repo_name: repo_name.clone(),
+                repo: repo.clone(),
+                git_ref: git_ref.clone(),
+                bin: bin.to_string(),
+            }),
+            SourceEntry::GithubRelease { repo, tag, sha256 } => {
+                DriverSource::GithubRelease(GithubReleaseSource {
+                    repo: repo.clone(),
+                    tag: tag.clone(),
+                    bin: bin.to_string(),
+                    // checked when the registry is loaded
+                    sha256: sha256[bin].clone(),
+                })
+            }
+            SourceEntry::Local {
+                path, proj_name, ..
+            } => DriverSource::Local(LocalSource {
+                proj_name: proj_name.clone(),
+                bin: bin.to_string(),
+                local_path_to_proj: path.clone().unwrap_or_default(),
+                s3_path: self.local_s3_path(unique_id, config),
+            }),
+        };
+
+        let build_cmd = self.build.as_ref().map(|build| {
+            build
+                .iter()
+                .map(|cmd| {
+                    cmd.replace("{cargo}", &state().cargo_path())
+                        .replace("{bin}", &state().host_bin_path())
+                        .replace("{driver}", driver_name)
+                })
+                .collect()
+        });
+
+        let mut args = self.args.clone();
+        let mut env = self.env.clone();
+        if let Some(run_config) = run_config {
+            args.extend(run_config.args.iter().cloned());
+            env.extend(run_config.env.clone());
+        }
+
+        NetbenchDriverType {
+            driver_name: DriverName::new(driver_name),
+            collector_version: config.driver_registry.collector_version.clone(),
+            source,
+            packages: self.packages.clone(),
+            build_cmd,
+            build_flags: self.build_flags(),
+            isolated,
+            args,
+            env,
+            label: run_config.map(|run_config| run_config.label.clone()),
+            protocol: self.protocol,
+            port: self.port(),
+            collector_interval: self.collector_interval.clone(),
+        }
+    }
+}
+
+const COLLECTOR_CRATE: &str = "s2n-netbench-collector";
+
+// The latest version of the crate matching the version requirement, according
+// to the crates.io sparse index. Best effort: if the index can't be reached,
+// the requirement is passed to `cargo install` as is.
+fn resolve_crate_version(krate: &str, version: &str) -> Option<String> {
+    let index = match fetch_crate_index(krate) {
+        Ok(Some(index)) => index,
+        _ => {
+            warn!("Failed to fetch the crates.io index for {krate}. The version won't be pinned");
+            return None;
+        }
+    };
+
+    let resolved = select_version(&index, version);
+    if resolved.is_none() {
+        warn!("No version of {krate} matches {version}. The version won't be pinned");
+    }
+    resolved
+}
+
+// Check that the ref exists in the repo. Commits can't be looked up without
+// cloning, so refs which look like a commit hash are assumed to exist.
+fn check_git_ref(repo: &str, git_ref: &str) -> Option<String> {
+    let is_commit =
+        (7..=40).contains(&git_ref.len()) && git_ref.chars().all(|c| c.is_ascii_hexdigit());
+    if is_commit {
+        return None;
+    }
+
+    let output = Command::new("git")
+        .args(["ls-remote", "--exit-code", repo, git_ref])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => None,
+        // `--exit-code` exits with 2 when no matching ref is found
+        Ok(output) if output.status.code() == Some(2) => {
+            Some(format!("ref {git_ref} doesn't exist in {repo}"))
+        }
+        Ok(output) => Some(format!(
+            "unable to access {repo}. {}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )),
+        Err(err) => {
+            warn!("Unable to check ref {git_ref} of {repo}. {err}");
+            None
+        }
+    }
+}
+
+// The crates.io sparse index file of the crate, or `None` if the crate
+// doesn't exist.
+fn fetch_crate_index(krate: &str) -> Result<Option<String>, String> {
+    let url = format!("https://index.crates.io/{}", index_path(krate));
+    match http_get(&url)? {
+        (200, body) => Ok(Some(body)),
+        (404, _) => Ok(None),
+        (status, _) => Err(format!("{url} returned {status}")),
+    }
+}
+
+// The status code and body of a GET request, following redirects.
+fn http_get(url: &str) -> Result<(u16, String), String> {
+    let output = Command::new("curl")
+        .args(["-sSL", "-w", "\n%{http_code}", url])
+        .output()
+        .map_err(|err| format!("Failed to run curl. {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to fetch {url}. {}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = output.rsplit_once('\n').unwrap_or(("", &output));
+    let status = status
+        .trim()
+        .parse()
+        .map_err(|_| format!("Failed to fetch {url}. Invalid status {status}"))?;
+    Ok((status, body.to_string()))
+}
+
+// The path of a crate in the crates.io index
+// https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files
+fn index_path(krate: &str) -> String {
+    let krate = krate.to_lowercase();
+    match krate.len() {
+        1 => format!("1/{krate}"),
+        2 => format!("2/{krate}"),
+        3 => format!("3/{}/{krate}", &krate[..1]),
+        _ => format!("{}/{}/{krate}", &krate[..2], &krate[2..4]),
+    }
+}
+
+// Select the latest non-yanked version in the index which matches the
+// version requirement, like `cargo install` does.
+fn select_version(index: &str, version: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct IndexEntry {
+        vers: String,
+        #[serde(default)]
+        yanked: bool,
+    }
+
+    let req = VersionReq::parse(version).ok()?;
+    index
+        .lines()
+        .filter_map(|line| serde_json::from_str::<IndexEntry>(line).ok())
+        .filter(|entry| !entry.yanked)
+        .filter_map(|entry| Version::parse(&entry.vers).ok())
+        .filter(|vers| req.matches(vers))
+        .max()
+        .map(|vers| vers.to_string())
+}
+
+// `aws sync` is preferred over `aws cp` since sync avoids object copy if the
+// same copy already exists.
+async fn local_upload_source_to_s3(
+    local_path_to_proj: &Path,
+    proj_name: &str,
+    s3_path: &str,
+    config: &OrchestratorConfig,
+) -> OrchResult<()> {
+    let mut local_to_s3_cmd = s3_utils::aws_cli_command(config).await?;
+    local_to_s3_cmd.args(["s3", "sync"]).stdout(Stdio::null());
+    local_to_s3_cmd
+        .arg(local_path_to_proj.join(proj_name))
+        .arg(format!("{s3_path}/{proj_name}/"));
+    local_to_s3_cmd.args(["--exclude", "target/*", "--exclude", ".git/*"]);
+    debug!("{:?}", local_to_s3_cmd);
+    let bar = progress::spinner(format!("Uploading local driver source {proj_name} to s3"));
+    let status = local_to_s3_cmd.status().map_err(|err| {
+        OrchError::new(
+            ErrorKind::S3,
+            format!("Failed to upload local driver source {proj_name}"),
+        )
+        .with_source(err)
+    })?;
+    bar.finish();
+    if !status.success() {
+        return Err(OrchError::new(
+            ErrorKind::S3,
+            format!("Failed to upload local driver source {proj_name}. aws sync command failed"),
+        ));
+    }
+    audit::record!("s3", "sync_local_driver_source", proj_name, s3_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_index_path() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(
+            index_path("s2n-netbench-driver-tcp"),
+            "s2/n-/s2n-netbench-driver-tcp"
+        );
+    }
+
+    #[test]
+    fn select_matching_version() {
+        let index = [
+            r#"{"name":"krate","vers":"0.1.0","yanked":false}"#,
+            r#"{"name":"krate","vers":"0.1.2","yanked":false}"#,
+            r#"{"name":"krate","vers":"0.1.3","yanked":true}"#,
+            r#"{"name":"krate","vers":"0.2.0","yanked":false}"#,
+            r#"{"name":"krate","vers":"0.3.0-beta.1","yanked":false}"#,
+        ]
+        .join("\n");
+
+        assert_eq!(select_version(&index, "*").as_deref(), Some("0.2.0"));
+        assert_eq!(select_version(&index, "0.1").as_deref(), Some("0.1.2"));
+        assert_eq!(select_version(&index, "=0.1.0").as_deref(), Some("0.1.0"));
+        assert_eq!(select_version(&index, "1"), None);
+        assert_eq!(select_version(&index, "not a version"), None);
+    }
+
+    #[test]
+    fn host_hooks() {
+        let registry: DriverRegistry = serde_json::from_str(
+            r#"{"drivers": [], "hosts": {"server": {"pre_build": ["install-agent"], "post_build": ["check-agent"]}}}"#,
+        )
+        .unwrap();
+        let server = registry.host_hooks(EndpointType::Server);
+        assert_eq!(server.pre_build, ["install-agent"]);
+        assert_eq!(server.post_build, ["check-agent"]);
+        assert!(registry
+            .host_hooks(EndpointType::Client)
+            .pre_build
+            .is_empty());
+
+        let registry: DriverRegistry = serde_json::from_str(r#"{"drivers": []}"#).unwrap();
+        assert!(registry
+            .host_hooks(EndpointType::Server)
+            .post_build
+            .is_empty());
+
+        assert!(serde_json::from_str::<DriverRegistry>(
+            r#"{"drivers": [], "hosts": {"server": {"pre_run": []}}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn isolated_build_variants() {
+        let registry: DriverRegistry = serde_json::from_str(
+            r#"{"drivers": [
+                {"name": "quic", "source": {"type": "github", "repo_name": "netbench"}, "server_bin": "server", "client_bin": "client"},
+                {"name": "quic-feature", "source": {"type": "github", "repo_name": "netbench"}, "server_bin": "server", "client_bin": "client", "features": ["feature"]},
+                {"name": "quic-pr", "source": {"type": "github", "repo_name": "netbench", "ref": "pr"}, "server_bin": "server", "client_bin": "client"},
+                {"name": "dc", "source": {"type": "github", "repo_name": "netbench"}, "server_bin": "dc-server", "client_bin": "dc-client", "rustflags": "--cfg unstable"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let isolated: Vec<_> = registry
+            .drivers
+            .iter()
+            .map(|entry| registry.is_isolated(entry))
+            .collect();
+        assert_eq!(isolated, [false, true, true, false]);
+    }
+
+    #[test]
+    fn paired_bins() {
+        let entry = |server_bin: &str, client_bin: &str| {
+            let entry: DriverEntry = serde_json::from_str(&format!(
+                r#"{{"name": "tcp", "source": {{"type": "crates_io", "krate": "tcp"}}, "server_bin": "{server_bin}", "client_bin": "{client_bin}"}}"#
+            ))
+            .unwrap();
+            entry.validate_bins()
+        };
+
+        assert!(entry(
+            "s2n-netbench-driver-server-tcp",
+            "s2n-netbench-driver-client-tcp"
+        )
+        .is_ok());
+        assert!(entry("server", "client").is_ok());
+        assert!(entry(
+            "s2n-netbench-driver-server-tcp",
+            "s2n-netbench-driver-client-s2n-tls"
+        )
+        .is_err());
+        assert!(entry(
+            "s2n-netbench-driver-client-tcp",
+            "s2n-netbench-driver-server-tcp"
+        )
+        .is_err());
+        assert!(entry("server", "server").is_err());
+    }
+
+    #[test]
+    fn release_checksums() {
+        let registry = |sha256: &str| -> OrchResult<()> {
+            let registry: DriverRegistry = serde_json::from_str(&format!(
+                r#"{{"drivers": [
+                    {{"name": "tcp", "source": {{"type": "github_release", "repo": "aws/s2n-netbench", "tag": "v0.1.0", "sha256": {sha256}}}, "server_bin": "server", "client_bin": "client"}}
+                ]}}"#
+            ))
+            .unwrap();
+            registry.validate()
+        };
+
+        let sha = "a".repeat(64);
+        assert!(registry(&format!(r#"{{"server": "{sha}", "client": "{sha}"}}"#)).is_ok());
+        assert!(registry(&format!(r#"{{"server": "{sha}"}}"#)).is_err());
+        assert!(registry(r#"{"server": "abc", "client": "abc"}"#).is_err());
+    }
+
+    #[test]
+    fn config_labels() {
+        let entry = |configs: &str| {
+            let entry: DriverEntry = serde_json::from_str(&format!(
+                r#"{{"name": "tcp", "source": {{"type": "crates_io", "krate": "tcp"}}, "server_bin": "server", "client_bin": "client", "configs": {configs}}}"#
+            ))
+            .unwrap();
+            entry.validate_configs()
+        };
+
+        assert!(
+            entry(r#"[{"label": "nagle", "args": ["--nagle"]}, {"label": "default"}]"#).is_ok()
+        );
+        assert!(entry(r#"[{"label": "nagle"}, {"label": "nagle"}]"#).is_err());
+        assert!(entry(r#"[{"label": "a/b"}]"#).is_err());
+        assert!(entry(r#"[{"label": ""}]"#).is_err());
+    }
+
+    #[test]
+    fn local_source_overrides() {
+        let mut registry: DriverRegistry = serde_json::from_str(
+            r#"{"drivers": [
+                {"name": "tcp", "source": {"type": "crates_io", "krate": "s2n-netbench-driver-tcp"}, "server_bin": "server", "client_bin": "client"},
+                {"name": "dc", "source": {"type": "local", "proj_name": "dc"}, "server_bin": "server", "client_bin": "client"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert!(registry.set_local_path("tcp", "/src".into()).is_err());
+        assert!(registry.set_local_path("unknown", "/src".into()).is_err());
+        registry.set_local_path("dc", "/src".into()).unwrap();
+        registry.set_local_s3_prefix("s3://bucket/prefix/");
+
+        match &registry.drivers[1].source {
+            SourceEntry::Local {
+                path, s3_prefix, ..
+            } => {
+                assert_eq!(path.as_deref(), Some(Path::new("/src")));
+                assert_eq!(s3_prefix.as_deref(), Some("s3://bucket/prefix/"));
+            }
+            _ => panic!("expected a local source"),
+        }
+    }
+}
@@ -1,28 +1,39 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use super::{send_command, Step};
+use super::{common, driver_args, send_command, Step};
 use crate::{
-    orchestrator::{OrchestratorConfig, STATE},
+    aws_api::SsmApi,
+    ec2_utils::EndpointType,
+    orchestrator::{OrchestratorConfig, ServerMap},
     ssm_utils::netbench_driver::NetbenchDriverType,
-    OrchError, OrchResult,
+    OrchResult,
 };
 use aws_sdk_ssm::operation::send_command::SendCommandOutput;
 use tracing::debug;
 
+// With `keep_servers` the workers leave their servers running, see
+// `--keep-servers`
 pub async fn run_russula_worker(
-    ssm_client: &aws_sdk_ssm::Client,
+    ssm_client: &impl SsmApi,
     instance_ids: Vec<String>,
+    servers: &ServerMap,
     driver: &NetbenchDriverType,
+    keep_servers: bool,
+    unique_id: &str,
     config: &OrchestratorConfig,
 ) -> OrchResult<SendCommandOutput> {
-    let netbench_cmd =
-        format!("env RUST_LOG=debug ./target/release/russula_cli netbench-server-worker --russula-port {} --driver {} --scenario {} --netbench-port {}",
-            STATE.russula_port, driver.driver_name(), config.netbench_scenario_filename(), STATE.netbench_port);
-    debug!("{}", netbench_cmd);
+    // each worker runs the servers of the map which are assigned to its host
+    let mut netbench_cmd =
+        format!("env RUST_LOG=debug ./target/release/russula_cli --log-stdout --unique-id {unique_id} netbench-server-worker --russula-port {} --driver {}{} --scenario {} --netbench-servers {}{}{}",
+            config.ports.russula(), driver.driver_name(), driver_args(driver), config.netbench_scenario_filename(), servers.as_arg(), config.profile.worker_args(EndpointType::Server), config.capture.worker_args(EndpointType::Server));
+    if let Some(ttl) = config.run.keep_servers().filter(|_| keep_servers) {
+        netbench_cmd.push_str(&format!(" --keep-servers {}", ttl.as_secs()));
+    }
+    debug!(driver = %driver.results_name(), "{}", netbench_cmd);
 
     send_command(
-        vec![Step::BuildDriver("".to_string()), Step::BuildRussula],
+        common::run_russula_wait_steps(EndpointType::Server, config),
         Step::RunRussula,
         "run_server_russula",
         ssm_client,
@@ -34,7 +45,4 @@ pub async fn run_russula_worker(
         config,
     )
     .await
-    .ok_or(OrchError::Ssm {
-        dbg: "failed to start russula worker".to_string(),
-    })
 }